@@ -0,0 +1,93 @@
+//! Output collection overhead for typical and large program outputs
+//!
+//! Run with `cargo bench --bench output_capture`. The 4 KB case should stay
+//! well under a millisecond per run.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rustbox::output_capture::{collect, CaptureProgress, OutputCapture, OutputLimits};
+use std::io::Write;
+use std::os::fd::{FromRawFd, OwnedFd};
+
+fn pipe() -> (OwnedFd, std::fs::File) {
+    let mut fds = [0; 2];
+    assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+    unsafe {
+        (
+            OwnedFd::from_raw_fd(fds[0]),
+            std::fs::File::from_raw_fd(fds[1]),
+        )
+    }
+}
+
+/// Write `size` bytes to stdout and a tenth of that to stderr from another thread
+fn writer(size: usize) -> (OwnedFd, OwnedFd, std::thread::JoinHandle<()>) {
+    let (stdout, mut stdout_writer) = pipe();
+    let (stderr, mut stderr_writer) = pipe();
+    let handle = std::thread::spawn(move || {
+        let line = [b'x'; 64];
+        let mut written = 0;
+        while written < size {
+            let n = line.len().min(size - written);
+            stdout_writer.write_all(&line[..n]).unwrap();
+            written += n;
+        }
+        stderr_writer.write_all(&vec![b'e'; size / 10]).unwrap();
+    });
+    (stdout, stderr, handle)
+}
+
+fn bench_collect(c: &mut Criterion) {
+    let mut group = c.benchmark_group("collect");
+    for size in [4 * 1024, 64 * 1024, 1024 * 1024] {
+        group.throughput(Throughput::Bytes(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &size| {
+            b.iter(|| {
+                let (stdout, stderr, handle) = writer(size);
+                let progress = CaptureProgress::default();
+                let output = collect(
+                    Some(stdout),
+                    Some(stderr),
+                    OutputLimits::default(),
+                    &progress,
+                )
+                .unwrap();
+                handle.join().unwrap();
+                assert_eq!(output.stdout.len(), size);
+            });
+        });
+    }
+    group.finish();
+}
+
+fn bench_background_capture(c: &mut Criterion) {
+    c.bench_function("capture_4k_background", |b| {
+        b.iter(|| {
+            let (stdout, stderr, handle) = writer(4 * 1024);
+            let capture =
+                OutputCapture::start(Some(stdout), Some(stderr), OutputLimits::default()).unwrap();
+            handle.join().unwrap();
+            assert_eq!(capture.finish().stdout.len(), 4 * 1024);
+        });
+    });
+}
+
+fn bench_output_limit(c: &mut Criterion) {
+    c.bench_function("collect_1m_limited_to_64k", |b| {
+        b.iter(|| {
+            let (stdout, stderr, handle) = writer(1024 * 1024);
+            let progress = CaptureProgress::default();
+            let limits = OutputLimits::total(Some(64 * 1024));
+            let output = collect(Some(stdout), Some(stderr), limits, &progress).unwrap();
+            // The writer sees a closed pipe once the limit is hit
+            let _ = handle.join();
+            assert!(output.limit_exceeded);
+        });
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_collect,
+    bench_background_capture,
+    bench_output_limit
+);
+criterion_main!(benches);
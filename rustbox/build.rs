@@ -0,0 +1,72 @@
+//! Embed build provenance (git commit, build time, target) for `--version --json`
+use std::process::Command;
+
+fn git(args: &[&str]) -> Option<String> {
+    let output = Command::new("git").args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn main() {
+    let commit = git(&["rev-parse", "--short=12", "HEAD"]).unwrap_or_default();
+    let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+        .is_some_and(|status| !status.is_empty());
+    println!("cargo:rustc-env=RUSTBOX_GIT_COMMIT={}", commit);
+    println!("cargo:rustc-env=RUSTBOX_GIT_DIRTY={}", dirty);
+
+    // Reproducible builds pin the date through SOURCE_DATE_EPOCH
+    let build_epoch = std::env::var("SOURCE_DATE_EPOCH").unwrap_or_else(|_| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs().to_string())
+            .unwrap_or_default()
+    });
+    println!("cargo:rustc-env=RUSTBOX_BUILD_EPOCH={}", build_epoch);
+    println!(
+        "cargo:rustc-env=RUSTBOX_BUILD_TARGET={}",
+        std::env::var("TARGET").unwrap_or_default()
+    );
+    println!(
+        "cargo:rustc-env=RUSTBOX_BUILD_PROFILE={}",
+        std::env::var("PROFILE").unwrap_or_default()
+    );
+    println!("cargo:rustc-env=RUSTBOX_FEATURES={}", features().join(","));
+
+    // Rebuild the info when HEAD moves, not on every source change
+    if let Some(git_dir) = git(&["rev-parse", "--absolute-git-dir"]) {
+        println!("cargo:rerun-if-changed={}/HEAD", git_dir);
+        if let Some(head_ref) = git(&["symbolic-ref", "-q", "HEAD"]) {
+            println!("cargo:rerun-if-changed={}/{}", git_dir, head_ref);
+        }
+    }
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+
+    #[cfg(feature = "grpc")]
+    compile_protos();
+}
+
+/// Enabled cargo features, from the `CARGO_FEATURE_<NAME>` variables cargo
+/// sets for each; no feature name has an underscore, so `_` maps back to `-`
+fn features() -> Vec<String> {
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(name, _)| {
+            let feature = name.strip_prefix("CARGO_FEATURE_")?;
+            (feature != "DEFAULT").then(|| feature.to_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    features
+}
+
+/// Generate the gRPC service (feature `grpc`) with the bundled protoc, so no
+/// system protobuf compiler is needed
+#[cfg(feature = "grpc")]
+fn compile_protos() {
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("No bundled protoc for this host");
+    std::env::set_var("PROTOC", protoc);
+    tonic_build::compile_protos("proto/rustbox.proto")
+        .expect("Failed to compile proto/rustbox.proto");
+}
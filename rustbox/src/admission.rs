@@ -0,0 +1,570 @@
+/// Admission control: reserve host resources before starting an execution
+///
+/// Every run is checked against the memory, pids and disk currently free on the
+/// host. Runs that could never fit are rejected immediately, runs that do not fit
+/// right now are queued until resources free up (or the queue timeout elapses),
+/// and everything else is admitted. This keeps a burst of submissions from
+/// pushing the host into the kernel OOM-killer, which may pick rustbox itself.
+///
+/// Admitted runs are recorded in a ledger in the state directory, shared by
+/// every rustbox process on the host, until their `Reservation` is dropped.
+/// What they asked for is deducted from what the host reports free, so
+/// concurrent runs cannot all pass against the same headroom. A run keeps its
+/// reservation even once it has allocated it, which errs on the side of
+/// queueing. Entries of processes that died without releasing are dropped.
+///
+/// Independently of free capacity, admission also pauses while host-wide pressure
+/// stall information (PSI) is above the configured thresholds, so a host that is
+/// already thrashing isn't pushed further. Set `RUSTBOX_IGNORE_PRESSURE=1` to
+/// override the pause manually.
+///
+/// The isolation self-test (`canary`) can also pause admission outright while
+/// a protection is known to be broken; runs are then rejected until it resumes.
+use crate::lock_manager::with_file_lock;
+use crate::types::{IsolateConfig, IsolateError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Memory kept free for the supervisor and the rest of the host
+const MEMORY_HEADROOM: u64 = 64 * 1024 * 1024;
+/// Pids kept free for the supervisor and the rest of the host
+const PID_HEADROOM: u64 = 64;
+/// Disk space kept free on the partition holding the workdir
+const DISK_HEADROOM: u64 = 16 * 1024 * 1024;
+/// How often a queued run re-checks host resources
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Environment variable that disables pausing on host pressure
+pub const IGNORE_PRESSURE_ENV: &str = "RUSTBOX_IGNORE_PRESSURE";
+
+/// Admission counters for this process
+static ADMITTED: AtomicU64 = AtomicU64::new(0);
+static QUEUED: AtomicU64 = AtomicU64::new(0);
+static REJECTED: AtomicU64 = AtomicU64::new(0);
+static PRESSURE_PAUSES: AtomicU64 = AtomicU64::new(0);
+static PRESSURE_WAIT_MS: AtomicU64 = AtomicU64::new(0);
+
+/// Why admission is paused, if it is
+static PAUSED: Mutex<Option<String>> = Mutex::new(None);
+
+/// Snapshot of admission counters
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct AdmissionMetrics {
+    /// Runs admitted
+    pub admitted: u64,
+    /// Runs that had to wait for capacity or pressure at least once
+    pub queued: u64,
+    /// Runs rejected with ResourceUnavailable
+    pub rejected: u64,
+    /// Runs paused because host pressure exceeded the thresholds
+    pub pressure_pauses: u64,
+    /// Total time spent paused on host pressure, in milliseconds
+    pub pressure_wait_ms: u64,
+}
+
+/// Current admission counters
+pub fn metrics() -> AdmissionMetrics {
+    AdmissionMetrics {
+        admitted: ADMITTED.load(Ordering::Relaxed),
+        queued: QUEUED.load(Ordering::Relaxed),
+        rejected: REJECTED.load(Ordering::Relaxed),
+        pressure_pauses: PRESSURE_PAUSES.load(Ordering::Relaxed),
+        pressure_wait_ms: PRESSURE_WAIT_MS.load(Ordering::Relaxed),
+    }
+}
+
+/// Reject every run with `reason` until `resume` is called
+pub fn pause(reason: String) {
+    *PAUSED.lock().unwrap_or_else(|e| e.into_inner()) = Some(reason);
+}
+
+/// Admit runs again after `pause`
+pub fn resume() {
+    *PAUSED.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+/// Why admission is paused, if it is
+pub fn paused() -> Option<String> {
+    PAUSED.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Host-wide PSI stall thresholds (avg10 percentages) that pause admission
+///
+/// A threshold of `None` disables that check.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PressureThresholds {
+    /// Share of time some task was stalled on CPU
+    pub cpu_some: Option<f64>,
+    /// Share of time some task was stalled on memory
+    pub memory_some: Option<f64>,
+    /// Share of time all non-idle tasks were stalled on memory
+    pub memory_full: Option<f64>,
+    /// Share of time all non-idle tasks were stalled on IO
+    pub io_full: Option<f64>,
+}
+
+impl Default for PressureThresholds {
+    fn default() -> Self {
+        Self {
+            cpu_some: Some(90.0),
+            memory_some: Some(40.0),
+            memory_full: Some(10.0),
+            io_full: Some(30.0),
+        }
+    }
+}
+
+/// Host-wide pressure stall averages over the last 10 seconds, in percent
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostPressure {
+    pub cpu_some: f64,
+    pub memory_some: f64,
+    pub memory_full: f64,
+    pub io_full: f64,
+}
+
+impl HostPressure {
+    /// Read /proc/pressure; returns None on kernels without PSI
+    pub fn probe() -> Option<Self> {
+        let cpu = fs::read_to_string("/proc/pressure/cpu").ok()?;
+        let memory = fs::read_to_string("/proc/pressure/memory").ok()?;
+        let io = fs::read_to_string("/proc/pressure/io").ok()?;
+        Some(Self::parse(&cpu, &memory, &io))
+    }
+
+    /// Averages out of the contents of the cpu, memory and io PSI files
+    pub fn parse(cpu: &str, memory: &str, io: &str) -> Self {
+        Self {
+            cpu_some: parse_psi_avg10(cpu, "some").unwrap_or(0.0),
+            memory_some: parse_psi_avg10(memory, "some").unwrap_or(0.0),
+            memory_full: parse_psi_avg10(memory, "full").unwrap_or(0.0),
+            io_full: parse_psi_avg10(io, "full").unwrap_or(0.0),
+        }
+    }
+
+    /// Describe the first threshold this snapshot exceeds, if any
+    pub fn exceeded(&self, thresholds: &PressureThresholds) -> Option<String> {
+        let checks = [
+            ("cpu some", self.cpu_some, thresholds.cpu_some),
+            ("memory some", self.memory_some, thresholds.memory_some),
+            ("memory full", self.memory_full, thresholds.memory_full),
+            ("io full", self.io_full, thresholds.io_full),
+        ];
+        checks.iter().find_map(|(name, value, limit)| {
+            limit
+                .filter(|limit| value > limit)
+                .map(|limit| format!("host {} pressure {:.1}% above {:.1}%", name, value, limit))
+        })
+    }
+}
+
+/// Snapshot of host resources relevant to admission
+#[derive(Debug, Clone, Copy)]
+pub struct HostResources {
+    /// Total physical memory in bytes
+    pub memory_total: u64,
+    /// Memory available for new allocations in bytes (MemAvailable)
+    pub memory_available: u64,
+    /// Number of processes/threads currently running on the host
+    pub pids_current: u64,
+    /// Maximum number of pids the kernel will hand out
+    pub pids_max: u64,
+    /// Free disk space in bytes on the partition holding the workdir
+    pub disk_free: Option<u64>,
+}
+
+impl HostResources {
+    /// Read the current host resource snapshot
+    pub fn probe(workdir: &Path) -> Result<Self> {
+        let meminfo = fs::read_to_string("/proc/meminfo")?;
+        let memory_total = parse_meminfo_field(&meminfo, "MemTotal:").ok_or_else(|| {
+            IsolateError::ResourceUnavailable("MemTotal missing from /proc/meminfo".to_string())
+        })?;
+        let memory_available = parse_meminfo_field(&meminfo, "MemAvailable:")
+            .or_else(|| parse_meminfo_field(&meminfo, "MemFree:"))
+            .unwrap_or(0);
+
+        // /proc/loadavg: "0.00 0.01 0.05 1/123 4567" - the 4th field is running/total
+        let pids_current = fs::read_to_string("/proc/loadavg")
+            .ok()
+            .and_then(|loadavg| {
+                loadavg
+                    .split_whitespace()
+                    .nth(3)
+                    .and_then(|field| field.split('/').nth(1))
+                    .and_then(|total| total.parse().ok())
+            })
+            .unwrap_or(0);
+        let pids_max = fs::read_to_string("/proc/sys/kernel/pid_max")
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(32768);
+
+        Ok(Self {
+            memory_total,
+            memory_available,
+            pids_current,
+            pids_max,
+            disk_free: disk_free(workdir),
+        })
+    }
+}
+
+/// Outcome of checking a run against host resources
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdmissionDecision {
+    /// Enough resources are free right now
+    Admit,
+    /// Resources are temporarily exhausted; retry later
+    Queue(String),
+    /// The request can never be satisfied on this host
+    Reject(String),
+}
+
+/// Resources a single execution asks for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResourceRequest {
+    pub memory: u64,
+    pub pids: u64,
+    pub disk: u64,
+}
+
+impl ResourceRequest {
+    /// Derive the reservation from an isolate configuration
+    pub fn from_config(config: &IsolateConfig) -> Self {
+        Self {
+            memory: config.memory_limit.unwrap_or(0),
+            pids: config.process_limit.unwrap_or(1) as u64,
+            // A tmpfs workdir already bounds writes and does not use the disk
+            disk: match config.workdir_tmpfs_size {
+                Some(_) => 0,
+                None => config.disk_quota.or(config.file_size_limit).unwrap_or(0),
+            },
+        }
+    }
+}
+
+/// Decide whether a request fits into the given host snapshot, next to the
+/// `reserved` requests of runs already admitted
+pub fn evaluate(
+    request: &ResourceRequest,
+    host: &HostResources,
+    reserved: &[ResourceRequest],
+) -> AdmissionDecision {
+    let memory_capacity = host.memory_total.saturating_sub(MEMORY_HEADROOM);
+    if request.memory > memory_capacity {
+        return AdmissionDecision::Reject(format!(
+            "memory limit {} MB exceeds host capacity {} MB",
+            request.memory / (1024 * 1024),
+            memory_capacity / (1024 * 1024)
+        ));
+    }
+
+    let pid_capacity = host.pids_max.saturating_sub(PID_HEADROOM);
+    if request.pids > pid_capacity {
+        return AdmissionDecision::Reject(format!(
+            "process limit {} exceeds host capacity {}",
+            request.pids, pid_capacity
+        ));
+    }
+
+    let held = reserved
+        .iter()
+        .fold(ResourceRequest::default(), |held, r| ResourceRequest {
+            memory: held.memory.saturating_add(r.memory),
+            pids: held.pids.saturating_add(r.pids),
+            disk: held.disk.saturating_add(r.disk),
+        });
+
+    let memory_free = host
+        .memory_available
+        .saturating_sub(MEMORY_HEADROOM)
+        .saturating_sub(held.memory);
+    if request.memory > memory_free {
+        return AdmissionDecision::Queue(format!(
+            "{} MB requested, {} MB available, {} MB reserved by admitted runs",
+            request.memory / (1024 * 1024),
+            host.memory_available / (1024 * 1024),
+            held.memory / (1024 * 1024)
+        ));
+    }
+
+    if host.pids_current + held.pids + request.pids > pid_capacity {
+        return AdmissionDecision::Queue(format!(
+            "{} pids requested, {} of {} in use, {} reserved by admitted runs",
+            request.pids, host.pids_current, host.pids_max, held.pids
+        ));
+    }
+
+    if let Some(disk_free) = host.disk_free {
+        let disk_free = disk_free
+            .saturating_sub(DISK_HEADROOM)
+            .saturating_sub(held.disk);
+        if request.disk > disk_free {
+            return AdmissionDecision::Queue(format!(
+                "{} MB of disk requested, {} MB free after {} MB reserved by admitted runs",
+                request.disk / (1024 * 1024),
+                disk_free / (1024 * 1024),
+                held.disk / (1024 * 1024)
+            ));
+        }
+    }
+
+    AdmissionDecision::Admit
+}
+
+/// Run recorded in the admission ledger
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LedgerEntry {
+    id: String,
+    /// Process holding the reservation; its entries go when it dies
+    pid: u32,
+    request: ResourceRequest,
+}
+
+/// Host-wide record of the resources admitted runs hold
+///
+/// Every access happens under an flock on `lock`, so checking the free
+/// resources and recording a new reservation is one step for all processes.
+#[derive(Debug, Clone)]
+pub struct Ledger {
+    path: PathBuf,
+    lock: PathBuf,
+}
+
+impl Ledger {
+    pub fn new(path: PathBuf, lock: PathBuf) -> Self {
+        Self { path, lock }
+    }
+
+    /// The ledger in the state directory
+    pub fn host() -> Self {
+        let paths = crate::paths::paths();
+        Self::new(paths.admission_ledger(), paths.admission_ledger_lock())
+    }
+
+    /// Requests held by live processes
+    pub fn reserved(&self) -> Result<Vec<ResourceRequest>> {
+        self.locked(|entries| Ok(entries.iter().map(|entry| entry.request).collect()))
+    }
+
+    /// Record `request` if `decide`, given the requests already held, admits it
+    pub fn reserve(
+        &self,
+        request: ResourceRequest,
+        decide: impl FnOnce(&[ResourceRequest]) -> Result<AdmissionDecision>,
+    ) -> Result<(AdmissionDecision, Option<Reservation>)> {
+        self.locked(|entries| {
+            let reserved: Vec<_> = entries.iter().map(|entry| entry.request).collect();
+            let decision = decide(&reserved)?;
+            if decision != AdmissionDecision::Admit {
+                return Ok((decision, None));
+            }
+            let id = uuid::Uuid::new_v4().to_string();
+            entries.push(LedgerEntry {
+                id: id.clone(),
+                pid: std::process::id(),
+                request,
+            });
+            let reservation = Reservation {
+                ledger: Some((self.clone(), id)),
+            };
+            Ok((decision, Some(reservation)))
+        })
+    }
+
+    fn release(&self, id: &str) -> Result<()> {
+        self.locked(|entries| {
+            entries.retain(|entry| entry.id != id);
+            Ok(())
+        })
+    }
+
+    /// Run `update` on the live entries under the lock and save what it leaves
+    fn locked<T>(&self, update: impl FnOnce(&mut Vec<LedgerEntry>) -> Result<T>) -> Result<T> {
+        if let Some(dir) = self.lock.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        with_file_lock(&self.lock, || Ok(self.update_locked(update)))
+            .map_err(IsolateError::AdvancedLock)?
+    }
+
+    fn update_locked<T>(
+        &self,
+        update: impl FnOnce(&mut Vec<LedgerEntry>) -> Result<T>,
+    ) -> Result<T> {
+        // A ledger cut short by a crash is started over
+        let mut entries: Vec<LedgerEntry> = fs::read_to_string(&self.path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        entries.retain(|entry| process_alive(entry.pid));
+        let result = update(&mut entries)?;
+        let content = serde_json::to_string(&entries).map_err(|e| {
+            IsolateError::Config(format!("Failed to serialize admission ledger: {}", e))
+        })?;
+        fs::write(&self.path, content)?;
+        Ok(result)
+    }
+}
+
+/// Resources held in the ledger for one admitted run, released on drop
+#[derive(Debug, Default)]
+pub struct Reservation {
+    ledger: Option<(Ledger, String)>,
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if let Some((ledger, id)) = self.ledger.take() {
+            if let Err(e) = ledger.release(&id) {
+                log::warn!("Failed to release admission reservation {}: {}", id, e);
+            }
+        }
+    }
+}
+
+/// Block until the configured run can be admitted, and hold its resources
+/// until the returned reservation is dropped
+///
+/// Returns `IsolateError::ResourceUnavailable` if the request can never fit or if
+/// resources did not free up within the configured queue timeout.
+pub fn admit(config: &IsolateConfig) -> Result<Reservation> {
+    admit_with(config, &Ledger::host())
+}
+
+/// `admit` against `ledger` instead of the host's
+pub fn admit_with(config: &IsolateConfig, ledger: &Ledger) -> Result<Reservation> {
+    if !config.admission_control {
+        return Ok(Reservation::default());
+    }
+    if let Some(reason) = paused() {
+        REJECTED.fetch_add(1, Ordering::Relaxed);
+        return Err(IsolateError::ResourceUnavailable(format!(
+            "admission paused: {}",
+            reason
+        )));
+    }
+
+    let request = ResourceRequest::from_config(config);
+    let started = Instant::now();
+    let deadline = started + config.admission_timeout;
+    let ignore_pressure = pressure_override();
+    let mut announced = false;
+    let mut pressure_paused_at: Option<Instant> = None;
+
+    // A disk quota image is mounted on the workdir but stored next to it
+    let partition = match (config.workdir_tmpfs_size, config.disk_quota) {
+        (None, Some(_)) => config.workdir.parent().unwrap_or(&config.workdir),
+        _ => &config.workdir,
+    };
+
+    let result = loop {
+        let mut pressure = None;
+        let (decision, reservation) = ledger.reserve(request, |reserved| {
+            let host = HostResources::probe(partition)?;
+            let decision = evaluate(&request, &host, reserved);
+            // Host pressure pauses admission even when capacity is available
+            if decision == AdmissionDecision::Admit && !ignore_pressure {
+                pressure =
+                    HostPressure::probe().and_then(|p| p.exceeded(&config.pressure_thresholds));
+                if let Some(ref reason) = pressure {
+                    return Ok(AdmissionDecision::Queue(reason.clone()));
+                }
+            }
+            Ok(decision)
+        })?;
+        match (&pressure, pressure_paused_at) {
+            (Some(_), None) => {
+                pressure_paused_at = Some(Instant::now());
+                PRESSURE_PAUSES.fetch_add(1, Ordering::Relaxed);
+            }
+            (None, Some(paused_at)) => {
+                record_pressure_wait(paused_at);
+                pressure_paused_at = None;
+            }
+            _ => {}
+        }
+
+        match decision {
+            AdmissionDecision::Admit => break Ok(reservation.unwrap_or_default()),
+            AdmissionDecision::Reject(reason) => {
+                break Err(IsolateError::ResourceUnavailable(reason));
+            }
+            AdmissionDecision::Queue(reason) => {
+                if Instant::now() >= deadline {
+                    break Err(IsolateError::ResourceUnavailable(format!(
+                        "gave up waiting after {:?}: {}",
+                        config.admission_timeout, reason
+                    )));
+                }
+                if !announced {
+                    log::warn!("Execution queued for host resources: {}", reason);
+                    QUEUED.fetch_add(1, Ordering::Relaxed);
+                    announced = true;
+                }
+                std::thread::sleep(QUEUE_POLL_INTERVAL);
+            }
+        }
+    };
+
+    if let Some(paused_at) = pressure_paused_at {
+        record_pressure_wait(paused_at);
+    }
+    match result {
+        Ok(_) => ADMITTED.fetch_add(1, Ordering::Relaxed),
+        Err(_) => REJECTED.fetch_add(1, Ordering::Relaxed),
+    };
+    result
+}
+
+/// Whether the operator asked to admit runs regardless of host pressure
+fn pressure_override() -> bool {
+    std::env::var(IGNORE_PRESSURE_ENV)
+        .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+fn record_pressure_wait(paused_at: Instant) {
+    PRESSURE_WAIT_MS.fetch_add(paused_at.elapsed().as_millis() as u64, Ordering::Relaxed);
+}
+
+fn process_alive(pid: u32) -> bool {
+    Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+/// Parse the avg10 value of the "some" or "full" line of a PSI file
+fn parse_psi_avg10(content: &str, kind: &str) -> Option<f64> {
+    content
+        .lines()
+        .find(|line| line.starts_with(kind))?
+        .split_whitespace()
+        .find_map(|field| field.strip_prefix("avg10="))
+        .and_then(|value| value.parse().ok())
+}
+
+/// Parse a "Key:   12345 kB" line from /proc/meminfo into bytes
+fn parse_meminfo_field(meminfo: &str, key: &str) -> Option<u64> {
+    meminfo
+        .lines()
+        .find(|line| line.starts_with(key))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|kb| kb.parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+}
+
+/// Free space on the partition holding `path`, using the nearest existing ancestor
+#[cfg(unix)]
+fn disk_free(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    let stat = nix::sys::statvfs::statvfs(existing).ok()?;
+    Some(stat.blocks_available() as u64 * stat.fragment_size() as u64)
+}
+
+#[cfg(not(unix))]
+fn disk_free(_path: &Path) -> Option<u64> {
+    None
+}
@@ -0,0 +1,395 @@
+/// Cache of compiled programs, shared by every box
+///
+/// Re-judging a submission on new test cases, or the same solution submitted
+/// again, builds the same source with the same compiler and flags. An
+/// `ArtifactKey` hashes the compiler binary (resolved path, size and
+/// modification time), the whole compile command and the source. The files a
+/// successful build leaves in the workdir are stored under the key, and later
+/// compiles copy them back instead of running the compiler. Entries live in
+/// `/var/cache/rustbox` by default; once the cache grows past `max_bytes` the
+/// least recently used builds are evicted. Hits, misses, stores and evictions
+/// are counted for `metrics`. Enable it under `isolate.artifact_cache` in
+/// config.json:
+///
+/// ```json
+/// "artifact_cache": { "enabled": true, "max_bytes": 1073741824 }
+/// ```
+use crate::types::{ExecutionResult, IsolateError, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+
+/// Result of the build that produced an entry's files
+const RESULT_FILE: &str = "compile.json";
+/// Directory of an entry holding the built files
+const FILES_DIR: &str = "files";
+
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static STORES: AtomicU64 = AtomicU64::new(0);
+static EVICTIONS: AtomicU64 = AtomicU64::new(0);
+
+/// Artifact cache settings (`isolate.artifact_cache` in config.json)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArtifactCacheOptions {
+    pub enabled: bool,
+    /// Directory holding the cached builds
+    pub dir: PathBuf,
+    /// Total size in bytes past which the least recently used builds are evicted
+    pub max_bytes: u64,
+}
+
+impl Default for ArtifactCacheOptions {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: PathBuf::from("/var/cache/rustbox"),
+            max_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// Snapshot of the artifact cache counters of this process
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ArtifactCacheMetrics {
+    /// Compiles served from the cache
+    pub hits: u64,
+    /// Compiles that found nothing cached
+    pub misses: u64,
+    /// Builds added to the cache
+    pub stores: u64,
+    /// Builds evicted to keep the cache within its size
+    pub evictions: u64,
+}
+
+/// Current artifact cache counters
+pub fn metrics() -> ArtifactCacheMetrics {
+    ArtifactCacheMetrics {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        stores: STORES.load(Ordering::Relaxed),
+        evictions: EVICTIONS.load(Ordering::Relaxed),
+    }
+}
+
+/// Hex SHA-256 identifying one build of one source
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ArtifactKey(String);
+
+impl ArtifactKey {
+    /// Key of building `code` with `compile_command`
+    pub fn new(compile_command: &[String], code: &str) -> Self {
+        let compiler = compile_command
+            .first()
+            .and_then(|program| resolve_program(program))
+            .and_then(|path| {
+                let metadata = fs::metadata(&path).ok()?;
+                let modified = metadata
+                    .modified()
+                    .ok()?
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .ok()?;
+                Some(serde_json::json!([
+                    path,
+                    metadata.len(),
+                    modified.as_nanos()
+                ]))
+            });
+        let material = serde_json::json!([
+            env!("CARGO_PKG_VERSION"),
+            compiler,
+            compile_command,
+            hex(&Sha256::digest(code.as_bytes())),
+        ]);
+        Self(hex(&Sha256::digest(material.to_string().as_bytes())))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Size and modification time of the regular files under a workdir
+#[derive(Debug, Clone, Default)]
+pub struct WorkdirSnapshot {
+    files: HashMap<PathBuf, (u64, Option<SystemTime>)>,
+}
+
+impl WorkdirSnapshot {
+    pub fn take(workdir: &Path) -> Self {
+        let mut files = HashMap::new();
+        for path in regular_files(workdir) {
+            if let Ok(metadata) = fs::metadata(&path) {
+                files.insert(path, (metadata.len(), metadata.modified().ok()));
+            }
+        }
+        Self { files }
+    }
+
+    /// Files under `workdir` created or rewritten since the snapshot
+    fn changed_files(&self, workdir: &Path) -> Vec<PathBuf> {
+        Self::take(workdir)
+            .files
+            .into_iter()
+            .filter(|(path, state)| self.files.get(path) != Some(state))
+            .map(|(path, _)| path)
+            .collect()
+    }
+}
+
+/// Compiled programs on disk, bounded in size
+#[derive(Debug, Clone)]
+pub struct ArtifactCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl ArtifactCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self { dir, max_bytes }
+    }
+
+    /// The cache config.json enables, if any
+    pub fn configured() -> Option<Self> {
+        let options = crate::config::RustBoxConfig::load_default()
+            .ok()?
+            .isolate
+            .artifact_cache;
+        options
+            .enabled
+            .then(|| Self::new(options.dir.join("artifacts"), options.max_bytes))
+    }
+
+    fn entry_dir(&self, key: &ArtifactKey) -> PathBuf {
+        self.dir.join(key.as_str())
+    }
+
+    /// Copy the build cached under `key` into `workdir` and return its compile result
+    ///
+    /// Restored files belong to `uid`/`gid`, the box user, when given. Earlier
+    /// runs could write the workdir, so nothing they left there is followed.
+    pub fn restore(
+        &self,
+        key: &ArtifactKey,
+        workdir: &Path,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> Option<ExecutionResult> {
+        let entry = self.entry_dir(key);
+        let result = fs::read(entry.join(RESULT_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_slice::<ExecutionResult>(&content).ok());
+        let Some(result) = result else {
+            MISSES.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        let files = entry.join(FILES_DIR);
+        for cached in regular_files(&files) {
+            let Ok(relative) = cached.strip_prefix(&files) else {
+                continue;
+            };
+            if let Err(e) = restore_file(&cached, workdir, relative, uid, gid) {
+                log::warn!("Failed to restore cached build {}: {}", key.as_str(), e);
+                MISSES.fetch_add(1, Ordering::Relaxed);
+                return None;
+            }
+        }
+        // Marks the entry as recently used for eviction
+        if let Ok(file) = fs::File::options()
+            .append(true)
+            .open(entry.join(RESULT_FILE))
+        {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        HITS.fetch_add(1, Ordering::Relaxed);
+        Some(result)
+    }
+
+    /// Store the files a successful build added to `workdir` since `before`
+    ///
+    /// `source` is left out; every compile writes it again.
+    pub fn store(
+        &self,
+        key: &ArtifactKey,
+        workdir: &Path,
+        before: &WorkdirSnapshot,
+        source: &Path,
+        result: &ExecutionResult,
+    ) -> Result<()> {
+        if !result.success {
+            return Ok(());
+        }
+        // Built aside and renamed, so a concurrent restore sees all files or none
+        let staging = self
+            .dir
+            .join(format!(".{}.{}.tmp", key.as_str(), std::process::id()));
+        let _ = fs::remove_dir_all(&staging);
+        fs::create_dir_all(staging.join(FILES_DIR))?;
+        for path in before.changed_files(workdir) {
+            let Ok(relative) = path.strip_prefix(workdir) else {
+                continue;
+            };
+            if path == source {
+                continue;
+            }
+            let target = staging.join(FILES_DIR).join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(&path, &target)?;
+        }
+        let content = serde_json::to_vec(result).map_err(|e| {
+            IsolateError::Config(format!("Failed to serialize compile result: {}", e))
+        })?;
+        fs::write(staging.join(RESULT_FILE), content)?;
+        if fs::rename(&staging, self.entry_dir(key)).is_err() {
+            // Another compile of the same source got there first
+            let _ = fs::remove_dir_all(&staging);
+            return Ok(());
+        }
+        STORES.fetch_add(1, Ordering::Relaxed);
+        self.evict();
+        Ok(())
+    }
+
+    /// Cached builds and their total size in bytes
+    pub fn usage(&self) -> (usize, u64) {
+        let entries = self.entries();
+        let bytes = entries.iter().map(|(_, size, _)| size).sum();
+        (entries.len(), bytes)
+    }
+
+    /// Evict the least recently used builds until the cache fits `max_bytes`
+    fn evict(&self) {
+        let mut entries = self.entries();
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort_by_key(|(_, _, last_used)| *last_used);
+        for (entry, size, _) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            if fs::remove_dir_all(&entry).is_ok() {
+                total = total.saturating_sub(size);
+                EVICTIONS.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// (directory, size, last use) of every complete entry
+    fn entries(&self) -> Vec<(PathBuf, u64, Option<SystemTime>)> {
+        let Ok(dir) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        dir.filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            // Entries still being stored are hidden
+            .filter(|path| !path.to_string_lossy().ends_with(".tmp"))
+            .filter_map(|path| {
+                let last_used = fs::metadata(path.join(RESULT_FILE)).ok()?.modified().ok();
+                let size = regular_files(&path)
+                    .iter()
+                    .filter_map(|file| fs::metadata(file).ok())
+                    .map(|metadata| metadata.len())
+                    .sum();
+                Some((path, size, last_used))
+            })
+            .collect()
+    }
+}
+
+/// Regular files under `dir`, recursively; symlinks are not followed
+/// Copy `cached` to `relative` under `workdir` as root without following a
+/// symlink anywhere below `workdir`
+///
+/// Each directory is opened relative to the last with `O_NOFOLLOW`; the file
+/// is unlinked and created anew with `O_EXCL`, then given its mode and owner
+/// through the descriptor.
+fn restore_file(
+    cached: &Path,
+    workdir: &Path,
+    relative: &Path,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> std::io::Result<()> {
+    use std::ffi::CString;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::PermissionsExt;
+
+    let invalid = || std::io::Error::from(std::io::ErrorKind::InvalidInput);
+    let mut names = relative
+        .components()
+        .map(|component| match component {
+            std::path::Component::Normal(name) => {
+                CString::new(name.as_bytes()).map_err(|_| invalid())
+            }
+            _ => Err(invalid()),
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let file_name = names.pop().ok_or_else(invalid)?;
+
+    let mut dir = OwnedFd::from(fs::File::open(workdir)?);
+    for name in names {
+        // Already there is fine; a symlink or file then fails to open
+        unsafe { libc::mkdirat(dir.as_raw_fd(), name.as_ptr(), 0o755) };
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+        let fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        dir = unsafe { OwnedFd::from_raw_fd(fd) };
+    }
+
+    let mode = fs::metadata(cached)?.permissions().mode() & 0o777;
+    unsafe { libc::unlinkat(dir.as_raw_fd(), file_name.as_ptr(), 0) };
+    let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+    let fd = unsafe { libc::openat(dir.as_raw_fd(), file_name.as_ptr(), flags, mode) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let mut target = unsafe { fs::File::from_raw_fd(fd) };
+    std::io::copy(&mut fs::File::open(cached)?, &mut target)?;
+    target.set_permissions(fs::Permissions::from_mode(mode))?;
+    if uid.is_some() || gid.is_some() {
+        std::os::unix::fs::fchown(&target, uid, gid)?;
+    }
+    Ok(())
+}
+
+fn regular_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            match entry.file_type() {
+                Ok(kind) if kind.is_dir() => pending.push(entry.path()),
+                Ok(kind) if kind.is_file() => files.push(entry.path()),
+                _ => {}
+            }
+        }
+    }
+    files
+}
+
+/// Host path of `program`, looked up in `PATH` unless it has a slash
+fn resolve_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        return fs::canonicalize(program).ok();
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+        .and_then(|path| fs::canonicalize(path).ok())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
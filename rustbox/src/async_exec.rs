@@ -0,0 +1,66 @@
+/// Async execution for embedding in tokio services
+///
+/// Runs are still monitored by the blocking executor loop; `Isolate::execute_async`
+/// moves that loop onto tokio's blocking pool so async worker threads are never
+/// held, and hands back an `ExecutionHandle` that can be awaited or cancelled.
+use crate::executor::CancelToken;
+use crate::isolate::Isolate;
+use crate::types::{ExecutionResult, IsolateError, Result};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::task::JoinHandle;
+
+/// A run in progress on the blocking pool
+///
+/// Resolves to the isolate and its result. Dropping the handle cancels the run.
+pub struct ExecutionHandle {
+    task: JoinHandle<Result<(Isolate, ExecutionResult)>>,
+    cancel: CancelToken,
+}
+
+impl ExecutionHandle {
+    pub(crate) fn new(
+        task: JoinHandle<Result<(Isolate, ExecutionResult)>>,
+        cancel: CancelToken,
+    ) -> Self {
+        Self { task, cancel }
+    }
+
+    /// Kill the sandboxed process; awaiting then yields a `Cancelled` result
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    /// Token that cancels this run, for sharing with other tasks
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+impl Future for ExecutionHandle {
+    type Output = Result<(Isolate, ExecutionResult)>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.task).poll(cx).map(|joined| {
+            joined.unwrap_or_else(|e| {
+                Err(IsolateError::Process(format!(
+                    "Execution task failed: {}",
+                    e
+                )))
+            })
+        })
+    }
+}
+
+impl Drop for ExecutionHandle {
+    fn drop(&mut self) {
+        if !self.task.is_finished() {
+            self.cancel.cancel();
+        }
+    }
+}
@@ -0,0 +1,81 @@
+/// Build provenance and compiled-in capabilities
+///
+/// Reported by `--version --json` and, on request, attached to execution
+/// results so bug reports and verdict audits can be tied to an exact build.
+use serde::{Deserialize, Serialize};
+
+/// Identity of the running binary
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub version: String,
+    /// Abbreviated commit hash, if built from a git checkout
+    pub git_commit: Option<String>,
+    /// Built with uncommitted changes to tracked files
+    pub git_dirty: bool,
+    /// RFC 3339 build time (or SOURCE_DATE_EPOCH)
+    pub build_date: Option<String>,
+    pub target: String,
+    pub profile: String,
+    /// Enabled cargo features, every one in Cargo.toml that was on
+    pub features: Vec<String>,
+    /// Seccomp implementation: `native` (no libseccomp linked)
+    pub seccomp: String,
+    /// Linked libseccomp version, if any
+    pub libseccomp_version: Option<String>,
+    /// Supported cgroup hierarchies
+    pub cgroup_versions: Vec<String>,
+}
+
+impl BuildInfo {
+    /// Information about the current binary
+    pub fn current() -> Self {
+        let features = env!("RUSTBOX_FEATURES")
+            .split(',')
+            .filter(|feature| !feature.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit: non_empty(env!("RUSTBOX_GIT_COMMIT")),
+            git_dirty: env!("RUSTBOX_GIT_DIRTY") == "true",
+            build_date: env!("RUSTBOX_BUILD_EPOCH")
+                .parse::<i64>()
+                .ok()
+                .and_then(|epoch| chrono::DateTime::from_timestamp(epoch, 0))
+                .map(|date| date.to_rfc3339()),
+            target: env!("RUSTBOX_BUILD_TARGET").to_string(),
+            profile: env!("RUSTBOX_BUILD_PROFILE").to_string(),
+            features,
+            seccomp: if cfg!(feature = "seccomp-native") {
+                "native"
+            } else {
+                "none"
+            }
+            .to_string(),
+            libseccomp_version: None,
+            cgroup_versions: if cfg!(feature = "cgroup-v1") {
+                vec!["v1".to_string()]
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    /// One-line summary for `--version`
+    pub fn summary(&self) -> String {
+        match self.git_commit {
+            Some(ref commit) => format!(
+                "rustbox {} ({}{})",
+                self.version,
+                commit,
+                if self.git_dirty { "-dirty" } else { "" }
+            ),
+            None => format!("rustbox {}", self.version),
+        }
+    }
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
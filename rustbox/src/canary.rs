@@ -0,0 +1,282 @@
+/// Periodic isolation self-test for long-running service mode
+///
+/// A fixed suite of escape attempts runs in a scratch box. Each probe tries
+/// something a working sandbox must stop: burning CPU past the limit, allocating
+/// past the memory limit, seeing a host network interface, reading host secrets
+/// or exec'ing past the exec limit. The first run records which probes are
+/// contained on this host; a later run that lets one of them through is a
+/// regression (e.g. a kernel update changed the cgroup mount layout). Regressions
+/// are logged, counted in the metrics, optionally posted to a webhook and, in
+/// strict deployments, pause admission until a run passes again.
+use crate::isolate::{CleanupPolicy, Isolate};
+use crate::types::{ExecutionOverrides, ExecutionResult, ExecutionStatus, IsolateConfig};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Self-test settings
+#[derive(Debug, Clone)]
+pub struct SelfTestConfig {
+    /// Time between runs
+    pub interval: Duration,
+    /// Scratch box the probes run in
+    pub box_id: u32,
+    /// URL receiving a JSON POST when protections regress or recover
+    pub webhook: Option<String>,
+    /// Reject executions while a protection is regressed
+    pub pause_admission: bool,
+}
+
+/// One escape attempt and how to tell it was stopped
+pub struct Probe {
+    pub name: &'static str,
+    /// Python source of the attempt
+    pub code: &'static str,
+    pub limits: ExecutionOverrides,
+    pub contained: fn(&ExecutionResult) -> bool,
+}
+
+/// Result of one probe
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ProbeOutcome {
+    pub name: String,
+    /// The sandbox stopped the attempt
+    pub contained: bool,
+    /// Verdict label, or the error that kept the probe from running
+    pub status: String,
+}
+
+/// Result of a whole suite run
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct SelfTestReport {
+    pub outcomes: Vec<ProbeOutcome>,
+}
+
+impl SelfTestReport {
+    /// Probes contained in `baseline` that are not contained any more
+    pub fn regressions(&self, baseline: &SelfTestReport) -> Vec<String> {
+        self.outcomes
+            .iter()
+            .filter(|outcome| !outcome.contained)
+            .filter(|outcome| {
+                baseline
+                    .outcomes
+                    .iter()
+                    .any(|base| base.name == outcome.name && base.contained)
+            })
+            .map(|outcome| outcome.name.clone())
+            .collect()
+    }
+}
+
+static RUNS: AtomicU64 = AtomicU64::new(0);
+static FAILED_RUNS: AtomicU64 = AtomicU64::new(0);
+static REGRESSED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Self-test counters for this process
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SelfTestMetrics {
+    /// Suite runs after the baseline
+    pub runs: u64,
+    /// Runs that found at least one regression
+    pub failed_runs: u64,
+    /// Probes regressed in the latest run
+    pub regressed: Vec<String>,
+}
+
+/// Current self-test counters
+pub fn metrics() -> SelfTestMetrics {
+    SelfTestMetrics {
+        runs: RUNS.load(Ordering::Relaxed),
+        failed_runs: FAILED_RUNS.load(Ordering::Relaxed),
+        regressed: REGRESSED.lock().unwrap_or_else(|e| e.into_inner()).clone(),
+    }
+}
+
+/// The escape attempts run by the self-test
+pub fn probes() -> Vec<Probe> {
+    vec![
+        Probe {
+            name: "cpu_time_limit",
+            code: "while True: pass",
+            limits: ExecutionOverrides {
+                max_cpu: Some(Duration::from_secs(1)),
+                max_wall_time: Some(Duration::from_secs(5)),
+                ..Default::default()
+            },
+            contained: |result| result.status == ExecutionStatus::TimeLimit,
+        },
+        Probe {
+            name: "memory_limit",
+            code: "x = []\nwhile True: x.append(bytearray(16 << 20))",
+            limits: ExecutionOverrides {
+                max_memory: Some(64),
+                max_wall_time: Some(Duration::from_secs(10)),
+                ..Default::default()
+            },
+            contained: |result| result.status == ExecutionStatus::MemoryLimit,
+        },
+        Probe {
+            name: "network_isolation",
+            code: "print(sum(1 for line in open('/proc/net/dev').readlines()[2:] \
+                   if line.split(':')[0].strip() != 'lo'))",
+            limits: ExecutionOverrides::default(),
+            contained: |result| result.success && result.stdout.trim() == "0",
+        },
+        Probe {
+            name: "host_secrets",
+            code: "open('/etc/shadow').read()\nprint('ESCAPED')",
+            limits: ExecutionOverrides::default(),
+            contained: |result| !result.stdout.contains("ESCAPED"),
+        },
+        Probe {
+            name: "exec_limit",
+            code: "import os\nos.execv('/bin/true', ['true'])",
+            limits: ExecutionOverrides {
+                max_execs: Some(1),
+                ..Default::default()
+            },
+            contained: |result| result.status == ExecutionStatus::ExecLimit,
+        },
+    ]
+}
+
+/// Run every probe once in box `box_id` and clean the box up afterwards
+pub fn run_suite(box_id: u32) -> SelfTestReport {
+    let config = IsolateConfig {
+        instance_id: format!("rustbox/{}", box_id),
+        strict_mode: false,
+        // The self-test must keep running while admission is paused
+        admission_control: false,
+        ..Default::default()
+    };
+    let mut isolate = match Isolate::new(config) {
+        Ok(isolate) => isolate,
+        Err(e) => {
+            let status = format!("error: {}", e);
+            return SelfTestReport {
+                outcomes: probes()
+                    .iter()
+                    .map(|probe| ProbeOutcome {
+                        name: probe.name.to_string(),
+                        contained: false,
+                        status: status.clone(),
+                    })
+                    .collect(),
+            };
+        }
+    };
+
+    let outcomes = probes()
+        .iter()
+        .map(|probe| {
+            let command = ["/usr/bin/python3", "-c", probe.code].map(String::from);
+            let (contained, status) =
+                match isolate.execute_with_overrides(&command, None, &probe.limits) {
+                    Ok(result) => ((probe.contained)(&result), result.status.label()),
+                    Err(e) => (false, format!("error: {}", e)),
+                };
+            ProbeOutcome {
+                name: probe.name.to_string(),
+                contained,
+                status,
+            }
+        })
+        .collect();
+
+    if let Err(e) = isolate.finish(CleanupPolicy::Always) {
+        log::warn!("Failed to clean up self-test box {}: {}", box_id, e);
+    }
+    SelfTestReport { outcomes }
+}
+
+/// Run the suite now for a baseline, then every `interval` on a background thread
+pub fn spawn(config: SelfTestConfig) -> std::io::Result<std::thread::JoinHandle<()>> {
+    std::thread::Builder::new()
+        .name("rustbox-self-test".to_string())
+        .spawn(move || {
+            let baseline = run_suite(config.box_id);
+            let uncontained: Vec<&str> = baseline
+                .outcomes
+                .iter()
+                .filter(|outcome| !outcome.contained)
+                .map(|outcome| outcome.name.as_str())
+                .collect();
+            if !uncontained.is_empty() {
+                eprintln!(
+                    "⚠️  Self-test: not enforced on this host, not monitored: {}",
+                    uncontained.join(", ")
+                );
+            }
+
+            loop {
+                std::thread::sleep(config.interval);
+                let report = run_suite(config.box_id);
+                record(&config, &report, report.regressions(&baseline));
+            }
+        })
+}
+
+/// Update metrics, log, alert and pause or resume admission after a run
+fn record(config: &SelfTestConfig, report: &SelfTestReport, regressed: Vec<String>) {
+    RUNS.fetch_add(1, Ordering::Relaxed);
+    let previous = std::mem::replace(
+        &mut *REGRESSED.lock().unwrap_or_else(|e| e.into_inner()),
+        regressed.clone(),
+    );
+
+    if !regressed.is_empty() {
+        FAILED_RUNS.fetch_add(1, Ordering::Relaxed);
+        log::error!("Isolation self-test regressed: {}", regressed.join(", "));
+        eprintln!("🚨 Isolation self-test regressed: {}", regressed.join(", "));
+        if config.pause_admission {
+            crate::admission::pause(format!(
+                "isolation self-test regressed: {}",
+                regressed.join(", ")
+            ));
+        }
+    } else if !previous.is_empty() {
+        eprintln!("✅ Isolation self-test passing again");
+        if config.pause_admission {
+            crate::admission::resume();
+        }
+    }
+
+    if regressed != previous {
+        if let Some(ref url) = config.webhook {
+            let event = if regressed.is_empty() {
+                "isolation_recovered"
+            } else {
+                "isolation_regressed"
+            };
+            notify(url, event, &regressed, report);
+        }
+    }
+}
+
+#[cfg(feature = "http")]
+fn notify(url: &str, event: &str, regressed: &[String], report: &SelfTestReport) {
+    let body = serde_json::json!({
+        "event": event,
+        "regressed": regressed,
+        "report": report,
+        "at": chrono::Utc::now().to_rfc3339(),
+    });
+    if let Err(e) = ureq::post(url)
+        .timeout(Duration::from_secs(10))
+        .set("Content-Type", "application/json")
+        .send_string(&body.to_string())
+    {
+        log::warn!("Failed to send self-test webhook to {}: {}", url, e);
+    }
+}
+
+#[cfg(not(feature = "http"))]
+fn notify(url: &str, event: &str, _regressed: &[String], _report: &SelfTestReport) {
+    log::warn!(
+        "Not sending self-test {} webhook to {}: built without the 'http' feature",
+        event,
+        url
+    );
+}
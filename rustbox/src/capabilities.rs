@@ -0,0 +1,291 @@
+/// Capability report: which protections are active for the current invocation
+///
+/// Built from the same probes the runtime uses when deciding whether to enforce
+/// or degrade a protection, so operators can gate on it programmatically.
+use crate::admission::HostPressure;
+use crate::cgroup::Cgroup;
+use crate::namespace::NamespaceIsolation;
+use serde::Serialize;
+use std::path::Path;
+
+/// How a protection behaves in the current context
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CapabilityState {
+    /// Fully enforced
+    Active,
+    /// Partially enforced or enforced with reduced accuracy
+    Degraded,
+    /// Not enforced at all
+    Absent,
+}
+
+/// A single protection and its state
+#[derive(Debug, Clone, Serialize)]
+pub struct Capability {
+    pub name: &'static str,
+    pub state: CapabilityState,
+    pub detail: String,
+}
+
+/// Full report for the current uid, kernel and mounts
+#[derive(Debug, Clone, Serialize)]
+pub struct CapabilityReport {
+    pub uid: u32,
+    pub euid: u32,
+    pub kernel: Option<String>,
+    pub capabilities: Vec<Capability>,
+}
+
+impl CapabilityReport {
+    /// Whether every protection is fully active
+    pub fn fully_protected(&self) -> bool {
+        self.capabilities
+            .iter()
+            .all(|c| c.state == CapabilityState::Active)
+    }
+}
+
+fn capability(name: &'static str, state: CapabilityState, detail: impl Into<String>) -> Capability {
+    Capability {
+        name,
+        state,
+        detail: detail.into(),
+    }
+}
+
+/// Probe the current invocation context
+pub fn probe() -> CapabilityReport {
+    let uid = unsafe { libc::getuid() };
+    let euid = unsafe { libc::geteuid() };
+    let is_root = euid == 0;
+    let kernel = std::fs::read_to_string("/proc/sys/kernel/osrelease")
+        .ok()
+        .map(|release| release.trim().to_string());
+
+    let mut capabilities = Vec::new();
+
+    // Cgroup-backed resource limits
+    let controllers = if crate::cgroup::cgroups_available() {
+        Cgroup::available_controllers().unwrap_or_default()
+    } else {
+        Default::default()
+    };
+    for (name, controller, what) in [
+        (
+            "memory_limit",
+            "memory",
+            "memory limit and peak memory accounting",
+        ),
+        (
+            "cpu_accounting",
+            "cpuacct",
+            "CPU time accounting for time limits",
+        ),
+        ("process_limit", "pids", "process count limit"),
+    ] {
+        let entry = if !crate::cgroup::cgroups_available() {
+            let reason = if cfg!(feature = "cgroup-v1") {
+                "cgroups are not mounted"
+            } else {
+                "built without the cgroup-v1 feature"
+            };
+            capability(name, CapabilityState::Absent, reason)
+        } else if !controllers.contains(controller) {
+            capability(
+                name,
+                CapabilityState::Absent,
+                format!("cgroup controller '{}' is not enabled", controller),
+            )
+        } else if !is_root {
+            capability(
+                name,
+                CapabilityState::Absent,
+                "creating cgroups requires root privileges",
+            )
+        } else {
+            capability(name, CapabilityState::Active, what)
+        };
+        capabilities.push(entry);
+    }
+
+    // Kernel memory: probe with a throwaway group, exactly as a run would set it
+    capabilities.push(if !is_root || !controllers.contains("memory") {
+        capability(
+            "kernel_memory_limit",
+            CapabilityState::Absent,
+            "requires root and the memory controller",
+        )
+    } else {
+        let probe_name = format!("rustbox-capability-probe-{}", std::process::id());
+        match Cgroup::new(&probe_name, false)
+            .and_then(|cgroup| cgroup.set_kernel_memory_limit(256 * 1024 * 1024))
+        {
+            Ok(true) => capability(
+                "kernel_memory_limit",
+                CapabilityState::Active,
+                "separate kmem cap enforced",
+            ),
+            Ok(false) => capability(
+                "kernel_memory_limit",
+                CapabilityState::Degraded,
+                "no separate kmem cap; kernel memory is charged to the memory limit",
+            ),
+            Err(e) => capability(
+                "kernel_memory_limit",
+                CapabilityState::Absent,
+                e.to_string(),
+            ),
+        }
+    });
+
+    // Wall clock limits are enforced by the supervisor itself
+    capabilities.push(capability(
+        "wall_time_limit",
+        CapabilityState::Active,
+        "enforced by the supervisor",
+    ));
+
+    // Namespaces
+    capabilities.push(if !NamespaceIsolation::is_supported() {
+        capability(
+            "namespaces",
+            CapabilityState::Absent,
+            "/proc/self/ns is not available",
+        )
+    } else if !is_root {
+        capability(
+            "namespaces",
+            CapabilityState::Absent,
+            "namespace isolation requires root privileges",
+        )
+    } else {
+        capability(
+            "namespaces",
+            CapabilityState::Active,
+            "pid/mount/network/ipc/uts",
+        )
+    });
+
+    // Filesystem isolation
+    capabilities.push(if is_root {
+        capability("chroot", CapabilityState::Active, "chroot and bind mounts")
+    } else {
+        capability(
+            "chroot",
+            CapabilityState::Absent,
+            "code can access the host filesystem without root",
+        )
+    });
+    capabilities.push(match crate::landlock::abi_version() {
+        Some(version) => capability(
+            "landlock",
+            CapabilityState::Active,
+            format!(
+                "Landlock ABI v{} filesystem confinement available with --landlock",
+                version
+            ),
+        ),
+        None => capability(
+            "landlock",
+            CapabilityState::Absent,
+            "kernel has no Landlock (Linux 5.13+ with landlock enabled)",
+        ),
+    });
+    capabilities.push(if is_root {
+        capability(
+            "uid_switch",
+            CapabilityState::Active,
+            "runs as the configured uid/gid",
+        )
+    } else {
+        capability(
+            "uid_switch",
+            CapabilityState::Absent,
+            "runs as the invoking user",
+        )
+    });
+
+    // Syscall filtering
+    capabilities.push(capability(
+        "syscall_filter",
+        CapabilityState::Absent,
+        "no syscall filter is built into this binary",
+    ));
+
+    // Auditing
+    capabilities.push(if crate::file_audit::FileAudit::supported() {
+        capability(
+            "file_audit",
+            CapabilityState::Active,
+            "fanotify write audit available with --audit-writes",
+        )
+    } else {
+        capability(
+            "file_audit",
+            CapabilityState::Absent,
+            "fanotify requires CAP_SYS_ADMIN",
+        )
+    });
+    capabilities.push(if crate::syscall_audit::SyscallAudit::supported() {
+        capability(
+            "syscall_audit",
+            CapabilityState::Active,
+            "syscall profile recording (--syscall-profile) and exec limits (--max-execs) available",
+        )
+    } else {
+        capability(
+            "syscall_audit",
+            CapabilityState::Absent,
+            if cfg!(feature = "seccomp-native") {
+                "seccomp user notification requires x86_64 and Linux 5.5+"
+            } else {
+                "built without the seccomp-native feature"
+            },
+        )
+    });
+
+    // Host protection
+    capabilities.push(if Path::new("/proc/meminfo").exists() {
+        capability(
+            "admission_control",
+            CapabilityState::Active,
+            "memory, pids and disk checked before each run",
+        )
+    } else {
+        capability(
+            "admission_control",
+            CapabilityState::Absent,
+            "/proc/meminfo is not readable",
+        )
+    });
+    capabilities.push(if HostPressure::probe().is_some() {
+        capability(
+            "pressure_admission",
+            CapabilityState::Active,
+            "admission pauses on host PSI pressure",
+        )
+    } else {
+        capability(
+            "pressure_admission",
+            CapabilityState::Degraded,
+            "kernel has no PSI; admission uses capacity checks only",
+        )
+    });
+    capabilities.push(capability(
+        "crash_watchdog",
+        if cfg!(unix) {
+            CapabilityState::Active
+        } else {
+            CapabilityState::Absent
+        },
+        "sandbox is killed if the supervisor dies",
+    ));
+
+    CapabilityReport {
+        uid,
+        euid,
+        kernel,
+        capabilities,
+    }
+}
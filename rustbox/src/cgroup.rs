@@ -2,13 +2,88 @@
 use crate::types::{IsolateError, Result};
 use std::collections::HashSet;
 use std::fs;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Where the cgroup v1 controller hierarchies are mounted unless set otherwise
+pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+static CGROUP_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Directory the controller hierarchies (memory, cpu, pids, ...) are mounted in
+pub fn cgroup_root() -> &'static Path {
+    CGROUP_ROOT.get_or_init(|| PathBuf::from(DEFAULT_CGROUP_ROOT))
+}
+
+/// Use the hierarchies mounted in `root`; fails once `cgroup_root` was used
+pub fn set_cgroup_root(root: PathBuf) -> Result<()> {
+    CGROUP_ROOT
+        .set(root)
+        .map_err(|_| IsolateError::Cgroup("Cgroup root is already in use".to_string()))
+}
+
+/// Kernel out-of-memory notifications of one memory cgroup
+pub struct OomEvents {
+    source: OomSource,
+    fired: bool,
+}
+
+enum OomSource {
+    /// v1 eventfd registered for memory.oom_control
+    EventFd(OwnedFd),
+    /// memory.events, and its oom_kill count when subscribing
+    Counter(PathBuf, u64),
+}
+
+impl OomEvents {
+    /// Whether the group ran out of memory since subscribing
+    pub fn fired(&mut self) -> bool {
+        if !self.fired {
+            self.fired = match &self.source {
+                OomSource::EventFd(eventfd) => {
+                    // Nonblocking; fails with EAGAIN until the first notification
+                    let mut count = 0u64;
+                    let read = unsafe {
+                        libc::read(
+                            eventfd.as_raw_fd(),
+                            &mut count as *mut u64 as *mut libc::c_void,
+                            std::mem::size_of::<u64>(),
+                        )
+                    };
+                    read == std::mem::size_of::<u64>() as isize
+                }
+                OomSource::Counter(events_file, baseline) => {
+                    oom_kill_count(events_file).is_some_and(|count| count > *baseline)
+                }
+            };
+        }
+        self.fired
+    }
+}
+
+/// `oom_kill` count of a `memory.events` file
+fn oom_kill_count(events_file: &Path) -> Option<u64> {
+    fs::read_to_string(events_file)
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// CFS bandwidth period in microseconds, the kernel default
+const CFS_PERIOD_US: u64 = 100_000;
 
 pub struct Cgroup {
     name: String,
     cgroup_paths: std::collections::HashMap<String, PathBuf>,
     available_controllers: HashSet<String>,
     has_cgroup_support: bool,
+    /// Dropping the handle removes the cgroup; false for `open`ed ones
+    owned: bool,
 }
 
 impl Cgroup {
@@ -21,7 +96,7 @@ impl Cgroup {
         }
 
         let sanitized_name = name.replace("/", "_").replace("..", "_");
-        let cgroup_base = "/sys/fs/cgroup";
+        let cgroup_base = cgroup_root();
 
         // Initialize paths for all controllers
         let mut cgroup_paths = std::collections::HashMap::new();
@@ -39,11 +114,12 @@ impl Cgroup {
                     cgroup_paths: std::collections::HashMap::new(),
                     available_controllers: HashSet::new(),
                     has_cgroup_support: false,
+                    owned: true,
                 });
             }
         }
 
-        let available_controllers = match Self::get_available_controllers() {
+        let available_controllers = match Self::available_controllers() {
             Ok(controllers) => controllers,
             Err(e) => {
                 if strict_mode {
@@ -59,7 +135,7 @@ impl Cgroup {
         };
 
         // Create controller paths and directories
-        let controllers_to_use = ["memory", "cpu", "cpuacct", "pids"];
+        let controllers_to_use = ["memory", "cpu", "cpuacct", "pids", "freezer", "blkio"];
         let mut creation_errors = Vec::new();
 
         for controller in &controllers_to_use {
@@ -113,6 +189,7 @@ impl Cgroup {
                     cgroup_paths: std::collections::HashMap::new(),
                     available_controllers,
                     has_cgroup_support: false,
+                    owned: true,
                 });
             }
         }
@@ -122,6 +199,34 @@ impl Cgroup {
             cgroup_paths,
             available_controllers,
             has_cgroup_support: true,
+            owned: true,
+        })
+    }
+
+    /// Handle on the existing cgroup `name`, e.g. of a run in another process
+    ///
+    /// Nothing is created, and dropping the handle leaves the cgroup and its
+    /// processes alone. `None` when the cgroup does not exist.
+    pub fn open(name: &str) -> Option<Self> {
+        let sanitized_name = name.replace("/", "_").replace("..", "_");
+        let available_controllers = Self::available_controllers().ok()?;
+        let cgroup_paths: std::collections::HashMap<String, PathBuf> = available_controllers
+            .iter()
+            .map(|controller| {
+                let path = cgroup_root().join(controller).join(&sanitized_name);
+                (controller.clone(), path)
+            })
+            .filter(|(_, path)| path.is_dir())
+            .collect();
+        if cgroup_paths.is_empty() {
+            return None;
+        }
+        Some(Self {
+            name: sanitized_name,
+            cgroup_paths,
+            available_controllers,
+            has_cgroup_support: true,
+            owned: false,
         })
     }
 
@@ -178,6 +283,231 @@ impl Cgroup {
         Ok(())
     }
 
+    /// Let the group use `swap_bytes` of swap on top of `memory_bytes`
+    ///
+    /// `set_memory_limit` allows no swap. Uses `memory.swap.max` where the
+    /// hierarchy provides it and the v1 `memory.memsw.limit_in_bytes` (memory
+    /// plus swap) otherwise; without swap accounting it cannot be enforced.
+    pub fn set_swap_limit(&self, memory_bytes: u64, swap_bytes: u64) -> Result<()> {
+        if !self.has_cgroup_support || !self.available_controllers.contains("memory") {
+            return Ok(());
+        }
+
+        let memory_path = self.cgroup_paths.get("memory").ok_or_else(|| {
+            IsolateError::Cgroup("Memory controller path not available".to_string())
+        })?;
+
+        let swap_max_file = memory_path.join("memory.swap.max");
+        let memsw_file = memory_path.join("memory.memsw.limit_in_bytes");
+        let (limit_file, limit) = if swap_max_file.exists() {
+            (swap_max_file, swap_bytes)
+        } else if memsw_file.exists() {
+            (memsw_file, memory_bytes.saturating_add(swap_bytes))
+        } else {
+            return Err(IsolateError::Cgroup(
+                "Swap accounting is not enabled (boot with swapaccount=1)".to_string(),
+            ));
+        };
+        fs::write(&limit_file, limit.to_string())
+            .map_err(|e| IsolateError::Cgroup(format!("Failed to set swap limit: {}", e)))?;
+
+        // set_memory_limit keeps the group out of swap; let the kernel decide again
+        if swap_bytes > 0 {
+            if let Ok(swappiness) = fs::read_to_string("/proc/sys/vm/swappiness") {
+                let _ = fs::write(memory_path.join("memory.swappiness"), swappiness.trim());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set a soft memory limit (`memory.soft_limit_in_bytes`)
+    ///
+    /// The run is not killed above it: when the host runs short of memory the
+    /// group is reclaimed back towards the soft limit first, which slows a
+    /// program working above it, until it reaches the hard limit.
+    pub fn set_memory_high(&self, limit_bytes: u64) -> Result<()> {
+        if !self.has_cgroup_support || !self.available_controllers.contains("memory") {
+            return Ok(());
+        }
+
+        Self::validate_memory_limit(limit_bytes)?;
+
+        let memory_path = self.cgroup_paths.get("memory").ok_or_else(|| {
+            IsolateError::Cgroup("Memory controller path not available".to_string())
+        })?;
+
+        fs::write(
+            memory_path.join("memory.soft_limit_in_bytes"),
+            limit_bytes.to_string(),
+        )
+        .map_err(|e| IsolateError::Cgroup(format!("Failed to set soft memory limit: {}", e)))?;
+
+        Ok(())
+    }
+
+    /// Cap kernel memory (kmem) charged to the group
+    ///
+    /// Must be set before any task joins the group. Returns `false` when the
+    /// kernel has no separate kmem cap (it was deprecated in 5.16 and writes are
+    /// ignored); kernel allocations are then still charged to the memory limit.
+    pub fn set_kernel_memory_limit(&self, limit_bytes: u64) -> Result<bool> {
+        if !self.has_cgroup_support || !self.available_controllers.contains("memory") {
+            return Ok(false);
+        }
+
+        Self::validate_memory_limit(limit_bytes)?;
+
+        let memory_path = self.cgroup_paths.get("memory").ok_or_else(|| {
+            IsolateError::Cgroup("Memory controller path not available".to_string())
+        })?;
+
+        let limit_file = memory_path.join("memory.kmem.limit_in_bytes");
+        if !limit_file.exists() {
+            return Ok(false);
+        }
+        if let Err(e) = fs::write(&limit_file, limit_bytes.to_string()) {
+            // EOPNOTSUPP/ENOTSUPP: the kernel refuses kmem limits outright
+            if matches!(e.raw_os_error(), Some(libc::EOPNOTSUPP) | Some(524)) {
+                return Ok(false);
+            }
+            return Err(IsolateError::Cgroup(format!(
+                "Failed to set kernel memory limit: {}",
+                e
+            )));
+        }
+
+        // The kernel rounds the limit down to a page; anything above it means ignored
+        let applied = fs::read_to_string(&limit_file)
+            .ok()
+            .and_then(|content| content.trim().parse::<u64>().ok())
+            .is_some_and(|applied| applied <= limit_bytes);
+        Ok(applied)
+    }
+
+    /// Cap memory used for TCP socket buffers
+    pub fn set_tcp_memory_limit(&self, limit_bytes: u64) -> Result<()> {
+        if !self.has_cgroup_support || !self.available_controllers.contains("memory") {
+            return Ok(());
+        }
+
+        Self::validate_memory_limit(limit_bytes)?;
+
+        let memory_path = self.cgroup_paths.get("memory").ok_or_else(|| {
+            IsolateError::Cgroup("Memory controller path not available".to_string())
+        })?;
+
+        let limit_file = memory_path.join("memory.kmem.tcp.limit_in_bytes");
+        if limit_file.exists() {
+            fs::write(&limit_file, limit_bytes.to_string()).map_err(|e| {
+                IsolateError::Cgroup(format!("Failed to set TCP buffer memory limit: {}", e))
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Peak kernel memory usage of the group in bytes
+    pub fn get_kernel_memory_peak(&self) -> Option<u64> {
+        if !self.has_cgroup_support {
+            return None;
+        }
+
+        let memory_path = self.cgroup_paths.get("memory")?;
+        fs::read_to_string(memory_path.join("memory.kmem.max_usage_in_bytes"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// User and system CPU seconds of the group (`cpuacct.usage_user` and
+    /// `cpuacct.usage_sys`)
+    pub fn get_cpu_split(&self) -> Option<(f64, f64)> {
+        let cpuacct_path = self.cgroup_paths.get("cpuacct")?;
+        let seconds = |file: &str| {
+            fs::read_to_string(cpuacct_path.join(file))
+                .ok()?
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .map(|usage_ns| usage_ns as f64 / 1_000_000_000.0)
+        };
+        Some((
+            seconds("cpuacct.usage_user")?,
+            seconds("cpuacct.usage_sys")?,
+        ))
+    }
+
+    /// Minor and major page faults of the group (`memory.stat`)
+    pub fn get_page_faults(&self) -> Option<(u64, u64)> {
+        let memory_path = self.cgroup_paths.get("memory")?;
+        let stat = fs::read_to_string(memory_path.join("memory.stat")).ok()?;
+        let value = |key: &str| {
+            stat.lines().find_map(|line| {
+                let (name, count) = line.split_once(' ')?;
+                (name == key).then(|| count.trim().parse::<u64>().ok())?
+            })
+        };
+        // pgfault counts every fault, the major ones included
+        let faults = value("total_pgfault").or_else(|| value("pgfault"))?;
+        let major = value("total_pgmajfault").or_else(|| value("pgmajfault"))?;
+        Some((faults.saturating_sub(major), major))
+    }
+
+    /// Bytes the group read from and wrote to block devices
+    /// (`blkio.throttle.io_service_bytes`)
+    ///
+    /// Writes that go through the page cache reach the disk from writeback,
+    /// which v1 does not charge to the group.
+    pub fn get_block_io(&self) -> Option<(u64, u64)> {
+        let blkio_path = self.cgroup_paths.get("blkio")?;
+        let stats = fs::read_to_string(blkio_path.join("blkio.throttle.io_service_bytes")).ok()?;
+        let (mut read, mut written) = (0, 0);
+        // "MAJ:MIN Read 4096" per device and operation, then "Total N"
+        for line in stats.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [_, operation, bytes] = fields[..] else {
+                continue;
+            };
+            let bytes: u64 = bytes.parse().unwrap_or(0);
+            match operation {
+                "Read" => read += bytes,
+                "Write" => written += bytes,
+                _ => {}
+            }
+        }
+        Some((read, written))
+    }
+
+    /// Total memory stall time in microseconds from PSI
+    ///
+    /// Cgroup v1 keeps the group's pressure files in its cpuacct directory,
+    /// and only when the kernel was booted with `psi_v1=1`; otherwise there is
+    /// no per-group stall time and this is `None`. The host-wide
+    /// `/proc/pressure/memory` would charge the run with other tasks' stalls.
+    pub fn memory_stall_total_us(&self) -> Option<u64> {
+        let pressure_file = self.cgroup_paths.get("cpuacct")?.join("memory.pressure");
+
+        let content = fs::read_to_string(pressure_file).ok()?;
+        content
+            .lines()
+            .find(|line| line.starts_with("some"))?
+            .split_whitespace()
+            .find_map(|field| field.strip_prefix("total="))
+            .and_then(|total| total.parse().ok())
+    }
+
+    /// Forks refused because the process limit was reached (`pids.events`)
+    pub fn forks_denied(&self) -> Option<u64> {
+        let pids_path = self.cgroup_paths.get("pids")?;
+        fs::read_to_string(pids_path.join("pids.events"))
+            .ok()?
+            .lines()
+            .find_map(|line| line.strip_prefix("max "))
+            .and_then(|count| count.trim().parse().ok())
+    }
+
     pub fn set_cpu_limit(&self, cpu_shares: u64) -> Result<()> {
         if !self.has_cgroup_support || !self.available_controllers.contains("cpu") {
             return Ok(());
@@ -204,6 +534,72 @@ impl Cgroup {
         Ok(())
     }
 
+    /// Cap CPU bandwidth at `percent` of one core (200 = two cores)
+    ///
+    /// Uses `cpu.max` where the hierarchy provides it and the v1
+    /// `cpu.cfs_quota_us` over the group's `cpu.cfs_period_us` otherwise. Once
+    /// the group used its quota it is throttled until the next period.
+    pub fn set_cpu_quota(&self, percent: u32) -> Result<()> {
+        if !self.has_cgroup_support || !self.available_controllers.contains("cpu") {
+            return Ok(());
+        }
+        if percent == 0 {
+            return Err(IsolateError::Cgroup(
+                "CPU quota must be greater than zero".to_string(),
+            ));
+        }
+
+        let cpu_path = self
+            .cgroup_paths
+            .get("cpu")
+            .ok_or_else(|| IsolateError::Cgroup("CPU controller path not available".to_string()))?;
+        // The kernel refuses quotas under 1ms
+        let quota = |period: u64| (period * percent as u64 / 100).max(1000);
+
+        let max_file = cpu_path.join("cpu.max");
+        let result = if max_file.exists() {
+            fs::write(
+                &max_file,
+                format!("{} {}", quota(CFS_PERIOD_US), CFS_PERIOD_US),
+            )
+        } else {
+            let period = fs::read_to_string(cpu_path.join("cpu.cfs_period_us"))
+                .ok()
+                .and_then(|period| period.trim().parse().ok())
+                .unwrap_or(CFS_PERIOD_US);
+            fs::write(cpu_path.join("cpu.cfs_quota_us"), quota(period).to_string())
+        };
+        result.map_err(|e| IsolateError::Cgroup(format!("Failed to set CPU quota: {}", e)))
+    }
+
+    /// Confine the cgroup to `cpus` with the cpuset controller
+    ///
+    /// The cpuset is only created for boxes that ask for it, since its tasks
+    /// cannot run until CPUs and memory nodes are assigned. Returns false when
+    /// the controller is unavailable.
+    pub fn set_cpu_affinity(&mut self, cpus: &[usize]) -> Result<bool> {
+        if !self.has_cgroup_support || !self.available_controllers.contains("cpuset") {
+            return Ok(false);
+        }
+
+        let root = cgroup_root().join("cpuset");
+        let cpuset_path = root.join(&self.name);
+        fs::create_dir_all(&cpuset_path)
+            .map_err(|e| IsolateError::Cgroup(format!("Failed to create cpuset: {}", e)))?;
+        self.cgroup_paths
+            .insert("cpuset".to_string(), cpuset_path.clone());
+
+        // Memory nodes are not restricted, only CPUs
+        let mems = fs::read_to_string(root.join("cpuset.mems"))
+            .map_err(|e| IsolateError::Cgroup(format!("Failed to read cpuset.mems: {}", e)))?;
+        fs::write(cpuset_path.join("cpuset.mems"), mems.trim())
+            .map_err(|e| IsolateError::Cgroup(format!("Failed to set cpuset.mems: {}", e)))?;
+        let cpu_list: Vec<String> = cpus.iter().map(|cpu| cpu.to_string()).collect();
+        fs::write(cpuset_path.join("cpuset.cpus"), cpu_list.join(","))
+            .map_err(|e| IsolateError::Cgroup(format!("Failed to set cpuset.cpus: {}", e)))?;
+        Ok(true)
+    }
+
     pub fn set_process_limit(&self, limit: u64) -> Result<()> {
         if !self.has_cgroup_support || !self.available_controllers.contains("pids") {
             return Ok(());
@@ -261,7 +657,9 @@ impl Cgroup {
         let mut successful_controllers = Vec::new();
 
         // Try to add process to all available controllers atomically
-        let controllers = ["memory", "cpu", "cpuacct", "pids"];
+        let controllers = [
+            "memory", "cpu", "cpuacct", "pids", "cpuset", "freezer", "blkio",
+        ];
 
         for controller in &controllers {
             if let Some(controller_path) = self.cgroup_paths.get(*controller) {
@@ -364,6 +762,51 @@ impl Cgroup {
     }
 
     /// Check if the process hit the memory limit (OOM condition)
+    /// Subscribe to the kernel running the group out of memory
+    ///
+    /// Registers an eventfd for `memory.oom_control` through
+    /// `cgroup.event_control` (v1), or watches the `oom_kill` counter of
+    /// `memory.events` where the hierarchy provides it. Subscribe before the
+    /// process starts so no event is missed.
+    pub fn subscribe_oom(&self) -> Result<Option<OomEvents>> {
+        if !self.has_cgroup_support || !self.available_controllers.contains("memory") {
+            return Ok(None);
+        }
+
+        let memory_path = self.cgroup_paths.get("memory").ok_or_else(|| {
+            IsolateError::Cgroup("Memory controller path not available".to_string())
+        })?;
+
+        let events_file = memory_path.join("memory.events");
+        if events_file.exists() {
+            let baseline = oom_kill_count(&events_file).unwrap_or(0);
+            return Ok(Some(OomEvents {
+                source: OomSource::Counter(events_file, baseline),
+                fired: false,
+            }));
+        }
+
+        let oom_control = fs::File::open(memory_path.join("memory.oom_control"))
+            .map_err(|e| IsolateError::Cgroup(format!("Failed to open oom_control: {}", e)))?;
+        let eventfd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if eventfd < 0 {
+            return Err(IsolateError::Cgroup(format!(
+                "Failed to create OOM eventfd: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        let eventfd = unsafe { OwnedFd::from_raw_fd(eventfd) };
+        let registration = format!("{} {}", eventfd.as_raw_fd(), oom_control.as_raw_fd());
+        fs::write(memory_path.join("cgroup.event_control"), registration).map_err(|e| {
+            IsolateError::Cgroup(format!("Failed to register for OOM events: {}", e))
+        })?;
+
+        Ok(Some(OomEvents {
+            source: OomSource::EventFd(eventfd),
+            fired: false,
+        }))
+    }
+
     pub fn check_oom_killed(&self) -> bool {
         if !self.has_cgroup_support || !self.available_controllers.contains("memory") {
             return false;
@@ -428,7 +871,8 @@ impl Cgroup {
         }
 
         // Method 1: Try cpuacct.usage (nanoseconds, most accurate)
-        let cpuacct_usage_path = Path::new("/sys/fs/cgroup/cpuacct")
+        let cpuacct_usage_path = cgroup_root()
+            .join("cpuacct")
             .join(&self.name)
             .join("cpuacct.usage");
 
@@ -442,7 +886,8 @@ impl Cgroup {
         }
 
         // Method 2: Try cpuacct.stat (USER_HZ units, fallback)
-        let cpuacct_stat_path = Path::new("/sys/fs/cgroup/cpuacct")
+        let cpuacct_stat_path = cgroup_root()
+            .join("cpuacct")
             .join(&self.name)
             .join("cpuacct.stat");
 
@@ -478,6 +923,19 @@ impl Cgroup {
         Ok(0.0)
     }
 
+    /// `cpuacct.usage` of this cgroup: its CPU time in nanoseconds
+    pub fn cpu_usage_file(&self) -> Option<PathBuf> {
+        if !self.has_cgroup_support || !self.available_controllers.contains("cpuacct") {
+            return None;
+        }
+        Some(
+            cgroup_root()
+                .join("cpuacct")
+                .join(&self.name)
+                .join("cpuacct.usage"),
+        )
+    }
+
     /// Get comprehensive resource usage statistics from cgroups exclusively
     pub fn get_resource_stats(&self) -> (f64, u64, bool) {
         let cpu_time = self.get_cpu_usage().unwrap_or(0.0);
@@ -515,6 +973,126 @@ impl Cgroup {
         (oom_killed || memory_limited, false) // (memory_limited, cpu_limited)
     }
 
+    /// SIGKILL every task currently in this cgroup
+    pub fn kill_all_tasks(&self) {
+        for tasks_file in self.task_files() {
+            if let Ok(tasks_content) = fs::read_to_string(&tasks_file) {
+                for line in tasks_content.lines() {
+                    if let Ok(pid) = line.trim().parse::<i32>() {
+                        unsafe {
+                            libc::kill(pid, libc::SIGKILL);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Kill every process in this cgroup and wait until none is left
+    ///
+    /// Signalling the tasks one by one races with a program that keeps
+    /// forking, so on cgroup v2 the kernel kills them all through
+    /// `cgroup.kill`, and on v1 the freezer stops the cgroup first: frozen
+    /// tasks cannot fork, so every one of them is signalled, and they die once
+    /// thawed. Without either, the tasks are signalled until none are left.
+    /// False when some are still there after `timeout`.
+    pub fn reap(&self, timeout: Duration) -> bool {
+        if !self.has_cgroup_support {
+            return true;
+        }
+        let deadline = Instant::now() + timeout;
+
+        for path in self.cgroup_paths.values() {
+            let kill = path.join("cgroup.kill");
+            if kill.exists() {
+                let _ = fs::write(kill, "1");
+            }
+        }
+        let _ = self.freeze(deadline.saturating_duration_since(Instant::now()));
+        self.kill_all_tasks();
+        // Also after a freeze that timed out, leaving the cgroup half frozen
+        let _ = self.thaw();
+
+        while !self.processes().is_empty() {
+            if Instant::now() >= deadline {
+                return false;
+            }
+            self.kill_all_tasks();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        true
+    }
+
+    /// Stop every task in this cgroup, and any it gains, until `thaw`
+    ///
+    /// Waits up to `timeout` for the last task to stop; a cgroup that is
+    /// still freezing then is left so, and has to be thawed all the same.
+    pub fn freeze(&self, timeout: Duration) -> Result<()> {
+        let state = self.freezer_state()?;
+        fs::write(&state, "FROZEN")
+            .map_err(|e| IsolateError::Cgroup(format!("Failed to freeze {}: {}", self.name, e)))?;
+        // FREEZING until the last task has stopped
+        let deadline = Instant::now() + timeout;
+        while !self.is_frozen() {
+            if Instant::now() >= deadline {
+                return Err(IsolateError::Cgroup(format!(
+                    "{} did not freeze within {:?}",
+                    self.name, timeout
+                )));
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        Ok(())
+    }
+
+    /// Let the tasks of a frozen cgroup run again
+    pub fn thaw(&self) -> Result<()> {
+        fs::write(self.freezer_state()?, "THAWED")
+            .map_err(|e| IsolateError::Cgroup(format!("Failed to thaw {}: {}", self.name, e)))
+    }
+
+    /// Whether every task in this cgroup is stopped by `freeze`
+    pub fn is_frozen(&self) -> bool {
+        self.freezer_state()
+            .ok()
+            .and_then(|state| fs::read_to_string(state).ok())
+            .is_some_and(|state| state.trim() == "FROZEN")
+    }
+
+    /// `freezer.state` of this cgroup
+    pub fn freezer_state(&self) -> Result<PathBuf> {
+        self.cgroup_paths
+            .get("freezer")
+            .map(|path| path.join("freezer.state"))
+            .ok_or_else(|| IsolateError::Cgroup(format!("{} has no freezer controller", self.name)))
+    }
+
+    /// `tasks` files of every controller this cgroup is attached to
+    pub fn task_files(&self) -> Vec<PathBuf> {
+        self.cgroup_paths
+            .values()
+            .map(|path| path.join("tasks"))
+            .collect()
+    }
+
+    /// Processes (thread group leaders) currently in this cgroup
+    pub fn processes(&self) -> Vec<u32> {
+        let mut processes: Vec<u32> = self
+            .cgroup_paths
+            .values()
+            .filter_map(|path| fs::read_to_string(path.join("cgroup.procs")).ok())
+            .flat_map(|procs| {
+                procs
+                    .lines()
+                    .filter_map(|line| line.trim().parse().ok())
+                    .collect::<Vec<u32>>()
+            })
+            .collect();
+        processes.sort_unstable();
+        processes.dedup();
+        processes
+    }
+
     pub fn cleanup(&self) -> Result<()> {
         if !self.has_cgroup_support {
             return Ok(());
@@ -530,8 +1108,7 @@ impl Cgroup {
                     for line in tasks_content.lines() {
                         if let Ok(pid) = line.trim().parse::<u32>() {
                             // Try to move process back to root cgroup
-                            let root_tasks =
-                                Path::new("/sys/fs/cgroup").join(controller).join("tasks");
+                            let root_tasks = cgroup_root().join(controller).join("tasks");
                             let _ = fs::write(&root_tasks, pid.to_string());
                         }
                     }
@@ -562,7 +1139,8 @@ impl Cgroup {
         Ok(())
     }
 
-    fn get_available_controllers() -> Result<HashSet<String>> {
+    /// Controllers enabled in /proc/cgroups
+    pub fn available_controllers() -> Result<HashSet<String>> {
         let content = fs::read_to_string("/proc/cgroups")
             .map_err(|e| IsolateError::Cgroup(format!("Failed to read /proc/cgroups: {}", e)))?;
 
@@ -584,13 +1162,15 @@ impl Cgroup {
     }
 
     pub fn cgroups_available() -> bool {
-        Path::new("/proc/cgroups").exists() && Path::new("/sys/fs/cgroup").exists()
+        Path::new("/proc/cgroups").exists() && cgroup_root().exists()
     }
 }
 
 impl Drop for Cgroup {
     fn drop(&mut self) {
-        let _ = self.cleanup();
+        if self.owned {
+            let _ = self.cleanup();
+        }
     }
 }
 
@@ -0,0 +1,192 @@
+/// Stand-in for the cgroup module in builds without the `cgroup-v1` feature
+///
+/// Cgroups are never available, so `Cgroup::new` fails and `Cgroup::open`
+/// finds nothing. `Cgroup` and `OomEvents` have no values at all; their methods
+/// only exist so callers holding an `Option<Cgroup>` compile unchanged.
+use crate::types::{IsolateError, Result};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Where the cgroup v1 controller hierarchies are mounted unless set otherwise
+pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Directory the controller hierarchies would be mounted in
+pub fn cgroup_root() -> &'static Path {
+    Path::new(DEFAULT_CGROUP_ROOT)
+}
+
+/// Ignored: nothing reads the hierarchies without the `cgroup-v1` feature
+pub fn set_cgroup_root(_root: PathBuf) -> Result<()> {
+    Ok(())
+}
+
+pub enum OomEvents {}
+
+impl OomEvents {
+    pub fn fired(&mut self) -> bool {
+        match *self {}
+    }
+}
+
+pub enum Cgroup {}
+
+impl Cgroup {
+    pub fn new(_name: &str, _strict_mode: bool) -> Result<Self> {
+        Err(IsolateError::Cgroup(
+            "rustbox was built without the cgroup-v1 feature".to_string(),
+        ))
+    }
+
+    pub fn open(_name: &str) -> Option<Self> {
+        None
+    }
+
+    pub fn set_memory_limit(&self, _limit_bytes: u64) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn set_swap_limit(&self, _memory_bytes: u64, _swap_bytes: u64) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn set_memory_high(&self, _limit_bytes: u64) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn set_kernel_memory_limit(&self, _limit_bytes: u64) -> Result<bool> {
+        match *self {}
+    }
+
+    pub fn set_tcp_memory_limit(&self, _limit_bytes: u64) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn get_kernel_memory_peak(&self) -> Option<u64> {
+        match *self {}
+    }
+
+    pub fn get_cpu_split(&self) -> Option<(f64, f64)> {
+        match *self {}
+    }
+
+    pub fn get_page_faults(&self) -> Option<(u64, u64)> {
+        match *self {}
+    }
+
+    pub fn get_block_io(&self) -> Option<(u64, u64)> {
+        match *self {}
+    }
+
+    pub fn memory_stall_total_us(&self) -> Option<u64> {
+        match *self {}
+    }
+
+    pub fn forks_denied(&self) -> Option<u64> {
+        match *self {}
+    }
+
+    pub fn set_cpu_limit(&self, _cpu_shares: u64) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn set_cpu_quota(&self, _percent: u32) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn set_cpu_affinity(&mut self, _cpus: &[usize]) -> Result<bool> {
+        match *self {}
+    }
+
+    pub fn set_process_limit(&self, _limit: u64) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn add_process(&self, _pid: u32) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn get_peak_memory_usage(&self) -> Result<u64> {
+        match *self {}
+    }
+
+    pub fn get_current_memory_usage(&self) -> Result<u64> {
+        match *self {}
+    }
+
+    pub fn get_memory_stats(&self) -> Result<(u64, u64, u64)> {
+        match *self {}
+    }
+
+    pub fn subscribe_oom(&self) -> Result<Option<OomEvents>> {
+        match *self {}
+    }
+
+    pub fn check_oom_killed(&self) -> bool {
+        match *self {}
+    }
+
+    pub fn get_cpu_usage(&self) -> Result<f64> {
+        match *self {}
+    }
+
+    pub fn cpu_usage_file(&self) -> Option<PathBuf> {
+        match *self {}
+    }
+
+    pub fn get_resource_stats(&self) -> (f64, u64, bool) {
+        match *self {}
+    }
+
+    pub fn is_resource_limited(&self) -> (bool, bool) {
+        match *self {}
+    }
+
+    pub fn kill_all_tasks(&self) {
+        match *self {}
+    }
+
+    pub fn reap(&self, _timeout: Duration) -> bool {
+        match *self {}
+    }
+
+    pub fn freeze(&self, _timeout: Duration) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn thaw(&self) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        match *self {}
+    }
+
+    pub fn freezer_state(&self) -> Result<PathBuf> {
+        match *self {}
+    }
+
+    pub fn task_files(&self) -> Vec<PathBuf> {
+        match *self {}
+    }
+
+    pub fn processes(&self) -> Vec<u32> {
+        match *self {}
+    }
+
+    pub fn cleanup(&self) -> Result<()> {
+        match *self {}
+    }
+
+    pub fn available_controllers() -> Result<HashSet<String>> {
+        Ok(HashSet::new())
+    }
+
+    pub fn cgroups_available() -> bool {
+        false
+    }
+}
+
+pub fn cgroups_available() -> bool {
+    false
+}
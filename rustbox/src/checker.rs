@@ -0,0 +1,194 @@
+/// Checker/validator phase configuration
+///
+/// Checkers are trusted code, but a buggy checker must still not be able to hang
+/// the judging pipeline. They therefore always run with their own, bounded limits
+/// through the same executor as submissions, and their results carry
+/// `ExecutionPhase::Checker`.
+use crate::config::SyscallConfig;
+use crate::types::{ExecutionResult, ExecutionStatus, IsolateConfig, IsolateError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+
+/// File name the expected output is staged under for the comparator
+pub const EXPECTED_OUTPUT_FILE: &str = "expected_output";
+/// File name the submission's output is staged under for the comparator
+pub const ACTUAL_OUTPUT_FILE: &str = "actual_output";
+
+/// Resource limits and syscall profile for the checker phase
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckerConfig {
+    /// CPU time limit
+    #[serde(default = "default_time_limit")]
+    pub time_limit: Duration,
+    /// Wall clock time limit
+    #[serde(default = "default_wall_time_limit")]
+    pub wall_time_limit: Duration,
+    /// Memory limit in bytes
+    #[serde(default = "default_memory_limit")]
+    pub memory_limit: u64,
+    /// Maximum number of processes
+    #[serde(default = "default_process_limit")]
+    pub process_limit: u32,
+    /// Maximum size of any file the checker writes, in bytes
+    #[serde(default = "default_file_size_limit")]
+    pub file_size_limit: u64,
+    /// Maximum number of open file descriptors
+    #[serde(default = "default_fd_limit")]
+    pub fd_limit: u64,
+    /// Syscall profile for the checker (same format as language profiles)
+    #[serde(default)]
+    pub syscalls: Option<SyscallConfig>,
+}
+
+fn default_time_limit() -> Duration {
+    Duration::from_secs(5)
+}
+
+fn default_wall_time_limit() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_memory_limit() -> u64 {
+    256 * 1024 * 1024
+}
+
+fn default_process_limit() -> u32 {
+    1
+}
+
+fn default_file_size_limit() -> u64 {
+    16 * 1024 * 1024
+}
+
+fn default_fd_limit() -> u64 {
+    64
+}
+
+impl Default for CheckerConfig {
+    fn default() -> Self {
+        Self {
+            time_limit: default_time_limit(),
+            wall_time_limit: default_wall_time_limit(),
+            memory_limit: default_memory_limit(),
+            process_limit: default_process_limit(),
+            file_size_limit: default_file_size_limit(),
+            fd_limit: default_fd_limit(),
+            syscalls: None,
+        }
+    }
+}
+
+impl CheckerConfig {
+    /// Derive the checker's isolate configuration from the submission's
+    ///
+    /// Sandbox layout (workdir, chroot, bindings, uid/gid) is shared with the
+    /// submission so the checker can read its output; every limit is replaced by
+    /// the checker's own so nothing the submission was allowed leaks through.
+    pub fn isolate_config(&self, submission: &IsolateConfig) -> IsolateConfig {
+        let mut config = submission.clone();
+        config.time_limit = Some(self.time_limit);
+        config.cpu_time_limit = Some(self.time_limit);
+        config.wall_time_limit = Some(self.wall_time_limit);
+        config.extra_time = None;
+        config.memory_limit = Some(self.memory_limit);
+        config.process_limit = Some(self.process_limit);
+        config.file_size_limit = Some(self.file_size_limit);
+        config.fd_limit = Some(self.fd_limit);
+        config.enable_network = false;
+        config
+    }
+}
+
+/// External output comparator, specified per problem
+///
+/// The command is run in the sandbox under `limits` with two extra arguments:
+/// the paths of the expected and the actual output. It must print a verdict
+/// token (`AC`, `WA` or `PE`) as the first word of its first non-empty line,
+/// optionally followed by a message; any further lines extend the message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Comparator {
+    /// Comparator command and its leading arguments
+    pub command: Vec<String>,
+    /// Limits the comparator runs under
+    #[serde(default)]
+    pub limits: CheckerConfig,
+}
+
+/// Verdict reported by a comparator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Verdict {
+    /// Accepted
+    #[serde(rename = "AC")]
+    Accepted,
+    /// Wrong answer
+    #[serde(rename = "WA")]
+    WrongAnswer,
+    /// Presentation error
+    #[serde(rename = "PE")]
+    PresentationError,
+}
+
+/// Parsed comparator decision together with the raw comparator run
+#[derive(Debug, Clone)]
+pub struct ComparatorOutcome {
+    pub verdict: Verdict,
+    pub message: String,
+    pub result: ExecutionResult,
+}
+
+impl Comparator {
+    /// Full command line for comparing the expected/actual files staged in
+    /// `staged` (relative to the workdir)
+    pub fn command_line(&self, staged: &Path) -> Vec<String> {
+        let mut command = self.command.clone();
+        for file in [EXPECTED_OUTPUT_FILE, ACTUAL_OUTPUT_FILE] {
+            command.push(staged.join(file).to_string_lossy().into_owned());
+        }
+        command
+    }
+
+    /// Interpret a finished comparator run according to the output contract
+    pub fn parse_outcome(result: ExecutionResult) -> Result<ComparatorOutcome> {
+        if result.status != ExecutionStatus::Success {
+            return Err(IsolateError::Process(format!(
+                "Comparator did not finish cleanly ({:?}): {}",
+                result.status,
+                result.stderr.trim()
+            )));
+        }
+
+        let mut lines = result
+            .stdout
+            .lines()
+            .skip_while(|line| line.trim().is_empty());
+        let first = lines.next().unwrap_or("").trim();
+        let (token, rest) = first.split_once(char::is_whitespace).unwrap_or((first, ""));
+
+        let verdict = match token {
+            "AC" => Verdict::Accepted,
+            "WA" => Verdict::WrongAnswer,
+            "PE" => Verdict::PresentationError,
+            other => {
+                return Err(IsolateError::Process(format!(
+                    "Comparator violated output contract: expected AC/WA/PE, got {:?}",
+                    other
+                )))
+            }
+        };
+
+        let mut message = rest.trim().to_string();
+        for line in lines {
+            if !message.is_empty() {
+                message.push('\n');
+            }
+            message.push_str(line);
+        }
+
+        Ok(ComparatorOutcome {
+            verdict,
+            message: message.trim_end().to_string(),
+            result,
+        })
+    }
+}
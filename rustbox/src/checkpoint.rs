@@ -0,0 +1,185 @@
+/// Checkpoint and restore of a box's running program with CRIU
+///
+/// Long-running stateful evaluations (notebook kernels, REPL sessions) can be
+/// frozen to an image directory and restored later, on this host or another
+/// one with the same files. The `criu` binary does the dump and restore;
+/// rustbox finds the program's process tree through the box cgroup and
+/// records which box the images belong to, so they are only restored there.
+///
+/// CRIU needs root. A program under the syscall audit or the exec limit holds
+/// a seccomp listener CRIU cannot dump, so those runs cannot be checkpointed.
+use crate::cgroup::Cgroup;
+use crate::types::{IsolateError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Where criu is installed when root's sbin directories are not in PATH
+const CRIU_DIRS: [&str; 2] = ["/usr/sbin", "/usr/local/sbin"];
+
+/// Record of the checkpoint, next to the CRIU images
+const CHECKPOINT_FILE: &str = "rustbox-checkpoint.json";
+
+/// Pid file the restored process tree's root is written to
+const RESTORED_PID_FILE: &str = "restored.pid";
+
+/// A checkpoint of the program running in a box
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Box the program ran in
+    pub instance_id: String,
+    /// Root of the dumped process tree
+    pub pid: u32,
+    /// Directory holding the CRIU images
+    pub images_dir: PathBuf,
+    /// When the checkpoint was taken
+    pub created: chrono::DateTime<chrono::Utc>,
+}
+
+impl Checkpoint {
+    /// Read the checkpoint recorded in `images_dir`
+    pub fn load(images_dir: &Path) -> Result<Self> {
+        let content = fs::read_to_string(images_dir.join(CHECKPOINT_FILE)).map_err(|e| {
+            IsolateError::Config(format!(
+                "No rustbox checkpoint in {}: {}",
+                images_dir.display(),
+                e
+            ))
+        })?;
+        serde_json::from_str(&content)
+            .map_err(|e| IsolateError::Config(format!("Invalid checkpoint record: {}", e)))
+    }
+}
+
+/// The criu binary, or an error saying it is not installed
+pub fn criu_binary() -> Result<PathBuf> {
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    std::env::split_paths(&path)
+        .chain(CRIU_DIRS.iter().map(PathBuf::from))
+        .map(|dir| dir.join("criu"))
+        .find(|binary| binary.is_file())
+        .ok_or_else(|| {
+            IsolateError::Config(
+                "CRIU is not installed: checkpoint and restore need the criu binary".to_string(),
+            )
+        })
+}
+
+/// Dump the program running in box `instance_id` to `images_dir`
+///
+/// The program is killed once dumped unless `leave_running` is set.
+pub fn dump(instance_id: &str, images_dir: &Path, leave_running: bool) -> Result<Checkpoint> {
+    let criu = criu_binary()?;
+    let pid = process_tree_root(instance_id)?;
+    fs::create_dir_all(images_dir)?;
+
+    let mut command = Command::new(criu);
+    command
+        .arg("dump")
+        .arg("--tree")
+        .arg(pid.to_string())
+        .args(common_args(images_dir, "dump.log"));
+    if leave_running {
+        command.arg("--leave-running");
+    }
+    run_criu(command, images_dir, "dump.log")?;
+
+    let checkpoint = Checkpoint {
+        instance_id: instance_id.to_string(),
+        pid,
+        images_dir: images_dir.to_path_buf(),
+        created: chrono::Utc::now(),
+    };
+    let record = serde_json::to_string_pretty(&checkpoint)
+        .map_err(|e| IsolateError::Config(format!("Failed to record checkpoint: {}", e)))?;
+    fs::write(images_dir.join(CHECKPOINT_FILE), record)?;
+    Ok(checkpoint)
+}
+
+/// Restore the checkpoint in `images_dir` into box `instance_id`
+///
+/// The restored program runs detached from rustbox; returns its pid.
+pub fn restore(instance_id: &str, images_dir: &Path) -> Result<u32> {
+    let checkpoint = Checkpoint::load(images_dir)?;
+    // CRIU puts the program back into the cgroup and workdir it came from
+    if checkpoint.instance_id != instance_id {
+        return Err(IsolateError::Config(format!(
+            "Checkpoint in {} was taken in {}, not {}",
+            images_dir.display(),
+            checkpoint.instance_id,
+            instance_id
+        )));
+    }
+    let criu = criu_binary()?;
+    let pid_file = images_dir.join(RESTORED_PID_FILE);
+    let _ = fs::remove_file(&pid_file);
+
+    let mut command = Command::new(criu);
+    command
+        .arg("restore")
+        .args(common_args(images_dir, "restore.log"))
+        .arg("--restore-detached")
+        .arg("--pidfile")
+        .arg(&pid_file);
+    run_criu(command, images_dir, "restore.log")?;
+
+    fs::read_to_string(&pid_file)
+        .ok()
+        .and_then(|pid| pid.trim().parse().ok())
+        .ok_or_else(|| IsolateError::Process("criu restore did not report a pid".to_string()))
+}
+
+/// Arguments shared by dump and restore
+fn common_args(images_dir: &Path, log_file: &str) -> Vec<std::ffi::OsString> {
+    vec![
+        "--images-dir".into(),
+        images_dir.into(),
+        "--log-file".into(),
+        log_file.into(),
+        // The program's stdio are pipes to a rustbox that is gone on restore
+        "--shell-job".into(),
+        "--file-locks".into(),
+    ]
+}
+
+/// Run `command`, turning a failure into an error quoting the end of its log
+fn run_criu(mut command: Command, images_dir: &Path, log_file: &str) -> Result<()> {
+    let output = command
+        .output()
+        .map_err(|e| IsolateError::Process(format!("Failed to run criu: {}", e)))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let log = fs::read_to_string(images_dir.join(log_file)).unwrap_or_default();
+    let reason = log
+        .lines()
+        .rev()
+        .find(|line| line.contains("Error"))
+        .map(str::to_string)
+        .unwrap_or_else(|| String::from_utf8_lossy(&output.stderr).trim().to_string());
+    Err(IsolateError::Process(format!(
+        "criu {} failed: {}",
+        log_file.trim_end_matches(".log"),
+        reason
+    )))
+}
+
+/// Root of the process tree running in the box cgroup of `instance_id`
+fn process_tree_root(instance_id: &str) -> Result<u32> {
+    let processes = Cgroup::open(instance_id)
+        .map(|cgroup| cgroup.processes())
+        .unwrap_or_default();
+    processes
+        .iter()
+        .copied()
+        .find(|pid| parent_pid(*pid).is_none_or(|parent| !processes.contains(&parent)))
+        .ok_or_else(|| IsolateError::Process(format!("No program is running in {}", instance_id)))
+}
+
+/// Parent of `pid` from /proc/PID/stat ("pid (comm) state ppid ...")
+fn parent_pid(pid: u32) -> Option<u32> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(1)?.parse().ok()
+}
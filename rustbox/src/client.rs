@@ -0,0 +1,260 @@
+/// Typed client of the gRPC service (`rustbox serve-grpc`)
+///
+/// Lets Rust backends submit work to a rustbox daemon without re-implementing
+/// the wire format of proto/rustbox.proto. A `Client` keeps a small pool of
+/// HTTP/2 connections, which tonic reconnects on its own, and hands calls to
+/// them in turn. Calls failing with `Unavailable`, because the daemon cannot be
+/// reached or is out of host resources, are retried with backoff; such a call
+/// never started a run, so retrying does not run anything twice.
+///
+/// `submit` and `execute` return an `Execution` whose output is read with
+/// `stream_output` and whose result comes from `wait`. As with any gRPC client,
+/// dropping it before the run is over cancels the run.
+use crate::grpc::proto::execute_event::Event;
+use crate::grpc::proto::rustbox_client::RustboxClient;
+use crate::grpc::proto::{
+    CleanupRequest, ExecuteCodeRequest, ExecuteEvent, ExecuteRequest, ExecuteResult, KillRequest,
+    OutputChunk, StatusRequest, StatusResponse,
+};
+use crate::types::{IsolateError, Result};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tonic::metadata::{Ascii, MetadataValue};
+use tonic::transport::{Channel, Endpoint};
+use tonic::{Code, Request, Status, Streaming};
+
+/// How often a failed call is retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts per call, the first one included
+    pub attempts: u32,
+    /// Delay before the first retry, doubled for each further one
+    pub backoff: Duration,
+    /// Longest delay between two attempts
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/// Client settings
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// Bearer token the daemon was started with, if any
+    pub token: Option<String>,
+    /// Connections to the daemon, used in turn
+    pub connections: usize,
+    /// How long establishing a connection may take
+    pub connect_timeout: Duration,
+    pub retry: RetryPolicy,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            token: None,
+            connections: 4,
+            connect_timeout: Duration::from_secs(5),
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// Connection pool to one rustbox daemon; share it between tasks by reference
+pub struct Client {
+    connections: Vec<RustboxClient<Channel>>,
+    next: AtomicUsize,
+    authorization: Option<MetadataValue<Ascii>>,
+    retry: RetryPolicy,
+}
+
+impl Client {
+    /// Connect to the daemon at `endpoint`, such as `http://127.0.0.1:50051`
+    pub async fn connect(endpoint: &str, options: ClientOptions) -> Result<Self> {
+        let endpoint = Endpoint::from_shared(endpoint.to_string())
+            .map_err(|e| IsolateError::Config(format!("Invalid daemon endpoint: {}", e)))?
+            .connect_timeout(options.connect_timeout);
+        let authorization = options
+            .token
+            .map(|token| format!("Bearer {}", token).parse())
+            .transpose()
+            .map_err(|_| IsolateError::Config("Invalid bearer token".to_string()))?;
+
+        let mut connections = Vec::new();
+        for _ in 0..options.connections.max(1) {
+            let channel = endpoint.connect().await.map_err(|e| {
+                IsolateError::ResourceUnavailable(format!("Failed to connect to daemon: {}", e))
+            })?;
+            connections.push(RustboxClient::new(channel));
+        }
+        Ok(Self {
+            connections,
+            next: AtomicUsize::new(0),
+            authorization,
+            retry: options.retry,
+        })
+    }
+
+    /// Compile and run source code in a fresh box
+    pub async fn submit(&self, request: ExecuteCodeRequest) -> Result<Execution> {
+        let box_id = request.box_id;
+        let events = self
+            .call(
+                |mut client, request| async move { client.execute_code(request).await },
+                request,
+            )
+            .await?;
+        Ok(Execution::new(box_id, events))
+    }
+
+    /// Run a command in a box set up with init
+    pub async fn execute(&self, request: ExecuteRequest) -> Result<Execution> {
+        let box_id = request.box_id;
+        let events = self
+            .call(
+                |mut client, request| async move { client.execute(request).await },
+                request,
+            )
+            .await?;
+        Ok(Execution::new(box_id, events))
+    }
+
+    /// Kill the execution running in `box_id`; returns how many processes were signalled
+    pub async fn cancel(&self, box_id: u32) -> Result<u32> {
+        let request = KillRequest { box_id, signal: 0 };
+        let response = self
+            .call(
+                |mut client, request| async move { client.kill(request).await },
+                request,
+            )
+            .await?;
+        Ok(response.signalled)
+    }
+
+    /// Whether `box_id` exists and the usage of the execution running in it
+    pub async fn status(&self, box_id: u32) -> Result<StatusResponse> {
+        let request = StatusRequest { box_id };
+        self.call(
+            |mut client, request| async move { client.status(request).await },
+            request,
+        )
+        .await
+    }
+
+    /// Remove `box_id`
+    pub async fn cleanup(&self, box_id: u32) -> Result<()> {
+        let request = CleanupRequest { box_id };
+        self.call(
+            |mut client, request| async move { client.cleanup(request).await },
+            request,
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Make `rpc` with `message` on the next connection, retrying per the policy
+    async fn call<M, T, F>(
+        &self,
+        rpc: impl Fn(RustboxClient<Channel>, Request<M>) -> F,
+        message: M,
+    ) -> Result<T>
+    where
+        M: Clone,
+        F: Future<Output = std::result::Result<tonic::Response<T>, Status>>,
+    {
+        let mut delay = self.retry.backoff;
+        let mut attempt = 1;
+        loop {
+            let mut request = Request::new(message.clone());
+            if let Some(authorization) = &self.authorization {
+                request
+                    .metadata_mut()
+                    .insert("authorization", authorization.clone());
+            }
+            let index = self.next.fetch_add(1, Ordering::Relaxed) % self.connections.len();
+            match rpc(self.connections[index].clone(), request).await {
+                Err(status)
+                    if status.code() == Code::Unavailable && attempt < self.retry.attempts =>
+                {
+                    log::debug!(
+                        "Daemon unavailable, retrying in {:?}: {}",
+                        delay,
+                        status.message()
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(self.retry.max_backoff);
+                    attempt += 1;
+                }
+                outcome => return outcome.map(tonic::Response::into_inner).map_err(error),
+            }
+        }
+    }
+}
+
+/// A run started on the daemon
+pub struct Execution {
+    box_id: u32,
+    events: Streaming<ExecuteEvent>,
+    result: Option<ExecuteResult>,
+}
+
+impl Execution {
+    fn new(box_id: u32, events: Streaming<ExecuteEvent>) -> Self {
+        Self {
+            box_id,
+            events,
+            result: None,
+        }
+    }
+
+    /// Box the run is in, for `Client::cancel` and `Client::status`
+    pub fn box_id(&self) -> u32 {
+        self.box_id
+    }
+
+    /// Next piece of output, in the order the daemon read it; None once the
+    /// run is over
+    pub async fn stream_output(&mut self) -> Result<Option<OutputChunk>> {
+        while self.result.is_none() {
+            let event = self.events.message().await.map_err(error)?;
+            match event.and_then(|event| event.event) {
+                Some(Event::Output(chunk)) => return Ok(Some(chunk)),
+                Some(Event::Result(result)) => self.result = Some(result),
+                None => {
+                    return Err(IsolateError::Process(
+                        "Daemon ended the run without a result".to_string(),
+                    ))
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Wait for the result; output not read yet is skipped, though the
+    /// result's `json` holds all of it
+    pub async fn wait(mut self) -> Result<ExecuteResult> {
+        while self.stream_output().await?.is_some() {}
+        self.result
+            .take()
+            .ok_or_else(|| IsolateError::Process("Daemon sent no result".to_string()))
+    }
+}
+
+/// The error a daemon call failed with, as the daemon raised it where possible
+fn error(status: Status) -> IsolateError {
+    let message = status.message().to_string();
+    match status.code() {
+        Code::InvalidArgument => IsolateError::Config(message),
+        Code::Aborted => IsolateError::LockBusy,
+        Code::Unavailable => IsolateError::ResourceUnavailable(message),
+        code => IsolateError::Process(format!("Daemon call failed ({}): {}", code, message)),
+    }
+}
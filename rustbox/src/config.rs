@@ -1,8 +1,9 @@
-use crate::types::{IsolateConfig, IsolateError, Result};
+use crate::profile::LanguageProfile;
+use crate::types::{ExecutionOverrides, IsolateConfig, IsolateError, Result};
 /// Configuration loading from config.json
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 /// Language-specific configuration from config.json
@@ -15,6 +16,12 @@ pub struct LanguageConfig {
     pub syscalls: SyscallConfig,
     pub environment: HashMap<String, String>,
     pub compilation: CompilationConfig,
+    /// Source file naming; built-in languages fall back to their defaults
+    #[serde(default)]
+    pub files: Option<FileConventions>,
+    /// Build and run settings replacing the built-in profile (see `profile`)
+    #[serde(default)]
+    pub profile: Option<crate::profile::LanguageProfile>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +34,23 @@ pub struct MemoryConfig {
 pub struct TimeConfig {
     pub cpu_time_seconds: u64,
     pub wall_time_seconds: u64,
+    /// Untimed runs before a batch's measured ones, to let a JIT warm up
+    #[serde(default)]
+    pub warmup_runs: u32,
+}
+
+impl TimeConfig {
+    /// Warm-up runs for `language` from config.json, 0 if it sets none
+    pub fn warmup_runs_for(language: &str) -> u32 {
+        RustBoxConfig::load_default()
+            .ok()
+            .and_then(|config| {
+                config
+                    .get_language_config(language)
+                    .map(|lang_config| lang_config.time.warmup_runs)
+            })
+            .unwrap_or(0)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +58,9 @@ pub struct ProcessConfig {
     pub max_processes: u32,
     pub max_forks: Option<u32>,
     pub max_threads: Option<u32>,
+    /// Exec calls allowed in the run phase, counting the one starting the program
+    #[serde(default)]
+    pub max_execs: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,6 +69,77 @@ pub struct FilesystemConfig {
     pub max_open_files: u32,
     pub additional_read_only_paths: Vec<String>,
     pub required_binaries: Vec<String>,
+    /// Prebuilt root filesystem the language runs on as an overlay
+    #[serde(default)]
+    pub rootfs_image: Option<PathBuf>,
+}
+
+impl FilesystemConfig {
+    /// Host paths the language needs in minimal visibility mode
+    ///
+    /// The required binaries plus the read-only paths; a `*` in the last
+    /// component of a read-only path matches any run of characters.
+    pub fn visible_paths(&self) -> Vec<PathBuf> {
+        self.required_binaries
+            .iter()
+            .map(PathBuf::from)
+            .chain(
+                self.additional_read_only_paths
+                    .iter()
+                    .flat_map(|pattern| expand_pattern(pattern)),
+            )
+            .collect()
+    }
+
+    /// Visible paths for `language` from config.json, if it lists any
+    pub fn visible_paths_for(language: &str) -> Vec<PathBuf> {
+        RustBoxConfig::load_default()
+            .ok()
+            .and_then(|config| {
+                config
+                    .get_language_config(language)
+                    .map(|lang_config| lang_config.filesystem.visible_paths())
+            })
+            .unwrap_or_default()
+    }
+
+    /// Root image for `language` from config.json, if it names one
+    pub fn rootfs_image_for(language: &str) -> Option<PathBuf> {
+        RustBoxConfig::load_default()
+            .ok()?
+            .get_language_config(language)?
+            .filesystem
+            .rootfs_image
+            .clone()
+    }
+}
+
+/// Paths matching `pattern`, which may hold one `*` in its last component
+fn expand_pattern(pattern: &str) -> Vec<PathBuf> {
+    let path = Path::new(pattern);
+    let Some((prefix, suffix)) = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split_once('*'))
+    else {
+        return vec![path.to_path_buf()];
+    };
+    let Some(Ok(entries)) = path.parent().map(std::fs::read_dir) else {
+        return Vec::new();
+    };
+    let mut matches: Vec<PathBuf> = entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.len() >= prefix.len() + suffix.len()
+                && name.starts_with(prefix)
+                && name.ends_with(suffix)
+        })
+        .map(|entry| entry.path())
+        .collect();
+    matches.sort();
+    matches
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -61,6 +159,235 @@ pub struct CompilationConfig {
     pub max_compilation_memory_mb: i64,
 }
 
+impl CompilationConfig {
+    /// Compile-step limits; non-positive values in config.json mean unset
+    pub fn limits(&self) -> ExecutionOverrides {
+        let seconds = u64::try_from(self.max_compilation_time)
+            .ok()
+            .filter(|&s| s > 0)
+            .map(Duration::from_secs);
+        ExecutionOverrides {
+            max_cpu: seconds,
+            max_wall_time: seconds,
+            max_memory: u64::try_from(self.max_compilation_memory_mb)
+                .ok()
+                .filter(|&mb| mb > 0),
+            ..Default::default()
+        }
+    }
+
+    /// Compile-step limits for `language` from config.json, if it has any
+    pub fn limits_for(language: &str) -> ExecutionOverrides {
+        RustBoxConfig::load_default()
+            .ok()
+            .and_then(|config| {
+                config
+                    .get_language_config(language)
+                    .map(|lang_config| lang_config.compilation.limits())
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Source filename and entrypoint conventions for a language
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FileConventions {
+    /// Source extensions, primary first
+    pub extensions: Vec<String>,
+    /// Other names the language may be requested by
+    pub aliases: Vec<String>,
+    /// Name the source is written under in the workdir (`{ext}` and `{class}` are substituted)
+    pub source_file: String,
+    /// How the entrypoint name is derived from the source
+    pub entrypoint: Entrypoint,
+}
+
+/// Entrypoint detection applied to submitted source
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Entrypoint {
+    /// The source file name is fixed by the template
+    #[default]
+    Fixed,
+    /// `{class}` is the public class name, `Main` when none is declared
+    JavaClass,
+}
+
+impl FileConventions {
+    fn new(
+        extensions: &[&str],
+        aliases: &[&str],
+        source_file: &str,
+        entrypoint: Entrypoint,
+    ) -> Self {
+        Self {
+            extensions: extensions.iter().map(|e| e.to_string()).collect(),
+            aliases: aliases.iter().map(|a| a.to_string()).collect(),
+            source_file: source_file.to_string(),
+            entrypoint,
+        }
+    }
+
+    /// Extension used when staging files for this language
+    pub fn primary_extension(&self) -> &str {
+        self.extensions.first().map(String::as_str).unwrap_or("txt")
+    }
+
+    /// Entrypoint name detected from `code`, if the language has one
+    pub fn entrypoint_name(&self, code: &str) -> Option<String> {
+        match self.entrypoint {
+            Entrypoint::Fixed => None,
+            Entrypoint::JavaClass => {
+                Some(java_public_class(code).unwrap_or_else(|| "Main".to_string()))
+            }
+        }
+    }
+
+    /// Workdir file name for `code`
+    pub fn source_file_name(&self, code: &str) -> String {
+        let mut name = self.source_file.replace("{ext}", self.primary_extension());
+        if let Some(entrypoint) = self.entrypoint_name(code) {
+            name = name.replace("{class}", &entrypoint);
+        }
+        name
+    }
+
+    /// Standardized name a CLI-staged file gets in box `box_id`
+    pub fn staged_file_name(&self, box_id: u32) -> String {
+        format!("{}.{}", box_id, self.primary_extension())
+    }
+}
+
+/// Extract the public class name from Java source (simple line-based heuristic)
+fn java_public_class(code: &str) -> Option<String> {
+    code.lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("public class "))
+        .find_map(|line| line.split_whitespace().nth(2))
+        .map(|name| name.trim_end_matches('{').trim().to_string())
+}
+
+/// Language names and their file conventions, in lookup order
+#[derive(Debug, Clone)]
+pub struct LanguageRegistry {
+    languages: Vec<(String, FileConventions)>,
+}
+
+impl LanguageRegistry {
+    /// Conventions for the languages rustbox knows out of the box
+    pub fn builtin() -> Self {
+        Self {
+            languages: vec![
+                (
+                    "python".to_string(),
+                    FileConventions::new(
+                        &["py"],
+                        &["py", "python3"],
+                        "solution.{ext}",
+                        Entrypoint::Fixed,
+                    ),
+                ),
+                (
+                    "cpp".to_string(),
+                    FileConventions::new(
+                        &["cpp", "cc", "cxx"],
+                        &["c++", "cxx"],
+                        "solution.{ext}",
+                        Entrypoint::Fixed,
+                    ),
+                ),
+                (
+                    "java".to_string(),
+                    FileConventions::new(&["java"], &[], "{class}.java", Entrypoint::JavaClass),
+                ),
+                (
+                    "go".to_string(),
+                    FileConventions::new(&["go"], &["golang"], "main.go", Entrypoint::Fixed),
+                ),
+            ],
+        }
+    }
+
+    /// Built-in conventions overridden and extended by `files` sections in config.json
+    pub fn from_config(config: &RustBoxConfig) -> Self {
+        let mut registry = Self::builtin();
+        let mut names: Vec<&String> = config.languages.keys().collect();
+        names.sort();
+        for name in names {
+            let Some(files) = config.languages[name].files.clone() else {
+                continue;
+            };
+            registry.insert(name.to_lowercase(), files);
+        }
+        registry
+    }
+
+    fn insert(&mut self, name: String, files: FileConventions) {
+        match self.languages.iter_mut().find(|(known, _)| *known == name) {
+            Some(entry) => entry.1 = files,
+            None => self.languages.push((name, files)),
+        }
+    }
+
+    /// Registry for ./config.json, or the built-in one when it cannot be loaded,
+    /// plus the languages of registered profiles
+    pub fn load() -> Self {
+        let mut registry = RustBoxConfig::load_default()
+            .map(|config| Self::from_config(&config))
+            .unwrap_or_else(|_| Self::builtin());
+        for (name, files) in LanguageProfile::registered_files() {
+            registry.insert(name, files);
+        }
+        registry
+    }
+
+    /// Add or replace language `name` for every later `load()` in this process
+    ///
+    /// `files` says where the source goes, `profile` how it is built and run
+    /// and under which default limits (its `name` and `files` are replaced).
+    /// Compiles through `Isolate` pick the language up by name or alias.
+    pub fn register(name: &str, files: FileConventions, profile: LanguageProfile) -> Result<()> {
+        LanguageProfile::register(LanguageProfile {
+            name: name.to_string(),
+            files: Some(files),
+            ..profile
+        })
+    }
+
+    /// Canonical language name for a name or alias
+    pub fn resolve(&self, language: &str) -> Option<&str> {
+        let language = language.to_lowercase();
+        self.languages
+            .iter()
+            .find(|(name, files)| *name == language || files.aliases.contains(&language))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Canonical language name for a source file extension
+    pub fn by_extension(&self, extension: &str) -> Option<&str> {
+        let extension = extension.to_lowercase();
+        self.languages
+            .iter()
+            .find(|(_, files)| files.extensions.contains(&extension))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// File conventions for a language name or alias
+    pub fn conventions(&self, language: &str) -> Option<&FileConventions> {
+        let language = self.resolve(language)?;
+        self.languages
+            .iter()
+            .find(|(name, _)| name == language)
+            .map(|(_, files)| files)
+    }
+
+    /// Canonical names in lookup order
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.languages.iter().map(|(name, _)| name.as_str())
+    }
+}
+
 /// Full config.json structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RustBoxConfig {
@@ -70,13 +397,60 @@ pub struct RustBoxConfig {
     pub languages: HashMap<String, LanguageConfig>,
 }
 
+/// First uid of the per-box range when config.json sets none (isolate's default)
+pub const DEFAULT_FIRST_UID: u32 = 60000;
+
+/// First gid of the per-box range when config.json sets none
+pub const DEFAULT_FIRST_GID: u32 = 60000;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IsolateGlobalConfig {
     pub box_dir: String,
     pub run_dir: String,
     pub user: String,
     pub group: String,
+    /// Host variables every language run inherits
     pub preserve_env: Vec<String>,
+    /// Box N runs as uid `first_uid + N`
+    #[serde(default = "default_first_uid")]
+    pub first_uid: u32,
+    /// Box N runs as gid `first_gid + N`
+    #[serde(default = "default_first_gid")]
+    pub first_gid: u32,
+    /// Host paths bound read-only into every chroot
+    #[serde(default = "crate::types::default_chroot_bind_paths")]
+    pub chroot_bind_paths: Vec<PathBuf>,
+    /// Cache of compiled programs shared by every box
+    #[serde(default)]
+    pub artifact_cache: crate::artifact_cache::ArtifactCacheOptions,
+    /// Boxes `init --box-id auto` and `Isolate::new_auto` pick from
+    #[serde(default)]
+    pub auto_box_ids: BoxIdRange,
+}
+
+/// Box ids `first..first + count`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BoxIdRange {
+    pub first: u32,
+    pub count: u32,
+}
+
+impl Default for BoxIdRange {
+    fn default() -> Self {
+        Self {
+            first: 10_000,
+            count: 10_000,
+        }
+    }
+}
+
+fn default_first_uid() -> u32 {
+    DEFAULT_FIRST_UID
+}
+
+fn default_first_gid() -> u32 {
+    DEFAULT_FIRST_GID
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -99,6 +473,9 @@ pub struct SecurityConfig {
     pub use_cgroups: bool,
     pub no_new_privileges: bool,
     pub chroot_jail: bool,
+    /// Security audit log rotation and system log sink
+    #[serde(default)]
+    pub audit_log: crate::security_logging::SecurityLogOptions,
 }
 
 impl RustBoxConfig {
@@ -142,6 +519,11 @@ impl IsolateConfig {
 
         // Try to load config.json and apply language-specific settings
         if let Ok(rustbox_config) = RustBoxConfig::load_default() {
+            config.drop_capabilities = rustbox_config.security.drop_capabilities;
+            config.no_new_privileges = rustbox_config.security.no_new_privileges;
+            config.chroot_bind_paths = rustbox_config.isolate.chroot_bind_paths.clone();
+            config.preserve_env = rustbox_config.isolate.preserve_env.clone();
+
             if let Some(lang_config) = rustbox_config.get_language_config(language) {
                 // Apply memory limits
                 config.memory_limit = Some(lang_config.memory.limit_mb * 1024 * 1024);
@@ -155,6 +537,7 @@ impl IsolateConfig {
 
                 // Apply process limits
                 config.process_limit = Some(lang_config.processes.max_processes);
+                config.exec_limit = lang_config.processes.max_execs;
 
                 // Apply file system limits
                 config.file_size_limit = Some(lang_config.filesystem.max_file_size_kb * 1024);
@@ -168,6 +551,9 @@ impl IsolateConfig {
                     lang_config.time.wall_time_seconds
                 );
                 eprintln!("   Max processes: {}", lang_config.processes.max_processes);
+                if let Some(max_execs) = lang_config.processes.max_execs {
+                    eprintln!("   Max exec calls: {}", max_execs);
+                }
             } else {
                 eprintln!(
                     "⚠️  Warning: Language '{}' not found in config.json, using defaults",
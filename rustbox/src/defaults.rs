@@ -0,0 +1,233 @@
+/// Defaults for CLI flags, read from config files and the environment
+///
+/// Layers, each overriding the one before: `/etc/rustbox/config.toml`, the
+/// user's `~/.config/rustbox/config.toml` (under `$XDG_CONFIG_HOME` when set),
+/// `RUSTBOX_*` environment variables, and finally the flags given on the
+/// command line. A missing file is skipped; an unreadable one, an unknown key
+/// or a bad value is an error, so a typo never silently drops a limit.
+///
+/// ```toml
+/// strict = true
+/// home = "/var/lib/rustbox"
+/// cgroup_root = "/sys/fs/cgroup"
+/// lock_dir = "/run/rustbox/locks"
+/// state_dir = "/var/lib/rustbox/state"
+///
+/// [limits]
+/// mem = 256           # MB
+/// cpu = 2.0           # seconds
+/// wall_time = 5       # seconds
+/// processes = 16
+/// output_limit = 1024 # KB
+/// ```
+use crate::paths::{self, PathProvider};
+use crate::types::{IsolateError, Result};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// System-wide config file, the lowest layer
+pub const SYSTEM_CONFIG: &str = "/etc/rustbox/config.toml";
+
+pub const STRICT_ENV: &str = "RUSTBOX_STRICT";
+pub const CGROUP_ROOT_ENV: &str = "RUSTBOX_CGROUP_ROOT";
+pub const LOCK_DIR_ENV: &str = "RUSTBOX_LOCK_DIR";
+pub const MEM_ENV: &str = "RUSTBOX_MEM";
+pub const CPU_ENV: &str = "RUSTBOX_CPU";
+pub const WALL_TIME_ENV: &str = "RUSTBOX_WALL_TIME";
+pub const PROCESSES_ENV: &str = "RUSTBOX_PROCESSES";
+pub const OUTPUT_LIMIT_ENV: &str = "RUSTBOX_OUTPUT_LIMIT";
+
+/// Settings that apply when the command line does not give them
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CliDefaults {
+    /// Strict mode for the commands that have `--strict`; a flag can only turn it on
+    pub strict: Option<bool>,
+    /// Home of all rustbox state and scratch files, as `RUSTBOX_HOME`
+    pub home: Option<PathBuf>,
+    /// Directory the cgroup v1 controllers are mounted in
+    pub cgroup_root: Option<PathBuf>,
+    /// Directory for box lock files
+    pub lock_dir: Option<PathBuf>,
+    /// State directory if not in `home`, as `RUSTBOX_STATE_DIR`
+    pub state_dir: Option<PathBuf>,
+    pub limits: DefaultLimits,
+}
+
+/// Limits for commands run without the matching flag; keys are the flag names
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DefaultLimits {
+    /// Memory limit in MB
+    pub mem: Option<u64>,
+    /// CPU limit in seconds
+    pub cpu: Option<f64>,
+    /// Wall clock time limit in seconds
+    pub wall_time: Option<f64>,
+    pub processes: Option<u32>,
+    /// Combined stdout and stderr limit in KB
+    pub output_limit: Option<u64>,
+}
+
+impl CliDefaults {
+    /// The system and user config files, then the process environment
+    pub fn load() -> Result<Self> {
+        let mut files = vec![PathBuf::from(SYSTEM_CONFIG)];
+        files.extend(user_config_path());
+        Self::load_layers(&files, |name| std::env::var(name).ok())
+    }
+
+    /// `files` in order, then the variables `env` looks up
+    pub fn load_layers(files: &[PathBuf], env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut defaults = Self::default();
+        for path in files {
+            let contents = match std::fs::read_to_string(path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(IsolateError::Config(format!(
+                        "Failed to read {}: {}",
+                        path.display(),
+                        e
+                    )))
+                }
+            };
+            let layer = toml::from_str(&contents).map_err(|e: toml::de::Error| {
+                let line = e
+                    .span()
+                    .map_or(1, |span| contents[..span.start].matches('\n').count() + 1);
+                IsolateError::Config(format!(
+                    "Invalid {} line {}: {}",
+                    path.display(),
+                    line,
+                    e.message()
+                ))
+            })?;
+            defaults = defaults.merge(layer);
+        }
+        defaults = defaults.merge(Self::from_env(env)?);
+        defaults.limits.validate()?;
+        Ok(defaults)
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        toml::from_str(contents)
+            .map_err(|e| IsolateError::Config(e.to_string().trim_end().to_string()))
+    }
+
+    /// The `RUSTBOX_*` variables `env` looks up; empty ones count as unset
+    fn from_env(env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let strict = match env_value::<String>(&env, STRICT_ENV)?.as_deref() {
+            None => None,
+            Some("1" | "true") => Some(true),
+            Some("0" | "false") => Some(false),
+            Some(value) => {
+                return Err(IsolateError::Config(format!(
+                    "Invalid {}: {} (expected true, false, 1 or 0)",
+                    STRICT_ENV, value
+                )))
+            }
+        };
+        Ok(Self {
+            strict,
+            home: env_value(&env, paths::HOME_ENV)?,
+            cgroup_root: env_value(&env, CGROUP_ROOT_ENV)?,
+            lock_dir: env_value(&env, LOCK_DIR_ENV)?,
+            state_dir: env_value(&env, paths::STATE_DIR_ENV)?,
+            limits: DefaultLimits {
+                mem: env_value(&env, MEM_ENV)?,
+                cpu: env_value(&env, CPU_ENV)?,
+                wall_time: env_value(&env, WALL_TIME_ENV)?,
+                processes: env_value(&env, PROCESSES_ENV)?,
+                output_limit: env_value(&env, OUTPUT_LIMIT_ENV)?,
+            },
+        })
+    }
+
+    /// `self` with every setting `other` has taken from `other`
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            strict: other.strict.or(self.strict),
+            home: other.home.or(self.home),
+            cgroup_root: other.cgroup_root.or(self.cgroup_root),
+            lock_dir: other.lock_dir.or(self.lock_dir),
+            state_dir: other.state_dir.or(self.state_dir),
+            limits: self.limits.merge(other.limits),
+        }
+    }
+
+    /// Point this process at the configured directories
+    ///
+    /// Must run before anything uses the paths, the cgroups or the lock manager.
+    pub fn apply(&self) -> Result<()> {
+        if self.home.is_some() || self.state_dir.is_some() {
+            let provider = PathProvider::resolve(self.home.clone(), self.state_dir.clone());
+            paths::set_paths(provider)?;
+        }
+        if let Some(ref cgroup_root) = self.cgroup_root {
+            crate::cgroup::set_cgroup_root(cgroup_root.clone())?;
+        }
+        if let Some(ref lock_dir) = self.lock_dir {
+            crate::lock_manager::set_lock_directory(lock_dir.clone())
+                .map_err(IsolateError::AdvancedLock)?;
+        }
+        Ok(())
+    }
+}
+
+impl DefaultLimits {
+    pub fn cpu(&self) -> Option<Duration> {
+        seconds(self.cpu)
+    }
+
+    pub fn wall_time(&self) -> Option<Duration> {
+        seconds(self.wall_time)
+    }
+
+    fn merge(self, other: Self) -> Self {
+        Self {
+            mem: other.mem.or(self.mem),
+            cpu: other.cpu.or(self.cpu),
+            wall_time: other.wall_time.or(self.wall_time),
+            processes: other.processes.or(self.processes),
+            output_limit: other.output_limit.or(self.output_limit),
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (name, secs) in [("cpu", self.cpu), ("wall_time", self.wall_time)] {
+            if let (Some(secs), None) = (secs, seconds(secs)) {
+                return Err(IsolateError::Config(format!(
+                    "Invalid default {}: {} seconds",
+                    name, secs
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `$XDG_CONFIG_HOME/rustbox/config.toml`, else `~/.config/rustbox/config.toml`
+pub fn user_config_path() -> Option<PathBuf> {
+    let non_empty = |name| std::env::var_os(name).filter(|value| !value.is_empty());
+    non_empty("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| non_empty("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("rustbox").join("config.toml"))
+}
+
+fn seconds(secs: Option<f64>) -> Option<Duration> {
+    secs.and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+}
+
+fn env_value<T: FromStr>(env: &impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<T>> {
+    let Some(value) = env(name).filter(|value| !value.is_empty()) else {
+        return Ok(None);
+    };
+    value
+        .parse()
+        .map(Some)
+        .map_err(|_| IsolateError::Config(format!("Invalid {}: {}", name, value)))
+}
@@ -0,0 +1,404 @@
+/// Controlled network egress for runs that fetch dependencies
+///
+/// A box with an egress allowlist runs in its own network namespace, as with
+/// `NetworkMode::LoopbackOnly`, plus one end of a veth pair. Before the run the
+/// supervisor creates that namespace on a helper thread, moves the `eth0` end
+/// of a fresh `rbxe<N>` pair into it and gives the pair a /30 out of
+/// `EGRESS_POOL`, with the box routing everything through the host end. On the
+/// host an nftables table of the box's own masquerades what leaves through
+/// other interfaces and accepts new connections from `rbxe<N>` only to
+/// addresses and ports an `EgressRule` allows, whether forwarded or meant for
+/// the host itself; everything else the box sends is dropped.
+///
+/// Needs `ip` (iproute2) and `nft`, and turns on `net.ipv4.ip_forward`. The box
+/// gets IPv4 only. Name resolution is the program's own: the resolver it uses
+/// must be allowlisted like any other destination.
+use crate::types::{IsolateError, NetworkMode, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+/// Addresses handed out to boxes, a /30 each: host end `.1`, box end `.2`
+const EGRESS_POOL: Ipv4Addr = Ipv4Addr::new(10, 231, 0, 0);
+
+/// Boxes `EGRESS_POOL` has room for, which also bounds the `rbxe<N>` names
+const EGRESS_LINKS: u32 = 1 << 14;
+
+/// Counter comment of the rule dropping what the allowlist refuses
+const REFUSED: &str = "refused";
+
+/// Destinations a box may connect to: an address range and optionally a port range
+///
+/// Written `ADDRESS/PREFIX[:PORT[-PORT]]`, e.g. `151.101.0.0/16:443` or
+/// `2a04:4e42::/32:80-443`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct EgressRule {
+    /// Address range
+    pub network: IpAddr,
+    /// Prefix length of the range
+    pub prefix: u8,
+    /// Allowed ports, inclusive; every port when unset
+    pub ports: Option<(u16, u16)>,
+}
+
+impl EgressRule {
+    /// Whether the rule lets the box connect to `destination`
+    pub fn allows(&self, destination: SocketAddr) -> bool {
+        let in_range = match (self.network, destination.ip().to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        };
+        in_range
+            && self
+                .ports
+                .is_none_or(|(first, last)| (first..=last).contains(&destination.port()))
+    }
+}
+
+impl FromStr for EgressRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid egress rule '{}'. Use: ADDRESS/PREFIX[:PORT[-PORT]]",
+                s
+            )
+        };
+        let (network, rest) = s.split_once('/').ok_or_else(invalid)?;
+        let (prefix, ports) = match rest.split_once(':') {
+            Some((prefix, ports)) => (prefix, Some(ports)),
+            None => (rest, None),
+        };
+        let network: IpAddr = network.parse().map_err(|_| invalid())?;
+        let prefix: u8 = prefix.parse().map_err(|_| invalid())?;
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        if prefix > max_prefix {
+            return Err(invalid());
+        }
+        let ports = match ports {
+            Some(ports) => {
+                let (first, last) = ports.split_once('-').unwrap_or((ports, ports));
+                let first: u16 = first.parse().map_err(|_| invalid())?;
+                let last: u16 = last.parse().map_err(|_| invalid())?;
+                if first > last {
+                    return Err(invalid());
+                }
+                Some((first, last))
+            }
+            None => None,
+        };
+        Ok(Self {
+            network,
+            prefix,
+            ports,
+        })
+    }
+}
+
+impl std::fmt::Display for EgressRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)?;
+        match self.ports {
+            Some((first, last)) if first == last => write!(f, ":{}", first),
+            Some((first, last)) => write!(f, ":{}-{}", first, last),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TryFrom<String> for EgressRule {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<EgressRule> for String {
+    fn from(rule: EgressRule) -> Self {
+        rule.to_string()
+    }
+}
+
+/// Connections a run opened through its egress network
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EgressReport {
+    /// New connections per allowlist rule, for rules that let any through
+    pub allowed: BTreeMap<String, u64>,
+    /// Attempts at new connections the allowlist dropped; a client retrying a
+    /// connection counts once per attempt
+    pub refused: u64,
+}
+
+/// Network a run joins; tear it down with `finish` to collect the report
+pub struct EgressNetwork {
+    /// Network namespace the run joins
+    netns: OwnedFd,
+    /// Host end of the veth pair
+    link: String,
+    /// Whether the nftables table was loaded and needs removing
+    filtered: bool,
+    finished: bool,
+}
+
+impl EgressNetwork {
+    /// Create the run's network namespace and connect it to the host under `rules`
+    pub fn start(rules: &[EgressRule]) -> Result<Self> {
+        if let Some(rule) = rules.iter().find(|rule| rule.network.is_ipv6()) {
+            return Err(IsolateError::Config(format!(
+                "Egress rule {} is IPv6; boxes only get IPv4 egress",
+                rule
+            )));
+        }
+        let failed = |step: &str, e: &dyn std::fmt::Display| {
+            IsolateError::Namespace(format!("Failed to {} for egress: {}", step, e))
+        };
+
+        // Namespaces are per thread, so only the helper leaves the host network
+        let netns = std::thread::spawn(isolated_namespace)
+            .join()
+            .map_err(|_| IsolateError::Namespace("Egress namespace setup panicked".to_string()))?
+            .map_err(|e| failed("create the network namespace", &e))?;
+        let netns_path = format!("/proc/{}/fd/{}", std::process::id(), netns.as_raw_fd());
+
+        // Whoever creates rbxe<N> first owns its addresses; start the search per process
+        let first = std::process::id() % EGRESS_LINKS;
+        let mut index = None;
+        for offset in 0..EGRESS_LINKS {
+            let candidate = (first + offset) % EGRESS_LINKS;
+            let link = format!("rbxe{}", candidate);
+            let peer = ["peer", "name", "eth0", "netns", netns_path.as_str()];
+            match ip(None, &["link", "add", &link, "type", "veth"], &peer) {
+                Ok(()) => {
+                    index = Some(candidate);
+                    break;
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(failed("create the veth pair", &e)),
+            }
+        }
+        let index = index.ok_or_else(|| {
+            IsolateError::ResourceUnavailable("Every egress address is in use".to_string())
+        })?;
+        let mut network = Self {
+            netns,
+            link: format!("rbxe{}", index),
+            filtered: false,
+            finished: false,
+        };
+
+        let base = u32::from(EGRESS_POOL) + index * 4;
+        let (gateway, address) = (Ipv4Addr::from(base + 1), Ipv4Addr::from(base + 2));
+        let link = network.link.clone();
+        let netns = Some(network.netns.as_raw_fd());
+        let host_end = format!("{}/30", gateway);
+        let box_end = format!("{}/30", address);
+        ip(None, &["addr", "add", &host_end, "dev", &link], &[])
+            .and_then(|_| ip(None, &["link", "set", &link, "up"], &[]))
+            .and_then(|_| ip(netns, &["addr", "add", &box_end, "dev", "eth0"], &[]))
+            .and_then(|_| ip(netns, &["link", "set", "eth0", "up"], &[]))
+            .and_then(|_| {
+                ip(
+                    netns,
+                    &["route", "add", "default", "via"],
+                    &[&gateway.to_string()],
+                )
+            })
+            .map_err(|e| failed("address the veth pair", &e))?;
+
+        std::fs::write("/proc/sys/net/ipv4/ip_forward", "1")
+            .map_err(|e| failed("enable IP forwarding", &e))?;
+        nft(&["-f", "-"], Some(&ruleset(&link, address, rules)))
+            .map_err(|e| failed("load the allowlist", &e))?;
+        network.filtered = true;
+        Ok(network)
+    }
+
+    /// Network namespace for the child to join with `namespace::join_network`
+    pub fn netns_fd(&self) -> RawFd {
+        self.netns.as_raw_fd()
+    }
+
+    /// Remove the box's link and filter and return the connections it made
+    pub fn finish(mut self) -> EgressReport {
+        let report = nft(&["-j", "list", "table", "inet", &table(&self.link)], None)
+            .map(|listing| report_from_listing(&listing))
+            .unwrap_or_default();
+        self.tear_down();
+        report
+    }
+
+    /// Deleting the host end takes the box end, and with it every connection, along
+    fn tear_down(&mut self) {
+        if self.finished {
+            return;
+        }
+        self.finished = true;
+        if self.filtered {
+            if let Err(e) = nft(&["delete", "table", "inet", &table(&self.link)], None) {
+                log::warn!(
+                    "Failed to remove egress filter {}: {}",
+                    table(&self.link),
+                    e
+                );
+            }
+        }
+        if let Err(e) = ip(None, &["link", "del", &self.link], &[]) {
+            log::warn!("Failed to remove egress link {}: {}", self.link, e);
+        }
+    }
+}
+
+impl Drop for EgressNetwork {
+    fn drop(&mut self) {
+        self.tear_down();
+    }
+}
+
+/// Enter a new loopback-only network namespace and open it
+fn isolated_namespace() -> std::io::Result<OwnedFd> {
+    crate::namespace::enter_network(NetworkMode::LoopbackOnly)?;
+    Ok(std::fs::File::open("/proc/thread-self/ns/net")?.into())
+}
+
+/// nftables table holding the filter and NAT of the box behind `link`
+fn table(link: &str) -> String {
+    format!("rustbox_{}", link)
+}
+
+/// nftables script replacing the table for the box behind `link` at `address`
+///
+/// Each allowlist rule counts the connections it accepts under its own text as
+/// comment, which `report_from_listing` reads back.
+pub(crate) fn ruleset(link: &str, address: Ipv4Addr, rules: &[EgressRule]) -> String {
+    let table = table(link);
+    // Declaring the table first lets the delete succeed on a fresh host, and a
+    // table left behind by a crashed supervisor is replaced rather than extended
+    let mut lines = vec![
+        format!("table inet {}", table),
+        format!("delete table inet {}", table),
+        format!("table inet {} {{", table),
+        "chain allowlist {".to_string(),
+        "ct state established,related accept".to_string(),
+    ];
+    for rule in rules {
+        let ports = match rule.ports {
+            Some((first, last)) if first == last => {
+                format!(" meta l4proto {{ tcp, udp }} th dport {}", first)
+            }
+            Some((first, last)) => {
+                format!(" meta l4proto {{ tcp, udp }} th dport {}-{}", first, last)
+            }
+            None => String::new(),
+        };
+        lines.push(format!(
+            "ip daddr {}/{}{} ct state new counter accept comment \"{}\"",
+            rule.network, rule.prefix, ports, rule
+        ));
+    }
+    lines.extend([
+        format!("ct state new counter drop comment \"{}\"", REFUSED),
+        "drop".to_string(),
+        "}".to_string(),
+        "chain input {".to_string(),
+        "type filter hook input priority -1; policy accept;".to_string(),
+        format!("iifname \"{}\" jump allowlist", link),
+        "}".to_string(),
+        "chain forward {".to_string(),
+        "type filter hook forward priority -1; policy accept;".to_string(),
+        format!("iifname \"{}\" jump allowlist", link),
+        format!("oifname \"{}\" ct state established,related accept", link),
+        format!("oifname \"{}\" drop", link),
+        "}".to_string(),
+        "chain postrouting {".to_string(),
+        "type nat hook postrouting priority srcnat; policy accept;".to_string(),
+        format!("ip saddr {} oifname != \"{}\" masquerade", address, link),
+        "}".to_string(),
+        "}".to_string(),
+    ]);
+    lines.join("\n") + "\n"
+}
+
+/// Connection counts out of `nft -j list table` for the box's table
+pub(crate) fn report_from_listing(listing: &str) -> EgressReport {
+    let mut report = EgressReport::default();
+    let Ok(listing) = serde_json::from_str::<serde_json::Value>(listing) else {
+        return report;
+    };
+    let objects = listing["nftables"].as_array().into_iter().flatten();
+    for rule in objects.filter_map(|object| object.get("rule")) {
+        let Some(comment) = rule["comment"].as_str() else {
+            continue;
+        };
+        let packets = rule["expr"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find_map(|expr| expr["counter"]["packets"].as_u64())
+            .unwrap_or(0);
+        if comment == REFUSED {
+            report.refused += packets;
+        } else if packets > 0 {
+            *report.allowed.entry(comment.to_string()).or_default() += packets;
+        }
+    }
+    report
+}
+
+/// Run `ip` with `args` followed by `more`, inside `netns` when given
+///
+/// An object that already exists comes back as `ErrorKind::AlreadyExists`.
+fn ip(netns: Option<RawFd>, args: &[&str], more: &[&str]) -> std::io::Result<()> {
+    let mut command = Command::new("ip");
+    command.args(args).args(more);
+    if let Some(netns) = netns {
+        unsafe {
+            command.pre_exec(move || crate::namespace::join_network(netns));
+        }
+    }
+    let output = command.stdin(Stdio::null()).output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let message = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    let kind = if message.contains("File exists") {
+        std::io::ErrorKind::AlreadyExists
+    } else {
+        std::io::ErrorKind::Other
+    };
+    Err(std::io::Error::new(kind, message))
+}
+
+/// Run `nft` with `args`, feeding it `script` on stdin; returns its output
+fn nft(args: &[&str], script: Option<&str>) -> std::io::Result<String> {
+    let mut child = Command::new("nft")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(script.unwrap_or_default().as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
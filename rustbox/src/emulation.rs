@@ -0,0 +1,169 @@
+/// Running foreign-architecture binaries under qemu-user
+///
+/// Courses grading RISC-V or ARM programs on x86 hosts submit binaries the host
+/// cannot run natively. With emulation enabled, the executor reads the ELF
+/// header of the program about to start. A foreign binary runs through the
+/// kernel's binfmt_misc handler when one is registered for its architecture,
+/// and is otherwise wrapped in `qemu-<arch>-static` (or `qemu-<arch>`). Limits
+/// apply to the emulator, which is what actually runs; the syscall audit and
+/// the exec limit likewise see its host syscalls rather than the guest's.
+use crate::types::{IsolateError, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Where qemu-user binaries are looked up (the command allowlist names them there)
+const EMULATOR_DIR: &str = "/usr/bin";
+
+/// Kernel binfmt_misc handler registrations
+const BINFMT_DIR: &str = "/proc/sys/fs/binfmt_misc";
+
+const EM_386: u16 = 3;
+const EM_ARM: u16 = 40;
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+const EM_RISCV: u16 = 243;
+
+/// CPU architecture of an ELF binary
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Arch {
+    X86_64,
+    I386,
+    Aarch64,
+    Arm,
+    Riscv64,
+    Riscv32,
+}
+
+impl Arch {
+    /// Architecture name as qemu spells it (`qemu-riscv64`)
+    pub fn qemu_name(self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64",
+            Arch::I386 => "i386",
+            Arch::Aarch64 => "aarch64",
+            Arch::Arm => "arm",
+            Arch::Riscv64 => "riscv64",
+            Arch::Riscv32 => "riscv32",
+        }
+    }
+
+    /// Architecture this rustbox was built for
+    pub fn host() -> Option<Self> {
+        match std::env::consts::ARCH {
+            "x86_64" => Some(Arch::X86_64),
+            "x86" => Some(Arch::I386),
+            "aarch64" => Some(Arch::Aarch64),
+            "arm" => Some(Arch::Arm),
+            "riscv64" => Some(Arch::Riscv64),
+            _ => None,
+        }
+    }
+
+    /// Architecture of the ELF binary at `path`; `None` for anything else
+    pub fn of_elf(path: &Path) -> Option<Self> {
+        let mut header = [0u8; 20];
+        File::open(path).ok()?.read_exact(&mut header).ok()?;
+        if &header[..4] != b"\x7fELF" {
+            return None;
+        }
+        let is_64 = header[4] == 2;
+        let machine = match header[5] {
+            1 => u16::from_le_bytes([header[18], header[19]]),
+            2 => u16::from_be_bytes([header[18], header[19]]),
+            _ => return None,
+        };
+        match machine {
+            EM_X86_64 => Some(Arch::X86_64),
+            EM_386 => Some(Arch::I386),
+            EM_AARCH64 => Some(Arch::Aarch64),
+            EM_ARM => Some(Arch::Arm),
+            EM_RISCV if is_64 => Some(Arch::Riscv64),
+            EM_RISCV => Some(Arch::Riscv32),
+            _ => None,
+        }
+    }
+}
+
+/// How a foreign binary is run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmulationMode {
+    /// The kernel starts the registered qemu handler on exec
+    Binfmt,
+    /// The command is wrapped in an explicit qemu-user call
+    Qemu,
+}
+
+/// Emulation used for a run, reported in its result
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Emulation {
+    pub arch: Arch,
+    pub mode: EmulationMode,
+    /// qemu binary the command was wrapped in (`Qemu` mode only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emulator: Option<PathBuf>,
+}
+
+impl Emulation {
+    /// Emulation needed to run `program`, or `None` if it runs natively
+    pub fn plan(program: &Path) -> Result<Option<Self>> {
+        let Some(arch) = Arch::of_elf(program) else {
+            return Ok(None);
+        };
+        if Some(arch) == Arch::host() {
+            return Ok(None);
+        }
+        if binfmt_registered(arch) {
+            return Ok(Some(Self {
+                arch,
+                mode: EmulationMode::Binfmt,
+                emulator: None,
+            }));
+        }
+        match find_emulator(arch) {
+            Some(emulator) => Ok(Some(Self {
+                arch,
+                mode: EmulationMode::Qemu,
+                emulator: Some(emulator),
+            })),
+            None => Err(IsolateError::Config(format!(
+                "{} is a {} binary and no qemu-{} emulator is installed",
+                program.display(),
+                arch.qemu_name(),
+                arch.qemu_name()
+            ))),
+        }
+    }
+
+    /// `command` as it has to be started under this emulation
+    pub fn wrap(&self, command: &[String]) -> Vec<String> {
+        match self.emulator {
+            Some(ref emulator) if self.mode == EmulationMode::Qemu => {
+                let mut wrapped = vec![emulator.to_string_lossy().into_owned()];
+                wrapped.extend_from_slice(command);
+                wrapped
+            }
+            _ => command.to_vec(),
+        }
+    }
+}
+
+/// Whether binfmt_misc has an enabled qemu handler for `arch`
+fn binfmt_registered(arch: Arch) -> bool {
+    let entry = Path::new(BINFMT_DIR).join(format!("qemu-{}", arch.qemu_name()));
+    std::fs::read_to_string(entry)
+        .map(|content| content.lines().next() == Some("enabled"))
+        .unwrap_or(false)
+}
+
+/// qemu-user binary for `arch`, preferring the static build (works in a chroot)
+fn find_emulator(arch: Arch) -> Option<PathBuf> {
+    let name = arch.qemu_name();
+    [format!("qemu-{}-static", name), format!("qemu-{}", name)]
+        .iter()
+        .map(|binary| Path::new(EMULATOR_DIR).join(binary))
+        .find(|path| path.is_file())
+}
@@ -1,29 +1,113 @@
 /// Process execution and monitoring with reliable resource limits
-use crate::cgroup::Cgroup;
+use crate::cgroup::{Cgroup, OomEvents};
+use crate::egress::EgressNetwork;
+use crate::emulation::Emulation;
+use crate::file_audit::FileAudit;
 use crate::filesystem::FilesystemSecurity;
-use crate::security::command_validation;
+use crate::output_capture::{into_text, CapturedOutput, OutputCapture, OutputLimits, OutputSink};
+use crate::process_memory::ProcessMemorySampler;
+use crate::pty::Pty;
+use crate::security::{command_validation, env_validation, privileges};
 use crate::security_logging::events;
-use crate::types::{ExecutionResult, ExecutionStatus, IsolateConfig, IsolateError, Result};
-use std::io::{Read, Write};
-use std::path::PathBuf;
+use crate::syscall_audit::{SupervisorOptions, SyscallAudit};
+use crate::time_limit::{self, Expired, TimeLimitWatch, TimeLimits};
+use crate::types::{
+    AdaptiveTimeout, EffectiveLimits, ExecutionResult, ExecutionStatus, IsolateConfig,
+    IsolateError, LimitKind, NetworkMode, ResourceDetail, Result,
+};
+use crate::visibility::VisibleRoot;
+use crate::watchdog::Watchdog;
+use std::io::Write;
+use std::os::fd::OwnedFd;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
 #[cfg(unix)]
 use std::os::unix::process::ExitStatusExt;
 
+/// Longest wait for the processes of a stopped run to be gone
+const REAP_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// Process executor that handles isolation and monitoring with focus on reliability
+///
+/// Dropping the executor while a process is in flight (early return, panic
+/// unwind) kills the sandboxed process group and every task left in its cgroup.
 pub struct ProcessExecutor {
     config: IsolateConfig,
     cgroup: Option<Cgroup>,
     filesystem_security: FilesystemSecurity,
+    /// Started process that has not been waited for yet
+    running: Option<RunningProcess>,
+    /// Pid of the sandboxed process until it has been reaped
+    in_flight: Option<u32>,
+    /// Stops the running process from another thread
+    cancel: Option<CancelToken>,
+    /// Receives the next run's output as it arrives
+    output_sink: Option<OutputSink>,
+    /// Host file the next run reads as stdin
+    stdin_source: Option<std::fs::File>,
+    /// Host resources held for this executor's runs
+    _reservation: crate::admission::Reservation,
+}
+
+/// Shared flag that cancels a run in progress
+///
+/// The monitor loop polls it alongside the limits; a cancelled run is killed
+/// and reported as `ExecutionStatus::Cancelled`.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation; takes effect within a few milliseconds
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A sandboxed process that has been started but not yet waited for
+struct RunningProcess {
+    child: std::process::Child,
+    pid: u32,
+    start_time: Instant,
+    watchdog: Watchdog,
+    /// PSI memory stall total when the process started
+    memory_stall_start: Option<u64>,
+    /// Write audit running for this process
+    file_audit: Option<FileAudit>,
+    /// Syscall audit running for this process
+    syscall_audit: Option<SyscallAudit>,
+    /// Emulation the program runs under, if it is a foreign binary
+    emulation: Option<Emulation>,
+    /// Egress network whose namespace the process runs in
+    egress: Option<EgressNetwork>,
+    /// Kernel OOM notifications of the process's cgroup
+    oom_events: Option<OomEvents>,
+    /// Peak RSS sampler of the box's processes
+    process_memory: Option<ProcessMemorySampler>,
+    /// Watchdog thread keeping the CPU and wall time limits
+    time_limits: TimeLimitWatch,
 }
 
 impl ProcessExecutor {
     /// Create a new process executor
     pub fn new(config: IsolateConfig) -> Result<Self> {
-        let cgroup = if crate::cgroup::cgroups_available() {
+        // Reserve host resources before setting anything up
+        let reservation = crate::admission::admit(&config)?;
+
+        let mut cgroup = if crate::cgroup::cgroups_available() {
             match Cgroup::new(&config.instance_id, config.strict_mode) {
                 Ok(cgroup) => Some(cgroup),
                 Err(e) => {
@@ -47,6 +131,24 @@ impl ProcessExecutor {
             None
         };
 
+        // Confine the box to its CPUs before any of its processes exist; the
+        // child also sets its own affinity, which is all there is without cpuset
+        if let Some(ref cpus) = config.cpu_affinity {
+            cpu_set(cpus)?;
+            if let Some(ref mut cgroup) = cgroup {
+                match cgroup.set_cpu_affinity(cpus) {
+                    Ok(true) => {}
+                    Ok(false) => log::warn!(
+                        "cpuset controller unavailable; pinning with sched_setaffinity only"
+                    ),
+                    Err(e) => eprintln!(
+                        "⚠️  WARNING: cpuset unavailable, pinning with sched_setaffinity only: {}",
+                        e
+                    ),
+                }
+            }
+        }
+
         // Create filesystem security controller
         let filesystem_security = FilesystemSecurity::new(
             config.chroot_dir.clone(),
@@ -57,6 +159,7 @@ impl ProcessExecutor {
         // Set up filesystem isolation if chroot is specified
         if config.chroot_dir.is_some() {
             filesystem_security.setup_isolation()?;
+            filesystem_security.populate_rootfs(&config.chroot_bind_paths)?;
         }
 
         // Set up directory bindings
@@ -64,13 +167,43 @@ impl ProcessExecutor {
             filesystem_security.setup_directory_bindings(&config.directory_bindings)?;
         }
 
+        // Set up host socket bindings
+        if !config.socket_bindings.is_empty() {
+            filesystem_security.setup_socket_bindings(
+                &config.socket_bindings,
+                config.uid,
+                config.gid,
+            )?;
+        }
+
         Ok(Self {
             config,
             cgroup,
             filesystem_security,
+            running: None,
+            in_flight: None,
+            cancel: None,
+            output_sink: None,
+            stdin_source: None,
+            _reservation: reservation,
         })
     }
 
+    /// Let `token` stop runs started by this executor
+    pub fn set_cancel_token(&mut self, token: CancelToken) {
+        self.cancel = Some(token);
+    }
+
+    /// Forward the next run's stdout and stderr to `sink` while it runs
+    pub fn set_output_sink(&mut self, sink: OutputSink) {
+        self.output_sink = Some(sink);
+    }
+
+    /// Connect the next run's stdin to `file` instead of a pipe fed with stdin data
+    pub fn set_stdin_source(&mut self, file: std::fs::File) {
+        self.stdin_source = Some(file);
+    }
+
     /// Setup resource limits using cgroups only
     fn setup_resource_limits(&self) -> Result<()> {
         if let Some(ref cgroup) = self.cgroup {
@@ -79,14 +212,43 @@ impl ProcessExecutor {
                 cgroup.set_memory_limit(memory_limit)?;
             }
 
+            // `IsolateConfig::validate` ensured a memory limit
+            if let (Some(swap_limit), Some(memory_limit)) =
+                (self.config.swap_limit, self.config.memory_limit)
+            {
+                cgroup.set_swap_limit(memory_limit, swap_limit)?;
+            }
+
+            // Set soft memory limit (reclaimed first before the hard limit kills)
+            if let Some(memory_high) = self.config.memory_high {
+                cgroup.set_memory_high(memory_high)?;
+            }
+
+            // Cap kernel-side allocations (pipes, epoll watches, socket buffers)
+            if let Some((kernel_memory_limit, tcp_memory_limit)) =
+                self.config.kernel_memory_limits()
+            {
+                if !cgroup.set_kernel_memory_limit(kernel_memory_limit)? {
+                    log::debug!(
+                        "No separate kmem cap on this kernel; kernel memory is charged to the memory limit"
+                    );
+                }
+                cgroup.set_tcp_memory_limit(tcp_memory_limit)?;
+            }
+
             // Set process limit
             if let Some(process_limit) = self.config.process_limit {
-                cgroup.set_process_limit(process_limit as u64)?;
+                let reaper = u64::from(self.config.subreaper);
+                cgroup.set_process_limit(process_limit as u64 + reaper)?;
             }
 
             // Set CPU shares
             cgroup.set_cpu_limit(1024)?;
 
+            if let Some(cpu_quota_percent) = self.config.cpu_quota_percent {
+                cgroup.set_cpu_quota(cpu_quota_percent)?;
+            }
+
             // Validate that resource monitoring is working
             self.validate_resource_monitoring()?;
         } else if self.config.strict_mode {
@@ -98,6 +260,14 @@ impl ProcessExecutor {
         Ok(())
     }
 
+    /// Box number of this execution, for security events
+    fn box_id(&self) -> Option<u32> {
+        self.config
+            .instance_id
+            .strip_prefix("rustbox/")
+            .and_then(|id| id.parse().ok())
+    }
+
     /// Validate command for security before execution
     fn validate_command(&self, command: &[String]) -> Result<PathBuf> {
         if command.is_empty() {
@@ -109,13 +279,27 @@ impl ProcessExecutor {
             Ok(path) => Ok(path),
             Err(e) => {
                 // Log security event for command injection attempt
-                let box_id = self.config.instance_id.parse::<u32>().ok();
+                let box_id = self.box_id();
                 events::command_injection_attempt(command[0].clone(), box_id);
                 Err(e)
             }
         }
     }
 
+    /// Where `program` is found on the host, for inspecting it before the run
+    fn host_program_path(&self, program: &str) -> PathBuf {
+        let path = Path::new(program);
+        if path.is_absolute() {
+            return path.to_path_buf();
+        }
+        // Relative programs start in the first bound directory or the workdir
+        self.config
+            .directory_bindings
+            .first()
+            .map_or(&self.config.workdir, |binding| &binding.source)
+            .join(path.strip_prefix("./").unwrap_or(path))
+    }
+
     /// Validate that resource monitoring is working properly
     fn validate_resource_monitoring(&self) -> Result<()> {
         if let Some(ref cgroup) = self.cgroup {
@@ -158,17 +342,131 @@ impl ProcessExecutor {
         command: &[String],
         stdin_data: Option<&str>,
     ) -> Result<ExecutionResult> {
+        self.spawn(command, stdin_data)?;
+        self.wait()
+    }
+
+    /// Execute a command, forwarding its output to `sink` as it is produced
+    ///
+    /// The result still holds the full output, as with `execute`.
+    pub fn execute_streaming(
+        &mut self,
+        command: &[String],
+        stdin_data: Option<&str>,
+        sink: OutputSink,
+    ) -> Result<ExecutionResult> {
+        self.set_output_sink(sink);
+        self.execute(command, stdin_data)
+    }
+
+    /// Start a command in the sandbox without waiting for it; returns its pid
+    ///
+    /// The process is owned by the executor until `wait` reaps it.
+    pub fn spawn(&mut self, command: &[String], stdin_data: Option<&str>) -> Result<u32> {
+        self.spawn_with_stdio(command, stdin_data, None)
+    }
+
+    /// Like `spawn`, but reading from `stdin` and writing to `stdout`
+    ///
+    /// Only stderr is collected, so the result's stdout stays empty.
+    pub fn spawn_connected(
+        &mut self,
+        command: &[String],
+        stdin: OwnedFd,
+        stdout: OwnedFd,
+    ) -> Result<u32> {
+        self.spawn_with_stdio(command, None, Some((stdin, stdout)))
+    }
+
+    fn spawn_with_stdio(
+        &mut self,
+        command: &[String],
+        stdin_data: Option<&str>,
+        connected: Option<(OwnedFd, OwnedFd)>,
+    ) -> Result<u32> {
+        if self.running.is_some() || self.in_flight.is_some() {
+            return Err(IsolateError::Process(
+                "A process is already running in this executor".to_string(),
+            ));
+        }
+
         if command.is_empty() {
             return Err(IsolateError::Config("Empty command provided".to_string()));
         }
+        if connected.is_some() && self.config.adaptive_timeout.is_some() {
+            return Err(IsolateError::Config(
+                "adaptive_timeout only works for batch runs, not interactive ones".to_string(),
+            ));
+        }
 
         let start_time = Instant::now();
 
+        // Foreign-architecture programs run under qemu-user when allowed
+        let emulation = if self.config.emulate_foreign_arch {
+            Emulation::plan(&self.host_program_path(&command[0]))?
+        } else {
+            None
+        };
+        let emulated_command;
+        let command = match emulation {
+            Some(ref emulation) => {
+                emulated_command = emulation.wrap(command);
+                &emulated_command[..]
+            }
+            None => command,
+        };
+
         // Validate command for security BEFORE any execution
         let validated_command = self.validate_command(command)?;
 
+        // Fork the watchdog before any setup so it can clean up after a crashed supervisor
+        let task_files = self
+            .cgroup
+            .as_ref()
+            .map(|cgroup| cgroup.task_files())
+            .unwrap_or_default();
+        let watchdog = Watchdog::spawn(task_files)?;
+        crate::watchdog::set_crash_context(Some(self.config.instance_id.clone()));
+
         // Setup resource limits
         self.setup_resource_limits()?;
+        let oom_events = self.subscribe_oom()?;
+
+        let cpu_affinity = self
+            .config
+            .cpu_affinity
+            .as_deref()
+            .map(cpu_set)
+            .transpose()?;
+        let network_mode = network_mode(&self.config)?;
+        let egress = self.start_egress_network(network_mode)?;
+        let egress_netns = egress.as_ref().map(EgressNetwork::netns_fd);
+
+        // Plan the minimal or image root now; the child may only make raw syscalls
+        let visible_root = if let Some(ref image) = self.config.rootfs_image {
+            Some(VisibleRoot::plan_overlay(
+                &crate::paths::paths().image_root_dir(),
+                image,
+                self.config
+                    .rootfs_upper_size
+                    .unwrap_or(crate::visibility::DEFAULT_UPPER_SIZE),
+                &self.config.workdir,
+            )?)
+        } else if self.config.minimal_visibility && self.config.chroot_dir.is_none() {
+            let mut visible = self.config.visible_paths.clone();
+            // A relative program is in the workdir, which is visible anyway
+            if validated_command.is_absolute() {
+                visible.push(validated_command.clone());
+            }
+            Some(VisibleRoot::plan(
+                &crate::paths::paths().visible_root_dir(),
+                &visible,
+                &self.config.workdir,
+            )?)
+        } else {
+            None
+        };
+        let landlock = crate::landlock::ruleset_for(&self.config, &validated_command)?;
 
         // Create the command with validated executable path
         let mut cmd = Command::new(validated_command);
@@ -186,40 +484,120 @@ impl ProcessExecutor {
         };
 
         // Configure basic I/O
+        let interactive = connected.is_some();
+        let (stdin, stdout) = match connected {
+            Some((stdin, stdout)) => (Stdio::from(stdin), Stdio::from(stdout)),
+            None => (Stdio::piped(), Stdio::piped()),
+        };
         cmd.current_dir(effective_workdir)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
+            .stdin(stdin)
+            .stdout(stdout)
             .stderr(Stdio::piped());
 
-        // Set basic environment
-        cmd.env_clear();
-        cmd.env("PATH", "/usr/local/bin:/usr/bin:/bin");
-
-        // Set Java environment if needed
-        if std::path::Path::new("/usr/lib/jvm/java-17-openjdk-amd64").exists() {
-            cmd.env("JAVA_HOME", "/usr/lib/jvm/java-17-openjdk-amd64");
+        // A terminal replaces the pipes unless the streams are already connected
+        let pty = if self.config.enable_tty && !interactive {
+            Some(Pty::open(self.config.tty_size)?)
+        } else {
+            None
+        };
+        if let Some(ref pty) = pty {
+            cmd.stdin(pty.slave()?)
+                .stdout(pty.slave()?)
+                .stderr(pty.slave()?);
         }
-
-        // Set Go environment if needed
-        if std::path::Path::new("/usr/lib/go-1.22").exists() {
-            cmd.env("GOROOT", "/usr/lib/go-1.22");
-            cmd.env("GOCACHE", "/tmp/gocache");
-            cmd.env("GOPATH", "/tmp/gopath");
+        let controlling_tty = pty.as_ref().map(Pty::slave_fd);
+        if let Some(file) = self.stdin_source.take().filter(|_| !interactive) {
+            cmd.stdin(file);
         }
 
-        // Add custom environment variables
-        for (key, value) in &self.config.environment {
-            cmd.env(key, value);
+        // Joined by the child itself, so nothing it forks starts outside the box
+        let cgroup_tasks: Vec<std::ffi::CString> = self
+            .cgroup
+            .as_ref()
+            .map(|cgroup| cgroup.task_files())
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|path| std::ffi::CString::new(path.into_os_string().into_vec()).ok())
+            .collect();
+        let subreaper = self.config.subreaper;
+
+        // Streams redirected to files are reopened by the child inside the box
+        let redirections = redirections(&self.config)?;
+        let redirect_size_cap = redirect_size_cap(&self.config);
+        if self.config.stdin_file.is_some() {
+            cmd.stdin(Stdio::null());
+        }
+        if self.config.stdout_file.is_some() {
+            cmd.stdout(Stdio::null());
+        }
+        if self.config.stderr_file.is_some() {
+            cmd.stderr(Stdio::null());
         }
 
+        // Start from an empty environment holding only what the sandbox needs
+        cmd.env_clear();
+        cmd.envs(sandbox_environment(&self.config)?);
+
+        // The notifying filter is loaded last in the child, so setup syscalls are not recorded
+        let supervisor_options = SupervisorOptions {
+            record_syscalls: self.config.audit_syscalls,
+            exec_limit: self.config.exec_limit,
+        };
+        let mut syscall_audit = if supervisor_options != SupervisorOptions::default() {
+            self.start_syscall_audit(supervisor_options)?
+        } else {
+            None
+        };
+        let syscall_handoff = syscall_audit
+            .as_ref()
+            .map(|audit| (audit.child_fd(), audit.records_syscalls()));
+
         // Setup resource limits using rlimits in pre_exec hook
         #[cfg(unix)]
         {
             use std::os::unix::process::CommandExt;
             let config_clone = self.config.clone();
             let filesystem_security = self.filesystem_security.clone();
+            let umask = filesystem_security.effective_umask(config_clone.umask);
             unsafe {
                 cmd.pre_exec(move || {
+                    // Own process group so the whole tree can be killed at once
+                    match controlling_tty {
+                        Some(tty) => crate::pty::make_controlling(tty)?,
+                        None => {
+                            libc::setpgid(0, 0);
+                        }
+                    }
+
+                    // Best effort; the supervisor adds the process again after spawn
+                    for tasks in &cgroup_tasks {
+                        let fd = libc::open(tasks.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+                        if fd >= 0 {
+                            libc::write(fd, b"0".as_ptr().cast(), 1);
+                            libc::close(fd);
+                        }
+                    }
+
+                    // Restrict permissions of files created by the sandboxed process
+                    libc::umask(umask as libc::mode_t);
+
+                    if let Some(ref cpus) = cpu_affinity {
+                        let size = std::mem::size_of::<libc::cpu_set_t>();
+                        if libc::sched_setaffinity(0, size, cpus) != 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+
+                    match egress_netns {
+                        Some(netns) => crate::namespace::join_network(netns)?,
+                        None => crate::namespace::enter_network(network_mode)?,
+                    }
+
+                    // Swap in the minimal root before anything else touches the filesystem
+                    if let Some(ref root) = visible_root {
+                        root.enter()?;
+                    }
+
                     // Apply filesystem isolation (chroot) first if configured
                     if config_clone.chroot_dir.is_some() {
                         if let Err(e) = filesystem_security.apply_chroot() {
@@ -230,6 +608,19 @@ impl ProcessExecutor {
                         }
                     }
 
+                    // The reaper stays behind, still root, and only waits and forwards
+                    // signals; everything below applies to the program alone
+                    if subreaper {
+                        crate::reaper::fork_reaper()?;
+                    }
+
+                    // The exec limit's listener needs a descriptor below RLIMIT_NOFILE,
+                    // which those inherited from a busy supervisor may all take; it
+                    // only stops execve, so it is loaded before the limit
+                    if let Some((handoff_fd, false)) = syscall_handoff {
+                        crate::syscall_audit::install_in_child(handoff_fd, false)?;
+                    }
+
                     // Set file descriptor limit if specified
                     if let Some(fd_limit) = config_clone.fd_limit {
                         #[cfg(unix)]
@@ -242,99 +633,529 @@ impl ProcessExecutor {
                         })?;
                     }
 
+                    // Empty the bounding set while CAP_SETPCAP is still held
+                    if config_clone.drop_capabilities && libc::geteuid() == 0 {
+                        privileges::clear_bounding_set()?;
+                    }
+
+                    // Switch to the box's own user last; everything above needs root
+                    if let Some(gid) = config_clone.gid {
+                        if libc::setgroups(1, &gid) != 0 || libc::setgid(gid) != 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+                    if let Some(uid) = config_clone.uid {
+                        if libc::setuid(uid) != 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+
+                    // Opened as the box user, so they reach no further than the program could
+                    for redirection in &redirections {
+                        redirection.apply()?;
+                    }
+                    if let Some(cap) = redirect_size_cap {
+                        let limit = libc::rlimit {
+                            rlim_cur: cap,
+                            rlim_max: cap,
+                        };
+                        if libc::setrlimit(libc::RLIMIT_FSIZE, &limit) != 0 {
+                            return Err(std::io::Error::last_os_error());
+                        }
+                    }
+
+                    // A program left running as root keeps no capability either
+                    if config_clone.drop_capabilities {
+                        privileges::drop_all()?;
+                    }
+                    if config_clone.no_new_privileges {
+                        privileges::set_no_new_privs()?;
+                    }
+                    if let Some(ref ruleset) = landlock {
+                        ruleset.restrict_self()?;
+                    }
+
+                    if let Some((handoff_fd, true)) = syscall_handoff {
+                        crate::syscall_audit::install_in_child(handoff_fd, true)?;
+                    }
+
                     Ok(())
                 });
             }
         }
 
+        // Watch writes before the process can make any
+        let file_audit = if self.config.audit_file_writes {
+            self.start_file_audit()?
+        } else {
+            None
+        };
+
         // Start the process
         let mut child = cmd
             .spawn()
             .map_err(|e| IsolateError::Process(format!("Failed to start process: {}", e)))?;
 
         let pid = child.id();
+        watchdog.arm(pid);
+        if let Some(ref audit) = file_audit {
+            audit.set_process_group(pid);
+        }
+        if let Some(ref mut audit) = syscall_audit {
+            audit.spawned();
+        }
+
+        // The program holds the only slave ends now; the master carries its output
+        drop(cmd);
+        if let Some(pty) = pty {
+            let master = pty.into_master();
+            crate::pty::feed(&master, stdin_data.unwrap_or_default().to_string())?;
+            child.stdout = Some(std::process::ChildStdout::from(master));
+        }
+
+        // Sampled from the cgroup, which the process joins below
+        let process_memory = self.config.track_process_memory.then(|| {
+            let task_files = self
+                .cgroup
+                .as_ref()
+                .map(|cgroup| cgroup.task_files())
+                .unwrap_or_default();
+            ProcessMemorySampler::start(task_files, pid)
+        });
+
+        let time_limits = TimeLimitWatch::start(
+            time_limit::Target {
+                pgid: pid,
+                cpu_usage_file: self.cgroup.as_ref().and_then(Cgroup::cpu_usage_file),
+                task_files: self
+                    .cgroup
+                    .as_ref()
+                    .map(|cgroup| cgroup.task_files())
+                    .unwrap_or_default(),
+                freezer_state: self
+                    .cgroup
+                    .as_ref()
+                    .and_then(|cgroup| cgroup.freezer_state().ok()),
+            },
+            self.time_limits(),
+            start_time,
+        );
+        let time_limits = match time_limits {
+            Ok(time_limits) => time_limits,
+            Err(e) => {
+                // Never leave the program running without its time limits
+                self.terminate_process(pid);
+                let _ = child.wait();
+                return Err(e);
+            }
+        };
+        let stdin = stdin_data.and_then(|_| child.stdin.take());
+
+        // From here on the executor owns the process, so any early return kills it
+        self.in_flight = Some(pid);
+        let memory_stall_start = self.memory_stall_total_us();
+        self.running = Some(RunningProcess {
+            child,
+            pid,
+            start_time,
+            watchdog,
+            memory_stall_start,
+            file_audit,
+            syscall_audit,
+            emulation,
+            egress,
+            oom_events,
+            process_memory,
+            time_limits,
+        });
 
         // Add process to cgroup after spawning
         if let Some(ref cgroup) = self.cgroup {
             cgroup.add_process(pid)?;
         }
 
-        // Handle stdin
-        if let Some(data) = stdin_data {
-            if let Some(mut stdin) = child.stdin.take() {
-                let _ = stdin.write_all(data.as_bytes());
-                drop(stdin); // Close stdin
+        // Fed from a thread once the limits are kept, as the program may read
+        // slowly or not at all
+        if let (Some(stdin), Some(data)) = (stdin, stdin_data) {
+            feed_stdin(stdin, data.to_string())?;
+        }
+
+        Ok(pid)
+    }
+
+    /// Wait for the process started by `spawn`, enforcing all limits
+    pub fn wait(&mut self) -> Result<ExecutionResult> {
+        let RunningProcess {
+            child,
+            pid,
+            start_time,
+            watchdog,
+            memory_stall_start,
+            file_audit,
+            syscall_audit,
+            emulation,
+            egress,
+            oom_events,
+            process_memory,
+            mut time_limits,
+        } = self
+            .running
+            .take()
+            .ok_or_else(|| IsolateError::Process("No process has been started".to_string()))?;
+
+        let sink = self.output_sink.take();
+        let mut child_usage = None;
+        let mut result = self.wait_with_timeout(
+            child,
+            &mut time_limits,
+            &mut child_usage,
+            start_time,
+            sink,
+            oom_events,
+        );
+        self.in_flight = None;
+        watchdog.disarm();
+        crate::watchdog::set_crash_context(None);
+        let file_audit = file_audit.map(FileAudit::finish);
+        let supervision = syscall_audit.map(SyscallAudit::finish).unwrap_or_default();
+        let egress = egress.map(EgressNetwork::finish);
+        let process_memory = process_memory.map(ProcessMemorySampler::finish);
+
+        // Report how long the run was throttled when a soft limit is in effect
+        if let Ok(ref mut execution) = result {
+            if self.config.memory_high.is_some() {
+                execution.memory_stall_us = memory_stall_start
+                    .zip(self.memory_stall_total_us())
+                    .map(|(start, end)| end.saturating_sub(start));
+            }
+            execution.file_audit = file_audit;
+            execution.syscall_audit = supervision.syscalls;
+            execution.emulation = emulation;
+            execution.egress = egress;
+            execution.process_memory = process_memory;
+            // A write failing on the full bounded workdir is what ended the run
+            let workdir_bounded =
+                self.config.workdir_tmpfs_size.is_some() || self.config.disk_quota.is_some();
+            if matches!(
+                execution.status,
+                ExecutionStatus::RuntimeError | ExecutionStatus::Signaled
+            ) && workdir_bounded
+                && self.filesystem_security.workdir_full()
+            {
+                execution.status = ExecutionStatus::DiskQuotaExceeded;
+                execution.success = false;
+                execution.error_message = Some("Disk quota exceeded: workdir is full".to_string());
+            }
+            if let Some(execs) = supervision.execs {
+                execution.spawns = Some(crate::types::SpawnReport {
+                    execs: execs.allowed,
+                    execs_denied: execs.denied,
+                    forks_denied: self
+                        .cgroup
+                        .as_ref()
+                        .and_then(|cgroup| cgroup.forks_denied()),
+                });
+                if execs.denied > 0 {
+                    events::syscall_denied(
+                        "execve".to_string(),
+                        execs.denied,
+                        pid,
+                        self.config.uid,
+                        self.box_id(),
+                    );
+                    execution.status = ExecutionStatus::ExecLimit;
+                    execution.success = false;
+                    execution.error_message = Some(format!(
+                        "Exec limit exceeded: {} exec call(s) refused",
+                        execs.denied
+                    ));
+                }
             }
+            execution.kernel_memory_peak = self
+                .cgroup
+                .as_ref()
+                .and_then(|cgroup| cgroup.get_kernel_memory_peak());
+            execution.limits = Some(self.effective_limits(execution, time_limits.expired()));
+            execution.resource_detail = Some(self.resource_detail(child_usage));
         }
+        result
+    }
 
-        // Wait for process with timeout
-        let wall_time_limit = self
-            .config
-            .wall_time_limit
-            .unwrap_or(Duration::from_secs(30));
+    /// Detailed usage of the run: from the box cgroup, which also counts
+    /// processes nobody waited for, else from `child_usage`, the usage of the
+    /// program and of every descendant that was waited for
+    fn resource_detail(&self, child_usage: Option<libc::rusage>) -> ResourceDetail {
+        let cgroup = self.cgroup.as_ref();
+        let cpu = cgroup.and_then(Cgroup::get_cpu_split);
+        let faults = cgroup.and_then(Cgroup::get_page_faults);
+        let block_io = cgroup.and_then(Cgroup::get_block_io);
+        let seconds = |time: libc::timeval| time.tv_sec as f64 + time.tv_usec as f64 / 1e6;
+        let usage = |field: fn(&libc::rusage) -> libc::c_long| {
+            child_usage.map(|usage| field(&usage) as u64)
+        };
 
-        self.wait_with_timeout(child, wall_time_limit, start_time, pid)
+        ResourceDetail {
+            user_time: cpu
+                .map(|(user, _)| user)
+                .or(child_usage.map(|usage| seconds(usage.ru_utime))),
+            system_time: cpu
+                .map(|(_, system)| system)
+                .or(child_usage.map(|usage| seconds(usage.ru_stime))),
+            voluntary_context_switches: usage(|usage| usage.ru_nvcsw),
+            involuntary_context_switches: usage(|usage| usage.ru_nivcsw),
+            minor_page_faults: faults
+                .map(|(minor, _)| minor)
+                .or(usage(|usage| usage.ru_minflt)),
+            major_page_faults: faults
+                .map(|(_, major)| major)
+                .or(usage(|usage| usage.ru_majflt)),
+            // Counted in blocks of 512 bytes by the kernel
+            block_read_bytes: block_io
+                .map(|(read, _)| read)
+                .or(usage(|usage| usage.ru_inblock * 512)),
+            block_write_bytes: block_io
+                .map(|(_, written)| written)
+                .or(usage(|usage| usage.ru_oublock * 512)),
+        }
+    }
+
+    /// The limits this run was held to, naming the one that ended it
+    fn effective_limits(
+        &self,
+        execution: &ExecutionResult,
+        expired: Option<Expired>,
+    ) -> EffectiveLimits {
+        let wall_time = self.wall_time_limit().as_secs_f64();
+        let triggered = match execution.status {
+            ExecutionStatus::TimeLimit => Some(match expired {
+                Some(Expired::Cpu) => LimitKind::CpuTime,
+                Some(Expired::Wall) => LimitKind::WallTime,
+                // Finished inside the extra time, or stopped early while CPU-bound
+                None if execution.wall_time > wall_time => LimitKind::WallTime,
+                None => LimitKind::CpuTime,
+            }),
+            ExecutionStatus::MemoryLimit => Some(LimitKind::Memory),
+            ExecutionStatus::ProcessLimit => Some(LimitKind::Processes),
+            ExecutionStatus::ExecLimit => Some(LimitKind::Execs),
+            ExecutionStatus::FileSizeLimit => Some(LimitKind::FileSize),
+            ExecutionStatus::StackLimit => Some(LimitKind::Stack),
+            ExecutionStatus::CoreLimit => Some(LimitKind::Core),
+            ExecutionStatus::DiskQuotaExceeded => Some(LimitKind::DiskQuota),
+            ExecutionStatus::OutputLimit => Some(LimitKind::Output),
+            ExecutionStatus::IdleTimeout => Some(LimitKind::Idle),
+            _ => None,
+        };
+        // Memory and process limits are only enforced through the cgroup
+        let cgroup = self.cgroup.is_some();
+        EffectiveLimits {
+            memory: self.config.memory_limit.filter(|_| cgroup),
+            cpu_time: self.config.cpu_time_limit.map(|limit| limit.as_secs_f64()),
+            wall_time: Some(wall_time),
+            processes: self.config.process_limit.filter(|_| cgroup),
+            fds: self.config.fd_limit,
+            triggered,
+        }
+    }
+
+    /// Subscribe to OOM kills in this run's cgroup; without it memory limit
+    /// hits are inferred from usage
+    fn subscribe_oom(&self) -> Result<Option<OomEvents>> {
+        let Some(ref cgroup) = self.cgroup else {
+            return Ok(None);
+        };
+        match cgroup.subscribe_oom() {
+            Ok(events) => Ok(events),
+            Err(e) if self.config.strict_mode => Err(e),
+            Err(e) => {
+                eprintln!("⚠️  WARNING: OOM notifications unavailable: {}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Start the fanotify write audit over every directory the sandbox can write to
+    fn start_file_audit(&self) -> Result<Option<FileAudit>> {
+        let mut roots = vec![self.config.workdir.clone()];
+        roots.extend(self.config.chroot_dir.clone());
+        roots.extend(
+            self.config
+                .directory_bindings
+                .iter()
+                .map(|binding| binding.source.clone()),
+        );
+
+        match FileAudit::start(&roots) {
+            Ok(audit) => Ok(Some(audit)),
+            Err(e) if self.config.strict_mode => Err(e),
+            Err(e) => {
+                eprintln!("⚠️  WARNING: File write audit disabled: {}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Start the seccomp user-notification supervisor (syscall audit, exec limit)
+    fn start_syscall_audit(&self, options: SupervisorOptions) -> Result<Option<SyscallAudit>> {
+        match SyscallAudit::start(options) {
+            Ok(audit) => Ok(Some(audit)),
+            Err(e) if self.config.strict_mode => Err(e),
+            Err(e) => {
+                eprintln!("⚠️  WARNING: Syscall audit and exec limit disabled: {}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Set up the egress network for a box with an allowlist; the run joins it
+    fn start_egress_network(&self, network_mode: NetworkMode) -> Result<Option<EgressNetwork>> {
+        // With the host network there is nothing to filter
+        if self.config.egress_allowlist.is_empty() || network_mode == NetworkMode::Full {
+            return Ok(None);
+        }
+        match EgressNetwork::start(&self.config.egress_allowlist) {
+            Ok(network) => Ok(Some(network)),
+            Err(e) if self.config.strict_mode => Err(e),
+            Err(e) => {
+                eprintln!("⚠️  WARNING: Network egress disabled: {}", e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// PSI memory stall total for this run's cgroup, if monitoring is available
+    fn memory_stall_total_us(&self) -> Option<u64> {
+        self.cgroup
+            .as_ref()
+            .and_then(|cgroup| cgroup.memory_stall_total_us())
+    }
+
+    /// Kill the in-flight process tree and anything left in the cgroup
+    fn kill_in_flight(&mut self) {
+        let Some(pid) = self.in_flight.take() else {
+            return;
+        };
+
+        #[cfg(unix)]
+        unsafe {
+            libc::kill(-(pid as i32), libc::SIGKILL);
+            libc::kill(pid as i32, libc::SIGKILL);
+        }
+
+        self.reap();
+
+        // Reap the child so it doesn't linger as a zombie
+        if let Some(mut running) = self.running.take() {
+            let _ = running.child.wait();
+        } else {
+            #[cfg(unix)]
+            unsafe {
+                libc::waitpid(pid as i32, std::ptr::null_mut(), 0);
+            }
+        }
     }
 
     /// Simple and reliable timeout implementation with proper CPU time monitoring
     fn wait_with_timeout(
         &self,
         mut child: std::process::Child,
-        timeout: Duration,
+        time_limits: &mut TimeLimitWatch,
+        child_usage: &mut Option<libc::rusage>,
         start_time: Instant,
-        pid: u32,
+        sink: Option<OutputSink>,
+        mut oom_events: Option<OomEvents>,
     ) -> Result<ExecutionResult> {
-        let child_id = child.id();
-        let timeout_start = Instant::now();
+        let pid = child.id();
+        let timeout = self.wall_time_limit();
 
         // Check if we have a CPU time limit
         let cpu_time_limit = self.config.cpu_time_limit;
 
-        // Create streams for non-blocking output collection
-        let stdout_stream = child.stdout.take();
-        let stderr_stream = child.stderr.take();
-
-        // Start background threads to collect output without blocking
-        let mut stdout_handle = if let Some(mut stdout) = stdout_stream {
-            Some(thread::spawn(move || {
-                let mut buffer = Vec::new();
-                let _ = stdout.read_to_end(&mut buffer);
-                buffer
-            }))
-        } else {
-            None
-        };
+        // Processes are only killed once they also exhaust the extra time
+        let extra_time = self.config.extra_time.unwrap_or_default();
 
-        let mut stderr_handle = if let Some(mut stderr) = stderr_stream {
-            Some(thread::spawn(move || {
-                let mut buffer = Vec::new();
-                let _ = stderr.read_to_end(&mut buffer);
-                buffer
-            }))
-        } else {
-            None
-        };
+        // Optional early kill for runs that are obviously looping. Only batch
+        // runs qualify: a program on a terminal or talking to an interactor is
+        // silent while it waits, and interactive runs refuse it at spawn.
+        let mut loop_detector = self
+            .config
+            .adaptive_timeout
+            .as_ref()
+            .filter(|_| !self.config.enable_tty)
+            .and_then(|settings| LoopDetector::new(settings, cpu_time_limit));
+
+        // Collect stdout and stderr on one thread, enforcing the output limit as it arrives
+        let mut capture = Some(
+            OutputCapture::start_streaming(
+                child.stdout.take().map(OwnedFd::from),
+                child.stderr.take().map(OwnedFd::from),
+                OutputLimits {
+                    total: self.config.output_limit,
+                    stdout: self.config.stdout_limit,
+                    stderr: self.config.stderr_limit,
+                },
+                sink,
+            )
+            .map_err(|e| IsolateError::Process(format!("Failed to collect output: {}", e)))?,
+        );
 
         // Simple polling loop with optimized timing
         loop {
-            match child.try_wait() {
+            // Peeked before std reaps the child, which discards its usage
+            let exited = match exited_usage(pid, false) {
+                Ok(None) => Ok(None),
+                Ok(Some(usage)) => {
+                    *child_usage = Some(usage);
+                    child.try_wait()
+                }
+                Err(_) => child.try_wait(),
+            };
+            match exited {
                 Ok(Some(exit_status)) => {
                     // Process completed - collect output from background threads
-                    let stdout = if let Some(handle) = stdout_handle.take() {
-                        handle.join().unwrap_or_default()
-                    } else {
-                        Vec::new()
-                    };
-
-                    let stderr = if let Some(handle) = stderr_handle.take() {
-                        handle.join().unwrap_or_default()
-                    } else {
-                        Vec::new()
-                    };
+                    let output = capture
+                        .take()
+                        .map(OutputCapture::finish)
+                        .unwrap_or_default();
 
                     let wall_time = start_time.elapsed().as_secs_f64();
                     let (cpu_time, memory_peak) = self.get_resource_usage(pid);
 
+                    // The time watchdog killed the run at one of its limits
+                    if time_limits.finish().is_some() {
+                        self.reap();
+                        return Ok(self.time_limit_result(start_time, pid, output));
+                    }
+
+                    // The kernel OOM killer ended the run at the memory limit
+                    if !exit_status.success() && oom_events.as_mut().is_some_and(OomEvents::fired) {
+                        return Ok(self.memory_limit_result(start_time, cpu_time, memory_peak));
+                    }
+
+                    if self.redirect_cap_reached(exit_status) {
+                        let output = CapturedOutput {
+                            limit_exceeded: true,
+                            ..output
+                        };
+                        return Ok(self.output_limit_result(start_time, pid, output));
+                    }
+
+                    // A run that finished inside the extra time still exceeded its limits
+                    let over_limit = !extra_time.is_zero()
+                        && (cpu_time_limit.is_some_and(|limit| cpu_time > limit.as_secs_f64())
+                            || wall_time > timeout.as_secs_f64());
+                    if over_limit {
+                        return Ok(ExecutionResult {
+                            exit_code: exit_status.code(),
+                            signal: None,
+                            ..self.time_limit_result(start_time, pid, output)
+                        });
+                    }
+
+                    // The program may have died of SIGPIPE once its output was cut off
+                    if output.limit_exceeded || output.stdout_truncated || output.stderr_truncated {
+                        return Ok(self.output_limit_result(start_time, pid, output));
+                    }
+
                     return Ok(ExecutionResult {
                         exit_code: exit_status.code(),
                         status: if exit_status.success() {
@@ -342,8 +1163,8 @@ impl ProcessExecutor {
                         } else {
                             ExecutionStatus::RuntimeError
                         },
-                        stdout: String::from_utf8_lossy(&stdout).to_string(),
-                        stderr: String::from_utf8_lossy(&stderr).to_string(),
+                        stdout: into_text(output.stdout),
+                        stderr: into_text(output.stderr),
                         cpu_time,
                         wall_time,
                         memory_peak,
@@ -358,133 +1179,113 @@ impl ProcessExecutor {
                             }
                         },
                         success: exit_status.success(),
-                        error_message: None,
+                        ..Default::default()
                     });
                 }
                 Ok(None) => {
                     // Process still running - check limits
-                    let elapsed = timeout_start.elapsed();
                     let (cpu_time, memory_peak) = self.get_resource_usage(pid);
 
-                    // Check resource limits using cgroups exclusively
-                    if let Some(ref cgroup) = self.cgroup {
-                        let (memory_limited, _cpu_limited) = cgroup.is_resource_limited();
-
-                        if memory_limited {
-                            // Memory limit exceeded - log security event
-                            let box_id = self.config.instance_id.parse::<u32>().ok();
-                            events::resource_limit_exceeded(
-                                "memory".to_string(),
-                                self.config
-                                    .memory_limit
-                                    .map(|m| format!("{} bytes", m))
-                                    .unwrap_or_else(|| "unknown".to_string()),
-                                box_id,
-                            );
-
-                            self.terminate_process(child_id);
-                            let _ = child.wait();
-
-                            // Suppress output for memory limit violations
-                            let _ = if let Some(handle) = stdout_handle.take() {
-                                handle.join()
-                            } else {
-                                Ok(Vec::new())
-                            };
-
-                            let _ = if let Some(handle) = stderr_handle.take() {
-                                handle.join()
-                            } else {
-                                Ok(Vec::new())
-                            };
+                    // The kernel's OOM notification is authoritative; usage near the
+                    // limit is only a guess for when it is unavailable
+                    let memory_limited = match oom_events {
+                        Some(ref mut events) => events.fired(),
+                        None => self
+                            .cgroup
+                            .as_ref()
+                            .is_some_and(|cgroup| cgroup.is_resource_limited().0),
+                    };
+                    if memory_limited {
+                        self.terminate_process(pid);
+                        *child_usage = wait_child(&mut child);
 
-                            let wall_time = start_time.elapsed().as_secs_f64();
+                        // Suppress output for memory limit violations
+                        let _ = capture.take().map(OutputCapture::finish);
 
-                            return Ok(ExecutionResult {
-                                exit_code: None,
-                                status: ExecutionStatus::MemoryLimit,
-                                stdout: String::new(),
-                                stderr: String::new(),
-                                cpu_time,
-                                wall_time,
-                                memory_peak,
-                                signal: Some(9), // SIGKILL
-                                success: false,
-                                error_message: Some("Memory Limit Exceeded".to_string()),
-                            });
-                        }
+                        return Ok(self.memory_limit_result(start_time, cpu_time, memory_peak));
                     }
 
-                    // Check CPU time limit
-                    if let Some(cpu_limit) = cpu_time_limit {
-                        if cpu_time >= cpu_limit.as_secs_f64() {
-                            // CPU time limit exceeded
-                            self.terminate_process(child_id);
-                            let _ = child.wait();
+                    // Kill early when CPU-bound and silent past the expected time
+                    if let Some(ref mut detector) = loop_detector {
+                        if detector.is_looping(
+                            cpu_time,
+                            capture
+                                .as_ref()
+                                .map_or(0, |capture| capture.progress().bytes()),
+                        ) {
+                            self.terminate_process(pid);
+                            *child_usage = wait_child(&mut child);
 
                             // Suppress output for time limit violations
-                            let _ = if let Some(handle) = stdout_handle.take() {
-                                handle.join()
-                            } else {
-                                Ok(Vec::new())
-                            };
-
-                            let _ = if let Some(handle) = stderr_handle.take() {
-                                handle.join()
-                            } else {
-                                Ok(Vec::new())
-                            };
+                            let _ = capture.take().map(OutputCapture::finish);
 
                             let wall_time = start_time.elapsed().as_secs_f64();
 
                             return Ok(ExecutionResult {
-                                exit_code: None,
                                 status: ExecutionStatus::TimeLimit,
-                                stdout: String::new(),
-                                stderr: String::new(),
                                 cpu_time,
                                 wall_time,
                                 memory_peak,
                                 signal: Some(9), // SIGKILL
-                                success: false,
-                                error_message: Some("Time Limit Exceeded".to_string()),
+                                error_message: Some(
+                                    "Time Limit Exceeded (killed early: CPU-bound with no output)"
+                                        .to_string(),
+                                ),
+                                ..Default::default()
                             });
                         }
                     }
 
-                    // Check wall time limit
-                    if elapsed >= timeout {
-                        // Wall time limit exceeded
-                        self.terminate_process(child_id);
-                        let _ = child.wait();
+                    // Stop writers as soon as the output limit is crossed
+                    if capture
+                        .as_ref()
+                        .is_some_and(|capture| capture.progress().limit_exceeded())
+                    {
+                        self.terminate_process(pid);
+                        *child_usage = wait_child(&mut child);
+                        let output = capture
+                            .take()
+                            .map(OutputCapture::finish)
+                            .unwrap_or_default();
+                        return Ok(self.output_limit_result(start_time, pid, output));
+                    }
 
-                        // Suppress output for wall time limit violations
-                        let _ = if let Some(handle) = stdout_handle.take() {
-                            handle.join()
-                        } else {
-                            Ok(Vec::new())
-                        };
+                    // The time watchdog killed the run at one of its limits
+                    if time_limits.expired().is_some() {
+                        *child_usage = wait_child(&mut child);
+                        time_limits.finish();
+                        self.reap();
+
+                        let output = capture
+                            .take()
+                            .map(OutputCapture::finish)
+                            .unwrap_or_default();
+                        return Ok(self.time_limit_result(start_time, pid, output));
+                    }
 
-                        let _ = if let Some(handle) = stderr_handle.take() {
-                            handle.join()
-                        } else {
-                            Ok(Vec::new())
-                        };
+                    // Stop at once when the caller gave up on the run
+                    if self.cancel.as_ref().is_some_and(CancelToken::is_cancelled) {
+                        self.terminate_process(pid);
+                        *child_usage = wait_child(&mut child);
+
+                        let output = capture
+                            .take()
+                            .map(OutputCapture::finish)
+                            .unwrap_or_default();
 
                         let wall_time = start_time.elapsed().as_secs_f64();
                         let (cpu_time, memory_peak) = self.get_resource_usage(pid);
 
                         return Ok(ExecutionResult {
-                            exit_code: None,
-                            status: ExecutionStatus::TimeLimit,
-                            stdout: String::new(),
-                            stderr: String::new(),
+                            status: ExecutionStatus::Cancelled,
+                            stdout: into_text(output.stdout),
+                            stderr: into_text(output.stderr),
                             cpu_time,
                             wall_time,
                             memory_peak,
                             signal: Some(9), // SIGKILL
-                            success: false,
-                            error_message: Some("Time Limit Exceeded".to_string()),
+                            error_message: Some("Execution cancelled".to_string()),
+                            ..Default::default()
                         });
                     }
 
@@ -501,7 +1302,150 @@ impl ProcessExecutor {
         }
     }
 
-    /// Terminate a process gracefully then forcefully
+    /// Wall time limit of a run, 30 seconds when unset
+    fn wall_time_limit(&self) -> Duration {
+        self.config
+            .wall_time_limit
+            .unwrap_or(Duration::from_secs(30))
+    }
+
+    /// Limits the time watchdog kills a run at
+    fn time_limits(&self) -> TimeLimits {
+        let extra_time = self.config.extra_time.unwrap_or_default();
+        TimeLimits {
+            cpu: self.config.cpu_time_limit.map(|limit| limit + extra_time),
+            wall: self.wall_time_limit() + extra_time,
+        }
+    }
+
+    /// Result of a run killed at its CPU or wall time limit
+    ///
+    /// Output is suppressed, unless the run had extra time: then the program
+    /// had its grace period to flush, and what it wrote up to the kill is kept
+    /// for diagnostics (isolate and CMS do the same).
+    fn time_limit_result(
+        &self,
+        start_time: Instant,
+        pid: u32,
+        output: CapturedOutput,
+    ) -> ExecutionResult {
+        let wall_time = start_time.elapsed().as_secs_f64();
+        let (cpu_time, memory_peak) = self.get_resource_usage(pid);
+        let output = if self.config.extra_time.unwrap_or_default().is_zero() {
+            CapturedOutput::default()
+        } else {
+            output
+        };
+        ExecutionResult {
+            status: ExecutionStatus::TimeLimit,
+            stdout: into_text(output.stdout),
+            stderr: into_text(output.stderr),
+            cpu_time,
+            wall_time,
+            memory_peak,
+            signal: Some(9), // SIGKILL
+            error_message: Some("Time Limit Exceeded".to_string()),
+            stdout_truncated: output.limit_exceeded || output.stdout_truncated,
+            stderr_truncated: output.limit_exceeded || output.stderr_truncated,
+            ..Default::default()
+        }
+    }
+
+    /// Result of a run stopped at its memory limit; output is suppressed
+    fn memory_limit_result(
+        &self,
+        start_time: Instant,
+        cpu_time: f64,
+        memory_peak: u64,
+    ) -> ExecutionResult {
+        let box_id = self.box_id();
+        events::resource_limit_exceeded(
+            "memory".to_string(),
+            self.config
+                .memory_limit
+                .map(|m| format!("{} bytes", m))
+                .unwrap_or_else(|| "unknown".to_string()),
+            box_id,
+        );
+
+        ExecutionResult {
+            status: ExecutionStatus::MemoryLimit,
+            cpu_time,
+            wall_time: start_time.elapsed().as_secs_f64(),
+            memory_peak,
+            signal: Some(9), // SIGKILL
+            error_message: Some("Memory Limit Exceeded".to_string()),
+            ..Default::default()
+        }
+    }
+
+    /// Whether a run that ended with `exit_status` was stopped by the size cap
+    /// of a redirected output file
+    fn redirect_cap_reached(&self, exit_status: std::process::ExitStatus) -> bool {
+        let Some(cap) = redirect_size_cap(&self.config) else {
+            return false;
+        };
+        if exit_status.signal() == Some(libc::SIGXFSZ) {
+            return true;
+        }
+        // Programs ignoring SIGXFSZ get EFBIG instead, and usually fail on it
+        let outputs = [&self.config.stdout_file, &self.config.stderr_file];
+        !exit_status.success()
+            && outputs.into_iter().flatten().any(|path| {
+                let Ok(path) = box_path(&self.config, path) else {
+                    return false;
+                };
+                let host_path = match self.config.chroot_dir {
+                    Some(ref root) => root.join(path.strip_prefix("/").unwrap_or(&path)),
+                    None => path,
+                };
+                std::fs::metadata(host_path).is_ok_and(|metadata| metadata.len() >= cap)
+            })
+    }
+
+    /// Result of a run stopped for writing more than the output limit
+    fn output_limit_result(
+        &self,
+        start_time: Instant,
+        pid: u32,
+        output: CapturedOutput,
+    ) -> ExecutionResult {
+        let wall_time = start_time.elapsed().as_secs_f64();
+        let (cpu_time, memory_peak) = self.get_resource_usage(pid);
+
+        // The combined limit suppresses all output; a stream over its own limit
+        // is kept up to that limit
+        let (stdout, stderr) = if output.limit_exceeded {
+            (String::new(), String::new())
+        } else {
+            (into_text(output.stdout), into_text(output.stderr))
+        };
+        let error_message = match (
+            output.limit_exceeded,
+            output.stdout_truncated,
+            output.stderr_truncated,
+        ) {
+            (false, true, false) => "Output Limit Exceeded (stdout)",
+            (false, false, true) => "Output Limit Exceeded (stderr)",
+            _ => "Output Limit Exceeded",
+        };
+
+        ExecutionResult {
+            status: ExecutionStatus::OutputLimit,
+            stdout,
+            stderr,
+            cpu_time,
+            wall_time,
+            memory_peak,
+            error_message: Some(error_message.to_string()),
+            stdout_truncated: output.limit_exceeded || output.stdout_truncated,
+            stderr_truncated: output.limit_exceeded || output.stderr_truncated,
+            ..Default::default()
+        }
+    }
+
+    /// Terminate a process gracefully then forcefully, along with everything
+    /// it started
     fn terminate_process(&self, pid: u32) {
         #[cfg(unix)]
         unsafe {
@@ -514,9 +1458,24 @@ impl ProcessExecutor {
 
         #[cfg(unix)]
         unsafe {
-            // Send SIGKILL if still running
+            // Send SIGKILL if still running, to the process group too
+            libc::kill(-(pid as i32), libc::SIGKILL);
             libc::kill(pid as i32, libc::SIGKILL);
         }
+
+        self.reap();
+    }
+
+    /// Kill whatever is left of the run in its cgroup and wait until it is gone
+    ///
+    /// Grandchildren may have left the process group (a shell wrapper, a
+    /// `setsid`), but not the cgroup, so no process outlives its run.
+    fn reap(&self) {
+        if let Some(ref cgroup) = self.cgroup {
+            if !cgroup.reap(REAP_TIMEOUT) {
+                log::warn!("Processes of {} survived the kill", self.config.instance_id);
+            }
+        }
     }
 
     /// Get resource usage exclusively from cgroups for security and reliability
@@ -533,9 +1492,285 @@ impl ProcessExecutor {
 
     /// Cleanup resources
     pub fn cleanup(&mut self) -> Result<()> {
+        self.kill_in_flight();
         if let Some(cgroup) = self.cgroup.take() {
             cgroup.cleanup()?;
         }
+        self.filesystem_security
+            .unmount_socket_bindings(&self.config.socket_bindings)?;
         Ok(())
     }
 }
+
+impl Drop for ProcessExecutor {
+    fn drop(&mut self) {
+        let _ = self.cleanup();
+    }
+}
+
+/// Samples CPU usage and output to decide when a run is obviously looping
+struct LoopDetector {
+    settings: AdaptiveTimeout,
+    /// CPU seconds after which the run may be killed
+    expected_cpu: f64,
+    last_sample: Instant,
+    last_cpu: f64,
+    last_output: u64,
+    /// Start of the current CPU-bound, silent stretch
+    pegged_since: Option<Instant>,
+}
+
+impl LoopDetector {
+    const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// Returns None when there is no expected CPU time to judge against
+    fn new(settings: &AdaptiveTimeout, cpu_time_limit: Option<Duration>) -> Option<Self> {
+        let expected = settings.expected_time.or(cpu_time_limit)?;
+        Some(Self {
+            settings: settings.clone(),
+            expected_cpu: expected.as_secs_f64(),
+            last_sample: Instant::now(),
+            last_cpu: 0.0,
+            last_output: 0,
+            pegged_since: None,
+        })
+    }
+
+    /// Feed the current totals; true once the run should be killed
+    fn is_looping(&mut self, cpu_time: f64, output_bytes: u64) -> bool {
+        let now = Instant::now();
+        let interval = now.duration_since(self.last_sample);
+        if interval < Self::SAMPLE_INTERVAL {
+            return false;
+        }
+
+        let utilization = (cpu_time - self.last_cpu) / interval.as_secs_f64();
+        let silent = output_bytes == self.last_output;
+        let sample_start = self.last_sample;
+        self.last_sample = now;
+        self.last_cpu = cpu_time;
+        self.last_output = output_bytes;
+
+        if utilization < self.settings.cpu_threshold || !silent {
+            self.pegged_since = None;
+            return false;
+        }
+        let pegged_since = *self.pegged_since.get_or_insert(sample_start);
+
+        cpu_time >= self.expected_cpu && now.duration_since(pegged_since) >= self.settings.silence
+    }
+}
+
+/// Write `input` to the program's stdin from a helper thread, closing it after
+fn feed_stdin(mut stdin: std::process::ChildStdin, input: String) -> Result<()> {
+    std::thread::Builder::new()
+        .name("rustbox-stdin".to_string())
+        .spawn(move || {
+            // Fails with EPIPE once the program exits or is killed
+            let _ = stdin.write_all(input.as_bytes());
+        })
+        .map_err(|e| IsolateError::Process(format!("Failed to start stdin writer: {}", e)))?;
+    Ok(())
+}
+
+/// CPU mask of `cpus`, each of which this process must be allowed to run on
+pub(crate) fn cpu_set(cpus: &[usize]) -> Result<libc::cpu_set_t> {
+    if cpus.is_empty() {
+        return Err(IsolateError::Config(
+            "CPU affinity needs at least one CPU".to_string(),
+        ));
+    }
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    let mut allowed: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    if unsafe { libc::sched_getaffinity(0, size, &mut allowed) } != 0 {
+        return Err(IsolateError::Io(std::io::Error::last_os_error()));
+    }
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for &cpu in cpus {
+        if cpu >= libc::CPU_SETSIZE as usize || !unsafe { libc::CPU_ISSET(cpu, &allowed) } {
+            return Err(IsolateError::Config(format!(
+                "CPU {} is not available to pin the box to",
+                cpu
+            )));
+        }
+        unsafe { libc::CPU_SET(cpu, &mut set) };
+    }
+    Ok(set)
+}
+
+/// Network the next run with `config` gets
+///
+/// Without root no network namespace can be created, so the run keeps the
+/// host network (an error in strict mode).
+pub(crate) fn network_mode(config: &IsolateConfig) -> Result<NetworkMode> {
+    if !config.enable_network_namespace || config.network_mode == NetworkMode::Full {
+        return Ok(NetworkMode::Full);
+    }
+    if unsafe { libc::geteuid() } == 0 {
+        return Ok(config.network_mode);
+    }
+    if config.strict_mode {
+        return Err(IsolateError::Namespace(
+            "Network isolation requires root".to_string(),
+        ));
+    }
+    log::warn!("Not running as root; the program keeps the host network");
+    Ok(NetworkMode::Full)
+}
+
+/// Variables a process sandboxed with `config` starts with, bounded by `env_validation`
+/// A standard stream the child reopens onto a file in the box
+struct Redirection {
+    path: std::ffi::CString,
+    fd: libc::c_int,
+    flags: libc::c_int,
+}
+
+impl Redirection {
+    /// Open the file and put it in place of the stream; runs in the child
+    unsafe fn apply(&self) -> std::io::Result<()> {
+        let flags = self.flags | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+        let file = libc::open(self.path.as_ptr(), flags, 0o644);
+        if file < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        // The duplicate does not inherit O_CLOEXEC
+        let duplicated = libc::dup2(file, self.fd);
+        libc::close(file);
+        if duplicated < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// The stream redirections of `config`, with their paths resolved in the box
+fn redirections(config: &IsolateConfig) -> Result<Vec<Redirection>> {
+    let write = libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC;
+    let streams = [
+        (&config.stdin_file, libc::STDIN_FILENO, libc::O_RDONLY),
+        (&config.stdout_file, libc::STDOUT_FILENO, write),
+        (&config.stderr_file, libc::STDERR_FILENO, write),
+    ];
+    let mut redirections = Vec::new();
+    for (path, fd, flags) in streams {
+        let Some(path) = path else {
+            continue;
+        };
+        let path = box_path(config, path)?;
+        redirections.push(Redirection {
+            path: std::ffi::CString::new(path.to_string_lossy().as_bytes())
+                .map_err(|e| IsolateError::Config(format!("Invalid redirection path: {}", e)))?,
+            fd,
+            flags,
+        });
+    }
+    Ok(redirections)
+}
+
+/// `path` inside the box: relative paths are taken from the workdir and
+/// nothing may lead out of it
+pub(crate) fn box_path(config: &IsolateConfig, path: &Path) -> Result<PathBuf> {
+    let resolved = config.workdir.join(path);
+    let escapes = resolved
+        .components()
+        .any(|component| component == std::path::Component::ParentDir);
+    if escapes || !resolved.starts_with(&config.workdir) {
+        return Err(IsolateError::Config(format!(
+            "{} is outside the box's workdir {}",
+            path.display(),
+            config.workdir.display()
+        )));
+    }
+    Ok(resolved)
+}
+
+/// Largest file the program may write when an output stream goes to a file:
+/// the limit of that stream, or the larger of the two when both do
+///
+/// It is enforced with `RLIMIT_FSIZE`, so it holds for every file the program writes.
+fn redirect_size_cap(config: &IsolateConfig) -> Option<u64> {
+    let stdout = config
+        .stdout_file
+        .as_ref()
+        .and(config.stdout_limit.or(config.output_limit));
+    let stderr = config
+        .stderr_file
+        .as_ref()
+        .and(config.stderr_limit.or(config.output_limit));
+    stdout.max(stderr)
+}
+
+/// Usage of the exited `pid` and of every descendant it waited for, read
+/// without reaping it; `None` while it is still running
+fn exited_usage(pid: u32, block: bool) -> std::io::Result<Option<libc::rusage>> {
+    let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let mut options = libc::WEXITED | libc::WNOWAIT;
+    if !block {
+        options |= libc::WNOHANG;
+    }
+    // Only the system call, not the libc wrapper, hands back the usage
+    let waited = unsafe {
+        libc::syscall(
+            libc::SYS_waitid,
+            libc::P_PID,
+            pid,
+            &mut info,
+            options,
+            &mut usage,
+        )
+    };
+    if waited != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if unsafe { info.si_pid() } == 0 {
+        return Ok(None);
+    }
+    Ok(Some(usage))
+}
+
+/// Wait for `child`, keeping the usage that `Child::wait` discards
+fn wait_child(child: &mut std::process::Child) -> Option<libc::rusage> {
+    let usage = exited_usage(child.id(), true).ok().flatten();
+    let _ = child.wait();
+    usage
+}
+
+pub(crate) fn sandbox_environment(config: &IsolateConfig) -> Result<Vec<(String, String)>> {
+    let mut vars = vec![(
+        "PATH".to_string(),
+        "/usr/local/bin:/usr/bin:/bin".to_string(),
+    )];
+
+    // Set Java environment if needed
+    if std::path::Path::new("/usr/lib/jvm/java-17-openjdk-amd64").exists() {
+        vars.push((
+            "JAVA_HOME".to_string(),
+            "/usr/lib/jvm/java-17-openjdk-amd64".to_string(),
+        ));
+    }
+
+    let mut set = |key: &str, value: String| match vars.iter_mut().find(|(name, _)| name == key) {
+        Some(existing) => existing.1 = value,
+        None => vars.push((key.to_string(), value)),
+    };
+    // Nothing from the host gets through unless asked for
+    if config.full_env {
+        for (key, value) in std::env::vars() {
+            set(&key, value);
+        }
+    }
+    for key in &config.preserve_env {
+        if let Ok(value) = std::env::var(key) {
+            set(key, value);
+        }
+    }
+    // Custom environment variables replace everything of the same name
+    for (key, value) in &config.environment {
+        set(key, value.clone());
+    }
+
+    env_validation::validate_environment(&vars)?;
+    Ok(vars)
+}
@@ -0,0 +1,332 @@
+/// Effective sandbox configuration of a box, resolved without running anything
+///
+/// `Isolate::effective_config` applies per-run overrides to a box's config and
+/// resolves it the way the executor does: the namespaces and network the run
+/// gets, the cgroup limits it writes, the syscalls handed to the seccomp
+/// supervisor, the rlimits, the mounts and the environment. Nothing is created,
+/// mounted or spawned, so `rustbox explain` is safe to run next to live boxes.
+use crate::types::{DirectoryPermissions, IsolateConfig, NetworkMode, Result};
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Everything a run with a given configuration would set up
+#[derive(Debug, Clone, Serialize)]
+pub struct EffectiveConfig {
+    pub instance_id: String,
+    pub euid: u32,
+    pub kernel: Option<String>,
+    /// Namespaces the program is placed in; the others are the host's
+    pub namespaces: Vec<&'static str>,
+    pub network: NetworkMode,
+    pub cgroup: CgroupPlan,
+    pub seccomp: SeccompPlan,
+    pub rlimits: Vec<Rlimit>,
+    /// Every capability is dropped and the bounding set emptied before exec
+    pub drop_capabilities: bool,
+    /// `PR_SET_NO_NEW_PRIVS` is set before exec
+    pub no_new_privileges: bool,
+    /// Landlock ABI version the program is confined with
+    pub landlock: Option<u32>,
+    /// Mounts in the order they are made
+    pub mounts: Vec<Mount>,
+    /// Variables the program starts with
+    pub environment: Vec<(String, String)>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// Directory the program starts in
+    pub workdir: PathBuf,
+    /// Protections that would be weaker than configured
+    pub warnings: Vec<String>,
+}
+
+/// Cgroup controllers on this host and the limits a run writes to them
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CgroupPlan {
+    pub available: bool,
+    pub controllers: Vec<String>,
+    /// (limit, value) in the order they are written; sizes in bytes
+    pub limits: Vec<(&'static str, String)>,
+}
+
+/// Syscall filtering of a run
+#[derive(Debug, Clone, Serialize)]
+pub struct SeccompPlan {
+    /// Whether a filter denies syscalls outright; none is built into this binary
+    pub enforcing: bool,
+    /// Every syscall is handed to the supervisor (audit run)
+    pub notify_all: bool,
+    /// Syscalls handed to the supervisor when not all of them are
+    pub notified: Vec<&'static str>,
+}
+
+/// A resource limit set with `setrlimit` in the child
+#[derive(Debug, Clone, Serialize)]
+pub struct Rlimit {
+    pub resource: &'static str,
+    /// Configured value; the program inherits rustbox's limit when unset
+    pub limit: Option<u64>,
+    /// Whether the run applies it
+    pub applied: bool,
+}
+
+/// A mount the run makes or the box was set up with
+#[derive(Debug, Clone, Serialize)]
+pub struct Mount {
+    /// Where the program sees it
+    pub target: PathBuf,
+    /// Host path or filesystem type
+    pub source: String,
+    pub kind: &'static str,
+    pub read_only: bool,
+}
+
+impl Mount {
+    fn new(target: impl Into<PathBuf>, source: impl ToString, kind: &'static str) -> Self {
+        Self {
+            target: target.into(),
+            source: source.to_string(),
+            kind,
+            read_only: false,
+        }
+    }
+
+    fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+}
+
+/// Resolve `config` the way a run would, without executing anything
+pub fn resolve(config: &IsolateConfig) -> Result<EffectiveConfig> {
+    let euid = unsafe { libc::geteuid() };
+    let mut warnings = Vec::new();
+
+    let network = crate::executor::network_mode(config)?;
+    if network != config.network_mode && config.enable_network_namespace {
+        warnings.push(format!(
+            "network isolation needs root; the program keeps the host network instead of {:?}",
+            config.network_mode
+        ));
+    }
+    let image_root = config.rootfs_image.is_some();
+    let minimal_root = !image_root && config.minimal_visibility && config.chroot_dir.is_none();
+
+    let mut namespaces = Vec::new();
+    if network != NetworkMode::Full {
+        namespaces.push("net");
+    }
+    if image_root || minimal_root {
+        namespaces.push("mount");
+    }
+
+    let cgroup = cgroup_plan(config);
+    if !cgroup.available {
+        warnings.push(if config.strict_mode {
+            "cgroups unavailable; the run fails in strict mode".to_string()
+        } else {
+            "cgroups unavailable; resource limits are not enforced".to_string()
+        });
+    }
+
+    let mut notified = Vec::new();
+    if config.exec_limit.is_some() && !config.audit_syscalls {
+        notified = vec!["execve", "execveat"];
+    }
+    let seccomp = SeccompPlan {
+        enforcing: false,
+        notify_all: config.audit_syscalls,
+        notified,
+    };
+
+    let rlimits = vec![
+        Rlimit {
+            resource: "RLIMIT_NOFILE",
+            limit: config.fd_limit,
+            applied: config.fd_limit.is_some(),
+        },
+        Rlimit {
+            resource: "RLIMIT_FSIZE",
+            limit: config.file_size_limit,
+            applied: false,
+        },
+        Rlimit {
+            resource: "RLIMIT_STACK",
+            limit: config.stack_limit,
+            applied: false,
+        },
+        Rlimit {
+            resource: "RLIMIT_CORE",
+            limit: config.core_limit,
+            applied: false,
+        },
+    ];
+    for rlimit in rlimits.iter().filter(|r| r.limit.is_some() && !r.applied) {
+        warnings.push(format!(
+            "{} is configured but not applied; the program inherits rustbox's limit",
+            rlimit.resource
+        ));
+    }
+
+    let landlock = config.landlock.then(crate::landlock::abi_version).flatten();
+    if config.landlock && landlock.is_none() {
+        warnings.push(if config.strict_mode {
+            "Landlock unavailable; the run fails in strict mode".to_string()
+        } else {
+            "Landlock unavailable; the program is not confined to its workdir".to_string()
+        });
+    }
+
+    let environment = crate::executor::sandbox_environment(config)?;
+
+    let workdir = config
+        .directory_bindings
+        .first()
+        .map_or(&config.workdir, |binding| &binding.target)
+        .clone();
+
+    Ok(EffectiveConfig {
+        instance_id: config.instance_id.clone(),
+        euid,
+        kernel: std::fs::read_to_string("/proc/sys/kernel/osrelease")
+            .ok()
+            .map(|release| release.trim().to_string()),
+        namespaces,
+        network,
+        cgroup,
+        seccomp,
+        rlimits,
+        drop_capabilities: config.drop_capabilities,
+        no_new_privileges: config.no_new_privileges,
+        landlock,
+        mounts: mounts(config, image_root, minimal_root),
+        environment,
+        uid: config.uid,
+        gid: config.gid,
+        workdir,
+        warnings,
+    })
+}
+
+fn cgroup_plan(config: &IsolateConfig) -> CgroupPlan {
+    if !crate::cgroup::cgroups_available() {
+        return CgroupPlan::default();
+    }
+    let mut controllers: Vec<String> = crate::cgroup::Cgroup::available_controllers()
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    controllers.sort();
+
+    let mut limits = Vec::new();
+    if let Some(memory) = config.memory_limit {
+        limits.push(("memory", memory.to_string()));
+    }
+    if let (Some(memory), Some(swap)) = (config.memory_limit, config.swap_limit) {
+        limits.push(("memory_and_swap", (memory + swap).to_string()));
+    }
+    if let Some(memory_high) = config.memory_high {
+        limits.push(("memory_high", memory_high.to_string()));
+    }
+    if let Some((kernel_memory, tcp_memory)) = config.kernel_memory_limits() {
+        limits.push(("kernel_memory", kernel_memory.to_string()));
+        limits.push(("tcp_memory", tcp_memory.to_string()));
+    }
+    if let Some(processes) = config.process_limit {
+        limits.push(("pids", processes.to_string()));
+    }
+    limits.push(("cpu_shares", "1024".to_string()));
+    if let Some(percent) = config.cpu_quota_percent {
+        limits.push(("cpu_quota", format!("{}%", percent)));
+    }
+    if let Some(ref cpus) = config.cpu_affinity {
+        let cpus: Vec<String> = cpus.iter().map(|cpu| cpu.to_string()).collect();
+        limits.push(("cpus", cpus.join(",")));
+    }
+
+    CgroupPlan {
+        available: true,
+        controllers,
+        limits,
+    }
+}
+
+fn mounts(config: &IsolateConfig, image_root: bool, minimal_root: bool) -> Vec<Mount> {
+    let mut mounts = Vec::new();
+    match (config.workdir_tmpfs_size, config.disk_quota) {
+        (Some(size), _) => mounts.push(Mount::new(
+            &config.workdir,
+            format!("tmpfs size={}", size),
+            "tmpfs",
+        )),
+        (None, Some(quota)) => mounts.push(Mount::new(
+            &config.workdir,
+            format!("ext4 image size={}", quota),
+            "loop",
+        )),
+        (None, None) => {}
+    }
+
+    if let Some(ref image) = config.rootfs_image {
+        let upper = config
+            .rootfs_upper_size
+            .unwrap_or(crate::visibility::DEFAULT_UPPER_SIZE);
+        mounts.push(Mount::new(
+            "/",
+            format!("{} + tmpfs size={}", image.display(), upper),
+            "overlay",
+        ));
+    } else if minimal_root {
+        mounts.push(Mount::new("/", "tmpfs size=16m", "tmpfs").read_only());
+        mounts.push(Mount::new("/tmp", "tmpfs size=64m", "tmpfs"));
+    }
+    if image_root || minimal_root {
+        mounts.push(Mount::new("/proc", "proc", "proc"));
+        for device in crate::visibility::DEVICES {
+            mounts.push(Mount::new(device, device, "bind"));
+        }
+    }
+    if minimal_root {
+        let visible = crate::visibility::DEFAULT_VISIBLE_PATHS
+            .iter()
+            .map(PathBuf::from)
+            .chain(config.visible_paths.iter().cloned());
+        for path in visible {
+            mounts.push(Mount::new(&path, path.display(), "bind").read_only());
+        }
+        mounts.push(Mount::new("<program>", "the resolved program", "bind").read_only());
+    }
+    if image_root || minimal_root {
+        mounts.push(Mount::new(
+            &config.workdir,
+            config.workdir.display(),
+            "bind",
+        ));
+    }
+    if let Some(ref chroot) = config.chroot_dir {
+        mounts.push(Mount::new("/", chroot.display(), "chroot"));
+        for path in &config.chroot_bind_paths {
+            mounts.push(Mount::new(path, path.display(), "bind").read_only());
+        }
+    }
+
+    for binding in &config.directory_bindings {
+        let source = if binding.is_tmp {
+            "tmp".to_string()
+        } else {
+            binding.source.display().to_string()
+        };
+        let mount = Mount::new(&binding.target, source, "bind");
+        mounts.push(match binding.permissions {
+            DirectoryPermissions::ReadOnly => mount.read_only(),
+            DirectoryPermissions::ReadWrite | DirectoryPermissions::NoExec => mount,
+        });
+    }
+    for binding in &config.socket_bindings {
+        mounts.push(Mount::new(
+            &binding.target,
+            binding.source.display(),
+            "socket",
+        ));
+    }
+    mounts
+}
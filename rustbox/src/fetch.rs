@@ -0,0 +1,379 @@
+/// Test data fetchers for stdin and other test-data references
+///
+/// Judge workers can point at test data by reference instead of pre-provisioning
+/// files. Supported references are local paths and `file://` URLs, `http://` and
+/// `https://` URLs (with the `http` feature), and `s3://bucket/key` (with the
+/// `s3` feature). Remote data is streamed into a content-addressed local cache
+/// and only used once its SHA-256 matches the checksum supplied with the
+/// reference; the program then reads the cached file as its stdin, byte for
+/// byte, without it passing through memory.
+use crate::types::{IsolateError, Result};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+/// Where a piece of test data lives
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataSource {
+    /// Local file, either a plain path or a `file://` URL
+    File(PathBuf),
+    /// `http://` or `https://` URL
+    Http(String),
+    /// `s3://bucket/key`
+    S3 { bucket: String, key: String },
+}
+
+impl DataSource {
+    /// Parse a test-data reference
+    pub fn parse(reference: &str) -> Result<Self> {
+        if let Some(path) = reference.strip_prefix("file://") {
+            return Ok(DataSource::File(PathBuf::from(path)));
+        }
+        if reference.starts_with("http://") || reference.starts_with("https://") {
+            return Ok(DataSource::Http(reference.to_string()));
+        }
+        if let Some(rest) = reference.strip_prefix("s3://") {
+            return match rest.split_once('/') {
+                Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => {
+                    Ok(DataSource::S3 {
+                        bucket: bucket.to_string(),
+                        key: key.to_string(),
+                    })
+                }
+                _ => Err(IsolateError::Config(format!(
+                    "Invalid S3 reference '{}': expected s3://bucket/key",
+                    reference
+                ))),
+            };
+        }
+        if reference.contains("://") {
+            return Err(IsolateError::Config(format!(
+                "Unsupported test data reference '{}'",
+                reference
+            )));
+        }
+        Ok(DataSource::File(PathBuf::from(reference)))
+    }
+
+    /// Whether the data has to be downloaded into the cache
+    pub fn is_remote(&self) -> bool {
+        !matches!(self, DataSource::File(_))
+    }
+}
+
+/// Content-addressed cache of fetched test data, keyed by SHA-256
+#[derive(Debug, Clone)]
+pub struct DataCache {
+    dir: PathBuf,
+}
+
+impl Default for DataCache {
+    fn default() -> Self {
+        Self::new(Self::default_dir())
+    }
+}
+
+impl DataCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Cache directory used when none is configured
+    pub fn default_dir() -> PathBuf {
+        std::env::var_os("RUSTBOX_DATA_CACHE")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| crate::paths::paths().data_cache_dir())
+    }
+
+    /// Resolve a reference to a verified local file
+    ///
+    /// Remote references require `sha256`; local files are verified when one is
+    /// given and used in place otherwise.
+    pub fn resolve(&self, reference: &str, sha256: Option<&str>) -> Result<PathBuf> {
+        let source = DataSource::parse(reference)?;
+        let expected = sha256.map(normalize_checksum).transpose()?;
+
+        if let DataSource::File(path) = &source {
+            if let Some(expected) = &expected {
+                verify_checksum(reference, &sha256_file(path)?, expected)?;
+            }
+            return Ok(path.clone());
+        }
+
+        let expected = expected.ok_or_else(|| {
+            IsolateError::Fetch(format!(
+                "Remote test data '{}' requires a SHA-256 checksum",
+                reference
+            ))
+        })?;
+
+        let cached = self.dir.join(&expected);
+        if cached.exists() {
+            if sha256_file(&cached)? == expected {
+                log::debug!("Test data cache hit for {}", reference);
+                return Ok(cached);
+            }
+            log::warn!("Discarding corrupted cache entry {}", cached.display());
+            fs::remove_file(&cached)?;
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let mut partial = tempfile::NamedTempFile::new_in(&self.dir)?;
+        let actual = {
+            let mut reader = open_remote(&source)?;
+            let mut writer = HashingWriter::new(partial.as_file_mut());
+            std::io::copy(&mut reader, &mut writer).map_err(|e| {
+                IsolateError::Fetch(format!("Failed to download '{}': {}", reference, e))
+            })?;
+            writer.finish()
+        };
+        verify_checksum(reference, &actual, &expected)?;
+
+        partial.persist(&cached).map_err(|e| e.error)?;
+        Ok(cached)
+    }
+
+    /// Resolve a reference and open it, for use as stdin
+    pub fn open(&self, reference: &str, sha256: Option<&str>) -> Result<fs::File> {
+        Ok(fs::File::open(self.resolve(reference, sha256)?)?)
+    }
+}
+
+/// Compute the hex SHA-256 of a file
+pub fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn normalize_checksum(checksum: &str) -> Result<String> {
+    let checksum = checksum.trim();
+    let checksum = checksum.strip_prefix("sha256:").unwrap_or(checksum);
+    if checksum.len() != 64 || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(IsolateError::Config(format!(
+            "Invalid SHA-256 checksum '{}'",
+            checksum
+        )));
+    }
+    Ok(checksum.to_ascii_lowercase())
+}
+
+fn verify_checksum(reference: &str, actual: &str, expected: &str) -> Result<()> {
+    if actual != expected {
+        return Err(IsolateError::Fetch(format!(
+            "Checksum mismatch for '{}': expected {}, got {}",
+            reference, expected, actual
+        )));
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Writer that hashes everything passing through it
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    fn finish(self) -> String {
+        hex(&self.hasher.finalize())
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn open_remote(source: &DataSource) -> Result<Box<dyn Read + Send + Sync>> {
+    match source {
+        DataSource::Http(url) => http_get(url, &[]),
+        DataSource::S3 { bucket, key } => s3_get(bucket, key),
+        DataSource::File(path) => Ok(Box::new(fs::File::open(path)?)),
+    }
+}
+
+#[cfg(feature = "http")]
+fn http_get(url: &str, headers: &[(String, String)]) -> Result<Box<dyn Read + Send + Sync>> {
+    let mut request = ureq::get(url);
+    for (name, value) in headers {
+        request = request.set(name, value);
+    }
+    let response = request
+        .call()
+        .map_err(|e| IsolateError::Fetch(format!("GET {} failed: {}", url, e)))?;
+    Ok(response.into_reader())
+}
+
+#[cfg(not(feature = "http"))]
+fn http_get(_url: &str, _headers: &[(String, String)]) -> Result<Box<dyn Read + Send + Sync>> {
+    Err(IsolateError::Fetch(
+        "http(s):// references require rustbox to be built with the 'http' feature".to_string(),
+    ))
+}
+
+#[cfg(feature = "s3")]
+fn s3_get(bucket: &str, key: &str) -> Result<Box<dyn Read + Send + Sync>> {
+    let request = s3::SignedRequest::get_object(bucket, key, chrono::Utc::now());
+    http_get(&request.url, &request.headers)
+}
+
+#[cfg(not(feature = "s3"))]
+fn s3_get(_bucket: &str, _key: &str) -> Result<Box<dyn Read + Send + Sync>> {
+    Err(IsolateError::Fetch(
+        "s3:// references require rustbox to be built with the 's3' feature".to_string(),
+    ))
+}
+
+/// Minimal SigV4 signing for S3 GetObject
+///
+/// Credentials and region come from the standard `AWS_*` environment variables;
+/// `AWS_ENDPOINT_URL` switches to path-style requests for S3-compatible stores.
+/// Without credentials the request is sent unsigned (public buckets).
+#[cfg(feature = "s3")]
+mod s3 {
+    use super::hex;
+    use hmac::{Hmac, Mac};
+    use sha2::{Digest, Sha256};
+
+    pub struct SignedRequest {
+        pub url: String,
+        pub headers: Vec<(String, String)>,
+    }
+
+    impl SignedRequest {
+        pub fn get_object(bucket: &str, key: &str, now: chrono::DateTime<chrono::Utc>) -> Self {
+            let env = |name: &str| std::env::var(name).ok().filter(|v| !v.is_empty());
+            let region = env("AWS_REGION")
+                .or_else(|| env("AWS_DEFAULT_REGION"))
+                .unwrap_or_else(|| "us-east-1".to_string());
+
+            let (url, host, path) = match env("AWS_ENDPOINT_URL") {
+                Some(endpoint) => {
+                    let endpoint = endpoint.trim_end_matches('/');
+                    let host = endpoint
+                        .split_once("://")
+                        .map_or(endpoint, |(_, host)| host)
+                        .to_string();
+                    let path = format!("/{}/{}", bucket, uri_encode(key));
+                    (format!("{}{}", endpoint, path), host, path)
+                }
+                None => {
+                    let host = format!("{}.s3.{}.amazonaws.com", bucket, region);
+                    let path = format!("/{}", uri_encode(key));
+                    (format!("https://{}{}", host, path), host, path)
+                }
+            };
+
+            let (access_key, secret_key) =
+                match (env("AWS_ACCESS_KEY_ID"), env("AWS_SECRET_ACCESS_KEY")) {
+                    (Some(access), Some(secret)) => (access, secret),
+                    _ => {
+                        return Self {
+                            url,
+                            headers: Vec::new(),
+                        }
+                    }
+                };
+
+            let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+            let date = now.format("%Y%m%d").to_string();
+            let mut headers = vec![
+                ("host".to_string(), host),
+                (
+                    "x-amz-content-sha256".to_string(),
+                    "UNSIGNED-PAYLOAD".to_string(),
+                ),
+                ("x-amz-date".to_string(), amz_date.clone()),
+            ];
+            if let Some(token) = env("AWS_SESSION_TOKEN") {
+                headers.push(("x-amz-security-token".to_string(), token));
+            }
+
+            let canonical_headers: String = headers
+                .iter()
+                .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+                .collect();
+            let signed_headers = headers
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(";");
+            let canonical_request = format!(
+                "GET\n{}\n\n{}\n{}\nUNSIGNED-PAYLOAD",
+                path, canonical_headers, signed_headers
+            );
+
+            let scope = format!("{}/{}/s3/aws4_request", date, region);
+            let string_to_sign = format!(
+                "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+                amz_date,
+                scope,
+                hex(&Sha256::digest(canonical_request.as_bytes()))
+            );
+
+            let mut signing_key = hmac(format!("AWS4{}", secret_key).as_bytes(), &date);
+            for part in [region.as_str(), "s3", "aws4_request"] {
+                signing_key = hmac(&signing_key, part);
+            }
+            let signature = hex(&hmac(&signing_key, &string_to_sign));
+
+            // ureq sets Host itself
+            headers.retain(|(name, _)| name != "host");
+            headers.push((
+                "Authorization".to_string(),
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    access_key, scope, signed_headers, signature
+                ),
+            ));
+
+            Self { url, headers }
+        }
+    }
+
+    fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    /// Percent-encode an object key, keeping `/` separators
+    fn uri_encode(key: &str) -> String {
+        key.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                    (b as char).to_string()
+                }
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,281 @@
+/// Filesystem write audit via fanotify
+///
+/// In audit mode the supervisor places a fanotify watch on the filesystem that
+/// holds the sandbox before the program starts and records every modification
+/// and every close of a file opened for writing, either under the sandbox
+/// directories or anywhere on the watched filesystems by the sandbox's process
+/// group. The log is attached to the result so integrity investigations can see
+/// what a submission tried to touch. Requires CAP_SYS_ADMIN.
+use crate::types::{IsolateError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// Upper bound on recorded events so a write loop can't exhaust supervisor memory
+pub const MAX_AUDIT_EVENTS: usize = 4096;
+
+/// Kind of write access observed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileAccessKind {
+    /// File contents modified
+    Modify,
+    /// File closed after being opened for writing, whether or not it was written
+    CloseWrite,
+}
+
+/// A single audited access
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileAccessEvent {
+    /// Milliseconds since the audit started
+    pub elapsed_ms: u64,
+    pub pid: i32,
+    pub path: PathBuf,
+    pub kind: FileAccessKind,
+}
+
+/// Access log attached to an execution result
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileAuditLog {
+    pub events: Vec<FileAccessEvent>,
+    /// Events were dropped because the log or the kernel queue was full
+    pub truncated: bool,
+}
+
+/// Running fanotify watch; stop it with `finish` to collect the log
+pub struct FileAudit {
+    stop: Arc<AtomicBool>,
+    pgid: Arc<AtomicI32>,
+    reader: Option<JoinHandle<FileAuditLog>>,
+}
+
+impl FileAudit {
+    /// Whether fanotify can be used in this context
+    pub fn supported() -> bool {
+        let fd = unsafe {
+            libc::fanotify_init(
+                libc::FAN_CLASS_NOTIF | libc::FAN_CLOEXEC,
+                libc::O_RDONLY as libc::c_uint,
+            )
+        };
+        if fd < 0 {
+            return false;
+        }
+        unsafe { libc::close(fd) };
+        true
+    }
+
+    /// Start watching writes under `roots`
+    pub fn start(roots: &[PathBuf]) -> Result<Self> {
+        let roots: Vec<PathBuf> = roots
+            .iter()
+            .map(|root| root.canonicalize().unwrap_or_else(|_| root.clone()))
+            .collect();
+
+        let fd = unsafe {
+            libc::fanotify_init(
+                libc::FAN_CLASS_NOTIF | libc::FAN_CLOEXEC | libc::FAN_NONBLOCK,
+                (libc::O_RDONLY | libc::O_LARGEFILE | libc::O_CLOEXEC) as libc::c_uint,
+            )
+        };
+        if fd < 0 {
+            return Err(IsolateError::Process(format!(
+                "Failed to initialize fanotify (requires CAP_SYS_ADMIN): {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+
+        let mask = libc::FAN_MODIFY | libc::FAN_CLOSE_WRITE;
+        for root in &roots {
+            if let Err(e) = mark(fd, root, mask) {
+                unsafe { libc::close(fd) };
+                return Err(e);
+            }
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let pgid = Arc::new(AtomicI32::new(0));
+        let reader = {
+            let stop = Arc::clone(&stop);
+            let pgid = Arc::clone(&pgid);
+            std::thread::Builder::new()
+                .name("rustbox-file-audit".to_string())
+                .spawn(move || read_events(fd, &roots, &pgid, &stop))
+                .map_err(|e| {
+                    unsafe { libc::close(fd) };
+                    IsolateError::Process(format!("Failed to start file audit thread: {}", e))
+                })?
+        };
+
+        Ok(Self {
+            stop,
+            pgid,
+            reader: Some(reader),
+        })
+    }
+
+    /// Also record writes outside the sandbox directories by this process group
+    pub fn set_process_group(&self, pgid: u32) {
+        self.pgid.store(pgid as i32, Ordering::SeqCst);
+    }
+
+    /// Stop watching and return the collected access log
+    pub fn finish(mut self) -> FileAuditLog {
+        self.stop.store(true, Ordering::SeqCst);
+        self.reader
+            .take()
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for FileAudit {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Mark the whole filesystem holding `path`, falling back to its mount
+fn mark(fd: libc::c_int, path: &Path, mask: u64) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+        .map_err(|_| IsolateError::Config(format!("Invalid audit path: {}", path.display())))?;
+
+    for scope in [libc::FAN_MARK_FILESYSTEM, libc::FAN_MARK_MOUNT] {
+        let rc = unsafe {
+            libc::fanotify_mark(
+                fd,
+                libc::FAN_MARK_ADD | scope,
+                mask,
+                libc::AT_FDCWD,
+                c_path.as_ptr(),
+            )
+        };
+        if rc == 0 {
+            return Ok(());
+        }
+    }
+
+    Err(IsolateError::Process(format!(
+        "Failed to watch {} with fanotify: {}",
+        path.display(),
+        std::io::Error::last_os_error()
+    )))
+}
+
+/// Reader thread: drain events until told to stop, then drain once more
+fn read_events(
+    fd: libc::c_int,
+    roots: &[PathBuf],
+    pgid: &AtomicI32,
+    stop: &AtomicBool,
+) -> FileAuditLog {
+    let started = std::time::Instant::now();
+    let own_pid = std::process::id() as i32;
+    let mut log = FileAuditLog::default();
+    let mut buf = vec![0u8; 64 * 1024];
+
+    loop {
+        let stopping = stop.load(Ordering::SeqCst);
+
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { libc::poll(&mut pollfd, 1, if stopping { 0 } else { 50 }) };
+
+        if ready > 0 {
+            let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+            if n > 0 {
+                let pgid = pgid.load(Ordering::SeqCst);
+                parse_events(&buf[..n as usize], roots, own_pid, pgid, started, &mut log);
+                // Keep draining before honoring a stop request
+                continue;
+            }
+        }
+
+        if stopping {
+            break;
+        }
+    }
+
+    unsafe { libc::close(fd) };
+    log
+}
+
+fn parse_events(
+    mut buf: &[u8],
+    roots: &[PathBuf],
+    own_pid: i32,
+    pgid: i32,
+    started: std::time::Instant,
+    log: &mut FileAuditLog,
+) {
+    let header_len = std::mem::size_of::<libc::fanotify_event_metadata>();
+
+    while buf.len() >= header_len {
+        let metadata: libc::fanotify_event_metadata =
+            unsafe { std::ptr::read_unaligned(buf.as_ptr() as *const _) };
+        let event_len = metadata.event_len as usize;
+        if metadata.vers != libc::FANOTIFY_METADATA_VERSION
+            || event_len < header_len
+            || event_len > buf.len()
+        {
+            break;
+        }
+        buf = &buf[event_len..];
+
+        if metadata.mask & libc::FAN_Q_OVERFLOW != 0 {
+            log.truncated = true;
+        }
+        if metadata.fd < 0 {
+            continue;
+        }
+
+        let path = std::fs::read_link(format!("/proc/self/fd/{}", metadata.fd)).ok();
+        unsafe { libc::close(metadata.fd) };
+
+        if metadata.pid == own_pid {
+            continue;
+        }
+        let Some(path) = path else {
+            continue;
+        };
+        let in_sandbox_group = pgid > 0 && unsafe { libc::getpgid(metadata.pid) } == pgid;
+        if !in_sandbox_group && !roots.iter().any(|root| path.starts_with(root)) {
+            continue;
+        }
+
+        let kinds = [
+            (libc::FAN_MODIFY, FileAccessKind::Modify),
+            (libc::FAN_CLOSE_WRITE, FileAccessKind::CloseWrite),
+        ];
+        for (bit, kind) in kinds {
+            if metadata.mask & bit == 0 {
+                continue;
+            }
+            // A write loop produces a stream of modify events; one is enough
+            if kind == FileAccessKind::Modify
+                && log.events.last().is_some_and(|last| {
+                    last.kind == kind && last.pid == metadata.pid && last.path == path
+                })
+            {
+                continue;
+            }
+            if log.events.len() >= MAX_AUDIT_EVENTS {
+                log.truncated = true;
+                break;
+            }
+            log.events.push(FileAccessEvent {
+                elapsed_ms: started.elapsed().as_millis() as u64,
+                pid: metadata.pid,
+                path: path.clone(),
+                kind,
+            });
+        }
+    }
+}
@@ -5,6 +5,58 @@ use std::path::{Path, PathBuf};
 
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+
+/// Host paths bound read-only into every chroot unless configured otherwise
+pub const DEFAULT_CHROOT_BIND_PATHS: &[&str] = &[
+    "/usr",
+    "/bin",
+    "/lib",
+    "/lib64",
+    "/etc/alternatives",
+    "/etc/ld.so.cache",
+];
+
+/// ioctl requests of the loop driver (linux/loop.h)
+#[cfg(unix)]
+const LOOP_SET_FD: libc::c_ulong = 0x4C00;
+#[cfg(unix)]
+const LOOP_CLR_FD: libc::c_ulong = 0x4C01;
+#[cfg(unix)]
+const LOOP_SET_STATUS64: libc::c_ulong = 0x4C04;
+#[cfg(unix)]
+const LOOP_CTL_GET_FREE: libc::c_ulong = 0x4C82;
+/// Detach the loop device when its last user goes away
+#[cfg(unix)]
+const LO_FLAGS_AUTOCLEAR: u32 = 4;
+/// Free loop devices tried before giving up to concurrent boxes
+#[cfg(unix)]
+const LOOP_ATTACH_ATTEMPTS: usize = 8;
+/// Largest page cache folio a buffered write reserves space for at once
+#[cfg(unix)]
+const LARGE_FOLIO_SIZE: u64 = 2 * 1024 * 1024;
+/// Smallest image mkfs.ext4 formats reliably
+const MIN_WORKDIR_IMAGE_SIZE: u64 = 1024 * 1024;
+
+/// `struct loop_info64` of linux/loop.h
+#[cfg(unix)]
+#[repr(C)]
+struct LoopInfo64 {
+    lo_device: u64,
+    lo_inode: u64,
+    lo_rdevice: u64,
+    lo_offset: u64,
+    lo_sizelimit: u64,
+    lo_number: u32,
+    lo_encrypt_type: u32,
+    lo_encrypt_key_size: u32,
+    lo_flags: u32,
+    lo_file_name: [u8; 64],
+    lo_crypt_name: [u8; 64],
+    lo_encrypt_key: [u8; 32],
+    lo_init: [u64; 2],
+}
 
 /// Filesystem security controller for process isolation
 #[derive(Clone, Debug)]
@@ -52,8 +104,6 @@ impl FilesystemSecurity {
     /// Setup a single directory binding
     #[cfg(unix)]
     fn setup_single_binding(&self, binding: &crate::types::DirectoryBinding) -> Result<()> {
-        use crate::types::DirectoryPermissions;
-
         // Skip if source doesn't exist and maybe flag is set
         if binding.maybe && !binding.source.exists() {
             log::debug!(
@@ -64,18 +114,7 @@ impl FilesystemSecurity {
         }
 
         // Determine the actual target path within chroot or working directory
-        let target_path = if let Some(ref chroot_path) = self.chroot_dir {
-            chroot_path.join(binding.target.strip_prefix("/").unwrap_or(&binding.target))
-        } else {
-            // If no chroot, use working directory as base for relative paths
-            if binding.target.is_absolute() {
-                // For absolute paths, create under working directory to avoid permission issues
-                self.workdir
-                    .join(binding.target.strip_prefix("/").unwrap_or(&binding.target))
-            } else {
-                self.workdir.join(&binding.target)
-            }
-        };
+        let target_path = self.resolve_binding_target(&binding.target);
 
         // Create target directory if it doesn't exist
         if let Some(parent) = target_path.parent() {
@@ -98,81 +137,273 @@ impl FilesystemSecurity {
             return Ok(()); // No mounting needed for tmp directories
         }
 
-        // Prepare mount flags based on permissions
-        let mut mount_flags = libc::MS_BIND;
+        let mount_flags = binding_flags(&binding.permissions);
+
+        // Remap ownership with an idmapped mount when the binding asks for it
+        if binding.uid_map.is_some() || binding.gid_map.is_some() {
+            match idmapped_bind_mount(binding, &target_path) {
+                Ok(()) => {
+                    log::info!(
+                        "Bound directory {} to {} with permissions {:?} (idmapped)",
+                        binding.source.display(),
+                        target_path.display(),
+                        binding.permissions
+                    );
+                    return Ok(());
+                }
+                Err(e) if self.strict_mode => return Err(e),
+                Err(e) => log::warn!(
+                    "Idmapped mount of {} unavailable, ownership will not be remapped: {}",
+                    binding.source.display(),
+                    e
+                ),
+            }
+        }
 
-        match binding.permissions {
-            DirectoryPermissions::ReadOnly => {
-                mount_flags |= libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV;
+        match bind_mount(&binding.source, &target_path, mount_flags) {
+            Ok(()) => log::info!(
+                "Bound directory {} to {} with permissions {:?}",
+                binding.source.display(),
+                target_path.display(),
+                binding.permissions
+            ),
+            Err(e) if self.strict_mode => return Err(e),
+            Err(e) => {
+                log::warn!("{} (falling back to file copy)", e);
+
+                // Fallback: copy files for non-root users
+                self.copy_directory_contents(&binding.source, &target_path)?;
+                log::info!(
+                    "Copied directory contents from {} to {} (fallback mode)",
+                    binding.source.display(),
+                    target_path.display()
+                );
             }
-            DirectoryPermissions::ReadWrite => {
-                mount_flags |= libc::MS_NOSUID | libc::MS_NODEV;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn setup_single_binding(&self, _binding: &crate::types::DirectoryBinding) -> Result<()> {
+        Err(IsolateError::Config(
+            "Directory binding is only supported on Unix systems".to_string(),
+        ))
+    }
+
+    /// Unmount directory bindings so removing the box never reaches into their sources
+    #[cfg(unix)]
+    pub fn unmount_directory_bindings(
+        &self,
+        bindings: &[crate::types::DirectoryBinding],
+    ) -> Result<()> {
+        for binding in bindings.iter().filter(|binding| !binding.is_tmp) {
+            let target_path = self.resolve_binding_target(&binding.target);
+            if !is_mount_point(&target_path) {
+                continue;
+            }
+            let target_cstr = std::ffi::CString::new(target_path.to_string_lossy().as_bytes())
+                .map_err(|e| IsolateError::Config(format!("Invalid target path: {}", e)))?;
+            if unsafe { libc::umount2(target_cstr.as_ptr(), libc::MNT_DETACH) } != 0 {
+                return Err(IsolateError::Config(format!(
+                    "Failed to unmount binding {}: {}",
+                    target_path.display(),
+                    std::io::Error::last_os_error()
+                )));
             }
-            DirectoryPermissions::NoExec => {
-                mount_flags |= libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn unmount_directory_bindings(
+        &self,
+        _bindings: &[crate::types::DirectoryBinding],
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    /// Map a sandbox path onto the host path backing it (chroot or working directory)
+    fn resolve_binding_target(&self, target: &Path) -> PathBuf {
+        if let Some(ref chroot_path) = self.chroot_dir {
+            chroot_path.join(target.strip_prefix("/").unwrap_or(target))
+        } else {
+            // If no chroot, use working directory as base for relative paths
+            if target.is_absolute() {
+                // For absolute paths, create under working directory to avoid permission issues
+                self.workdir
+                    .join(target.strip_prefix("/").unwrap_or(target))
+            } else {
+                self.workdir.join(target)
             }
         }
+    }
+
+    /// `open_beneath` the chroot or working directory holding `target_path`
+    #[cfg(unix)]
+    fn open_binding_target(
+        &self,
+        target_path: &Path,
+        create: bool,
+    ) -> std::io::Result<std::os::fd::OwnedFd> {
+        let base = self.chroot_dir.as_deref().unwrap_or(&self.workdir);
+        let relative = target_path.strip_prefix(base).unwrap_or(target_path);
+        open_beneath(base, relative, create)
+    }
+
+    /// Setup host Unix socket bindings for the sandbox
+    pub fn setup_socket_bindings(
+        &self,
+        bindings: &[crate::types::SocketBinding],
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> Result<()> {
+        for binding in bindings {
+            self.setup_single_socket_binding(binding, uid, gid)?;
+        }
+        Ok(())
+    }
+
+    /// Setup a single socket binding by bind mounting the socket inode onto a placeholder file
+    ///
+    /// The socket inode is shared with the host, so its owner and mode are left
+    /// as they are: whoever runs the service grants the box user access there.
+    #[cfg(unix)]
+    fn setup_single_socket_binding(
+        &self,
+        binding: &crate::types::SocketBinding,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> Result<()> {
+        use std::os::fd::AsRawFd;
+        use std::os::unix::fs::MetadataExt;
+
+        let target_path = self.resolve_binding_target(&binding.target);
+
+        // Bindings persist across runs of the box; mounting again would stack
+        if is_mount_point(&target_path) {
+            log::debug!("Socket {} is already bound", target_path.display());
+            return Ok(());
+        }
+
+        // The box may have planted symlinks anywhere along the path on an
+        // earlier run, so it is walked without following any and the mount
+        // goes through the descriptor
+        let mount_point = self.open_binding_target(&target_path, true).map_err(|e| {
+            IsolateError::Config(format!(
+                "Failed to prepare socket mount point {}: {}",
+                target_path.display(),
+                e
+            ))
+        })?;
 
-        // Perform bind mount
         let source_cstr = std::ffi::CString::new(binding.source.to_string_lossy().as_bytes())
-            .map_err(|e| IsolateError::Config(format!("Invalid source path: {}", e)))?;
-        let target_cstr = std::ffi::CString::new(target_path.to_string_lossy().as_bytes())
-            .map_err(|e| IsolateError::Config(format!("Invalid target path: {}", e)))?;
+            .map_err(|e| IsolateError::Config(format!("Invalid socket path: {}", e)))?;
+        let target_cstr =
+            std::ffi::CString::new(format!("/proc/self/fd/{}", mount_point.as_raw_fd()))
+                .map_err(|e| IsolateError::Config(format!("Invalid socket target path: {}", e)))?;
 
         let result = unsafe {
             libc::mount(
                 source_cstr.as_ptr(),
                 target_cstr.as_ptr(),
                 std::ptr::null(),
-                mount_flags,
+                libc::MS_BIND,
                 std::ptr::null(),
             )
         };
 
+        // A symlink to the host path would not resolve inside a chroot, so a
+        // socket that cannot be mounted is not bound at all
         if result != 0 {
-            let errno = unsafe { *libc::__errno_location() };
-            if self.strict_mode {
-                return Err(IsolateError::Config(format!(
-                    "Failed to bind mount {} to {}: errno {}",
-                    binding.source.display(),
-                    target_path.display(),
-                    errno
-                )));
-            } else {
-                log::warn!(
-                    "Failed to bind mount {} to {}: errno {} (falling back to file copy)",
-                    binding.source.display(),
-                    target_path.display(),
-                    errno
-                );
+            return Err(IsolateError::Config(format!(
+                "Failed to bind mount socket {} to {}: {}",
+                binding.source.display(),
+                target_path.display(),
+                std::io::Error::last_os_error()
+            )));
+        }
 
-                // Fallback: copy files for non-root users
-                self.copy_directory_contents(&binding.source, &target_path)?;
-                log::info!(
-                    "Copied directory contents from {} to {} (fallback mode)",
+        if let Some(uid) = uid {
+            let metadata = fs::metadata(&binding.source)?;
+            if !socket_connectable(&metadata, uid, gid) {
+                let message = format!(
+                    "Socket {} (owner {}:{}, mode {:o}) is not writable by box user {}",
                     binding.source.display(),
-                    target_path.display()
+                    metadata.uid(),
+                    metadata.gid(),
+                    metadata.mode() & 0o777,
+                    uid
                 );
+                if self.strict_mode {
+                    let _ = self.unmount_socket_bindings(std::slice::from_ref(binding));
+                    return Err(IsolateError::Config(message));
+                }
+                log::warn!("{}", message);
             }
-        } else {
-            log::info!(
-                "Bound directory {} to {} with permissions {:?}",
-                binding.source.display(),
-                target_path.display(),
-                binding.permissions
-            );
         }
 
+        log::info!(
+            "Bound socket {} to {}",
+            binding.source.display(),
+            target_path.display()
+        );
+
         Ok(())
     }
 
     #[cfg(not(unix))]
-    fn setup_single_binding(&self, _binding: &crate::types::DirectoryBinding) -> Result<()> {
+    fn setup_single_socket_binding(
+        &self,
+        _binding: &crate::types::SocketBinding,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+    ) -> Result<()> {
         Err(IsolateError::Config(
-            "Directory binding is only supported on Unix systems".to_string(),
+            "Socket binding is only supported on Unix systems".to_string(),
         ))
     }
 
+    /// Unmount socket bindings so they do not stack or outlive the box
+    #[cfg(unix)]
+    pub fn unmount_socket_bindings(&self, bindings: &[crate::types::SocketBinding]) -> Result<()> {
+        for binding in bindings {
+            use std::os::fd::AsRawFd;
+
+            let target_path = self.resolve_binding_target(&binding.target);
+            if !is_mount_point(&target_path) {
+                continue;
+            }
+            // Reached the same way as when it was mounted, as the box may have
+            // replaced a directory on the path with a symlink since
+            let mount_point = self.open_binding_target(&target_path, false).map_err(|e| {
+                IsolateError::Config(format!(
+                    "Failed to open socket binding {}: {}",
+                    target_path.display(),
+                    e
+                ))
+            })?;
+            let target_cstr =
+                std::ffi::CString::new(format!("/proc/self/fd/{}", mount_point.as_raw_fd()))
+                    .map_err(|e| {
+                        IsolateError::Config(format!("Invalid socket target path: {}", e))
+                    })?;
+            if unsafe { libc::umount2(target_cstr.as_ptr(), libc::MNT_DETACH) } != 0 {
+                return Err(IsolateError::Config(format!(
+                    "Failed to unmount socket binding {}: {}",
+                    target_path.display(),
+                    std::io::Error::last_os_error()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn unmount_socket_bindings(&self, _bindings: &[crate::types::SocketBinding]) -> Result<()> {
+        Ok(())
+    }
+
     /// Copy directory contents as fallback when bind mounting fails
     fn copy_directory_contents(&self, source: &Path, target: &Path) -> Result<()> {
         use std::fs;
@@ -566,6 +797,107 @@ impl FilesystemSecurity {
         Ok(())
     }
 
+    /// Bind the host `paths` read-only (nosuid, nodev) into the chroot at the
+    /// same locations, recreating the symlinks they go through
+    ///
+    /// Paths missing on this host and mounts left by an earlier run are skipped.
+    /// Without the privilege to mount this fails only in strict mode.
+    #[cfg(unix)]
+    pub fn populate_rootfs(&self, paths: &[PathBuf]) -> Result<()> {
+        use crate::visibility::HostEntry;
+
+        let Some(ref chroot_path) = self.chroot_dir else {
+            return Ok(());
+        };
+        let entries = crate::visibility::host_entries(paths.iter().cloned());
+
+        // Entries below a bound directory are already visible through it
+        let mut bound: Vec<&PathBuf> = Vec::new();
+        for (path, entry) in &entries {
+            if bound.iter().any(|dir| path.starts_with(dir)) {
+                continue;
+            }
+            let target = chroot_path.join(path.strip_prefix("/").unwrap_or(path));
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match entry {
+                HostEntry::Symlink(link) => {
+                    // The chroot skeleton has real bin/lib dirs where the host may link
+                    if target.is_dir() && !target.is_symlink() {
+                        let _ = fs::remove_dir(&target);
+                    }
+                    if fs::symlink_metadata(&target).is_err() {
+                        std::os::unix::fs::symlink(link, &target)?;
+                    }
+                    continue;
+                }
+                HostEntry::Dir => fs::create_dir_all(&target)?,
+                HostEntry::File => {
+                    if !target.exists() {
+                        fs::File::create(&target)?;
+                    }
+                }
+            }
+            let flags = libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV;
+            if let Err(e) = bind_mount(path, &target, flags) {
+                if self.strict_mode {
+                    return Err(e);
+                }
+                log::warn!("Chroot left without {}: {}", path.display(), e);
+                continue;
+            }
+            if *entry == HostEntry::Dir {
+                bound.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn populate_rootfs(&self, _paths: &[PathBuf]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Unmount everything mounted inside the chroot, deepest first
+    #[cfg(unix)]
+    pub fn unmount_rootfs(&self) -> Result<()> {
+        let Some(ref chroot_path) = self.chroot_dir else {
+            return Ok(());
+        };
+        let Ok(mountinfo) = fs::read_to_string("/proc/self/mountinfo") else {
+            return Ok(());
+        };
+        let mut mounts: Vec<&Path> = mountinfo
+            .lines()
+            .filter_map(|line| line.split_whitespace().nth(4))
+            .map(Path::new)
+            .filter(|mount_point| {
+                mount_point.starts_with(chroot_path) && *mount_point != chroot_path
+            })
+            .collect();
+        mounts.sort_by_key(|mount_point| std::cmp::Reverse(mount_point.components().count()));
+        mounts.dedup();
+
+        for mount_point in mounts {
+            let target_cstr = std::ffi::CString::new(mount_point.to_string_lossy().as_bytes())
+                .map_err(|e| IsolateError::Config(format!("Invalid mount point: {}", e)))?;
+            if unsafe { libc::umount2(target_cstr.as_ptr(), libc::MNT_DETACH) } != 0 {
+                return Err(IsolateError::Config(format!(
+                    "Failed to unmount {}: {}",
+                    mount_point.display(),
+                    std::io::Error::last_os_error()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn unmount_rootfs(&self) -> Result<()> {
+        Ok(())
+    }
+
     #[cfg(not(unix))]
     pub fn apply_chroot(&self) -> Result<()> {
         if self.chroot_dir.is_some() {
@@ -604,6 +936,311 @@ impl FilesystemSecurity {
         Ok(())
     }
 
+    /// Mount a tmpfs of `size` bytes on the workdir, owned by the box user
+    ///
+    /// Writes by the sandboxed program are then bounded by the tmpfs size and
+    /// discarded when it is unmounted. A workdir that already is a mount point
+    /// is left as it is.
+    #[cfg(unix)]
+    pub fn mount_workdir_tmpfs(&self, size: u64, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        if size == 0 {
+            return Err(IsolateError::Config(
+                "Workdir tmpfs size must be greater than zero".to_string(),
+            ));
+        }
+        fs::create_dir_all(&self.workdir)
+            .map_err(|e| IsolateError::Config(format!("Failed to create workdir: {}", e)))?;
+        if is_mount_point(&self.workdir) {
+            return Ok(());
+        }
+
+        let mount_flags = libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOATIME;
+        let mut options = format!("size={},mode=755", size);
+        if let Some(uid) = uid {
+            options.push_str(&format!(",uid={}", uid));
+        }
+        if let Some(gid) = gid {
+            options.push_str(&format!(",gid={}", gid));
+        }
+
+        let source_cstr = std::ffi::CString::new("tmpfs")
+            .map_err(|e| IsolateError::Config(format!("Invalid source string: {}", e)))?;
+        let target_cstr = std::ffi::CString::new(self.workdir.to_string_lossy().as_bytes())
+            .map_err(|e| IsolateError::Config(format!("Invalid workdir path: {}", e)))?;
+        let options_cstr = std::ffi::CString::new(options)
+            .map_err(|e| IsolateError::Config(format!("Invalid options string: {}", e)))?;
+
+        let result = unsafe {
+            libc::mount(
+                source_cstr.as_ptr(),
+                target_cstr.as_ptr(),
+                source_cstr.as_ptr(),
+                mount_flags,
+                options_cstr.as_ptr() as *const libc::c_void,
+            )
+        };
+
+        if result != 0 {
+            let err = std::io::Error::last_os_error();
+            if self.strict_mode {
+                return Err(IsolateError::Config(format!(
+                    "Failed to mount tmpfs on workdir {}: {}",
+                    self.workdir.display(),
+                    err
+                )));
+            }
+            log::warn!(
+                "Failed to mount tmpfs on workdir {}: {}",
+                self.workdir.display(),
+                err
+            );
+        } else {
+            log::info!("Mounted {} byte tmpfs at {}", size, self.workdir.display());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn mount_workdir_tmpfs(
+        &self,
+        _size: u64,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+    ) -> Result<()> {
+        Err(IsolateError::Config(
+            "Workdir tmpfs is only supported on Unix".to_string(),
+        ))
+    }
+
+    /// Mount an ext4 image of `size` bytes on the workdir, owned by the box user
+    ///
+    /// This is the disk quota of the box: writes by the sandboxed program are
+    /// bounded by the image size, which is backed by the disk rather than
+    /// memory. The image is unlinked once mounted, so it disappears with the
+    /// mount. A workdir that already is a mount point is left as it is.
+    #[cfg(unix)]
+    pub fn mount_workdir_image(&self, size: u64, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        if size < MIN_WORKDIR_IMAGE_SIZE {
+            return Err(IsolateError::Config(format!(
+                "Disk quota must be at least {} bytes",
+                MIN_WORKDIR_IMAGE_SIZE
+            )));
+        }
+        fs::create_dir_all(&self.workdir)
+            .map_err(|e| IsolateError::Config(format!("Failed to create workdir: {}", e)))?;
+        if is_mount_point(&self.workdir) {
+            return Ok(());
+        }
+
+        match self.format_and_mount_image(size, uid, gid) {
+            Ok(()) => {
+                log::info!(
+                    "Mounted {} byte disk quota image at {}",
+                    size,
+                    self.workdir.display()
+                );
+                Ok(())
+            }
+            Err(e) if self.strict_mode => Err(e),
+            Err(e) => {
+                log::warn!("{}", e);
+                Ok(())
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn mount_workdir_image(
+        &self,
+        _size: u64,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+    ) -> Result<()> {
+        Err(IsolateError::Config(
+            "Disk quotas are only supported on Unix".to_string(),
+        ))
+    }
+
+    #[cfg(unix)]
+    fn format_and_mount_image(&self, size: u64, uid: Option<u32>, gid: Option<u32>) -> Result<()> {
+        let failed = |what: &str, e: &dyn std::fmt::Display| {
+            IsolateError::Config(format!(
+                "Failed to {} for workdir {}: {}",
+                what,
+                self.workdir.display(),
+                e
+            ))
+        };
+
+        // Sparse, so only the blocks the program writes take disk space
+        let parent = self.workdir.parent().unwrap_or(Path::new("/"));
+        let image = tempfile::Builder::new()
+            .prefix(".rustbox-quota-")
+            .suffix(".img")
+            .tempfile_in(parent)
+            .map_err(|e| failed("create disk quota image", &e))?;
+        image
+            .as_file()
+            .set_len(size)
+            .map_err(|e| failed("size disk quota image", &e))?;
+
+        // No journal and no reserved blocks: the whole image is for the program
+        let owner = format!(
+            "root_owner={}:{}",
+            uid.unwrap_or_else(|| unsafe { libc::geteuid() }),
+            gid.unwrap_or_else(|| unsafe { libc::getegid() })
+        );
+        let output = std::process::Command::new("mkfs.ext4")
+            .args(["-q", "-F", "-m", "0", "-O", "^has_journal", "-E", &owner])
+            .arg(image.path())
+            .output()
+            .map_err(|e| failed("run mkfs.ext4", &e))?;
+        if !output.status.success() {
+            return Err(failed(
+                "format disk quota image",
+                &String::from_utf8_lossy(&output.stderr).trim(),
+            ));
+        }
+
+        let (device, device_path) =
+            attach_loop_device(image.as_file()).map_err(|e| failed("attach loop device", &e))?;
+
+        let source_cstr = std::ffi::CString::new(device_path.to_string_lossy().as_bytes())
+            .map_err(|e| IsolateError::Config(format!("Invalid device path: {}", e)))?;
+        let target_cstr = std::ffi::CString::new(self.workdir.to_string_lossy().as_bytes())
+            .map_err(|e| IsolateError::Config(format!("Invalid workdir path: {}", e)))?;
+        let fstype_cstr = std::ffi::CString::new("ext4")
+            .map_err(|e| IsolateError::Config(format!("Invalid filesystem type: {}", e)))?;
+        let mount_flags = libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOATIME;
+        let result = unsafe {
+            libc::mount(
+                source_cstr.as_ptr(),
+                target_cstr.as_ptr(),
+                fstype_cstr.as_ptr(),
+                mount_flags,
+                std::ptr::null(),
+            )
+        };
+        if result != 0 {
+            return Err(failed(
+                "mount disk quota image",
+                &std::io::Error::last_os_error(),
+            ));
+        }
+        // The mount now holds the loop device, which holds the image
+        drop(device);
+        Ok(())
+    }
+
+    /// Whether the bounded workdir has (next to) no space left
+    #[cfg(unix)]
+    pub fn workdir_full(&self) -> bool {
+        let Ok(stat) = nix::sys::statvfs::statvfs(&self.workdir) else {
+            return false;
+        };
+        let block = stat.fragment_size();
+        let total = stat.blocks() * block;
+        let available = stat.blocks_available() * block;
+        // The kernel refuses a buffered write it cannot fit whole, so the write
+        // that failed can leave up to one large folio free
+        available <= (total / 2).min(LARGE_FOLIO_SIZE).max(16 * block)
+    }
+
+    #[cfg(not(unix))]
+    pub fn workdir_full(&self) -> bool {
+        false
+    }
+
+    /// Unmount the workdir tmpfs or disk quota image, discarding everything
+    /// written to it
+    #[cfg(unix)]
+    pub fn unmount_workdir(&self) -> Result<()> {
+        if !is_mount_point(&self.workdir) {
+            return Ok(());
+        }
+        let target_cstr = std::ffi::CString::new(self.workdir.to_string_lossy().as_bytes())
+            .map_err(|e| IsolateError::Config(format!("Invalid workdir path: {}", e)))?;
+        // Detach so a process still holding a file open can't keep the box busy
+        if unsafe { libc::umount2(target_cstr.as_ptr(), libc::MNT_DETACH) } != 0 {
+            return Err(IsolateError::Config(format!(
+                "Failed to unmount workdir {}: {}",
+                self.workdir.display(),
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn unmount_workdir(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Compute the umask applied to the sandboxed process
+    ///
+    /// When the working directory lives under the host temp directory, other
+    /// users on the host can traverse it, so permissions for "other" are always masked.
+    pub fn effective_umask(&self, umask: u32) -> u32 {
+        if self.workdir.starts_with(std::env::temp_dir()) {
+            (umask | 0o007) & 0o777
+        } else {
+            umask & 0o777
+        }
+    }
+
+    /// Write a file into the sandbox with permissions derived from the umask,
+    /// owned by `uid`/`gid` when given
+    ///
+    /// Runs as root in a directory the box user can write, so whatever is at
+    /// `path` is unlinked and the file created anew without following a
+    /// symlink; mode and owner are set through the open descriptor.
+    #[cfg(unix)]
+    pub fn write_sandbox_file(
+        &self,
+        path: &Path,
+        contents: &[u8],
+        umask: u32,
+        uid: Option<u32>,
+        gid: Option<u32>,
+    ) -> Result<()> {
+        use std::io::Write;
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let mode = 0o666 & !self.effective_umask(umask);
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        // Something put back in between makes this fail rather than be followed
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .mode(mode)
+            .open(path)?;
+        file.write_all(contents)?;
+
+        // The mode above is filtered through the process umask
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+        if uid.is_some() || gid.is_some() {
+            std::os::unix::fs::fchown(&file, uid, gid)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    pub fn write_sandbox_file(
+        &self,
+        path: &Path,
+        contents: &[u8],
+        _umask: u32,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+    ) -> Result<()> {
+        fs::write(path, contents)?;
+        Ok(())
+    }
+
     /// Validate that a path is within the allowed boundaries
     pub fn validate_path(&self, path: &Path) -> Result<()> {
         let canonical_path = path
@@ -681,3 +1318,383 @@ impl FilesystemSecurity {
         }
     }
 }
+
+/// Mount flags enforcing `permissions` on a directory binding
+#[cfg(unix)]
+fn binding_flags(permissions: &crate::types::DirectoryPermissions) -> libc::c_ulong {
+    use crate::types::DirectoryPermissions;
+
+    let flags = libc::MS_NOSUID | libc::MS_NODEV;
+    match permissions {
+        DirectoryPermissions::ReadOnly => flags | libc::MS_RDONLY,
+        DirectoryPermissions::ReadWrite => flags,
+        DirectoryPermissions::NoExec => flags | libc::MS_RDONLY | libc::MS_NOEXEC,
+    }
+}
+
+/// `move_mount` flag: the source is the detached tree referenced by the fd itself
+#[cfg(unix)]
+const MOVE_MOUNT_F_EMPTY_PATH: libc::c_uint = 0x0000_0004;
+
+/// Bind a directory through an idmapped mount (Linux 5.12+)
+///
+/// The detached bind mount is attached to a user namespace whose maps present
+/// the binding's host owner ids as the sandbox ids; all other ids are unchanged.
+#[cfg(unix)]
+fn idmapped_bind_mount(binding: &crate::types::DirectoryBinding, target: &Path) -> Result<()> {
+    use crate::types::DirectoryPermissions;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    let userns = mapped_user_namespace(binding.uid_map, binding.gid_map)?;
+
+    let source_cstr = std::ffi::CString::new(binding.source.to_string_lossy().as_bytes())
+        .map_err(|e| IsolateError::Config(format!("Invalid source path: {}", e)))?;
+    let target_cstr = std::ffi::CString::new(target.to_string_lossy().as_bytes())
+        .map_err(|e| IsolateError::Config(format!("Invalid target path: {}", e)))?;
+    let empty = std::ffi::CString::default();
+
+    let tree = unsafe {
+        libc::syscall(
+            libc::SYS_open_tree,
+            libc::AT_FDCWD,
+            source_cstr.as_ptr(),
+            libc::OPEN_TREE_CLONE | libc::OPEN_TREE_CLOEXEC,
+        )
+    };
+    if tree < 0 {
+        return Err(IsolateError::Config(format!(
+            "open_tree failed for {}: {}",
+            binding.source.display(),
+            std::io::Error::last_os_error()
+        )));
+    }
+    let tree = unsafe { OwnedFd::from_raw_fd(tree as i32) };
+
+    let access = match binding.permissions {
+        DirectoryPermissions::ReadOnly => libc::MOUNT_ATTR_RDONLY,
+        DirectoryPermissions::ReadWrite => 0,
+        DirectoryPermissions::NoExec => libc::MOUNT_ATTR_RDONLY | libc::MOUNT_ATTR_NOEXEC,
+    };
+    let attr = libc::mount_attr {
+        attr_set: libc::MOUNT_ATTR_IDMAP
+            | libc::MOUNT_ATTR_NOSUID
+            | libc::MOUNT_ATTR_NODEV
+            | access,
+        attr_clr: 0,
+        propagation: 0,
+        userns_fd: userns.as_raw_fd() as u64,
+    };
+    let rc = unsafe {
+        libc::syscall(
+            libc::SYS_mount_setattr,
+            tree.as_raw_fd(),
+            empty.as_ptr(),
+            libc::AT_EMPTY_PATH,
+            &attr as *const libc::mount_attr,
+            std::mem::size_of::<libc::mount_attr>(),
+        )
+    };
+    if rc != 0 {
+        return Err(IsolateError::Config(format!(
+            "Filesystem of {} does not support idmapped mounts: {}",
+            binding.source.display(),
+            std::io::Error::last_os_error()
+        )));
+    }
+
+    let rc = unsafe {
+        libc::syscall(
+            libc::SYS_move_mount,
+            tree.as_raw_fd(),
+            empty.as_ptr(),
+            libc::AT_FDCWD,
+            target_cstr.as_ptr(),
+            MOVE_MOUNT_F_EMPTY_PATH,
+        )
+    };
+    if rc != 0 {
+        return Err(IsolateError::Config(format!(
+            "Failed to attach idmapped mount at {}: {}",
+            target.display(),
+            std::io::Error::last_os_error()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Create a user namespace with the given remaps and return a handle to it
+///
+/// A forked helper unshares the namespace and is killed once its maps are
+/// written and the namespace fd is open.
+#[cfg(unix)]
+fn mapped_user_namespace(
+    uid_map: Option<crate::types::IdRemap>,
+    gid_map: Option<crate::types::IdRemap>,
+) -> Result<std::fs::File> {
+    let mut ready = [0; 2];
+    if unsafe { libc::pipe2(ready.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(IsolateError::Namespace(format!(
+            "Failed to create pipe: {}",
+            std::io::Error::last_os_error()
+        )));
+    }
+
+    let pid = unsafe { libc::fork() };
+    if pid < 0 {
+        unsafe {
+            libc::close(ready[0]);
+            libc::close(ready[1]);
+        }
+        return Err(IsolateError::Namespace(format!(
+            "Failed to fork user namespace helper: {}",
+            std::io::Error::last_os_error()
+        )));
+    }
+    if pid == 0 {
+        // Only async-signal-safe calls between fork and exit
+        unsafe {
+            let status = u8::from(libc::unshare(libc::CLONE_NEWUSER) == 0);
+            libc::write(ready[1], &status as *const u8 as *const libc::c_void, 1);
+            loop {
+                libc::pause();
+            }
+        }
+    }
+
+    unsafe { libc::close(ready[1]) };
+    let mut status = 0u8;
+    let n = unsafe { libc::read(ready[0], &mut status as *mut u8 as *mut libc::c_void, 1) };
+    unsafe { libc::close(ready[0]) };
+
+    let namespace = if n == 1 && status == 1 {
+        fs::write(format!("/proc/{}/uid_map", pid), id_map_lines(uid_map))
+            .and_then(|_| fs::write(format!("/proc/{}/gid_map", pid), id_map_lines(gid_map)))
+            .and_then(|_| fs::File::open(format!("/proc/{}/ns/user", pid)))
+            .map_err(|e| IsolateError::Namespace(format!("Failed to set up id mapping: {}", e)))
+    } else {
+        Err(IsolateError::Namespace(
+            "User namespaces are not available".to_string(),
+        ))
+    };
+
+    unsafe {
+        libc::kill(pid, libc::SIGKILL);
+        libc::waitpid(pid, std::ptr::null_mut(), 0);
+    }
+    namespace
+}
+
+/// Map lines presenting `remap.host` as `remap.sandbox`, identity for every other id
+fn id_map_lines(remap: Option<crate::types::IdRemap>) -> String {
+    const ID_LIMIT: u64 = u32::MAX as u64;
+
+    let Some(remap) = remap else {
+        return format!("0 0 {}\n", ID_LIMIT);
+    };
+
+    let mut lines = format!("{} {} 1\n", remap.host, remap.sandbox);
+    let mut excluded = [remap.host as u64, remap.sandbox as u64];
+    excluded.sort_unstable();
+
+    let mut start = 0;
+    for id in excluded {
+        if id > start {
+            lines.push_str(&format!("{} {} {}\n", start, start, id - start));
+        }
+        start = start.max(id + 1);
+    }
+    if start < ID_LIMIT {
+        lines.push_str(&format!("{} {} {}\n", start, start, ID_LIMIT - start));
+    }
+    lines
+}
+
+/// Attach `image` to a free loop device that detaches itself once unused
+#[cfg(unix)]
+fn attach_loop_device(image: &fs::File) -> std::io::Result<(fs::File, PathBuf)> {
+    let control = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/loop-control")?;
+    for _ in 0..LOOP_ATTACH_ATTEMPTS {
+        let number = unsafe { libc::ioctl(control.as_raw_fd(), LOOP_CTL_GET_FREE as _) };
+        if number < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let path = PathBuf::from(format!("/dev/loop{}", number));
+        let device = fs::OpenOptions::new().read(true).write(true).open(&path)?;
+        if unsafe { libc::ioctl(device.as_raw_fd(), LOOP_SET_FD as _, image.as_raw_fd()) } != 0 {
+            let err = std::io::Error::last_os_error();
+            // Another box attached the free device first
+            if err.raw_os_error() == Some(libc::EBUSY) {
+                continue;
+            }
+            return Err(err);
+        }
+
+        let mut info: LoopInfo64 = unsafe { std::mem::zeroed() };
+        info.lo_flags = LO_FLAGS_AUTOCLEAR;
+        if unsafe { libc::ioctl(device.as_raw_fd(), LOOP_SET_STATUS64 as _, &info) } != 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::ioctl(device.as_raw_fd(), LOOP_CLR_FD as _) };
+            return Err(err);
+        }
+        return Ok((device, path));
+    }
+    Err(std::io::Error::other("no free loop device"))
+}
+
+/// Bind `source` at `target` and remount it with `flags` (`MS_RDONLY`,
+/// `MS_NOEXEC`, ...); an existing mount at `target` only has its flags reset
+///
+/// The initial bind ignores every flag but `MS_REC`, so the remount is what
+/// enforces them. A bind whose flags cannot be applied is undone rather than
+/// left more permissive than asked.
+#[cfg(unix)]
+fn bind_mount(source: &Path, target: &Path, flags: libc::c_ulong) -> Result<()> {
+    let c_path = |path: &Path| {
+        std::ffi::CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|e| IsolateError::Config(format!("Invalid path {}: {}", path.display(), e)))
+    };
+    let (source_cstr, target_cstr) = (c_path(source)?, c_path(target)?);
+    let mount_error = |what: &str| {
+        IsolateError::Config(format!(
+            "Failed to {} {} at {}: {}",
+            what,
+            source.display(),
+            target.display(),
+            std::io::Error::last_os_error()
+        ))
+    };
+
+    let already_mounted = is_mount_point(target);
+    if !already_mounted {
+        let bound = unsafe {
+            libc::mount(
+                source_cstr.as_ptr(),
+                target_cstr.as_ptr(),
+                std::ptr::null(),
+                libc::MS_BIND,
+                std::ptr::null(),
+            )
+        };
+        if bound != 0 {
+            return Err(mount_error("bind"));
+        }
+    }
+    let remounted = unsafe {
+        libc::mount(
+            std::ptr::null(),
+            target_cstr.as_ptr(),
+            std::ptr::null(),
+            libc::MS_BIND | libc::MS_REMOUNT | flags,
+            std::ptr::null(),
+        )
+    };
+    if remounted != 0 {
+        let error = mount_error("apply mount flags to the bind of");
+        if !already_mounted {
+            unsafe { libc::umount2(target_cstr.as_ptr(), libc::MNT_DETACH) };
+        }
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Whether a box running as `uid`/`gid` may connect to the socket described by `metadata`
+#[cfg(unix)]
+fn socket_connectable(metadata: &fs::Metadata, uid: u32, gid: Option<u32>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    let mode = metadata.mode();
+    if metadata.uid() == uid {
+        mode & 0o200 != 0
+    } else if gid == Some(metadata.gid()) {
+        mode & 0o020 != 0
+    } else {
+        mode & 0o002 != 0
+    }
+}
+
+/// Whether `path` is itself a mount point
+/// Open what is at `relative` under `base` as an `O_PATH` descriptor, to mount
+/// over or unmount through `/proc/self/fd`
+///
+/// No component may be a symlink or `..`, so the result is always beneath
+/// `base` whatever the box left there. With `create`, missing parent
+/// directories and a missing file to mount over are created.
+#[cfg(unix)]
+fn open_beneath(
+    base: &Path,
+    relative: &Path,
+    create: bool,
+) -> std::io::Result<std::os::fd::OwnedFd> {
+    use std::ffi::CString;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+    use std::os::unix::ffi::OsStrExt;
+
+    let invalid = || std::io::Error::from(std::io::ErrorKind::InvalidInput);
+    let mut names = relative
+        .components()
+        .map(|component| match component {
+            std::path::Component::Normal(name) => {
+                CString::new(name.as_bytes()).map_err(|_| invalid())
+            }
+            _ => Err(invalid()),
+        })
+        .collect::<std::io::Result<Vec<_>>>()?;
+    let file_name = names.pop().ok_or_else(invalid)?;
+
+    let mut dir = OwnedFd::from(fs::File::open(base)?);
+    for name in names {
+        if create {
+            // Already there is fine; a symlink or file then fails to open
+            unsafe { libc::mkdirat(dir.as_raw_fd(), name.as_ptr(), 0o755) };
+        }
+        let flags = libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+        let fd = unsafe { libc::openat(dir.as_raw_fd(), name.as_ptr(), flags) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        dir = unsafe { OwnedFd::from_raw_fd(fd) };
+    }
+
+    if create {
+        let flags =
+            libc::O_WRONLY | libc::O_CREAT | libc::O_EXCL | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+        let fd = unsafe { libc::openat(dir.as_raw_fd(), file_name.as_ptr(), flags, 0o644) };
+        if fd >= 0 {
+            unsafe { libc::close(fd) };
+        }
+    }
+    // With O_NOFOLLOW this opens a symlink itself, which is refused below
+    let flags = libc::O_PATH | libc::O_NOFOLLOW | libc::O_CLOEXEC;
+    let fd = unsafe { libc::openat(dir.as_raw_fd(), file_name.as_ptr(), flags) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let file = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(file.as_raw_fd(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if stat.st_mode & libc::S_IFMT == libc::S_IFLNK {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "is a symlink",
+        ));
+    }
+    Ok(file)
+}
+
+fn is_mount_point(path: &Path) -> bool {
+    let Ok(mountinfo) = fs::read_to_string("/proc/self/mountinfo") else {
+        return false;
+    };
+    mountinfo
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(4))
+        .any(|mount_point| Path::new(mount_point) == path)
+}
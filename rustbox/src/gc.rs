@@ -0,0 +1,172 @@
+/// Garbage collection of sandboxes left behind by crashed or idle workers
+///
+/// A worker that dies mid-run never cleans up after itself: its box stays
+/// registered with its directories, its lock file and its cgroups, and
+/// nothing ever reuses or removes them. `collect` removes boxes whose owner is
+/// gone (the process recorded in the lock file, or the supervisor recorded for
+/// the run in progress, died), killing what is left of their run, and, given a
+/// TTL, boxes idle for longer than it. Boxes locked by a live process are
+/// never touched. The state check then sweeps the registry entries, box
+/// directories and cgroups no box accounts for any more.
+use crate::isolate::{CleanupPolicy, Isolate};
+use crate::lock_manager::{abandoned_box_locks, box_lock_held, remove_abandoned_lock};
+use crate::state_check::{self, StateReport};
+use crate::types::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// What to collect
+#[derive(Debug, Clone, Default)]
+pub struct GcOptions {
+    /// Also collect boxes not used for longer than this
+    pub older_than: Option<chrono::Duration>,
+    /// Report what would be collected without removing anything
+    pub dry_run: bool,
+}
+
+/// Why a box was collected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GcReason {
+    /// The process that held the box died without releasing it
+    DeadOwner,
+    /// The box was idle for longer than `older_than`
+    Expired,
+}
+
+/// A box that was, or in a dry run would be, collected
+#[derive(Debug, Clone, Serialize)]
+pub struct CollectedBox {
+    pub box_id: u32,
+    pub reason: GcReason,
+    pub detail: String,
+    /// Whether the box is gone; false in a dry run or when removing it failed
+    pub removed: bool,
+}
+
+/// Outcome of `collect`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GcReport {
+    pub boxes: Vec<CollectedBox>,
+    /// Registry entries, directories and cgroups that belonged to no box
+    pub residue: StateReport,
+}
+
+/// Remove the sandboxes `options` selects, then sweep what they left behind
+///
+/// Needs the lock manager (`lock_manager::init_lock_manager`).
+pub fn collect(options: &GcOptions) -> GcReport {
+    let mut report = GcReport::default();
+    let mut abandoned: BTreeMap<u32, Option<u32>> = abandoned_box_locks().into_iter().collect();
+
+    for instance_id in Isolate::list_all().unwrap_or_default() {
+        let Some(box_id) = instance_id
+            .strip_prefix("rustbox/")
+            .and_then(|box_id| box_id.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        let owner = abandoned.remove(&box_id);
+        // Entries whose directory is gone are left to the state check
+        let Ok(Some(isolate)) = Isolate::load(&instance_id) else {
+            continue;
+        };
+        // Processes a dead owner forked may hold its lock until they are killed
+        if owner.is_none() && box_lock_held(box_id) {
+            continue;
+        }
+        let Some((reason, detail)) = verdict(&isolate, owner, options) else {
+            continue;
+        };
+        let removed = !options.dry_run
+            && match remove(isolate, owner, options) {
+                Ok(removed) => removed,
+                Err(e) => {
+                    log::warn!("Failed to collect box {}: {}", box_id, e);
+                    false
+                }
+            };
+        report.boxes.push(CollectedBox {
+            box_id,
+            reason,
+            detail,
+            removed,
+        });
+    }
+
+    // Lock files of boxes that are not registered at all
+    for (box_id, owner) in abandoned {
+        report.boxes.push(CollectedBox {
+            box_id,
+            reason: GcReason::DeadOwner,
+            detail: owner_detail(owner),
+            removed: !options.dry_run && remove_abandoned_lock(box_id),
+        });
+    }
+
+    report.residue = state_check::check(!options.dry_run);
+    report
+}
+
+/// Why `isolate` should be collected, if it should
+///
+/// `owner` is the recorded holder of its lock file when that was abandoned.
+fn verdict(
+    isolate: &Isolate,
+    owner: Option<Option<u32>>,
+    options: &GcOptions,
+) -> Option<(GcReason, String)> {
+    if let Some(owner) = owner {
+        return Some((GcReason::DeadOwner, owner_detail(owner)));
+    }
+    if let Some(supervisor) = isolate.crashed_supervisor() {
+        return Some((
+            GcReason::DeadOwner,
+            format!("run supervisor {} is gone", supervisor),
+        ));
+    }
+    let idle = chrono::Utc::now() - isolate.last_used();
+    options
+        .older_than
+        .filter(|&older_than| idle > older_than)
+        .map(|_| {
+            (
+                GcReason::Expired,
+                format!("idle for {}s", idle.num_seconds()),
+            )
+        })
+}
+
+fn owner_detail(owner: Option<u32>) -> String {
+    match owner {
+        Some(pid) => format!("lock holder {} is gone", pid),
+        None => "lock file has no holder".to_string(),
+    }
+}
+
+/// Kill what is left of the box's run and remove it; false if it was taken
+/// or used again in the meantime
+///
+/// Nothing is signalled before the box is locked. Locking takes over the lock
+/// of a dead holder even while what is left of its run still holds the old
+/// lock file, and fails if a live worker took the box since the scan.
+pub(crate) fn remove(
+    mut isolate: Isolate,
+    owner: Option<Option<u32>>,
+    options: &GcOptions,
+) -> Result<bool> {
+    if !isolate.try_acquire_execution_lock(Duration::ZERO)? {
+        return Ok(false);
+    }
+    let instance_id = isolate.config().instance_id.clone();
+    // The registry as it is now that nobody else can use the box
+    let Some(current) = Isolate::load(&instance_id)? else {
+        return Ok(false);
+    };
+    if current.last_used() != isolate.last_used() || verdict(&current, owner, options).is_none() {
+        return Ok(false);
+    }
+    isolate.kill_running(libc::SIGKILL)?;
+    isolate.finish(CleanupPolicy::Always)
+}
@@ -0,0 +1,334 @@
+/// gRPC control interface (`rustbox serve-grpc`)
+///
+/// The `Rustbox` service of proto/rustbox.proto, for infrastructure that talks
+/// gRPC rather than parsing CLI output: the box operations of the HTTP API (see
+/// `server`) plus Status and Kill, with typed messages. Execute and ExecuteCode
+/// stream the program's output while it runs and end with the result; a client
+/// that goes away cancels its run.
+///
+/// There is no TLS; keep the listener on localhost or behind a proxy, and set a
+/// bearer token (`authorization` metadata) when other users can reach it.
+use crate::executor::CancelToken;
+use crate::isolate::{CleanupPolicy, Isolate};
+use crate::output_capture::{OutputSink, OutputStream};
+use crate::types::{ExecutionOverrides, ExecutionResult, IsolateConfig, IsolateError, LiveStats};
+use proto::execute_event::Event;
+use proto::rustbox_server::{Rustbox, RustboxServer};
+use std::net::TcpListener;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{TcpListenerStream, UnboundedReceiverStream};
+use tokio_stream::Stream;
+use tonic::{Request, Response, Status};
+
+/// Messages, server and client generated from proto/rustbox.proto
+pub mod proto {
+    tonic::include_proto!("rustbox.v1");
+}
+
+/// Events of one Execute or ExecuteCode call; dropping it, as happens when the
+/// client goes away, cancels the run
+pub struct EventStream {
+    events: UnboundedReceiverStream<Result<proto::ExecuteEvent, Status>>,
+    cancel: CancelToken,
+}
+
+impl Stream for EventStream {
+    type Item = Result<proto::ExecuteEvent, Status>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.events).poll_next(cx)
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// gRPC server settings
+#[derive(Debug, Clone, Default)]
+pub struct GrpcOptions {
+    /// Required `authorization: Bearer` metadata, if any
+    pub token: Option<String>,
+}
+
+/// Serve the `Rustbox` service on `listener` until the process is stopped
+pub fn serve(listener: TcpListener, options: GrpcOptions) -> crate::types::Result<()> {
+    listener.set_nonblocking(true)?;
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        let expected = options.token.map(|token| format!("Bearer {}", token));
+        let service = RustboxServer::with_interceptor(RustboxService, move |request| {
+            check_token(expected.as_deref(), request)
+        });
+        tonic::transport::Server::builder()
+            .add_service(service)
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+            .map_err(|e| IsolateError::Process(format!("gRPC server failed: {}", e)))
+    })
+}
+
+/// Reject calls without the expected `authorization` metadata
+fn check_token(expected: Option<&str>, request: Request<()>) -> Result<Request<()>, Status> {
+    let Some(expected) = expected else {
+        return Ok(request);
+    };
+    let authorization = request
+        .metadata()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok());
+    if authorization == Some(expected) {
+        Ok(request)
+    } else {
+        Err(Status::unauthenticated("Missing or invalid bearer token"))
+    }
+}
+
+/// The `Rustbox` service, operating on the boxes of this host
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RustboxService;
+
+#[tonic::async_trait]
+impl Rustbox for RustboxService {
+    type ExecuteStream = EventStream;
+    type ExecuteCodeStream = EventStream;
+
+    async fn execute(
+        &self,
+        request: Request<proto::ExecuteRequest>,
+    ) -> Result<Response<EventStream>, Status> {
+        let request = request.into_inner();
+        if request.command.is_empty() {
+            return Err(Status::invalid_argument("Empty command"));
+        }
+        let box_id = request.box_id;
+        let isolate = blocking(move || {
+            let mut isolate = load(box_id)?;
+            isolate.acquire_execution_lock()?;
+            Ok(isolate)
+        })
+        .await?;
+
+        let limits = overrides(request.limits);
+        let events = stream_execution(
+            isolate,
+            move |isolate| {
+                isolate.execute_with_overrides(&request.command, request.stdin.as_deref(), &limits)
+            },
+            drop,
+        );
+        Ok(Response::new(events))
+    }
+
+    async fn execute_code(
+        &self,
+        request: Request<proto::ExecuteCodeRequest>,
+    ) -> Result<Response<EventStream>, Status> {
+        let request = request.into_inner();
+        let box_id = request.box_id;
+        let language = request.language.clone();
+        let isolate = blocking(move || {
+            let config = IsolateConfig::with_language_defaults(&language, instance_id(box_id))?;
+            Ok(Isolate::new(config)?)
+        })
+        .await?;
+
+        let limits = overrides(request.limits);
+        let compile_limits = overrides(request.compile_limits);
+        let policy = if request.auto_cleanup {
+            CleanupPolicy::Always
+        } else {
+            CleanupPolicy::Keep
+        };
+        let events = stream_execution(
+            isolate,
+            move |isolate| {
+                isolate
+                    .compile(&request.language, &request.code, &compile_limits)
+                    .and_then(|program| match program.failure() {
+                        Some(compile_error) => Ok(compile_error),
+                        None => isolate.run(&program, request.stdin.as_deref(), &limits),
+                    })
+            },
+            move |isolate| {
+                if let Err(e) = isolate.finish(policy) {
+                    log::warn!("Failed to clean up sandbox {}: {}", box_id, e);
+                }
+            },
+        );
+        Ok(Response::new(events))
+    }
+
+    async fn status(
+        &self,
+        request: Request<proto::StatusRequest>,
+    ) -> Result<Response<proto::StatusResponse>, Status> {
+        let box_id = request.into_inner().box_id;
+        let status = blocking(move || {
+            let Some(isolate) = Isolate::load(&instance_id(box_id))? else {
+                return Ok(proto::StatusResponse {
+                    exists: false,
+                    running: None,
+                });
+            };
+            Ok(proto::StatusResponse {
+                exists: true,
+                running: isolate.stats().ok().map(Into::into),
+            })
+        })
+        .await?;
+        Ok(Response::new(status))
+    }
+
+    async fn kill(
+        &self,
+        request: Request<proto::KillRequest>,
+    ) -> Result<Response<proto::KillResponse>, Status> {
+        let request = request.into_inner();
+        let signal = match request.signal {
+            0 => libc::SIGKILL,
+            signal => nix::sys::signal::Signal::try_from(signal)
+                .map_err(|_| Status::invalid_argument(format!("Unknown signal {}", signal)))?
+                as i32,
+        };
+        let box_id = request.box_id;
+        let signalled = blocking(move || Ok(load(box_id)?.kill_running(signal)?)).await?;
+        Ok(Response::new(proto::KillResponse {
+            signalled: signalled as u32,
+        }))
+    }
+
+    async fn cleanup(
+        &self,
+        request: Request<proto::CleanupRequest>,
+    ) -> Result<Response<proto::CleanupResponse>, Status> {
+        let box_id = request.into_inner().box_id;
+        blocking(move || Ok(load(box_id)?.cleanup()?)).await?;
+        Ok(Response::new(proto::CleanupResponse {}))
+    }
+}
+
+/// Run `work` on tokio's blocking pool
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, Status> + Send + 'static,
+) -> Result<T, Status> {
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| Status::internal(format!("Task failed: {}", e)))?
+}
+
+/// Start `run` on the blocking pool, streaming its output and then its result;
+/// `finish` gets the isolate back once the run is over
+fn stream_execution(
+    mut isolate: Isolate,
+    run: impl FnOnce(&mut Isolate) -> crate::types::Result<ExecutionResult> + Send + 'static,
+    finish: impl FnOnce(Isolate) + Send + 'static,
+) -> EventStream {
+    let (events, receiver) = mpsc::unbounded_channel();
+    let token = CancelToken::new();
+    isolate.set_cancel_token(Some(token.clone()));
+
+    let output = events.clone();
+    isolate.set_output_sink(OutputSink::new(move |stream, data| {
+        let stream = match stream {
+            OutputStream::Stdout => proto::Stream::Stdout,
+            OutputStream::Stderr => proto::Stream::Stderr,
+        };
+        let _ = output.send(Ok(proto::ExecuteEvent {
+            event: Some(Event::Output(proto::OutputChunk {
+                stream: stream as i32,
+                data: data.to_vec(),
+            })),
+        }));
+    }));
+
+    tokio::task::spawn_blocking(move || {
+        let outcome = run(&mut isolate);
+        finish(isolate);
+        let _ = events.send(
+            outcome
+                .map(|result| proto::ExecuteEvent {
+                    event: Some(Event::Result(result.into())),
+                })
+                .map_err(Status::from),
+        );
+    });
+    EventStream {
+        events: UnboundedReceiverStream::new(receiver),
+        cancel: token,
+    }
+}
+
+fn instance_id(box_id: u32) -> String {
+    format!("rustbox/{}", box_id)
+}
+
+/// The initialized box `box_id`
+fn load(box_id: u32) -> Result<Isolate, Status> {
+    Isolate::load(&instance_id(box_id))?
+        .ok_or_else(|| Status::not_found(format!("Sandbox {} not found. Init it first.", box_id)))
+}
+
+/// Overrides from the limits of a request; unset limits are left alone
+fn overrides(limits: Option<proto::Limits>) -> ExecutionOverrides {
+    let limits = limits.unwrap_or_default();
+    ExecutionOverrides {
+        max_memory: limits.max_memory,
+        max_time: limits.max_time.map(Duration::from_secs),
+        max_wall_time: limits.max_wall_time.map(Duration::from_secs),
+        extra_time: limits.extra_time.map(Duration::from_secs),
+        max_processes: limits.max_processes,
+        fd_limit: limits.fd_limit,
+        max_output: limits.max_output,
+        ..Default::default()
+    }
+}
+
+impl From<IsolateError> for Status {
+    fn from(error: IsolateError) -> Self {
+        let message = error.to_string();
+        match error {
+            IsolateError::Config(_) => Status::invalid_argument(message),
+            IsolateError::LockBusy => Status::aborted(message),
+            IsolateError::ResourceUnavailable(_) => Status::unavailable(message),
+            _ => Status::internal(message),
+        }
+    }
+}
+
+impl From<ExecutionResult> for proto::ExecuteResult {
+    fn from(result: ExecutionResult) -> Self {
+        Self {
+            status: result.status.label(),
+            success: result.success,
+            exit_code: result.exit_code,
+            signal: result.signal,
+            cpu_time: result.cpu_time,
+            wall_time: result.wall_time,
+            memory_peak: result.memory_peak,
+            json: result.to_cli_json().to_string(),
+            error_message: result.error_message,
+        }
+    }
+}
+
+impl From<LiveStats> for proto::LiveStats {
+    fn from(stats: LiveStats) -> Self {
+        Self {
+            phase: format!("{:?}", stats.phase).to_lowercase(),
+            memory_usage: stats.memory_usage,
+            cpu_time: stats.cpu_time,
+            processes: stats.processes as u32,
+            wall_time: stats.wall_time,
+            wall_time_remaining: stats.wall_time_remaining,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+/// Per-box execution history
+///
+/// Each box keeps a short log of its most recent executions in its own instance
+/// record, so recording one never waits on another box. The log survives
+/// reinitialising the box and goes away with `cleanup`; it exists to debug
+/// failures that keep recurring on the same box.
+use crate::types::{ExecutionPhase, ExecutionResult, ExecutionStatus};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// How much history each box keeps
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryPolicy {
+    /// Maximum number of entries kept per box (0 disables history)
+    pub max_entries: usize,
+    /// Entries older than this are pruned
+    pub max_age: Option<Duration>,
+}
+
+impl Default for HistoryPolicy {
+    fn default() -> Self {
+        Self {
+            max_entries: 50,
+            max_age: Some(Duration::from_secs(7 * 24 * 60 * 60)),
+        }
+    }
+}
+
+/// Summary of a single execution
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionSummary {
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// SHA-256 prefix of the command line, to spot repeated submissions
+    pub command_hash: String,
+    pub phase: ExecutionPhase,
+    pub status: ExecutionStatus,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub cpu_time: f64,
+    pub wall_time: f64,
+    pub memory_peak: u64,
+}
+
+impl ExecutionSummary {
+    pub fn new(command: &[String], result: &ExecutionResult) -> Self {
+        let mut hasher = Sha256::new();
+        for arg in command {
+            hasher.update(arg.as_bytes());
+            hasher.update([0u8]);
+        }
+        let command_hash = hasher
+            .finalize()
+            .iter()
+            .take(8)
+            .map(|b| format!("{:02x}", b))
+            .collect();
+
+        Self {
+            timestamp: chrono::Utc::now(),
+            command_hash,
+            phase: result.phase,
+            status: result.status.clone(),
+            exit_code: result.exit_code,
+            signal: result.signal,
+            cpu_time: result.cpu_time,
+            wall_time: result.wall_time,
+            memory_peak: result.memory_peak,
+        }
+    }
+}
+
+/// Append an execution to a box's history and apply the pruning policy
+pub fn record(
+    entries: &mut VecDeque<ExecutionSummary>,
+    summary: ExecutionSummary,
+    policy: &HistoryPolicy,
+) {
+    if policy.max_entries == 0 {
+        entries.clear();
+        return;
+    }
+    entries.push_back(summary);
+    prune(entries, policy);
+}
+
+fn prune(entries: &mut VecDeque<ExecutionSummary>, policy: &HistoryPolicy) {
+    if let Some(max_age) = policy
+        .max_age
+        .and_then(|age| chrono::Duration::from_std(age).ok())
+    {
+        let cutoff = chrono::Utc::now() - max_age;
+        entries.retain(|entry| entry.timestamp >= cutoff);
+    }
+    while entries.len() > policy.max_entries {
+        entries.pop_front();
+    }
+}
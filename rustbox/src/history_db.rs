@@ -0,0 +1,316 @@
+/// Execution audit database (`rustbox history list/show/purge`)
+///
+/// The per-box history (see `history`) keeps a few summaries per box to debug
+/// recurring failures. This store is for audits instead: every execution on the
+/// host is recorded in an SQLite database under the state dir with its box,
+/// full command line, effective limits, result, start and finish times and the
+/// uid that started it, and kept until purged.
+///
+/// Recording never fails an execution; the output kept with each result is cut
+/// at `MAX_STORED_OUTPUT` bytes per stream.
+use crate::types::{
+    ExecutionPhase, ExecutionResult, ExecutionStatus, IsolateConfig, IsolateError, Result,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use std::path::Path;
+use std::time::Duration;
+
+/// Bytes of stdout and of stderr kept with each result
+pub const MAX_STORED_OUTPUT: usize = 4096;
+
+/// How long a writer waits for another one to finish
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS executions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    instance_id TEXT NOT NULL,
+    uid INTEGER NOT NULL,
+    phase TEXT NOT NULL,
+    command TEXT NOT NULL,
+    limits TEXT NOT NULL,
+    status TEXT NOT NULL,
+    exit_code INTEGER,
+    signal INTEGER,
+    cpu_time REAL NOT NULL,
+    wall_time REAL NOT NULL,
+    memory_peak INTEGER NOT NULL,
+    result TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    finished_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS executions_by_box ON executions (instance_id, started_at);
+CREATE INDEX IF NOT EXISTS executions_by_time ON executions (started_at);
+";
+
+const COLUMNS: &str = "id, instance_id, uid, phase, command, limits, status, exit_code, signal, \
+                       cpu_time, wall_time, memory_peak, result, started_at, finished_at";
+
+/// One recorded execution
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryRecord {
+    pub id: i64,
+    pub instance_id: String,
+    /// User that started the execution
+    pub uid: u32,
+    pub phase: ExecutionPhase,
+    pub command: Vec<String>,
+    /// Limits the execution ran under
+    pub limits: serde_json::Value,
+    pub status: ExecutionStatus,
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub cpu_time: f64,
+    pub wall_time: f64,
+    pub memory_peak: u64,
+    /// CLI JSON of the result, output cut at `MAX_STORED_OUTPUT`
+    pub result: serde_json::Value,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+}
+
+/// Which records `list` and `purge` select; unset fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct HistoryFilter {
+    pub instance_id: Option<String>,
+    /// Started at or after
+    pub since: Option<DateTime<Utc>>,
+    /// Started before
+    pub before: Option<DateTime<Utc>>,
+}
+
+/// Connection to the audit database
+pub struct HistoryDb {
+    conn: Connection,
+}
+
+impl HistoryDb {
+    /// The database under the state dir, created on first use
+    pub fn open_default() -> Result<Self> {
+        Self::open(&crate::paths::paths().history_db())
+    }
+
+    /// The database at `path`, created on first use
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path).map_err(db_error)?;
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(db_error)?;
+        conn.execute_batch(SCHEMA).map_err(db_error)?;
+        Ok(Self { conn })
+    }
+
+    /// Record an execution of `command` under `config` started at `started_at`;
+    /// returns its id
+    pub fn record(
+        &self,
+        config: &IsolateConfig,
+        command: &[String],
+        started_at: DateTime<Utc>,
+        result: &ExecutionResult,
+    ) -> Result<i64> {
+        let mut stored = result.to_cli_json();
+        for stream in ["stdout", "stderr"] {
+            if let Some(output) = stored[stream].as_str() {
+                stored[stream] = truncate(output, MAX_STORED_OUTPUT).into();
+            }
+        }
+        self.conn
+            .execute(
+                &format!(
+                    "INSERT INTO executions ({}) VALUES (NULL, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, \
+                     ?10, ?11, ?12, ?13, ?14)",
+                    COLUMNS
+                ),
+                params![
+                    config.instance_id,
+                    unsafe { libc::getuid() },
+                    name(&result.phase),
+                    serde_json::to_string(command).unwrap_or_default(),
+                    limits(config).to_string(),
+                    name(&result.status),
+                    result.exit_code,
+                    result.signal,
+                    result.cpu_time,
+                    result.wall_time,
+                    result.memory_peak as i64,
+                    stored.to_string(),
+                    timestamp(started_at),
+                    timestamp(Utc::now()),
+                ],
+            )
+            .map_err(db_error)?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Records matching `filter`, newest first, at most `limit`
+    pub fn list(&self, filter: &HistoryFilter, limit: usize) -> Result<Vec<HistoryRecord>> {
+        let (condition, values) = filter.condition();
+        let mut statement = self
+            .conn
+            .prepare(&format!(
+                "SELECT {} FROM executions WHERE {} ORDER BY started_at DESC, id DESC LIMIT {}",
+                COLUMNS, condition, limit
+            ))
+            .map_err(db_error)?;
+        let records = statement
+            .query_map(rusqlite::params_from_iter(values), read_record)
+            .map_err(db_error)?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(db_error)?;
+        Ok(records)
+    }
+
+    /// Record `id`, if it exists
+    pub fn get(&self, id: i64) -> Result<Option<HistoryRecord>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM executions WHERE id = ?1", COLUMNS),
+                [id],
+                read_record,
+            )
+            .optional()
+            .map_err(db_error)
+    }
+
+    /// Delete the records matching `filter`; returns how many were deleted
+    pub fn purge(&self, filter: &HistoryFilter) -> Result<usize> {
+        let (condition, values) = filter.condition();
+        let deleted = self
+            .conn
+            .execute(
+                &format!("DELETE FROM executions WHERE {}", condition),
+                rusqlite::params_from_iter(values),
+            )
+            .map_err(db_error)?;
+        Ok(deleted)
+    }
+}
+
+impl HistoryFilter {
+    /// SQL condition selecting the matching rows, with its parameters
+    fn condition(&self) -> (String, Vec<String>) {
+        let mut clauses = vec!["1 = 1".to_string()];
+        let mut values = Vec::new();
+        let mut add = |clause: &str, value: String| {
+            values.push(value);
+            clauses.push(format!("{} ?{}", clause, values.len()));
+        };
+        if let Some(instance_id) = &self.instance_id {
+            add("instance_id =", instance_id.clone());
+        }
+        if let Some(since) = self.since {
+            add("started_at >=", timestamp(since));
+        }
+        if let Some(before) = self.before {
+            add("started_at <", timestamp(before));
+        }
+        (clauses.join(" AND "), values)
+    }
+}
+
+/// Record the execution in the default database, logging instead of failing
+pub fn record(
+    config: &IsolateConfig,
+    command: &[String],
+    started_at: DateTime<Utc>,
+    result: &ExecutionResult,
+) {
+    let recorded =
+        HistoryDb::open_default().and_then(|db| db.record(config, command, started_at, result));
+    if let Err(e) = recorded {
+        log::warn!("Failed to record execution in the audit database: {}", e);
+    }
+}
+
+/// Limits of `config` as stored with a record; times in seconds, sizes in bytes
+fn limits(config: &IsolateConfig) -> serde_json::Value {
+    let seconds = |limit: Option<Duration>| limit.map(|limit| limit.as_secs_f64());
+    serde_json::json!({
+        "memory_limit": config.memory_limit,
+        "swap_limit": config.swap_limit,
+        "time_limit": seconds(config.time_limit),
+        "cpu_time_limit": seconds(config.cpu_time_limit),
+        "wall_time_limit": seconds(config.wall_time_limit),
+        "extra_time": seconds(config.extra_time),
+        "process_limit": config.process_limit,
+        "exec_limit": config.exec_limit,
+        "file_size_limit": config.file_size_limit,
+        "stack_limit": config.stack_limit,
+        "fd_limit": config.fd_limit,
+        "output_limit": config.output_limit,
+    })
+}
+
+fn read_record(row: &Row) -> rusqlite::Result<HistoryRecord> {
+    Ok(HistoryRecord {
+        id: row.get(0)?,
+        instance_id: row.get(1)?,
+        uid: row.get(2)?,
+        phase: from_name(row, 3)?,
+        command: from_json(row, 4)?,
+        limits: from_json(row, 5)?,
+        status: from_name(row, 6)?,
+        exit_code: row.get(7)?,
+        signal: row.get(8)?,
+        cpu_time: row.get(9)?,
+        wall_time: row.get(10)?,
+        memory_peak: row.get::<_, i64>(11)? as u64,
+        result: from_json(row, 12)?,
+        started_at: from_timestamp(row, 13)?,
+        finished_at: from_timestamp(row, 14)?,
+    })
+}
+
+/// Serialized name of a unit variant, stored unquoted so the table reads plainly
+fn name(value: &impl Serialize) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+fn from_name<T: serde::de::DeserializeOwned>(row: &Row, index: usize) -> rusqlite::Result<T> {
+    let text: String = row.get(index)?;
+    serde_json::from_value(serde_json::Value::String(text)).map_err(|e| conversion(index, e))
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(row: &Row, index: usize) -> rusqlite::Result<T> {
+    let text: String = row.get(index)?;
+    serde_json::from_str(&text).map_err(|e| conversion(index, e))
+}
+
+/// Fixed-width UTC timestamps, so text order is time order
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+fn from_timestamp(row: &Row, index: usize) -> rusqlite::Result<DateTime<Utc>> {
+    let text: String = row.get(index)?;
+    DateTime::parse_from_rfc3339(&text)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|e| conversion(index, e))
+}
+
+fn conversion(
+    index: usize,
+    error: impl std::error::Error + Send + Sync + 'static,
+) -> rusqlite::Error {
+    rusqlite::Error::FromSqlConversionFailure(index, rusqlite::types::Type::Text, Box::new(error))
+}
+
+/// `text` cut at `max` bytes, on a character boundary
+fn truncate(text: &str, max: usize) -> &str {
+    let mut end = text.len().min(max);
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+fn db_error(error: rusqlite::Error) -> IsolateError {
+    IsolateError::Config(format!("Execution history database: {}", error))
+}
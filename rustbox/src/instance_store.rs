@@ -0,0 +1,240 @@
+/// Crash-safe store of instance records, one JSON file per box
+///
+/// Replaces the single `instances.json` registry, which every box rewrote
+/// under one global lock and which a kill at the wrong moment could leave
+/// unreadable for all of them. A record is written to a temporary file, synced
+/// and renamed over the old one, so readers see the old or the new record and
+/// never a mix, and boxes never wait on each other. A record that cannot be
+/// parsed only affects its own box. `migrate` moves the entries of an old
+/// `instances.json`, of any version `schema` reads, into the store once.
+use crate::lock_manager::with_file_lock;
+use crate::schema;
+use crate::types::{IsolateError, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Result of rebuilding an unreadable single-file registry
+#[derive(Clone, Debug)]
+pub struct RegistryRecovery {
+    /// Where the unreadable registry was moved
+    pub backup: PathBuf,
+    /// Entries that could be salvaged
+    pub kept: usize,
+    /// Entries that were unreadable and dropped
+    pub dropped: usize,
+}
+
+/// Directory of instance records
+#[derive(Debug, Clone)]
+pub struct InstanceStore {
+    dir: PathBuf,
+}
+
+impl InstanceStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// File holding the record of `instance_id`; `rustbox/3` is `rustbox%2F3.json`
+    pub fn record_path(&self, instance_id: &str) -> PathBuf {
+        let name = instance_id.replace('%', "%25").replace('/', "%2F");
+        self.dir.join(format!("{}.json", name))
+    }
+
+    pub fn contains(&self, instance_id: &str) -> bool {
+        self.record_path(instance_id).exists()
+    }
+
+    /// Ids of all records, readable or not
+    pub fn ids(&self) -> Result<Vec<String>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let name = name.strip_suffix(".json")?;
+                Some(name.replace("%2F", "/").replace("%25", "%"))
+            })
+            .collect())
+    }
+
+    /// The record of `instance_id`, `None` if there is none
+    pub fn load<T: DeserializeOwned>(&self, instance_id: &str) -> Result<Option<T>> {
+        let path = self.record_path(instance_id);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        schema::parse_record(&content).map(Some).map_err(|e| {
+            IsolateError::Config(format!(
+                "Failed to parse instance record {}: {} (run with --repair to recover)",
+                path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Replace the record of `instance_id`
+    pub fn save<T: Serialize>(&self, instance_id: &str, instance: &T) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let content = schema::record_to_string(instance)
+            .map_err(|e| IsolateError::Config(format!("Failed to serialize instance: {}", e)))?;
+        let mut temp = tempfile::NamedTempFile::new_in(&self.dir)?;
+        temp.write_all(content.as_bytes())?;
+        temp.as_file().sync_all()?;
+        temp.persist(self.record_path(instance_id))
+            .map_err(|e| e.error)?;
+        self.sync_dir();
+        Ok(())
+    }
+
+    /// Delete the record of `instance_id`, if there is one
+    pub fn remove(&self, instance_id: &str) -> Result<()> {
+        match fs::remove_file(self.record_path(instance_id)) {
+            Ok(()) => {
+                self.sync_dir();
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Records that cannot be read, with the reason
+    pub fn unreadable<T: DeserializeOwned>(&self) -> Result<Vec<(String, IsolateError)>> {
+        Ok(self
+            .ids()?
+            .into_iter()
+            .filter_map(|instance_id| {
+                let error = self.load::<T>(&instance_id).err()?;
+                Some((instance_id, error))
+            })
+            .collect())
+    }
+
+    /// Move the record of `instance_id` aside as `<record>.corrupt-<timestamp>`
+    pub fn quarantine(&self, instance_id: &str) -> Result<PathBuf> {
+        let record = self.record_path(instance_id);
+        let backup = with_suffix(&record, &corrupt_suffix());
+        fs::rename(&record, &backup)?;
+        Ok(backup)
+    }
+
+    /// Move the entries of the single-file `registry` into the store
+    ///
+    /// Records already in the store win. The registry is renamed to
+    /// `<registry>.migrated` afterwards, so this happens once; `lock` keeps
+    /// concurrent processes from migrating at the same time. Fails, leaving
+    /// the registry in place, if it cannot be parsed. Returns the number of
+    /// entries moved.
+    pub fn migrate<T>(&self, registry: &Path, lock: &Path) -> Result<usize>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        with_file_lock(lock, || Ok(self.migrate_locked::<T>(registry)))
+            .map_err(IsolateError::AdvancedLock)?
+    }
+
+    fn migrate_locked<T>(&self, registry: &Path) -> Result<usize>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let content = match fs::read_to_string(registry) {
+            Ok(content) => content,
+            // Another process migrated it first
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let instances: HashMap<String, T> = if content.trim().is_empty() {
+            HashMap::new()
+        } else {
+            schema::parse(&content).map_err(|e| {
+                IsolateError::Config(format!(
+                    "Failed to parse instances registry {}: {} (run with --repair to recover)",
+                    registry.display(),
+                    e
+                ))
+            })?
+        };
+
+        let mut moved = 0;
+        for (instance_id, instance) in &instances {
+            if !self.contains(instance_id) {
+                self.save(instance_id, instance)?;
+                moved += 1;
+            }
+        }
+        fs::rename(registry, with_suffix(registry, "migrated"))?;
+        Ok(moved)
+    }
+
+    /// Move what can still be parsed of an unreadable `registry` into the store
+    ///
+    /// The registry is kept as `<registry>.corrupt-<timestamp>`.
+    pub fn salvage<T>(&self, registry: &Path, lock: &Path) -> Result<RegistryRecovery>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        with_file_lock(lock, || Ok(self.salvage_locked::<T>(registry)))
+            .map_err(IsolateError::AdvancedLock)?
+    }
+
+    fn salvage_locked<T>(&self, registry: &Path) -> Result<RegistryRecovery>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let content = fs::read_to_string(registry)?;
+        let entries = schema::entries(&content).unwrap_or_default();
+        let total = entries.len();
+        let mut kept = 0;
+        for (instance_id, value) in entries {
+            let Ok(instance) = serde_json::from_value::<T>(value) else {
+                continue;
+            };
+            if !self.contains(&instance_id) {
+                self.save(&instance_id, &instance)?;
+            }
+            kept += 1;
+        }
+
+        let backup = with_suffix(registry, &corrupt_suffix());
+        fs::rename(registry, &backup)?;
+        Ok(RegistryRecovery {
+            backup,
+            kept,
+            dropped: total - kept,
+        })
+    }
+
+    /// Make renames and removals in the store survive a power loss
+    fn sync_dir(&self) {
+        if let Ok(dir) = fs::File::open(&self.dir) {
+            let _ = dir.sync_all();
+        }
+    }
+}
+
+fn corrupt_suffix() -> String {
+    format!("corrupt-{}", chrono::Utc::now().format("%Y%m%dT%H%M%S"))
+}
+
+/// `path` with `.suffix` appended to its file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
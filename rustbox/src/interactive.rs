@@ -0,0 +1,147 @@
+/// Interactive judging: a submission talking to an interactor over pipes
+///
+/// The submission's stdout is the interactor's stdin and the interactor's
+/// stdout the submission's stdin. Only the submission runs in the sandbox and
+/// under its limits; the interactor is trusted judge code and runs on the host,
+/// bounded only by `INTERACTOR_GRACE` past the submission's exit. Interactors
+/// report their verdict testlib-style through the exit code: 0 accepted,
+/// 1 wrong answer, 2 presentation error, anything else a judge failure.
+use crate::checker::Verdict;
+use crate::executor::ProcessExecutor;
+use crate::output_capture::{into_text, OutputCapture, OutputLimits};
+use crate::types::{ExecutionResult, IsolateError, Result};
+use serde::Serialize;
+use std::os::fd::{FromRawFd, OwnedFd};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// How long the interactor may keep running once the submission has exited
+pub const INTERACTOR_GRACE: Duration = Duration::from_secs(5);
+
+/// Most interactor stderr kept for the result
+const INTERACTOR_STDERR_LIMIT: u64 = 64 * 1024;
+
+/// How the interactor finished
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct InteractorResult {
+    pub exit_code: Option<i32>,
+    pub signal: Option<i32>,
+    pub stderr: String,
+    /// Killed for outliving the submission by more than `INTERACTOR_GRACE`
+    pub timed_out: bool,
+}
+
+impl InteractorResult {
+    /// Verdict from the exit code; `None` when the interactor itself failed
+    pub fn verdict(&self) -> Option<Verdict> {
+        match self.exit_code {
+            Some(0) if !self.timed_out => Some(Verdict::Accepted),
+            Some(1) => Some(Verdict::WrongAnswer),
+            Some(2) => Some(Verdict::PresentationError),
+            _ => None,
+        }
+    }
+}
+
+/// Submission and interactor results of one interactive run
+#[derive(Debug, Clone)]
+pub struct InteractiveResult {
+    pub submission: ExecutionResult,
+    pub interactor: InteractorResult,
+}
+
+impl InteractiveResult {
+    /// The interactor's verdict, given only when the submission ran cleanly
+    ///
+    /// A rejection still stands when the submission then died of SIGPIPE,
+    /// writing to an interactor that had already quit.
+    pub fn verdict(&self) -> Option<Verdict> {
+        let verdict = self.interactor.verdict();
+        let broken_pipe = self.submission.signal == Some(libc::SIGPIPE);
+        if self.submission.success || (broken_pipe && verdict != Some(Verdict::Accepted)) {
+            verdict
+        } else {
+            None
+        }
+    }
+
+    /// JSON object printed by `rustbox run-interactive`
+    pub fn to_cli_json(&self) -> serde_json::Value {
+        let mut json = self.submission.to_cli_json();
+        json["verdict"] = serde_json::json!(self.verdict());
+        json["interactor"] = serde_json::json!(self.interactor);
+        json
+    }
+}
+
+/// Run `command` in `executor`'s sandbox connected to `interactor` on the host
+pub fn run(
+    executor: &mut ProcessExecutor,
+    command: &[String],
+    interactor: &[String],
+) -> Result<InteractiveResult> {
+    if interactor.is_empty() {
+        return Err(IsolateError::Config("Empty interactor command".to_string()));
+    }
+    let (to_submission, from_interactor) = pipe()?;
+    let (to_interactor, from_submission) = pipe()?;
+
+    // Dropping the executor on an early return kills the submission
+    executor.spawn_connected(command, to_submission, from_submission)?;
+
+    let mut child = Command::new(&interactor[0])
+        .args(&interactor[1..])
+        .stdin(Stdio::from(to_interactor))
+        .stdout(Stdio::from(from_interactor))
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| IsolateError::Process(format!("Failed to start interactor: {}", e)))?;
+    let stderr = OutputCapture::start(
+        None,
+        child.stderr.take().map(OwnedFd::from),
+        OutputLimits::total(Some(INTERACTOR_STDERR_LIMIT)),
+    )
+    .map_err(|e| IsolateError::Process(format!("Failed to collect interactor output: {}", e)))?;
+
+    let submission = executor.wait();
+    let mut interactor = wait_interactor(&mut child)?;
+    interactor.stderr = into_text(stderr.finish().stderr);
+
+    Ok(InteractiveResult {
+        submission: submission?,
+        interactor,
+    })
+}
+
+/// Reap the interactor, killing it once it outlives the grace period
+fn wait_interactor(child: &mut Child) -> Result<InteractorResult> {
+    let deadline = Instant::now() + INTERACTOR_GRACE;
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            timed_out = true;
+            break child.wait()?;
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    };
+    Ok(InteractorResult {
+        exit_code: status.code(),
+        signal: status.signal(),
+        stderr: String::new(),
+        timed_out,
+    })
+}
+
+/// Pipe as (read end, write end), both close-on-exec
+fn pipe() -> Result<(OwnedFd, OwnedFd)> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(IsolateError::Io(std::io::Error::last_os_error()));
+    }
+    Ok(unsafe { (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1])) })
+}
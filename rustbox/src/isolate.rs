@@ -1,11 +1,31 @@
 /// Main isolate management interface
-use crate::executor::ProcessExecutor;
-use crate::lock_manager::{acquire_box_lock, with_file_lock, BoxLockGuard};
-use crate::types::{ExecutionResult, IsolateConfig, IsolateError, Result};
+use crate::artifact_cache::{ArtifactCache, ArtifactKey, WorkdirSnapshot};
+use crate::cgroup::Cgroup;
+use crate::checker::{CheckerConfig, Comparator, ComparatorOutcome};
+use crate::config::{
+    BoxIdRange, CompilationConfig, FilesystemConfig, LanguageRegistry, RustBoxConfig, TimeConfig,
+    DEFAULT_FIRST_GID, DEFAULT_FIRST_UID,
+};
+use crate::egress::EgressRule;
+use crate::executor::{CancelToken, ProcessExecutor};
+use crate::history::ExecutionSummary;
+use crate::instance_store::{InstanceStore, RegistryRecovery};
+use crate::interactive::InteractiveResult;
+use crate::lock_manager::{
+    acquire_box_lock, acquire_box_lock_with_timeout, acquire_box_set, try_acquire_box_lock,
+    BoxLockGuard,
+};
+use crate::output_capture::OutputSink;
+use crate::paths::paths;
+use crate::profile::LanguageProfile;
+use crate::types::{
+    BatchResult, BoxState, EnvRule, ExecutionOverrides, ExecutionPhase, ExecutionResult,
+    InstanceSummary, IsolateConfig, IsolateError, LiveStats, LockError, NetworkMode, Result,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 /// Persistent isolate instance configuration
@@ -14,6 +34,100 @@ struct IsolateInstance {
     config: IsolateConfig,
     created_at: chrono::DateTime<chrono::Utc>,
     last_used: chrono::DateTime<chrono::Utc>,
+    /// Most recent executions, oldest first
+    #[serde(default)]
+    history: VecDeque<ExecutionSummary>,
+}
+
+/// File in the instance directory describing the execution in progress
+const RUN_RECORD_FILE: &str = "running.json";
+
+/// The execution in progress, for `Isolate::stats` in other processes
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct RunRecord {
+    phase: ExecutionPhase,
+    started: chrono::DateTime<chrono::Utc>,
+    /// Wall clock limit including extra time
+    wall_time_limit: Duration,
+    /// Process waiting for the execution, which cleans up after it
+    supervisor: u32,
+}
+
+/// How long `kill_running` waits for the signalled processes to exit
+const KILL_GRACE: Duration = Duration::from_secs(1);
+
+/// How long `pause` waits for every process of the run to stop
+const FREEZE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Removes the run record when the execution ends
+struct RunRecordGuard(PathBuf);
+
+impl Drop for RunRecordGuard {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// What happens to a sandbox once an execution has finished
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CleanupPolicy {
+    /// Remove the sandbox and its files right after the execution
+    Always,
+    /// Keep the sandbox for further runs; the caller cleans up explicitly
+    Keep,
+}
+
+/// Source staged and, for compiled languages, built in an isolate
+///
+/// Produced by `Isolate::compile` and passed to `Isolate::run`, possibly many
+/// times.
+#[derive(Debug, Clone)]
+pub struct Compilation {
+    /// Canonical language name
+    pub language: String,
+    /// The compiler run; `None` for interpreted languages
+    pub result: Option<ExecutionResult>,
+    /// Command starting the program
+    pub command: Vec<String>,
+}
+
+impl Compilation {
+    pub fn succeeded(&self) -> bool {
+        self.result.as_ref().is_none_or(|result| result.success)
+    }
+
+    /// Compiler stdout and stderr (Judge0's `compile_output`)
+    pub fn output(&self) -> Option<String> {
+        self.result
+            .as_ref()
+            .map(|result| format!("{}{}", result.stdout, result.stderr))
+    }
+
+    /// The `CompileError` result to report when the build failed
+    pub fn failure(&self) -> Option<ExecutionResult> {
+        let result = self.result.as_ref().filter(|result| !result.success)?;
+        let display_name = LanguageProfile::get(&self.language).and_then(|p| p.display_name);
+        let (heading, message) = match display_name {
+            Some(name) => (
+                format!("{} Compilation Error", name),
+                format!("{} compilation failed", name),
+            ),
+            None => (
+                "Compilation Error".to_string(),
+                "Compilation failed".to_string(),
+            ),
+        };
+        Some(ExecutionResult {
+            status: crate::types::ExecutionStatus::CompileError,
+            stdout: String::new(),
+            stderr: format!("{}:\n{}", heading, result.stderr),
+            signal: None,
+            error_message: Some(message),
+            phase: ExecutionPhase::Compile,
+            compile_output: self.output(),
+            ..result.clone()
+        })
+    }
 }
 
 /// Main isolate manager for handling multiple isolated environments
@@ -21,6 +135,14 @@ pub struct Isolate {
     instance: IsolateInstance,
     base_path: PathBuf,
     box_lock_guard: Option<BoxLockGuard>,
+    /// Cancels the execution in progress, if set
+    cancel_token: Option<CancelToken>,
+    /// Receives the output of the next run-phase execution as it arrives
+    output_sink: Option<OutputSink>,
+    /// Stdin of the next run-phase execution, in place of stdin data
+    stdin_source: Option<fs::File>,
+    /// Compiled programs reused across compiles of the same source
+    artifact_cache: Option<ArtifactCache>,
 }
 
 impl Isolate {
@@ -40,9 +162,111 @@ impl Isolate {
 
     /// Create a new isolate instance
     pub fn new(config: IsolateConfig) -> Result<Self> {
-        let mut base_path = std::env::temp_dir();
-        base_path.push("rustbox");
-        base_path.push(&config.instance_id);
+        Self::create(config, None)
+    }
+
+    /// Create a new isolate instance, waiting at most `timeout` for its box
+    /// if another process is using it
+    pub fn new_with_lock_timeout(config: IsolateConfig, timeout: Duration) -> Result<Self> {
+        let box_id = Self::extract_box_id(&config.instance_id)?;
+        let guard =
+            acquire_box_lock_with_timeout(box_id, timeout).map_err(IsolateError::AdvancedLock)?;
+        Self::create(config, Some(guard))
+    }
+
+    /// Create an isolate in a free box of config.json's `isolate.auto_box_ids`
+    ///
+    /// The instance id of `config` is replaced by the allocated box's; read it
+    /// back with `box_id`.
+    pub fn new_auto(config: IsolateConfig) -> Result<Self> {
+        let range = RustBoxConfig::load_default()
+            .map(|global| global.isolate.auto_box_ids)
+            .unwrap_or_default();
+        Self::new_in_range(config, range)
+    }
+
+    /// Create an isolate in the first free box of `range`
+    ///
+    /// A box is free when it is not in the registry and its lock is not held.
+    /// Locks are tried without waiting, and the registry is checked again under
+    /// the lock, so concurrent allocations, in this process or others, never
+    /// get the same box.
+    pub fn new_in_range(mut config: IsolateConfig, range: BoxIdRange) -> Result<Self> {
+        let last = range.first.checked_add(range.count).ok_or_else(|| {
+            IsolateError::Config(format!(
+                "Box range {}+{} overflows",
+                range.first, range.count
+            ))
+        })?;
+        let store = Self::store()?;
+        for box_id in range.first..last {
+            let instance_id = format!("rustbox/{}", box_id);
+            if store.contains(&instance_id) {
+                continue;
+            }
+            let Some(guard) = try_acquire_box_lock(box_id).map_err(IsolateError::AdvancedLock)?
+            else {
+                continue;
+            };
+            // Another process may have set the box up since it was checked
+            if store.contains(&instance_id) {
+                continue;
+            }
+            config.instance_id = instance_id;
+            return Self::create(config, Some(guard));
+        }
+        Err(IsolateError::ResourceUnavailable(format!(
+            "No free box among {}..{}",
+            range.first, last
+        )))
+    }
+
+    /// Create isolates for several boxes, locking all of them or none
+    ///
+    /// For multi-program submissions (submission, interactor, checker) that
+    /// need every box at once. The locks are taken in box id order through
+    /// `acquire_box_set`, so concurrent groups sharing boxes cannot deadlock;
+    /// each box is released when its isolate is dropped. Isolates are returned
+    /// in the order of `configs`.
+    pub fn new_group(configs: Vec<IsolateConfig>, timeout: Duration) -> Result<Vec<Self>> {
+        let box_ids = configs
+            .iter()
+            .map(|config| Self::extract_box_id(&config.instance_id))
+            .collect::<Result<Vec<_>>>()?;
+        let mut unique = box_ids.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        if unique.len() != box_ids.len() {
+            return Err(IsolateError::Config(format!(
+                "Box group lists a box more than once: {:?}",
+                box_ids
+            )));
+        }
+
+        let mut guards: Vec<Option<BoxLockGuard>> = acquire_box_set(&box_ids, timeout)
+            .map_err(IsolateError::AdvancedLock)?
+            .into_guards()
+            .into_iter()
+            .map(Some)
+            .collect();
+        configs
+            .into_iter()
+            .zip(box_ids)
+            .map(|(config, box_id)| {
+                let guard = guards
+                    .iter_mut()
+                    .find(|guard| guard.as_ref().is_some_and(|guard| guard.box_id() == box_id))
+                    .and_then(Option::take);
+                Self::create(config, guard)
+            })
+            .collect()
+    }
+
+    /// Create an isolate, locking its box unless `lock_guard` already holds it
+    fn create(mut config: IsolateConfig, lock_guard: Option<BoxLockGuard>) -> Result<Self> {
+        config.validate()?;
+        Self::assign_box_identity(&mut config)?;
+        let base_path = Self::instance_dir(&config.instance_id);
 
         // Create base directory
         fs::create_dir_all(&base_path).map_err(IsolateError::Io)?;
@@ -51,49 +275,84 @@ impl Isolate {
             config,
             created_at: chrono::Utc::now(),
             last_used: chrono::Utc::now(),
+            history: VecDeque::new(),
         };
 
         let mut isolate = Self {
             instance,
             base_path,
-            box_lock_guard: None,
+            box_lock_guard: lock_guard,
+            cancel_token: None,
+            output_sink: None,
+            stdin_source: None,
+            artifact_cache: ArtifactCache::configured(),
         };
 
         // Acquire lock before any operations
-        isolate.acquire_lock(true)?;
+        if isolate.box_lock_guard.is_none() {
+            isolate.acquire_lock(true)?;
+        }
+
+        isolate.mount_workdir()?;
 
         // Save the new instance
-        isolate.atomic_instances_update(|instances| {
-            instances.insert(
-                isolate.instance.config.instance_id.clone(),
-                isolate.instance.clone(),
-            );
-        })?;
+        isolate.save()?;
 
         Ok(isolate)
     }
 
-    /// Load an existing isolate instance
-    pub fn load(instance_id: &str) -> Result<Option<Self>> {
-        let mut config_file = std::env::temp_dir();
-        config_file.push("rustbox");
-        config_file.push("instances.json");
-
-        if !config_file.exists() {
-            return Ok(None);
+    /// Give the box its own user and group, `first_uid + box_id` as in isolate
+    ///
+    /// Processes in different boxes then cannot signal or ptrace each other even
+    /// without namespaces. Only applies when running as root and `uid`/`gid`
+    /// were not set explicitly. A box on the shared scratch dir moves to its
+    /// own workdir, since the box user must be able to write there.
+    fn assign_box_identity(config: &mut IsolateConfig) -> Result<()> {
+        if unsafe { libc::geteuid() } != 0 || (config.uid.is_some() && config.gid.is_some()) {
+            return Ok(());
+        }
+        let box_id = Self::extract_box_id(&config.instance_id)?;
+        let (first_uid, first_gid) = RustBoxConfig::load_default()
+            .map(|global| (global.isolate.first_uid, global.isolate.first_gid))
+            .unwrap_or((DEFAULT_FIRST_UID, DEFAULT_FIRST_GID));
+        let out_of_range = || {
+            IsolateError::Config(format!(
+                "Box {} is past the end of the uid/gid range",
+                box_id
+            ))
+        };
+        let uid = *config
+            .uid
+            .get_or_insert(first_uid.checked_add(box_id).ok_or_else(out_of_range)?);
+        let gid = *config
+            .gid
+            .get_or_insert(first_gid.checked_add(box_id).ok_or_else(out_of_range)?);
+
+        if paths().scratch_dir().starts_with(&config.workdir)
+            || paths().state_dir().starts_with(&config.workdir)
+        {
+            config.workdir = paths().standard_workdir(box_id);
         }
+        fs::create_dir_all(&config.workdir)?;
+        std::os::unix::fs::chown(&config.workdir, Some(uid), Some(gid))?;
+        Ok(())
+    }
 
-        let instances = Self::load_all_instances()?;
-        if let Some(instance) = instances.get(instance_id) {
-            let mut base_path = std::env::temp_dir();
-            base_path.push("rustbox");
-            base_path.push(instance_id);
+    /// Load an existing isolate instance
+    pub fn load(instance_id: &str) -> Result<Option<Self>> {
+        let instance = Self::store()?.load::<IsolateInstance>(instance_id)?;
+        if let Some(instance) = instance {
+            let base_path = Self::instance_dir(instance_id);
 
             if base_path.exists() {
                 let isolate = Self {
-                    instance: instance.clone(),
+                    instance,
                     base_path,
                     box_lock_guard: None,
+                    cancel_token: None,
+                    output_sink: None,
+                    stdin_source: None,
+                    artifact_cache: ArtifactCache::configured(),
                 };
                 // Don't acquire lock for load - only for exclusive operations
                 Ok(Some(isolate))
@@ -107,8 +366,24 @@ impl Isolate {
 
     /// List all isolate instances
     pub fn list_all() -> Result<Vec<String>> {
-        let instances = Self::load_all_instances()?;
-        Ok(instances.keys().cloned().collect())
+        Self::store()?.ids()
+    }
+
+    /// Summaries of every registered box whose directory exists, by box id
+    ///
+    /// Boxes whose record cannot be read are left out.
+    pub fn list_summaries() -> Result<Vec<InstanceSummary>> {
+        let mut instance_ids = Self::list_all()?;
+        instance_ids.sort_by_key(|instance_id| Self::extract_box_id(instance_id).ok());
+        let mut summaries = Vec::new();
+        for instance_id in instance_ids {
+            match Self::load(&instance_id) {
+                Ok(Some(isolate)) => summaries.push(isolate.summary()),
+                Ok(None) => {}
+                Err(e) => log::warn!("Skipping {}: {}", instance_id, e),
+            }
+        }
+        Ok(summaries)
     }
 
     /// Execute a command in this isolate
@@ -116,21 +391,298 @@ impl Isolate {
         &mut self,
         command: &[String],
         stdin_data: Option<&str>,
+    ) -> Result<ExecutionResult> {
+        self.execute_in_phase(command, stdin_data, ExecutionPhase::Run)
+    }
+
+    fn execute_in_phase(
+        &mut self,
+        command: &[String],
+        stdin_data: Option<&str>,
+        phase: ExecutionPhase,
     ) -> Result<ExecutionResult> {
         // Acquire lock for execution to prevent conflicts
         if self.box_lock_guard.is_none() {
             self.acquire_lock(false)?;
         }
+        if let Some(ref guard) = self.box_lock_guard {
+            guard.verify().map_err(IsolateError::AdvancedLock)?;
+        }
 
         // Update last used timestamp
         self.instance.last_used = chrono::Utc::now();
         self.save()?;
 
-        // Create executor with current config
-        let mut executor = ProcessExecutor::new(self.instance.config.clone())?;
+        // Execute with current config; compilers run many programs, so the exec
+        // limit only applies to the submission itself
+        let mut config = self.instance.config.clone();
+        if phase == ExecutionPhase::Compile {
+            config.exec_limit = None;
+        }
+        self.run_recorded(config, command, stdin_data, phase)
+    }
 
-        // Execute the command
-        executor.execute(command, stdin_data)
+    /// Run a command with the given config and record it in the box history
+    fn run_recorded(
+        &mut self,
+        config: IsolateConfig,
+        command: &[String],
+        stdin_data: Option<&str>,
+        phase: ExecutionPhase,
+    ) -> Result<ExecutionResult> {
+        self.run_recorded_with(config, command, phase, |executor| {
+            executor.execute(command, stdin_data)
+        })
+    }
+
+    /// Like `run_recorded`, with `run` starting and waiting for the process
+    fn run_recorded_with(
+        &mut self,
+        config: IsolateConfig,
+        command: &[String],
+        phase: ExecutionPhase,
+        run: impl FnOnce(&mut ProcessExecutor) -> Result<ExecutionResult>,
+    ) -> Result<ExecutionResult> {
+        let report_build_info = config.report_build_info;
+        // Same default as the executor
+        let wall_time_limit = config.wall_time_limit.unwrap_or(Duration::from_secs(30))
+            + config.extra_time.unwrap_or_default();
+        config.validate()?;
+        #[cfg(feature = "history-db")]
+        let (audited_config, started_at) = (config.clone(), chrono::Utc::now());
+        let mut executor = ProcessExecutor::new(config)?;
+        if let Some(ref token) = self.cancel_token {
+            executor.set_cancel_token(token.clone());
+        }
+        if phase == ExecutionPhase::Run {
+            if let Some(sink) = self.output_sink.take() {
+                executor.set_output_sink(sink);
+            }
+            if let Some(file) = self.stdin_source.take() {
+                executor.set_stdin_source(file);
+            }
+        }
+        let run_record = self.record_run(phase, wall_time_limit);
+        let mut result = run(&mut executor)?;
+        drop(executor);
+        drop(run_record);
+        result.phase = phase;
+        crate::metrics::record_execution(&result);
+        if report_build_info {
+            result.build = Some(crate::build_info::BuildInfo::current());
+        }
+
+        // History is a debugging aid; never fail an execution over it
+        let summary = ExecutionSummary::new(command, &result);
+        let policy = self.instance.config.history.clone();
+        crate::history::record(&mut self.instance.history, summary, &policy);
+        if let Err(e) = self.save() {
+            log::warn!("Failed to record execution history: {}", e);
+        }
+        #[cfg(feature = "history-db")]
+        crate::history_db::record(&audited_config, command, started_at, &result);
+
+        Ok(result)
+    }
+
+    /// Record the execution about to start; a failure only disables `stats`
+    fn record_run(&self, phase: ExecutionPhase, wall_time_limit: Duration) -> RunRecordGuard {
+        let path = self.base_path.join(RUN_RECORD_FILE);
+        let record = RunRecord {
+            phase,
+            started: chrono::Utc::now(),
+            wall_time_limit,
+            supervisor: std::process::id(),
+        };
+        let written = serde_json::to_string(&record)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(&path, json).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            log::warn!("Failed to record the running execution: {}", e);
+        }
+        RunRecordGuard(path)
+    }
+
+    /// Resource usage of the execution running in this box, possibly started
+    /// by another process
+    pub fn stats(&self) -> Result<LiveStats> {
+        let instance_id = &self.instance.config.instance_id;
+        let not_running =
+            || IsolateError::Process(format!("No execution is running in {}", instance_id));
+        let record = self.run_record().ok_or_else(not_running)?;
+        let cgroup = Cgroup::open(instance_id).ok_or_else(|| {
+            IsolateError::Cgroup(format!("{} has no cgroup to read usage from", instance_id))
+        })?;
+        // A record left behind by a supervisor that crashed
+        let processes = cgroup
+            .processes()
+            .into_iter()
+            .filter(|&pid| !crate::reaper::is_reaper(pid, record.supervisor))
+            .count();
+        if processes == 0 {
+            return Err(not_running());
+        }
+
+        let wall_time = (chrono::Utc::now() - record.started)
+            .to_std()
+            .unwrap_or_default();
+        Ok(LiveStats {
+            phase: record.phase,
+            memory_usage: cgroup.get_current_memory_usage().unwrap_or(0),
+            cpu_time: cgroup.get_cpu_usage().unwrap_or(0.0),
+            processes,
+            wall_time: wall_time.as_secs_f64(),
+            wall_time_remaining: record
+                .wall_time_limit
+                .saturating_sub(wall_time)
+                .as_secs_f64(),
+        })
+    }
+
+    /// Send `signal` to every process of the execution running in this box,
+    /// possibly started by another process; returns how many were signalled,
+    /// 0 when nothing was running
+    ///
+    /// SIGKILL is repeated until the box is empty, so a forking program cannot
+    /// outrun it. When the supervisor of the run is gone (a crashed worker),
+    /// the box cgroup it left behind is removed once its processes have exited.
+    pub fn kill_running(&self, signal: i32) -> Result<usize> {
+        let Some(cgroup) = Cgroup::open(&self.instance.config.instance_id) else {
+            return Ok(0);
+        };
+
+        // A paused run only gets its signals once thawed
+        if signal == libc::SIGKILL {
+            let _ = cgroup.thaw();
+        }
+
+        // The reaper passes other signals on to the program, which would get them twice
+        let supervisor = self.run_record().map(|record| record.supervisor);
+        let skip_reaper = |pid: u32| {
+            signal != libc::SIGKILL
+                && supervisor.is_some_and(|supervisor| crate::reaper::is_reaper(pid, supervisor))
+        };
+        let mut signalled = std::collections::HashSet::new();
+        let deadline = std::time::Instant::now() + KILL_GRACE;
+        loop {
+            let mut processes = cgroup.processes();
+            processes.retain(|&pid| !skip_reaper(pid));
+            if processes.is_empty() || std::time::Instant::now() >= deadline {
+                break;
+            }
+            for pid in processes {
+                if signalled.insert(pid) || signal == libc::SIGKILL {
+                    unsafe { libc::kill(pid as i32, signal) };
+                }
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        // Without a record the cgroup may belong to a run that is being set up
+        let supervisor_gone = self
+            .run_record()
+            .is_some_and(|record| unsafe { libc::kill(record.supervisor as i32, 0) } != 0);
+        if supervisor_gone && cgroup.processes().is_empty() {
+            cgroup.cleanup()?;
+            let _ = fs::remove_file(self.base_path.join(RUN_RECORD_FILE));
+        }
+        Ok(signalled.len())
+    }
+
+    /// Pause the execution running in this box, possibly started by another
+    /// process, by freezing its cgroup; false when nothing was running
+    ///
+    /// Its processes stop where they are and keep their memory until `resume`,
+    /// so under load a box can make way for others without losing its work.
+    /// Time spent paused does not count against the wall time limit, though
+    /// the reported wall time includes it.
+    pub fn pause(&self) -> Result<bool> {
+        let Some(cgroup) = Cgroup::open(&self.instance.config.instance_id) else {
+            return Ok(false);
+        };
+        if self.run_record().is_none() || cgroup.processes().is_empty() {
+            return Ok(false);
+        }
+        if let Err(e) = cgroup.freeze(FREEZE_TIMEOUT) {
+            // Don't leave the run half frozen
+            let _ = cgroup.thaw();
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    /// Resume the execution paused in this box; false when it was not paused
+    pub fn resume(&self) -> Result<bool> {
+        let Some(cgroup) = Cgroup::open(&self.instance.config.instance_id) else {
+            return Ok(false);
+        };
+        let paused = cgroup.is_frozen();
+        if paused {
+            cgroup.thaw()?;
+        }
+        Ok(paused)
+    }
+
+    /// What the box is doing now, possibly in another process
+    ///
+    /// A box locked by this very isolate reports `Locked` too.
+    pub fn state(&self) -> BoxState {
+        let instance_id = &self.instance.config.instance_id;
+        if let Some(cgroup) = Cgroup::open(instance_id) {
+            if cgroup.is_frozen() {
+                return BoxState::Paused;
+            }
+            if self.run_record().is_some() && !cgroup.processes().is_empty() {
+                return BoxState::Running;
+            }
+        }
+        let locked =
+            Self::extract_box_id(instance_id).is_ok_and(crate::lock_manager::box_lock_held);
+        if locked {
+            BoxState::Locked
+        } else {
+            BoxState::Idle
+        }
+    }
+
+    /// The box's state, owner and limits, for listings
+    pub fn summary(&self) -> InstanceSummary {
+        let config = &self.instance.config;
+        InstanceSummary {
+            instance_id: config.instance_id.clone(),
+            state: self.state(),
+            uid: config.uid,
+            gid: config.gid,
+            created_at: self.instance.created_at,
+            last_used: self.instance.last_used,
+            memory_limit: config.memory_limit,
+            time_limit: config.time_limit.map(|limit| limit.as_secs_f64()),
+            wall_time_limit: config.wall_time_limit.map(|limit| limit.as_secs_f64()),
+            process_limit: config.process_limit,
+        }
+    }
+
+    /// The box's registry entry as persisted in its record (configuration,
+    /// creation and last use), with its current state
+    pub fn inspect(&self) -> Result<serde_json::Value> {
+        let mut record = serde_json::to_value(&self.instance)
+            .map_err(|e| IsolateError::Config(format!("Failed to serialize instance: {}", e)))?;
+        record["state"] = serde_json::json!(self.state());
+        Ok(record)
+    }
+
+    /// Pid of the process that started the execution recorded in this box,
+    /// if it died without cleaning up after it
+    pub fn crashed_supervisor(&self) -> Option<u32> {
+        self.run_record()
+            .map(|record| record.supervisor)
+            .filter(|&supervisor| unsafe { libc::kill(supervisor as i32, 0) } != 0)
+    }
+
+    /// The execution in progress, as recorded by whichever process started it
+    fn run_record(&self) -> Option<RunRecord> {
+        let content = fs::read_to_string(self.base_path.join(RUN_RECORD_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
     }
 
     /// Execute a command in this isolate with runtime resource overrides
@@ -138,291 +690,586 @@ impl Isolate {
         &mut self,
         command: &[String],
         stdin_data: Option<&str>,
-        max_cpu: Option<u64>,
-        max_memory: Option<u64>,
-        max_time: Option<u64>,
-        max_wall_time: Option<u64>,
-        fd_limit: Option<u64>,
+        overrides: &ExecutionOverrides,
+    ) -> Result<ExecutionResult> {
+        self.execute_overridden(command, stdin_data, overrides, ExecutionPhase::Run)
+    }
+
+    fn execute_overridden(
+        &mut self,
+        command: &[String],
+        stdin_data: Option<&str>,
+        overrides: &ExecutionOverrides,
+        phase: ExecutionPhase,
     ) -> Result<ExecutionResult> {
         // Update last used timestamp
         self.instance.last_used = chrono::Utc::now();
         self.save()?;
 
-        // Clone config and apply overrides
+        let config = self.overridden_config(overrides, phase);
+        self.run_recorded(config, command, stdin_data, phase)
+    }
+
+    /// Run `command` against `interactor`, each reading what the other writes
+    ///
+    /// Only the submission is sandboxed and limited (by this box's config and
+    /// `overrides`); see `interactive` for the interactor's contract.
+    pub fn execute_interactive(
+        &mut self,
+        command: &[String],
+        interactor: &[String],
+        overrides: &ExecutionOverrides,
+    ) -> Result<InteractiveResult> {
+        self.instance.last_used = chrono::Utc::now();
+        self.save()?;
+
+        let config = self.overridden_config(overrides, ExecutionPhase::Run);
+        let mut interactor_result = None;
+        let submission =
+            self.run_recorded_with(config, command, ExecutionPhase::Run, |executor| {
+                let outcome = crate::interactive::run(executor, command, interactor)?;
+                interactor_result = Some(outcome.interactor);
+                Ok(outcome.submission)
+            })?;
+        Ok(InteractiveResult {
+            submission,
+            interactor: interactor_result.unwrap_or_default(),
+        })
+    }
+
+    /// This box's config with `overrides` applied
+    fn overridden_config(
+        &self,
+        overrides: &ExecutionOverrides,
+        phase: ExecutionPhase,
+    ) -> IsolateConfig {
         let mut config = self.instance.config.clone();
 
-        if let Some(cpu_seconds) = max_cpu {
-            config.cpu_time_limit = Some(Duration::from_secs(cpu_seconds));
-            config.time_limit = Some(Duration::from_secs(cpu_seconds));
+        if let Some(cpu_limit) = overrides.max_cpu.or(overrides.max_time) {
+            config.cpu_time_limit = Some(cpu_limit);
+            config.time_limit = Some(cpu_limit);
+        }
+
+        if let Some(memory_mb) = overrides.max_memory {
+            let memory_limit = memory_mb * 1024 * 1024; // Convert MB to bytes
+            config.memory_limit = Some(memory_limit);
+            // The stack is part of the memory, so a lower limit lowers it too
+            config.stack_limit = config.stack_limit.map(|stack| stack.min(memory_limit));
+        }
+
+        if let Some(memory_high_mb) = overrides.memory_high {
+            config.memory_high = Some(memory_high_mb * 1024 * 1024);
         }
 
-        if let Some(memory_mb) = max_memory {
-            config.memory_limit = Some(memory_mb * 1024 * 1024); // Convert MB to bytes
+        if let Some(swap_mb) = overrides.max_swap {
+            config.swap_limit = Some(swap_mb * 1024 * 1024);
         }
 
-        if let Some(time_seconds) = max_time {
-            config.cpu_time_limit = Some(Duration::from_secs(time_seconds));
+        if let Some(kernel_memory_mb) = overrides.kernel_memory {
+            config.kernel_memory_limit = Some(kernel_memory_mb * 1024 * 1024);
         }
 
-        if let Some(wall_time_seconds) = max_wall_time {
-            config.wall_time_limit = Some(Duration::from_secs(wall_time_seconds));
+        if let Some(cpu_quota_percent) = overrides.cpu_quota_percent {
+            config.cpu_quota_percent = Some(cpu_quota_percent);
         }
 
-        if let Some(fd_limit_val) = fd_limit {
+        if let Some(wall_time_limit) = overrides.max_wall_time {
+            config.wall_time_limit = Some(wall_time_limit);
+        }
+
+        if let Some(extra_time) = overrides.extra_time {
+            config.extra_time = Some(extra_time);
+        }
+
+        if let Some(fd_limit_val) = overrides.fd_limit {
             config.fd_limit = Some(fd_limit_val);
         }
 
-        // Create executor with modified config
-        let mut executor = ProcessExecutor::new(config)?;
+        if overrides.audit_file_writes {
+            config.audit_file_writes = true;
+        }
+        if overrides.track_process_memory {
+            config.track_process_memory = true;
+        }
+        if overrides.emulate_foreign_arch {
+            config.emulate_foreign_arch = true;
+        }
+
+        if overrides.audit_syscalls {
+            config.audit_syscalls = true;
+        }
+
+        if overrides.build_info {
+            config.report_build_info = true;
+        }
+
+        if let Some(max_processes) = overrides.max_processes {
+            config.process_limit = Some(max_processes);
+        }
+
+        if let Some(output_kb) = overrides.max_output {
+            config.output_limit = Some(output_kb * 1024);
+        }
+        if let Some(stdout_kb) = overrides.max_stdout {
+            config.stdout_limit = Some(stdout_kb * 1024);
+        }
+        if let Some(stderr_kb) = overrides.max_stderr {
+            config.stderr_limit = Some(stderr_kb * 1024);
+        }
+
+        // Compilers run many programs, so the exec limit only applies to the submission
+        if let Some(max_execs) = overrides.max_execs {
+            config.exec_limit = Some(max_execs);
+        }
+        if phase == ExecutionPhase::Compile {
+            config.exec_limit = None;
+        }
+
+        if let Some(silence_seconds) = overrides.adaptive_timeout {
+            config.adaptive_timeout = Some(crate::types::AdaptiveTimeout::new(
+                Duration::from_secs(silence_seconds),
+            ));
+        }
+
+        for rule in &overrides.env {
+            match rule {
+                EnvRule::Set(name, value) => {
+                    config.environment.retain(|(key, _)| key != name);
+                    config.environment.push((name.clone(), value.clone()));
+                }
+                EnvRule::Preserve(name) => {
+                    config.environment.retain(|(key, _)| key != name);
+                    config.preserve_env.push(name.clone());
+                }
+            }
+        }
+        if overrides.full_env {
+            config.full_env = true;
+        }
+
+        if overrides.stdin_file.is_some() {
+            config.stdin_file = overrides.stdin_file.clone();
+        }
+        if overrides.stdout_file.is_some() {
+            config.stdout_file = overrides.stdout_file.clone();
+        }
+        if overrides.stderr_file.is_some() {
+            config.stderr_file = overrides.stderr_file.clone();
+        }
 
-        // Execute the command
-        executor.execute(command, stdin_data)
+        if overrides.tty {
+            config.enable_tty = true;
+        }
+        if let Some(size) = overrides.tty_size {
+            config.tty_size = size;
+        }
+        config
     }
 
-    /// Execute code directly from string input (Judge0-style)
-    pub fn execute_code_string(
+    /// Let `token` cancel executions started from this isolate
+    pub fn set_cancel_token(&mut self, token: Option<CancelToken>) {
+        self.cancel_token = token;
+    }
+
+    /// Forward the output of the next run-phase execution to `sink` live
+    ///
+    /// Compilation and checker runs don't consume it, so this also works for
+    /// `execute_code_string`.
+    pub fn set_output_sink(&mut self, sink: OutputSink) {
+        self.output_sink = Some(sink);
+    }
+
+    /// Read the stdin of the next run-phase execution from `file`
+    ///
+    /// The program gets the file itself, so large or binary input is passed
+    /// through byte for byte without being read into memory. Like the output
+    /// sink, compilation and checker runs leave it for the run.
+    pub fn set_stdin_source(&mut self, file: fs::File) {
+        self.stdin_source = Some(file);
+    }
+
+    /// Reuse builds from `cache` in `compile`, or always compile with `None`
+    ///
+    /// Defaults to the cache enabled in config.json.
+    pub fn set_artifact_cache(&mut self, cache: Option<ArtifactCache>) {
+        self.artifact_cache = cache;
+    }
+
+    /// `execute_with_overrides`, forwarding output to `sink` as it is produced
+    pub fn execute_streaming(
         &mut self,
-        language: &str,
-        code: &str,
+        command: &[String],
         stdin_data: Option<&str>,
-        max_cpu: Option<u64>,
-        max_memory: Option<u64>,
-        max_time: Option<u64>,
-        max_wall_time: Option<u64>,
-        fd_limit: Option<u64>,
+        overrides: &ExecutionOverrides,
+        sink: OutputSink,
     ) -> Result<ExecutionResult> {
-        match language.to_lowercase().as_str() {
-            "python" | "py" => self
-                .execute_python_string(code, stdin_data, max_cpu, max_memory, max_time, max_wall_time, fd_limit),
-            "cpp" | "c++" | "cxx" => self
-                .compile_and_execute_cpp(code, stdin_data, max_cpu, max_memory, max_time, max_wall_time, fd_limit),
-            "java" => self.compile_and_execute_java(
-                code, stdin_data, max_cpu, max_memory, max_time, max_wall_time, fd_limit,
-            ),
-            _ => Err(IsolateError::Config(format!(
-                "Unsupported language: {}",
-                language
-            ))),
-        }
+        self.set_output_sink(sink);
+        self.execute_with_overrides(command, stdin_data, overrides)
     }
 
-    /// Execute Python code directly from string
-    fn execute_python_string(
+    /// Execute with overrides on tokio's blocking pool
+    ///
+    /// The isolate moves into the task and comes back with the result. Dropping
+    /// the handle before it completes cancels the run, killing the sandboxed
+    /// process; use `ExecutionHandle::cancel` to stop it and still await the
+    /// `Cancelled` result.
+    #[cfg(feature = "tokio")]
+    pub fn execute_async(
+        mut self,
+        command: Vec<String>,
+        stdin_data: Option<String>,
+        overrides: ExecutionOverrides,
+    ) -> crate::async_exec::ExecutionHandle {
+        let token = CancelToken::new();
+        self.cancel_token = Some(token.clone());
+        let task = tokio::task::spawn_blocking(move || {
+            let result = self.execute_with_overrides(&command, stdin_data.as_deref(), &overrides);
+            self.cancel_token = None;
+            result.map(|result| (self, result))
+        });
+        crate::async_exec::ExecutionHandle::new(task, token)
+    }
+
+    /// Run a checker/validator in this isolate under the checker's own limits
+    pub fn run_checker(
         &mut self,
-        code: &str,
+        command: &[String],
         stdin_data: Option<&str>,
-        max_cpu: Option<u64>,
-        max_memory: Option<u64>,
-        max_time: Option<u64>,
-        max_wall_time: Option<u64>,
-        fd_limit: Option<u64>,
+        checker: &CheckerConfig,
     ) -> Result<ExecutionResult> {
-        let command = vec![
-            "/usr/bin/python3".to_string(),
-            "-u".to_string(),
-            "-c".to_string(),
-            code.to_string(),
-        ];
-        self.execute_with_overrides(
-            &command, stdin_data, max_cpu, max_memory, max_time, max_wall_time, fd_limit,
-        )
+        // Update last used timestamp
+        self.instance.last_used = chrono::Utc::now();
+        self.save()?;
+
+        let config = checker.isolate_config(&self.instance.config);
+        self.run_recorded(config, command, stdin_data, ExecutionPhase::Checker)
     }
 
-    /// Compile and execute C++ code from string
-    fn compile_and_execute_cpp(
+    /// Compare a submission's output against the expected output file
+    ///
+    /// Both outputs are staged into the workdir so the comparator can reach them
+    /// even inside a chroot, then the comparator runs under its own limits. They
+    /// go into a directory created afresh for this comparison, owned by root
+    /// and read-only to the box, so nothing the submission left in the workdir
+    /// is followed or written through, and removed once the comparator is done.
+    pub fn compare_output(
         &mut self,
+        comparator: &Comparator,
+        expected: &std::path::Path,
+        actual_output: &str,
+    ) -> Result<ComparatorOutcome> {
+        if comparator.command.is_empty() {
+            return Err(IsolateError::Config("Empty comparator command".to_string()));
+        }
+
+        let expected_output = fs::read(expected).map_err(|e| {
+            IsolateError::Config(format!(
+                "Cannot read expected output {}: {}",
+                expected.display(),
+                e
+            ))
+        })?;
+
+        let staged = PathBuf::from(format!(
+            ".rustbox-compare-{}-{}",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()
+        ));
+        let staged_dir = self.instance.config.workdir.join(&staged);
+        let outcome = self
+            .stage_comparison(&staged_dir, &expected_output, actual_output)
+            .and_then(|()| {
+                let command = comparator.command_line(&staged);
+                self.run_checker(&command, None, &comparator.limits)
+            })
+            .and_then(Comparator::parse_outcome);
+        if let Err(e) = fs::remove_dir_all(&staged_dir) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                log::warn!("Failed to remove {}: {}", staged_dir.display(), e);
+            }
+        }
+        outcome
+    }
+
+    /// Create `dir` and write both outputs into it, readable by the box's group
+    fn stage_comparison(&self, dir: &Path, expected: &[u8], actual: &str) -> Result<()> {
+        use std::os::unix::fs::DirBuilderExt;
+
+        let config = &self.instance.config;
+        // Fails on anything already there, a symlink included
+        fs::DirBuilder::new().mode(0o750).create(dir)?;
+        std::os::unix::fs::lchown(dir, None, config.gid)?;
+        let filesystem = self.filesystem_security();
+        for (file, contents) in [
+            (crate::checker::EXPECTED_OUTPUT_FILE, expected),
+            (crate::checker::ACTUAL_OUTPUT_FILE, actual.as_bytes()),
+        ] {
+            filesystem.write_sandbox_file(&dir.join(file), contents, 0o227, None, config.gid)?;
+        }
+        Ok(())
+    }
+
+    /// Execute code directly from string input (Judge0-style)
+    ///
+    /// Compiles under the language's compile limits (see `compile`), then runs
+    /// under `overrides`.
+    pub fn execute_code_string(
+        &mut self,
+        language: &str,
         code: &str,
         stdin_data: Option<&str>,
-        max_cpu: Option<u64>,
-        max_memory: Option<u64>,
-        max_time: Option<u64>,
-        max_wall_time: Option<u64>,
-        fd_limit: Option<u64>,
+        overrides: &ExecutionOverrides,
     ) -> Result<ExecutionResult> {
-        // Write source code to file in sandbox
-        let source_file = self.instance.config.workdir.join("solution.cpp");
-        fs::write(&source_file, code)?;
+        let program = self.compile(language, code, &ExecutionOverrides::default())?;
+        match program.failure() {
+            Some(compile_error) => Ok(compile_error),
+            None => self.run(&program, stdin_data, overrides),
+        }
+    }
 
-        // Temporarily increase process limit for C++ compilation
-        let original_config = self.instance.config.clone();
+    /// Compile `code` once and run it against every stdin in `inputs`
+    ///
+    /// All runs share the sandbox and the limits in `overrides`. A compile
+    /// failure skips the runs and becomes the batch verdict. JIT languages
+    /// first run the first input `warmup_runs` times (config.json
+    /// `time.warmup_runs` unless overridden); those runs don't count.
+    pub fn execute_batch(
+        &mut self,
+        language: &str,
+        code: &str,
+        inputs: &[String],
+        overrides: &ExecutionOverrides,
+    ) -> Result<BatchResult> {
+        let program = self.compile(language, code, &ExecutionOverrides::default())?;
+        if let Some(compile_error) = program.failure() {
+            return Ok(BatchResult::compile_failed(compile_error));
+        }
+        let warmup_runs = overrides
+            .warmup_runs
+            .unwrap_or_else(|| TimeConfig::warmup_runs_for(language));
+        let warmup = match inputs.first() {
+            Some(stdin) => (0..warmup_runs)
+                .map(|_| self.run(&program, Some(stdin), overrides))
+                .collect::<Result<Vec<_>>>()?,
+            None => Vec::new(),
+        };
+        let results = inputs
+            .iter()
+            .map(|stdin| self.run(&program, Some(stdin), overrides))
+            .collect::<Result<Vec<_>>>()?;
+        let mut batch = BatchResult::from_results(results);
+        batch.warmup = warmup;
+        Ok(batch)
+    }
 
-        // C++ compiler needs more processes for compilation phases
-        self.instance.config.process_limit = Some(30);
+    /// Write `code` into the workdir and build it under compile limits
+    ///
+    /// `limits` override the language's `compilation` section in config.json
+    /// (`max_compilation_time`, `max_compilation_memory_mb`). Interpreted
+    /// languages are only staged. A failed build is not an error: check
+    /// `Compilation::failure` before calling `run`.
+    pub fn compile(
+        &mut self,
+        language: &str,
+        code: &str,
+        limits: &ExecutionOverrides,
+    ) -> Result<Compilation> {
+        let registry = LanguageRegistry::load();
+        let (Some(name), Some(files)) =
+            (registry.resolve(language), registry.conventions(language))
+        else {
+            return Err(IsolateError::Config(format!(
+                "Unsupported language: {}",
+                language
+            )));
+        };
+        let source_name = files.source_file_name(code);
+        let Some(profile) = LanguageProfile::get(name) else {
+            return Err(IsolateError::Config(format!(
+                "Unsupported language: {}",
+                language
+            )));
+        };
+        let (compile_command, command) = profile.commands(&source_name, code);
+        let mut program = Compilation {
+            language: name.to_string(),
+            result: None,
+            command,
+        };
+        let source_file = self.instance.config.workdir.join(&source_name);
+        if profile.stages_source() {
+            self.write_source_file(&source_file, code)?;
+        }
+        let Some(compile_command) = compile_command else {
+            return Ok(program);
+        };
 
-        if let Some(memory) = max_memory {
-            self.instance.config.memory_limit = Some(memory * 1024 * 1024);
-        } else {
-            self.instance.config.memory_limit = Some(256 * 1024 * 1024); // 256MB for C++
-        }
-
-        // Compile the code
-        let compile_command = vec![
-            "g++".to_string(),
-            "-o".to_string(),
-            "solution".to_string(),
-            "solution.cpp".to_string(),
-            "-std=c++17".to_string(),
-            "-O2".to_string(),
-        ];
-
-        let compile_result = self.execute(&compile_command, None)?;
-
-        if !compile_result.success {
-            // Restore original config
-            self.instance.config = original_config;
-            return Ok(ExecutionResult {
-                status: crate::types::ExecutionStatus::RuntimeError,
-                exit_code: compile_result.exit_code,
-                stdout: "".to_string(),
-                stderr: format!("Compilation Error:\n{}", compile_result.stderr),
-                wall_time: compile_result.wall_time,
-                cpu_time: compile_result.cpu_time,
-                memory_peak: compile_result.memory_peak,
-                success: false,
-                signal: None,
-                error_message: Some("Compilation failed".to_string()),
-            });
-        }
-
-        // Execute the compiled binary
-        let execute_command = vec!["./solution".to_string()];
-        let result = self.execute_with_overrides(
-            &execute_command,
-            stdin_data,
-            max_cpu,
-            max_memory,
-            max_time,
-            max_wall_time,
-            fd_limit,
-        );
+        let defaults = CompilationConfig::limits_for(name);
+        let limits = ExecutionOverrides {
+            max_cpu: limits.max_cpu.or(limits.max_time).or(defaults.max_cpu),
+            max_wall_time: limits.max_wall_time.or(defaults.max_wall_time),
+            max_memory: limits.max_memory.or(defaults.max_memory),
+            ..limits.clone()
+        };
+        self.acquire_execution_lock()?;
+
+        let workdir = self.instance.config.workdir.clone();
+        let artifacts = self
+            .artifact_cache
+            .clone()
+            .map(|cache| (cache, ArtifactKey::new(&compile_command, code)));
+        if let Some((cache, key)) = &artifacts {
+            let config = &self.instance.config;
+            if let Some(result) = cache.restore(key, &workdir, config.uid, config.gid) {
+                program.result = Some(result);
+                return Ok(program);
+            }
+        }
+        let before = artifacts.as_ref().map(|_| WorkdirSnapshot::take(&workdir));
 
-        // Restore original config
-        self.instance.config = original_config;
-        result
+        let result = self.with_language_settings(name, |isolate| {
+            isolate.execute_overridden(&compile_command, None, &limits, ExecutionPhase::Compile)
+        })?;
+        if let (Some((cache, key)), Some(before)) = (&artifacts, &before) {
+            if let Err(e) = cache.store(key, &workdir, before, &source_file, &result) {
+                log::warn!("Failed to cache build {}: {}", key.as_str(), e);
+            }
+        }
+        program.result = Some(result);
+        Ok(program)
     }
 
-    /// Compile and execute Java code from string
-    fn compile_and_execute_java(
+    /// Run a program built by `compile` under `overrides`
+    pub fn run(
         &mut self,
-        code: &str,
+        program: &Compilation,
         stdin_data: Option<&str>,
-        max_cpu: Option<u64>,
-        max_memory: Option<u64>,
-        max_time: Option<u64>,
-        max_wall_time: Option<u64>,
-        fd_limit: Option<u64>,
+        overrides: &ExecutionOverrides,
     ) -> Result<ExecutionResult> {
-        // Extract class name from code (simple heuristic)
-        let class_name = self
-            .extract_java_class_name(code)
-            .unwrap_or("Main".to_string());
-        let source_file = self
-            .instance
-            .config
-            .workdir
-            .join(format!("{}.java", class_name));
-        fs::write(&source_file, code)?;
-
-        // Java needs relaxed isolation settings due to JVM threading requirements
-        // Temporarily modify config for Java compilation and execution
-        let original_config = self.instance.config.clone();
-
-        // Relax isolation for Java (JVM requires more system access)
-        self.instance.config.enable_pid_namespace = false;
-        self.instance.config.enable_network_namespace = false;
+        if !program.succeeded() {
+            return Err(IsolateError::Config(format!(
+                "Cannot run {} program: compilation failed",
+                program.language
+            )));
+        }
+        let mut result = self.with_language_settings(&program.language, |isolate| {
+            isolate.execute_with_overrides(&program.command, stdin_data, overrides)
+        })?;
+        result.compile_output = program.output();
+        Ok(result)
+    }
 
-        // Increase resource limits for JVM
-        if let Some(memory) = max_memory {
-            self.instance.config.memory_limit = Some(memory * 1024 * 1024);
-        } else {
-            self.instance.config.memory_limit = Some(512 * 1024 * 1024); // 512MB default for Java
-        }
-
-        // Increase process limit for JVM threads
-        self.instance.config.process_limit = Some(50);
-
-        // Compile the code with relaxed settings
-        let compile_command = vec![
-            "javac".to_string(),
-            "-cp".to_string(),
-            ".".to_string(),
-            format!("{}.java", class_name),
-        ];
-
-        let compile_result = self.execute(&compile_command, None)?;
-
-        if !compile_result.success {
-            // Restore original config
-            self.instance.config = original_config;
-            return Ok(ExecutionResult {
-                status: crate::types::ExecutionStatus::RuntimeError,
-                exit_code: compile_result.exit_code,
-                stdout: "".to_string(),
-                stderr: format!("Java Compilation Error:\n{}", compile_result.stderr),
-                wall_time: compile_result.wall_time,
-                cpu_time: compile_result.cpu_time,
-                memory_peak: compile_result.memory_peak,
-                success: false,
-                signal: None,
-                error_message: Some("Java compilation failed".to_string()),
-            });
-        }
-
-        // Execute the compiled class with relaxed settings
-        let execute_command = vec![
-            "java".to_string(),
-            "-cp".to_string(),
-            ".".to_string(),
-            class_name,
-        ];
-
-        let result = self.execute_with_overrides(
-            &execute_command,
-            stdin_data,
-            max_cpu,
-            max_memory,
-            max_time,
-            max_wall_time,
-            fd_limit,
-        );
+    /// Run `f` with the sandbox adjustments a language needs in both phases
+    fn with_language_settings<T>(
+        &mut self,
+        language: &str,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        let original_config = self.instance.config.clone();
+        if let Some(profile) = LanguageProfile::get(language) {
+            profile.apply(&mut self.instance.config);
+        }
+        if self.instance.config.minimal_visibility || self.instance.config.landlock {
+            self.instance
+                .config
+                .visible_paths
+                .extend(FilesystemConfig::visible_paths_for(language));
+        } else if self.instance.config.chroot_dir.is_some() {
+            self.instance
+                .config
+                .chroot_bind_paths
+                .extend(FilesystemConfig::visible_paths_for(language));
+        } else if self.instance.config.rootfs_image.is_none()
+            && self.instance.config.chroot_dir.is_none()
+        {
+            self.instance.config.rootfs_image = FilesystemConfig::rootfs_image_for(language);
+        }
+        let result = f(self);
 
         // Restore original config
         self.instance.config = original_config;
-
         result
     }
 
-    /// Extract Java class name from source code (simple regex-based extraction)
-    fn extract_java_class_name(&self, code: &str) -> Option<String> {
-        // Look for "public class ClassName" pattern
-        for line in code.lines() {
-            let line = line.trim();
-            if line.starts_with("public class ") {
-                let parts: Vec<&str> = line.split_whitespace().collect();
-                if parts.len() >= 3 {
-                    let class_name = parts[2].trim_end_matches('{').trim();
-                    return Some(class_name.to_string());
-                }
-            }
+    /// Filesystem controller for this box's workdir
+    fn filesystem_security(&self) -> crate::filesystem::FilesystemSecurity {
+        let config = &self.instance.config;
+        crate::filesystem::FilesystemSecurity::new(
+            config.chroot_dir.clone(),
+            config.workdir.clone(),
+            config.strict_mode,
+        )
+    }
+
+    /// Mount the size-bounded workdir (tmpfs or disk quota image) the
+    /// configuration asks for; the tmpfs wins when both are set
+    fn mount_workdir(&self) -> Result<()> {
+        let config = &self.instance.config;
+        if config.workdir_tmpfs_size.is_none() && config.disk_quota.is_none() {
+            return Ok(());
+        }
+        // The default workdir is the shared scratch dir; never mount over that
+        let workdir = &config.workdir;
+        if paths().scratch_dir().starts_with(workdir) || paths().state_dir().starts_with(workdir) {
+            return Err(IsolateError::Config(format!(
+                "Workdir {} is shared with other boxes and cannot be bounded",
+                workdir.display()
+            )));
         }
-        None
+        let filesystem = self.filesystem_security();
+        match (config.workdir_tmpfs_size, config.disk_quota) {
+            (Some(size), _) => filesystem.mount_workdir_tmpfs(size, config.uid, config.gid),
+            (None, Some(quota)) => filesystem.mount_workdir_image(quota, config.uid, config.gid),
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Write submitted source into the workdir honoring the configured umask
+    fn write_source_file(&self, path: &std::path::Path, code: &str) -> Result<()> {
+        let config = &self.instance.config;
+        // The box user must be able to read it under a umask masking "other"
+        self.filesystem_security().write_sandbox_file(
+            path,
+            code.as_bytes(),
+            config.umask,
+            config.uid,
+            config.gid,
+        )
     }
 
     /// Clean up this isolate instance
     pub fn cleanup(mut self) -> Result<()> {
-        let instance_id = self.instance.config.instance_id.clone();
-
         // Acquire lock for cleanup to prevent conflicts
         if self.box_lock_guard.is_none() {
             self.acquire_lock(false)?;
         }
 
-        // Remove from storage atomically
-        self.atomic_instances_update(|instances| {
-            instances.remove(&instance_id);
-        })?;
+        let removed = self.remove_instance();
+        if removed.is_err() {
+            crate::metrics::record_cleanup_failure();
+        }
+        removed
+    }
 
-        // Clean up filesystem
+    /// Remove the registry entry and directories of this locked instance
+    fn remove_instance(&mut self) -> Result<()> {
+        let instance_id = self.instance.config.instance_id.clone();
+
+        // Remove from storage atomically
+        Self::store()?.remove(&instance_id)?;
+
+        // Clean up filesystem, discarding the bounded workdir first
+        let config = &self.instance.config;
+        self.filesystem_security()
+            .unmount_directory_bindings(&config.directory_bindings)?;
+        self.filesystem_security()
+            .unmount_socket_bindings(&config.socket_bindings)?;
+        if config.workdir_tmpfs_size.is_some() || config.disk_quota.is_some() {
+            self.filesystem_security().unmount_workdir()?;
+        }
+        if config.chroot_dir.is_some() {
+            self.filesystem_security().unmount_rootfs()?;
+        }
         if self.base_path.exists() {
             fs::remove_dir_all(&self.base_path).map_err(IsolateError::Io)?;
         }
@@ -435,11 +1282,95 @@ impl Isolate {
         Ok(())
     }
 
+    /// Apply the post-execution cleanup policy
+    ///
+    /// Returns `true` if the sandbox was removed. Besides the instance itself this
+    /// also removes the standardized `rustbox-<box_id>` work directory used by `run`.
+    pub fn finish(self, policy: CleanupPolicy) -> Result<bool> {
+        if policy == CleanupPolicy::Keep {
+            return Ok(false);
+        }
+
+        let box_id = Self::extract_box_id(&self.instance.config.instance_id).ok();
+        self.cleanup()?;
+
+        if let Some(box_id) = box_id {
+            let standard_dir = paths().standard_workdir(box_id);
+            if standard_dir.exists() {
+                fs::remove_dir_all(&standard_dir).map_err(IsolateError::Io)?;
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Make the box ready for an unrelated submission without re-creating it
+    ///
+    /// Empties the workdir and replaces the configuration with `config`, which
+    /// must describe the same box; the lock, registry entry and directories stay.
+    pub fn reset(&mut self, config: IsolateConfig) -> Result<()> {
+        if config.instance_id != self.instance.config.instance_id {
+            return Err(IsolateError::Config(format!(
+                "Cannot reset {} with the configuration of {}",
+                self.instance.config.instance_id, config.instance_id
+            )));
+        }
+
+        // The default workdir is the shared scratch dir; never empty that
+        let workdir = &self.instance.config.workdir;
+        if paths().scratch_dir().starts_with(workdir) || paths().state_dir().starts_with(workdir) {
+            return Err(IsolateError::Config(format!(
+                "Workdir {} is shared with other boxes and cannot be reset",
+                workdir.display()
+            )));
+        }
+        if workdir.exists() {
+            for entry in fs::read_dir(workdir)? {
+                let path = entry?.path();
+                if path.is_dir() && !path.is_symlink() {
+                    fs::remove_dir_all(&path)?;
+                } else {
+                    fs::remove_file(&path)?;
+                }
+            }
+        }
+
+        self.instance.config = config;
+        self.instance.last_used = chrono::Utc::now();
+        self.cancel_token = None;
+        self.output_sink = None;
+        self.stdin_source = None;
+        self.save()
+    }
+
+    /// Box id of this isolate, from its instance id
+    pub fn box_id(&self) -> Result<u32> {
+        Self::extract_box_id(&self.instance.config.instance_id)
+    }
+
+    /// When this box was last set up, run in or reconfigured
+    pub fn last_used(&self) -> chrono::DateTime<chrono::Utc> {
+        self.instance.last_used
+    }
+
     /// Get configuration
     pub fn config(&self) -> &IsolateConfig {
         &self.instance.config
     }
 
+    /// Recent executions in this box, oldest first
+    pub fn history(&self) -> &VecDeque<ExecutionSummary> {
+        &self.instance.history
+    }
+
+    /// What a run with `overrides` would set up, resolved without executing anything
+    pub fn effective_config(
+        &self,
+        overrides: &ExecutionOverrides,
+    ) -> Result<crate::explain::EffectiveConfig> {
+        crate::explain::resolve(&self.overridden_config(overrides, ExecutionPhase::Run))
+    }
+
     /// Add directory bindings to the isolate configuration
     pub fn add_directory_bindings(
         &mut self,
@@ -486,41 +1417,232 @@ impl Isolate {
         Ok(())
     }
 
-    /// Save instance configuration with atomic operations
-    pub fn save(&self) -> Result<()> {
-        self.atomic_instances_update(|instances| {
-            instances.insert(
-                self.instance.config.instance_id.clone(),
-                self.instance.clone(),
-            );
-        })
+    /// Add host Unix socket bindings to the isolate configuration
+    pub fn add_socket_bindings(
+        &mut self,
+        bindings: Vec<crate::types::SocketBinding>,
+    ) -> Result<()> {
+        for binding in &bindings {
+            if !binding.target.is_absolute() {
+                return Err(IsolateError::Config(format!(
+                    "Socket target path must be absolute (start with /): {}",
+                    binding.target.display()
+                )));
+            }
+
+            if self
+                .instance
+                .config
+                .socket_bindings
+                .iter()
+                .any(|existing| existing.target == binding.target)
+            {
+                return Err(IsolateError::Config(format!(
+                    "Socket target already bound: {}",
+                    binding.target.display()
+                )));
+            }
+        }
+
+        self.instance.config.socket_bindings.extend(bindings);
+        self.instance.last_used = chrono::Utc::now();
+        self.save()?;
+
+        Ok(())
     }
 
-    /// Load all instances from storage
-    fn load_all_instances() -> Result<HashMap<String, IsolateInstance>> {
-        let mut config_file = std::env::temp_dir();
-        config_file.push("rustbox");
+    /// Hide the host filesystem from later runs except the runtime directories,
+    /// the workdir and `visible_paths`
+    pub fn restrict_visibility(&mut self, visible_paths: Vec<PathBuf>) -> Result<()> {
+        if self.instance.config.chroot_dir.is_some() || self.instance.config.landlock {
+            return Err(IsolateError::Config(
+                "Minimal visibility only applies to sandboxes without a chroot or Landlock"
+                    .to_string(),
+            ));
+        }
+        if let Some(path) = visible_paths.iter().find(|path| !path.is_absolute()) {
+            return Err(IsolateError::Config(format!(
+                "Visible path must be absolute: {}",
+                path.display()
+            )));
+        }
+
+        self.instance.config.minimal_visibility = true;
+        self.instance.config.visible_paths.extend(visible_paths);
+        self.instance.last_used = chrono::Utc::now();
+        self.save()
+    }
 
-        // Create directory if it doesn't exist
-        if !config_file.exists() {
-            fs::create_dir_all(&config_file)?;
+    /// Confine later runs with Landlock to the workdir, the runtime directories
+    /// and `readable_paths` (read-only), without needing root or a chroot
+    pub fn confine_with_landlock(&mut self, readable_paths: Vec<PathBuf>) -> Result<()> {
+        let config = &self.instance.config;
+        if config.chroot_dir.is_some() || config.minimal_visibility || config.rootfs_image.is_some()
+        {
+            return Err(IsolateError::Config(
+                "Landlock confinement only applies to sandboxes without a chroot, minimal visibility or root image".to_string(),
+            ));
+        }
+        if let Some(path) = readable_paths.iter().find(|path| !path.is_absolute()) {
+            return Err(IsolateError::Config(format!(
+                "Readable path must be absolute: {}",
+                path.display()
+            )));
         }
 
-        config_file.push("instances.json");
+        self.instance.config.landlock = true;
+        self.instance.config.visible_paths.extend(readable_paths);
+        self.instance.last_used = chrono::Utc::now();
+        self.save()
+    }
 
-        if !config_file.exists() {
-            return Ok(HashMap::new());
+    /// Run later commands on an overlay of the root filesystem image `image`
+    ///
+    /// Writes outside the workdir land in a per-run tmpfs of `upper_size` bytes
+    /// (`visibility::DEFAULT_UPPER_SIZE` if unset) and vanish with the run.
+    pub fn use_rootfs_image(&mut self, image: PathBuf, upper_size: Option<u64>) -> Result<()> {
+        let config = &self.instance.config;
+        if config.chroot_dir.is_some() || config.minimal_visibility || config.landlock {
+            return Err(IsolateError::Config(
+                "A root image cannot be combined with a chroot, minimal visibility or Landlock"
+                    .to_string(),
+            ));
+        }
+        if !image.is_absolute() || !image.is_dir() {
+            return Err(IsolateError::Config(format!(
+                "Root image must be an absolute path to a directory: {}",
+                image.display()
+            )));
         }
 
-        let content = fs::read_to_string(config_file)?;
-        if content.trim().is_empty() {
-            return Ok(HashMap::new());
+        self.instance.config.rootfs_image = Some(image);
+        self.instance.config.rootfs_upper_size = upper_size;
+        self.instance.last_used = chrono::Utc::now();
+        self.save()
+    }
+
+    /// Give later runs the network `mode`
+    pub fn set_network_mode(&mut self, mode: NetworkMode) -> Result<()> {
+        self.instance.config.network_mode = mode;
+        self.instance.last_used = chrono::Utc::now();
+        self.save()
+    }
+
+    /// Run later programs on `cpus` only, for steadier timing
+    pub fn pin_cpus(&mut self, cpus: Vec<usize>) -> Result<()> {
+        crate::executor::cpu_set(&cpus)?;
+        self.instance.config.cpu_affinity = Some(cpus);
+        self.instance.last_used = chrono::Utc::now();
+        self.save()
+    }
+
+    /// Let later runs connect to the destinations `rules` allow; see `egress`
+    pub fn allow_egress(&mut self, rules: Vec<EgressRule>) -> Result<()> {
+        let config = &self.instance.config;
+        if config.network_mode == NetworkMode::Full || !config.enable_network_namespace {
+            return Err(IsolateError::Config(
+                "Egress rules only apply to an isolated network".to_string(),
+            ));
         }
+        if let Some(rule) = rules.iter().find(|rule| rule.network.is_ipv6()) {
+            return Err(IsolateError::Config(format!(
+                "Egress rule {} is IPv6; boxes only get IPv4 egress",
+                rule
+            )));
+        }
+
+        self.instance.config.egress_allowlist.extend(rules);
+        self.instance.last_used = chrono::Utc::now();
+        self.save()
+    }
+
+    /// Freeze the program running in this box to `images_dir` with CRIU
+    ///
+    /// The program is killed once dumped unless `leave_running` is set; see
+    /// `checkpoint` for what can be checkpointed.
+    #[cfg(feature = "criu")]
+    pub fn checkpoint(
+        &self,
+        images_dir: &std::path::Path,
+        leave_running: bool,
+    ) -> Result<crate::checkpoint::Checkpoint> {
+        crate::checkpoint::dump(&self.instance.config.instance_id, images_dir, leave_running)
+    }
+
+    /// Restore a program checkpointed in this box; returns its pid
+    #[cfg(feature = "criu")]
+    pub fn restore(&self, images_dir: &std::path::Path) -> Result<u32> {
+        crate::checkpoint::restore(&self.instance.config.instance_id, images_dir)
+    }
+
+    /// Save instance configuration with atomic operations
+    pub fn save(&self) -> Result<()> {
+        Self::store()?.save(&self.instance.config.instance_id, &self.instance)
+    }
+
+    /// Store of instance records, with the entries of an old instances.json
+    /// moved into it first
+    fn store() -> Result<InstanceStore> {
+        let store = InstanceStore::new(paths().instances_dir());
+        let registry = Self::registry_path();
+        if registry.exists() {
+            let lock = paths().instances_lock_file();
+            let moved = store.migrate::<IsolateInstance>(&registry, &lock)?;
+            log::info!(
+                "Moved {} instances from {} to {}",
+                moved,
+                registry.display(),
+                store.dir().display()
+            );
+        }
+        Ok(store)
+    }
+
+    /// Path of the single-file registry of older releases
+    pub fn registry_path() -> PathBuf {
+        paths().instances_file()
+    }
+
+    /// Directory backing an instance
+    pub fn instance_dir(instance_id: &str) -> PathBuf {
+        paths().instance_dir(instance_id)
+    }
+
+    /// Drop registry entries, e.g. ones whose box directory no longer exists
+    pub fn forget_instances(instance_ids: &[String]) -> Result<()> {
+        let store = Self::store()?;
+        for instance_id in instance_ids {
+            store.remove(instance_id)?;
+        }
+        Ok(())
+    }
+
+    /// Move the entries of an unreadable old instances.json that can still be
+    /// parsed into the instance records
+    ///
+    /// The original file is kept next to it as `instances.json.corrupt-<timestamp>`.
+    pub fn recover_registry() -> Result<RegistryRecovery> {
+        let store = InstanceStore::new(paths().instances_dir());
+        let lock = paths().instances_lock_file();
+        store.salvage::<IsolateInstance>(&Self::registry_path(), &lock)
+    }
 
-        let instances: HashMap<String, IsolateInstance> = serde_json::from_str(&content)
-            .map_err(|e| IsolateError::Config(format!("Failed to parse instances: {}", e)))?;
+    /// Instance records that cannot be read, with the reason
+    pub fn unreadable_records() -> Result<Vec<(String, PathBuf, IsolateError)>> {
+        let store = Self::store()?;
+        Ok(store
+            .unreadable::<IsolateInstance>()?
+            .into_iter()
+            .map(|(instance_id, error)| {
+                let path = store.record_path(&instance_id);
+                (instance_id, path, error)
+            })
+            .collect())
+    }
 
-        Ok(instances)
+    /// Move an unreadable instance record aside as `<record>.corrupt-<timestamp>`
+    pub fn quarantine_record(instance_id: &str) -> Result<PathBuf> {
+        Self::store()?.quarantine(instance_id)
     }
 
     /// Acquire exclusive lock for this isolate instance using enhanced lock manager
@@ -536,56 +1658,6 @@ impl Isolate {
         Ok(())
     }
 
-    /// Atomic update of instances.json using enhanced lock manager
-    fn atomic_instances_update<F>(&self, update_fn: F) -> Result<()>
-    where
-        F: FnOnce(&mut HashMap<String, IsolateInstance>),
-    {
-        let instances_dir = std::env::temp_dir().join("rustbox");
-        fs::create_dir_all(&instances_dir)?;
-        let instances_file = instances_dir.join("instances.json");
-
-        // Use the enhanced lock manager's file locking
-        with_file_lock(&instances_file, || {
-            // Load current instances
-            let mut instances = if instances_file.exists() {
-                let content = fs::read_to_string(&instances_file)?;
-                if content.trim().is_empty() {
-                    HashMap::new()
-                } else {
-                    serde_json::from_str(&content).map_err(|e| {
-                        crate::types::LockError::SystemError {
-                            message: format!("Failed to parse instances: {}", e),
-                        }
-                    })?
-                }
-            } else {
-                HashMap::new()
-            };
-
-            // Apply update
-            update_fn(&mut instances);
-
-            // Write atomically
-            let temp_file = instances_dir.join("instances.json.tmp");
-            let content = serde_json::to_string_pretty(&instances).map_err(|e| {
-                crate::types::LockError::SystemError {
-                    message: format!("Failed to serialize instances: {}", e),
-                }
-            })?;
-
-            fs::write(&temp_file, content)?;
-            fs::rename(&temp_file, &instances_file)?;
-
-            Ok(())
-        })
-        .map_err(|e| match e {
-            crate::types::LockError::FilesystemError(io_err) => IsolateError::Io(io_err),
-            crate::types::LockError::SystemError { message } => IsolateError::Config(message),
-            _ => IsolateError::Lock(e.to_string()),
-        })
-    }
-
     /// Acquire execution lock for loaded isolate (public version of acquire_lock)
     pub fn acquire_execution_lock(&mut self) -> Result<()> {
         if self.box_lock_guard.is_some() {
@@ -594,6 +1666,32 @@ impl Isolate {
         self.acquire_lock(false)
     }
 
+    /// Lock the box, retrying with exponential backoff for at most `timeout`
+    ///
+    /// Fails with `LockError::Timeout` if another process still holds the box
+    /// by then; a zero timeout fails at once.
+    pub fn acquire_execution_lock_with_timeout(&mut self, timeout: Duration) -> Result<()> {
+        if self.box_lock_guard.is_some() {
+            return Ok(());
+        }
+        let box_id = Self::extract_box_id(&self.instance.config.instance_id)?;
+        let guard =
+            acquire_box_lock_with_timeout(box_id, timeout).map_err(IsolateError::AdvancedLock)?;
+        self.box_lock_guard = Some(guard);
+        Ok(())
+    }
+
+    /// Lock the box, waiting at most `timeout`; false if another process holds it
+    pub fn try_acquire_execution_lock(&mut self, timeout: Duration) -> Result<bool> {
+        match self.acquire_execution_lock_with_timeout(timeout) {
+            Ok(()) => Ok(true),
+            Err(IsolateError::AdvancedLock(LockError::Busy { .. } | LockError::Timeout { .. })) => {
+                Ok(false)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     /// Release the lock (happens automatically on drop)
     fn release_lock(&mut self) {
         self.box_lock_guard = None; // Lock guard automatically releases on drop
@@ -0,0 +1,280 @@
+/// Judge0 submission schema adapter
+///
+/// Maps between rustbox and the submission objects of the Judge0 API, so
+/// clients written against Judge0 can talk to rustbox without glue code: a
+/// `SubmissionRequest` (numeric `language_id`, limits in Judge0's units)
+/// becomes a rustbox language and `ExecutionOverrides`, and an
+/// `ExecutionResult` becomes a `SubmissionResponse` with Judge0's status ids,
+/// a token, `compile_output`, `message`, and times as decimal strings.
+///
+/// With `base64_encoded`, the text fields of both are base64 as in Judge0.
+/// Judge0 takes fractional seconds while rustbox limits are whole seconds, so
+/// time limits are rounded up.
+use crate::types::{ExecutionOverrides, ExecutionResult, ExecutionStatus, IsolateError, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// A Judge0 submission status
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Judge0Status {
+    pub id: u8,
+    pub description: &'static str,
+}
+
+impl Judge0Status {
+    const fn new(id: u8, description: &'static str) -> Self {
+        Self { id, description }
+    }
+}
+
+pub const IN_QUEUE: Judge0Status = Judge0Status::new(1, "In Queue");
+pub const PROCESSING: Judge0Status = Judge0Status::new(2, "Processing");
+pub const ACCEPTED: Judge0Status = Judge0Status::new(3, "Accepted");
+pub const WRONG_ANSWER: Judge0Status = Judge0Status::new(4, "Wrong Answer");
+pub const TIME_LIMIT_EXCEEDED: Judge0Status = Judge0Status::new(5, "Time Limit Exceeded");
+pub const COMPILATION_ERROR: Judge0Status = Judge0Status::new(6, "Compilation Error");
+pub const RUNTIME_ERROR_SIGSEGV: Judge0Status = Judge0Status::new(7, "Runtime Error (SIGSEGV)");
+pub const RUNTIME_ERROR_SIGXFSZ: Judge0Status = Judge0Status::new(8, "Runtime Error (SIGXFSZ)");
+pub const RUNTIME_ERROR_SIGFPE: Judge0Status = Judge0Status::new(9, "Runtime Error (SIGFPE)");
+pub const RUNTIME_ERROR_SIGABRT: Judge0Status = Judge0Status::new(10, "Runtime Error (SIGABRT)");
+pub const RUNTIME_ERROR_NZEC: Judge0Status = Judge0Status::new(11, "Runtime Error (NZEC)");
+pub const RUNTIME_ERROR_OTHER: Judge0Status = Judge0Status::new(12, "Runtime Error (Other)");
+pub const INTERNAL_ERROR: Judge0Status = Judge0Status::new(13, "Internal Error");
+pub const EXEC_FORMAT_ERROR: Judge0Status = Judge0Status::new(14, "Exec Format Error");
+
+/// Every status, as Judge0's `GET /statuses` lists them
+pub const STATUSES: [Judge0Status; 14] = [
+    IN_QUEUE,
+    PROCESSING,
+    ACCEPTED,
+    WRONG_ANSWER,
+    TIME_LIMIT_EXCEEDED,
+    COMPILATION_ERROR,
+    RUNTIME_ERROR_SIGSEGV,
+    RUNTIME_ERROR_SIGXFSZ,
+    RUNTIME_ERROR_SIGFPE,
+    RUNTIME_ERROR_SIGABRT,
+    RUNTIME_ERROR_NZEC,
+    RUNTIME_ERROR_OTHER,
+    INTERNAL_ERROR,
+    EXEC_FORMAT_ERROR,
+];
+
+/// Judge0 language ids of the built-in languages: (id, rustbox language,
+/// Judge0 name). The first id of each language is the one reported back.
+pub const LANGUAGES: &[(u32, &str, &str)] = &[
+    (71, "python", "Python (3.8.1)"),
+    (92, "python", "Python (3.11.2)"),
+    (100, "python", "Python (3.12.5)"),
+    (54, "cpp", "C++ (GCC 9.2.0)"),
+    (52, "cpp", "C++ (GCC 7.4.0)"),
+    (53, "cpp", "C++ (GCC 8.3.0)"),
+    (76, "cpp", "C++ (Clang 7.0.1)"),
+    (62, "java", "Java (OpenJDK 13.0.1)"),
+    (91, "java", "Java (JDK 17.0.6)"),
+    (60, "go", "Go (1.13.5)"),
+    (95, "go", "Go (1.18.5)"),
+];
+
+/// rustbox language for Judge0 language `id`
+pub fn language_name(id: u32) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(known, _, _)| *known == id)
+        .map(|(_, language, _)| *language)
+}
+
+/// Judge0 language id reported for rustbox `language`
+pub fn language_id(language: &str) -> Option<u32> {
+    let language = language.to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(_, known, _)| *known == language)
+        .map(|(id, _, _)| *id)
+}
+
+/// Body of Judge0's `POST /submissions`; unset limits keep rustbox's defaults
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SubmissionRequest {
+    pub source_code: String,
+    pub language_id: u32,
+    pub stdin: Option<String>,
+    /// Output the program must print to be `Accepted`
+    pub expected_output: Option<String>,
+    /// CPU time limit in seconds
+    pub cpu_time_limit: Option<f64>,
+    /// Seconds past the CPU limit before the program is killed
+    pub cpu_extra_time: Option<f64>,
+    /// Wall clock time limit in seconds
+    pub wall_time_limit: Option<f64>,
+    /// Memory limit in KB
+    pub memory_limit: Option<u64>,
+    pub max_processes_and_or_threads: Option<u32>,
+    /// Largest output the program may write, in KB
+    pub max_file_size: Option<u64>,
+}
+
+impl SubmissionRequest {
+    /// Decode the base64 fields of a request sent with `base64_encoded=true`
+    pub fn decode(mut self) -> Result<Self> {
+        self.source_code = decode_field("source_code", &self.source_code)?;
+        self.stdin = self
+            .stdin
+            .map(|stdin| decode_field("stdin", &stdin))
+            .transpose()?;
+        self.expected_output = self
+            .expected_output
+            .map(|expected| decode_field("expected_output", &expected))
+            .transpose()?;
+        Ok(self)
+    }
+
+    /// rustbox language of `language_id`
+    pub fn language(&self) -> Result<&'static str> {
+        language_name(self.language_id).ok_or_else(|| {
+            IsolateError::Config(format!(
+                "Unsupported Judge0 language_id {}",
+                self.language_id
+            ))
+        })
+    }
+
+    /// The request's limits as execution overrides
+    pub fn overrides(&self) -> ExecutionOverrides {
+        let seconds = |limit: Option<f64>| {
+            limit.and_then(|secs| Duration::try_from_secs_f64(secs.max(0.0)).ok())
+        };
+        ExecutionOverrides {
+            max_cpu: seconds(self.cpu_time_limit),
+            extra_time: seconds(self.cpu_extra_time),
+            max_wall_time: seconds(self.wall_time_limit),
+            max_memory: self.memory_limit.map(|kb| kb.div_ceil(1024)),
+            max_processes: self.max_processes_and_or_threads,
+            max_output: self.max_file_size,
+            ..Default::default()
+        }
+    }
+}
+
+/// A finished submission as Judge0 returns it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SubmissionResponse {
+    pub token: String,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+    pub compile_output: Option<String>,
+    pub message: Option<String>,
+    pub exit_code: Option<i32>,
+    pub exit_signal: Option<i32>,
+    pub status: Judge0Status,
+    /// CPU time in seconds
+    pub time: String,
+    /// Wall clock time in seconds
+    pub wall_time: String,
+    /// Peak memory in KB
+    pub memory: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language_id: Option<u32>,
+}
+
+impl SubmissionResponse {
+    /// Judge0 view of `result`, judged against `expected_output` when given,
+    /// with its text fields base64-encoded when `base64_encoded` is set
+    pub fn new(
+        result: &ExecutionResult,
+        expected_output: Option<&str>,
+        base64_encoded: bool,
+    ) -> Self {
+        let encode = |text: Option<String>| {
+            if base64_encoded {
+                text.map(|text| BASE64.encode(text))
+            } else {
+                text
+            }
+        };
+        let compiled = result.status != ExecutionStatus::CompileError;
+        Self {
+            token: uuid::Uuid::new_v4().to_string(),
+            stdout: encode(compiled.then(|| result.stdout.clone())),
+            stderr: encode(compiled.then(|| result.stderr.clone())),
+            compile_output: encode(result.compile_output.clone()),
+            message: encode(message(result)),
+            exit_code: result.exit_code,
+            exit_signal: result.signal,
+            status: status(result, expected_output),
+            time: format!("{:.3}", result.cpu_time),
+            wall_time: format!("{:.3}", result.wall_time),
+            memory: result.memory_peak / 1024,
+            language_id: None,
+        }
+    }
+
+    /// Report `language_id` for rustbox `language`
+    pub fn with_language(mut self, language: &str) -> Self {
+        self.language_id = language_id(language);
+        self
+    }
+}
+
+/// Judge0 status of `result`; a clean exit is `Wrong Answer` when its stdout
+/// differs from `expected_output`
+pub fn status(result: &ExecutionResult, expected_output: Option<&str>) -> Judge0Status {
+    match result.status {
+        ExecutionStatus::Success => match expected_output {
+            Some(expected) if normalize(expected) != normalize(&result.stdout) => WRONG_ANSWER,
+            _ => ACCEPTED,
+        },
+        ExecutionStatus::TimeLimit | ExecutionStatus::IdleTimeout => TIME_LIMIT_EXCEEDED,
+        ExecutionStatus::CompileError => COMPILATION_ERROR,
+        ExecutionStatus::FileSizeLimit | ExecutionStatus::OutputLimit => RUNTIME_ERROR_SIGXFSZ,
+        ExecutionStatus::StackLimit => RUNTIME_ERROR_SIGSEGV,
+        ExecutionStatus::InternalError | ExecutionStatus::Cancelled | ExecutionStatus::Unknown => {
+            INTERNAL_ERROR
+        }
+        _ => match result.signal {
+            Some(libc::SIGSEGV) => RUNTIME_ERROR_SIGSEGV,
+            Some(libc::SIGXFSZ) => RUNTIME_ERROR_SIGXFSZ,
+            Some(libc::SIGFPE) => RUNTIME_ERROR_SIGFPE,
+            Some(libc::SIGABRT) => RUNTIME_ERROR_SIGABRT,
+            None if result.status == ExecutionStatus::RuntimeError => RUNTIME_ERROR_NZEC,
+            _ => RUNTIME_ERROR_OTHER,
+        },
+    }
+}
+
+/// Judge0's `message`: why the program was stopped, or its exit status
+fn message(result: &ExecutionResult) -> Option<String> {
+    match result.status {
+        ExecutionStatus::Success => None,
+        ExecutionStatus::TimeLimit => Some("Time limit exceeded".to_string()),
+        _ if result.error_message.is_some() => result.error_message.clone(),
+        _ => match (result.signal, result.exit_code) {
+            (Some(signal), _) => Some(format!("Exited with signal {}", signal)),
+            (None, Some(code)) if code != 0 => Some(format!("Exited with error status {}", code)),
+            _ => None,
+        },
+    }
+}
+
+/// Output as Judge0 compares it: trailing whitespace of each line and
+/// surrounding blank lines ignored
+fn normalize(output: &str) -> String {
+    output
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+fn decode_field(name: &str, value: &str) -> Result<String> {
+    let bytes = BASE64
+        .decode(value.trim())
+        .map_err(|e| IsolateError::Config(format!("Invalid base64 in {}: {}", name, e)))?;
+    String::from_utf8(bytes)
+        .map_err(|_| IsolateError::Config(format!("{} is not valid UTF-8", name)))
+}
@@ -0,0 +1,217 @@
+/// Landlock filesystem confinement for runs without a chroot
+///
+/// Landlock (Linux 5.13+) lets a process restrict its own filesystem access
+/// without any privilege, so it confines the program even when rustbox cannot
+/// chroot or mount. The supervisor builds the ruleset before fork: everything
+/// under the workdir is allowed, the runtime directories and allowlisted host
+/// paths may be read and executed, a few device nodes read and written, and
+/// the rest is denied. The child enforces it right before exec.
+///
+/// Newer ABI versions handle more access rights (REFER in v2, TRUNCATE in v3,
+/// IOCTL_DEV in v5); the ruleset handles all the running kernel knows. Without
+/// Landlock the run goes ahead unconfined, or fails in strict mode.
+use crate::types::{IsolateConfig, IsolateError, Result};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+
+/// `LANDLOCK_CREATE_RULESET_VERSION`
+const CREATE_RULESET_VERSION: libc::c_uint = 1;
+/// `LANDLOCK_RULE_PATH_BENEATH`
+const RULE_PATH_BENEATH: libc::c_int = 1;
+
+const ACCESS_EXECUTE: u64 = 1 << 0;
+const ACCESS_WRITE_FILE: u64 = 1 << 1;
+const ACCESS_READ_FILE: u64 = 1 << 2;
+const ACCESS_READ_DIR: u64 = 1 << 3;
+/// Every right of ABI v1: the four above plus removing and making entries
+const ACCESS_V1: u64 = (1 << 13) - 1;
+const ACCESS_REFER: u64 = 1 << 13;
+const ACCESS_TRUNCATE: u64 = 1 << 14;
+const ACCESS_IOCTL_DEV: u64 = 1 << 15;
+/// Rights a rule on a file (not a directory) may carry
+const FILE_ACCESS: u64 =
+    ACCESS_EXECUTE | ACCESS_WRITE_FILE | ACCESS_READ_FILE | ACCESS_TRUNCATE | ACCESS_IOCTL_DEV;
+const READ_ACCESS: u64 = ACCESS_EXECUTE | ACCESS_READ_FILE | ACCESS_READ_DIR;
+
+/// Host paths readable under Landlock besides the minimal-root runtime paths
+const READABLE_PATHS: &[&str] = &["/proc"];
+
+#[repr(C)]
+struct RulesetAttr {
+    handled_access_fs: u64,
+}
+
+#[repr(C, packed)]
+struct PathBeneathAttr {
+    allowed_access: u64,
+    parent_fd: i32,
+}
+
+/// Landlock ABI version of the running kernel, if it has Landlock enabled
+pub fn abi_version() -> Option<u32> {
+    let version = unsafe {
+        libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            std::ptr::null::<RulesetAttr>(),
+            0usize,
+            CREATE_RULESET_VERSION,
+        )
+    };
+    (version > 0).then_some(version as u32)
+}
+
+/// Whether runs can be confined with Landlock
+pub fn supported() -> bool {
+    abi_version().is_some()
+}
+
+/// Access rights ABI `version` handles
+fn handled_access(version: u32) -> u64 {
+    let mut access = ACCESS_V1;
+    if version >= 2 {
+        access |= ACCESS_REFER;
+    }
+    if version >= 3 {
+        access |= ACCESS_TRUNCATE;
+    }
+    if version >= 5 {
+        access |= ACCESS_IOCTL_DEV;
+    }
+    access
+}
+
+/// A Landlock ruleset ready to be enforced in the sandboxed child
+#[derive(Debug)]
+pub struct Ruleset {
+    fd: OwnedFd,
+    /// Rights this kernel handles, denied wherever no rule allows them
+    handled: u64,
+}
+
+impl Ruleset {
+    /// Ruleset allowing everything under `workdir` and reading and executing
+    /// `readable`
+    ///
+    /// Readable paths missing on this host are skipped.
+    pub fn build(workdir: &Path, readable: &[PathBuf]) -> Result<Self> {
+        let version = abi_version().ok_or_else(|| {
+            IsolateError::Config("Landlock is not available on this kernel".to_string())
+        })?;
+        let handled = handled_access(version);
+        let attr = RulesetAttr {
+            handled_access_fs: handled,
+        };
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_create_ruleset,
+                &attr,
+                std::mem::size_of::<RulesetAttr>(),
+                0,
+            )
+        };
+        if fd < 0 {
+            return Err(IsolateError::Config(format!(
+                "Failed to create Landlock ruleset: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        let ruleset = Self {
+            fd: unsafe { OwnedFd::from_raw_fd(fd as i32) },
+            handled,
+        };
+
+        ruleset.allow(workdir, handled)?;
+        for device in crate::visibility::DEVICES {
+            ruleset.allow(Path::new(device), ACCESS_READ_FILE | ACCESS_WRITE_FILE)?;
+        }
+        let default_paths = crate::visibility::DEFAULT_VISIBLE_PATHS
+            .iter()
+            .chain(READABLE_PATHS)
+            .map(PathBuf::from);
+        for path in default_paths.chain(readable.iter().cloned()) {
+            ruleset.allow(&path, READ_ACCESS)?;
+        }
+        Ok(ruleset)
+    }
+
+    /// Allow `access` beneath `path`, skipping it if it does not exist
+    fn allow(&self, path: &Path, access: u64) -> Result<()> {
+        let Ok(metadata) = std::fs::metadata(path) else {
+            log::debug!("Skipping missing Landlock path {}", path.display());
+            return Ok(());
+        };
+        let mut access = access & self.handled;
+        if !metadata.is_dir() {
+            access &= FILE_ACCESS;
+        }
+
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_PATH | libc::O_CLOEXEC)
+            .open(path)
+            .map_err(|e| {
+                IsolateError::Config(format!("Failed to open {}: {}", path.display(), e))
+            })?;
+        let rule = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd: file.as_raw_fd(),
+        };
+        let added = unsafe {
+            libc::syscall(
+                libc::SYS_landlock_add_rule,
+                self.fd.as_raw_fd(),
+                RULE_PATH_BENEATH,
+                &rule,
+                0,
+            )
+        };
+        if added != 0 {
+            return Err(IsolateError::Config(format!(
+                "Failed to add Landlock rule for {}: {}",
+                path.display(),
+                std::io::Error::last_os_error()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Confine the calling process and everything it starts
+    ///
+    /// Runs in the forked child, so it only makes raw syscalls. Sets
+    /// `no_new_privs`, which Landlock requires without CAP_SYS_ADMIN.
+    pub fn restrict_self(&self) -> std::io::Result<()> {
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let restricted =
+            unsafe { libc::syscall(libc::SYS_landlock_restrict_self, self.fd.as_raw_fd(), 0) };
+        if restricted != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// The ruleset a run with `config` of `program` is confined by, if any
+///
+/// Without Landlock the run continues unconfined, except in strict mode.
+pub(crate) fn ruleset_for(config: &IsolateConfig, program: &Path) -> Result<Option<Ruleset>> {
+    if !config.landlock {
+        return Ok(None);
+    }
+    if !supported() {
+        if config.strict_mode {
+            return Err(IsolateError::Config(
+                "Landlock confinement requires Linux 5.13+ with Landlock enabled".to_string(),
+            ));
+        }
+        log::warn!("Landlock unavailable; the program is not confined to its workdir");
+        return Ok(None);
+    }
+    let mut readable = config.visible_paths.clone();
+    if program.is_absolute() {
+        readable.push(program.to_path_buf());
+    }
+    Ruleset::build(&config.workdir, &readable).map(Some)
+}
@@ -1,14 +1,68 @@
 //! rustbox: A process isolation and resource control system
 //! Inspired by IOI Isolate, focused on secure process execution with cgroup-v1 support
 
+pub mod admission;
+pub mod artifact_cache;
+#[cfg(feature = "tokio")]
+pub mod async_exec;
+pub mod build_info;
+pub mod canary;
+pub mod capabilities;
+#[cfg(feature = "cgroup-v1")]
 pub mod cgroup;
+#[cfg(not(feature = "cgroup-v1"))]
+#[path = "cgroup_disabled.rs"]
+pub mod cgroup;
+pub mod checker;
+#[cfg(feature = "criu")]
+pub mod checkpoint;
+#[cfg(feature = "grpc")]
+pub mod client;
 pub mod config;
+pub mod defaults;
+pub mod egress;
+pub mod emulation;
 pub mod executor;
+pub mod explain;
+pub mod fetch;
+pub mod file_audit;
 pub mod filesystem;
+pub mod gc;
+#[cfg(feature = "grpc")]
+#[allow(clippy::result_large_err)] // tonic::Status is fixed by the service trait
+pub mod grpc;
+pub mod history;
+#[cfg(feature = "history-db")]
+pub mod history_db;
+pub mod instance_store;
+pub mod interactive;
 pub mod isolate;
+pub mod judge0;
+pub mod landlock;
 pub mod lock_manager;
+pub mod metrics;
 pub mod namespace;
+pub mod orchestrator;
+pub mod output_capture;
+pub mod paths;
+pub mod pool;
+pub mod process_memory;
+pub mod profile;
+pub mod pty;
+pub mod queue;
+pub mod reaper;
+pub mod regrade;
+pub mod report;
+pub mod result_cache;
+pub mod schema;
 pub mod security;
 pub mod security_logging;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod state_check;
+pub mod syscall_audit;
 pub mod tests;
+pub mod time_limit;
 pub mod types;
+pub mod visibility;
+pub mod watchdog;
@@ -1,11 +1,27 @@
 /// Enhanced lock manager implementing hybrid file + process-based locking with heartbeat
 /// Based on the senior SDE design principles from new_lock.md
+///
+/// This is the only place boxes are locked; `Isolate`, the CLI and the server
+/// all go through the functions under PUBLIC API below, after
+/// `init_lock_manager`:
+///
+/// - `acquire_box_lock` waits up to 30 seconds, `acquire_box_lock_with_timeout`
+///   as long as it is told, and `try_acquire_box_lock` not at all
+/// - `acquire_box_set` locks several boxes, all or none
+/// - the returned `BoxLockGuard` holds an flock on `box-<id>.lock` and writes a
+///   heartbeat to `box-<id>.heartbeat` every second until it is dropped
+/// - a lock whose holder died, or has not sent a heartbeat for 10 seconds, is
+///   taken over by the next caller wanting the box and logged as a security
+///   event; `BoxLockGuard::verify` tells the old holder it lost the box
+/// - `with_file_lock` serializes access to other shared files
+use crate::security_logging::events;
 use crate::types::{HealthStatus, LockError, LockInfo, LockManagerHealth, LockMetrics, LockResult};
 use crossbeam_channel::{self, Sender};
 use log::{error, info, warn};
 use std::collections::HashMap;
 use std::fs::{File, OpenOptions};
-use std::io::{Seek, SeekFrom, Write};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
 use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::sync::OnceLock;
@@ -17,7 +33,13 @@ use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Global lock manager instance
-static GLOBAL_LOCK_MANAGER: OnceLock<Arc<Mutex<RustboxLockManager>>> = OnceLock::new();
+///
+/// Not behind a mutex: a caller waiting out a busy box must not keep other
+/// threads from locking free ones.
+static GLOBAL_LOCK_MANAGER: OnceLock<RustboxLockManager> = OnceLock::new();
+
+/// Lock directory chosen by the operator, used instead of the defaults
+static LOCK_DIR_OVERRIDE: OnceLock<PathBuf> = OnceLock::new();
 
 /// flock operation constants
 const LOCK_EX: i32 = 2; // Exclusive lock
@@ -35,6 +57,7 @@ const MAX_MEMORY_USAGE_MB: u64 = 100;
 pub struct RustboxLockManager {
     lock_dir: PathBuf,
     heartbeat_interval: Duration,
+    /// A held lock without a heartbeat for this long is taken over
     stale_timeout: Duration,
     cleanup_thread: Option<JoinHandle<()>>,
     cleanup_shutdown: Option<Sender<()>>,
@@ -65,6 +88,8 @@ pub struct BoxLock {
     heartbeat_file: File,
     heartbeat_handle: Option<JoinHandle<()>>,
     heartbeat_shutdown: Sender<()>,
+    /// Why the heartbeat thread stopped, if it failed or saw the lock taken over
+    heartbeat_failure: Arc<OnceLock<String>>,
 }
 
 /// RAII guard for box locks
@@ -74,27 +99,35 @@ pub struct BoxLockGuard {
     _cleanup: DropGuard,
 }
 
+/// RAII guard for a set of box locks taken together
+///
+/// Releases the boxes in reverse acquisition order when dropped.
+#[derive(Debug)]
+pub struct BoxSetGuard {
+    guards: Vec<BoxLockGuard>,
+}
+
 /// Drop guard for cleanup
 #[derive(Debug)]
 struct DropGuard {
     box_id: u32,
-    lock_dir: PathBuf,
     active_locks_counter: Arc<AtomicU64>,
 }
 
 impl RustboxLockManager {
     /// Determine the best lock directory to use with fallback options
     fn get_lock_directory() -> LockResult<PathBuf> {
+        if let Some(lock_dir) = LOCK_DIR_OVERRIDE.get() {
+            return Ok(lock_dir.clone());
+        }
+
         // Preferred directories in order of preference
         let preferred_dirs = [
-            "/var/run/rustbox/locks",
-            "/tmp/rustbox/locks",
-            "/tmp/.rustbox-locks",
+            PathBuf::from("/var/run/rustbox/locks"),
+            crate::paths::paths().lock_dir(),
         ];
 
-        for dir_path in &preferred_dirs {
-            let path = PathBuf::from(dir_path);
-
+        for path in preferred_dirs {
             // Try to create the parent directory first
             if let Some(parent) = path.parent() {
                 if let Err(e) = std::fs::create_dir_all(parent) {
@@ -205,6 +238,13 @@ impl RustboxLockManager {
         Ok(manager)
     }
 
+    /// Heartbeat every `interval` and take over locks silent for `stale_timeout`
+    pub fn with_heartbeat(mut self, interval: Duration, stale_timeout: Duration) -> Self {
+        self.heartbeat_interval = interval;
+        self.stale_timeout = stale_timeout;
+        self
+    }
+
     /// Start background cleanup thread
     pub fn start_cleanup_thread(&mut self) -> LockResult<()> {
         let (shutdown_tx, shutdown_rx) = crossbeam_channel::bounded(1);
@@ -269,12 +309,7 @@ impl RustboxLockManager {
             box_id, current_locks
         );
 
-        // Step 1: Clean any stale lock first
-        if let Err(e) = self.cleanup_stale_lock_if_needed(box_id) {
-            warn!("Failed to cleanup stale lock for box {}: {}", box_id, e);
-        }
-
-        // Step 2: Retry loop with exponential backoff
+        // Retry loop with exponential backoff
         let mut retry_delay = Duration::from_millis(10);
         loop {
             match self.try_acquire_immediate(box_id, &lock_path, &heartbeat_path) {
@@ -287,6 +322,9 @@ impl RustboxLockManager {
                     return Ok(lock_guard);
                 }
                 Err(LockError::Busy { .. }) => {
+                    if self.take_over_stale_lock(box_id, &lock_path, &heartbeat_path) {
+                        continue;
+                    }
                     if start_time.elapsed() >= timeout {
                         self.record_timeout();
                         return Err(LockError::Timeout {
@@ -296,10 +334,11 @@ impl RustboxLockManager {
                         });
                     }
 
-                    // Exponential backoff with jitter
+                    // Exponential backoff with jitter, never sleeping past the timeout
                     let jitter =
                         Duration::from_millis(fastrand::u64(0..=retry_delay.as_millis() as u64));
-                    thread::sleep(retry_delay + jitter);
+                    let remaining = timeout.saturating_sub(start_time.elapsed());
+                    thread::sleep((retry_delay + jitter).min(remaining));
                     retry_delay = std::cmp::min(retry_delay * 2, Duration::from_millis(500));
 
                     self.record_contention();
@@ -312,6 +351,51 @@ impl RustboxLockManager {
         }
     }
 
+    /// Lock every box in `box_ids` or none of them
+    ///
+    /// Boxes are taken in ascending id order, so callers wanting overlapping sets
+    /// always contend on the lowest shared box first and can never each hold a
+    /// box the other is waiting for. `timeout` covers the whole set; on failure
+    /// the boxes already locked are released before returning.
+    pub fn acquire_set(&self, box_ids: &[u32], timeout: Duration) -> LockResult<BoxSetGuard> {
+        let mut ordered = box_ids.to_vec();
+        ordered.sort_unstable();
+        ordered.dedup();
+
+        let current_locks = self.active_locks.load(Ordering::Acquire);
+        if current_locks + ordered.len() as u64 > MAX_CONCURRENT_LOCKS {
+            return Err(LockError::SystemError {
+                message: format!(
+                    "Too many concurrent locks: {} held, {} requested, limit {}",
+                    current_locks,
+                    ordered.len(),
+                    MAX_CONCURRENT_LOCKS
+                ),
+            });
+        }
+
+        let start_time = Instant::now();
+        let mut guards = Vec::with_capacity(ordered.len());
+        for box_id in ordered {
+            let remaining = timeout.saturating_sub(start_time.elapsed());
+            match self.acquire_lock(box_id, remaining) {
+                Ok(guard) => guards.push(guard),
+                Err(e) => {
+                    warn!(
+                        "Failed to lock box {} of set {:?}, releasing {} held box(es): {}",
+                        box_id,
+                        box_ids,
+                        guards.len(),
+                        e
+                    );
+                    drop(BoxSetGuard { guards });
+                    return Err(e);
+                }
+            }
+        }
+        Ok(BoxSetGuard { guards })
+    }
+
     /// Atomic lock acquisition attempt
     fn try_acquire_immediate(
         &self,
@@ -319,11 +403,12 @@ impl RustboxLockManager {
         lock_path: &Path,
         heartbeat_path: &Path,
     ) -> LockResult<BoxLockGuard> {
-        // Step 1: Create lock file with exclusive access
+        // Step 1: Create lock file with exclusive access; it is only emptied once
+        // locked, so a contender cannot wipe the holder's info
         let lock_file = OpenOptions::new()
             .create(true)
             .write(true)
-            .truncate(true) // Clear any existing content
+            .truncate(false)
             .open(lock_path)?;
 
         // Step 2: Try to acquire exclusive lock (non-blocking)
@@ -340,6 +425,17 @@ impl RustboxLockManager {
                 }),
             };
         }
+        // Lock files are unlinked before they are released, so a lock on a file
+        // no longer at `lock_path` was only just released by its holder and
+        // another process may already hold the file replacing it
+        let locked_id = file_id(&lock_file.metadata()?);
+        if path_id(lock_path) != Some(locked_id) {
+            return Err(LockError::Busy {
+                box_id,
+                owner_pid: None,
+            });
+        }
+        lock_file.set_len(0)?;
 
         // Step 3: Write our lock info to lock file (for debugging)
         let lock_info = LockInfo {
@@ -365,8 +461,13 @@ impl RustboxLockManager {
             .open(heartbeat_path)?;
 
         // Step 5: Start heartbeat thread
-        let (heartbeat_handle, heartbeat_shutdown) =
-            self.start_heartbeat_thread(box_id, heartbeat_path.to_owned())?;
+        let heartbeat_failure = Arc::new(OnceLock::new());
+        let (heartbeat_handle, heartbeat_shutdown) = self.start_heartbeat_thread(
+            box_id,
+            heartbeat_path.to_owned(),
+            (lock_path.to_owned(), locked_id),
+            heartbeat_failure.clone(),
+        )?;
 
         // Step 6: Create the lock object
         let lock = BoxLock {
@@ -378,6 +479,7 @@ impl RustboxLockManager {
             heartbeat_file,
             heartbeat_handle: Some(heartbeat_handle),
             heartbeat_shutdown,
+            heartbeat_failure,
         };
 
         // Step 7: Increment active lock counter
@@ -388,17 +490,21 @@ impl RustboxLockManager {
             lock: Some(Arc::new(Mutex::new(lock))),
             _cleanup: DropGuard {
                 box_id,
-                lock_dir: self.lock_dir.clone(),
                 active_locks_counter: self.active_locks.clone(),
             },
         })
     }
 
     /// Start heartbeat thread for a specific lock
+    ///
+    /// The thread stops, recording why in `failure`, once a write fails or the
+    /// lock file at `lock.0` is no longer the one with id `lock.1`.
     fn start_heartbeat_thread(
         &self,
         box_id: u32,
         heartbeat_path: PathBuf,
+        lock: (PathBuf, (u64, u64)),
+        failure: Arc<OnceLock<String>>,
     ) -> LockResult<(JoinHandle<()>, Sender<()>)> {
         let (shutdown_tx, shutdown_rx) = crossbeam_channel::bounded(1);
         let interval = self.heartbeat_interval;
@@ -412,6 +518,7 @@ impl RustboxLockManager {
                 Ok(f) => f,
                 Err(e) => {
                     error!("Failed to open heartbeat file for box {}: {}", box_id, e);
+                    let _ = failure.set(format!("cannot open heartbeat file: {}", e));
                     return;
                 }
             };
@@ -422,6 +529,12 @@ impl RustboxLockManager {
                     break;
                 }
 
+                if path_id(&lock.0) != Some(lock.1) {
+                    error!("Lock for box {} was taken over by another process", box_id);
+                    let _ = failure.set("taken over by another process".to_string());
+                    break;
+                }
+
                 let timestamp = SystemTime::now()
                     .duration_since(UNIX_EPOCH)
                     .unwrap()
@@ -429,17 +542,20 @@ impl RustboxLockManager {
 
                 if let Err(e) = writeln!(heartbeat_file, "{}", timestamp) {
                     warn!("Failed to write heartbeat for box {}: {}", box_id, e);
+                    let _ = failure.set(format!("cannot write heartbeat: {}", e));
                     break;
                 }
 
                 if let Err(e) = heartbeat_file.flush() {
                     warn!("Failed to flush heartbeat for box {}: {}", box_id, e);
+                    let _ = failure.set(format!("cannot write heartbeat: {}", e));
                     break;
                 }
 
                 // Reset file position for next write
                 if let Err(e) = heartbeat_file.seek(SeekFrom::Start(0)) {
                     warn!("Failed to seek heartbeat file for box {}: {}", box_id, e);
+                    let _ = failure.set(format!("cannot write heartbeat: {}", e));
                     break;
                 }
 
@@ -453,55 +569,82 @@ impl RustboxLockManager {
         Ok((handle, shutdown_tx))
     }
 
-    /// Clean up stale lock if needed
-    fn cleanup_stale_lock_if_needed(&self, box_id: u32) -> LockResult<()> {
-        let lock_path = self.lock_dir.join(format!("box-{}.lock", box_id));
-        let heartbeat_path = self.lock_dir.join(format!("box-{}.heartbeat", box_id));
-
-        // If no lock file exists, nothing to clean
-        if !lock_path.exists() {
-            return Ok(());
-        }
-
-        // Try to read lock info
-        let lock_content = std::fs::read_to_string(&lock_path)?;
-        let lock_info: LockInfo = serde_json::from_str(lock_content.lines().next().unwrap_or(""))
-            .map_err(|_| LockError::CorruptedLock {
-            box_id,
-            details: "Invalid lock file format".to_string(),
-        })?;
+    /// Take over the held lock of a box whose holder died or hung
+    ///
+    /// The flock alone cannot tell: processes a dead holder forked keep its lock
+    /// file open, and a hung holder never releases it. If the recorded owner is
+    /// gone, or its heartbeat is older than `stale_timeout`, its lock and
+    /// heartbeat files are unlinked so the next attempt locks a fresh file; a
+    /// holder still alive finds out from `BoxLockGuard::verify`. Returns
+    /// whether the lock was taken over.
+    fn take_over_stale_lock(&self, box_id: u32, lock_path: &Path, heartbeat_path: &Path) -> bool {
+        let Ok(lock_file) = File::open(lock_path) else {
+            return false;
+        };
+        let Some(content) = read_lock_file(&lock_file) else {
+            return false;
+        };
+        // An unparsable lock file is still being written by its new holder
+        let Ok(lock_info) = serde_json::from_str::<LockInfo>(content.lines().next().unwrap_or(""))
+        else {
+            return false;
+        };
 
-        // Check if the owning process is still alive
-        if self.is_process_alive(lock_info.pid) {
-            // Process exists, check heartbeat
-            if let Ok(last_heartbeat) = self.get_last_heartbeat(&heartbeat_path) {
-                let heartbeat_age = SystemTime::now()
-                    .duration_since(last_heartbeat)
-                    .unwrap_or(Duration::from_secs(999));
-
-                if heartbeat_age < self.stale_timeout {
-                    // Lock is active and healthy
-                    return Err(LockError::Busy {
-                        box_id,
-                        owner_pid: Some(lock_info.pid),
-                    });
-                }
+        let reason = if !self.is_process_alive(lock_info.pid) {
+            format!("owner PID {} is dead", lock_info.pid)
+        } else {
+            // A heartbeat file older than the lock is left over from a crash
+            let last_heartbeat = self
+                .get_last_heartbeat(heartbeat_path)
+                .map_or(lock_info.created_at, |heartbeat| {
+                    heartbeat.max(lock_info.created_at)
+                });
+            let age = SystemTime::now()
+                .duration_since(last_heartbeat)
+                .unwrap_or_default();
+            if age < self.stale_timeout {
+                return false;
             }
-        }
-
-        // Lock is stale - clean it up
-        warn!(
-            "Cleaning up stale lock for box {} (pid {} not responding)",
-            box_id, lock_info.pid
-        );
-        self.force_cleanup_box_resources(box_id)?;
+            format!(
+                "owner PID {} sent no heartbeat for {:.1}s",
+                lock_info.pid,
+                age.as_secs_f64()
+            )
+        };
 
-        // Remove lock files
-        let _ = std::fs::remove_file(&lock_path);
-        let _ = std::fs::remove_file(&heartbeat_path);
+        // Only unlink the lock just inspected, not one a new holder took since
+        let inspected = lock_file.metadata().ok().map(|metadata| file_id(&metadata));
+        if inspected.is_none()
+            || inspected != path_id(lock_path)
+            || read_lock_file(&lock_file) != Some(content)
+        {
+            return false;
+        }
+        let _ = std::fs::remove_file(lock_path);
+        let _ = std::fs::remove_file(heartbeat_path);
 
+        warn!("Took over lock for box {}: {}", box_id, reason);
+        events::stale_lock_taken_over(box_id, lock_info.pid, reason);
         self.record_cleanup();
-        Ok(())
+        true
+    }
+
+    /// Remove a box's lock and heartbeat files unless the lock is held
+    ///
+    /// The flock is held for as long as the box is in use, so a lock file that
+    /// can be locked is stale whatever its heartbeat says, and one that cannot is
+    /// not, even before its holder's first heartbeat. The files are unlinked
+    /// while locked, which `try_acquire_immediate` relies on.
+    fn remove_unheld_lock(lock_path: &Path, heartbeat_path: &Path) -> bool {
+        let Ok(lock_file) = File::open(lock_path) else {
+            return false;
+        };
+        if unsafe { flock(lock_file.as_raw_fd(), LOCK_EX | LOCK_NB) } != 0 {
+            return false;
+        }
+        let _ = std::fs::remove_file(lock_path);
+        let _ = std::fs::remove_file(heartbeat_path);
+        true
     }
 
     /// Check if process is alive
@@ -509,30 +652,9 @@ impl RustboxLockManager {
         std::path::Path::new(&format!("/proc/{}", pid)).exists()
     }
 
-    /// Get last heartbeat time
+    /// Last time the heartbeat file was written
     fn get_last_heartbeat(&self, heartbeat_path: &Path) -> LockResult<SystemTime> {
-        let content = std::fs::read_to_string(heartbeat_path)?;
-        let timestamp_str = content.trim();
-        let timestamp = timestamp_str
-            .parse::<u64>()
-            .map_err(|_| LockError::CorruptedLock {
-                box_id: 0,
-                details: "Invalid heartbeat timestamp".to_string(),
-            })?;
-
-        Ok(UNIX_EPOCH + Duration::from_secs(timestamp))
-    }
-
-    /// Force cleanup box resources
-    fn force_cleanup_box_resources(&self, box_id: u32) -> LockResult<()> {
-        warn!("Force cleaning up resources for box {}", box_id);
-        // In a real implementation, this would cleanup:
-        // - Kill processes in the box
-        // - Unmount filesystems
-        // - Remove cgroups
-        // - Clean up network namespaces
-        // For now, we'll just log it
-        Ok(())
+        Ok(std::fs::metadata(heartbeat_path)?.modified()?)
     }
 
     /// Get current lock owner info
@@ -568,12 +690,11 @@ impl RustboxLockManager {
                     if let Some(box_id_str) = stem.strip_prefix("box-") {
                         if let Ok(box_id) = box_id_str.parse::<u32>() {
                             // Check if this lock is stale
-                            if Self::is_lock_stale(&path, stale_timeout).unwrap_or(false) {
-                                warn!("Background cleanup: removing stale lock for box {}", box_id);
-                                let _ = std::fs::remove_file(&path);
-                                let heartbeat_path =
-                                    lock_dir.join(format!("box-{}.heartbeat", box_id));
-                                let _ = std::fs::remove_file(heartbeat_path);
+                            let heartbeat_path = lock_dir.join(format!("box-{}.heartbeat", box_id));
+                            if Self::is_lock_stale(&path, stale_timeout).unwrap_or(false)
+                                && Self::remove_unheld_lock(&path, &heartbeat_path)
+                            {
+                                warn!("Background cleanup: removed stale lock for box {}", box_id);
                                 cleaned_count += 1;
                             }
                         }
@@ -720,6 +841,7 @@ impl RustboxLockManager {
                 LockError::CorruptedLock { .. } => "corrupted_lock",
                 LockError::SystemError { .. } => "system_error",
                 LockError::NotInitialized => "not_initialized",
+                LockError::HeartbeatFailed { .. } => "heartbeat_failed",
             };
             *metrics
                 .errors_by_type
@@ -813,11 +935,46 @@ impl BoxLockGuard {
         }
         0
     }
+
+    /// Fail if the box may no longer be ours: the lock was taken over after
+    /// a missed heartbeat, or the heartbeat stopped and soon will be
+    pub fn verify(&self) -> LockResult<()> {
+        let Some(lock) = &self.lock else {
+            return Ok(());
+        };
+        let Ok(lock) = lock.lock() else {
+            return Ok(());
+        };
+        let details = if let Some(failure) = lock.heartbeat_failure.get() {
+            failure.clone()
+        } else if lock.lock_file.metadata().ok().map(|m| file_id(&m)) != path_id(&lock.lock_path) {
+            "taken over by another process".to_string()
+        } else {
+            return Ok(());
+        };
+        Err(LockError::HeartbeatFailed {
+            box_id: lock.box_id,
+            details,
+        })
+    }
 }
 
 impl Drop for BoxLockGuard {
     fn drop(&mut self) {
         if let Some(lock_arc) = self.lock.take() {
+            // Unlinked while still locked, so the next holder's fresh file is
+            // never removed from under it (see `try_acquire_immediate`); after a
+            // takeover they belong to the new holder and are left alone
+            if let Ok(lock) = lock_arc.lock() {
+                let ours = lock.lock_file.metadata().ok().map(|m| file_id(&m));
+                if ours.is_some() && ours == path_id(&lock.lock_path) {
+                    let _ = std::fs::remove_file(&lock.lock_path);
+                    let _ = std::fs::remove_file(
+                        lock.lock_path
+                            .with_file_name(format!("box-{}.heartbeat", lock.box_id)),
+                    );
+                }
+            }
             // First, signal shutdown and extract the thread handle
             let handle_option = {
                 if let Ok(mut lock) = lock_arc.lock() {
@@ -871,14 +1028,29 @@ impl Drop for BoxLockGuard {
     }
 }
 
-impl Drop for DropGuard {
+impl BoxSetGuard {
+    /// Locked box ids in acquisition (ascending) order
+    pub fn box_ids(&self) -> Vec<u32> {
+        self.guards.iter().map(BoxLockGuard::box_id).collect()
+    }
+
+    /// Split into the individual box guards, e.g. to hand each to its `Isolate`
+    pub fn into_guards(mut self) -> Vec<BoxLockGuard> {
+        std::mem::take(&mut self.guards)
+    }
+}
+
+impl Drop for BoxSetGuard {
     fn drop(&mut self) {
-        // Clean up lock files when guard is dropped
-        let lock_path = self.lock_dir.join(format!("box-{}.lock", self.box_id));
-        let heartbeat_path = self.lock_dir.join(format!("box-{}.heartbeat", self.box_id));
+        while let Some(guard) = self.guards.pop() {
+            drop(guard);
+        }
+    }
+}
 
-        let _ = std::fs::remove_file(lock_path);
-        let _ = std::fs::remove_file(heartbeat_path);
+impl Drop for DropGuard {
+    fn drop(&mut self) {
+        // The lock files were removed by `BoxLockGuard` while still locked
 
         // Decrement the active lock counter
         let prev_count = self.active_locks_counter.fetch_sub(1, Ordering::Release);
@@ -901,7 +1073,7 @@ pub fn init_lock_manager() -> LockResult<()> {
     manager.start_cleanup_thread()?;
 
     GLOBAL_LOCK_MANAGER
-        .set(Arc::new(Mutex::new(manager)))
+        .set(manager)
         .map_err(|_| LockError::SystemError {
             message: "Lock manager already initialized".to_string(),
         })?;
@@ -910,41 +1082,123 @@ pub fn init_lock_manager() -> LockResult<()> {
     Ok(())
 }
 
+/// Keep box locks in `lock_dir` instead of the first writable default
+///
+/// Must be called before `init_lock_manager`.
+pub fn set_lock_directory(lock_dir: PathBuf) -> LockResult<()> {
+    LOCK_DIR_OVERRIDE
+        .set(lock_dir)
+        .map_err(|_| LockError::SystemError {
+            message: "Lock directory already set".to_string(),
+        })
+}
+
 /// Acquire a box lock with default 30 second timeout
 pub fn acquire_box_lock(box_id: u32) -> LockResult<BoxLockGuard> {
     acquire_box_lock_with_timeout(box_id, Duration::from_secs(30))
 }
 
-/// Acquire a box lock with custom timeout
+/// Acquire a box lock, retrying with exponential backoff for up to `timeout`
 pub fn acquire_box_lock_with_timeout(box_id: u32, timeout: Duration) -> LockResult<BoxLockGuard> {
     let manager = GLOBAL_LOCK_MANAGER.get().ok_or(LockError::NotInitialized)?;
+    manager.acquire_lock(box_id, timeout)
+}
 
-    let manager = manager.lock().map_err(|_| LockError::SystemError {
-        message: "Failed to acquire lock manager mutex".to_string(),
-    })?;
+/// Lock a box only if nobody holds it; `None` if another holder has it
+pub fn try_acquire_box_lock(box_id: u32) -> LockResult<Option<BoxLockGuard>> {
+    match acquire_box_lock_with_timeout(box_id, Duration::ZERO) {
+        Ok(guard) => Ok(Some(guard)),
+        Err(LockError::Busy { .. } | LockError::Timeout { .. }) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
 
-    manager.acquire_lock(box_id, timeout)
+/// Acquire locks on several boxes at once, all or nothing (see `RustboxLockManager::acquire_set`)
+pub fn acquire_box_set(box_ids: &[u32], timeout: Duration) -> LockResult<BoxSetGuard> {
+    let manager = GLOBAL_LOCK_MANAGER.get().ok_or(LockError::NotInitialized)?;
+    manager.acquire_set(box_ids, timeout)
+}
+
+/// Directory holding the box lock files, once the lock manager is initialized
+pub fn lock_directory() -> Option<PathBuf> {
+    GLOBAL_LOCK_MANAGER
+        .get()
+        .map(|manager| manager.lock_dir.clone())
+}
+
+/// Whether the lock file at `lock_path` is held by a live process
+///
+/// A crashed holder never unlinks its lock file, but the kernel drops its
+/// flock, so only the flock tells a box in use from an abandoned one.
+pub fn lock_file_held(lock_path: &Path) -> bool {
+    let Ok(lock_file) = File::open(lock_path) else {
+        return false;
+    };
+    unsafe { flock(lock_file.as_raw_fd(), LOCK_EX | LOCK_NB) != 0 }
+}
+
+/// Whether a box's lock is held by a live process
+pub fn box_lock_held(box_id: u32) -> bool {
+    lock_directory().is_some_and(|dir| lock_file_held(&dir.join(format!("box-{}.lock", box_id))))
+}
+
+/// Boxes whose lock holder died, with the pid its lock file records
+///
+/// Either nobody holds the lock any more, or only processes the holder forked
+/// before it died do: the remains of a run it was supervising.
+pub fn abandoned_box_locks() -> Vec<(u32, Option<u32>)> {
+    let Some(lock_dir) = lock_directory() else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(&lock_dir) else {
+        return Vec::new();
+    };
+    let mut abandoned: Vec<_> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let box_id = name
+                .to_str()?
+                .strip_prefix("box-")?
+                .strip_suffix(".lock")?
+                .parse()
+                .ok()?;
+            let path = entry.path();
+            let owner = std::fs::read_to_string(&path).ok().and_then(|content| {
+                serde_json::from_str::<LockInfo>(content.lines().next()?)
+                    .ok()
+                    .map(|info| info.pid)
+            });
+            let owner_alive = owner.is_some_and(|pid| unsafe { libc::kill(pid as i32, 0) } == 0);
+            if lock_file_held(&path) && (owner.is_none() || owner_alive) {
+                return None;
+            }
+            Some((box_id, owner))
+        })
+        .collect();
+    abandoned.sort_unstable();
+    abandoned
+}
+
+/// Remove a box's lock files unless the lock is held; true if removed
+pub fn remove_abandoned_lock(box_id: u32) -> bool {
+    lock_directory().is_some_and(|dir| {
+        RustboxLockManager::remove_unheld_lock(
+            &dir.join(format!("box-{}.lock", box_id)),
+            &dir.join(format!("box-{}.heartbeat", box_id)),
+        )
+    })
 }
 
 /// Get lock manager health status
 pub fn get_lock_health() -> LockResult<LockManagerHealth> {
     let manager = GLOBAL_LOCK_MANAGER.get().ok_or(LockError::NotInitialized)?;
-
-    let manager = manager.lock().map_err(|_| LockError::SystemError {
-        message: "Failed to acquire lock manager mutex".to_string(),
-    })?;
-
     Ok(manager.health_check())
 }
 
 /// Export metrics in Prometheus format
 pub fn get_lock_metrics() -> LockResult<String> {
     let manager = GLOBAL_LOCK_MANAGER.get().ok_or(LockError::NotInitialized)?;
-
-    let manager = manager.lock().map_err(|_| LockError::SystemError {
-        message: "Failed to acquire lock manager mutex".to_string(),
-    })?;
-
     Ok(manager.export_metrics())
 }
 
@@ -975,3 +1229,23 @@ where
     // Lock is automatically released when file goes out of scope
     operation()
 }
+
+/// Device and inode of a file, to tell a lock file from its replacement
+fn file_id(metadata: &std::fs::Metadata) -> (u64, u64) {
+    (metadata.dev(), metadata.ino())
+}
+
+/// `file_id` of whatever is at `path` now
+fn path_id(path: &Path) -> Option<(u64, u64)> {
+    std::fs::metadata(path)
+        .ok()
+        .map(|metadata| file_id(&metadata))
+}
+
+/// Whole contents of an open lock file
+fn read_lock_file(mut file: &File) -> Option<String> {
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut content).ok()?;
+    Some(content)
+}
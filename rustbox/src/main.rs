@@ -1,5 +1,5 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 /// rustbox: Secure Process Isolation and Resource Control System
 ///
 /// A modern, Rust-based implementation inspired by IOI Isolate, designed for secure
@@ -22,55 +22,195 @@ use clap::{Parser, Subcommand};
 /// rustbox run --box-id 0 --mem 128 --time 10 /usr/bin/python3 solution.py
 /// rustbox cleanup --box-id 0
 /// ```
+use rustbox::config::LanguageRegistry;
+use rustbox::isolate::CleanupPolicy;
+use rustbox::report::ReportProfile;
 use rustbox::*;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+/// How long a run waits for a box another process is using, unless --wait says otherwise
+const DEFAULT_LOCK_WAIT: Duration = Duration::from_secs(30);
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
+#[command(author, version, about, long_about = None, disable_version_flag = true)]
+#[command(arg_required_else_help = true)]
 struct Cli {
+    /// Print version and build information
+    #[arg(short = 'V', long)]
+    version: bool,
+    /// With --version: print build information as JSON
+    #[arg(long, requires = "version")]
+    json: bool,
+    /// Fix inconsistent state found by the startup check (registry, locks, leftover cgroups)
+    #[arg(long, global = true)]
+    repair: bool,
     #[command(subcommand)]
-    command: Commands,
+    command: Option<Commands>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Initialize a new sandbox environment
     Init {
-        /// Box ID for the sandbox
-        #[arg(long)]
-        box_id: u32,
+        /// Box ID for the sandbox, or "auto" for a free one from `isolate.auto_box_ids`
+        #[arg(long, value_name = "ID|auto", value_parser = parse_init_box_id)]
+        box_id: InitBoxId,
+        /// Mount the box workdir as a tmpfs of this many MB, discarded on cleanup
+        #[arg(long, value_name = "MB")]
+        workdir_tmpfs: Option<u64>,
+        /// Mount the box workdir as a disk image of this many MB, discarded on cleanup
+        #[arg(long, value_name = "MB")]
+        disk_quota: Option<u64>,
     },
     /// Run a command in the sandbox
+    #[command(group(clap::ArgGroup::new("confinement").args(["minimal_visibility", "landlock"])))]
     Run {
         /// Box ID for the sandbox
         #[arg(long)]
         box_id: u32,
+        /// Seconds to wait for a box another process is using (default 30, 0 fails at once)
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        wait: Option<Duration>,
         /// Memory limit in MB
         #[arg(long)]
         mem: Option<u64>,
-        /// Time limit in seconds
+        /// Soft memory limit in MB: reclaimed first instead of killed until --mem is reached
         #[arg(long)]
-        time: Option<u64>,
-        /// CPU limit in seconds
+        mem_high: Option<u64>,
+        /// Swap in MB the program may use on top of --mem (none by default)
+        #[arg(long, value_name = "MB", requires = "mem")]
+        swap: Option<u64>,
+        /// Kernel memory limit in MB (pipes, epoll watches, sockets); defaults to --mem
         #[arg(long)]
-        cpu: Option<u64>,
+        kmem: Option<u64>,
+        /// CPU bandwidth in percent of one core (50 = half a core); throttles, never kills
+        #[arg(long, value_name = "PERCENT")]
+        cpu_quota: Option<u32>,
+        /// Time limit in seconds
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        time: Option<Duration>,
+        /// CPU limit in seconds
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        cpu: Option<Duration>,
         /// Wall clock time limit in seconds
-        #[arg(long)]
-        wall_time: Option<u64>,
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        wall_time: Option<Duration>,
+        /// Extra time in seconds granted past the time limits before killing
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        extra_time: Option<Duration>,
         /// Maximum number of processes
         #[arg(long)]
         processes: Option<u32>,
-        /// Directory bindings (format: source=target:options)
+        /// Maximum number of exec calls, counting the one starting the program
+        #[arg(long)]
+        max_execs: Option<u32>,
+        /// Combined stdout and stderr limit in KB
+        #[arg(long, value_name = "KB")]
+        output_limit: Option<u64>,
+        /// stdout limit in KB; the result keeps stdout cut at it
+        #[arg(long, value_name = "KB")]
+        stdout_limit: Option<u64>,
+        /// stderr limit in KB; the result keeps stderr cut at it
+        #[arg(long, value_name = "KB")]
+        stderr_limit: Option<u64>,
+        /// Forward the program's stdout and stderr live, before the result
+        #[arg(long)]
+        stream: bool,
+        /// Stop forwarding a stream after this many KB (it is still collected)
+        #[arg(long, value_name = "KB", requires = "stream")]
+        stream_limit: Option<u64>,
+        /// Read stdin from this file in the box (relative to the box workdir)
+        #[arg(long, value_name = "PATH")]
+        stdin_file: Option<std::path::PathBuf>,
+        /// Write stdout to this file in the box, capped at --stdout-limit/--output-limit
+        #[arg(long, value_name = "PATH")]
+        stdout_file: Option<std::path::PathBuf>,
+        /// Write stderr to this file in the box, capped at --stderr-limit/--output-limit
+        #[arg(long, value_name = "PATH")]
+        stderr_file: Option<std::path::PathBuf>,
+        /// Run the program on a pseudo-terminal; stdout holds the raw terminal output
+        #[arg(long)]
+        tty: bool,
+        /// Terminal window size with --tty (default 80x24)
+        #[arg(long, value_name = "COLSxROWS", requires = "tty")]
+        tty_size: Option<rustbox::types::TtySize>,
+        /// Directory bindings (format: source=target:options, options: rw, ro, noexec, maybe, tmp,
+        /// uidmap=HOST-SANDBOX, gidmap=HOST-SANDBOX)
         #[arg(long = "dir", value_name = "BINDING")]
         directory_bindings: Vec<String>,
+        /// Host Unix socket bindings (format: host.sock=/sandbox/path.sock)
+        #[arg(long = "bind-socket", value_name = "SOCKET")]
+        socket_bindings: Vec<String>,
+        /// Hide the host filesystem except language runtimes and the workdir (no chroot needed)
+        #[arg(long)]
+        minimal_visibility: bool,
+        /// Confine the program to the workdir and read-only runtimes with Landlock (no root needed)
+        #[arg(long)]
+        landlock: bool,
+        /// Extra host path shown read-only with --minimal-visibility or --landlock; repeat for more
+        #[arg(long = "visible", value_name = "PATH", requires = "confinement")]
+        visible_paths: Vec<std::path::PathBuf>,
+        /// Run on an overlay of this prebuilt root filesystem directory (writes go to a tmpfs)
+        #[arg(long, value_name = "DIR", conflicts_with = "confinement")]
+        rootfs: Option<std::path::PathBuf>,
+        /// Size in MB of the writable layer over --rootfs
+        #[arg(long, value_name = "MB", requires = "rootfs")]
+        rootfs_upper: Option<u64>,
+        /// Network: none (default), loopback (localhost only) or full (host network)
+        #[arg(long, value_name = "MODE")]
+        network: Option<rustbox::types::NetworkMode>,
+        /// Let the program connect to IPv4 ADDRESS/PREFIX[:PORT[-PORT]]; repeat for more
+        #[arg(long = "egress", value_name = "RULE")]
+        egress_rules: Vec<rustbox::egress::EgressRule>,
+        /// Set VAR=VALUE for the program, or pass the host's VAR through; repeat for more
+        #[arg(long = "env", short = 'E', value_name = "VAR[=VALUE]")]
+        env: Vec<rustbox::types::EnvRule>,
+        /// Pass the host's whole environment through, not just config.json's preserve_env
+        #[arg(long)]
+        full_env: bool,
+        /// Run the program on these CPUs only (cpuset, else sched_setaffinity), e.g. 2,3
+        #[arg(long, value_name = "LIST", value_delimiter = ',')]
+        cpus: Option<Vec<usize>>,
+        /// Record every write under the sandbox (fanotify, requires root)
+        #[arg(long)]
+        audit_writes: bool,
+        /// Report the largest single-process peak RSS next to the cgroup peak
+        #[arg(long)]
+        process_memory: bool,
+        /// Run RISC-V/ARM/other foreign ELF programs under qemu-user (binfmt or qemu-<arch>-static)
+        #[arg(long)]
+        emulate: bool,
+        /// Audit-only seccomp run: record every syscall and write a profile allowing them to FILE
+        #[arg(long, value_name = "FILE")]
+        syscall_profile: Option<std::path::PathBuf>,
+        /// Include build information (commit, features) in the result
+        #[arg(long)]
+        build_info: bool,
+        /// Kill early with TLE once past the CPU limit while CPU-bound and silent for this many seconds
+        #[arg(long, value_name = "SECS", conflicts_with = "tty")]
+        adaptive_timeout: Option<u64>,
+        /// Result format: json, cms for an isolate-style meta report, or judge0
+        #[arg(long, default_value = "json")]
+        report_profile: ReportProfile,
+        /// Also write an isolate-style meta file (time, time-wall, max-rss, status, ...) to FILE
+        #[arg(long, value_name = "FILE")]
+        meta: Option<std::path::PathBuf>,
+        /// Keep the sandbox after execution instead of cleaning it up
+        #[arg(long)]
+        keep: bool,
         /// Command and arguments to execute
         command: Vec<String>,
     },
     /// Execute code directly from string input (Judge0-style)
+    #[command(group(clap::ArgGroup::new("confinement").args(["minimal_visibility", "landlock"])))]
     ExecuteCode {
         /// Box ID for the sandbox
         #[arg(long)]
         box_id: u32,
+        /// Seconds to wait for a box another process is using (default 30, 0 fails at once)
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        wait: Option<Duration>,
         /// Programming language (python, c and java)
         #[arg(long)]
         language: String,
@@ -80,41 +220,715 @@ enum Commands {
         /// Input data to pass to stdin
         #[arg(long)]
         stdin: Option<String>,
+        /// Test data reference to pass to stdin (path, file://, http(s):// or s3://)
+        #[arg(long, conflicts_with = "stdin")]
+        stdin_ref: Option<String>,
+        /// SHA-256 of the --stdin-ref data; required for remote references
+        #[arg(long, requires = "stdin_ref")]
+        stdin_sha256: Option<String>,
         /// Memory limit in MB
         #[arg(long)]
         mem: Option<u64>,
-        /// Time limit in seconds
+        /// Soft memory limit in MB: reclaimed first instead of killed until --mem is reached
         #[arg(long)]
-        time: Option<u64>,
-        /// CPU limit in seconds
+        mem_high: Option<u64>,
+        /// Swap in MB the program may use on top of --mem (none by default)
+        #[arg(long, value_name = "MB", requires = "mem")]
+        swap: Option<u64>,
+        /// Kernel memory limit in MB (pipes, epoll watches, sockets); defaults to --mem
         #[arg(long)]
-        cpu: Option<u64>,
+        kmem: Option<u64>,
+        /// CPU bandwidth in percent of one core (50 = half a core); throttles, never kills
+        #[arg(long, value_name = "PERCENT")]
+        cpu_quota: Option<u32>,
+        /// Time limit in seconds
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        time: Option<Duration>,
+        /// CPU limit in seconds
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        cpu: Option<Duration>,
         /// Wall clock time limit in seconds
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        wall_time: Option<Duration>,
+        /// Extra time in seconds granted past the time limits before killing
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        extra_time: Option<Duration>,
+        /// Maximum number of processes
+        #[arg(long)]
+        processes: Option<u32>,
+        /// Maximum number of exec calls, counting the one starting the program
+        #[arg(long)]
+        max_execs: Option<u32>,
+        /// Combined stdout and stderr limit in KB
+        #[arg(long, value_name = "KB")]
+        output_limit: Option<u64>,
+        /// stdout limit in KB; the result keeps stdout cut at it
+        #[arg(long, value_name = "KB")]
+        stdout_limit: Option<u64>,
+        /// stderr limit in KB; the result keeps stderr cut at it
+        #[arg(long, value_name = "KB")]
+        stderr_limit: Option<u64>,
+        /// Compile time limit in seconds (CPU and wall clock); defaults to config.json
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        compile_time: Option<Duration>,
+        /// Compile memory limit in MB; defaults to config.json
+        #[arg(long, value_name = "MB")]
+        compile_mem: Option<u64>,
+        /// Maximum number of processes while compiling
+        #[arg(long)]
+        compile_processes: Option<u32>,
+        /// File mode creation mask in octal (e.g. 027)
+        #[arg(long, value_parser = parse_umask)]
+        umask: Option<u32>,
+        /// Hide the host filesystem except language runtimes and the workdir (no chroot needed)
+        #[arg(long)]
+        minimal_visibility: bool,
+        /// Confine the program to the workdir and read-only runtimes with Landlock (no root needed)
+        #[arg(long)]
+        landlock: bool,
+        /// Extra host path shown read-only with --minimal-visibility or --landlock; repeat for more
+        #[arg(long = "visible", value_name = "PATH", requires = "confinement")]
+        visible_paths: Vec<std::path::PathBuf>,
+        /// Run on an overlay of this prebuilt root filesystem directory (writes go to a tmpfs)
+        #[arg(long, value_name = "DIR", conflicts_with = "confinement")]
+        rootfs: Option<std::path::PathBuf>,
+        /// Size in MB of the writable layer over --rootfs
+        #[arg(long, value_name = "MB", requires = "rootfs")]
+        rootfs_upper: Option<u64>,
+        /// Network: none (default), loopback (localhost only) or full (host network)
+        #[arg(long, value_name = "MODE")]
+        network: Option<rustbox::types::NetworkMode>,
+        /// Let the program connect to IPv4 ADDRESS/PREFIX[:PORT[-PORT]]; repeat for more
+        #[arg(long = "egress", value_name = "RULE")]
+        egress_rules: Vec<rustbox::egress::EgressRule>,
+        /// Set VAR=VALUE for the program, or pass the host's VAR through; repeat for more
+        #[arg(long = "env", short = 'E', value_name = "VAR[=VALUE]")]
+        env: Vec<rustbox::types::EnvRule>,
+        /// Pass the host's whole environment through, not just config.json's preserve_env
+        #[arg(long)]
+        full_env: bool,
+        /// Run the program on these CPUs only (cpuset, else sched_setaffinity), e.g. 2,3
+        #[arg(long, value_name = "LIST", value_delimiter = ',')]
+        cpus: Option<Vec<usize>>,
+        /// Record every write under the sandbox (fanotify, requires root)
         #[arg(long)]
-        wall_time: Option<u64>,
+        audit_writes: bool,
+        /// Report the largest single-process peak RSS next to the cgroup peak
+        #[arg(long)]
+        process_memory: bool,
+        /// Audit-only seccomp run: record every syscall and write a profile allowing them to FILE
+        #[arg(long, value_name = "FILE")]
+        syscall_profile: Option<std::path::PathBuf>,
+        /// Include build information (commit, features) in the result
+        #[arg(long)]
+        build_info: bool,
+        /// Kill early with TLE once past the CPU limit while CPU-bound and silent for this many seconds
+        #[arg(long, value_name = "SECS")]
+        adaptive_timeout: Option<u64>,
+        /// Result format: json, cms for an isolate-style meta report, or judge0
+        #[arg(long, default_value = "json")]
+        report_profile: ReportProfile,
+        /// Also write an isolate-style meta file (time, time-wall, max-rss, status, ...) to FILE
+        #[arg(long, value_name = "FILE")]
+        meta: Option<std::path::PathBuf>,
+        /// Clean up the sandbox after execution
+        #[arg(long)]
+        auto_cleanup: bool,
+        /// Reuse the stored result of an identical submission (same code, stdin and limits)
+        #[arg(long)]
+        cache: bool,
+        /// Expected output to judge the program's stdout against (path or test data reference)
+        #[arg(long, requires = "comparator")]
+        expected_output: Option<String>,
+        /// SHA-256 of the --expected-output data; required for remote references
+        #[arg(long, requires = "expected_output")]
+        expected_sha256: Option<String>,
+        /// Comparator command (whitespace separated); receives expected and actual output paths
+        #[arg(long, requires = "expected_output")]
+        comparator: Option<String>,
+        /// Strict mode: require root privileges and fail if security features unavailable
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Compile code once and run it against several stdin test cases
+    RunBatch {
+        /// Box ID for the sandbox
+        #[arg(long)]
+        box_id: u32,
+        /// Seconds to wait for a box another process is using (default 30, 0 fails at once)
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        wait: Option<Duration>,
+        /// Programming language (python, c and java)
+        #[arg(long)]
+        language: String,
+        /// Source code as string
+        #[arg(long)]
+        code: String,
+        /// File whose contents are one test case's stdin; repeat for each test
+        #[arg(long = "input", value_name = "FILE", required = true)]
+        inputs: Vec<std::path::PathBuf>,
+        /// Memory limit in MB
+        #[arg(long)]
+        mem: Option<u64>,
+        /// CPU limit in seconds, per test
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        cpu: Option<Duration>,
+        /// Wall clock time limit in seconds, per test
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        wall_time: Option<Duration>,
+        /// Extra time in seconds granted past the time limits before killing
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        extra_time: Option<Duration>,
         /// Maximum number of processes
         #[arg(long)]
         processes: Option<u32>,
+        /// Maximum number of exec calls per test, counting the one starting the program
+        #[arg(long)]
+        max_execs: Option<u32>,
+        /// Untimed runs on the first input before the tests (default: the language's config)
+        #[arg(long)]
+        warmup: Option<u32>,
+        /// Clean up the sandbox after execution
+        #[arg(long)]
+        auto_cleanup: bool,
         /// Strict mode: require root privileges and fail if security features unavailable
         #[arg(long)]
         strict: bool,
     },
+    /// Run a command against an interactor, each reading the other's output
+    RunInteractive {
+        /// Box ID for the sandbox
+        #[arg(long)]
+        box_id: u32,
+        /// Seconds to wait for a box another process is using (default 30, 0 fails at once)
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        wait: Option<Duration>,
+        /// Interactor command run on the host; exits 0 (AC), 1 (WA) or 2 (PE)
+        #[arg(long)]
+        interactor: String,
+        /// Memory limit in MB
+        #[arg(long)]
+        mem: Option<u64>,
+        /// CPU limit in seconds
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        cpu: Option<Duration>,
+        /// Wall clock time limit in seconds
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        wall_time: Option<Duration>,
+        /// Maximum number of processes
+        #[arg(long)]
+        processes: Option<u32>,
+        /// Keep the sandbox after execution instead of cleaning it up
+        #[arg(long)]
+        keep: bool,
+        /// Command and arguments to execute
+        command: Vec<String>,
+    },
     /// Clean up sandbox environment
     Cleanup {
         /// Box ID for the sandbox
         #[arg(long)]
         box_id: u32,
     },
+    /// Remove sandboxes left behind by crashed workers, and idle ones
+    Gc {
+        /// Also remove sandboxes not used for longer than this (e.g. 1h)
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        older_than: Option<chrono::Duration>,
+        /// Only list what would be removed
+        #[arg(long)]
+        dry_run: bool,
+        /// Print the report as JSON on stdout
+        #[arg(long)]
+        json: bool,
+    },
+    /// List every sandbox with its state, owner, age and limits
+    List {
+        /// Print the sandboxes as JSON on stdout
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print a sandbox's persisted configuration and state as JSON
+    Inspect {
+        /// Box ID for the sandbox
+        #[arg(long)]
+        box_id: u32,
+    },
+    /// Show the resource usage of the execution running in a sandbox
+    Stats {
+        /// Box ID for the sandbox
+        #[arg(long)]
+        box_id: u32,
+    },
+    /// Signal every process of the execution running in a sandbox
+    Kill {
+        /// Box ID for the sandbox
+        #[arg(long)]
+        box_id: u32,
+        /// Signal to send, by name (TERM, SIGTERM) or number
+        #[arg(long, default_value = "KILL", value_parser = parse_signal)]
+        signal: i32,
+    },
+    /// Freeze the execution running in a sandbox until `resume`
+    Pause {
+        /// Box ID for the sandbox
+        #[arg(long)]
+        box_id: u32,
+    },
+    /// Resume an execution paused with `pause`
+    Resume {
+        /// Box ID for the sandbox
+        #[arg(long)]
+        box_id: u32,
+    },
+    /// Freeze the program running in a sandbox to a directory with CRIU
+    #[cfg(feature = "criu")]
+    Checkpoint {
+        /// Box ID for the sandbox
+        #[arg(long)]
+        box_id: u32,
+        /// Directory the CRIU images are written to
+        #[arg(long)]
+        dir: std::path::PathBuf,
+        /// Keep the program running after dumping it
+        #[arg(long)]
+        leave_running: bool,
+    },
+    /// Restore a program checkpointed in a sandbox
+    #[cfg(feature = "criu")]
+    Restore {
+        /// Box ID for the sandbox
+        #[arg(long)]
+        box_id: u32,
+        /// Directory holding the CRIU images
+        #[arg(long)]
+        dir: std::path::PathBuf,
+    },
     /// Check if all language dependencies are installed
     CheckDeps {
         /// Verbose output showing detailed version information
         #[arg(long)]
         verbose: bool,
     },
+    /// Show the recent execution history of a sandbox, or query the audit database
+    #[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+    History {
+        /// Box ID for the sandbox
+        #[arg(long, required = true)]
+        box_id: Option<u32>,
+        /// Print the history as JSON on stdout
+        #[arg(long)]
+        json: bool,
+        #[cfg(feature = "history-db")]
+        #[command(subcommand)]
+        query: Option<HistoryQuery>,
+    },
+    /// Re-execute stored submissions and report verdict drift
+    Regrade {
+        /// JSON manifest of previous runs
+        #[arg(long)]
+        manifest: std::path::PathBuf,
+        /// Number of submissions executed in parallel, one sandbox each
+        #[arg(long, default_value_t = 1)]
+        workers: usize,
+        /// Print a drift report against the previous verdicts instead of all results
+        #[arg(long)]
+        compare: bool,
+        /// First box ID used by the workers
+        #[arg(long, default_value_t = 900)]
+        box_id_base: u32,
+    },
+    /// Show the namespaces, cgroup limits, seccomp, rlimits, mounts and environment
+    /// a run in a box would get, without executing anything
+    Explain {
+        /// Box ID for the sandbox
+        #[arg(long)]
+        box_id: u32,
+        /// Memory limit in MB
+        #[arg(long)]
+        mem: Option<u64>,
+        /// Soft memory limit in MB
+        #[arg(long)]
+        mem_high: Option<u64>,
+        /// Swap in MB the program may use on top of --mem
+        #[arg(long, value_name = "MB", requires = "mem")]
+        swap: Option<u64>,
+        /// Kernel memory limit in MB; defaults to --mem
+        #[arg(long)]
+        kmem: Option<u64>,
+        /// CPU bandwidth in percent of one core
+        #[arg(long, value_name = "PERCENT")]
+        cpu_quota: Option<u32>,
+        /// Time limit in seconds
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        time: Option<Duration>,
+        /// CPU limit in seconds
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        cpu: Option<Duration>,
+        /// Wall clock time limit in seconds
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        wall_time: Option<Duration>,
+        /// Maximum number of processes
+        #[arg(long)]
+        processes: Option<u32>,
+        /// Maximum number of exec calls
+        #[arg(long)]
+        max_execs: Option<u32>,
+        /// Explain an audit-only seccomp run
+        #[arg(long)]
+        audit_syscalls: bool,
+        /// Print the configuration as JSON on stdout
+        #[arg(long)]
+        json: bool,
+    },
+    /// Report which protections are active, degraded or absent in this context
+    Capabilities {
+        /// Print the report as JSON on stdout
+        #[arg(long)]
+        json: bool,
+    },
+    /// Serve init/run/execute-code/cleanup over an HTTP JSON API
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// Number of requests handled concurrently
+        #[arg(long, default_value_t = 4)]
+        workers: usize,
+        /// Run the isolation self-test every SECS seconds
+        #[arg(long, value_name = "SECS")]
+        self_test_interval: Option<u64>,
+        /// Scratch box used by the self-test
+        #[arg(long, default_value_t = 999)]
+        self_test_box_id: u32,
+        /// POST a JSON alert to URL when the self-test regresses or recovers
+        #[arg(long, value_name = "URL", requires = "self_test_interval")]
+        self_test_webhook: Option<String>,
+        /// Reject executions while the self-test is regressed
+        #[arg(long, requires = "self_test_interval")]
+        strict: bool,
+        /// Run queued submissions (POST /queue) on this many workers, one box each
+        #[arg(long, default_value_t = 0)]
+        queue_workers: usize,
+        /// Box of the first queue worker; worker n uses this plus n
+        #[arg(long, default_value_t = 1000)]
+        queue_first_box_id: u32,
+    },
+    /// Serve execute/execute-code/status/kill/cleanup over gRPC (proto/rustbox.proto)
+    #[cfg(feature = "grpc")]
+    ServeGrpc {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        listen: String,
+    },
+    /// Queue a submission on a `serve --queue-workers` daemon and print its job id
+    #[cfg(feature = "server")]
+    Enqueue {
+        /// Base URL of the daemon
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Programming language
+        #[arg(long)]
+        language: String,
+        /// Source code as string
+        #[arg(long)]
+        code: String,
+        /// Input data to pass to stdin
+        #[arg(long)]
+        stdin: Option<String>,
+        /// Higher priorities run first
+        #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+        priority: i32,
+        /// Group the submission counts against for --max-concurrent
+        #[arg(long)]
+        group: Option<String>,
+        /// Most submissions of the group running at once
+        #[arg(long, requires = "group")]
+        max_concurrent: Option<usize>,
+        /// Memory limit in MB
+        #[arg(long)]
+        mem: Option<u64>,
+        /// Time limit in seconds
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        time: Option<Duration>,
+        /// Wall clock time limit in seconds
+        #[arg(long, value_name = "SECS", value_parser = parse_seconds)]
+        wall_time: Option<Duration>,
+    },
+    /// Show the queue of a `serve --queue-workers` daemon, or one job with its result
+    #[cfg(feature = "server")]
+    QueueStatus {
+        /// Base URL of the daemon
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        server: String,
+        /// Job to show
+        #[arg(long)]
+        id: Option<u64>,
+    },
+}
+
+/// Queries of the execution audit database
+#[cfg(feature = "history-db")]
+#[derive(Subcommand)]
+enum HistoryQuery {
+    /// List recorded executions, newest first
+    List {
+        /// Only executions in this box
+        #[arg(long)]
+        box_id: Option<u32>,
+        /// Only executions started within this long (e.g. 90m, 24h, 7d)
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        since: Option<chrono::Duration>,
+        /// Most executions listed
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        /// Print the records as JSON on stdout
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show one recorded execution with its command, limits and result
+    Show {
+        /// Record id, as listed
+        id: i64,
+    },
+    /// Delete recorded executions
+    #[command(group(clap::ArgGroup::new("selection").required(true).args(["box_id", "older_than", "all"])))]
+    Purge {
+        /// Only executions in this box
+        #[arg(long)]
+        box_id: Option<u32>,
+        /// Only executions started longer ago than this (e.g. 30d)
+        #[arg(long, value_name = "AGE", value_parser = parse_age)]
+        older_than: Option<chrono::Duration>,
+        /// Delete every record
+        #[arg(long, conflicts_with_all = ["box_id", "older_than"])]
+        all: bool,
+    },
 }
 
 static CURRENT_BOX_ID: AtomicU32 = AtomicU32::new(0);
 
+/// Apply the cleanup policy to a sandbox after execution and report the outcome
+fn finish_sandbox(isolate: rustbox::isolate::Isolate, box_id: u32, policy: CleanupPolicy) {
+    match isolate.finish(policy) {
+        Ok(true) => eprintln!(
+            "Automatically cleaned up sandbox {} after execution",
+            box_id
+        ),
+        Ok(false) => eprintln!(
+            "Keeping sandbox {} (run `rustbox cleanup --box-id {}` when done)",
+            box_id, box_id
+        ),
+        Err(e) => eprintln!("Warning: Failed to cleanup sandbox {}: {}", box_id, e),
+    }
+}
+
+/// Write the seccomp profile recorded by an audited run, if one was requested
+fn write_syscall_profile(path: Option<&std::path::Path>, result: &rustbox::types::ExecutionResult) {
+    let Some(path) = path else {
+        return;
+    };
+    let Some(ref audit) = result.syscall_audit else {
+        eprintln!("⚠️  No syscall profile written: syscall audit was not active");
+        return;
+    };
+    match audit.write_profile(path) {
+        Ok(()) => eprintln!(
+            "📝 Syscall profile ({} syscalls) written to {}",
+            audit.syscalls.len(),
+            path.display()
+        ),
+        Err(e) => eprintln!("Warning: Failed to write syscall profile: {}", e),
+    }
+}
+
+/// Write the isolate meta file for `result` when `--meta` was given
+fn write_meta_file(path: Option<&std::path::Path>, result: &rustbox::types::ExecutionResult) {
+    let Some(path) = path else {
+        return;
+    };
+    if let Err(e) = std::fs::write(path, result.to_isolate_meta()) {
+        eprintln!(
+            "Warning: Failed to write meta file {}: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Language of a source file from its extension, defaulting to Python
+fn source_language(registry: &LanguageRegistry, path: &std::path::Path) -> String {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .and_then(|ext| registry.by_extension(ext))
+        .unwrap_or("python")
+        .to_string()
+}
+
+/// Fill the limits and strict mode `command` was not given from `defaults`
+fn apply_cli_defaults(command: &mut Commands, defaults: &rustbox::defaults::CliDefaults) {
+    let limits = &defaults.limits;
+    match command {
+        Commands::Run {
+            mem,
+            cpu,
+            wall_time,
+            processes,
+            output_limit,
+            ..
+        }
+        | Commands::ExecuteCode {
+            mem,
+            cpu,
+            wall_time,
+            processes,
+            output_limit,
+            ..
+        } => {
+            *mem = mem.or(limits.mem);
+            *cpu = cpu.or(limits.cpu());
+            *wall_time = wall_time.or(limits.wall_time());
+            *processes = processes.or(limits.processes);
+            *output_limit = output_limit.or(limits.output_limit);
+        }
+        Commands::RunBatch {
+            mem,
+            cpu,
+            wall_time,
+            processes,
+            ..
+        }
+        | Commands::RunInteractive {
+            mem,
+            cpu,
+            wall_time,
+            processes,
+            ..
+        }
+        | Commands::Explain {
+            mem,
+            cpu,
+            wall_time,
+            processes,
+            ..
+        } => {
+            *mem = mem.or(limits.mem);
+            *cpu = cpu.or(limits.cpu());
+            *wall_time = wall_time.or(limits.wall_time());
+            *processes = processes.or(limits.processes);
+        }
+        _ => {}
+    }
+    if let Commands::ExecuteCode { strict, .. } | Commands::RunBatch { strict, .. } = command {
+        *strict = *strict || defaults.strict == Some(true);
+    }
+}
+
+/// Exit with a hint when another process kept `box_id` locked for the whole wait
+fn exit_if_busy<T>(result: rustbox::types::Result<T>, box_id: u32) -> Result<T> {
+    use rustbox::types::{IsolateError, LockError};
+
+    if let Err(IsolateError::AdvancedLock(LockError::Timeout {
+        waited,
+        current_owner,
+        ..
+    })) = &result
+    {
+        eprintln!("Error: Sandbox {} is busy", box_id);
+        eprintln!(
+            "Another process ({}) held it for the {:.1}s waited; use --wait to wait longer",
+            current_owner.as_deref().unwrap_or("unknown"),
+            waited.as_secs_f64()
+        );
+        std::process::exit(1);
+    }
+    Ok(result?)
+}
+
+/// Parse a number of seconds, with a fraction for sub-second limits (1.5)
+fn parse_seconds(value: &str) -> std::result::Result<Duration, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .ok_or_else(|| format!("invalid number of seconds: {}", value))
+}
+
+/// Box `init` sets up
+#[derive(Debug, Clone, Copy)]
+enum InitBoxId {
+    Auto,
+    Fixed(u32),
+}
+
+/// Parse a box id, or "auto"
+fn parse_init_box_id(value: &str) -> std::result::Result<InitBoxId, String> {
+    if value == "auto" {
+        return Ok(InitBoxId::Auto);
+    }
+    value
+        .parse()
+        .map(InitBoxId::Fixed)
+        .map_err(|_| format!("invalid box id: {}", value))
+}
+
+/// Parse an octal umask such as "027" or "0o077"
+fn parse_umask(value: &str) -> std::result::Result<u32, String> {
+    let digits = value.trim_start_matches("0o");
+    let umask =
+        u32::from_str_radix(digits, 8).map_err(|_| format!("invalid octal umask: {}", value))?;
+    if umask > 0o777 {
+        return Err(format!("umask out of range: {}", value));
+    }
+    Ok(umask)
+}
+
+/// Parse a signal name such as "TERM" or "SIGTERM", or a signal number
+fn parse_signal(value: &str) -> std::result::Result<i32, String> {
+    use std::str::FromStr;
+
+    let name = value.to_uppercase();
+    let name = if name.starts_with("SIG") {
+        name
+    } else {
+        format!("SIG{}", name)
+    };
+    value
+        .parse::<i32>()
+        .ok()
+        .and_then(|number| nix::sys::signal::Signal::try_from(number).ok())
+        .or_else(|| nix::sys::signal::Signal::from_str(&name).ok())
+        .map(|signal| signal as i32)
+        .ok_or_else(|| format!("unknown signal: {}", value))
+}
+
+/// Parse an age such as 90s, 30m, 24h or 7d
+fn parse_age(value: &str) -> std::result::Result<chrono::Duration, String> {
+    let invalid = || {
+        format!(
+            "Invalid age '{}' (expected e.g. 90s, 30m, 24h or 7d)",
+            value
+        )
+    };
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    let unit_seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    amount
+        .checked_mul(unit_seconds)
+        .and_then(chrono::Duration::try_seconds)
+        .ok_or_else(invalid)
+}
+
 extern "C" fn signal_handler(sig: i32) {
     let box_id = CURRENT_BOX_ID.load(Ordering::Relaxed);
     if box_id != 0 {
@@ -135,11 +949,47 @@ fn setup_signal_handlers() {
 fn main() -> Result<()> {
     setup_signal_handlers();
 
+    // Kill the active sandbox and leave a crash report if the supervisor panics
+    rustbox::watchdog::install_panic_hook();
+
+    // Parse command line arguments
+    let cli = Cli::parse();
+    if cli.version {
+        print_version(cli.json);
+        return Ok(());
+    }
+    let Some(mut command) = cli.command else {
+        Cli::command().print_help()?;
+        std::process::exit(2);
+    };
+
     // Initialize structured logging for security monitoring
     env_logger::init();
 
+    // Config files and RUSTBOX_* variables fill in what the flags leave out
+    let defaults = match rustbox::defaults::CliDefaults::load().and_then(|defaults| {
+        defaults.apply()?;
+        Ok(defaults)
+    }) {
+        Ok(defaults) => defaults,
+        Err(e) => {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
+        }
+    };
+    apply_cli_defaults(&mut command, &defaults);
+
+    // State and scratch directories must be usable before anything is written
+    if let Err(e) = rustbox::paths::paths().verify() {
+        eprintln!("❌ {}", e);
+        std::process::exit(1);
+    }
+
     // Initialize security logger for audit trail
-    if let Err(e) = rustbox::security_logging::init_security_logger(None) {
+    let audit_log = rustbox::config::RustBoxConfig::load_default()
+        .map(|config| config.security.audit_log)
+        .unwrap_or_default();
+    if let Err(e) = rustbox::security_logging::init_security_logger_with(audit_log) {
         eprintln!("Failed to initialize security logger: {}", e);
         std::process::exit(1);
     }
@@ -157,9 +1007,6 @@ fn main() -> Result<()> {
         std::process::exit(1);
     }
 
-    // Parse command line arguments
-    let cli = Cli::parse();
-
     // Privilege check - many security features require elevated permissions
     if unsafe { libc::getuid() } != 0 {
         eprintln!("Warning: rustbox may require root privileges for full functionality");
@@ -172,30 +1019,98 @@ fn main() -> Result<()> {
     // Security subsystem availability checks
     perform_security_checks();
 
-    // Execute the appropriate command
-    match cli.command {
-        Commands::Init { box_id } => {
-            CURRENT_BOX_ID.store(box_id, Ordering::Relaxed);
-            eprintln!("Initializing sandbox with box-id: {}", box_id);
+    // State directory consistency check
+    check_state(cli.repair);
 
+    // Execute the appropriate command
+    match command {
+        Commands::Init {
+            box_id,
+            workdir_tmpfs,
+            disk_quota,
+        } => {
             let mut config = rustbox::types::IsolateConfig::default();
-            config.instance_id = format!("rustbox/{}", box_id);
-            // The workdir will be created under /tmp/rustbox/{instance_id}/ by default
+            // The workdir will be created under the state dir's {instance_id}/ by default
             // So we don't need to override it, just use the default behavior
             config.strict_mode = false;
-
-            let _isolate = rustbox::isolate::Isolate::new(config)?;
-            eprintln!("Sandbox initialized successfully");
+            config.workdir_tmpfs_size = workdir_tmpfs.map(|mb| mb * 1024 * 1024);
+            config.disk_quota = disk_quota.map(|mb| mb * 1024 * 1024);
+
+            let isolate = match box_id {
+                InitBoxId::Fixed(box_id) => {
+                    CURRENT_BOX_ID.store(box_id, Ordering::Relaxed);
+                    eprintln!("Initializing sandbox with box-id: {}", box_id);
+                    config.instance_id = format!("rustbox/{}", box_id);
+                    rustbox::isolate::Isolate::new(config)?
+                }
+                InitBoxId::Auto => {
+                    eprintln!("Initializing sandbox with a free box-id");
+                    let isolate = rustbox::isolate::Isolate::new_auto(config)?;
+                    CURRENT_BOX_ID.store(isolate.box_id()?, Ordering::Relaxed);
+                    isolate
+                }
+            };
+            let box_id = isolate.box_id()?;
+            if let (Some(uid), Some(gid)) = (isolate.config().uid, isolate.config().gid) {
+                eprintln!("Box user: uid {} gid {}", uid, gid);
+            }
+            eprintln!("Sandbox {} initialized successfully", box_id);
+            println!(
+                "{}",
+                serde_json::json!({
+                    "box_id": box_id,
+                    "instance_id": isolate.config().instance_id,
+                    "uid": isolate.config().uid,
+                    "gid": isolate.config().gid,
+                })
+            );
             Ok(())
         }
         Commands::Run {
             box_id,
+            wait,
             mem,
+            mem_high,
+            swap,
+            kmem,
+            cpu_quota,
             time,
             cpu,
             wall_time,
+            extra_time,
             processes,
+            max_execs,
+            output_limit,
+            stdout_limit,
+            stderr_limit,
+            stream,
+            stream_limit,
+            stdin_file,
+            stdout_file,
+            stderr_file,
+            tty,
+            tty_size,
             directory_bindings,
+            socket_bindings,
+            minimal_visibility,
+            landlock,
+            visible_paths,
+            rootfs,
+            rootfs_upper,
+            network,
+            env,
+            full_env,
+            egress_rules,
+            cpus,
+            audit_writes,
+            process_memory,
+            emulate,
+            syscall_profile,
+            build_info,
+            adaptive_timeout,
+            report_profile,
+            meta,
+            keep,
             command,
         } => {
             CURRENT_BOX_ID.store(box_id, Ordering::Relaxed);
@@ -204,33 +1119,79 @@ fn main() -> Result<()> {
                 eprintln!("Memory limit: {} MB", mem);
             }
             if let Some(time) = time {
-                eprintln!("Time limit: {} seconds", time);
+                eprintln!("Time limit: {} seconds", time.as_secs_f64());
             }
             if let Some(cpu) = cpu {
-                eprintln!("CPU limit: {} seconds", cpu);
+                eprintln!("CPU limit: {} seconds", cpu.as_secs_f64());
             }
             if let Some(wall_time) = wall_time {
-                eprintln!("Wall time limit: {} seconds", wall_time);
+                eprintln!("Wall time limit: {} seconds", wall_time.as_secs_f64());
+            }
+            if let Some(extra_time) = extra_time {
+                eprintln!("Extra time: {} seconds", extra_time.as_secs_f64());
             }
             if let Some(processes) = processes {
                 eprintln!("Process limit: {}", processes);
             }
+            if let Some(max_execs) = max_execs {
+                eprintln!("Exec limit: {}", max_execs);
+            }
+            if let Some(output_limit) = output_limit {
+                eprintln!("Output limit: {} KB", output_limit);
+            }
+            if let Some(stdout_limit) = stdout_limit {
+                eprintln!("stdout limit: {} KB", stdout_limit);
+            }
+            if let Some(stderr_limit) = stderr_limit {
+                eprintln!("stderr limit: {} KB", stderr_limit);
+            }
+
+            let overrides = rustbox::types::ExecutionOverrides {
+                max_cpu: cpu,
+                max_memory: mem,
+                memory_high: mem_high,
+                max_swap: swap,
+                kernel_memory: kmem,
+                cpu_quota_percent: cpu_quota,
+                max_time: time,
+                max_wall_time: wall_time,
+                extra_time,
+                fd_limit: None,
+                audit_file_writes: audit_writes,
+                track_process_memory: process_memory,
+                audit_syscalls: syscall_profile.is_some(),
+                emulate_foreign_arch: emulate,
+                build_info,
+                max_execs,
+                max_processes: processes,
+                adaptive_timeout,
+                max_output: output_limit,
+                max_stdout: stdout_limit,
+                max_stderr: stderr_limit,
+                warmup_runs: None,
+                env,
+                full_env,
+                stdin_file,
+                stdout_file,
+                stderr_file,
+                tty,
+                tty_size,
+            };
+            let cleanup_policy = if keep {
+                CleanupPolicy::Keep
+            } else {
+                CleanupPolicy::Always
+            };
 
             let instance_id = format!("rustbox/{}", box_id);
             let mut isolate = rustbox::isolate::Isolate::load(&instance_id)?
                 .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found. Run init first.", box_id))?;
 
             // Acquire lock for exclusive execution to prevent concurrent access
-            if let Err(e) = isolate.acquire_execution_lock() {
-                match e {
-                    rustbox::types::IsolateError::LockBusy => {
-                        eprintln!("Error: Lock already held by process");
-                        eprintln!("Another process is currently using sandbox {}", box_id);
-                        std::process::exit(1);
-                    }
-                    _ => return Err(e.into()),
-                }
-            }
+            exit_if_busy(
+                isolate.acquire_execution_lock_with_timeout(wait.unwrap_or(DEFAULT_LOCK_WAIT)),
+                box_id,
+            )?;
 
             // Parse and apply directory bindings
             if !directory_bindings.is_empty() {
@@ -255,89 +1216,116 @@ fn main() -> Result<()> {
                 isolate.add_directory_bindings(bindings)?;
             }
 
-            if command.is_empty() {
-                // No command specified - look for standardized pattern /tmp/<box-id>.py in sandbox
-                let standard_filename = format!("{}.py", box_id);
-                let sandbox_work_dir =
-                    std::path::Path::new("/tmp/rustbox").join(format!("rustbox-{}", box_id));
-                let standard_path = sandbox_work_dir.join(&standard_filename);
+            // Parse and apply host socket bindings
+            if !socket_bindings.is_empty() {
+                let mut bindings = Vec::new();
+                for binding_str in &socket_bindings {
+                    match rustbox::types::SocketBinding::parse(binding_str) {
+                        Ok(binding) => {
+                            eprintln!(
+                                "Socket binding: {} -> {}",
+                                binding.source.display(),
+                                binding.target.display()
+                            );
+                            bindings.push(binding);
+                        }
+                        Err(e) => {
+                            eprintln!("Error parsing socket binding '{}': {}", binding_str, e);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                isolate.add_socket_bindings(bindings)?;
+            }
+
+            if minimal_visibility {
+                eprintln!(
+                    "Minimal visibility: host filesystem hidden outside runtimes and workdir"
+                );
+                isolate.restrict_visibility(visible_paths)?;
+            } else if landlock {
+                eprintln!("Landlock: filesystem confined to runtimes (read-only) and workdir");
+                isolate.confine_with_landlock(visible_paths)?;
+            }
+            if let Some(image) = rootfs {
+                eprintln!("Root image: {} (copy-on-write)", image.display());
+                isolate.use_rootfs_image(image, rootfs_upper.map(|mb| mb * 1024 * 1024))?;
+            }
+            if let Some(mode) = network {
+                eprintln!("Network: {:?}", mode);
+                isolate.set_network_mode(mode)?;
+            }
+            if !egress_rules.is_empty() {
+                let rules: Vec<String> = egress_rules.iter().map(|rule| rule.to_string()).collect();
+                eprintln!("Network egress allowed to: {}", rules.join(", "));
+                isolate.allow_egress(egress_rules)?;
+            }
+            if let Some(cpus) = cpus {
+                eprintln!("Pinned to CPUs: {:?}", cpus);
+                isolate.pin_cpus(cpus)?;
+            }
+
+            if stream {
+                use rustbox::output_capture::{OutputSink, OutputStream};
+                use std::io::Write;
+
+                let cap = stream_limit.map(|kb| kb * 1024);
+                isolate.set_output_sink(
+                    OutputSink::new(|stream, data| {
+                        let _ = match stream {
+                            OutputStream::Stdout => std::io::stdout().write_all(data),
+                            OutputStream::Stderr => std::io::stderr().write_all(data),
+                        };
+                        let _ = std::io::stdout().flush();
+                    })
+                    .with_caps(cap, cap),
+                );
+            }
 
-                if standard_path.exists() {
+            if command.is_empty() {
+                // No command specified - look for a standardized /tmp/<box-id>.<ext> in sandbox
+                let registry = LanguageRegistry::load();
+                let sandbox_work_dir = rustbox::paths::paths().standard_workdir(box_id);
+                let standard_files: Vec<(String, String)> = registry
+                    .languages()
+                    .filter_map(|language| {
+                        let name = registry.conventions(language)?.staged_file_name(box_id);
+                        Some((language.to_string(), name))
+                    })
+                    .collect();
+                let standard_file = standard_files
+                    .iter()
+                    .find(|(_, name)| sandbox_work_dir.join(name).exists());
+
+                if let Some((language, standard_filename)) = standard_file {
                     eprintln!("Executing standardized file: {}", standard_filename);
-                    let code = std::fs::read_to_string(&standard_path)?;
+                    let code = std::fs::read_to_string(sandbox_work_dir.join(standard_filename))?;
                     let result = isolate.execute_code_string(
-                        "python",
-                        &code,
-                        None, // stdin
-                        cpu,
-                        mem,
-                        time,
-                        wall_time,
-                        None, // fd_limit
+                        language, &code, None, // stdin
+                        &overrides,
                     )?;
 
                     // Print execution results
-                    let status_message = match result.status {
-                        crate::types::ExecutionStatus::TimeLimit => "TLE".to_string(),
-                        crate::types::ExecutionStatus::MemoryLimit => {
-                            "Memory Limit Exceeded".to_string()
-                        }
-                        _ => format!("{:?}", result.status),
-                    };
+                    println!("{}", report::render(&result, report_profile));
+                    write_syscall_profile(syscall_profile.as_deref(), &result);
+                    write_meta_file(meta.as_deref(), &result);
 
-                    let json_result = serde_json::json!({
-                        "status": status_message,
-                        "exit_code": result.exit_code,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "wall_time": result.wall_time,
-                        "cpu_time": result.cpu_time,
-                        "memory_peak_kb": result.memory_peak / 1024,
-                        "success": result.success,
-                        "signal": result.signal,
-                        "error_message": result.error_message
-                    });
-                    println!("{}", serde_json::to_string_pretty(&json_result).unwrap());
-
-                    // Automatic cleanup after execution (no command specified path)
-                    let cleanup_result = isolate.cleanup();
-                    match cleanup_result {
-                        Ok(_) => {
-                            // Also clean up the standardized files we created
-                            let sandbox_work_dir = std::path::Path::new("/tmp/rustbox")
-                                .join(format!("rustbox-{}", box_id));
-                            if sandbox_work_dir.exists() {
-                                if let Err(e) = std::fs::remove_dir_all(&sandbox_work_dir) {
-                                    eprintln!(
-                                        "Warning: Failed to remove sandbox files {}: {}",
-                                        sandbox_work_dir.display(),
-                                        e
-                                    );
-                                } else {
-                                    eprintln!(
-                                        "Automatically cleaned up sandbox {} files and instance",
-                                        box_id
-                                    );
-                                }
-                            } else {
-                                eprintln!(
-                                    "Automatically cleaned up sandbox {} after execution",
-                                    box_id
-                                );
-                            }
-                        }
-                        Err(e) => eprintln!("Warning: Failed to cleanup sandbox {}: {}", box_id, e),
-                    }
+                    // Apply cleanup policy after execution (no command specified path)
+                    finish_sandbox(isolate, box_id, cleanup_policy);
 
                     if !result.success {
                         std::process::exit(1);
                     }
                 } else {
+                    let candidates: Vec<&str> = standard_files
+                        .iter()
+                        .map(|(_, name)| name.as_str())
+                        .collect();
                     eprintln!(
-                        "Error: No command specified and standardized file {} not found in sandbox",
-                        standard_filename
+                        "Error: No command specified and no standardized file ({}) found in sandbox",
+                        candidates.join(", ")
                     );
-                    eprintln!("Usage: rustbox run --box-id {} <filename> or ensure {} exists in sandbox /tmp/", box_id, standard_filename);
+                    eprintln!("Usage: rustbox run --box-id {} <filename> or ensure one of them exists in sandbox /tmp/", box_id);
                     std::process::exit(1);
                 }
             } else if command.len() == 1 {
@@ -348,8 +1336,7 @@ fn main() -> Result<()> {
 
                 // Check if file exists in current directory, copy to standardized location in sandbox
                 if source_path.exists() {
-                    let sandbox_work_dir =
-                        std::path::Path::new("/tmp/rustbox").join(format!("rustbox-{}", box_id));
+                    let sandbox_work_dir = rustbox::paths::paths().standard_workdir(box_id);
 
                     // Ensure sandbox work directory exists
                     if !sandbox_work_dir.exists() {
@@ -358,12 +1345,13 @@ fn main() -> Result<()> {
                         })?;
                     }
 
-                    // Determine file extension and create standardized name
-                    let extension = source_path
-                        .extension()
-                        .and_then(|ext| ext.to_str())
-                        .unwrap_or("py"); // default to .py
-                    let standardized_name = format!("{}.{}", box_id, extension);
+                    // Determine the language from the extension and create standardized name
+                    let registry = LanguageRegistry::load();
+                    let language = source_language(&registry, &source_path);
+                    let standardized_name = registry
+                        .conventions(&language)
+                        .map(|files| files.staged_file_name(box_id))
+                        .unwrap_or_else(|| format!("{}.py", box_id));
                     let dest_path = sandbox_work_dir.join(&standardized_name);
 
                     // Check if standardized file already exists (conflict detection)
@@ -414,75 +1402,18 @@ fn main() -> Result<()> {
 
                     // Execute the copied file using the standardized path
                     let code = std::fs::read_to_string(&dest_path)?;
-                    let language = match extension {
-                        "py" => "python",
-                        "cpp" | "cc" | "cxx" => "cpp",
-                        "java" => "java",
-                        _ => "python", // default
-                    };
                     let result = isolate.execute_code_string(
-                        language,
-                        &code,
-                        None, // stdin
-                        cpu,
-                        mem,
-                        time,
-                        wall_time,
-                        None, // fd_limit
+                        &language, &code, None, // stdin
+                        &overrides,
                     )?;
 
-                    // Print execution results in JSON format
-                    let status_message = match result.status {
-                        crate::types::ExecutionStatus::TimeLimit => "TLE".to_string(),
-                        crate::types::ExecutionStatus::MemoryLimit => {
-                            "Memory Limit Exceeded".to_string()
-                        }
-                        _ => format!("{:?}", result.status),
-                    };
+                    // Print execution results
+                    println!("{}", report::render(&result, report_profile));
+                    write_syscall_profile(syscall_profile.as_deref(), &result);
+                    write_meta_file(meta.as_deref(), &result);
 
-                    let json_result = serde_json::json!({
-                        "status": status_message,
-                        "exit_code": result.exit_code,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "wall_time": result.wall_time,
-                        "cpu_time": result.cpu_time,
-                        "memory_peak_kb": result.memory_peak / 1024,
-                        "success": result.success,
-                        "signal": result.signal,
-                        "error_message": result.error_message
-                    });
-                    println!("{}", serde_json::to_string_pretty(&json_result).unwrap());
-
-                    // Automatic cleanup after execution (file specified path)
-                    let cleanup_result = isolate.cleanup();
-                    match cleanup_result {
-                        Ok(_) => {
-                            // Also clean up the standardized files we created
-                            let sandbox_work_dir = std::path::Path::new("/tmp/rustbox")
-                                .join(format!("rustbox-{}", box_id));
-                            if sandbox_work_dir.exists() {
-                                if let Err(e) = std::fs::remove_dir_all(&sandbox_work_dir) {
-                                    eprintln!(
-                                        "Warning: Failed to remove sandbox files {}: {}",
-                                        sandbox_work_dir.display(),
-                                        e
-                                    );
-                                } else {
-                                    eprintln!(
-                                        "Automatically cleaned up sandbox {} files and instance",
-                                        box_id
-                                    );
-                                }
-                            } else {
-                                eprintln!(
-                                    "Automatically cleaned up sandbox {} after execution",
-                                    box_id
-                                );
-                            }
-                        }
-                        Err(e) => eprintln!("Warning: Failed to cleanup sandbox {}: {}", box_id, e),
-                    }
+                    // Apply cleanup policy after execution (file specified path)
+                    finish_sandbox(isolate, box_id, cleanup_policy);
 
                     if !result.success {
                         std::process::exit(1);
@@ -491,79 +1422,19 @@ fn main() -> Result<()> {
                     // File exists as absolute path - execute directly
                     let file_path = std::path::Path::new(command_arg);
                     let code = std::fs::read_to_string(file_path)?;
-                    let language = match file_path
-                        .extension()
-                        .and_then(|ext| ext.to_str())
-                        .unwrap_or("py")
-                    {
-                        "py" => "python",
-                        "cpp" | "cc" | "cxx" => "cpp",
-                        "java" => "java",
-                        _ => "python", // default
-                    };
+                    let language = source_language(&LanguageRegistry::load(), file_path);
                     let result = isolate.execute_code_string(
-                        language,
-                        &code,
-                        None, // stdin
-                        cpu,
-                        mem,
-                        time,
-                        wall_time,
-                        None, // fd_limit
+                        &language, &code, None, // stdin
+                        &overrides,
                     )?;
 
-                    // Print execution results in JSON format
-                    let status_message = match result.status {
-                        crate::types::ExecutionStatus::TimeLimit => "TLE".to_string(),
-                        crate::types::ExecutionStatus::MemoryLimit => {
-                            "Memory Limit Exceeded".to_string()
-                        }
-                        _ => format!("{:?}", result.status),
-                    };
+                    // Print execution results
+                    println!("{}", report::render(&result, report_profile));
+                    write_syscall_profile(syscall_profile.as_deref(), &result);
+                    write_meta_file(meta.as_deref(), &result);
 
-                    let json_result = serde_json::json!({
-                        "status": status_message,
-                        "exit_code": result.exit_code,
-                        "stdout": result.stdout,
-                        "stderr": result.stderr,
-                        "wall_time": result.wall_time,
-                        "cpu_time": result.cpu_time,
-                        "memory_peak_kb": result.memory_peak / 1024,
-                        "success": result.success,
-                        "signal": result.signal,
-                        "error_message": result.error_message
-                    });
-                    println!("{}", serde_json::to_string_pretty(&json_result).unwrap());
-
-                    // Automatic cleanup after execution (absolute path)
-                    let cleanup_result = isolate.cleanup();
-                    match cleanup_result {
-                        Ok(_) => {
-                            // Also clean up the standardized files we created
-                            let sandbox_work_dir = std::path::Path::new("/tmp/rustbox")
-                                .join(format!("rustbox-{}", box_id));
-                            if sandbox_work_dir.exists() {
-                                if let Err(e) = std::fs::remove_dir_all(&sandbox_work_dir) {
-                                    eprintln!(
-                                        "Warning: Failed to remove sandbox files {}: {}",
-                                        sandbox_work_dir.display(),
-                                        e
-                                    );
-                                } else {
-                                    eprintln!(
-                                        "Automatically cleaned up sandbox {} files and instance",
-                                        box_id
-                                    );
-                                }
-                            } else {
-                                eprintln!(
-                                    "Automatically cleaned up sandbox {} after execution",
-                                    box_id
-                                );
-                            }
-                        }
-                        Err(e) => eprintln!("Warning: Failed to cleanup sandbox {}: {}", box_id, e),
-                    }
+                    // Apply cleanup policy after execution (absolute path)
+                    finish_sandbox(isolate, box_id, cleanup_policy);
 
                     if !result.success {
                         std::process::exit(1);
@@ -578,67 +1449,17 @@ fn main() -> Result<()> {
             } else {
                 // Multiple arguments or command - execute directly
                 let result = isolate.execute_with_overrides(
-                    &command,
-                    None, // stdin
-                    cpu,
-                    mem,
-                    time,
-                    wall_time,
-                    None, // fd_limit
+                    &command, None, // stdin
+                    &overrides,
                 )?;
 
-                // Print execution results in JSON format
-                let status_message = match result.status {
-                    crate::types::ExecutionStatus::TimeLimit => "TLE".to_string(),
-                    crate::types::ExecutionStatus::MemoryLimit => {
-                        "Memory Limit Exceeded".to_string()
-                    }
-                    _ => format!("{:?}", result.status),
-                };
+                // Print execution results
+                println!("{}", report::render(&result, report_profile));
+                write_syscall_profile(syscall_profile.as_deref(), &result);
+                write_meta_file(meta.as_deref(), &result);
 
-                let json_result = serde_json::json!({
-                    "status": status_message,
-                    "exit_code": result.exit_code,
-                    "stdout": result.stdout,
-                    "stderr": result.stderr,
-                    "wall_time": result.wall_time,
-                    "cpu_time": result.cpu_time,
-                    "memory_peak_kb": result.memory_peak / 1024,
-                    "success": result.success,
-                    "signal": result.signal,
-                    "error_message": result.error_message
-                });
-                println!("{}", serde_json::to_string_pretty(&json_result).unwrap());
-
-                // Automatic cleanup after execution (multiple arguments path)
-                let cleanup_result = isolate.cleanup();
-                match cleanup_result {
-                    Ok(_) => {
-                        // Also clean up the standardized files we created (if any)
-                        let sandbox_work_dir = std::path::Path::new("/tmp/rustbox")
-                            .join(format!("rustbox-{}", box_id));
-                        if sandbox_work_dir.exists() {
-                            if let Err(e) = std::fs::remove_dir_all(&sandbox_work_dir) {
-                                eprintln!(
-                                    "Warning: Failed to remove sandbox files {}: {}",
-                                    sandbox_work_dir.display(),
-                                    e
-                                );
-                            } else {
-                                eprintln!(
-                                    "Automatically cleaned up sandbox {} files and instance",
-                                    box_id
-                                );
-                            }
-                        } else {
-                            eprintln!(
-                                "Automatically cleaned up sandbox {} after execution",
-                                box_id
-                            );
-                        }
-                    }
-                    Err(e) => eprintln!("Warning: Failed to cleanup sandbox {}: {}", box_id, e),
-                }
+                // Apply cleanup policy after execution (multiple arguments path)
+                finish_sandbox(isolate, box_id, cleanup_policy);
 
                 if !result.success {
                     std::process::exit(1);
@@ -649,14 +1470,52 @@ fn main() -> Result<()> {
         }
         Commands::ExecuteCode {
             box_id,
+            wait,
             language,
             code,
             stdin,
+            stdin_ref,
+            stdin_sha256,
             mem,
+            mem_high,
+            swap,
+            kmem,
+            cpu_quota,
             time,
             cpu,
             wall_time,
+            extra_time,
             processes,
+            max_execs,
+            output_limit,
+            stdout_limit,
+            stderr_limit,
+            compile_time,
+            compile_mem,
+            compile_processes,
+            umask,
+            minimal_visibility,
+            landlock,
+            visible_paths,
+            rootfs,
+            rootfs_upper,
+            network,
+            env,
+            full_env,
+            egress_rules,
+            cpus,
+            audit_writes,
+            process_memory,
+            syscall_profile,
+            build_info,
+            adaptive_timeout,
+            report_profile,
+            meta,
+            auto_cleanup,
+            cache,
+            expected_output,
+            expected_sha256,
+            comparator,
             strict,
         } => {
             CURRENT_BOX_ID.store(box_id, Ordering::Relaxed);
@@ -718,56 +1577,341 @@ fn main() -> Result<()> {
                 eprintln!("🔧 CLI Override - Memory limit: {} MB", mem);
             }
             if let Some(cpu_limit) = cpu.or(time) {
-                config.cpu_time_limit = Some(std::time::Duration::from_secs(cpu_limit));
-                config.time_limit = Some(std::time::Duration::from_secs(cpu_limit));
-                eprintln!("🔧 CLI Override - CPU time limit: {} seconds", cpu_limit);
+                config.cpu_time_limit = Some(cpu_limit);
+                config.time_limit = Some(cpu_limit);
+                eprintln!(
+                    "🔧 CLI Override - CPU time limit: {} seconds",
+                    cpu_limit.as_secs_f64()
+                );
             }
             if let Some(wall_limit) = wall_time {
-                config.wall_time_limit = Some(std::time::Duration::from_secs(wall_limit));
-                eprintln!("🔧 CLI Override - Wall time limit: {} seconds", wall_limit);
+                config.wall_time_limit = Some(wall_limit);
+                eprintln!(
+                    "🔧 CLI Override - Wall time limit: {} seconds",
+                    wall_limit.as_secs_f64()
+                );
             }
             if let Some(proc_limit) = processes {
                 config.process_limit = Some(proc_limit);
                 eprintln!("🔧 CLI Override - Process limit: {}", proc_limit);
             }
+            if let Some(max_execs) = max_execs {
+                eprintln!("🔧 CLI Override - Exec limit: {}", max_execs);
+            }
+            if let Some(output_limit) = output_limit {
+                eprintln!("🔧 CLI Override - Output limit: {} KB", output_limit);
+            }
+            if let Some(stdout_limit) = stdout_limit {
+                eprintln!("🔧 CLI Override - stdout limit: {} KB", stdout_limit);
+            }
+            if let Some(stderr_limit) = stderr_limit {
+                eprintln!("🔧 CLI Override - stderr limit: {} KB", stderr_limit);
+            }
+            if let Some(umask) = umask {
+                config.umask = umask;
+                eprintln!("🔧 CLI Override - Umask: {:03o}", umask);
+            }
+            if minimal_visibility {
+                eprintln!("🔧 CLI Override - Minimal filesystem visibility");
+            }
+            if landlock {
+                eprintln!("🔧 CLI Override - Landlock filesystem confinement");
+            }
+            if let Some(ref image) = rootfs {
+                eprintln!("🔧 CLI Override - Root image: {}", image.display());
+            }
 
-            let mut isolate = rustbox::isolate::Isolate::new(config)?;
+            // Pull referenced test data into the local cache before setting up the sandbox
+            let data_cache = rustbox::fetch::DataCache::default();
+            let stdin_path = match &stdin_ref {
+                Some(reference) => {
+                    eprintln!("📥 Fetching stdin from {}", reference);
+                    Some(data_cache.resolve(reference, stdin_sha256.as_deref())?)
+                }
+                None => None,
+            };
+            let expected_output = expected_output
+                .map(|reference| data_cache.resolve(&reference, expected_sha256.as_deref()))
+                .transpose()?;
+
+            let wait = wait.unwrap_or(DEFAULT_LOCK_WAIT);
+            let mut isolate = exit_if_busy(
+                rustbox::isolate::Isolate::new_with_lock_timeout(config, wait),
+                box_id,
+            )?;
+            if minimal_visibility {
+                isolate.restrict_visibility(visible_paths)?;
+            } else if landlock {
+                isolate.confine_with_landlock(visible_paths)?;
+            }
+            if let Some(image) = rootfs {
+                isolate.use_rootfs_image(image, rootfs_upper.map(|mb| mb * 1024 * 1024))?;
+            }
+            if let Some(mode) = network {
+                isolate.set_network_mode(mode)?;
+            }
+            if !egress_rules.is_empty() {
+                isolate.allow_egress(egress_rules)?;
+            }
+            if let Some(cpus) = cpus {
+                isolate.pin_cpus(cpus)?;
+            }
+            if let Some(ref path) = stdin_path {
+                isolate.set_stdin_source(std::fs::File::open(path)?);
+            }
 
             // Execute code string directly
-            let result = isolate.execute_code_string(
+            let overrides = rustbox::types::ExecutionOverrides {
+                max_cpu: cpu,
+                max_memory: mem,
+                memory_high: mem_high,
+                max_swap: swap,
+                kernel_memory: kmem,
+                cpu_quota_percent: cpu_quota,
+                max_time: time,
+                max_wall_time: wall_time,
+                extra_time,
+                fd_limit: None,
+                audit_file_writes: audit_writes,
+                track_process_memory: process_memory,
+                audit_syscalls: syscall_profile.is_some(),
+                emulate_foreign_arch: false,
+                build_info,
+                max_execs,
+                max_processes: processes,
+                adaptive_timeout,
+                max_output: output_limit,
+                max_stdout: stdout_limit,
+                max_stderr: stderr_limit,
+                warmup_runs: None,
+                env,
+                full_env,
+                stdin_file: None,
+                stdout_file: None,
+                stderr_file: None,
+                tty: false,
+                tty_size: None,
+            };
+            let compile_limits = rustbox::types::ExecutionOverrides {
+                max_cpu: compile_time,
+                max_wall_time: compile_time,
+                max_memory: compile_mem,
+                max_processes: compile_processes,
+                ..Default::default()
+            };
+            let result_cache = rustbox::result_cache::ResultCache::on_disk();
+            let cache_key = cache
+                .then(|| -> rustbox::types::Result<_> {
+                    let key = rustbox::result_cache::CacheKey::new(
+                        &language,
+                        &code,
+                        stdin.as_deref(),
+                        isolate.config(),
+                        &overrides,
+                        &compile_limits,
+                    )?;
+                    Ok(match stdin_path {
+                        Some(ref path) => key.with_stdin_file(&rustbox::fetch::sha256_file(path)?),
+                        None => key,
+                    })
+                })
+                .transpose()?;
+            let cached = cache_key.as_ref().and_then(|key| result_cache.get(key));
+            let is_cached = cached.is_some();
+            let result = match cached {
+                Some(result) => {
+                    eprintln!("♻️  Reusing the cached result of an identical submission");
+                    result
+                }
+                None => {
+                    let program = isolate.compile(&language, &code, &compile_limits)?;
+                    let result = match program.failure() {
+                        Some(compile_error) => compile_error,
+                        None => isolate.run(&program, stdin.as_deref(), &overrides)?,
+                    };
+                    if let Some(key) = &cache_key {
+                        result_cache.put(key, &result);
+                    }
+                    result
+                }
+            };
+
+            // Delegate the verdict to the problem's comparator if one was given
+            let mut verdict = None;
+            let mut verdict_message = None;
+            if let (Some(expected), Some(comparator)) = (&expected_output, &comparator) {
+                if result.success {
+                    let comparator = rustbox::checker::Comparator {
+                        command: comparator.split_whitespace().map(String::from).collect(),
+                        limits: rustbox::checker::CheckerConfig::default(),
+                    };
+                    let outcome = isolate.compare_output(&comparator, expected, &result.stdout)?;
+                    verdict = Some(outcome.verdict);
+                    verdict_message = Some(outcome.message);
+                }
+            }
+
+            // Print execution results
+            if report_profile == ReportProfile::Json {
+                let mut json_result = result.to_cli_json();
+                json_result["verdict"] = serde_json::json!(verdict);
+                json_result["verdict_message"] = serde_json::json!(verdict_message);
+                json_result["language"] = serde_json::json!(language);
+                if cache {
+                    json_result["cached"] = serde_json::json!(is_cached);
+                }
+                println!("{}", serde_json::to_string_pretty(&json_result).unwrap());
+            } else if report_profile == ReportProfile::Judge0 {
+                let submission = rustbox::judge0::SubmissionResponse::new(&result, None, false)
+                    .with_language(&language);
+                println!("{}", serde_json::to_string_pretty(&submission).unwrap());
+            } else {
+                println!("{}", report::render(&result, report_profile));
+            }
+            write_syscall_profile(syscall_profile.as_deref(), &result);
+            write_meta_file(meta.as_deref(), &result);
+
+            let cleanup_policy = if auto_cleanup {
+                CleanupPolicy::Always
+            } else {
+                CleanupPolicy::Keep
+            };
+            finish_sandbox(isolate, box_id, cleanup_policy);
+
+            if !result.success {
+                std::process::exit(1);
+            }
+
+            Ok(())
+        }
+        Commands::RunBatch {
+            box_id,
+            wait,
+            language,
+            code,
+            inputs,
+            mem,
+            cpu,
+            wall_time,
+            extra_time,
+            processes,
+            max_execs,
+            warmup,
+            auto_cleanup,
+            strict,
+        } => {
+            CURRENT_BOX_ID.store(box_id, Ordering::Relaxed);
+
+            if strict && unsafe { libc::getuid() } != 0 {
+                eprintln!("❌ SECURITY ERROR: --strict mode requires root privileges");
+                eprintln!("   Run with sudo: sudo rustbox run-batch --strict ...");
+                std::process::exit(1);
+            }
+
+            let inputs = inputs
+                .iter()
+                .map(|path| {
+                    std::fs::read_to_string(path).map_err(|e| {
+                        anyhow::anyhow!("Failed to read input {}: {}", path.display(), e)
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            eprintln!(
+                "Running {} code in sandbox {} against {} test case(s)",
+                language,
+                box_id,
+                inputs.len()
+            );
+
+            let mut config = rustbox::types::IsolateConfig::with_language_defaults(
                 &language,
-                &code,
-                stdin.as_deref(),
-                cpu.or(time),
-                mem,
-                time,
-                wall_time,
-                None, // fd_limit
+                format!("rustbox/{}", box_id),
+            )?;
+            config.strict_mode = strict;
+            if let Some(proc_limit) = processes {
+                config.process_limit = Some(proc_limit);
+            }
+
+            let wait = wait.unwrap_or(DEFAULT_LOCK_WAIT);
+            let mut isolate = exit_if_busy(
+                rustbox::isolate::Isolate::new_with_lock_timeout(config, wait),
+                box_id,
             )?;
+            let overrides = rustbox::types::ExecutionOverrides {
+                max_cpu: cpu,
+                max_memory: mem,
+                max_wall_time: wall_time,
+                extra_time,
+                max_execs,
+                warmup_runs: warmup,
+                ..Default::default()
+            };
+            let batch = isolate.execute_batch(&language, &code, &inputs, &overrides)?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&batch.to_cli_json()).unwrap()
+            );
 
-            // Print execution results in JSON format
-            let status_message = match result.status {
-                crate::types::ExecutionStatus::TimeLimit => "TLE".to_string(),
-                crate::types::ExecutionStatus::MemoryLimit => "Memory Limit Exceeded".to_string(),
-                _ => format!("{:?}", result.status),
+            let cleanup_policy = if auto_cleanup {
+                CleanupPolicy::Always
+            } else {
+                CleanupPolicy::Keep
             };
+            finish_sandbox(isolate, box_id, cleanup_policy);
 
-            let json_result = serde_json::json!({
-                "status": status_message,
-                "exit_code": result.exit_code,
-                "stdout": result.stdout,
-                "stderr": result.stderr,
-                "wall_time": result.wall_time,
-                "cpu_time": result.cpu_time,
-                "memory_peak_kb": result.memory_peak / 1024,
-                "success": result.success,
-                "signal": result.signal,
-                "error_message": result.error_message,
-                "language": language
-            });
-            println!("{}", serde_json::to_string_pretty(&json_result).unwrap());
+            if batch.verdict != rustbox::types::ExecutionStatus::Success {
+                std::process::exit(1);
+            }
 
-            if !result.success {
+            Ok(())
+        }
+        Commands::RunInteractive {
+            box_id,
+            wait,
+            interactor,
+            mem,
+            cpu,
+            wall_time,
+            processes,
+            keep,
+            command,
+        } => {
+            CURRENT_BOX_ID.store(box_id, Ordering::Relaxed);
+            eprintln!(
+                "Running command in sandbox {} against interactor {:?}: {:?}",
+                box_id, interactor, command
+            );
+
+            let instance_id = format!("rustbox/{}", box_id);
+            let mut isolate = rustbox::isolate::Isolate::load(&instance_id)?
+                .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found. Run init first.", box_id))?;
+            exit_if_busy(
+                isolate.acquire_execution_lock_with_timeout(wait.unwrap_or(DEFAULT_LOCK_WAIT)),
+                box_id,
+            )?;
+
+            let overrides = rustbox::types::ExecutionOverrides {
+                max_cpu: cpu,
+                max_memory: mem,
+                max_wall_time: wall_time,
+                max_processes: processes,
+                ..Default::default()
+            };
+            let interactor: Vec<String> = interactor.split_whitespace().map(String::from).collect();
+            let result = isolate.execute_interactive(&command, &interactor, &overrides)?;
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&result.to_cli_json()).unwrap()
+            );
+
+            let cleanup_policy = if keep {
+                CleanupPolicy::Keep
+            } else {
+                CleanupPolicy::Always
+            };
+            finish_sandbox(isolate, box_id, cleanup_policy);
+
+            if result.verdict() != Some(rustbox::checker::Verdict::Accepted) {
                 std::process::exit(1);
             }
 
@@ -785,14 +1929,631 @@ fn main() -> Result<()> {
             }
             Ok(())
         }
+        Commands::Gc {
+            older_than,
+            dry_run,
+            json,
+        } => {
+            let report = rustbox::gc::collect(&rustbox::gc::GcOptions {
+                older_than,
+                dry_run,
+            });
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+                return Ok(());
+            }
+            for collected in &report.boxes {
+                let action = match (dry_run, collected.removed) {
+                    (true, _) => "Would remove",
+                    (false, true) => "Removed",
+                    (false, false) => "Kept",
+                };
+                println!("{} box {}: {}", action, collected.box_id, collected.detail);
+            }
+            for issue in &report.residue.issues {
+                let action = if issue.repaired { "Removed" } else { "Found" };
+                println!("{} {}", action, issue.detail);
+            }
+            if report.boxes.is_empty() && report.residue.is_consistent() {
+                eprintln!("Nothing to collect");
+            }
+            Ok(())
+        }
+        Commands::List { json } => {
+            let summaries = rustbox::isolate::Isolate::list_summaries()?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&summaries)?);
+                return Ok(());
+            }
+            if summaries.is_empty() {
+                eprintln!("No sandboxes");
+                return Ok(());
+            }
+            println!(
+                "{:<16} {:<8} {:>6} {:<20} {:<20} {:>8} {:>7} {:>7} {:>5}",
+                "INSTANCE", "STATE", "UID", "CREATED", "LAST USED", "MEM", "TIME", "WALL", "PROCS"
+            );
+            let limit = |value: Option<String>| value.unwrap_or_else(|| "-".to_string());
+            for summary in &summaries {
+                let state = format!("{:?}", summary.state).to_lowercase();
+                let memory = summary
+                    .memory_limit
+                    .map(|bytes| format!("{}M", bytes / (1024 * 1024)));
+                println!(
+                    "{:<16} {:<8} {:>6} {:<20} {:<20} {:>8} {:>7} {:>7} {:>5}",
+                    summary.instance_id,
+                    state,
+                    limit(summary.uid.map(|uid| uid.to_string())),
+                    summary.created_at.format("%Y-%m-%d %H:%M:%S"),
+                    summary.last_used.format("%Y-%m-%d %H:%M:%S"),
+                    limit(memory),
+                    limit(summary.time_limit.map(|secs| format!("{}s", secs))),
+                    limit(summary.wall_time_limit.map(|secs| format!("{}s", secs))),
+                    limit(summary.process_limit.map(|processes| processes.to_string())),
+                );
+            }
+            Ok(())
+        }
+        Commands::Inspect { box_id } => {
+            let instance_id = format!("rustbox/{}", box_id);
+            let isolate = rustbox::isolate::Isolate::load(&instance_id)?
+                .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found. Run init first.", box_id))?;
+            println!("{}", serde_json::to_string_pretty(&isolate.inspect()?)?);
+            Ok(())
+        }
+        Commands::Stats { box_id } => {
+            let instance_id = format!("rustbox/{}", box_id);
+            let isolate = rustbox::isolate::Isolate::load(&instance_id)?
+                .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found. Run init first.", box_id))?;
+            println!("{}", serde_json::to_string_pretty(&isolate.stats()?)?);
+            Ok(())
+        }
+        Commands::Kill { box_id, signal } => {
+            let instance_id = format!("rustbox/{}", box_id);
+            let isolate = rustbox::isolate::Isolate::load(&instance_id)?
+                .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found. Run init first.", box_id))?;
+            match isolate.kill_running(signal)? {
+                0 => eprintln!("No execution is running in sandbox {}", box_id),
+                signalled => {
+                    eprintln!("Signalled {} process(es) in sandbox {}", signalled, box_id)
+                }
+            }
+            Ok(())
+        }
+        Commands::Pause { box_id } => {
+            let instance_id = format!("rustbox/{}", box_id);
+            let isolate = rustbox::isolate::Isolate::load(&instance_id)?
+                .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found. Run init first.", box_id))?;
+            if isolate.pause()? {
+                eprintln!("Paused the execution in sandbox {}", box_id);
+            } else {
+                eprintln!("No execution is running in sandbox {}", box_id);
+            }
+            Ok(())
+        }
+        Commands::Resume { box_id } => {
+            let instance_id = format!("rustbox/{}", box_id);
+            let isolate = rustbox::isolate::Isolate::load(&instance_id)?
+                .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found. Run init first.", box_id))?;
+            if isolate.resume()? {
+                eprintln!("Resumed the execution in sandbox {}", box_id);
+            } else {
+                eprintln!("No execution is paused in sandbox {}", box_id);
+            }
+            Ok(())
+        }
+        #[cfg(feature = "criu")]
+        Commands::Checkpoint {
+            box_id,
+            dir,
+            leave_running,
+        } => {
+            let instance_id = format!("rustbox/{}", box_id);
+            let isolate = rustbox::isolate::Isolate::load(&instance_id)?
+                .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found. Run init first.", box_id))?;
+            let checkpoint = isolate.checkpoint(&dir, leave_running)?;
+            eprintln!("Checkpointed pid {} of sandbox {}", checkpoint.pid, box_id);
+            println!("{}", serde_json::to_string_pretty(&checkpoint)?);
+            Ok(())
+        }
+        #[cfg(feature = "criu")]
+        Commands::Restore { box_id, dir } => {
+            let instance_id = format!("rustbox/{}", box_id);
+            let isolate = rustbox::isolate::Isolate::load(&instance_id)?
+                .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found. Run init first.", box_id))?;
+            let pid = isolate.restore(&dir)?;
+            eprintln!("Restored sandbox {} as pid {}", box_id, pid);
+            Ok(())
+        }
         Commands::CheckDeps { verbose } => check_language_dependencies(verbose),
+        Commands::History {
+            box_id,
+            json,
+            #[cfg(feature = "history-db")]
+            query,
+        } => {
+            #[cfg(feature = "history-db")]
+            if let Some(query) = query {
+                return query_history_db(query);
+            }
+            let Some(box_id) = box_id else {
+                anyhow::bail!("--box-id is required");
+            };
+            let history = rustbox::isolate::Isolate::load(&format!("rustbox/{}", box_id))?
+                .map(|isolate| isolate.history().clone())
+                .unwrap_or_default();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&history)?);
+                return Ok(());
+            }
+
+            if history.is_empty() {
+                eprintln!("No execution history for box {}", box_id);
+            }
+            for entry in &history {
+                eprintln!(
+                    "{}  {:<8} {:<24} cmd={} exit={:?} cpu={:.3}s wall={:.3}s mem={}KB",
+                    entry.timestamp.format("%Y-%m-%d %H:%M:%S"),
+                    format!("{:?}", entry.phase).to_lowercase(),
+                    entry.status.label(),
+                    entry.command_hash,
+                    entry.exit_code,
+                    entry.cpu_time,
+                    entry.wall_time,
+                    entry.memory_peak / 1024
+                );
+            }
+            Ok(())
+        }
+        Commands::Regrade {
+            manifest,
+            workers,
+            compare,
+            box_id_base,
+        } => {
+            let runs = rustbox::regrade::load_manifest(&manifest)?;
+            eprintln!(
+                "Regrading {} submissions with {} workers (boxes {}..)",
+                runs.len(),
+                workers,
+                box_id_base
+            );
+
+            let outcomes = rustbox::regrade::regrade(runs, workers, box_id_base);
+            if compare {
+                let report = rustbox::regrade::DriftReport::from_outcomes(&outcomes);
+                eprintln!(
+                    "📊 {} unchanged, {} changed, {} failed to run",
+                    report.unchanged, report.changed, report.errors
+                );
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{}", serde_json::to_string_pretty(&outcomes)?);
+            }
+            Ok(())
+        }
+        Commands::Explain {
+            box_id,
+            mem,
+            mem_high,
+            swap,
+            kmem,
+            cpu_quota,
+            time,
+            cpu,
+            wall_time,
+            processes,
+            max_execs,
+            audit_syscalls,
+            json,
+        } => {
+            let overrides = rustbox::types::ExecutionOverrides {
+                max_cpu: cpu,
+                max_memory: mem,
+                memory_high: mem_high,
+                max_swap: swap,
+                kernel_memory: kmem,
+                cpu_quota_percent: cpu_quota,
+                max_time: time,
+                max_wall_time: wall_time,
+                extra_time: None,
+                fd_limit: None,
+                audit_file_writes: false,
+                track_process_memory: false,
+                audit_syscalls,
+                emulate_foreign_arch: false,
+                build_info: false,
+                max_execs,
+                max_processes: processes,
+                adaptive_timeout: None,
+                max_output: None,
+                max_stdout: None,
+                max_stderr: None,
+                warmup_runs: None,
+                env: Vec::new(),
+                full_env: false,
+                stdin_file: None,
+                stdout_file: None,
+                stderr_file: None,
+                tty: false,
+                tty_size: None,
+            };
+            explain_box(box_id, &overrides, json)
+        }
+        Commands::Capabilities { json } => report_capabilities(json),
+        #[cfg(feature = "server")]
+        Commands::Serve {
+            listen,
+            workers,
+            self_test_interval,
+            self_test_box_id,
+            self_test_webhook,
+            strict,
+            queue_workers,
+            queue_first_box_id,
+        } => {
+            let listener = std::net::TcpListener::bind(&listen)
+                .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", listen, e))?;
+            // Read from the environment so the token does not show up in `ps`
+            let token = std::env::var("RUSTBOX_API_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty());
+            if token.is_none() {
+                eprintln!("⚠️  RUSTBOX_API_TOKEN not set: the API is unauthenticated");
+            }
+            let self_test = self_test_interval.map(|secs| rustbox::canary::SelfTestConfig {
+                interval: std::time::Duration::from_secs(secs.max(1)),
+                box_id: self_test_box_id,
+                webhook: self_test_webhook,
+                pause_admission: strict,
+            });
+            eprintln!(
+                "🌐 Serving rustbox API on http://{} ({} workers)",
+                listen, workers
+            );
+            rustbox::server::serve(
+                listener,
+                rustbox::server::ServeOptions {
+                    workers,
+                    token,
+                    self_test,
+                    queue: (queue_workers > 0).then_some(rustbox::queue::QueueOptions {
+                        workers: queue_workers,
+                        first_box_id: queue_first_box_id,
+                    }),
+                },
+            )?;
+            Ok(())
+        }
+        #[cfg(feature = "grpc")]
+        Commands::ServeGrpc { listen } => {
+            let listener = std::net::TcpListener::bind(&listen)
+                .map_err(|e| anyhow::anyhow!("Failed to listen on {}: {}", listen, e))?;
+            // Read from the environment so the token does not show up in `ps`
+            let token = std::env::var("RUSTBOX_API_TOKEN")
+                .ok()
+                .filter(|token| !token.is_empty());
+            if token.is_none() {
+                eprintln!("⚠️  RUSTBOX_API_TOKEN not set: the gRPC API is unauthenticated");
+            }
+            eprintln!("🌐 Serving rustbox gRPC API on {}", listen);
+            rustbox::grpc::serve(listener, rustbox::grpc::GrpcOptions { token })?;
+            Ok(())
+        }
+        #[cfg(feature = "server")]
+        Commands::Enqueue {
+            server,
+            language,
+            code,
+            stdin,
+            priority,
+            group,
+            max_concurrent,
+            mem,
+            time,
+            wall_time,
+        } => {
+            let submission = serde_json::json!({
+                "language": language,
+                "code": code,
+                "stdin": stdin,
+                "priority": priority,
+                "group": group,
+                "max_concurrent": max_concurrent,
+                "limits": {
+                    "max_memory": mem,
+                    "max_time": time,
+                    "max_wall_time": wall_time,
+                },
+            });
+            let url = format!("{}/queue", server.trim_end_matches('/'));
+            let job = daemon_request(ureq::post(&url), Some(submission))?;
+            println!("{}", serde_json::to_string_pretty(&job)?);
+            Ok(())
+        }
+        #[cfg(feature = "server")]
+        Commands::QueueStatus { server, id } => {
+            let mut url = format!("{}/queue", server.trim_end_matches('/'));
+            if let Some(id) = id {
+                url = format!("{}/{}", url, id);
+            }
+            let status = daemon_request(ureq::get(&url), None)?;
+            println!("{}", serde_json::to_string_pretty(&status)?);
+            Ok(())
+        }
+    }
+}
+
+/// Send a request to a `rustbox serve` daemon, authenticated with
+/// RUSTBOX_API_TOKEN when set, and return its JSON response
+#[cfg(feature = "server")]
+fn daemon_request(
+    request: ureq::Request,
+    body: Option<serde_json::Value>,
+) -> Result<serde_json::Value> {
+    let request = match std::env::var("RUSTBOX_API_TOKEN") {
+        Ok(token) if !token.is_empty() => {
+            request.set("Authorization", &format!("Bearer {}", token))
+        }
+        _ => request,
+    };
+    let response = match body {
+        Some(body) => request
+            .set("Content-Type", "application/json")
+            .send_string(&body.to_string()),
+        None => request.call(),
+    };
+    match response {
+        Ok(response) => Ok(serde_json::from_str(&response.into_string()?)?),
+        Err(ureq::Error::Status(status, response)) => {
+            let body: serde_json::Value = response
+                .into_string()
+                .ok()
+                .and_then(|body| serde_json::from_str(&body).ok())
+                .unwrap_or_default();
+            let message = body["error"].as_str().unwrap_or("request failed");
+            Err(anyhow::anyhow!("Daemon answered {}: {}", status, message))
+        }
+        Err(e) => Err(anyhow::anyhow!("Failed to reach the daemon: {}", e)),
     }
 }
 
+/// Run a `history list/show/purge` query against the execution audit database
+#[cfg(feature = "history-db")]
+fn query_history_db(query: HistoryQuery) -> Result<()> {
+    use rustbox::history_db::{HistoryDb, HistoryFilter};
+
+    let db = HistoryDb::open_default()?;
+    let instance_id = |box_id: Option<u32>| box_id.map(|box_id| format!("rustbox/{}", box_id));
+    let ago = |age: chrono::Duration| chrono::Utc::now() - age;
+    match query {
+        HistoryQuery::List {
+            box_id,
+            since,
+            limit,
+            json,
+        } => {
+            let filter = HistoryFilter {
+                instance_id: instance_id(box_id),
+                since: since.map(ago),
+                before: None,
+            };
+            let records = db.list(&filter, limit)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&records)?);
+                return Ok(());
+            }
+            if records.is_empty() {
+                eprintln!("No recorded executions");
+            }
+            for record in &records {
+                println!(
+                    "{:>6}  {}  {:<12} {:<8} {:<24} uid={} cpu={:.3}s mem={}KB  {}",
+                    record.id,
+                    record.started_at.format("%Y-%m-%d %H:%M:%S"),
+                    record.instance_id,
+                    format!("{:?}", record.phase).to_lowercase(),
+                    record.status.label(),
+                    record.uid,
+                    record.cpu_time,
+                    record.memory_peak / 1024,
+                    record.command.join(" ")
+                );
+            }
+        }
+        HistoryQuery::Show { id } => {
+            let record = db
+                .get(id)?
+                .ok_or_else(|| anyhow::anyhow!("No recorded execution {}", id))?;
+            println!("{}", serde_json::to_string_pretty(&record)?);
+        }
+        HistoryQuery::Purge {
+            box_id,
+            older_than,
+            all: _,
+        } => {
+            let filter = HistoryFilter {
+                instance_id: instance_id(box_id),
+                since: None,
+                before: older_than.map(ago),
+            };
+            let purged = db.purge(&filter)?;
+            eprintln!("Purged {} recorded execution(s)", purged);
+        }
+    }
+    Ok(())
+}
+
+/// Print the capability report for the current invocation context
+fn report_capabilities(json: bool) -> Result<()> {
+    use rustbox::capabilities::CapabilityState;
+
+    let report = rustbox::capabilities::probe();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    eprintln!(
+        "Capabilities for uid {} (euid {}), kernel {}:",
+        report.uid,
+        report.euid,
+        report.kernel.as_deref().unwrap_or("unknown")
+    );
+    for capability in &report.capabilities {
+        let marker = match capability.state {
+            CapabilityState::Active => "✅",
+            CapabilityState::Degraded => "⚠️ ",
+            CapabilityState::Absent => "❌",
+        };
+        eprintln!("{} {:<20} {}", marker, capability.name, capability.detail);
+    }
+    Ok(())
+}
+
+/// Print what a run in box `box_id` with `overrides` would set up
+fn explain_box(
+    box_id: u32,
+    overrides: &rustbox::types::ExecutionOverrides,
+    json: bool,
+) -> Result<()> {
+    let isolate = rustbox::isolate::Isolate::load(&format!("rustbox/{}", box_id))?
+        .ok_or_else(|| anyhow::anyhow!("Sandbox {} not found. Run init first.", box_id))?;
+    let effective = isolate.effective_config(overrides)?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&effective)?);
+        return Ok(());
+    }
+
+    let optional = |value: Option<u32>| value.map_or("-".to_string(), |v| v.to_string());
+    eprintln!(
+        "Sandbox {} as euid {}, kernel {}:",
+        box_id,
+        effective.euid,
+        effective.kernel.as_deref().unwrap_or("unknown")
+    );
+    eprintln!(
+        "  namespaces:  {} (network {:?})",
+        if effective.namespaces.is_empty() {
+            "none".to_string()
+        } else {
+            effective.namespaces.join(", ")
+        },
+        effective.network
+    );
+    eprintln!(
+        "  user:        uid {} gid {}, workdir {}",
+        optional(effective.uid),
+        optional(effective.gid),
+        effective.workdir.display()
+    );
+    if effective.cgroup.available {
+        eprintln!("  cgroups:     {}", effective.cgroup.controllers.join(", "));
+        for (limit, value) in &effective.cgroup.limits {
+            eprintln!("    {:<16} {}", limit, value);
+        }
+    } else {
+        eprintln!("  cgroups:     unavailable");
+    }
+    let seccomp = &effective.seccomp;
+    eprintln!(
+        "  seccomp:     {}, supervised: {}",
+        if seccomp.enforcing {
+            "enforcing"
+        } else {
+            "no filter"
+        },
+        if seccomp.notify_all {
+            "all syscalls".to_string()
+        } else if seccomp.notified.is_empty() {
+            "none".to_string()
+        } else {
+            seccomp.notified.join(", ")
+        }
+    );
+    eprintln!(
+        "  privileges:  capabilities {}, no_new_privs {}",
+        if effective.drop_capabilities {
+            "dropped"
+        } else {
+            "kept"
+        },
+        if effective.no_new_privileges {
+            "set"
+        } else {
+            "unset"
+        }
+    );
+    if let Some(version) = effective.landlock {
+        eprintln!(
+            "  landlock:    ABI v{}, workdir and read-only runtimes only",
+            version
+        );
+    }
+    eprintln!("  rlimits:");
+    for rlimit in &effective.rlimits {
+        eprintln!(
+            "    {:<16} {}{}",
+            rlimit.resource,
+            rlimit
+                .limit
+                .map_or("inherited".to_string(), |l| l.to_string()),
+            if rlimit.limit.is_some() && !rlimit.applied {
+                " (not applied)"
+            } else {
+                ""
+            }
+        );
+    }
+    eprintln!("  mounts:");
+    for mount in &effective.mounts {
+        eprintln!(
+            "    {:<24} {:<8} {}{}",
+            mount.target.display(),
+            mount.kind,
+            mount.source,
+            if mount.read_only { " (ro)" } else { "" }
+        );
+    }
+    eprintln!("  environment:");
+    for (name, value) in &effective.environment {
+        eprintln!("    {}={}", name, value);
+    }
+    for warning in &effective.warnings {
+        eprintln!("⚠️  {}", warning);
+    }
+    Ok(())
+}
+
 /// Perform comprehensive security subsystem checks
 ///
 /// This function validates that all necessary security mechanisms are available
 /// and properly configured on the host system.
+/// Print the version, or the full build information as JSON
+fn print_version(json: bool) {
+    let build = rustbox::build_info::BuildInfo::current();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&build).unwrap());
+    } else {
+        println!("{}", build.summary());
+    }
+}
+
+/// Report (and with `--repair` fix) inconsistencies in the state directory
+fn check_state(repair: bool) {
+    let report = rustbox::state_check::check(repair);
+    for issue in &report.issues {
+        if issue.repaired {
+            eprintln!("🔧 Repaired: {}", issue.detail);
+        } else {
+            eprintln!("⚠️  State inconsistency: {}", issue.detail);
+        }
+    }
+    if !repair && !report.is_consistent() {
+        eprintln!("   Run with --repair to fix");
+    }
+}
+
 fn perform_security_checks() {
     // Check cgroups availability for resource control
     if !crate::cgroup::cgroups_available() {
@@ -823,16 +2584,9 @@ fn perform_security_checks() {
 /// Validate that critical system directories are properly configured
 ///
 /// # Security Considerations
-/// - Ensures /tmp is writable for sandbox operations
 /// - Validates /proc and /sys are mounted for system information
 /// - Checks that sensitive directories are protected
 fn validate_system_directories() {
-    // Check /tmp accessibility for sandbox operations
-    if !std::path::Path::new("/tmp").exists() || !std::path::Path::new("/tmp").is_dir() {
-        eprintln!("⚠️  Warning: /tmp directory not accessible");
-        eprintln!("   Sandbox operations may fail without writable temporary space");
-    }
-
     // Validate /proc filesystem for process monitoring
     if !std::path::Path::new("/proc/self").exists() {
         eprintln!("⚠️  Warning: /proc filesystem not mounted");
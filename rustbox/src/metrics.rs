@@ -0,0 +1,278 @@
+/// Execution metrics for this process, in Prometheus format for `rustbox serve`
+///
+/// Counts finished executions by status, keeps a histogram of their wall time
+/// and counts failed box cleanups. `Metrics::collect` snapshots these together
+/// with the number of boxes in the instance registry, lock contention from the
+/// lock manager and the admission, self-test and artifact cache counters, for
+/// embedders; the daemon serves `Metrics::to_prometheus` on `GET /metrics`.
+use crate::admission::AdmissionMetrics;
+use crate::artifact_cache::ArtifactCacheMetrics;
+use crate::canary::SelfTestMetrics;
+use crate::types::ExecutionResult;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Upper bounds of the execution latency buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 11] =
+    [0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Finished executions by status
+static EXECUTIONS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+/// Executions per latency bucket, the last one past every bound
+static LATENCY: Mutex<Latency> = Mutex::new(Latency {
+    counts: [0; LATENCY_BUCKETS.len() + 1],
+    sum: 0.0,
+});
+static CLEANUP_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+struct Latency {
+    counts: [u64; LATENCY_BUCKETS.len() + 1],
+    sum: f64,
+}
+
+/// Wall time of finished executions
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LatencyHistogram {
+    /// (upper bound in seconds, executions that took at most that long)
+    pub buckets: Vec<(f64, u64)>,
+    /// Executions recorded
+    pub count: u64,
+    /// Total wall time in seconds
+    pub sum: f64,
+}
+
+/// Snapshot of the metrics of this process
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Metrics {
+    /// Finished executions by status
+    pub executions: BTreeMap<String, u64>,
+    pub latency: LatencyHistogram,
+    /// Boxes in the instance registry
+    pub active_boxes: u64,
+    /// Box lock acquisitions that had to wait, once the lock manager is initialized
+    pub lock_contentions: u64,
+    /// Box cleanups that failed
+    pub cleanup_failures: u64,
+    pub admission: AdmissionMetrics,
+    pub self_test: SelfTestMetrics,
+    pub artifact_cache: ArtifactCacheMetrics,
+}
+
+impl Metrics {
+    /// Current metrics
+    pub fn collect() -> Self {
+        let executions = EXECUTIONS.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        let latency = {
+            let latency = LATENCY.lock().unwrap_or_else(|e| e.into_inner());
+            let mut seen = 0;
+            let buckets = LATENCY_BUCKETS
+                .iter()
+                .zip(latency.counts)
+                .map(|(bound, count)| {
+                    seen += count;
+                    (*bound, seen)
+                })
+                .collect();
+            LatencyHistogram {
+                buckets,
+                count: latency.counts.iter().sum(),
+                sum: latency.sum,
+            }
+        };
+        Self {
+            executions,
+            latency,
+            active_boxes: crate::isolate::Isolate::list_all()
+                .map(|boxes| boxes.len() as u64)
+                .unwrap_or(0),
+            lock_contentions: crate::lock_manager::get_lock_health()
+                .map(|health| health.metrics.lock_contentions)
+                .unwrap_or(0),
+            cleanup_failures: CLEANUP_FAILURES.load(Ordering::Relaxed),
+            admission: crate::admission::metrics(),
+            self_test: crate::canary::metrics(),
+            artifact_cache: crate::artifact_cache::metrics(),
+        }
+    }
+
+    /// Finished executions of any status
+    pub fn executions_total(&self) -> u64 {
+        self.executions.values().sum()
+    }
+
+    /// The metrics in the Prometheus text exposition format
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+            let _ = writeln!(out, "# HELP rustbox_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE rustbox_{} {}", name, kind);
+            for (suffix, value) in samples {
+                let _ = writeln!(out, "rustbox_{}{} {}", name, suffix, value);
+            }
+        };
+        let sample = |value: &dyn ToString| vec![(String::new(), value.to_string())];
+
+        metric(
+            "executions_total",
+            "counter",
+            "Finished executions",
+            &sample(&self.executions_total()),
+        );
+        let by_status: Vec<_> = self
+            .executions
+            .iter()
+            .map(|(status, count)| (format!("{{status=\"{}\"}}", status), count.to_string()))
+            .collect();
+        metric(
+            "executions_by_status_total",
+            "counter",
+            "Finished executions by status",
+            &by_status,
+        );
+
+        let mut latency: Vec<_> = self
+            .latency
+            .buckets
+            .iter()
+            .map(|(bound, count)| (format!("_bucket{{le=\"{}\"}}", bound), count.to_string()))
+            .collect();
+        latency.push((
+            "_bucket{le=\"+Inf\"}".to_string(),
+            self.latency.count.to_string(),
+        ));
+        latency.push(("_sum".to_string(), self.latency.sum.to_string()));
+        latency.push(("_count".to_string(), self.latency.count.to_string()));
+        metric(
+            "execution_duration_seconds",
+            "histogram",
+            "Wall time of finished executions",
+            &latency,
+        );
+
+        metric(
+            "active_boxes",
+            "gauge",
+            "Boxes in the instance registry",
+            &sample(&self.active_boxes),
+        );
+        metric(
+            "lock_contentions_total",
+            "counter",
+            "Box lock acquisitions that had to wait",
+            &sample(&self.lock_contentions),
+        );
+        metric(
+            "cleanup_failures_total",
+            "counter",
+            "Box cleanups that failed",
+            &sample(&self.cleanup_failures),
+        );
+
+        let admission = &self.admission;
+        metric(
+            "admission_admitted_total",
+            "counter",
+            "Runs admitted",
+            &sample(&admission.admitted),
+        );
+        metric(
+            "admission_queued_total",
+            "counter",
+            "Runs that waited for capacity or pressure",
+            &sample(&admission.queued),
+        );
+        metric(
+            "admission_rejected_total",
+            "counter",
+            "Runs rejected for lack of resources",
+            &sample(&admission.rejected),
+        );
+        metric(
+            "admission_pressure_pauses_total",
+            "counter",
+            "Runs paused on host pressure",
+            &sample(&admission.pressure_pauses),
+        );
+        metric(
+            "admission_pressure_wait_seconds_total",
+            "counter",
+            "Time spent paused on host pressure",
+            &sample(&(admission.pressure_wait_ms as f64 / 1000.0)),
+        );
+
+        metric(
+            "self_test_runs_total",
+            "counter",
+            "Isolation self-test runs",
+            &sample(&self.self_test.runs),
+        );
+        metric(
+            "self_test_failed_runs_total",
+            "counter",
+            "Isolation self-test runs that found a regression",
+            &sample(&self.self_test.failed_runs),
+        );
+        metric(
+            "self_test_regressed",
+            "gauge",
+            "Probes regressed in the latest self-test run",
+            &sample(&self.self_test.regressed.len()),
+        );
+
+        let artifact_cache = &self.artifact_cache;
+        metric(
+            "artifact_cache_hits_total",
+            "counter",
+            "Compiles served from the artifact cache",
+            &sample(&artifact_cache.hits),
+        );
+        metric(
+            "artifact_cache_misses_total",
+            "counter",
+            "Compiles that found no cached build",
+            &sample(&artifact_cache.misses),
+        );
+        metric(
+            "artifact_cache_stores_total",
+            "counter",
+            "Builds added to the artifact cache",
+            &sample(&artifact_cache.stores),
+        );
+        metric(
+            "artifact_cache_evictions_total",
+            "counter",
+            "Builds evicted from the artifact cache",
+            &sample(&artifact_cache.evictions),
+        );
+        out
+    }
+}
+
+/// Count a finished execution
+pub(crate) fn record_execution(result: &ExecutionResult) {
+    let status = match serde_json::to_value(&result.status) {
+        Ok(serde_json::Value::String(status)) => status,
+        _ => format!("{:?}", result.status),
+    };
+    *EXECUTIONS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(status)
+        .or_default() += 1;
+
+    let bucket = LATENCY_BUCKETS
+        .iter()
+        .position(|bound| result.wall_time <= *bound)
+        .unwrap_or(LATENCY_BUCKETS.len());
+    let mut latency = LATENCY.lock().unwrap_or_else(|e| e.into_inner());
+    latency.counts[bucket] += 1;
+    latency.sum += result.wall_time;
+}
+
+/// Count a box cleanup that failed
+pub(crate) fn record_cleanup_failure() {
+    CLEANUP_FAILURES.fetch_add(1, Ordering::Relaxed);
+}
@@ -1,6 +1,6 @@
 /// Namespace isolation for enhanced security
 /// Provides PID, mount, and network namespace isolation capabilities
-use crate::types::{IsolateError, Result};
+use crate::types::{IsolateError, NetworkMode, Result};
 
 #[cfg(unix)]
 use nix::sched::{unshare, CloneFlags};
@@ -155,4 +155,55 @@ impl NamespaceIsolation {
 
         namespaces
     }
-}
\ No newline at end of file
+}
+
+/// Give the calling process the network `mode` asks for
+///
+/// Runs between fork and exec, so it only makes raw syscalls. Needs
+/// CAP_SYS_ADMIN for anything but `Full`.
+pub fn enter_network(mode: NetworkMode) -> std::io::Result<()> {
+    if mode == NetworkMode::Full {
+        return Ok(());
+    }
+    if unsafe { libc::unshare(libc::CLONE_NEWNET) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if mode == NetworkMode::LoopbackOnly {
+        bring_up_loopback()?;
+    }
+    Ok(())
+}
+
+/// Move the calling process into the network namespace open as `netns`
+///
+/// Runs between fork and exec, so it only makes raw syscalls.
+pub fn join_network(netns: std::os::fd::RawFd) -> std::io::Result<()> {
+    if unsafe { libc::setns(netns, libc::CLONE_NEWNET) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Set IFF_UP on `lo`, which starts down in a new network namespace
+fn bring_up_loopback() -> std::io::Result<()> {
+    unsafe {
+        let socket = libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0);
+        if socket < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let mut request: libc::ifreq = std::mem::zeroed();
+        request.ifr_name[0] = b'l' as libc::c_char;
+        request.ifr_name[1] = b'o' as libc::c_char;
+        let mut status = libc::ioctl(socket, libc::SIOCGIFFLAGS, &mut request);
+        if status == 0 {
+            request.ifr_ifru.ifru_flags |= libc::IFF_UP as libc::c_short;
+            status = libc::ioctl(socket, libc::SIOCSIFFLAGS, &request);
+        }
+        let error = std::io::Error::last_os_error();
+        libc::close(socket);
+        if status != 0 {
+            return Err(error);
+        }
+    }
+    Ok(())
+}
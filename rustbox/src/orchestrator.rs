@@ -0,0 +1,205 @@
+/// Run many submissions at once, each in a box of its own
+///
+/// `run_many` is for embedders judging a batch (a contest's pending
+/// submissions, one solution on many inputs) without inventing box ids or
+/// handling lock errors. Up to `max_parallel` workers pull submissions in
+/// order; each claims a free box in `FIRST_BOX_ID..FIRST_BOX_ID + MAX_BOXES`,
+/// skipping boxes in the instance registry and boxes whose lock is held by
+/// another process, and compiles and runs the submission there like
+/// `execute-code`. The box is cleaned up after every submission, also when the
+/// run panics; the panic becomes that submission's error and the others go on.
+use crate::isolate::{CleanupPolicy, Isolate};
+use crate::queue::{compile_and_run, Submission};
+use crate::types::{
+    ExecutionResult, ExecutionStatus, IsolateConfig, IsolateError, LockError, Result,
+};
+use std::collections::{BTreeMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// First box id `run_many` considers
+pub const FIRST_BOX_ID: u32 = 20_000;
+/// Box ids `run_many` considers, from `FIRST_BOX_ID` on
+pub const MAX_BOXES: u32 = 1_000;
+
+/// Results of `run_many`, one per submission in the order given
+#[derive(Debug)]
+pub struct BatchOutcome {
+    /// The verdict of each submission, or why it could not be compiled or run
+    pub results: Vec<Result<ExecutionResult>>,
+}
+
+impl BatchOutcome {
+    /// Submissions that ran to a verdict, by status label (`TLE`, `Success`, ...)
+    pub fn by_status(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for result in self.results.iter().flatten() {
+            *counts.entry(result.status.label()).or_default() += 1;
+        }
+        counts
+    }
+
+    /// Submissions that could not be compiled or run
+    pub fn errors(&self) -> usize {
+        self.results.iter().filter(|result| result.is_err()).count()
+    }
+
+    /// Whether every submission ran and succeeded
+    pub fn all_succeeded(&self) -> bool {
+        self.results.iter().all(|result| {
+            result
+                .as_ref()
+                .is_ok_and(|result| result.status == ExecutionStatus::Success)
+        })
+    }
+
+    /// Largest CPU time and memory peak over the submissions that ran
+    pub fn max_usage(&self) -> (f64, u64) {
+        self.results
+            .iter()
+            .flatten()
+            .fold((0.0, 0), |(cpu_time, memory), result| {
+                (
+                    f64::max(cpu_time, result.cpu_time),
+                    memory.max(result.memory_peak),
+                )
+            })
+    }
+}
+
+/// Compile and run every submission, at most `max_parallel` at a time
+///
+/// Needs the lock manager (`lock_manager::init_lock_manager`).
+pub fn run_many(configs: &[Submission], max_parallel: usize) -> BatchOutcome {
+    let next = AtomicUsize::new(0);
+    let claimed = Mutex::new(HashSet::new());
+    let results: Vec<Mutex<Option<Result<ExecutionResult>>>> =
+        configs.iter().map(|_| Mutex::new(None)).collect();
+
+    let workers = max_parallel.clamp(1, configs.len().max(1));
+    std::thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::SeqCst);
+                let Some(submission) = configs.get(index) else {
+                    break;
+                };
+                let result = catch_unwind(AssertUnwindSafe(|| run_one(submission, &claimed)))
+                    .unwrap_or_else(|panic| {
+                        let message = panic
+                            .downcast_ref::<&str>()
+                            .map(|message| message.to_string())
+                            .or_else(|| panic.downcast_ref::<String>().cloned())
+                            .unwrap_or_default();
+                        Err(IsolateError::Process(format!(
+                            "Execution panicked: {}",
+                            message
+                        )))
+                    });
+                *results[index].lock().unwrap_or_else(|e| e.into_inner()) = Some(result);
+            });
+        }
+    });
+
+    BatchOutcome {
+        results: results
+            .into_iter()
+            .map(|result| {
+                result
+                    .into_inner()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .unwrap_or_else(|| {
+                        Err(IsolateError::Process("Submission was not run".to_string()))
+                    })
+            })
+            .collect(),
+    }
+}
+
+/// Compile and run `submission` in a box claimed for it
+fn run_one(submission: &Submission, claimed: &Mutex<HashSet<u32>>) -> Result<ExecutionResult> {
+    let mut sandbox = ClaimedBox::claim(&submission.language, claimed)?;
+    compile_and_run(&mut sandbox, submission)
+}
+
+/// A box held by one `run_many` worker, cleaned up and released on drop
+struct ClaimedBox<'a> {
+    box_id: u32,
+    isolate: Option<Isolate>,
+    claimed: &'a Mutex<HashSet<u32>>,
+}
+
+impl<'a> ClaimedBox<'a> {
+    /// Create a box for `language` with the first free id
+    fn claim(language: &str, claimed: &'a Mutex<HashSet<u32>>) -> Result<Self> {
+        let registered: HashSet<String> = Isolate::list_all()
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        for box_id in FIRST_BOX_ID..FIRST_BOX_ID + MAX_BOXES {
+            let instance_id = format!("rustbox/{}", box_id);
+            if registered.contains(&instance_id)
+                || !claimed
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .insert(box_id)
+            {
+                continue;
+            }
+            // Guards the id from here on, so every failure below releases it
+            let mut sandbox = Self {
+                box_id,
+                isolate: None,
+                claimed,
+            };
+            let config = IsolateConfig::with_language_defaults(language, instance_id)?;
+            // Never waits: a box locked by another process is skipped
+            match Isolate::new_group(vec![config], Duration::ZERO) {
+                Ok(mut isolates) => {
+                    sandbox.isolate = isolates.pop();
+                    return Ok(sandbox);
+                }
+                Err(IsolateError::AdvancedLock(
+                    LockError::Busy { .. } | LockError::Timeout { .. },
+                )) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Err(IsolateError::ResourceUnavailable(format!(
+            "No free box among {}..{}",
+            FIRST_BOX_ID,
+            FIRST_BOX_ID + MAX_BOXES
+        )))
+    }
+}
+
+impl Deref for ClaimedBox<'_> {
+    type Target = Isolate;
+
+    fn deref(&self) -> &Isolate {
+        self.isolate.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for ClaimedBox<'_> {
+    fn deref_mut(&mut self) -> &mut Isolate {
+        self.isolate.as_mut().unwrap()
+    }
+}
+
+impl Drop for ClaimedBox<'_> {
+    fn drop(&mut self) {
+        if let Some(isolate) = self.isolate.take() {
+            if let Err(e) = isolate.finish(CleanupPolicy::Always) {
+                log::warn!("Failed to clean up box {}: {}", self.box_id, e);
+            }
+        }
+        self.claimed
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.box_id);
+    }
+}
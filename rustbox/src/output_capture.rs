@@ -0,0 +1,383 @@
+/// Collection of a sandboxed program's stdout and stderr
+///
+/// One thread waits on both pipes with epoll and reads each ready pipe with
+/// `readv`: first into the spare capacity of that stream's buffer, then into a
+/// reused scratch buffer, so small outputs are read straight into place in a
+/// single syscall and buffers grow geometrically instead of per read. The output
+/// limits are enforced as bytes arrive: once one is exceeded, collection stops
+/// and the pipes are closed so the program cannot keep writing. An `OutputSink` gets
+/// each read as it happens, for forwarding output while the program runs.
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Scratch space for reads that do not fit a stream's spare capacity
+const SCRATCH_SIZE: usize = 64 * 1024;
+
+/// Capacity reserved for a stream before its first read
+const INITIAL_CAPACITY: usize = 4096;
+
+/// Byte limits enforced while collecting
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct OutputLimits {
+    /// Both streams together
+    pub total: Option<u64>,
+    pub stdout: Option<u64>,
+    pub stderr: Option<u64>,
+}
+
+impl OutputLimits {
+    /// Only a limit on both streams together
+    pub fn total(limit: Option<u64>) -> Self {
+        Self {
+            total: limit,
+            ..Default::default()
+        }
+    }
+
+    fn stream(&self, index: usize) -> Option<u64> {
+        if index == 0 {
+            self.stdout
+        } else {
+            self.stderr
+        }
+    }
+}
+
+/// Both streams of a finished capture
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CapturedOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// The streams together exceeded the total limit and were cut at it
+    pub limit_exceeded: bool,
+    /// stdout exceeded its own limit and was cut at it
+    pub stdout_truncated: bool,
+    /// stderr exceeded its own limit and was cut at it
+    pub stderr_truncated: bool,
+}
+
+/// Which of the program's streams some output came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Callback an `OutputSink` hands each chunk to
+type Forward = Box<dyn FnMut(OutputStream, &[u8]) + Send>;
+
+/// Receives output live, on the collecting thread, as it is read
+///
+/// Output is still collected into the result; the sink only sees a copy, cut
+/// off per stream once that stream's cap is reached.
+pub struct OutputSink {
+    forward: Forward,
+    caps: [Option<u64>; 2],
+    sent: [u64; 2],
+}
+
+impl OutputSink {
+    /// Call `forward` with every chunk read
+    pub fn new(forward: impl FnMut(OutputStream, &[u8]) + Send + 'static) -> Self {
+        Self {
+            forward: Box::new(forward),
+            caps: [None, None],
+            sent: [0, 0],
+        }
+    }
+
+    /// Write both streams into `writer`, flushing after every chunk
+    pub fn to_writer(mut writer: impl Write + Send + 'static) -> Self {
+        Self::new(move |_, data| {
+            // A reader that went away must not affect the run
+            let _ = writer.write_all(data).and_then(|_| writer.flush());
+        })
+    }
+
+    /// Forward at most `stdout` and `stderr` bytes of the respective stream
+    pub fn with_caps(mut self, stdout: Option<u64>, stderr: Option<u64>) -> Self {
+        self.caps = [stdout, stderr];
+        self
+    }
+
+    fn send(&mut self, index: usize, data: &[u8]) {
+        let allowed = self.caps[index].map_or(data.len() as u64, |cap| {
+            cap.saturating_sub(self.sent[index]).min(data.len() as u64)
+        }) as usize;
+        if allowed == 0 {
+            return;
+        }
+        self.sent[index] += allowed as u64;
+        let stream = if index == 0 {
+            OutputStream::Stdout
+        } else {
+            OutputStream::Stderr
+        };
+        (self.forward)(stream, &data[..allowed]);
+    }
+}
+
+/// Progress of a capture shared with the thread running it
+#[derive(Debug, Default)]
+pub struct CaptureProgress {
+    bytes: AtomicU64,
+    limit_exceeded: AtomicBool,
+}
+
+impl CaptureProgress {
+    /// Bytes received on both streams so far
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Some limit was exceeded and collection stopped
+    pub fn limit_exceeded(&self) -> bool {
+        self.limit_exceeded.load(Ordering::Relaxed)
+    }
+}
+
+/// Output collection running in the background
+pub struct OutputCapture {
+    handle: JoinHandle<CapturedOutput>,
+    progress: Arc<CaptureProgress>,
+}
+
+impl OutputCapture {
+    /// Collect `stdout` and `stderr` on a background thread within `limits`
+    pub fn start(
+        stdout: Option<OwnedFd>,
+        stderr: Option<OwnedFd>,
+        limits: OutputLimits,
+    ) -> io::Result<Self> {
+        Self::start_streaming(stdout, stderr, limits, None)
+    }
+
+    /// Like `start`, also handing every read to `sink` as it happens
+    pub fn start_streaming(
+        stdout: Option<OwnedFd>,
+        stderr: Option<OwnedFd>,
+        limits: OutputLimits,
+        mut sink: Option<OutputSink>,
+    ) -> io::Result<Self> {
+        let progress = Arc::new(CaptureProgress::default());
+        let shared = Arc::clone(&progress);
+        let handle = thread::Builder::new()
+            .name("rustbox-output".to_string())
+            .spawn(move || {
+                collect_streaming(stdout, stderr, limits, &shared, sink.as_mut()).unwrap_or_else(
+                    |e| {
+                        log::warn!("Output collection failed: {}", e);
+                        CapturedOutput::default()
+                    },
+                )
+            })?;
+        Ok(Self { handle, progress })
+    }
+
+    pub fn progress(&self) -> &CaptureProgress {
+        &self.progress
+    }
+
+    /// Wait for both streams to close and return what was collected
+    pub fn finish(self) -> CapturedOutput {
+        self.handle.join().unwrap_or_default()
+    }
+}
+
+/// Read both streams to the end on the calling thread
+///
+/// Returns early, dropping (and so closing) the pipes, once a limit is exceeded.
+pub fn collect(
+    stdout: Option<OwnedFd>,
+    stderr: Option<OwnedFd>,
+    limits: OutputLimits,
+    progress: &CaptureProgress,
+) -> io::Result<CapturedOutput> {
+    collect_streaming(stdout, stderr, limits, progress, None)
+}
+
+/// Like `collect`, also handing every read to `sink` as it happens
+pub fn collect_streaming(
+    stdout: Option<OwnedFd>,
+    stderr: Option<OwnedFd>,
+    limits: OutputLimits,
+    progress: &CaptureProgress,
+    mut sink: Option<&mut OutputSink>,
+) -> io::Result<CapturedOutput> {
+    let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+    if epoll < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let epoll = unsafe { <OwnedFd as std::os::fd::FromRawFd>::from_raw_fd(epoll) };
+
+    let mut streams = [Stream::new(stdout), Stream::new(stderr)];
+    for (index, stream) in streams.iter().enumerate() {
+        if let Some(ref fd) = stream.fd {
+            set_nonblocking(fd.as_raw_fd())?;
+            let mut event = libc::epoll_event {
+                events: libc::EPOLLIN as u32,
+                u64: index as u64,
+            };
+            let rc = unsafe {
+                libc::epoll_ctl(
+                    epoll.as_raw_fd(),
+                    libc::EPOLL_CTL_ADD,
+                    fd.as_raw_fd(),
+                    &mut event,
+                )
+            };
+            if rc != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+
+    let mut scratch = vec![0u8; SCRATCH_SIZE];
+    let mut total = 0u64;
+    let mut events = [libc::epoll_event { events: 0, u64: 0 }; 2];
+    while streams.iter().any(|stream| stream.fd.is_some()) {
+        let ready = unsafe {
+            libc::epoll_wait(
+                epoll.as_raw_fd(),
+                events.as_mut_ptr(),
+                events.len() as i32,
+                -1,
+            )
+        };
+        if ready < 0 {
+            let error = io::Error::last_os_error();
+            if error.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(error);
+        }
+
+        for event in &events[..ready as usize] {
+            let index = event.u64 as usize;
+            let stream = &mut streams[index];
+            let start = stream.buffer.len();
+            let read = stream.read_once(&mut scratch)?;
+            total += read as u64;
+            progress.bytes.fetch_add(read as u64, Ordering::Relaxed);
+
+            // Keep exactly up to the limit; the excess arrived in this read
+            let mut limit_exceeded = false;
+            if let Some(limit) = limits.total.filter(|&limit| total > limit) {
+                let excess = (total - limit) as usize;
+                let kept = stream.buffer.len().saturating_sub(excess);
+                stream.buffer.truncate(kept);
+                limit_exceeded = true;
+            }
+            if let Some(limit) = limits.stream(index) {
+                if stream.buffer.len() as u64 > limit {
+                    stream.buffer.truncate(limit as usize);
+                    stream.truncated = true;
+                }
+            }
+
+            if let Some(sink) = sink.as_mut() {
+                sink.send(index, &stream.buffer[start.min(stream.buffer.len())..]);
+            }
+            if limit_exceeded || stream.truncated {
+                progress.limit_exceeded.store(true, Ordering::Relaxed);
+                return Ok(finish(streams, limit_exceeded));
+            }
+        }
+    }
+
+    Ok(finish(streams, false))
+}
+
+fn finish([stdout, stderr]: [Stream; 2], limit_exceeded: bool) -> CapturedOutput {
+    CapturedOutput {
+        stdout: stdout.buffer,
+        stderr: stderr.buffer,
+        limit_exceeded,
+        stdout_truncated: stdout.truncated,
+        stderr_truncated: stderr.truncated,
+    }
+}
+
+/// Decode collected output, reusing the buffer when it is valid UTF-8
+pub fn into_text(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes)
+        .unwrap_or_else(|invalid| String::from_utf8_lossy(invalid.as_bytes()).into_owned())
+}
+
+/// One pipe and the bytes read from it
+struct Stream {
+    /// `None` once the pipe reached end of file
+    fd: Option<OwnedFd>,
+    buffer: Vec<u8>,
+    /// Cut at this stream's own limit
+    truncated: bool,
+}
+
+impl Stream {
+    fn new(fd: Option<OwnedFd>) -> Self {
+        Self {
+            fd,
+            buffer: Vec::new(),
+            truncated: false,
+        }
+    }
+
+    /// One `readv` from the pipe; returns the number of bytes read
+    ///
+    /// epoll is level-triggered, so a pipe with more data pending is reported
+    /// again and the limit is checked between reads.
+    fn read_once(&mut self, scratch: &mut [u8]) -> io::Result<usize> {
+        let Some(fd) = self.fd.as_ref().map(AsRawFd::as_raw_fd) else {
+            return Ok(0);
+        };
+        if self.buffer.capacity() == 0 {
+            self.buffer.reserve(INITIAL_CAPACITY);
+        }
+        let spare = self.buffer.spare_capacity_mut();
+        let iov = [
+            libc::iovec {
+                iov_base: spare.as_mut_ptr() as *mut libc::c_void,
+                iov_len: spare.len(),
+            },
+            libc::iovec {
+                iov_base: scratch.as_mut_ptr() as *mut libc::c_void,
+                iov_len: scratch.len(),
+            },
+        ];
+        let n = unsafe { libc::readv(fd, iov.as_ptr(), iov.len() as i32) };
+        if n < 0 {
+            let error = io::Error::last_os_error();
+            return match error.kind() {
+                io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock => Ok(0),
+                // A pty master whose slave side every process has closed
+                _ if error.raw_os_error() == Some(libc::EIO) => {
+                    self.fd = None;
+                    Ok(0)
+                }
+                _ => Err(error),
+            };
+        }
+        if n == 0 {
+            self.fd = None;
+            return Ok(0);
+        }
+
+        let n = n as usize;
+        let in_place = n.min(iov[0].iov_len);
+        // SAFETY: readv initialized the first `in_place` bytes of the spare capacity
+        unsafe { self.buffer.set_len(self.buffer.len() + in_place) };
+        self.buffer.extend_from_slice(&scratch[..n - in_place]);
+        Ok(n)
+    }
+}
+
+fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
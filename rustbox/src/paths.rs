@@ -0,0 +1,203 @@
+/// Host paths for rustbox state and scratch files
+///
+/// Everything lives under one home directory, `RUSTBOX_HOME`, which defaults to
+/// `$TMPDIR/rustbox` (`/tmp/rustbox`): state (instance registry, history,
+/// locks, audit log, crash reports) and scratch (sandbox workdirs, compiled
+/// binaries, the test data cache). `RUSTBOX_STATE_DIR` moves the state
+/// elsewhere, so hosts where `/tmp` is world-writable, read-only, noexec or
+/// cleared on reboot can keep boxes somewhere durable. Every module asks
+/// `paths()` instead of building `/tmp` paths.
+use crate::types::{IsolateError, Result};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Overrides the home directory
+pub const HOME_ENV: &str = "RUSTBOX_HOME";
+/// Overrides the state directory
+pub const STATE_DIR_ENV: &str = "RUSTBOX_STATE_DIR";
+
+/// Resolved rustbox directories
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathProvider {
+    state_dir: PathBuf,
+    scratch_dir: PathBuf,
+}
+
+static PATHS: OnceLock<PathProvider> = OnceLock::new();
+
+/// Directories for this process, resolved from the environment on first use
+pub fn paths() -> &'static PathProvider {
+    PATHS.get_or_init(PathProvider::from_env)
+}
+
+/// Use `provider` instead of the environment; fails once `paths` was used
+pub fn set_paths(provider: PathProvider) -> Result<()> {
+    PATHS
+        .set(provider)
+        .map_err(|_| IsolateError::Config("Paths are already in use".to_string()))
+}
+
+impl PathProvider {
+    pub fn new(state_dir: PathBuf, scratch_dir: PathBuf) -> Self {
+        Self {
+            state_dir,
+            scratch_dir,
+        }
+    }
+
+    /// `RUSTBOX_HOME` and `RUSTBOX_STATE_DIR`, see `resolve`
+    pub fn from_env() -> Self {
+        let env_dir = |name| {
+            std::env::var_os(name)
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+        };
+        Self::resolve(env_dir(HOME_ENV), env_dir(STATE_DIR_ENV))
+    }
+
+    /// Scratch in `home` (`$TMPDIR/rustbox` if not given), state in
+    /// `state_dir` if given and in `home` otherwise
+    pub fn resolve(home: Option<PathBuf>, state_dir: Option<PathBuf>) -> Self {
+        let home = home.unwrap_or_else(|| std::env::temp_dir().join("rustbox"));
+        Self::new(state_dir.unwrap_or_else(|| home.clone()), home)
+    }
+
+    pub fn state_dir(&self) -> &Path {
+        &self.state_dir
+    }
+
+    /// Default sandbox workdir; compiled programs run from here
+    pub fn scratch_dir(&self) -> &Path {
+        &self.scratch_dir
+    }
+
+    /// Records of initialized boxes, one file each
+    pub fn instances_dir(&self) -> PathBuf {
+        self.state_dir.join("instances")
+    }
+
+    /// Single-file registry of older releases, moved into `instances_dir`
+    pub fn instances_file(&self) -> PathBuf {
+        self.state_dir.join("instances.json")
+    }
+
+    /// Sidecar file locked while the old registry is moved
+    pub fn instances_lock_file(&self) -> PathBuf {
+        self.state_dir.join("instances.json.lock")
+    }
+
+    /// Resources held by admitted runs, shared by every rustbox process
+    pub fn admission_ledger(&self) -> PathBuf {
+        self.state_dir.join("admission.json")
+    }
+
+    /// Sidecar file locked while the admission ledger is read or updated
+    pub fn admission_ledger_lock(&self) -> PathBuf {
+        self.state_dir.join("admission.json.lock")
+    }
+
+    /// Per-instance directory (`rustbox/<id>` nests under the state dir)
+    pub fn instance_dir(&self, instance_id: &str) -> PathBuf {
+        self.state_dir.join(instance_id)
+    }
+
+    /// Execution audit database (feature `history-db`)
+    pub fn history_db(&self) -> PathBuf {
+        self.state_dir.join("history.db")
+    }
+
+    pub fn security_audit_log(&self) -> PathBuf {
+        self.state_dir.join("security-audit.log")
+    }
+
+    pub fn crash_reports_dir(&self) -> PathBuf {
+        self.state_dir.join("crash-reports")
+    }
+
+    pub fn lock_dir(&self) -> PathBuf {
+        self.state_dir.join("locks")
+    }
+
+    pub fn data_cache_dir(&self) -> PathBuf {
+        self.scratch_dir.join("data-cache")
+    }
+
+    /// Cached execution results, one JSON file per submission
+    pub fn result_cache_dir(&self) -> PathBuf {
+        self.scratch_dir.join("result-cache")
+    }
+
+    /// Mount point each minimal-visibility run builds its private root on
+    pub fn visible_root_dir(&self) -> PathBuf {
+        self.scratch_dir.join("visible-root")
+    }
+
+    /// Mount point each run on a root image builds its overlay on
+    pub fn image_root_dir(&self) -> PathBuf {
+        self.scratch_dir.join("image-root")
+    }
+
+    /// Work directory `rustbox run` stages standardized source files in
+    pub fn standard_workdir(&self, box_id: u32) -> PathBuf {
+        self.scratch_dir.join(format!("rustbox-{}", box_id))
+    }
+
+    /// Check both directories are usable before anything is written
+    ///
+    /// The state dir must be writable; the scratch dir must also allow exec,
+    /// since compiled submissions run from it.
+    pub fn verify(&self) -> Result<()> {
+        check_writable(&self.state_dir).map_err(|reason| {
+            IsolateError::Config(format!(
+                "State directory {} {}; set {} to a writable directory",
+                self.state_dir.display(),
+                reason,
+                STATE_DIR_ENV
+            ))
+        })?;
+        check_writable(&self.scratch_dir)
+            .and_then(|()| check_exec_allowed(&self.scratch_dir))
+            .map_err(|reason| {
+                IsolateError::Config(format!(
+                    "Scratch directory {} {}; set {} to a directory on a writable, \
+                     exec-allowed filesystem (e.g. {}=/var/lib/rustbox)",
+                    self.scratch_dir.display(),
+                    reason,
+                    HOME_ENV,
+                    HOME_ENV
+                ))
+            })
+    }
+}
+
+fn check_writable(dir: &Path) -> std::result::Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("cannot be created ({})", e))?;
+    if mount_flags(dir).is_some_and(|flags| flags & libc::ST_RDONLY != 0) {
+        return Err("is on a read-only filesystem".to_string());
+    }
+    let probe = dir.join(format!(".write_test_{}", std::process::id()));
+    std::fs::write(&probe, b"")
+        .map_err(|e| format!("is not writable ({})", e))
+        .map(|()| {
+            let _ = std::fs::remove_file(&probe);
+        })
+}
+
+fn check_exec_allowed(dir: &Path) -> std::result::Result<(), String> {
+    if mount_flags(dir).is_some_and(|flags| flags & libc::ST_NOEXEC != 0) {
+        return Err("is on a noexec filesystem, so compiled programs cannot run".to_string());
+    }
+    Ok(())
+}
+
+/// `statvfs` mount flags of the filesystem holding `path`
+fn mount_flags(path: &Path) -> Option<libc::c_ulong> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_flag)
+}
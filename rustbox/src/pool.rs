@@ -0,0 +1,151 @@
+/// Pool of pre-initialized sandboxes
+///
+/// Creating a box takes its lock, writes the instance registry and creates its
+/// directories; cleaning up undoes all of it. A `SandboxPool` pays that once
+/// per box and hands the boxes out repeatedly, resetting each one (workdir
+/// emptied, configuration restored) when it is returned.
+use crate::isolate::{CleanupPolicy, Isolate};
+use crate::paths::paths;
+use crate::types::{IsolateConfig, IsolateError, Result};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long `SandboxPool::new` waits for the pool's box locks
+const POOL_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Boxes `first_box_id..first_box_id + size`, each handed to one user at a time
+pub struct SandboxPool {
+    /// Configuration every box is reset to, minus its id and workdir
+    template: IsolateConfig,
+    idle: Mutex<Vec<Isolate>>,
+    returned: Condvar,
+}
+
+impl SandboxPool {
+    /// Initialize `size` boxes from `template`, locking all of them or none
+    ///
+    /// Each box gets its own workdir (`paths().standard_workdir(box_id)`), so a
+    /// reset only ever empties that box's files.
+    pub fn new(template: IsolateConfig, first_box_id: u32, size: u32) -> Result<Self> {
+        let last_box_id = first_box_id.checked_add(size).ok_or_else(|| {
+            IsolateError::Config(format!(
+                "Pool of {} boxes from {} overflows the box id range",
+                size, first_box_id
+            ))
+        })?;
+        let configs = (first_box_id..last_box_id)
+            .map(|box_id| Self::box_config(&template, box_id))
+            .collect::<Result<Vec<_>>>()?;
+        let idle = Isolate::new_group(configs, POOL_LOCK_TIMEOUT)?;
+
+        Ok(Self {
+            template,
+            idle: Mutex::new(idle),
+            returned: Condvar::new(),
+        })
+    }
+
+    /// Take a free box, waiting up to `timeout` for one to be returned
+    pub fn acquire(&self, timeout: Duration) -> Result<PooledIsolate<'_>> {
+        let deadline = Instant::now() + timeout;
+        let mut idle = self.idle.lock().unwrap();
+        loop {
+            if let Some(isolate) = idle.pop() {
+                return Ok(PooledIsolate {
+                    pool: self,
+                    isolate: Some(isolate),
+                });
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(IsolateError::ResourceUnavailable(format!(
+                    "No pooled sandbox became free within {:?}",
+                    timeout
+                )));
+            }
+            idle = self.returned.wait_timeout(idle, remaining).unwrap().0;
+        }
+    }
+
+    /// Take a free box if one is available right now
+    pub fn try_acquire(&self) -> Option<PooledIsolate<'_>> {
+        self.acquire(Duration::ZERO).ok()
+    }
+
+    /// Boxes currently waiting in the pool
+    pub fn idle_count(&self) -> usize {
+        self.idle.lock().unwrap().len()
+    }
+
+    /// Clean up every idle box
+    ///
+    /// Boxes still handed out are not waited for; call this once all of them
+    /// have been returned.
+    pub fn shutdown(&self) -> Result<()> {
+        let idle: Vec<Isolate> = self.idle.lock().unwrap().drain(..).collect();
+        for isolate in idle {
+            isolate.finish(CleanupPolicy::Always)?;
+        }
+        Ok(())
+    }
+
+    fn box_config(template: &IsolateConfig, box_id: u32) -> Result<IsolateConfig> {
+        let workdir = paths().standard_workdir(box_id);
+        std::fs::create_dir_all(&workdir)?;
+        Ok(IsolateConfig {
+            instance_id: format!("rustbox/{}", box_id),
+            workdir,
+            ..template.clone()
+        })
+    }
+
+    /// Reset `isolate` and put it back; a box that cannot be reset leaves the pool
+    fn release(&self, mut isolate: Isolate) {
+        let config = IsolateConfig {
+            instance_id: isolate.config().instance_id.clone(),
+            workdir: isolate.config().workdir.clone(),
+            uid: isolate.config().uid,
+            gid: isolate.config().gid,
+            ..self.template.clone()
+        };
+        if let Err(e) = isolate.reset(config) {
+            log::error!(
+                "Dropping {} from the pool, reset failed: {}",
+                isolate.config().instance_id,
+                e
+            );
+            return;
+        }
+        self.idle.lock().unwrap().push(isolate);
+        self.returned.notify_one();
+    }
+}
+
+/// A box borrowed from a `SandboxPool`, returned to it on drop
+pub struct PooledIsolate<'a> {
+    pool: &'a SandboxPool,
+    isolate: Option<Isolate>,
+}
+
+impl Deref for PooledIsolate<'_> {
+    type Target = Isolate;
+
+    fn deref(&self) -> &Isolate {
+        self.isolate.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledIsolate<'_> {
+    fn deref_mut(&mut self) -> &mut Isolate {
+        self.isolate.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledIsolate<'_> {
+    fn drop(&mut self) {
+        if let Some(isolate) = self.isolate.take() {
+            self.pool.release(isolate);
+        }
+    }
+}
@@ -0,0 +1,162 @@
+/// Peak memory of the largest single process in a run
+///
+/// The cgroup peak is the memory of every process in the box together. For
+/// multi-process runtimes (the JVM with its compiler threads, a program with
+/// helper processes) that number says little about any one of them, so when
+/// enabled a sampler thread reads the peak RSS (VmHWM in /proc/PID/status) of
+/// every process in the box every few milliseconds while the program runs.
+///
+/// VmHWM is gone once a process exits, so a process that lives shorter than
+/// one sampling interval may be missed, and for the others the peak seen at
+/// their last sample is reported.
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Time between two reads of the box's processes
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(5);
+
+/// Per-process memory attached to an execution result
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProcessMemoryReport {
+    /// Largest peak RSS of a single process, in bytes
+    pub max_process_rss: u64,
+    /// Process that reached it
+    pub pid: u32,
+    /// Its command name (/proc/PID/comm)
+    pub command: String,
+    /// Number of processes seen during the run
+    pub processes: usize,
+}
+
+/// Running sampler; stop it with `finish` to collect the report
+pub struct ProcessMemorySampler {
+    stop: Arc<AtomicBool>,
+    sampler: Option<JoinHandle<ProcessMemoryReport>>,
+}
+
+impl ProcessMemorySampler {
+    /// Start sampling the processes listed in `task_files` (cgroup tasks
+    /// files), or the process group of `pid` when there are none
+    pub fn start(task_files: Vec<PathBuf>, pid: u32) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = Arc::clone(&stop);
+        let sampler = std::thread::spawn(move || {
+            let mut peaks: HashMap<u32, (u64, String)> = HashMap::new();
+            loop {
+                let pids = if task_files.is_empty() {
+                    process_group(pid)
+                } else {
+                    cgroup_tasks(&task_files)
+                };
+                // The reaper's peak is the supervisor's, from before the fork
+                let supervisor = std::process::id();
+                for task in pids
+                    .into_iter()
+                    .filter(|&task| !crate::reaper::is_reaper(task, supervisor))
+                {
+                    if let Some((tgid, peak, command)) = read_status(task) {
+                        let entry = peaks.entry(tgid).or_insert((0, command));
+                        entry.0 = entry.0.max(peak);
+                    }
+                }
+                if stopped.load(Ordering::Relaxed) {
+                    break;
+                }
+                std::thread::sleep(SAMPLE_INTERVAL);
+            }
+
+            let processes = peaks.len();
+            peaks
+                .into_iter()
+                .max_by_key(|(_, (peak, _))| *peak)
+                .map(|(pid, (max_process_rss, command))| ProcessMemoryReport {
+                    max_process_rss,
+                    pid,
+                    command,
+                    processes,
+                })
+                .unwrap_or_default()
+        });
+        Self {
+            stop,
+            sampler: Some(sampler),
+        }
+    }
+
+    /// Stop sampling and return the report
+    pub fn finish(mut self) -> ProcessMemoryReport {
+        self.stop.store(true, Ordering::Relaxed);
+        self.sampler
+            .take()
+            .and_then(|sampler| sampler.join().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for ProcessMemorySampler {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Tasks (threads) listed in the cgroup tasks files
+fn cgroup_tasks(task_files: &[PathBuf]) -> Vec<u32> {
+    let mut tasks: Vec<u32> = task_files
+        .iter()
+        .filter_map(|file| fs::read_to_string(file).ok())
+        .flat_map(|content| {
+            content
+                .lines()
+                .filter_map(|line| line.trim().parse().ok())
+                .collect::<Vec<u32>>()
+        })
+        .collect();
+    tasks.sort_unstable();
+    tasks.dedup();
+    tasks
+}
+
+/// Processes in process group `pgid`, from the fifth field of /proc/PID/stat
+fn process_group(pgid: u32) -> Vec<u32> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return vec![pgid];
+    };
+    entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|pid| {
+            fs::read_to_string(format!("/proc/{}/stat", pid))
+                .ok()
+                .and_then(|stat| {
+                    let (_, fields) = stat.rsplit_once(')')?;
+                    fields.split_whitespace().nth(2)?.parse::<u32>().ok()
+                })
+                == Some(pgid)
+        })
+        .collect()
+}
+
+/// Thread group, peak RSS in bytes and command name of task `pid`
+fn read_status(pid: u32) -> Option<(u32, u64, String)> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let field = |name: &str| {
+        status
+            .lines()
+            .find_map(|line| line.strip_prefix(name))
+            .map(str::trim)
+    };
+    let tgid = field("Tgid:")?.parse().ok()?;
+    // Kernel threads and exited processes have no address space
+    let peak_kb: u64 = field("VmHWM:")?
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    let command = field("Name:").unwrap_or_default().to_string();
+    Some((tgid, peak_kb * 1024, command))
+}
@@ -0,0 +1,265 @@
+/// Language profiles: how a language is built and run, and what sandbox it needs
+///
+/// A profile holds the compile and run commands of a language together with
+/// the sandbox adjustments applied in both phases (limits, namespaces,
+/// environment). Profiles are looked up in this order: those registered at
+/// runtime with `LanguageProfile::register`, the `profile` section of the
+/// language in config.json, then the built-in ones. A profile replaces the one
+/// it shadows as a whole.
+///
+/// Commands may use `{source}` (source file name in the workdir), `{stem}`
+/// (that name without its extension) and `{code}` (the submitted source).
+use crate::config::{FileConventions, RustBoxConfig};
+use crate::types::{IsolateConfig, IsolateError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+/// Go toolchain of the Debian/Ubuntu golang-1.22 package
+const GO_ROOT: &str = "/usr/lib/go-1.22";
+
+/// Profiles registered at runtime, by language name
+static REGISTERED: RwLock<BTreeMap<String, LanguageProfile>> = RwLock::new(BTreeMap::new());
+
+/// Build and run settings of one language
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LanguageProfile {
+    /// Canonical language name, lowercase
+    pub name: String,
+    /// Name used in compile error messages ("Java Compilation Error")
+    pub display_name: Option<String>,
+    /// Compiler command; `None` for interpreted languages
+    pub compile: Option<Vec<String>>,
+    /// Command starting the program
+    pub run: Vec<String>,
+    /// Process limit in both phases (runtime threads count against it)
+    pub process_limit: Option<u32>,
+    /// Memory limit in MB in both phases
+    pub memory_limit_mb: Option<u64>,
+    /// CPU time limit in seconds in both phases, unless the run overrides it
+    pub cpu_time_seconds: Option<u64>,
+    /// Wall clock limit in seconds in both phases, unless the run overrides it
+    pub wall_time_seconds: Option<u64>,
+    /// Run in a PID namespace; the JVM needs to see host processes
+    pub pid_namespace: bool,
+    /// Run in a network namespace; without one the program has the host network
+    pub network_namespace: bool,
+    /// Variables added to the sandbox environment unless the box sets them
+    pub environment: BTreeMap<String, String>,
+    /// File conventions for a language `LanguageRegistry` does not know yet
+    pub files: Option<FileConventions>,
+}
+
+impl Default for LanguageProfile {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            display_name: None,
+            compile: None,
+            run: Vec::new(),
+            process_limit: None,
+            memory_limit_mb: None,
+            cpu_time_seconds: None,
+            wall_time_seconds: None,
+            pid_namespace: true,
+            network_namespace: true,
+            environment: BTreeMap::new(),
+            files: None,
+        }
+    }
+}
+
+impl LanguageProfile {
+    /// Profiles for the languages rustbox supports out of the box
+    pub fn builtin() -> Vec<Self> {
+        vec![
+            Self {
+                name: "python".to_string(),
+                run: strings(&["/usr/bin/python3", "-u", "-c", "{code}"]),
+                ..Default::default()
+            },
+            Self {
+                name: "cpp".to_string(),
+                compile: Some(strings(&[
+                    "g++",
+                    "-o",
+                    "solution",
+                    "{source}",
+                    "-std=c++17",
+                    "-O2",
+                ])),
+                run: strings(&["./solution"]),
+                // The compiler driver runs cc1plus, as and ld as separate processes
+                process_limit: Some(30),
+                memory_limit_mb: Some(256),
+                ..Default::default()
+            },
+            Self {
+                name: "java".to_string(),
+                display_name: Some("Java".to_string()),
+                compile: Some(strings(&["javac", "-cp", ".", "{source}"])),
+                // The class to run is the source file's stem (Main.java -> Main)
+                run: strings(&["java", "-cp", ".", "{stem}"]),
+                // JVM threads count as processes
+                process_limit: Some(50),
+                memory_limit_mb: Some(512),
+                pid_namespace: false,
+                network_namespace: false,
+                ..Default::default()
+            },
+            Self {
+                name: "go".to_string(),
+                compile: Some(strings(&["go", "build", "-o", "solution", "{source}"])),
+                run: strings(&["./solution"]),
+                // The go tool runs compile and link as subprocesses, each with many threads
+                process_limit: Some(64),
+                memory_limit_mb: Some(512),
+                environment: go_environment(),
+                ..Default::default()
+            },
+        ]
+    }
+
+    /// Make `profile` available to every later compile and run in this process
+    ///
+    /// Replaces a profile registered earlier for the same language, and
+    /// shadows the config.json and built-in ones.
+    pub fn register(mut profile: Self) -> Result<()> {
+        profile.name = profile.name.to_lowercase();
+        profile.validate()?;
+        REGISTERED
+            .write()
+            .map_err(|_| IsolateError::Config("Language profile registry poisoned".to_string()))?
+            .insert(profile.name.clone(), profile);
+        Ok(())
+    }
+
+    /// Profile for the canonical language name `language`
+    pub fn get(language: &str) -> Option<Self> {
+        let language = language.to_lowercase();
+        if let Some(profile) = REGISTERED.read().ok()?.get(&language) {
+            return Some(profile.clone());
+        }
+        let configured = RustBoxConfig::load_default().ok().and_then(|config| {
+            let mut profile = config.get_language_config(&language)?.profile.clone()?;
+            profile.name = language.clone();
+            Some(profile)
+        });
+        match configured {
+            Some(profile) if profile.validate().is_ok() => Some(profile),
+            Some(profile) => {
+                log::warn!("Ignoring invalid config.json profile for {}", profile.name);
+                Self::builtin().into_iter().find(|p| p.name == language)
+            }
+            None => Self::builtin().into_iter().find(|p| p.name == language),
+        }
+    }
+
+    /// Registered profiles that bring their own file conventions
+    pub fn registered_files() -> Vec<(String, FileConventions)> {
+        REGISTERED
+            .read()
+            .map(|registered| {
+                registered
+                    .values()
+                    .filter_map(|profile| Some((profile.name.clone(), profile.files.clone()?)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.name.is_empty() {
+            return Err(IsolateError::Config(
+                "Language profile needs a name".to_string(),
+            ));
+        }
+        if self.run.is_empty() || self.compile.as_ref().is_some_and(Vec::is_empty) {
+            return Err(IsolateError::Config(format!(
+                "Language profile {} has an empty command",
+                self.name
+            )));
+        }
+        if self
+            .files
+            .as_ref()
+            .is_some_and(|files| files.source_file.is_empty())
+        {
+            return Err(IsolateError::Config(format!(
+                "Language profile {} has no source file name",
+                self.name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Compile and run commands for source `code` written to `source_name`
+    pub fn commands(&self, source_name: &str, code: &str) -> (Option<Vec<String>>, Vec<String>) {
+        let stem = source_name
+            .rsplit_once('.')
+            .map_or(source_name, |(stem, _)| stem);
+        let expand = |command: &Vec<String>| -> Vec<String> {
+            command
+                .iter()
+                .map(|arg| match arg.as_str() {
+                    // The source may itself contain placeholder text
+                    "{code}" => code.to_string(),
+                    _ => arg.replace("{source}", source_name).replace("{stem}", stem),
+                })
+                .collect()
+        };
+        (self.compile.as_ref().map(expand), expand(&self.run))
+    }
+
+    /// Whether the source has to be written to the workdir, i.e. it is not
+    /// passed inline as `{code}` to an interpreter
+    pub fn stages_source(&self) -> bool {
+        self.compile.is_some() || !self.run.iter().any(|arg| arg == "{code}")
+    }
+
+    /// Apply the profile's sandbox adjustments to `config`
+    pub fn apply(&self, config: &mut IsolateConfig) {
+        if let Some(process_limit) = self.process_limit {
+            config.process_limit = Some(process_limit);
+        }
+        if let Some(memory_limit_mb) = self.memory_limit_mb {
+            config.memory_limit = Some(memory_limit_mb * 1024 * 1024);
+        }
+        if let Some(cpu_time_seconds) = self.cpu_time_seconds {
+            config.cpu_time_limit = Some(Duration::from_secs(cpu_time_seconds));
+            config.time_limit = Some(Duration::from_secs(cpu_time_seconds));
+        }
+        if let Some(wall_time_seconds) = self.wall_time_seconds {
+            config.wall_time_limit = Some(Duration::from_secs(wall_time_seconds));
+        }
+        if !self.pid_namespace {
+            config.enable_pid_namespace = false;
+        }
+        if !self.network_namespace {
+            config.enable_network_namespace = false;
+        }
+        for (key, value) in &self.environment {
+            if !config.environment.iter().any(|(name, _)| name == key) {
+                config.environment.push((key.clone(), value.clone()));
+            }
+        }
+    }
+}
+
+/// Build cache and module path in the writable /tmp, GOROOT when installed there
+fn go_environment() -> BTreeMap<String, String> {
+    let mut environment = BTreeMap::from([
+        ("GOCACHE".to_string(), "/tmp/gocache".to_string()),
+        ("GOPATH".to_string(), "/tmp/gopath".to_string()),
+    ]);
+    if std::path::Path::new(GO_ROOT).exists() {
+        environment.insert("GOROOT".to_string(), GO_ROOT.to_string());
+    }
+    environment
+}
+
+fn strings(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
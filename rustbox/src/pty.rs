@@ -0,0 +1,125 @@
+/// Pseudo-terminals for programs that need a terminal
+///
+/// With `enable_tty` the program's stdin, stdout and stderr are the slave side
+/// of a fresh pty, which also becomes its controlling terminal: `isatty` holds,
+/// prompts and REPLs behave as they do for a user and curses programs get a
+/// window size. The supervisor keeps the master side. Input is typed into it
+/// and ended with ^D, and everything the terminal shows is collected as stdout
+/// byte for byte, echoed input and CRLF line endings included.
+use crate::types::{IsolateError, Result, TtySize};
+use std::io::Write;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::process::Stdio;
+
+/// The terminal's end-of-file character (`VEOF`, ^D by default)
+const EOF: u8 = 0x04;
+
+/// A pty pair opened for one run
+#[derive(Debug)]
+pub struct Pty {
+    master: OwnedFd,
+    slave: OwnedFd,
+}
+
+impl Pty {
+    /// Open a pty with a window of `size`
+    pub fn open(size: TtySize) -> Result<Self> {
+        let window = libc::winsize {
+            ws_row: size.rows,
+            ws_col: size.cols,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        let (mut master, mut slave) = (-1, -1);
+        let opened = unsafe {
+            libc::openpty(
+                &mut master,
+                &mut slave,
+                std::ptr::null_mut(),
+                std::ptr::null(),
+                &window,
+            )
+        };
+        if opened != 0 {
+            return Err(IsolateError::ResourceUnavailable(format!(
+                "Failed to open a pseudo-terminal: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        let pty = unsafe {
+            Self {
+                master: OwnedFd::from_raw_fd(master),
+                slave: OwnedFd::from_raw_fd(slave),
+            }
+        };
+        // Only the program gets the terminal, through its standard streams
+        for fd in [pty.master.as_raw_fd(), pty.slave.as_raw_fd()] {
+            if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } != 0 {
+                return Err(IsolateError::ResourceUnavailable(format!(
+                    "Failed to set up the pseudo-terminal: {}",
+                    std::io::Error::last_os_error()
+                )));
+            }
+        }
+        Ok(pty)
+    }
+
+    /// The slave side as one of the program's standard streams
+    pub fn slave(&self) -> Result<Stdio> {
+        let slave = self.slave.try_clone().map_err(|e| {
+            IsolateError::ResourceUnavailable(format!("Failed to share the terminal: {}", e))
+        })?;
+        Ok(Stdio::from(slave))
+    }
+
+    /// Descriptor of the slave side, open in the child until exec
+    pub fn slave_fd(&self) -> RawFd {
+        self.slave.as_raw_fd()
+    }
+
+    /// Close the slave side and keep the master
+    ///
+    /// Once the program and everything it started have closed the terminal,
+    /// reading the master fails with `EIO`, which marks the end of the output.
+    pub fn into_master(self) -> OwnedFd {
+        self.master
+    }
+}
+
+/// Make the slave at `fd` the controlling terminal of a new session
+///
+/// Runs in the forked child in place of `setpgid`: the session's process group
+/// is led by the program, so the whole tree can still be killed at once.
+pub fn make_controlling(fd: RawFd) -> std::io::Result<()> {
+    unsafe {
+        if libc::setsid() < 0 || libc::ioctl(fd, libc::TIOCSCTTY, 0) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Type `input` into the terminal on its own thread, then end the input
+///
+/// A line is buffered by the terminal until the program reads it, so writing
+/// may block; the thread ends with an error once the program is gone.
+pub fn feed(master: &OwnedFd, input: String) -> Result<()> {
+    let mut terminal = std::fs::File::from(master.try_clone().map_err(|e| {
+        IsolateError::ResourceUnavailable(format!("Failed to share the terminal: {}", e))
+    })?);
+    std::thread::Builder::new()
+        .name("rustbox-tty-input".to_string())
+        .spawn(move || {
+            let mut input = input.into_bytes();
+            // ^D ends a pending partial line, and another then reads as end of file
+            if !input.is_empty() && !input.ends_with(b"\n") {
+                input.push(EOF);
+            }
+            input.push(EOF);
+            let _ = terminal.write_all(&input);
+        })
+        .map_err(|e| {
+            IsolateError::ResourceUnavailable(format!("Failed to start terminal input: {}", e))
+        })?;
+    Ok(())
+}
@@ -0,0 +1,342 @@
+/// Priority queue of submissions run by a fixed set of workers
+///
+/// Lets one rustbox daemon keep the machine busy without an external
+/// orchestrator: submissions are queued with a priority (higher first, in
+/// submission order among equals) and optionally a group with a concurrency
+/// limit, so one contest or tenant cannot take every worker. Each worker owns
+/// one box (`first_box_id + n`) and runs the submissions it pulls in it like
+/// `execute-code`, cleaning the box up after each one.
+///
+/// Results of finished jobs are kept in memory, up to `MAX_FINISHED`, for
+/// clients polling `job`.
+use crate::isolate::{CleanupPolicy, Isolate};
+use crate::types::{ExecutionOverrides, ExecutionResult, IsolateConfig, IsolateError, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Submissions waiting at most; further ones are rejected
+pub const MAX_QUEUED: usize = 10_000;
+
+/// Finished jobs whose results are kept
+pub const MAX_FINISHED: usize = 1_000;
+
+/// Queue settings
+#[derive(Debug, Clone)]
+pub struct QueueOptions {
+    /// Submissions run concurrently, one box each
+    pub workers: usize,
+    /// Box of the first worker; worker n uses `first_box_id + n`
+    pub first_box_id: u32,
+}
+
+/// Source compiled and run like `execute-code`
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Submission {
+    pub language: String,
+    pub code: String,
+    pub stdin: Option<String>,
+    pub limits: ExecutionOverrides,
+    /// Limits for the compile step; unset fields come from config.json
+    pub compile_limits: ExecutionOverrides,
+    /// Higher runs first
+    pub priority: i32,
+    /// Submissions sharing a group count against `max_concurrent`
+    pub group: Option<String>,
+    /// Most submissions of `group` running at once
+    pub max_concurrent: Option<usize>,
+}
+
+/// Where a job is
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum JobState {
+    Queued,
+    Running {
+        box_id: u32,
+    },
+    /// Ran to a verdict; `result` is the CLI JSON of the execution
+    Finished {
+        result: serde_json::Value,
+    },
+    /// Could not be compiled or run
+    Failed {
+        error: String,
+    },
+}
+
+/// A submitted job
+#[derive(Debug, Clone, Serialize)]
+pub struct Job {
+    pub id: u64,
+    pub language: String,
+    pub priority: i32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    pub submitted: chrono::DateTime<chrono::Utc>,
+    #[serde(flatten)]
+    pub state: JobState,
+}
+
+/// Queue overview
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueStatus {
+    pub workers: usize,
+    pub queued: usize,
+    pub running: usize,
+    /// Finished jobs still kept
+    pub finished: usize,
+    /// Running jobs, then queued ones in the order they will be picked
+    pub jobs: Vec<Job>,
+}
+
+/// Runs a submission in a worker's box
+type Runner = dyn Fn(u32, &Submission) -> Result<ExecutionResult> + Send + Sync;
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    pending: Vec<(u64, Submission)>,
+    jobs: HashMap<u64, Job>,
+    /// Running jobs per group
+    running_groups: HashMap<String, usize>,
+    finished: VecDeque<u64>,
+    stopping: bool,
+}
+
+impl State {
+    /// Remove the highest-priority pending submission whose group has room
+    fn take_next(&mut self) -> Option<(u64, Submission)> {
+        let index = self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, submission))| self.group_has_room(submission))
+            .max_by_key(|(_, (id, submission))| (submission.priority, Reverse(*id)))
+            .map(|(index, _)| index)?;
+        Some(self.pending.swap_remove(index))
+    }
+
+    /// Whether `submission` may start without exceeding its group's limit
+    fn group_has_room(&self, submission: &Submission) -> bool {
+        match (&submission.group, submission.max_concurrent) {
+            (Some(group), Some(max)) => self.running_groups.get(group).copied().unwrap_or(0) < max,
+            _ => true,
+        }
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    /// Signalled when a submission arrives or a group slot frees up
+    available: Condvar,
+    workers: usize,
+}
+
+/// Queue with its workers; dropping it stops them after their current job
+pub struct ExecutionQueue {
+    shared: Arc<Shared>,
+}
+
+impl ExecutionQueue {
+    /// Start `options.workers` workers running submissions in their boxes
+    pub fn start(options: QueueOptions) -> Result<Self> {
+        Self::with_runner(options, run_submission)
+    }
+
+    /// Start the workers with `runner` in place of `run_submission`
+    pub fn with_runner(
+        options: QueueOptions,
+        runner: impl Fn(u32, &Submission) -> Result<ExecutionResult> + Send + Sync + 'static,
+    ) -> Result<Self> {
+        let workers = options.workers.max(1);
+        let last_box_id = options
+            .first_box_id
+            .checked_add(workers as u32)
+            .ok_or_else(|| {
+                IsolateError::Config(format!(
+                    "{} queue workers from box {} overflow the box id range",
+                    workers, options.first_box_id
+                ))
+            })?;
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State::default()),
+            available: Condvar::new(),
+            workers,
+        });
+        let runner: Arc<Runner> = Arc::new(runner);
+        for box_id in options.first_box_id..last_box_id {
+            let shared = Arc::clone(&shared);
+            let runner = Arc::clone(&runner);
+            std::thread::Builder::new()
+                .name(format!("rustbox-queue-{}", box_id))
+                .spawn(move || work(&shared, box_id, runner.as_ref()))?;
+        }
+        Ok(Self { shared })
+    }
+
+    /// Queue `submission`; returns its job id
+    pub fn submit(&self, submission: Submission) -> Result<u64> {
+        if submission.language.is_empty() {
+            return Err(IsolateError::Config(
+                "Submission needs a language".to_string(),
+            ));
+        }
+        match (&submission.group, submission.max_concurrent) {
+            (_, Some(0)) => {
+                return Err(IsolateError::Config(
+                    "max_concurrent must be at least 1".to_string(),
+                ))
+            }
+            (None, Some(_)) => {
+                return Err(IsolateError::Config(
+                    "max_concurrent needs a group".to_string(),
+                ))
+            }
+            _ => {}
+        }
+
+        let mut state = self.shared.state.lock().unwrap();
+        if state.pending.len() >= MAX_QUEUED {
+            return Err(IsolateError::ResourceUnavailable(format!(
+                "Execution queue is full ({} submissions waiting)",
+                MAX_QUEUED
+            )));
+        }
+        state.next_id += 1;
+        let id = state.next_id;
+        state.jobs.insert(
+            id,
+            Job {
+                id,
+                language: submission.language.clone(),
+                priority: submission.priority,
+                group: submission.group.clone(),
+                submitted: chrono::Utc::now(),
+                state: JobState::Queued,
+            },
+        );
+        state.pending.push((id, submission));
+        self.shared.available.notify_one();
+        Ok(id)
+    }
+
+    /// Job `id`, unless it is unknown or its result was dropped
+    pub fn job(&self, id: u64) -> Option<Job> {
+        self.shared.state.lock().unwrap().jobs.get(&id).cloned()
+    }
+
+    /// Counts and the jobs not finished yet
+    pub fn status(&self) -> QueueStatus {
+        let state = self.shared.state.lock().unwrap();
+        let mut running: Vec<Job> = state
+            .jobs
+            .values()
+            .filter(|job| matches!(job.state, JobState::Running { .. }))
+            .cloned()
+            .collect();
+        running.sort_by_key(|job| job.id);
+        let mut queued: Vec<Job> = state
+            .pending
+            .iter()
+            .filter_map(|(id, _)| state.jobs.get(id).cloned())
+            .collect();
+        queued.sort_by_key(|job| (Reverse(job.priority), job.id));
+
+        QueueStatus {
+            workers: self.shared.workers,
+            queued: queued.len(),
+            running: running.len(),
+            finished: state.finished.len(),
+            jobs: running.into_iter().chain(queued).collect(),
+        }
+    }
+}
+
+impl Drop for ExecutionQueue {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().stopping = true;
+        self.shared.available.notify_all();
+    }
+}
+
+/// Worker loop: run the next submission that may run, until the queue stops
+fn work(shared: &Shared, box_id: u32, runner: &Runner) {
+    loop {
+        let (id, submission) = {
+            let mut state = shared.state.lock().unwrap();
+            let next = loop {
+                if state.stopping {
+                    return;
+                }
+                if let Some(next) = state.take_next() {
+                    break next;
+                }
+                state = shared.available.wait(state).unwrap();
+            };
+            if let Some(group) = &next.1.group {
+                *state.running_groups.entry(group.clone()).or_default() += 1;
+            }
+            if let Some(job) = state.jobs.get_mut(&next.0) {
+                job.state = JobState::Running { box_id };
+            }
+            next
+        };
+
+        let outcome = match runner(box_id, &submission) {
+            Ok(result) => {
+                let mut result = result.to_cli_json();
+                result["language"] = serde_json::json!(submission.language);
+                JobState::Finished { result }
+            }
+            Err(e) => JobState::Failed {
+                error: e.to_string(),
+            },
+        };
+
+        let mut state = shared.state.lock().unwrap();
+        if let Some(group) = &submission.group {
+            if let Some(running) = state.running_groups.get_mut(group) {
+                *running -= 1;
+            }
+        }
+        if let Some(job) = state.jobs.get_mut(&id) {
+            job.state = outcome;
+        }
+        state.finished.push_back(id);
+        while state.finished.len() > MAX_FINISHED {
+            if let Some(oldest) = state.finished.pop_front() {
+                state.jobs.remove(&oldest);
+            }
+        }
+        // A group slot may have freed up for a waiting submission
+        shared.available.notify_all();
+    }
+}
+
+/// Compile and run `submission` in box `box_id`, removing the box afterwards
+pub fn run_submission(box_id: u32, submission: &Submission) -> Result<ExecutionResult> {
+    let config =
+        IsolateConfig::with_language_defaults(&submission.language, format!("rustbox/{}", box_id))?;
+    let mut isolate = Isolate::new(config)?;
+    let result = compile_and_run(&mut isolate, submission);
+    if let Err(e) = isolate.finish(CleanupPolicy::Always) {
+        log::warn!("Failed to clean up queue box {}: {}", box_id, e);
+    }
+    result
+}
+
+/// Compile `submission` in `isolate` and run it, or report the compile error
+pub fn compile_and_run(isolate: &mut Isolate, submission: &Submission) -> Result<ExecutionResult> {
+    let program = isolate.compile(
+        &submission.language,
+        &submission.code,
+        &submission.compile_limits,
+    )?;
+    match program.failure() {
+        Some(compile_error) => Ok(compile_error),
+        None => isolate.run(&program, submission.stdin.as_deref(), &submission.limits),
+    }
+}
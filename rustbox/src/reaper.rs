@@ -0,0 +1,132 @@
+/// Reaper process between the executor and the program
+///
+/// Orphans of the program are handed to the nearest child subreaper, else to
+/// the host's init, and a multi-process submission whose orphans nobody waits
+/// for leaves zombies counting against the pids limit. `fork_reaper` runs in
+/// the spawned child between fork and exec: it makes that process a child
+/// subreaper (`PR_SET_CHILD_SUBREAPER`), forks the program and stays behind as
+/// its parent, like the init of a PID namespace. It reaps every descendant
+/// reparented to it, passes signals sent to it on to the program, and exits the
+/// way the program did, so the executor sees the program's status. Everything
+/// here runs after fork in a multithreaded process, so only raw syscalls.
+use std::ffi::CStr;
+use std::io;
+
+/// Command name of the reaper, so it can be told apart from the program
+const NAME: &CStr = c"rustbox-reaper";
+
+/// Signals sent to the reaper that are passed on to the program
+const FORWARDED: [libc::c_int; 7] = [
+    libc::SIGHUP,
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTERM,
+    libc::SIGUSR1,
+    libc::SIGUSR2,
+    libc::SIGALRM,
+];
+
+/// Become the reaper and fork the program
+///
+/// Returns only in the program's process, which goes on to exec; the reaper
+/// exits when the program does.
+pub fn fork_reaper() -> io::Result<()> {
+    unsafe {
+        if libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Blocked before the fork, so none can kill the reaper before it waits
+        let mut signals: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        for signal in FORWARDED {
+            libc::sigaddset(&mut signals, signal);
+        }
+        libc::sigaddset(&mut signals, libc::SIGCHLD);
+        let mut previous: libc::sigset_t = std::mem::zeroed();
+        if libc::sigprocmask(libc::SIG_BLOCK, &signals, &mut previous) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        match libc::fork() {
+            -1 => Err(io::Error::last_os_error()),
+            0 => {
+                libc::sigprocmask(libc::SIG_SETMASK, &previous, std::ptr::null_mut());
+                Ok(())
+            }
+            program => supervise(program, &signals),
+        }
+    }
+}
+
+/// Reap children and forward signals until the program exits
+unsafe fn supervise(program: libc::pid_t, signals: &libc::sigset_t) -> ! {
+    libc::prctl(libc::PR_SET_NAME, NAME.as_ptr(), 0, 0, 0);
+
+    // Holding no descriptors, the reaper cannot delay EOF on the program's
+    // output or the spawn error pipe
+    if libc::syscall(libc::SYS_close_range, 0, libc::c_uint::MAX, 0) != 0 {
+        for fd in 0..1024 {
+            libc::close(fd);
+        }
+    }
+
+    loop {
+        loop {
+            let mut status = 0;
+            let pid = libc::waitpid(-1, &mut status, libc::WNOHANG);
+            if pid <= 0 {
+                break;
+            }
+            if pid == program {
+                exit_like(status);
+            }
+        }
+
+        // SIGCHLD stays pending while blocked, so no exit is missed between
+        // the waits above and this one
+        let mut info: libc::siginfo_t = std::mem::zeroed();
+        let signal = libc::sigwaitinfo(signals, &mut info);
+        // Signals from the terminal reach the program's process group already
+        if signal > 0 && signal != libc::SIGCHLD && info.si_code <= 0 {
+            libc::kill(program, signal);
+        }
+    }
+}
+
+/// Whether `pid` is the reaper of a run waited for by `supervisor`
+///
+/// Only a child of the supervisor counts, so a program taking the reaper's
+/// name is not mistaken for it.
+pub fn is_reaper(pid: u32, supervisor: u32) -> bool {
+    let Ok(stat) = std::fs::read_to_string(format!("/proc/{}/stat", pid)) else {
+        return false;
+    };
+    // "pid (comm) state ppid ..."
+    let Some((head, fields)) = stat.rsplit_once(')') else {
+        return false;
+    };
+    head.split_once('(')
+        .is_some_and(|(_, comm)| comm.as_bytes() == NAME.to_bytes())
+        && fields.split_whitespace().nth(1) == Some(supervisor.to_string().as_str())
+}
+
+/// Exit with the program's wait `status`, dying of its signal if it did
+unsafe fn exit_like(status: libc::c_int) -> ! {
+    if libc::WIFSIGNALED(status) {
+        let signal = libc::WTERMSIG(status);
+        let no_core = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        libc::setrlimit(libc::RLIMIT_CORE, &no_core);
+        libc::signal(signal, libc::SIG_DFL);
+        let mut unblock: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut unblock);
+        libc::sigaddset(&mut unblock, signal);
+        libc::sigprocmask(libc::SIG_UNBLOCK, &unblock, std::ptr::null_mut());
+        libc::kill(libc::getpid(), signal);
+        libc::_exit(128 + signal);
+    }
+    libc::_exit(libc::WEXITSTATUS(status))
+}
@@ -0,0 +1,201 @@
+/// Parallel regrade of stored submissions with verdict drift reporting
+///
+/// A manifest lists previous runs (language, code, input, limits and the verdict
+/// they got). Regrading re-executes every run on a pool of workers, one sandbox
+/// per worker, and reports which runs changed verdict, e.g. after a toolchain or
+/// limit change.
+use crate::fetch::DataCache;
+use crate::isolate::{CleanupPolicy, Isolate};
+use crate::types::{ExecutionOverrides, ExecutionStatus, IsolateConfig, IsolateError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// A stored submission to re-execute
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegradeRun {
+    pub id: String,
+    pub language: String,
+    pub code: String,
+    #[serde(default)]
+    pub stdin: Option<String>,
+    /// Test data reference for stdin (see `fetch`)
+    #[serde(default)]
+    pub stdin_ref: Option<String>,
+    #[serde(default)]
+    pub stdin_sha256: Option<String>,
+    #[serde(default)]
+    pub limits: ExecutionOverrides,
+    /// What the run produced last time
+    #[serde(default)]
+    pub previous: Option<PreviousRun>,
+}
+
+/// Verdict and output recorded for a run when it was last graded
+#[derive(Debug, Clone, Deserialize)]
+pub struct PreviousRun {
+    /// Status label as printed by rustbox (`TLE`, `Success`, ...) or status name
+    pub status: String,
+    /// Previous stdout; compared (ignoring trailing whitespace) when present
+    #[serde(default)]
+    pub stdout: Option<String>,
+}
+
+/// Result of regrading one run
+#[derive(Debug, Clone, Serialize)]
+pub struct RegradeOutcome {
+    pub id: String,
+    pub previous_status: Option<String>,
+    pub status: Option<String>,
+    /// Verdict differs from the previous one
+    pub changed: bool,
+    /// Output differs from the previous one (only when previous output is known)
+    pub stdout_changed: bool,
+    pub cpu_time: f64,
+    pub wall_time: f64,
+    /// Why the run could not be executed
+    pub error: Option<String>,
+}
+
+/// Drift summary over a whole regrade
+#[derive(Debug, Clone, Serialize)]
+pub struct DriftReport {
+    pub total: usize,
+    pub unchanged: usize,
+    pub changed: usize,
+    pub errors: usize,
+    /// Runs whose verdict or output changed, or that failed to run
+    pub drift: Vec<RegradeOutcome>,
+}
+
+impl DriftReport {
+    pub fn from_outcomes(outcomes: &[RegradeOutcome]) -> Self {
+        let errors = outcomes.iter().filter(|o| o.error.is_some()).count();
+        let drift: Vec<RegradeOutcome> = outcomes
+            .iter()
+            .filter(|o| o.error.is_some() || o.changed || o.stdout_changed)
+            .cloned()
+            .collect();
+        let changed = drift.len() - errors;
+        Self {
+            total: outcomes.len(),
+            unchanged: outcomes.len() - drift.len(),
+            changed,
+            errors,
+            drift,
+        }
+    }
+}
+
+/// Load a manifest: a JSON array of runs
+pub fn load_manifest(path: &Path) -> Result<Vec<RegradeRun>> {
+    let content = std::fs::read_to_string(path)?;
+    serde_json::from_str(&content).map_err(|e| {
+        IsolateError::Config(format!(
+            "Invalid regrade manifest {}: {}",
+            path.display(),
+            e
+        ))
+    })
+}
+
+/// Re-execute every run on `workers` sandboxes starting at `box_id_base`
+///
+/// Outcomes are returned in manifest order.
+pub fn regrade(runs: Vec<RegradeRun>, workers: usize, box_id_base: u32) -> Vec<RegradeOutcome> {
+    let workers = workers.clamp(1, runs.len().max(1));
+    let total = runs.len();
+    let (job_tx, job_rx) = crossbeam_channel::unbounded();
+    let (result_tx, result_rx) = crossbeam_channel::unbounded();
+    for job in runs.into_iter().enumerate() {
+        let _ = job_tx.send(job);
+    }
+    drop(job_tx);
+
+    std::thread::scope(|scope| {
+        for worker in 0..workers {
+            let job_rx = job_rx.clone();
+            let result_tx = result_tx.clone();
+            let box_id = box_id_base + worker as u32;
+            scope.spawn(move || {
+                let cache = DataCache::default();
+                for (index, run) in job_rx {
+                    let outcome = regrade_one(&run, box_id, &cache);
+                    let _ = result_tx.send((index, outcome));
+                }
+            });
+        }
+    });
+    drop(result_tx);
+
+    let mut outcomes: Vec<Option<RegradeOutcome>> = vec![None; total];
+    for (index, outcome) in result_rx {
+        outcomes[index] = Some(outcome);
+    }
+    outcomes.into_iter().flatten().collect()
+}
+
+fn regrade_one(run: &RegradeRun, box_id: u32, cache: &DataCache) -> RegradeOutcome {
+    let previous_status = run.previous.as_ref().map(|p| p.status.clone());
+    let failed = |error: IsolateError| RegradeOutcome {
+        id: run.id.clone(),
+        previous_status: previous_status.clone(),
+        status: None,
+        changed: false,
+        stdout_changed: false,
+        cpu_time: 0.0,
+        wall_time: 0.0,
+        error: Some(error.to_string()),
+    };
+
+    let stdin_source = match &run.stdin_ref {
+        Some(reference) => match cache.open(reference, run.stdin_sha256.as_deref()) {
+            Ok(file) => Some(file),
+            Err(e) => return failed(e),
+        },
+        None => None,
+    };
+
+    let result =
+        IsolateConfig::with_language_defaults(&run.language, format!("rustbox/{}", box_id))
+            .and_then(Isolate::new)
+            .and_then(|mut isolate| {
+                if let Some(file) = stdin_source {
+                    isolate.set_stdin_source(file);
+                }
+                let result = isolate.execute_code_string(
+                    &run.language,
+                    &run.code,
+                    run.stdin.as_deref(),
+                    &run.limits,
+                );
+                if let Err(e) = isolate.finish(CleanupPolicy::Always) {
+                    log::warn!("Failed to clean up regrade box {}: {}", box_id, e);
+                }
+                result
+            });
+    let result = match result {
+        Ok(result) => result,
+        Err(e) => return failed(e),
+    };
+
+    let changed = run
+        .previous
+        .as_ref()
+        .is_some_and(|previous| ExecutionStatus::from_label(&previous.status) != result.status);
+    let stdout_changed = run
+        .previous
+        .as_ref()
+        .and_then(|previous| previous.stdout.as_deref())
+        .is_some_and(|stdout| stdout.trim_end() != result.stdout.trim_end());
+
+    RegradeOutcome {
+        id: run.id.clone(),
+        previous_status,
+        status: Some(result.status.label()),
+        changed,
+        stdout_changed,
+        cpu_time: result.cpu_time,
+        wall_time: result.wall_time,
+        error: None,
+    }
+}
@@ -0,0 +1,91 @@
+/// Result report formats for the CLI
+///
+/// `json` is rustbox's own output (`ExecutionResult::to_cli_json`). `cms` is the
+/// `key:value` meta file written by IOI isolate, which CMS and similar graders
+/// parse: times in seconds with three decimals, separate `time` (CPU) and
+/// `time-wall`, and `status` set to `RE`, `SG`, `TO` or `XX` for anything but a
+/// clean exit. `judge0` is a Judge0 submission object (see `judge0`).
+use crate::types::{ExecutionResult, ExecutionStatus};
+use std::str::FromStr;
+
+/// Output format of an execution result
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReportProfile {
+    #[default]
+    Json,
+    Cms,
+    Judge0,
+}
+
+impl FromStr for ReportProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ReportProfile::Json),
+            "cms" => Ok(ReportProfile::Cms),
+            "judge0" => Ok(ReportProfile::Judge0),
+            other => Err(format!(
+                "Unknown report profile '{}' (expected json, cms or judge0)",
+                other
+            )),
+        }
+    }
+}
+
+/// Render `result` in the given profile
+pub fn render(result: &ExecutionResult, profile: ReportProfile) -> String {
+    match profile {
+        ReportProfile::Json => serde_json::to_string_pretty(&result.to_cli_json()).unwrap(),
+        ReportProfile::Cms => result.to_isolate_meta().trim_end().to_string(),
+        ReportProfile::Judge0 => {
+            let submission = crate::judge0::SubmissionResponse::new(result, None, false);
+            serde_json::to_string_pretty(&submission).unwrap()
+        }
+    }
+}
+
+/// Isolate-style meta entries for `result`
+pub fn cms_meta(result: &ExecutionResult) -> Vec<(&'static str, String)> {
+    let mut meta = vec![
+        ("time", format!("{:.3}", result.cpu_time)),
+        ("time-wall", format!("{:.3}", result.wall_time)),
+        ("max-rss", (result.memory_peak / 1024).to_string()),
+        ("cg-mem", (result.memory_peak / 1024).to_string()),
+    ];
+
+    let status = Some(result.isolate_status()).filter(|status| *status != "OK");
+    let killed = matches!(
+        result.status,
+        ExecutionStatus::TimeLimit | ExecutionStatus::IdleTimeout | ExecutionStatus::MemoryLimit
+    );
+
+    if result.status == ExecutionStatus::MemoryLimit {
+        meta.push(("cg-oom-killed", "1".to_string()));
+    }
+    match (status, result.signal, result.exit_code) {
+        (Some("SG"), signal, _) => meta.push(("exitsig", signal.unwrap_or(9).to_string())),
+        (Some("XX"), _, _) => {}
+        (_, _, Some(code)) => meta.push(("exitcode", code.to_string())),
+        _ => {}
+    }
+    if killed {
+        meta.push(("killed", "1".to_string()));
+    }
+
+    if let Some(status) = status {
+        let message = match (status, &result.error_message) {
+            ("TO", _) => "Time limit exceeded".to_string(),
+            (_, Some(message)) => message.clone(),
+            ("SG", _) => format!("Caught fatal signal {}", result.signal.unwrap_or(9)),
+            ("RE", _) => format!(
+                "Exited with error status {}",
+                result.exit_code.unwrap_or_default()
+            ),
+            _ => "Internal error".to_string(),
+        };
+        meta.push(("status", status.to_string()));
+        meta.push(("message", message));
+    }
+    meta
+}
@@ -0,0 +1,249 @@
+/// Content-addressed cache of execution results
+///
+/// Mass contests see the same submission many times over (templates, shared
+/// or copied solutions), and running it again on the same input under the
+/// same limits gives the same verdict. A `CacheKey` is the SHA-256 of
+/// everything that decides the result: language, code, stdin, the effective
+/// sandbox configuration, the run and compile limits and the rustbox version.
+/// Results are kept by pluggable backends, an in-memory LRU for a long-running
+/// server and one JSON file per key on disk, shared by every CLI invocation.
+/// Verdicts that depend on host load or on an outside actor (time limits,
+/// idle timeouts, cancellations, kills, internal errors) are never stored.
+use crate::paths::paths;
+use crate::types::{
+    ExecutionOverrides, ExecutionResult, ExecutionStatus, IsolateConfig, IsolateError, Result,
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Hex SHA-256 identifying one submission under one set of limits
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey(String);
+
+impl CacheKey {
+    /// Key of running `code` in `language` on `stdin` in a sandbox set up as `config`
+    ///
+    /// What identifies the box (instance id, workdir, the uid/gid derived from
+    /// its id and the CPUs it is pinned to) is left out, so the same submission
+    /// hits the cache whichever box runs it.
+    pub fn new(
+        language: &str,
+        code: &str,
+        stdin: Option<&str>,
+        config: &IsolateConfig,
+        limits: &ExecutionOverrides,
+        compile_limits: &ExecutionOverrides,
+    ) -> Result<Self> {
+        let mut config = config.clone();
+        config.instance_id = String::new();
+        config.workdir = PathBuf::new();
+        config.uid = None;
+        config.gid = None;
+        config.cpu_affinity = None;
+        let config = serde_json::to_value(&config).map_err(|e| {
+            IsolateError::Config(format!("Failed to serialize configuration: {}", e))
+        })?;
+        // Serialized as one JSON array, so no two different inputs hash alike
+        let material = serde_json::json!([
+            env!("CARGO_PKG_VERSION"),
+            language,
+            code,
+            stdin,
+            config,
+            format!("{:?}", limits),
+            format!("{:?}", compile_limits),
+        ]);
+        let digest = Sha256::digest(material.to_string().as_bytes());
+        Ok(Self(digest.iter().map(|b| format!("{:02x}", b)).collect()))
+    }
+
+    /// Key of the same submission reading stdin from a file with SHA-256 `sha256`
+    pub fn with_stdin_file(self, sha256: &str) -> Self {
+        let material = serde_json::json!(["stdin-file", self.0, sha256]);
+        let digest = Sha256::digest(material.to_string().as_bytes());
+        Self(digest.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Whether `result` would come out the same if the submission ran again
+pub fn is_cacheable(result: &ExecutionResult) -> bool {
+    match result.status {
+        ExecutionStatus::TimeLimit
+        | ExecutionStatus::IdleTimeout
+        | ExecutionStatus::Cancelled
+        | ExecutionStatus::InternalError
+        | ExecutionStatus::Unknown => false,
+        // SIGKILL comes from `rustbox kill` or the OOM killer, not the program
+        ExecutionStatus::Signaled => result.signal != Some(libc::SIGKILL),
+        _ => true,
+    }
+}
+
+/// Storage for cached results
+pub trait CacheBackend: Send + Sync {
+    fn get(&self, key: &CacheKey) -> Option<ExecutionResult>;
+    fn put(&self, key: &CacheKey, result: &ExecutionResult) -> Result<()>;
+}
+
+/// In-memory cache dropping the least recently used result when full
+#[derive(Debug)]
+pub struct MemoryCache {
+    capacity: usize,
+    entries: Mutex<LruEntries>,
+}
+
+#[derive(Debug, Default)]
+struct LruEntries {
+    results: HashMap<CacheKey, (ExecutionResult, u64)>,
+    clock: u64,
+}
+
+impl MemoryCache {
+    /// Cache holding at most `capacity` results
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(LruEntries::default()),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().map(|e| e.results.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl CacheBackend for MemoryCache {
+    fn get(&self, key: &CacheKey) -> Option<ExecutionResult> {
+        let mut entries = self.entries.lock().ok()?;
+        entries.clock += 1;
+        let now = entries.clock;
+        let (result, last_used) = entries.results.get_mut(key)?;
+        *last_used = now;
+        Some(result.clone())
+    }
+
+    fn put(&self, key: &CacheKey, result: &ExecutionResult) -> Result<()> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+        let mut entries = self
+            .entries
+            .lock()
+            .map_err(|_| IsolateError::ResourceUnavailable("Result cache poisoned".to_string()))?;
+        entries.clock += 1;
+        let now = entries.clock;
+        if !entries.results.contains_key(key) && entries.results.len() >= self.capacity {
+            let oldest = entries
+                .results
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.results.remove(&oldest);
+            }
+        }
+        entries.results.insert(key.clone(), (result.clone(), now));
+        Ok(())
+    }
+}
+
+/// On-disk cache keeping each result as `<key>.json` in one directory
+#[derive(Debug, Clone)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn path(&self, key: &CacheKey) -> PathBuf {
+        self.dir.join(format!("{}.json", key.as_str()))
+    }
+}
+
+impl Default for DiskCache {
+    fn default() -> Self {
+        Self::new(paths().result_cache_dir())
+    }
+}
+
+impl CacheBackend for DiskCache {
+    fn get(&self, key: &CacheKey) -> Option<ExecutionResult> {
+        let content = fs::read(self.path(key)).ok()?;
+        serde_json::from_slice(&content).ok()
+    }
+
+    fn put(&self, key: &CacheKey, result: &ExecutionResult) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let content = serde_json::to_vec(result).map_err(|e| {
+            IsolateError::Config(format!("Failed to serialize execution result: {}", e))
+        })?;
+        // Written aside and renamed, so concurrent readers never see half a result
+        let path = self.path(key);
+        let temp_file = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        fs::write(&temp_file, content)?;
+        fs::rename(&temp_file, &path)?;
+        Ok(())
+    }
+}
+
+/// Backends looked up in order; a hit in a later one fills the earlier ones
+pub struct ResultCache {
+    backends: Vec<Box<dyn CacheBackend>>,
+}
+
+impl ResultCache {
+    pub fn new(backends: Vec<Box<dyn CacheBackend>>) -> Self {
+        Self { backends }
+    }
+
+    /// Cache on disk in the scratch directory
+    pub fn on_disk() -> Self {
+        Self::new(vec![Box::new(DiskCache::default())])
+    }
+
+    /// In-memory LRU of `capacity` results in front of the on-disk cache
+    pub fn layered(capacity: usize) -> Self {
+        Self::new(vec![
+            Box::new(MemoryCache::new(capacity)),
+            Box::new(DiskCache::default()),
+        ])
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<ExecutionResult> {
+        for (layer, backend) in self.backends.iter().enumerate() {
+            if let Some(result) = backend.get(key) {
+                for earlier in &self.backends[..layer] {
+                    let _ = earlier.put(key, &result);
+                }
+                return Some(result);
+            }
+        }
+        None
+    }
+
+    /// Store `result` in every backend if it is cacheable
+    pub fn put(&self, key: &CacheKey, result: &ExecutionResult) {
+        if !is_cacheable(result) {
+            return;
+        }
+        for backend in &self.backends {
+            if let Err(e) = backend.put(key, result) {
+                log::warn!("Failed to cache result {}: {}", key.as_str(), e);
+            }
+        }
+    }
+}
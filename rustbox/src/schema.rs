@@ -0,0 +1,133 @@
+/// Versioned on-disk formats of the instance registry
+///
+/// Version 1 was the bare `{instance_id: instance}` map that `instances.json`
+/// held before it carried a version. From version 2 the map sits under
+/// `instances` next to a `version` number. Older files are migrated one step at
+/// a time when read. Version 2 is the last single-file registry: boxes now keep
+/// one record each in the `instance_store`, `{"version": 1, "instance": ...}`,
+/// and `instances.json` is only read to move its entries there. Fields added to
+/// `IsolateConfig` need only a serde default, not a new version.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// Last version of the single-file registry
+pub const REGISTRY_VERSION: u64 = 2;
+
+/// Version of the per-instance records written by this build
+pub const RECORD_VERSION: u64 = 1;
+
+/// `MIGRATIONS[n - 1]` turns a version `n` document into version `n + 1`
+const MIGRATIONS: &[fn(Value) -> Value] = &[v1_to_v2];
+
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("expected an object of instances")]
+    NotAnObject,
+    #[error("registry version {0} is newer than this rustbox supports ({REGISTRY_VERSION})")]
+    TooNew(u64),
+    #[error("record version {0} is newer than this rustbox supports ({RECORD_VERSION})")]
+    RecordTooNew(u64),
+    #[error("record has no instance")]
+    NoInstance,
+}
+
+/// Current-version document wrapping the instances map
+#[derive(Serialize)]
+struct Document<'a, T> {
+    version: u64,
+    instances: &'a HashMap<String, T>,
+}
+
+/// Current-version record of one instance
+#[derive(Serialize)]
+struct Record<'a, T> {
+    version: u64,
+    instance: &'a T,
+}
+
+/// Parse a registry of any supported version into its instances
+pub fn parse<T: DeserializeOwned>(content: &str) -> Result<HashMap<String, T>, SchemaError> {
+    let entries = entries(content)?;
+    Ok(serde_json::from_value(Value::Object(entries))?)
+}
+
+/// Migrated but not yet deserialized instances, so callers can skip bad entries
+pub fn entries(content: &str) -> Result<Map<String, Value>, SchemaError> {
+    let mut document: Value = serde_json::from_str(content)?;
+    let mut version = version_of(&document)?;
+    if version > REGISTRY_VERSION {
+        return Err(SchemaError::TooNew(version));
+    }
+    if version < REGISTRY_VERSION {
+        log::info!(
+            "Migrating instances registry from version {} to {}",
+            version,
+            REGISTRY_VERSION
+        );
+    }
+    while version < REGISTRY_VERSION {
+        document = MIGRATIONS[version as usize - 1](document);
+        version += 1;
+    }
+
+    match document {
+        Value::Object(mut document) => match document.remove("instances") {
+            Some(Value::Object(instances)) => Ok(instances),
+            _ => Err(SchemaError::NotAnObject),
+        },
+        _ => Err(SchemaError::NotAnObject),
+    }
+}
+
+/// Serialize `instances` as a current-version registry
+pub fn to_string<T: Serialize>(instances: &HashMap<String, T>) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(&Document {
+        version: REGISTRY_VERSION,
+        instances,
+    })
+}
+
+/// Parse the record of one instance
+pub fn parse_record<T: DeserializeOwned>(content: &str) -> Result<T, SchemaError> {
+    let document: Value = serde_json::from_str(content)?;
+    let version = version_of(&document)?;
+    if version > RECORD_VERSION {
+        return Err(SchemaError::RecordTooNew(version));
+    }
+    match document {
+        Value::Object(mut document) => {
+            let instance = document.remove("instance").ok_or(SchemaError::NoInstance)?;
+            Ok(serde_json::from_value(instance)?)
+        }
+        _ => Err(SchemaError::NotAnObject),
+    }
+}
+
+/// Serialize `instance` as a current-version record
+pub fn record_to_string<T: Serialize>(instance: &T) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(&Record {
+        version: RECORD_VERSION,
+        instance,
+    })
+}
+
+/// A document with a numeric `version` is versioned; any other object is version 1
+fn version_of(document: &Value) -> Result<u64, SchemaError> {
+    let Value::Object(fields) = document else {
+        return Err(SchemaError::NotAnObject);
+    };
+    Ok(fields.get("version").and_then(Value::as_u64).unwrap_or(1))
+}
+
+/// Wrap the bare instances map
+fn v1_to_v2(instances: Value) -> Value {
+    serde_json::json!({
+        "version": 2,
+        "instances": instances,
+    })
+}
@@ -25,6 +25,9 @@ pub enum SecurityError {
 
     #[error("Symlink not allowed in directory binding")]
     SymlinkNotAllowed,
+
+    #[error("Invalid environment: {0}")]
+    InvalidEnvironment(String),
 }
 
 impl From<SecurityError> for IsolateError {
@@ -65,6 +68,32 @@ pub mod command_validation {
         "/usr/bin/cargo",
         "/usr/bin/make",
         "/usr/bin/cmake",
+        // qemu-user, for foreign-architecture programs (see `emulation`)
+        "/usr/bin/qemu-aarch64-static",
+        "/usr/bin/qemu-aarch64",
+        "/usr/bin/qemu-arm-static",
+        "/usr/bin/qemu-arm",
+        "/usr/bin/qemu-riscv64-static",
+        "/usr/bin/qemu-riscv64",
+        "/usr/bin/qemu-riscv32-static",
+        "/usr/bin/qemu-riscv32",
+        "/usr/bin/qemu-x86_64-static",
+        "/usr/bin/qemu-x86_64",
+        "/usr/bin/qemu-i386-static",
+        "/usr/bin/qemu-i386",
+    ];
+
+    /// Toolchains installed under their version (`g++` links to
+    /// `x86_64-linux-gnu-g++-12` on one host and `-13` on the next): any
+    /// version after one of these prefixes is allowed
+    static VERSIONED_EXECUTABLES: &[&str] = &[
+        "/usr/bin/python3.",
+        "/usr/bin/gcc-",
+        "/usr/bin/x86_64-linux-gnu-gcc-",
+        "/usr/bin/g++-",
+        "/usr/bin/x86_64-linux-gnu-g++-",
+        "/usr/bin/clang-",
+        "/usr/bin/clang++-",
     ];
 
     /// Additional safe system commands that are commonly needed
@@ -86,12 +115,11 @@ pub mod command_validation {
     pub fn validate_and_resolve_command(command: &str) -> Result<PathBuf> {
         // 1. Handle special case for compiled solution executables
         if command == "./solution" {
-            // Allow execution of compiled binary in sandbox directory
-            let current_dir = std::env::current_dir()
-                .map_err(|_| SecurityError::InvalidCommand("Cannot get current directory".to_string()))?;
-            return Ok(current_dir.join("solution"));
+            // Allow execution of compiled binary in sandbox directory; left
+            // relative so it resolves in the child's working directory, not ours
+            return Ok(PathBuf::from(command));
         }
-        
+
         // 2. Handle relative paths by checking PATH
         let resolved_path = if command.starts_with('/') {
             // Absolute path - validate directly
@@ -122,6 +150,15 @@ pub mod command_validation {
             }
         }
 
+        // Versioned toolchain binaries
+        if !allowed {
+            allowed = VERSIONED_EXECUTABLES.iter().any(|prefix| {
+                path_str.strip_prefix(prefix).is_some_and(|version| {
+                    !version.is_empty() && version.chars().all(|c| c.is_ascii_digit() || c == '.')
+                })
+            });
+        }
+
         // Check safe system commands if not already allowed
         if !allowed {
             for safe_cmd in SAFE_SYSTEM_COMMANDS {
@@ -176,7 +213,6 @@ pub mod command_validation {
             "/bin/",
             "/usr/lib/go-1.22/bin/",
             "/usr/lib/jvm/",
-            "/tmp/rustbox/", // Allow execution of compiled binaries in sandbox directories
         ];
 
         let mut under_secure_prefix = false;
@@ -187,6 +223,11 @@ pub mod command_validation {
             }
         }
 
+        // Allow execution of compiled binaries in sandbox directories
+        if path.starts_with(crate::paths::paths().scratch_dir()) {
+            under_secure_prefix = true;
+        }
+
         if !under_secure_prefix {
             return Err(SecurityError::CommandNotAllowed(format!(
                 "Path not under secure prefix: {}",
@@ -199,6 +240,57 @@ pub mod command_validation {
     }
 }
 
+/// Environment validation module bounding what is passed into the sandbox
+pub mod env_validation {
+    use super::*;
+
+    /// Most variables a sandboxed process may be started with
+    pub const MAX_ENV_VARS: usize = 64;
+
+    /// Most bytes of `KEY=value\0` strings a sandboxed process may be started with
+    pub const MAX_ENV_BYTES: usize = 32 * 1024;
+
+    /// Check the count, total size and well-formedness of `vars`
+    pub fn validate_environment(vars: &[(String, String)]) -> Result<()> {
+        if vars.len() > MAX_ENV_VARS {
+            return Err(SecurityError::InvalidEnvironment(format!(
+                "{} variables exceeds the limit of {}",
+                vars.len(),
+                MAX_ENV_VARS
+            ))
+            .into());
+        }
+
+        let mut total = 0;
+        for (key, value) in vars {
+            if key.is_empty() || key.contains('=') || key.contains('\0') {
+                return Err(SecurityError::InvalidEnvironment(format!(
+                    "Bad variable name {:?}",
+                    key
+                ))
+                .into());
+            }
+            if value.contains('\0') {
+                return Err(SecurityError::InvalidEnvironment(format!(
+                    "Value of {} contains a NUL byte",
+                    key
+                ))
+                .into());
+            }
+            total += key.len() + value.len() + 2;
+        }
+        if total > MAX_ENV_BYTES {
+            return Err(SecurityError::InvalidEnvironment(format!(
+                "{} bytes exceeds the limit of {}",
+                total, MAX_ENV_BYTES
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
 /// Path validation module for directory bindings and filesystem access
 pub mod path_validation {
     use super::*;
@@ -301,6 +393,47 @@ pub mod path_validation {
         Ok((validated_source, validated_target))
     }
 
+    /// Validate a host Unix socket binding
+    pub fn validate_socket_binding(source: &Path, target: &Path) -> Result<(PathBuf, PathBuf)> {
+        #[cfg(unix)]
+        use std::os::unix::fs::FileTypeExt;
+
+        // Sockets commonly live under /run or /var/run, so the directory blocklist
+        // does not apply here; instead require that the source really is a socket
+        let canonical = source.canonicalize().map_err(|e| {
+            SecurityError::InvalidSourcePath(format!("Cannot access {}: {}", source.display(), e))
+        })?;
+
+        let metadata = std::fs::metadata(&canonical).map_err(|e| {
+            SecurityError::InvalidSourcePath(format!("Cannot stat {}: {}", canonical.display(), e))
+        })?;
+
+        #[cfg(unix)]
+        if !metadata.file_type().is_socket() {
+            return Err(SecurityError::InvalidSourcePath(format!(
+                "Path is not a Unix socket: {}",
+                canonical.display()
+            ))
+            .into());
+        }
+        #[cfg(not(unix))]
+        let _ = metadata;
+
+        let target_str = target.to_string_lossy();
+        if !target.is_absolute() {
+            return Err(SecurityError::InvalidSourcePath(format!(
+                "Socket target must be absolute: {}",
+                target_str
+            ))
+            .into());
+        }
+        if target_str.contains("..") || target_str.contains('~') {
+            return Err(SecurityError::PathTraversal.into());
+        }
+
+        Ok((canonical, target.to_path_buf()))
+    }
+
     /// Security checks across source and target binding
     fn check_binding_security(source: &Path, target: &Path) -> Result<()> {
         let source_str = source.to_string_lossy();
@@ -321,3 +454,81 @@ pub mod path_validation {
         Ok(())
     }
 }
+
+/// Capability and privilege dropping for the sandboxed process
+///
+/// These run in the forked child between fork and exec, so they only make raw
+/// syscalls.
+pub mod privileges {
+    /// `_LINUX_CAPABILITY_VERSION_3`: two 32-bit capability words
+    const CAPABILITY_VERSION_3: u32 = 0x2008_0522;
+
+    #[repr(C)]
+    struct CapUserHeader {
+        version: u32,
+        pid: libc::c_int,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct CapUserData {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+
+    /// Capability numbers fit in the two 32-bit words of version 3
+    const MAX_CAPABILITIES: u32 = 64;
+
+    /// Empty the bounding and ambient sets, so no later exec can gain a
+    /// capability, not even a root program or one with file capabilities
+    ///
+    /// Needs CAP_SETPCAP: call it before switching to the box user.
+    pub fn clear_bounding_set() -> std::io::Result<()> {
+        for capability in 0..MAX_CAPABILITIES {
+            if unsafe { libc::prctl(libc::PR_CAPBSET_DROP, capability, 0, 0, 0) } != 0 {
+                let error = std::io::Error::last_os_error();
+                // EINVAL past the last capability the running kernel knows
+                if error.raw_os_error() == Some(libc::EINVAL) && capability > 0 {
+                    break;
+                }
+                return Err(error);
+            }
+        }
+        let cleared = unsafe {
+            libc::prctl(
+                libc::PR_CAP_AMBIENT,
+                libc::PR_CAP_AMBIENT_CLEAR_ALL,
+                0,
+                0,
+                0,
+            )
+        };
+        // Kernels before 4.3 have no ambient set to clear
+        if cleared != 0 && std::io::Error::last_os_error().raw_os_error() != Some(libc::EINVAL) {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Drop every effective, permitted and inheritable capability of this process
+    pub fn drop_all() -> std::io::Result<()> {
+        let mut header = CapUserHeader {
+            version: CAPABILITY_VERSION_3,
+            pid: 0,
+        };
+        let data = [CapUserData::default(); 2];
+        if unsafe { libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Keep exec from granting privileges (setuid/setgid bits, file capabilities)
+    pub fn set_no_new_privs() -> std::io::Result<()> {
+        if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
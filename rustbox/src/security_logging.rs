@@ -1,14 +1,26 @@
 /// Security event logging framework for rustbox
 /// Provides structured logging of security-relevant events for compliance and incident response
+///
+/// Every event is appended to the audit log as one JSON object per line (see
+/// `SecurityLogEntry`), ready for SIEM ingestion. The file is rotated by size,
+/// keeping `max_files` older files (`security-audit.log.1` the newest), and each
+/// entry can also be sent to syslog or journald. Settings come from
+/// `security.audit_log` in config.json.
 use crate::types::{IsolateError, Result};
+use chrono::{DateTime, Utc};
 use log::{error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::fs::{File, OpenOptions};
 use std::io::Write;
-use std::path::PathBuf;
-use std::sync::{Arc, Mutex, OnceLock};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
 use std::time::SystemTime;
 
+/// syslog's authpriv facility, for security messages
+const SYSLOG_FACILITY: u8 = 10;
+
 /// Security event severity levels
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SecuritySeverity {
@@ -18,6 +30,18 @@ pub enum SecuritySeverity {
     Low,
 }
 
+impl SecuritySeverity {
+    /// syslog severity (also journald's PRIORITY)
+    pub fn syslog_level(&self) -> u8 {
+        match self {
+            SecuritySeverity::Critical => 2,
+            SecuritySeverity::High => 3,
+            SecuritySeverity::Medium => 4,
+            SecuritySeverity::Low => 6,
+        }
+    }
+}
+
 /// Types of security events we track
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SecurityEventType {
@@ -31,6 +55,7 @@ pub enum SecurityEventType {
     LockManagerViolation,
     ConfigurationViolation,
     ProcessEscalation,
+    SyscallDenied,
 }
 
 impl SecurityEventType {
@@ -46,6 +71,7 @@ impl SecurityEventType {
             SecurityEventType::UnauthorizedFileAccess => SecuritySeverity::High,
             SecurityEventType::LockManagerViolation => SecuritySeverity::Medium,
             SecurityEventType::SuspiciousCommand => SecuritySeverity::Medium,
+            SecurityEventType::SyscallDenied => SecuritySeverity::Medium,
             SecurityEventType::ConfigurationViolation => SecuritySeverity::Low,
         }
     }
@@ -63,6 +89,12 @@ pub struct SecurityEvent {
     pub timestamp: SystemTime,
     pub command: Option<String>,
     pub file_path: Option<String>,
+    /// Process the event concerns
+    pub pid: Option<u32>,
+    /// User that process runs as
+    pub uid: Option<u32>,
+    /// Syscall that was refused
+    pub syscall: Option<String>,
 }
 
 impl SecurityEvent {
@@ -79,6 +111,9 @@ impl SecurityEvent {
             timestamp: SystemTime::now(),
             command: None,
             file_path: None,
+            pid: None,
+            uid: None,
+            syscall: None,
         }
     }
 
@@ -102,22 +137,133 @@ impl SecurityEvent {
         self.severity = severity;
         self
     }
+
+    pub fn with_pid(mut self, pid: u32) -> Self {
+        self.pid = Some(pid);
+        self
+    }
+
+    pub fn with_uid(mut self, uid: u32) -> Self {
+        self.uid = Some(uid);
+        self
+    }
+
+    pub fn with_syscall(mut self, syscall: String) -> Self {
+        self.syscall = Some(syscall);
+        self
+    }
+}
+
+/// One line of the audit log
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityLogEntry {
+    pub timestamp: DateTime<Utc>,
+    pub event_type: SecurityEventType,
+    pub severity: SecuritySeverity,
+    pub box_id: Option<u32>,
+    /// Process the event concerns, or rustbox itself
+    pub pid: u32,
+    /// User that process runs as, or rustbox's
+    pub uid: u32,
+    pub syscall: Option<String>,
+    pub details: String,
+    pub command: Option<String>,
+    pub file_path: Option<String>,
+    pub source_ip: Option<String>,
+    pub user_id: Option<String>,
+    /// rustbox process that logged the event
+    pub process_id: u32,
+}
+
+impl SecurityLogEntry {
+    pub fn new(event: SecurityEvent) -> Self {
+        Self {
+            timestamp: event.timestamp.into(),
+            event_type: event.event_type,
+            severity: event.severity,
+            box_id: event.box_id,
+            pid: event.pid.unwrap_or_else(std::process::id),
+            uid: event.uid.unwrap_or_else(|| unsafe { libc::getuid() }),
+            syscall: event.syscall,
+            details: event.details,
+            command: event.command,
+            file_path: event.file_path,
+            source_ip: event.source_ip,
+            user_id: event.user_id,
+            process_id: std::process::id(),
+        }
+    }
+}
+
+/// System log that also receives every entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SecuritySink {
+    /// RFC 3164 messages on /dev/log, facility authpriv
+    Syslog,
+    /// journald's native protocol, with the entry's fields as RUSTBOX_* fields
+    Journald,
+}
+
+impl SecuritySink {
+    /// Socket the sink listens on
+    pub fn default_socket(self) -> &'static Path {
+        match self {
+            SecuritySink::Syslog => Path::new("/dev/log"),
+            SecuritySink::Journald => Path::new("/run/systemd/journal/socket"),
+        }
+    }
+}
+
+/// Audit log settings (`security.audit_log` in config.json)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SecurityLogOptions {
+    /// Audit file; security-audit.log in the state dir by default
+    pub path: Option<PathBuf>,
+    /// Size in bytes past which the file is rotated; 0 never rotates
+    pub max_bytes: u64,
+    /// Rotated files kept
+    pub max_files: u32,
+    pub sink: Option<SecuritySink>,
+    /// Socket of the sink, if not its default
+    pub sink_socket: Option<PathBuf>,
+}
+
+impl Default for SecurityLogOptions {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+            sink: None,
+            sink_socket: None,
+        }
+    }
 }
 
 /// Security logger that handles both structured logging and audit trail
 pub struct SecurityLogger {
-    audit_file: Arc<Mutex<File>>,
+    audit_file: Mutex<File>,
     audit_path: PathBuf,
+    options: SecurityLogOptions,
 }
 
 impl SecurityLogger {
-    /// Create a new security logger
+    /// Create a new security logger with default rotation and no sink
     pub fn new(audit_path: Option<PathBuf>) -> Result<Self> {
-        let audit_path = audit_path.unwrap_or_else(|| {
-            std::env::temp_dir()
-                .join("rustbox")
-                .join("security-audit.log")
-        });
+        Self::with_options(SecurityLogOptions {
+            path: audit_path,
+            ..Default::default()
+        })
+    }
+
+    /// Create a security logger with `options`
+    pub fn with_options(options: SecurityLogOptions) -> Result<Self> {
+        let audit_path = options
+            .path
+            .clone()
+            .unwrap_or_else(|| crate::paths::paths().security_audit_log());
 
         // Ensure parent directory exists
         if let Some(parent) = audit_path.parent() {
@@ -126,39 +272,19 @@ impl SecurityLogger {
             })?;
         }
 
-        let audit_file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&audit_path)
-            .map_err(|e| {
-                IsolateError::Config(format!("Failed to open security audit log: {}", e))
-            })?;
+        let audit_file = open_audit_file(&audit_path).map_err(|e| {
+            IsolateError::Config(format!("Failed to open security audit log: {}", e))
+        })?;
 
         Ok(Self {
-            audit_file: Arc::new(Mutex::new(audit_file)),
+            audit_file: Mutex::new(audit_file),
             audit_path,
+            options,
         })
     }
 
     /// Log a security event
     pub fn log_security_event(&self, event: SecurityEvent) {
-        // Create structured log entry
-        let log_entry = serde_json::json!({
-            "timestamp": event.timestamp
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap_or_default()
-                .as_secs(),
-            "event_type": event.event_type,
-            "severity": event.severity,
-            "box_id": event.box_id,
-            "details": event.details,
-            "source_ip": event.source_ip,
-            "user_id": event.user_id,
-            "command": event.command,
-            "file_path": event.file_path,
-            "process_id": std::process::id(),
-        });
-
         // Log to standard logger based on severity
         match event.severity {
             SecuritySeverity::Critical => {
@@ -191,9 +317,21 @@ impl SecurityLogger {
             }
         }
 
+        let entry = SecurityLogEntry::new(event);
+        let line = match serde_json::to_string(&entry) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize security event: {}", e);
+                return;
+            }
+        };
+
         // Write to audit file for compliance
         if let Ok(mut file) = self.audit_file.lock() {
-            if let Err(e) = writeln!(file, "{}", log_entry) {
+            if let Err(e) = self.prepare_file(&mut file, line.len() as u64 + 1) {
+                error!("Failed to rotate security audit log: {}", e);
+            }
+            if let Err(e) = writeln!(file, "{}", line) {
                 error!("Failed to write to security audit log: {}", e);
             }
             if let Err(e) = file.flush() {
@@ -202,12 +340,97 @@ impl SecurityLogger {
         } else {
             error!("Failed to acquire lock on security audit file");
         }
+
+        if let Some(sink) = self.options.sink {
+            if let Err(e) = self.send_to_sink(sink, &entry, &line) {
+                log::debug!("Failed to send security event to {:?}: {}", sink, e);
+            }
+        }
     }
 
     /// Get the audit log file path
     pub fn audit_path(&self) -> &PathBuf {
         &self.audit_path
     }
+
+    /// Reopen the file if another process rotated it, and rotate it if
+    /// `incoming` more bytes would take it past `max_bytes`
+    fn prepare_file(&self, file: &mut File, incoming: u64) -> std::io::Result<()> {
+        let current = file.metadata()?;
+        let on_disk = std::fs::metadata(&self.audit_path).ok();
+        if on_disk.as_ref().map(|m| (m.dev(), m.ino())) != Some((current.dev(), current.ino())) {
+            *file = open_audit_file(&self.audit_path)?;
+        }
+
+        let max_bytes = self.options.max_bytes;
+        let size = file.metadata()?.len();
+        if max_bytes == 0 || size == 0 || size + incoming <= max_bytes {
+            return Ok(());
+        }
+        let rotated = |n: u32| PathBuf::from(format!("{}.{}", self.audit_path.display(), n));
+        if self.options.max_files == 0 {
+            std::fs::remove_file(&self.audit_path)?;
+        } else {
+            for n in (1..self.options.max_files).rev() {
+                if rotated(n).exists() {
+                    std::fs::rename(rotated(n), rotated(n + 1))?;
+                }
+            }
+            std::fs::rename(&self.audit_path, rotated(1))?;
+        }
+        *file = open_audit_file(&self.audit_path)?;
+        Ok(())
+    }
+
+    fn send_to_sink(
+        &self,
+        sink: SecuritySink,
+        entry: &SecurityLogEntry,
+        line: &str,
+    ) -> std::io::Result<()> {
+        let level = entry.severity.syslog_level();
+        let message = match sink {
+            SecuritySink::Syslog => format!(
+                "<{}>rustbox[{}]: {}",
+                SYSLOG_FACILITY * 8 + level,
+                entry.process_id,
+                line
+            ),
+            SecuritySink::Journald => {
+                // Values never hold a newline: the JSON line escapes them
+                let mut fields = vec![
+                    format!("MESSAGE={}", line),
+                    format!("PRIORITY={}", level),
+                    format!("SYSLOG_FACILITY={}", SYSLOG_FACILITY),
+                    "SYSLOG_IDENTIFIER=rustbox".to_string(),
+                    format!("RUSTBOX_EVENT_TYPE={:?}", entry.event_type),
+                    format!("RUSTBOX_PID={}", entry.pid),
+                    format!("RUSTBOX_UID={}", entry.uid),
+                ];
+                if let Some(box_id) = entry.box_id {
+                    fields.push(format!("RUSTBOX_BOX_ID={}", box_id));
+                }
+                if let Some(syscall) = &entry.syscall {
+                    fields.push(format!("RUSTBOX_SYSCALL={}", syscall));
+                }
+                fields.join("\n") + "\n"
+            }
+        };
+        let socket = self
+            .options
+            .sink_socket
+            .as_deref()
+            .unwrap_or_else(|| sink.default_socket());
+        // A stalled log daemon must not stall executions; drop the message instead
+        let datagram = UnixDatagram::unbound()?;
+        datagram.set_nonblocking(true)?;
+        datagram.send_to(message.as_bytes(), socket)?;
+        Ok(())
+    }
+}
+
+fn open_audit_file(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
 }
 
 /// Global security logger instance
@@ -215,7 +438,15 @@ static SECURITY_LOGGER: OnceLock<SecurityLogger> = OnceLock::new();
 
 /// Initialize the global security logger
 pub fn init_security_logger(audit_path: Option<PathBuf>) -> Result<()> {
-    match SecurityLogger::new(audit_path) {
+    init_security_logger_with(SecurityLogOptions {
+        path: audit_path,
+        ..Default::default()
+    })
+}
+
+/// Initialize the global security logger with `options`
+pub fn init_security_logger_with(options: SecurityLogOptions) -> Result<()> {
+    match SecurityLogger::with_options(options) {
         Ok(logger) => {
             if SECURITY_LOGGER.set(logger).is_err() {
                 error!("Security logger already initialized");
@@ -338,4 +569,44 @@ pub mod events {
 
         log_security_event(event);
     }
-}
\ No newline at end of file
+
+    /// Log taking over the lock of a box from `owner_pid`, which died or hung
+    pub fn stale_lock_taken_over(box_id: u32, owner_pid: u32, reason: String) {
+        let event = SecurityEvent::new(
+            SecurityEventType::LockManagerViolation,
+            format!("Took over stale lock: {}", reason),
+        )
+        .with_box_id(box_id)
+        .with_pid(owner_pid);
+
+        log_security_event(event);
+    }
+
+    /// Log syscalls refused to the sandboxed process `pid` running as `uid`
+    pub fn syscall_denied(
+        syscall: String,
+        count: u64,
+        pid: u32,
+        uid: Option<u32>,
+        box_id: Option<u32>,
+    ) {
+        let event = SecurityEvent::new(
+            SecurityEventType::SyscallDenied,
+            format!("Refused {} {} call(s)", count, syscall),
+        )
+        .with_syscall(syscall)
+        .with_pid(pid);
+
+        let event = match uid {
+            Some(uid) => event.with_uid(uid),
+            None => event,
+        };
+        let event = if let Some(id) = box_id {
+            event.with_box_id(id)
+        } else {
+            event
+        };
+
+        log_security_event(event);
+    }
+}
@@ -0,0 +1,484 @@
+/// HTTP API server mode (`rustbox serve`)
+///
+/// A long-running daemon exposing init/run/execute-code/cleanup over a small
+/// JSON REST API, so backends no longer pay process startup and security checks
+/// per submission. Connections are handled by a fixed pool of workers, one
+/// request per connection. Responses mirror the CLI JSON output.
+///
+/// ```text
+/// GET    /health                     liveness, version and admission pause
+/// GET    /metrics                    Prometheus metrics (see `metrics`)
+/// POST   /boxes/{id}                 init
+/// POST   /boxes/{id}/run             {"command": [...], "stdin": ..., "limits": {...}}
+/// POST   /boxes/{id}/execute-code    {"language": ..., "code": ..., "stdin": ..., "limits": {...},
+///                                     "compile_limits": {...}, "auto_cleanup": false,
+///                                     "cache": false}
+/// DELETE /boxes/{id}                 cleanup
+/// POST   /queue                      {"language": ..., "code": ..., "priority": 0,
+///                                     "group": ..., "max_concurrent": ...}
+/// GET    /queue                      queued and running jobs
+/// GET    /queue/{id}                 job state and, once finished, its result
+/// ```
+///
+/// There is no TLS; keep the listener on localhost or behind a proxy, and set a
+/// bearer token when other users can reach it. With a self-test configured, the
+/// isolation canary suite (see `canary`) runs in the background. The `/queue`
+/// endpoints need queue workers (see `queue`). With `"cache": true`, an
+/// execute-code request identical to an earlier one returns its stored result
+/// (see `result_cache`).
+use crate::isolate::{CleanupPolicy, Isolate};
+use crate::metrics::Metrics;
+use crate::queue::{ExecutionQueue, QueueOptions};
+use crate::result_cache::{CacheKey, ResultCache};
+use crate::types::{ExecutionOverrides, IsolateConfig, IsolateError};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// Largest request body accepted (source code plus stdin)
+const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+/// How long a client may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+/// Content type of the Prometheus text exposition format
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+/// Results kept in memory in front of the on-disk result cache
+const RESULT_CACHE_CAPACITY: usize = 1024;
+
+/// Execution queue of this daemon, when started with queue workers
+static QUEUE: OnceLock<ExecutionQueue> = OnceLock::new();
+/// Results of earlier execute-code requests, filled on first use
+static RESULTS: OnceLock<ResultCache> = OnceLock::new();
+
+/// Server settings
+#[derive(Debug, Clone)]
+pub struct ServeOptions {
+    /// Requests handled concurrently
+    pub workers: usize,
+    /// Required `Authorization: Bearer` token, if any
+    pub token: Option<String>,
+    /// Periodic isolation self-test, if enabled
+    pub self_test: Option<crate::canary::SelfTestConfig>,
+    /// Execution queue workers, if the queue is enabled
+    pub queue: Option<QueueOptions>,
+}
+
+/// Body of `POST /boxes/{id}/run`
+#[derive(Debug, Deserialize)]
+pub struct RunRequest {
+    pub command: Vec<String>,
+    #[serde(default)]
+    pub stdin: Option<String>,
+    #[serde(default)]
+    pub limits: ExecutionOverrides,
+}
+
+/// Body of `POST /boxes/{id}/execute-code`
+#[derive(Debug, Deserialize)]
+pub struct ExecuteCodeRequest {
+    pub language: String,
+    pub code: String,
+    #[serde(default)]
+    pub stdin: Option<String>,
+    #[serde(default)]
+    pub limits: ExecutionOverrides,
+    /// Limits for the compile step; unset fields come from config.json
+    #[serde(default)]
+    pub compile_limits: ExecutionOverrides,
+    /// Clean up the sandbox after execution
+    #[serde(default)]
+    pub auto_cleanup: bool,
+    /// Return the stored result of an identical earlier request instead of running
+    #[serde(default)]
+    pub cache: bool,
+}
+
+/// A parsed HTTP request
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+/// JSON response with its status code
+#[derive(Debug, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Value,
+    /// Plain-text body sent instead of `body`, such as the Prometheus metrics
+    pub text: Option<String>,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Self {
+            status: 200,
+            body,
+            text: None,
+        }
+    }
+
+    fn text(text: String) -> Self {
+        Self {
+            status: 200,
+            body: Value::Null,
+            text: Some(text),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.into() }),
+            text: None,
+        }
+    }
+}
+
+impl From<IsolateError> for Response {
+    fn from(error: IsolateError) -> Self {
+        let status = match error {
+            IsolateError::Config(_) => 400,
+            IsolateError::LockBusy => 409,
+            IsolateError::ResourceUnavailable(_) => 503,
+            _ => 500,
+        };
+        Response::error(status, error.to_string())
+    }
+}
+
+/// Accept connections on `listener` until the process is stopped
+pub fn serve(listener: TcpListener, options: ServeOptions) -> crate::types::Result<()> {
+    if let Some(self_test) = options.self_test.clone() {
+        crate::canary::spawn(self_test)?;
+    }
+    if let Some(queue) = options.queue.clone() {
+        let _ = QUEUE.set(ExecutionQueue::start(queue)?);
+    }
+    let (job_tx, job_rx) = crossbeam_channel::bounded::<TcpStream>(options.workers.max(1) * 4);
+    for worker in 0..options.workers.max(1) {
+        let job_rx = job_rx.clone();
+        let token = options.token.clone();
+        std::thread::Builder::new()
+            .name(format!("rustbox-serve-{}", worker))
+            .spawn(move || {
+                for stream in job_rx {
+                    handle_connection(stream, token.as_deref());
+                }
+            })?;
+    }
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if job_tx.send(stream).is_err() {
+                    break;
+                }
+            }
+            Err(e) => log::warn!("Failed to accept connection: {}", e),
+        }
+    }
+    Ok(())
+}
+
+fn handle_connection(mut stream: TcpStream, token: Option<&str>) {
+    let _ = stream.set_read_timeout(Some(READ_TIMEOUT));
+    let response = match read_request(&stream) {
+        Ok(request) => {
+            if token.is_some_and(|token| !authorized(request.authorization.as_deref(), token)) {
+                Response::error(401, "Missing or invalid bearer token")
+            } else {
+                route(&request.method, &request.path, &request.body)
+            }
+        }
+        Err(response) => response,
+    };
+    if let Err(e) = write_response(&mut stream, &response) {
+        log::debug!("Failed to send response: {}", e);
+    }
+}
+
+/// Whether an `Authorization` header carries `token` as its bearer token
+fn authorized(authorization: Option<&str>, token: &str) -> bool {
+    let Some(header) = authorization else {
+        return false;
+    };
+    // The scheme is case-insensitive; the token itself is not
+    match header.get(..7) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("bearer ") => {
+            constant_time_eq(&header.as_bytes()[7..], token.as_bytes())
+        }
+        _ => false,
+    }
+}
+
+/// Compare secrets without returning at the first differing byte, so the time
+/// taken does not tell how much of a guess was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+fn read_request(stream: &TcpStream) -> std::result::Result<Request, Response> {
+    let bad_request = |message: &str| Response::error(400, message);
+    let mut reader = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .map_err(|_| bad_request("Failed to read request"))?;
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(bad_request("Malformed request line"));
+    };
+    let path = target.split('?').next().unwrap_or(target).to_string();
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    loop {
+        let mut line = String::new();
+        reader
+            .read_line(&mut line)
+            .map_err(|_| bad_request("Failed to read headers"))?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let Some((name, value)) = line.split_once(':') else {
+            return Err(bad_request("Malformed header"));
+        };
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .parse()
+                .map_err(|_| bad_request("Invalid Content-Length"))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            authorization = Some(value.to_string());
+        }
+    }
+
+    if content_length > MAX_BODY_BYTES {
+        return Err(Response::error(413, "Request body too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| bad_request("Truncated request body"))?;
+
+    Ok(Request {
+        method: method.to_string(),
+        path,
+        authorization,
+        body,
+    })
+}
+
+fn write_response(stream: &mut TcpStream, response: &Response) -> std::io::Result<()> {
+    let (content_type, body) = match &response.text {
+        Some(text) => (PROMETHEUS_CONTENT_TYPE, text.clone()),
+        None => (
+            "application/json",
+            serde_json::to_string(&response.body).unwrap_or_default(),
+        ),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        reason_phrase(response.status),
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Dispatch a request to its handler
+pub fn route(method: &str, path: &str, body: &[u8]) -> Response {
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match (method, segments.as_slice()) {
+        ("GET", ["health"]) => Response::ok(json!({
+            "status": "ok",
+            "version": env!("CARGO_PKG_VERSION"),
+            "admission_paused": crate::admission::paused(),
+        })),
+        ("GET", ["metrics"]) => Response::text(Metrics::collect().to_prometheus()),
+        ("POST", ["queue"]) => match serde_json::from_slice(body) {
+            Ok(submission) => with_queue(|queue| match queue.submit(submission) {
+                Ok(id) => Response::ok(json!({ "id": id, "state": "queued" })),
+                Err(e) => e.into(),
+            }),
+            Err(e) => Response::error(400, format!("Invalid queue submission: {}", e)),
+        },
+        ("GET", ["queue"]) => with_queue(|queue| Response::ok(json!(queue.status()))),
+        ("GET", ["queue", id]) => {
+            let Ok(id) = id.parse::<u64>() else {
+                return Response::error(400, format!("Invalid job id: {}", id));
+            };
+            with_queue(|queue| match queue.job(id) {
+                Some(job) => Response::ok(json!(job)),
+                None => Response::error(404, format!("No such job: {}", id)),
+            })
+        }
+        (method, ["boxes", box_id, rest @ ..]) => {
+            let Ok(box_id) = box_id.parse::<u32>() else {
+                return Response::error(400, format!("Invalid box id: {}", box_id));
+            };
+            match (method, rest) {
+                ("POST", []) => init_box(box_id),
+                ("DELETE", []) => cleanup_box(box_id),
+                ("POST", ["run"]) => match serde_json::from_slice(body) {
+                    Ok(request) => run_command(box_id, request),
+                    Err(e) => Response::error(400, format!("Invalid run request: {}", e)),
+                },
+                ("POST", ["execute-code"]) => match serde_json::from_slice(body) {
+                    Ok(request) => execute_code(box_id, request),
+                    Err(e) => Response::error(400, format!("Invalid execute-code request: {}", e)),
+                },
+                (_, [] | ["run"] | ["execute-code"]) => {
+                    Response::error(405, format!("{} not allowed on {}", method, path))
+                }
+                _ => Response::error(404, format!("No such endpoint: {}", path)),
+            }
+        }
+        _ => Response::error(404, format!("No such endpoint: {}", path)),
+    }
+}
+
+/// Run `handler` on the queue, or answer 503 when the daemon has none
+fn with_queue(handler: impl FnOnce(&ExecutionQueue) -> Response) -> Response {
+    match QUEUE.get() {
+        Some(queue) => handler(queue),
+        None => Response::error(503, "Execution queue not enabled (serve --queue-workers)"),
+    }
+}
+
+fn instance_id(box_id: u32) -> String {
+    format!("rustbox/{}", box_id)
+}
+
+fn init_box(box_id: u32) -> Response {
+    let config = IsolateConfig {
+        instance_id: instance_id(box_id),
+        strict_mode: false,
+        ..Default::default()
+    };
+    match Isolate::new(config) {
+        Ok(_) => Response::ok(json!({ "box_id": box_id, "status": "initialized" })),
+        Err(e) => e.into(),
+    }
+}
+
+fn cleanup_box(box_id: u32) -> Response {
+    match Isolate::load(&instance_id(box_id)) {
+        Ok(Some(isolate)) => match isolate.cleanup() {
+            Ok(()) => Response::ok(json!({ "box_id": box_id, "status": "cleaned_up" })),
+            Err(e) => e.into(),
+        },
+        Ok(None) => Response::error(404, format!("Sandbox {} not found", box_id)),
+        Err(e) => e.into(),
+    }
+}
+
+fn run_command(box_id: u32, request: RunRequest) -> Response {
+    if request.command.is_empty() {
+        return Response::error(400, "Empty command");
+    }
+    let mut isolate = match Isolate::load(&instance_id(box_id)) {
+        Ok(Some(isolate)) => isolate,
+        Ok(None) => {
+            return Response::error(404, format!("Sandbox {} not found. Init it first.", box_id))
+        }
+        Err(e) => return e.into(),
+    };
+    if let Err(e) = isolate.acquire_execution_lock() {
+        return e.into();
+    }
+    match isolate.execute_with_overrides(
+        &request.command,
+        request.stdin.as_deref(),
+        &request.limits,
+    ) {
+        Ok(result) => Response::ok(result.to_cli_json()),
+        Err(e) => e.into(),
+    }
+}
+
+fn execute_code(box_id: u32, request: ExecuteCodeRequest) -> Response {
+    let config = match IsolateConfig::with_language_defaults(&request.language, instance_id(box_id))
+    {
+        Ok(config) => config,
+        Err(e) => return e.into(),
+    };
+    let cache_key = request.cache.then(|| {
+        CacheKey::new(
+            &request.language,
+            &request.code,
+            request.stdin.as_deref(),
+            &config,
+            &request.limits,
+            &request.compile_limits,
+        )
+    });
+    let cache_key = match cache_key.transpose() {
+        Ok(key) => key,
+        Err(e) => return e.into(),
+    };
+    let results = RESULTS.get_or_init(|| ResultCache::layered(RESULT_CACHE_CAPACITY));
+    if let Some(result) = cache_key.as_ref().and_then(|key| results.get(key)) {
+        let mut body = result.to_cli_json();
+        body["language"] = json!(request.language);
+        body["cached"] = json!(true);
+        return Response::ok(body);
+    }
+    let mut isolate = match Isolate::new(config) {
+        Ok(isolate) => isolate,
+        Err(e) => return e.into(),
+    };
+    let result = isolate
+        .compile(&request.language, &request.code, &request.compile_limits)
+        .and_then(|program| match program.failure() {
+            Some(compile_error) => Ok(compile_error),
+            None => isolate.run(&program, request.stdin.as_deref(), &request.limits),
+        });
+    let policy = if request.auto_cleanup {
+        CleanupPolicy::Always
+    } else {
+        CleanupPolicy::Keep
+    };
+    if let Err(e) = isolate.finish(policy) {
+        log::warn!("Failed to clean up sandbox {}: {}", box_id, e);
+    }
+
+    match result {
+        Ok(result) => {
+            if let Some(key) = &cache_key {
+                results.put(key, &result);
+            }
+            let mut body = result.to_cli_json();
+            body["language"] = json!(request.language);
+            if request.cache {
+                body["cached"] = json!(false);
+            }
+            Response::ok(body)
+        }
+        Err(e) => e.into(),
+    }
+}
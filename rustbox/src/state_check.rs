@@ -0,0 +1,329 @@
+/// Startup consistency check and repair of rustbox state
+///
+/// Verifies the lock directory, the instances registry against the box
+/// directories on disk, and cgroups left behind by earlier runs. Problems are
+/// reported on every start; with `repair` they are fixed as well.
+use crate::isolate::Isolate;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Cgroup controllers rustbox creates groups in; those not mounted on the
+/// host are skipped
+const CGROUP_CONTROLLERS: [&str; 7] = [
+    "memory", "cpu", "cpuacct", "pids", "blkio", "freezer", "cpuset",
+];
+
+/// Kind of inconsistency found
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateIssueKind {
+    /// Lock directory missing, not ours, or writable by others
+    LockDirectory,
+    /// An instance record, or the old instances.json, cannot be parsed
+    CorruptRegistry,
+    /// Registry entry whose box directory is gone
+    OrphanedEntry,
+    /// Box directory with no registry entry
+    UnregisteredBox,
+    /// Cgroup left behind by a box that is not running
+    CgroupResidue,
+}
+
+/// A single inconsistency and whether it was fixed
+#[derive(Debug, Clone, Serialize)]
+pub struct StateIssue {
+    pub kind: StateIssueKind,
+    pub path: PathBuf,
+    pub detail: String,
+    pub repaired: bool,
+}
+
+/// Outcome of a consistency check
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StateReport {
+    pub issues: Vec<StateIssue>,
+}
+
+impl StateReport {
+    pub fn is_consistent(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Issues that are still present
+    pub fn unrepaired(&self) -> impl Iterator<Item = &StateIssue> {
+        self.issues.iter().filter(|issue| !issue.repaired)
+    }
+
+    fn push(&mut self, kind: StateIssueKind, path: &Path, detail: String, repaired: bool) {
+        self.issues.push(StateIssue {
+            kind,
+            path: path.to_path_buf(),
+            detail,
+            repaired,
+        });
+    }
+}
+
+/// Check the state directory, fixing what is found when `repair` is set
+pub fn check(repair: bool) -> StateReport {
+    let mut report = StateReport::default();
+    let lock_dir = crate::lock_manager::lock_directory();
+
+    if let Some(ref lock_dir) = lock_dir {
+        check_lock_directory(lock_dir, repair, &mut report);
+    }
+    let registered = check_registry(repair, &mut report);
+    if let Some(ref registered) = registered {
+        check_box_directories(registered, lock_dir.as_deref(), repair, &mut report);
+    }
+    check_cgroups(lock_dir.as_deref(), repair, &mut report);
+
+    report
+}
+
+fn check_lock_directory(lock_dir: &Path, repair: bool, report: &mut StateReport) {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    let Ok(metadata) = fs::metadata(lock_dir) else {
+        let repaired = repair && fs::create_dir_all(lock_dir).is_ok();
+        report.push(
+            StateIssueKind::LockDirectory,
+            lock_dir,
+            "lock directory is missing".to_string(),
+            repaired,
+        );
+        return;
+    };
+
+    let euid = unsafe { libc::geteuid() };
+    let mode = metadata.mode() & 0o7777;
+    let mut problems = Vec::new();
+    if metadata.uid() != euid {
+        problems.push(format!(
+            "owned by uid {} instead of {}",
+            metadata.uid(),
+            euid
+        ));
+    }
+    if mode & 0o022 != 0 {
+        problems.push(format!("mode {:o} is writable by other users", mode));
+    }
+    if problems.is_empty() {
+        return;
+    }
+
+    let repaired = repair
+        && std::os::unix::fs::chown(lock_dir, Some(euid), None).is_ok()
+        && fs::set_permissions(lock_dir, fs::Permissions::from_mode(0o755)).is_ok();
+    report.push(
+        StateIssueKind::LockDirectory,
+        lock_dir,
+        problems.join(", "),
+        repaired,
+    );
+}
+
+/// Returns the registered instance ids, or `None` if the registry is unusable
+fn check_registry(repair: bool, report: &mut StateReport) -> Option<HashSet<String>> {
+    let registry = Isolate::registry_path();
+    let mut registered: HashSet<String> = match Isolate::list_all() {
+        Ok(instances) => instances.into_iter().collect(),
+        Err(e) if !repair => {
+            report.push(
+                StateIssueKind::CorruptRegistry,
+                &registry,
+                e.to_string(),
+                false,
+            );
+            return None;
+        }
+        Err(e) => match Isolate::recover_registry() {
+            Ok(recovery) => {
+                report.push(
+                    StateIssueKind::CorruptRegistry,
+                    &registry,
+                    format!(
+                        "instances registry was unreadable; moved {} entries, dropped {}, original saved as {}",
+                        recovery.kept,
+                        recovery.dropped,
+                        recovery.backup.display()
+                    ),
+                    true,
+                );
+                Isolate::list_all().ok()?.into_iter().collect()
+            }
+            Err(recover_error) => {
+                report.push(
+                    StateIssueKind::CorruptRegistry,
+                    &registry,
+                    format!("{}; recovery failed: {}", e, recover_error),
+                    false,
+                );
+                return None;
+            }
+        },
+    };
+
+    // A record that cannot be read only loses its own box
+    for (instance_id, path, error) in Isolate::unreadable_records().unwrap_or_default() {
+        let repaired = repair && Isolate::quarantine_record(&instance_id).is_ok();
+        let detail = error.to_string();
+        report.push(StateIssueKind::CorruptRegistry, &path, detail, repaired);
+        if repaired {
+            registered.remove(&instance_id);
+        }
+    }
+
+    let orphaned: Vec<String> = registered
+        .iter()
+        .filter(|instance_id| !Isolate::instance_dir(instance_id).exists())
+        .cloned()
+        .collect();
+    if orphaned.is_empty() {
+        return Some(registered);
+    }
+
+    let repaired = repair && Isolate::forget_instances(&orphaned).is_ok();
+    for instance_id in &orphaned {
+        report.push(
+            StateIssueKind::OrphanedEntry,
+            &registry,
+            format!("{} is registered but its directory is missing", instance_id),
+            repaired,
+        );
+        if repaired {
+            registered.remove(instance_id);
+        }
+    }
+    Some(registered)
+}
+
+fn check_box_directories(
+    registered: &HashSet<String>,
+    lock_dir: Option<&Path>,
+    repair: bool,
+    report: &mut StateReport,
+) {
+    let boxes_dir = Isolate::instance_dir("rustbox");
+    let Ok(entries) = fs::read_dir(&boxes_dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let Some(box_id) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if registered.contains(&format!("rustbox/{}", box_id)) || box_in_use(lock_dir, box_id) {
+            continue;
+        }
+
+        let path = entry.path();
+        // Never recurse into something still mounted from the host
+        let mounted = has_mounts_under(&path);
+        let repaired = repair && !mounted && fs::remove_dir_all(&path).is_ok();
+        let detail = if mounted {
+            format!("box {} is not registered and still has mounts", box_id)
+        } else {
+            format!("box {} has a directory but no registry entry", box_id)
+        };
+        report.push(StateIssueKind::UnregisteredBox, &path, detail, repaired);
+    }
+}
+
+fn check_cgroups(lock_dir: Option<&Path>, repair: bool, report: &mut StateReport) {
+    // Co-mounted controllers (cpu,cpuacct) share one hierarchy
+    let mut seen = HashSet::new();
+    for controller in CGROUP_CONTROLLERS {
+        let controller_dir = crate::cgroup::cgroup_root().join(controller);
+        let hierarchy = controller_dir
+            .canonicalize()
+            .unwrap_or(controller_dir.clone());
+        if !seen.insert(hierarchy) {
+            continue;
+        }
+        // Not mounted here (cpuset often is not), so nothing can be left over
+        let Ok(entries) = fs::read_dir(&controller_dir) else {
+            continue;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let in_use = if let Some(box_id) = name.strip_prefix("rustbox_") {
+                box_id
+                    .parse::<u32>()
+                    .map_or(true, |box_id| box_in_use(lock_dir, box_id))
+            } else if let Some(pid) = name.strip_prefix("rustbox-capability-probe-") {
+                pid.parse::<i32>()
+                    .map_or(true, |pid| unsafe { libc::kill(pid, 0) } == 0)
+            } else {
+                continue;
+            };
+            if in_use {
+                continue;
+            }
+
+            let path = entry.path();
+            let tasks = cgroup_tasks(&path);
+            let repaired = repair && remove_cgroup(&path, &tasks);
+            let detail = if tasks.is_empty() {
+                format!("{} cgroup {} is left over", controller, name)
+            } else {
+                format!(
+                    "{} cgroup {} is left over with {} stray processes",
+                    controller,
+                    name,
+                    tasks.len()
+                )
+            };
+            report.push(StateIssueKind::CgroupResidue, &path, detail, repaired);
+        }
+    }
+}
+
+/// A box holds its lock for as long as it is running; a crashed holder
+/// leaves the file behind but not the lock
+fn box_in_use(lock_dir: Option<&Path>, box_id: u32) -> bool {
+    lock_dir.is_some_and(|dir| {
+        crate::lock_manager::lock_file_held(&dir.join(format!("box-{}.lock", box_id)))
+    })
+}
+
+fn has_mounts_under(path: &Path) -> bool {
+    let Ok(mountinfo) = fs::read_to_string("/proc/self/mountinfo") else {
+        return true;
+    };
+    mountinfo
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(4))
+        .any(|mount_point| Path::new(mount_point).starts_with(path))
+}
+
+fn cgroup_tasks(path: &Path) -> Vec<i32> {
+    fs::read_to_string(path.join("tasks"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.trim().parse().ok())
+        .collect()
+}
+
+/// Kill stray processes and remove the cgroup
+fn remove_cgroup(path: &Path, tasks: &[i32]) -> bool {
+    // A frozen group holds SIGKILL until it is thawed
+    let _ = fs::write(path.join("freezer.state"), "THAWED");
+    for &pid in tasks {
+        unsafe { libc::kill(pid, libc::SIGKILL) };
+    }
+    for _ in 0..50 {
+        if fs::remove_dir(path).is_ok() {
+            return true;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    false
+}
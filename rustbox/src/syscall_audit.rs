@@ -0,0 +1,127 @@
+/// Syscall audit (dry-run) mode for authoring seccomp profiles
+///
+/// Instead of enforcing a filter, audit mode loads one that hands every syscall
+/// to the supervisor through a seccomp user notification and lets it continue
+/// unchanged. Running a workload once this way yields the exact set of syscalls
+/// it needs, which is emitted as an OCI/Docker seccomp profile. Every syscall
+/// makes a round trip through the supervisor, so audited runs are much slower
+/// and their timings should not be used for judging.
+///
+/// The same supervisor enforces exec limits: with only `execve`/`execveat`
+/// notified (native, i386 and x32), it counts every attempt, lets through up
+/// to the limit and refuses the rest with EPERM. Attempts on missing files
+/// count too, so a shell trying each `PATH` entry uses up several.
+use crate::types::{IsolateError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+const PROFILE_ARCH: &str = "SCMP_ARCH_X86_64";
+
+/// Syscalls observed during an audited run
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyscallAuditLog {
+    /// Call count per syscall name
+    pub syscalls: BTreeMap<String, u64>,
+    /// Syscall numbers that could not be named (unknown or foreign architecture)
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub unresolved: BTreeSet<i32>,
+}
+
+impl SyscallAuditLog {
+    /// Seccomp profile (OCI/Docker format) allowing exactly the observed syscalls
+    pub fn profile(&self) -> serde_json::Value {
+        serde_json::json!({
+            "defaultAction": "SCMP_ACT_ERRNO",
+            "defaultErrnoRet": libc::EPERM,
+            "architectures": [PROFILE_ARCH],
+            "syscalls": [{
+                "names": self.syscalls.keys().collect::<Vec<_>>(),
+                "action": "SCMP_ACT_ALLOW",
+            }],
+        })
+    }
+
+    /// Write the profile to `path` as pretty-printed JSON
+    pub fn write_profile(&self, path: &Path) -> Result<()> {
+        let profile = serde_json::to_string_pretty(&self.profile()).map_err(|e| {
+            IsolateError::Config(format!("Failed to serialize syscall profile: {}", e))
+        })?;
+        std::fs::write(path, profile + "\n")?;
+        Ok(())
+    }
+}
+
+/// What the supervisor does with the syscalls it is notified of
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SupervisorOptions {
+    /// Notify on every syscall and record it (audit mode)
+    pub record_syscalls: bool,
+    /// Refuse exec calls past this many
+    pub exec_limit: Option<u32>,
+}
+
+/// Exec calls counted under an exec limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExecTally {
+    /// Exec attempts let through, including the one starting the program
+    pub allowed: u64,
+    /// Exec attempts refused with EPERM because the limit was reached
+    pub denied: u64,
+}
+
+/// What the supervisor observed during a run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SupervisorReport {
+    /// Every syscall made, when recording
+    pub syscalls: Option<SyscallAuditLog>,
+    /// Exec calls, when an exec limit was set
+    pub execs: Option<ExecTally>,
+}
+
+#[cfg(feature = "seccomp-native")]
+mod native;
+#[cfg(feature = "seccomp-native")]
+pub use native::{install_in_child, SyscallAudit};
+
+/// Without the `seccomp-native` feature nothing can be supervised, so there
+/// is never a `SyscallAudit`
+#[cfg(not(feature = "seccomp-native"))]
+pub enum SyscallAudit {}
+
+#[cfg(not(feature = "seccomp-native"))]
+impl SyscallAudit {
+    pub fn supported() -> bool {
+        false
+    }
+
+    pub fn start(_options: SupervisorOptions) -> Result<Self> {
+        Err(IsolateError::Process(
+            "rustbox was built without the seccomp-native feature".to_string(),
+        ))
+    }
+
+    pub fn records_syscalls(&self) -> bool {
+        match *self {}
+    }
+
+    pub fn child_fd(&self) -> std::os::unix::io::RawFd {
+        match *self {}
+    }
+
+    pub fn spawned(&mut self) {
+        match *self {}
+    }
+
+    pub fn finish(self) -> SupervisorReport {
+        match self {}
+    }
+}
+
+#[cfg(not(feature = "seccomp-native"))]
+pub fn install_in_child(
+    _handoff_fd: std::os::unix::io::RawFd,
+    _record_syscalls: bool,
+) -> std::io::Result<()> {
+    Err(std::io::Error::from_raw_os_error(libc::ENOSYS))
+}
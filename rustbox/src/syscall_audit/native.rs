@@ -0,0 +1,807 @@
+/// Seccomp user-notification supervisor (feature `seccomp-native`)
+use super::{ExecTally, SupervisorOptions, SupervisorReport, SyscallAuditLog};
+use crate::types::{IsolateError, Result};
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// `_IOWR('!', 0, struct seccomp_notif)`
+const SECCOMP_IOCTL_NOTIF_RECV: libc::c_ulong = 0xc050_2100;
+/// `_IOWR('!', 1, struct seccomp_notif_resp)`
+const SECCOMP_IOCTL_NOTIF_SEND: libc::c_ulong = 0xc018_2101;
+
+#[cfg(target_arch = "x86_64")]
+const AUDIT_ARCH: u32 = 0xc000_003e;
+#[cfg(not(target_arch = "x86_64"))]
+const AUDIT_ARCH: u32 = 0;
+
+/// 32-bit syscalls (`int $0x80`) made by an x86_64 process
+const AUDIT_ARCH_I386: u32 = 0x4000_0003;
+const I386_EXECVE: i32 = 11;
+const I386_EXECVEAT: i32 = 358;
+
+/// x32 syscalls carry the native arch with this bit set in the number
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+const X32_EXECVE: i32 = (X32_SYSCALL_BIT | 520) as i32;
+const X32_EXECVEAT: i32 = (X32_SYSCALL_BIT | 545) as i32;
+
+/// The handoff socket is moved this high (or to the top of RLIMIT_NOFILE) so
+/// the sandboxed program is unlikely to ever reuse its number
+const HANDOFF_FD_MIN: libc::rlim_t = 4096;
+
+impl SyscallAuditLog {
+    fn record(&mut self, arch: u32, nr: i32) {
+        match syscall_name(arch, nr) {
+            Some(name) => *self.syscalls.entry(name.to_string()).or_insert(0) += 1,
+            None => {
+                self.unresolved.insert(nr);
+            }
+        }
+    }
+}
+
+/// Supervisor side of a notified run; stop it with `finish` to collect the report
+pub struct SyscallAudit {
+    /// Socket end inherited by the child to hand over the notification fd
+    child_socket: Option<RawFd>,
+    stop: Arc<AtomicBool>,
+    reader: Option<JoinHandle<SupervisorReport>>,
+    record_syscalls: bool,
+}
+
+impl SyscallAudit {
+    /// Whether the kernel supports continuing syscalls from user notifications
+    pub fn supported() -> bool {
+        let action = libc::SECCOMP_RET_USER_NOTIF;
+        cfg!(target_arch = "x86_64")
+            && unsafe {
+                libc::syscall(
+                    libc::SYS_seccomp,
+                    libc::SECCOMP_GET_ACTION_AVAIL,
+                    0,
+                    &action as *const libc::c_uint,
+                )
+            } == 0
+    }
+
+    /// Prepare the handoff channel and start the supervisor thread
+    ///
+    /// The child must call `install_in_child` with `child_fd` and
+    /// `records_syscalls` right before exec.
+    pub fn start(options: SupervisorOptions) -> Result<Self> {
+        if !Self::supported() {
+            return Err(IsolateError::Process(
+                "Syscall supervision requires x86_64 and Linux 5.5 or newer".to_string(),
+            ));
+        }
+
+        let mut fds = [0; 2];
+        if unsafe {
+            libc::socketpair(
+                libc::AF_UNIX,
+                libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC,
+                0,
+                fds.as_mut_ptr(),
+            )
+        } != 0
+        {
+            return Err(IsolateError::Process(format!(
+                "Failed to create syscall audit socket: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        let [supervisor_socket, child_socket] = fds;
+
+        let mut nofile = libc::rlimit {
+            rlim_cur: HANDOFF_FD_MIN + 1,
+            rlim_max: 0,
+        };
+        unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut nofile) };
+        let min_fd = HANDOFF_FD_MIN.min(nofile.rlim_cur.saturating_sub(1)) as libc::c_int;
+        let high_fd = unsafe { libc::fcntl(child_socket, libc::F_DUPFD_CLOEXEC, min_fd) };
+        unsafe { libc::close(child_socket) };
+        if high_fd < 0 {
+            unsafe { libc::close(supervisor_socket) };
+            return Err(IsolateError::Process(format!(
+                "Failed to prepare syscall audit socket: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let reader = {
+            let stop = Arc::clone(&stop);
+            std::thread::Builder::new()
+                .name("rustbox-syscall-audit".to_string())
+                .spawn(move || supervise(supervisor_socket, &stop, options))
+                .map_err(|e| {
+                    unsafe {
+                        libc::close(supervisor_socket);
+                        libc::close(high_fd);
+                    }
+                    IsolateError::Process(format!("Failed to start syscall audit thread: {}", e))
+                })?
+        };
+
+        Ok(Self {
+            child_socket: Some(high_fd),
+            stop,
+            reader: Some(reader),
+            record_syscalls: options.record_syscalls,
+        })
+    }
+
+    /// Whether the child filter must notify every syscall
+    pub fn records_syscalls(&self) -> bool {
+        self.record_syscalls
+    }
+
+    /// Descriptor to pass to `install_in_child`
+    pub fn child_fd(&self) -> RawFd {
+        self.child_socket.unwrap_or(-1)
+    }
+
+    /// Drop the supervisor's copy of the child socket once the child exists,
+    /// so a child that never installs the filter is noticed
+    pub fn spawned(&mut self) {
+        if let Some(fd) = self.child_socket.take() {
+            unsafe { libc::close(fd) };
+        }
+    }
+
+    /// Stop supervising and return what was observed
+    pub fn finish(mut self) -> SupervisorReport {
+        self.spawned();
+        self.stop.store(true, Ordering::SeqCst);
+        self.reader
+            .take()
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for SyscallAudit {
+    fn drop(&mut self) {
+        self.spawned();
+        self.stop.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Load the notifying filter in the child and send its notification fd to the supervisor
+///
+/// Runs between fork and exec, so it only makes raw syscalls and never allocates.
+/// With `record_syscalls` every syscall is notified except `sendmsg` on the
+/// handoff socket itself, since nobody is listening yet; otherwise only exec calls,
+/// including those made through the i386 and x32 entry points. Syscalls of any
+/// other architecture kill the process.
+pub fn install_in_child(handoff_fd: RawFd, record_syscalls: bool) -> std::io::Result<()> {
+    let stmt = |code: u32, k: u32| libc::sock_filter {
+        code: code as u16,
+        jt: 0,
+        jf: 0,
+        k,
+    };
+    let jump = |k: u32, jt: u8, jf: u8| libc::sock_filter {
+        code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16,
+        jt,
+        jf,
+        k,
+    };
+    let load = libc::BPF_LD | libc::BPF_W | libc::BPF_ABS;
+    let mut record_filter = [
+        // seccomp_data: nr at 0, arch at 4, args[0] at 16
+        stmt(load, 4),
+        jump(AUDIT_ARCH, 0, 4),
+        stmt(load, 0),
+        jump(libc::SYS_sendmsg as u32, 0, 2),
+        stmt(load, 16),
+        jump(handoff_fd as u32, 1, 0),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_USER_NOTIF),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW),
+    ];
+    let jump_ge = |k: u32, jt: u8, jf: u8| libc::sock_filter {
+        code: (libc::BPF_JMP | libc::BPF_JGE | libc::BPF_K) as u16,
+        jt,
+        jf,
+        k,
+    };
+    let mut exec_filter = [
+        stmt(load, 4),
+        jump(AUDIT_ARCH, 0, 6),
+        // Native: execve, execveat and every x32 syscall
+        stmt(load, 0),
+        jump_ge(X32_SYSCALL_BIT, 3, 0),
+        jump(libc::SYS_execve as u32, 2, 0),
+        jump(libc::SYS_execveat as u32, 1, 0),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_USER_NOTIF),
+        // i386: its own execve and execveat
+        jump(AUDIT_ARCH_I386, 0, 5),
+        stmt(load, 0),
+        jump(I386_EXECVE as u32, 2, 0),
+        jump(I386_EXECVEAT as u32, 1, 0),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_USER_NOTIF),
+        stmt(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+    ];
+    let filter: &mut [libc::sock_filter] = if record_syscalls {
+        &mut record_filter
+    } else {
+        &mut exec_filter
+    };
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_mut_ptr(),
+    };
+
+    let load_filter = || unsafe {
+        libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_NEW_LISTENER,
+            &program as *const libc::sock_fprog,
+        )
+    };
+    let mut listener = load_filter();
+    if listener < 0 && std::io::Error::last_os_error().raw_os_error() == Some(libc::EACCES) {
+        // Without CAP_SYS_ADMIN a filter can only be loaded under no_new_privs
+        unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+        listener = load_filter();
+    }
+    if listener < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    send_fd(handoff_fd, listener as RawFd)?;
+    unsafe { libc::close(listener as RawFd) };
+    Ok(())
+}
+
+/// Send `fd` over a unix socket with SCM_RIGHTS, without allocating
+fn send_fd(socket: RawFd, fd: RawFd) -> std::io::Result<()> {
+    let mut byte = 0u8;
+    let mut iov = libc::iovec {
+        iov_base: &mut byte as *mut u8 as *mut libc::c_void,
+        iov_len: 1,
+    };
+    let mut control = [0u64; 4];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = unsafe { libc::CMSG_SPACE(std::mem::size_of::<RawFd>() as u32) } as _;
+
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        (*cmsg).cmsg_level = libc::SOL_SOCKET;
+        (*cmsg).cmsg_type = libc::SCM_RIGHTS;
+        (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<RawFd>() as u32) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut RawFd, fd);
+
+        if libc::sendmsg(socket, &msg, 0) < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Receive a descriptor sent with `send_fd`; `None` once the peer is gone
+fn recv_fd(socket: RawFd) -> Option<RawFd> {
+    let mut byte = 0u8;
+    let mut iov = libc::iovec {
+        iov_base: &mut byte as *mut u8 as *mut libc::c_void,
+        iov_len: 1,
+    };
+    let mut control = [0u64; 4];
+    let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+    unsafe {
+        if libc::recvmsg(socket, &mut msg, libc::MSG_CMSG_CLOEXEC) <= 0 {
+            return None;
+        }
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        if cmsg.is_null()
+            || (*cmsg).cmsg_level != libc::SOL_SOCKET
+            || (*cmsg).cmsg_type != libc::SCM_RIGHTS
+        {
+            return None;
+        }
+        Some(std::ptr::read_unaligned(
+            libc::CMSG_DATA(cmsg) as *const RawFd
+        ))
+    }
+}
+
+/// Wait until `fd` is readable; false when the stop flag is raised or it hangs up
+fn wait_readable(fd: RawFd, stop: &AtomicBool) -> bool {
+    loop {
+        if stop.load(Ordering::SeqCst) {
+            return false;
+        }
+        let mut pollfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { libc::poll(&mut pollfd, 1, 50) };
+        if ready > 0 {
+            return pollfd.revents & libc::POLLIN != 0;
+        }
+    }
+}
+
+/// Supervisor thread: receive the listener, then answer notifications until done
+fn supervise(socket: RawFd, stop: &AtomicBool, options: SupervisorOptions) -> SupervisorReport {
+    let listener = if wait_readable(socket, stop) {
+        recv_fd(socket)
+    } else {
+        None
+    };
+    unsafe { libc::close(socket) };
+
+    let mut report = SupervisorReport {
+        syscalls: options.record_syscalls.then(SyscallAuditLog::default),
+        execs: options.exec_limit.map(|_| ExecTally::default()),
+    };
+    let Some(listener) = listener else {
+        return report;
+    };
+
+    while wait_readable(listener, stop) {
+        let mut request: libc::seccomp_notif = unsafe { std::mem::zeroed() };
+        if unsafe { libc::ioctl(listener, SECCOMP_IOCTL_NOTIF_RECV as _, &mut request) } != 0 {
+            // The caller died before the notification could be read
+            continue;
+        }
+        if let Some(ref mut log) = report.syscalls {
+            log.record(request.data.arch, request.data.nr);
+        }
+
+        let mut response = libc::seccomp_notif_resp {
+            id: request.id,
+            val: 0,
+            error: 0,
+            flags: libc::SECCOMP_USER_NOTIF_FLAG_CONTINUE as u32,
+        };
+        if let (Some(limit), Some(tally)) = (options.exec_limit, report.execs.as_mut()) {
+            if is_exec(&request) {
+                if tally.allowed < u64::from(limit) {
+                    tally.allowed += 1;
+                } else {
+                    tally.denied += 1;
+                    response.error = -libc::EPERM;
+                    response.flags = 0;
+                }
+            }
+        }
+        unsafe { libc::ioctl(listener, SECCOMP_IOCTL_NOTIF_SEND as _, &mut response) };
+    }
+
+    unsafe { libc::close(listener) };
+    report
+}
+
+/// Whether the notification is an exec attempt, through any entry point
+///
+/// Every attempt counts, whether or not it can succeed: the path it names lives
+/// in memory the caller's other threads can change after it is checked.
+fn is_exec(request: &libc::seccomp_notif) -> bool {
+    let nr = request.data.nr;
+    match request.data.arch {
+        AUDIT_ARCH => {
+            nr == libc::SYS_execve as i32
+                || nr == libc::SYS_execveat as i32
+                || nr == X32_EXECVE
+                || nr == X32_EXECVEAT
+        }
+        AUDIT_ARCH_I386 => nr == I386_EXECVE || nr == I386_EXECVEAT,
+        _ => false,
+    }
+}
+
+/// Name of syscall `nr` on `arch`, if known
+fn syscall_name(arch: u32, nr: i32) -> Option<&'static str> {
+    if arch != AUDIT_ARCH || nr < 0 {
+        return None;
+    }
+    let nr = nr as usize;
+    if nr < X86_64_SYSCALLS.len() {
+        Some(X86_64_SYSCALLS[nr])
+    } else if nr >= X86_64_SYSCALLS_424 {
+        X86_64_SYSCALLS_FROM_424
+            .get(nr - X86_64_SYSCALLS_424)
+            .copied()
+    } else {
+        None
+    }
+}
+
+/// x86_64 syscall names 0..=334
+const X86_64_SYSCALLS: &[&str] = &[
+    "read",
+    "write",
+    "open",
+    "close",
+    "stat",
+    "fstat",
+    "lstat",
+    "poll",
+    "lseek",
+    "mmap",
+    "mprotect",
+    "munmap",
+    "brk",
+    "rt_sigaction",
+    "rt_sigprocmask",
+    "rt_sigreturn",
+    "ioctl",
+    "pread64",
+    "pwrite64",
+    "readv",
+    "writev",
+    "access",
+    "pipe",
+    "select",
+    "sched_yield",
+    "mremap",
+    "msync",
+    "mincore",
+    "madvise",
+    "shmget",
+    "shmat",
+    "shmctl",
+    "dup",
+    "dup2",
+    "pause",
+    "nanosleep",
+    "getitimer",
+    "alarm",
+    "setitimer",
+    "getpid",
+    "sendfile",
+    "socket",
+    "connect",
+    "accept",
+    "sendto",
+    "recvfrom",
+    "sendmsg",
+    "recvmsg",
+    "shutdown",
+    "bind",
+    "listen",
+    "getsockname",
+    "getpeername",
+    "socketpair",
+    "setsockopt",
+    "getsockopt",
+    "clone",
+    "fork",
+    "vfork",
+    "execve",
+    "exit",
+    "wait4",
+    "kill",
+    "uname",
+    "semget",
+    "semop",
+    "semctl",
+    "shmdt",
+    "msgget",
+    "msgsnd",
+    "msgrcv",
+    "msgctl",
+    "fcntl",
+    "flock",
+    "fsync",
+    "fdatasync",
+    "truncate",
+    "ftruncate",
+    "getdents",
+    "getcwd",
+    "chdir",
+    "fchdir",
+    "rename",
+    "mkdir",
+    "rmdir",
+    "creat",
+    "link",
+    "unlink",
+    "symlink",
+    "readlink",
+    "chmod",
+    "fchmod",
+    "chown",
+    "fchown",
+    "lchown",
+    "umask",
+    "gettimeofday",
+    "getrlimit",
+    "getrusage",
+    "sysinfo",
+    "times",
+    "ptrace",
+    "getuid",
+    "syslog",
+    "getgid",
+    "setuid",
+    "setgid",
+    "geteuid",
+    "getegid",
+    "setpgid",
+    "getppid",
+    "getpgrp",
+    "setsid",
+    "setreuid",
+    "setregid",
+    "getgroups",
+    "setgroups",
+    "setresuid",
+    "getresuid",
+    "setresgid",
+    "getresgid",
+    "getpgid",
+    "setfsuid",
+    "setfsgid",
+    "getsid",
+    "capget",
+    "capset",
+    "rt_sigpending",
+    "rt_sigtimedwait",
+    "rt_sigqueueinfo",
+    "rt_sigsuspend",
+    "sigaltstack",
+    "utime",
+    "mknod",
+    "uselib",
+    "personality",
+    "ustat",
+    "statfs",
+    "fstatfs",
+    "sysfs",
+    "getpriority",
+    "setpriority",
+    "sched_setparam",
+    "sched_getparam",
+    "sched_setscheduler",
+    "sched_getscheduler",
+    "sched_get_priority_max",
+    "sched_get_priority_min",
+    "sched_rr_get_interval",
+    "mlock",
+    "munlock",
+    "mlockall",
+    "munlockall",
+    "vhangup",
+    "modify_ldt",
+    "pivot_root",
+    "_sysctl",
+    "prctl",
+    "arch_prctl",
+    "adjtimex",
+    "setrlimit",
+    "chroot",
+    "sync",
+    "acct",
+    "settimeofday",
+    "mount",
+    "umount2",
+    "swapon",
+    "swapoff",
+    "reboot",
+    "sethostname",
+    "setdomainname",
+    "iopl",
+    "ioperm",
+    "create_module",
+    "init_module",
+    "delete_module",
+    "get_kernel_syms",
+    "query_module",
+    "quotactl",
+    "nfsservctl",
+    "getpmsg",
+    "putpmsg",
+    "afs_syscall",
+    "tuxcall",
+    "security",
+    "gettid",
+    "readahead",
+    "setxattr",
+    "lsetxattr",
+    "fsetxattr",
+    "getxattr",
+    "lgetxattr",
+    "fgetxattr",
+    "listxattr",
+    "llistxattr",
+    "flistxattr",
+    "removexattr",
+    "lremovexattr",
+    "fremovexattr",
+    "tkill",
+    "time",
+    "futex",
+    "sched_setaffinity",
+    "sched_getaffinity",
+    "set_thread_area",
+    "io_setup",
+    "io_destroy",
+    "io_getevents",
+    "io_submit",
+    "io_cancel",
+    "get_thread_area",
+    "lookup_dcookie",
+    "epoll_create",
+    "epoll_ctl_old",
+    "epoll_wait_old",
+    "remap_file_pages",
+    "getdents64",
+    "set_tid_address",
+    "restart_syscall",
+    "semtimedop",
+    "fadvise64",
+    "timer_create",
+    "timer_settime",
+    "timer_gettime",
+    "timer_getoverrun",
+    "timer_delete",
+    "clock_settime",
+    "clock_gettime",
+    "clock_getres",
+    "clock_nanosleep",
+    "exit_group",
+    "epoll_wait",
+    "epoll_ctl",
+    "tgkill",
+    "utimes",
+    "vserver",
+    "mbind",
+    "set_mempolicy",
+    "get_mempolicy",
+    "mq_open",
+    "mq_unlink",
+    "mq_timedsend",
+    "mq_timedreceive",
+    "mq_notify",
+    "mq_getsetattr",
+    "kexec_load",
+    "waitid",
+    "add_key",
+    "request_key",
+    "keyctl",
+    "ioprio_set",
+    "ioprio_get",
+    "inotify_init",
+    "inotify_add_watch",
+    "inotify_rm_watch",
+    "migrate_pages",
+    "openat",
+    "mkdirat",
+    "mknodat",
+    "fchownat",
+    "futimesat",
+    "newfstatat",
+    "unlinkat",
+    "renameat",
+    "linkat",
+    "symlinkat",
+    "readlinkat",
+    "fchmodat",
+    "faccessat",
+    "pselect6",
+    "ppoll",
+    "unshare",
+    "set_robust_list",
+    "get_robust_list",
+    "splice",
+    "tee",
+    "sync_file_range",
+    "vmsplice",
+    "move_pages",
+    "utimensat",
+    "epoll_pwait",
+    "signalfd",
+    "timerfd_create",
+    "eventfd",
+    "fallocate",
+    "timerfd_settime",
+    "timerfd_gettime",
+    "accept4",
+    "signalfd4",
+    "eventfd2",
+    "epoll_create1",
+    "dup3",
+    "pipe2",
+    "inotify_init1",
+    "preadv",
+    "pwritev",
+    "rt_tgsigqueueinfo",
+    "perf_event_open",
+    "recvmmsg",
+    "fanotify_init",
+    "fanotify_mark",
+    "prlimit64",
+    "name_to_handle_at",
+    "open_by_handle_at",
+    "clock_adjtime",
+    "syncfs",
+    "sendmmsg",
+    "setns",
+    "getcpu",
+    "process_vm_readv",
+    "process_vm_writev",
+    "kcmp",
+    "finit_module",
+    "sched_setattr",
+    "sched_getattr",
+    "renameat2",
+    "seccomp",
+    "getrandom",
+    "memfd_create",
+    "kexec_file_load",
+    "bpf",
+    "execveat",
+    "userfaultfd",
+    "membarrier",
+    "mlock2",
+    "copy_file_range",
+    "preadv2",
+    "pwritev2",
+    "pkey_mprotect",
+    "pkey_alloc",
+    "pkey_free",
+    "statx",
+    "io_pgetevents",
+    "rseq",
+];
+
+/// Numbers 335..=423 are unused on x86_64
+const X86_64_SYSCALLS_424: usize = 424;
+
+/// x86_64 syscall names from 424 on
+const X86_64_SYSCALLS_FROM_424: &[&str] = &[
+    "pidfd_send_signal",
+    "io_uring_setup",
+    "io_uring_enter",
+    "io_uring_register",
+    "open_tree",
+    "move_mount",
+    "fsopen",
+    "fsconfig",
+    "fsmount",
+    "fspick",
+    "pidfd_open",
+    "clone3",
+    "close_range",
+    "openat2",
+    "pidfd_getfd",
+    "faccessat2",
+    "process_madvise",
+    "epoll_pwait2",
+    "mount_setattr",
+    "quotactl_fd",
+    "landlock_create_ruleset",
+    "landlock_add_rule",
+    "landlock_restrict_self",
+    "memfd_secret",
+    "process_mrelease",
+    "futex_waitv",
+    "set_mempolicy_home_node",
+    "cachestat",
+    "fchmodat2",
+    "map_shadow_stack",
+    "futex_wake",
+    "futex_wait",
+    "futex_requeue",
+    "statmount",
+    "listmount",
+    "lsm_get_self_attr",
+    "lsm_set_self_attr",
+    "lsm_list_modules",
+    "mseal",
+];
@@ -73,21 +73,28 @@ pub fn test_language_support() -> Result<()> {
     let config = TestConfig::default();
     let results = crate::tests::languages::run_language_tests(&config)?;
     assert!(!results.is_empty());
-    
+
     let passed_count = results.iter().filter(|r| r.passed).count();
     println!(
         "Language tests completed: {}/{} passed",
         passed_count,
         results.len()
     );
-    
+
     // Print details of failed tests
     for result in &results {
         if !result.passed {
-            println!("FAILED: {} - {}", result.name, result.error_message.as_ref().unwrap_or(&"No error message".to_string()));
+            println!(
+                "FAILED: {} - {}",
+                result.name,
+                result
+                    .error_message
+                    .as_ref()
+                    .unwrap_or(&"No error message".to_string())
+            );
         } else {
             println!("PASSED: {}", result.name);
         }
     }
     Ok(())
-}
\ No newline at end of file
+}
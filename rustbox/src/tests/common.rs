@@ -113,3 +113,117 @@ where
         details: None,
     }
 }
+
+/// Configuration for box `box_id` in a unit test
+///
+/// Admission control is off so tests running in parallel do not queue behind
+/// each other for host resources.
+pub fn box_config(box_id: u32) -> crate::types::IsolateConfig {
+    crate::types::IsolateConfig {
+        instance_id: format!("rustbox/{}", box_id),
+        admission_control: false,
+        ..Default::default()
+    }
+}
+
+/// Default configuration with a fresh workdir of its own, removed with the
+/// returned directory
+pub fn config_in_tempdir() -> (crate::types::IsolateConfig, tempfile::TempDir) {
+    let workdir = tempfile::tempdir().expect("create test workdir");
+    let config = crate::types::IsolateConfig {
+        workdir: workdir.path().to_path_buf(),
+        ..Default::default()
+    };
+    (config, workdir)
+}
+
+/// `box_config` with a fresh workdir of its own, removed with the returned directory
+pub fn box_config_in_tempdir(box_id: u32) -> (crate::types::IsolateConfig, tempfile::TempDir) {
+    let (config, workdir) = config_in_tempdir();
+    let config = crate::types::IsolateConfig {
+        workdir: config.workdir,
+        ..box_config(box_id)
+    };
+    (config, workdir)
+}
+
+/// Set up the box `config` describes, starting the lock manager if no other
+/// test has yet
+pub fn create_box(config: crate::types::IsolateConfig) -> crate::isolate::Isolate {
+    let _ = crate::lock_manager::init_lock_manager();
+    crate::isolate::Isolate::new(config).expect("create test box")
+}
+
+/// Command line running `code` with python3
+pub fn python(code: &str) -> Vec<String> {
+    vec![
+        "/usr/bin/python3".to_string(),
+        "-c".to_string(),
+        code.to_string(),
+    ]
+}
+
+/// Run `command` once under `config`, without a box registered for it
+pub fn execute(
+    config: &crate::types::IsolateConfig,
+    command: &[String],
+) -> crate::types::ExecutionResult {
+    let mut executor = crate::executor::ProcessExecutor::new(config.clone()).expect("executor");
+    executor.execute(command, None).expect("execute")
+}
+
+/// Whether a test needing root, and a usable cgroup hierarchy if `cgroups`
+/// is set, has to be skipped here
+///
+/// The skip is reported on stderr with the test's name, so a run without
+/// privileges does not pass for coverage.
+pub fn skip_unless_root(cgroups: bool) -> bool {
+    if unsafe { libc::geteuid() } != 0 {
+        skip("root")
+    } else {
+        cgroups && skip_unless_cgroups()
+    }
+}
+
+/// Whether a test needing a usable cgroup hierarchy has to be skipped here
+pub fn skip_unless_cgroups() -> bool {
+    !crate::cgroup::cgroups_available() && skip("cgroups")
+}
+
+/// Report the running test as skipped for want of `missing`
+///
+/// Written to stderr directly rather than with `eprintln!`, which the test
+/// harness captures and only shows for failed tests.
+pub fn skip(missing: &str) -> bool {
+    use std::io::Write;
+
+    // libtest names each test's thread after the test
+    let thread = std::thread::current();
+    let _ = writeln!(
+        std::io::stderr(),
+        "skipped {}: needs {}",
+        thread.name().unwrap_or("test"),
+        missing
+    );
+    true
+}
+
+/// Leave a lock on `box_id` behind as a worker that died holding it would,
+/// returning the lock file and the dead worker's pid
+pub fn leave_dead_holder_lock(box_id: u32) -> (std::path::PathBuf, u32) {
+    let _ = crate::lock_manager::init_lock_manager();
+    let mut worker = Command::new("true").spawn().expect("spawn true");
+    worker.wait().expect("wait for true");
+    let lock_info = crate::types::LockInfo {
+        pid: worker.id(),
+        box_id,
+        created_at: std::time::SystemTime::now(),
+        rustbox_version: env!("CARGO_PKG_VERSION").to_string(),
+    };
+    let lock_path = crate::lock_manager::lock_directory()
+        .expect("lock directory")
+        .join(format!("box-{}.lock", box_id));
+    std::fs::write(&lock_path, serde_json::to_string(&lock_info).unwrap())
+        .expect("write lock file");
+    (lock_path, worker.id())
+}
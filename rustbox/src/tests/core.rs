@@ -52,6 +52,11 @@ pub fn run_core_tests(config: &TestConfig) -> Result<Vec<TestResult>> {
         test_error_handling(config)
     }));
 
+    // Test 9: External output comparator
+    results.push(run_test(config, "External output comparator", || {
+        test_output_comparator(config)
+    }));
+
     Ok(results)
 }
 
@@ -291,9 +296,68 @@ fn test_error_handling(config: &TestConfig) -> Result<()> {
     Ok(())
 }
 
+/// Test delegating the verdict to an external comparator command
+fn test_output_comparator(config: &TestConfig) -> Result<()> {
+    let problem_dir = tempfile::tempdir()?;
+    let comparator = problem_dir.path().join("compare.py");
+    let expected = problem_dir.path().join("expected.txt");
+    std::fs::write(
+        &comparator,
+        "import sys
+expected = open(sys.argv[1]).read().split()
+actual = open(sys.argv[2]).read().split()
+print('AC' if expected == actual else 'WA tokens differ')
+",
+    )?;
+    std::fs::write(&expected, "42\n")?;
+
+    let comparator_command = format!("/usr/bin/python3 {}", comparator.display());
+    let expected_path = expected.display().to_string();
+
+    for (code, verdict) in [("print(42)", "AC"), ("print(41)", "WA")] {
+        let box_id = generate_box_id();
+        let result = execute_rustbox_command(
+            config,
+            &[
+                "execute-code",
+                "--strict",
+                "--box-id",
+                &box_id.to_string(),
+                "--language",
+                "python",
+                "--code",
+                code,
+                "--expected-output",
+                &expected_path,
+                "--comparator",
+                &comparator_command,
+            ],
+        )?;
+
+        TestUtils::validate_success_result(&result)?;
+        let actual = result.get("verdict").and_then(|v| v.as_str()).unwrap_or("");
+        if actual != verdict {
+            return Err(anyhow::anyhow!(
+                "Expected verdict {} for {}, got {:?}",
+                verdict,
+                code,
+                result.get("verdict")
+            ));
+        }
+
+        cleanup_test_box(config, box_id);
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tests::common::{
+        box_config, box_config_in_tempdir, config_in_tempdir, create_box, leave_dead_holder_lock,
+        python, skip, skip_unless_root,
+    };
 
     #[test]
     fn test_generate_test_code() {
@@ -326,6 +390,2472 @@ mod tests {
         assert!(TestUtils::validate_execution_result(&invalid_json).is_err());
     }
 
+    #[test]
+    fn test_comparator_output_contract() {
+        use crate::checker::{Comparator, Verdict};
+        use crate::types::ExecutionResult;
+
+        let run = |stdout: &str| ExecutionResult {
+            stdout: stdout.to_string(),
+            success: true,
+            ..Default::default()
+        };
+
+        let outcome = Comparator::parse_outcome(run("\nWA line 3 differs\nsee diff\n")).unwrap();
+        assert_eq!(outcome.verdict, Verdict::WrongAnswer);
+        assert_eq!(outcome.message, "line 3 differs\nsee diff");
+
+        let outcome = Comparator::parse_outcome(run("PE\n")).unwrap();
+        assert_eq!(outcome.verdict, Verdict::PresentationError);
+        assert!(outcome.message.is_empty());
+
+        assert!(Comparator::parse_outcome(run("maybe\n")).is_err());
+    }
+
+    #[test]
+    fn test_execution_status_unknown_fallback() {
+        use crate::types::ExecutionStatus;
+
+        let status: ExecutionStatus = serde_json::from_str("\"CompileError\"").unwrap();
+        assert_eq!(status, ExecutionStatus::CompileError);
+
+        let status: ExecutionStatus = serde_json::from_str("\"SomeFutureVerdict\"").unwrap();
+        assert_eq!(status, ExecutionStatus::Unknown);
+    }
+
+    #[test]
+    fn test_test_data_references() {
+        use crate::fetch::{DataCache, DataSource};
+
+        assert_eq!(
+            DataSource::parse("s3://bucket/tests/1.in").unwrap(),
+            DataSource::S3 {
+                bucket: "bucket".to_string(),
+                key: "tests/1.in".to_string()
+            }
+        );
+        assert!(DataSource::parse("s3://bucket").is_err());
+        assert!(DataSource::parse("ftp://host/1.in").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("1.in");
+        std::fs::write(&input, "3 4\n").unwrap();
+        let reference = format!("file://{}", input.display());
+        let cache = DataCache::new(dir.path().join("cache"));
+
+        let sha = "ce434a743148081fcb8cbbaf67ac1f09cbe2dcf9515fadb668d7a6e555825600";
+        let mut data = String::new();
+        let mut file = cache.open(&reference, Some(sha)).unwrap();
+        std::io::Read::read_to_string(&mut file, &mut data).unwrap();
+        assert_eq!(data, "3 4\n");
+        assert!(cache.resolve(&reference, Some(&"0".repeat(64))).is_err());
+        assert!(cache.resolve("https://example.invalid/1.in", None).is_err());
+
+        // Fetched data reaches the program byte for byte, UTF-8 or not
+        if skip_unless_root(false) {
+            return;
+        }
+        let binary = dir.path().join("2.in");
+        std::fs::write(&binary, [0xff, 0x00, 0xfe, b'\n']).unwrap();
+        let mut isolate = create_box(box_config(30110));
+        isolate.set_stdin_source(cache.open(&binary.to_string_lossy(), None).unwrap());
+        let command = python("import sys; print(sys.stdin.buffer.read().hex())");
+        let result = isolate.execute(&command, None).unwrap();
+        isolate.cleanup().unwrap();
+        assert_eq!(result.stdout.trim(), "ff00fe0a", "{:?}", result);
+    }
+
+    #[test]
+    fn test_execution_history_pruning() {
+        use crate::history::{ExecutionSummary, HistoryPolicy};
+        use crate::types::ExecutionResult;
+
+        let mut history = std::collections::VecDeque::new();
+        let policy = HistoryPolicy {
+            max_entries: 2,
+            max_age: None,
+        };
+        for code in 0..3 {
+            let result = ExecutionResult {
+                exit_code: Some(code),
+                ..Default::default()
+            };
+            let summary = ExecutionSummary::new(&["true".to_string()], &result);
+            crate::history::record(&mut history, summary, &policy);
+        }
+
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].exit_code, Some(1));
+        assert_eq!(history[0].command_hash, history[1].command_hash);
+
+        let expired = HistoryPolicy {
+            max_entries: 2,
+            max_age: Some(std::time::Duration::ZERO),
+        };
+        let summary = ExecutionSummary::new(&["true".to_string()], &ExecutionResult::default());
+        crate::history::record(&mut history, summary, &expired);
+        assert!(history.len() <= 1);
+
+        // Each box keeps its history in its own record
+        if skip_unless_root(false) {
+            return;
+        }
+        let mut isolate = create_box(box_config(30100));
+        let command = python("pass");
+        isolate.execute(&command, None).unwrap();
+
+        isolate.execute(&command, None).unwrap();
+        let loaded = crate::isolate::Isolate::load("rustbox/30100")
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.history().len(), 2);
+        assert_eq!(loaded.history()[1].exit_code, Some(0));
+        isolate.cleanup().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "history-db")]
+    fn test_history_db_records_and_purges() {
+        use crate::history_db::{HistoryDb, HistoryFilter, MAX_STORED_OUTPUT};
+        use crate::types::{ExecutionResult, ExecutionStatus, IsolateConfig};
+
+        let dir = tempfile::tempdir().unwrap();
+        let db = HistoryDb::open(&dir.path().join("history.db")).unwrap();
+        let started = chrono::Utc::now() - chrono::Duration::hours(2);
+        let config = |box_id: u32| IsolateConfig {
+            instance_id: format!("rustbox/{}", box_id),
+            wall_time_limit: Some(std::time::Duration::from_millis(1500)),
+            ..Default::default()
+        };
+        let result = ExecutionResult {
+            status: ExecutionStatus::RuntimeError,
+            exit_code: Some(3),
+            stdout: "x".repeat(MAX_STORED_OUTPUT * 2),
+            ..Default::default()
+        };
+        let command = vec![
+            "/bin/sh".to_string(),
+            "-c".to_string(),
+            "exit 3".to_string(),
+        ];
+        let old = db.record(&config(7), &command, started, &result).unwrap();
+        let recent = db
+            .record(&config(7), &command, chrono::Utc::now(), &result)
+            .unwrap();
+        db.record(&config(8), &command, chrono::Utc::now(), &result)
+            .unwrap();
+
+        let record = db.get(old).unwrap().unwrap();
+        assert_eq!(record.instance_id, "rustbox/7");
+        assert_eq!(record.command, command);
+        assert_eq!(record.status, ExecutionStatus::RuntimeError);
+        assert_eq!(record.limits["wall_time_limit"], 1.5);
+        assert_eq!(
+            record.started_at.timestamp_micros(),
+            started.timestamp_micros()
+        );
+        assert_eq!(
+            record.result["stdout"].as_str().unwrap().len(),
+            MAX_STORED_OUTPUT
+        );
+        assert!(db.get(recent + 10).unwrap().is_none());
+
+        // Newest first, filtered by box and start time
+        let box_7 = HistoryFilter {
+            instance_id: Some("rustbox/7".to_string()),
+            ..Default::default()
+        };
+        let ids: Vec<i64> = db.list(&box_7, 10).unwrap().iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![recent, old]);
+        let last_hour = HistoryFilter {
+            since: Some(chrono::Utc::now() - chrono::Duration::hours(1)),
+            ..Default::default()
+        };
+        assert_eq!(db.list(&last_hour, 10).unwrap().len(), 2);
+        assert_eq!(db.list(&HistoryFilter::default(), 1).unwrap().len(), 1);
+
+        let older = HistoryFilter {
+            before: Some(chrono::Utc::now() - chrono::Duration::hours(1)),
+            ..Default::default()
+        };
+        assert_eq!(db.purge(&older).unwrap(), 1);
+        assert!(db.get(old).unwrap().is_none());
+        assert_eq!(db.purge(&HistoryFilter::default()).unwrap(), 2);
+    }
+
+    #[test]
+    fn test_build_info() {
+        use crate::build_info::BuildInfo;
+        use crate::types::ExecutionResult;
+
+        let build = BuildInfo::current();
+        assert_eq!(build.version, env!("CARGO_PKG_VERSION"));
+
+        // Every Cargo.toml feature is reported exactly when it is on
+        let enabled = [
+            ("core", cfg!(feature = "core")),
+            ("zen", cfg!(feature = "zen")),
+            ("http", cfg!(feature = "http")),
+            ("s3", cfg!(feature = "s3")),
+            ("cli", cfg!(feature = "cli")),
+            ("server", cfg!(feature = "server")),
+            ("seccomp-native", cfg!(feature = "seccomp-native")),
+            ("cgroup-v1", cfg!(feature = "cgroup-v1")),
+            ("tokio", cfg!(feature = "tokio")),
+            ("grpc", cfg!(feature = "grpc")),
+            ("criu", cfg!(feature = "criu")),
+            ("history-db", cfg!(feature = "history-db")),
+            ("perf-tests", cfg!(feature = "perf-tests")),
+        ];
+        let manifest: toml::Table = include_str!("../../Cargo.toml").parse().unwrap();
+        let declared: std::collections::BTreeSet<&str> = manifest["features"]
+            .as_table()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .filter(|&name| name != "default")
+            .collect();
+        assert_eq!(
+            declared,
+            enabled.iter().map(|(name, _)| *name).collect(),
+            "the feature table above is out of date with Cargo.toml"
+        );
+        for (name, on) in enabled {
+            assert_eq!(build.features.contains(&name.to_string()), on, "{}", name);
+        }
+        assert!(build.summary().starts_with("rustbox "));
+
+        // Only results that asked for it carry build information
+        let json = serde_json::to_value(ExecutionResult::default()).unwrap();
+        assert!(json.get("build").is_none());
+        let result = ExecutionResult {
+            build: Some(build.clone()),
+            ..Default::default()
+        };
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["build"]["version"], build.version);
+    }
+
+    #[test]
+    fn test_language_file_conventions() {
+        use crate::config::{Entrypoint, FileConventions, LanguageRegistry, RustBoxConfig};
+
+        let registry = LanguageRegistry::builtin();
+        assert_eq!(registry.by_extension("cc"), Some("cpp"));
+        assert_eq!(registry.by_extension("GO"), Some("go"));
+        assert_eq!(registry.by_extension("rb"), None);
+        assert_eq!(registry.resolve("C++"), Some("cpp"));
+        assert_eq!(registry.resolve("py"), Some("python"));
+
+        let java = registry.conventions("java").unwrap();
+        let code = "import java.util.*;\npublic class Solution {\n}\n";
+        assert_eq!(java.source_file_name(code), "Solution.java");
+        assert_eq!(java.source_file_name("class Helper {}"), "Main.java");
+        assert_eq!(
+            registry.conventions("go").unwrap().source_file_name(""),
+            "main.go"
+        );
+        assert_eq!(
+            registry.conventions("cxx").unwrap().staged_file_name(7),
+            "7.cpp"
+        );
+
+        // config.json can add languages without touching the CLI
+        let mut config: RustBoxConfig =
+            serde_json::from_str(&std::fs::read_to_string("config.json").unwrap()).unwrap();
+        let mut kotlin = config.languages["java"].clone();
+        kotlin.files = Some(FileConventions {
+            extensions: vec!["kt".to_string()],
+            aliases: vec!["kotlin".to_string()],
+            source_file: "Main.{ext}".to_string(),
+            entrypoint: Entrypoint::Fixed,
+        });
+        config.languages.insert("kt".to_string(), kotlin);
+        let registry = LanguageRegistry::from_config(&config);
+        assert_eq!(registry.by_extension("kt"), Some("kt"));
+        assert_eq!(
+            registry.conventions("kotlin").unwrap().source_file_name(""),
+            "Main.kt"
+        );
+        assert_eq!(registry.by_extension("py"), Some("python"));
+    }
+
+    #[test]
+    fn test_registered_language_profile() {
+        use crate::config::{Entrypoint, FileConventions, LanguageRegistry};
+        use crate::isolate::CleanupPolicy;
+        use crate::profile::LanguageProfile;
+        use crate::types::{ExecutionOverrides, ExecutionStatus, IsolateConfig};
+
+        let java = LanguageProfile::get("java").unwrap();
+        let mut config = IsolateConfig::default();
+        java.apply(&mut config);
+        assert!(!config.enable_pid_namespace);
+        assert_eq!(config.process_limit, Some(50));
+        let (compile, run) = java.commands("Solution.java", "");
+        assert_eq!(compile.unwrap()[3], "Solution.java");
+        assert_eq!(run, ["java", "-cp", ".", "Solution"]);
+        assert!(!LanguageProfile::get("python").unwrap().stages_source());
+
+        assert!(LanguageProfile::register(LanguageProfile {
+            name: "empty".to_string(),
+            ..Default::default()
+        })
+        .is_err());
+        LanguageProfile::register(LanguageProfile {
+            name: "PyScript".to_string(),
+            run: vec!["/usr/bin/python3".to_string(), "{source}".to_string()],
+            environment: [("GREETING".to_string(), "hi".to_string())].into(),
+            files: Some(FileConventions {
+                extensions: vec!["pys".to_string()],
+                aliases: Vec::new(),
+                source_file: "main.{ext}".to_string(),
+                entrypoint: Entrypoint::Fixed,
+            }),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            LanguageRegistry::load().by_extension("pys"),
+            Some("pyscript")
+        );
+        assert!(LanguageProfile::get("pyscript").unwrap().stages_source());
+
+        if skip_unless_root(true) {
+            return;
+        }
+        let mut isolate = create_box(box_config(964));
+        let code = "import os, sys\nprint(os.environ['GREETING'], sys.argv[0])";
+        let result = isolate
+            .execute_code_string("pyscript", code, None, &ExecutionOverrides::default())
+            .unwrap();
+        isolate.finish(CleanupPolicy::Always).unwrap();
+
+        assert_eq!(result.status, ExecutionStatus::Success);
+        assert_eq!(result.stdout.trim(), "hi main.pys");
+    }
+
+    #[test]
+    fn test_language_registry_register() {
+        use crate::config::{Entrypoint, FileConventions, LanguageRegistry};
+        use crate::isolate::CleanupPolicy;
+        use crate::profile::LanguageProfile;
+        use crate::types::{ExecutionOverrides, ExecutionStatus, IsolateConfig};
+        use std::time::Duration;
+
+        let files = FileConventions {
+            extensions: vec!["py2".to_string()],
+            aliases: vec!["snake".to_string()],
+            source_file: "prog.{ext}".to_string(),
+            entrypoint: Entrypoint::Fixed,
+        };
+        let profile = LanguageProfile {
+            // Stands in for a compiler: "builds" the source into ./prog
+            compile: Some(vec![
+                "/usr/bin/python3".to_string(),
+                "-c".to_string(),
+                "import shutil; shutil.copy('{source}', '{stem}')".to_string(),
+            ]),
+            run: vec!["/usr/bin/python3".to_string(), "{stem}".to_string()],
+            cpu_time_seconds: Some(3),
+            wall_time_seconds: Some(6),
+            ..Default::default()
+        };
+        let mut nameless = files.clone();
+        nameless.source_file.clear();
+        assert!(LanguageRegistry::register("broken", nameless, profile.clone()).is_err());
+        LanguageRegistry::register("Snakelang", files, profile).unwrap();
+
+        let registry = LanguageRegistry::load();
+        assert_eq!(registry.resolve("snake"), Some("snakelang"));
+        assert_eq!(registry.by_extension("py2"), Some("snakelang"));
+        assert_eq!(registry.resolve("broken"), None);
+        let mut config = IsolateConfig::default();
+        LanguageProfile::get("snakelang")
+            .unwrap()
+            .apply(&mut config);
+        assert_eq!(config.cpu_time_limit, Some(Duration::from_secs(3)));
+        assert_eq!(config.wall_time_limit, Some(Duration::from_secs(6)));
+
+        if skip_unless_root(true) {
+            return;
+        }
+        let mut isolate = create_box(box_config(965));
+        let result = isolate
+            .execute_code_string(
+                "snake",
+                "print(input()[::-1])",
+                Some("abc\n"),
+                &ExecutionOverrides::default(),
+            )
+            .unwrap();
+        isolate.finish(CleanupPolicy::Always).unwrap();
+
+        assert_eq!(result.status, ExecutionStatus::Success);
+        assert_eq!(result.stdout.trim(), "cba");
+    }
+
+    #[test]
+    fn test_self_test_regressions_against_baseline() {
+        use crate::canary::{probes, ProbeOutcome, SelfTestReport};
+
+        let report = |outcomes: &[(&str, bool)]| SelfTestReport {
+            outcomes: outcomes
+                .iter()
+                .map(|(name, contained)| ProbeOutcome {
+                    name: name.to_string(),
+                    contained: *contained,
+                    status: String::new(),
+                })
+                .collect(),
+        };
+        // Network isolation never worked on this host, so it is not a regression
+        let baseline = report(&[("cpu_time_limit", true), ("network_isolation", false)]);
+        assert!(
+            report(&[("cpu_time_limit", true), ("network_isolation", false)])
+                .regressions(&baseline)
+                .is_empty()
+        );
+        assert_eq!(
+            report(&[("cpu_time_limit", false), ("network_isolation", false)])
+                .regressions(&baseline),
+            vec!["cpu_time_limit".to_string()]
+        );
+
+        let names: Vec<&str> = probes().iter().map(|probe| probe.name).collect();
+        assert!(names.contains(&"memory_limit"));
+        assert!(names.contains(&"exec_limit"));
+    }
+
+    #[test]
+    fn test_metrics_count_executions() {
+        use crate::metrics::{Metrics, LATENCY_BUCKETS};
+        use crate::types::{ExecutionResult, ExecutionStatus};
+
+        // Other tests run executions concurrently; compare against a baseline
+        let before = Metrics::collect();
+        let result = ExecutionResult {
+            status: ExecutionStatus::TimeLimit,
+            wall_time: 0.3,
+            ..Default::default()
+        };
+        crate::metrics::record_execution(&result);
+        crate::metrics::record_execution(&result);
+        crate::metrics::record_cleanup_failure();
+        let after = Metrics::collect();
+        let time_limit = |metrics: &Metrics| metrics.executions.get("TimeLimit").copied();
+        assert!(time_limit(&after).unwrap() >= time_limit(&before).unwrap_or(0) + 2);
+        assert!(after.executions_total() >= before.executions_total() + 2);
+        assert!(after.latency.count >= before.latency.count + 2);
+        assert!(after.cleanup_failures > before.cleanup_failures);
+        assert_eq!(after.latency.buckets.len(), LATENCY_BUCKETS.len());
+        // 0.3s lands in the 0.5s bucket and every one after it, not in 0.25s
+        let bucket = |metrics: &Metrics, le: f64| {
+            metrics
+                .latency
+                .buckets
+                .iter()
+                .find(|(bound, _)| *bound == le)
+                .unwrap()
+                .1
+        };
+        assert!(bucket(&after, 0.5) >= bucket(&before, 0.5) + 2);
+        assert!(bucket(&after, 60.0) >= bucket(&before, 60.0) + 2);
+
+        let exported = after.to_prometheus();
+        assert!(exported.contains("# TYPE rustbox_execution_duration_seconds histogram"));
+        assert!(exported.contains(&format!(
+            "rustbox_executions_by_status_total{{status=\"TimeLimit\"}} {}",
+            time_limit(&after).unwrap()
+        )));
+        assert!(exported.contains(&format!(
+            "rustbox_execution_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            after.latency.count
+        )));
+        assert!(exported.contains("rustbox_cleanup_failures_total "));
+        assert!(exported.contains("rustbox_active_boxes "));
+    }
+
+    #[test]
+    #[cfg(feature = "server")]
+    fn test_server_routes_and_auth() {
+        use crate::server::{route, serve, ServeOptions};
+        use std::io::{Read, Write};
+
+        assert_eq!(route("GET", "/health", b"").status, 200);
+        assert_eq!(route("GET", "/nope", b"").status, 404);
+        assert_eq!(route("GET", "/boxes/1/run", b"").status, 405);
+        assert_eq!(route("POST", "/boxes/x", b"").status, 400);
+        let invalid = route("POST", "/boxes/1/execute-code", b"{\"code\": 1}");
+        assert_eq!(invalid.status, 400);
+        assert!(invalid.body["error"]
+            .as_str()
+            .unwrap()
+            .contains("execute-code"));
+        // Without queue workers the queue endpoints are unavailable
+        assert_eq!(route("GET", "/queue", b"").status, 503);
+        let metrics = route("GET", "/metrics", b"");
+        assert_eq!(metrics.status, 200);
+        assert!(metrics
+            .text
+            .unwrap()
+            .contains("# TYPE rustbox_executions_total counter"));
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let options = ServeOptions {
+            workers: 1,
+            token: Some("secret".to_string()),
+            self_test: None,
+            queue: None,
+        };
+        std::thread::spawn(move || serve(listener, options));
+
+        let request = |authorization: &str| {
+            let mut stream = std::net::TcpStream::connect(addr).unwrap();
+            write!(stream, "GET /health HTTP/1.1\r\n{}\r\n", authorization).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        assert!(request("").starts_with("HTTP/1.1 401"));
+        let response = request("Authorization: Bearer secret\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("\"status\":\"ok\""));
+        assert!(request("Authorization: bearer secret\r\n").starts_with("HTTP/1.1 200 OK"));
+        assert!(request("Authorization: Bearer secreT\r\n").starts_with("HTTP/1.1 401"));
+        assert!(request("Authorization: Bearer secret2\r\n").starts_with("HTTP/1.1 401"));
+        assert!(request("Authorization: Basic secret\r\n").starts_with("HTTP/1.1 401"));
+    }
+
+    #[test]
+    fn test_queue_orders_by_priority_within_group_limits() {
+        use crate::queue::{ExecutionQueue, JobState, QueueOptions, Submission};
+        use crate::types::ExecutionResult;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, Mutex};
+        use std::time::Duration;
+
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let in_group = Arc::new(AtomicUsize::new(0));
+        let (release, blocked) = crossbeam_channel::unbounded::<()>();
+        let (record, group_count) = (Arc::clone(&started), Arc::clone(&in_group));
+        let options = QueueOptions {
+            workers: 2,
+            first_box_id: 2000,
+        };
+        let queue = ExecutionQueue::with_runner(options, move |_, submission| {
+            record.lock().unwrap().push(submission.code.clone());
+            let grouped = submission.group.is_some();
+            if grouped {
+                assert_eq!(group_count.fetch_add(1, Ordering::SeqCst), 0);
+            }
+            if submission.code == "block" {
+                blocked.recv().unwrap();
+            } else {
+                std::thread::sleep(Duration::from_millis(20));
+            }
+            if grouped {
+                group_count.fetch_sub(1, Ordering::SeqCst);
+            }
+            Ok(ExecutionResult::default())
+        })
+        .unwrap();
+        let submit = |code: &str, priority: i32, group: Option<&str>| {
+            queue
+                .submit(Submission {
+                    language: "python".to_string(),
+                    code: code.to_string(),
+                    priority,
+                    group: group.map(str::to_string),
+                    max_concurrent: group.map(|_| 1),
+                    ..Default::default()
+                })
+                .unwrap()
+        };
+
+        let mut ids = vec![submit("block", 0, None), submit("block", 0, None)];
+        while queue.status().running < 2 {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        ids.push(submit("low", -1, None));
+        ids.push(submit("group-1", 9, Some("contest")));
+        ids.push(submit("group-2", 9, Some("contest")));
+        ids.push(submit("high", 5, None));
+        let status = queue.status();
+        assert_eq!((status.running, status.queued), (2, 4));
+        assert_eq!(status.jobs[2].priority, 9);
+        assert!(queue
+            .submit(Submission {
+                language: "python".to_string(),
+                max_concurrent: Some(1),
+                ..Default::default()
+            })
+            .is_err());
+
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        for id in &ids {
+            let mut waited = 0;
+            while !matches!(queue.job(*id).unwrap().state, JobState::Finished { .. }) {
+                std::thread::sleep(Duration::from_millis(5));
+                waited += 1;
+                assert!(waited < 1000, "job {} did not finish", id);
+            }
+        }
+
+        // The group runs one at a time, so "high" overtakes its second submission,
+        // and a worker finding the group busy takes "low" rather than waiting
+        let started = started.lock().unwrap();
+        let position = |code: &str| started.iter().position(|c| c == code).unwrap();
+        assert_eq!(started.len(), 6);
+        assert!(position("group-1") < position("high"));
+        assert!(position("high") < position("group-2"));
+        assert_eq!(queue.status().finished, 6);
+    }
+
+    #[test]
+    #[cfg(feature = "grpc")]
+    fn test_grpc_streams_execute_code_output() {
+        use crate::grpc::proto::{
+            execute_event::Event, rustbox_client::RustboxClient, ExecuteCodeRequest, StatusRequest,
+            Stream,
+        };
+        use crate::grpc::{serve, GrpcOptions};
+
+        fn authorized<T>(message: T) -> tonic::Request<T> {
+            let mut request = tonic::Request::new(message);
+            let token = "Bearer secret".parse().unwrap();
+            request.metadata_mut().insert("authorization", token);
+            request
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let options = GrpcOptions {
+            token: Some("secret".to_string()),
+        };
+        std::thread::spawn(move || serve(listener, options));
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut client = RustboxClient::connect(format!("http://{}", addr))
+                .await
+                .unwrap();
+            let status = client.status(StatusRequest { box_id: 974 }).await;
+            assert_eq!(status.unwrap_err().code(), tonic::Code::Unauthenticated);
+            if skip_unless_root(false) {
+                return;
+            }
+            let _ = crate::lock_manager::init_lock_manager();
+
+            let request = ExecuteCodeRequest {
+                box_id: 974,
+                language: "python".to_string(),
+                code: "import sys\nprint('first', flush=True)\nprint('oops', file=sys.stderr)"
+                    .to_string(),
+                auto_cleanup: true,
+                ..Default::default()
+            };
+            let mut events = client
+                .execute_code(authorized(request))
+                .await
+                .unwrap()
+                .into_inner();
+            let (mut stdout, mut stderr, mut result) = (Vec::new(), Vec::new(), None);
+            while let Some(event) = events.message().await.unwrap() {
+                match event.event.unwrap() {
+                    Event::Output(chunk) if chunk.stream == Stream::Stdout as i32 => {
+                        stdout.extend(chunk.data)
+                    }
+                    Event::Output(chunk) => stderr.extend(chunk.data),
+                    Event::Result(done) => result = Some(done),
+                }
+            }
+            assert_eq!(String::from_utf8(stdout).unwrap(), "first\n");
+            assert!(String::from_utf8(stderr).unwrap().contains("oops"));
+            let result = result.expect("stream should end with the result");
+            assert_eq!(result.status, "Success");
+            let json: serde_json::Value = serde_json::from_str(&result.json).unwrap();
+            assert_eq!(json["stdout"], "first\n");
+
+            // Cleaned up after the run
+            let status = client
+                .status(authorized(StatusRequest { box_id: 974 }))
+                .await
+                .unwrap()
+                .into_inner();
+            assert!(!status.exists && status.running.is_none());
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "grpc")]
+    fn test_grpc_client_streams_waits_and_cancels() {
+        use crate::client::{Client, ClientOptions};
+        use crate::grpc::proto::{ExecuteCodeRequest, Stream};
+        use crate::grpc::{serve, GrpcOptions};
+        use crate::types::IsolateError;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let options = GrpcOptions {
+            token: Some("secret".to_string()),
+        };
+        std::thread::spawn(move || serve(listener, options));
+
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let unreachable = format!("http://{}", closed.local_addr().unwrap());
+            drop(closed);
+            let refused = Client::connect(&unreachable, ClientOptions::default()).await;
+            assert!(matches!(refused, Err(IsolateError::ResourceUnavailable(_))));
+
+            let client = Client::connect(
+                &addr,
+                ClientOptions {
+                    token: Some("secret".to_string()),
+                    connections: 2,
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+            if skip_unless_root(false) {
+                return;
+            }
+            let _ = crate::lock_manager::init_lock_manager();
+
+            let mut execution = client
+                .submit(ExecuteCodeRequest {
+                    box_id: 975,
+                    language: "python".to_string(),
+                    code: "print('first', flush=True)\nprint('second')".to_string(),
+                    auto_cleanup: true,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            let chunk = execution.stream_output().await.unwrap().unwrap();
+            assert_eq!(chunk.stream, Stream::Stdout as i32);
+            assert!(chunk.data.starts_with(b"first"));
+            let result = execution.wait().await.unwrap();
+            assert_eq!(result.status, "Success");
+            let json: serde_json::Value = serde_json::from_str(&result.json).unwrap();
+            assert_eq!(json["stdout"], "first\nsecond\n");
+            assert!(!client.status(975).await.unwrap().exists);
+
+            let mut execution = client
+                .submit(ExecuteCodeRequest {
+                    box_id: 976,
+                    language: "python".to_string(),
+                    code: "print('ready', flush=True)\nwhile True: pass".to_string(),
+                    auto_cleanup: true,
+                    ..Default::default()
+                })
+                .await
+                .unwrap();
+            execution.stream_output().await.unwrap();
+            assert!(client.cancel(execution.box_id()).await.unwrap() > 0);
+            assert!(!execution.wait().await.unwrap().success);
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "criu")]
+    fn test_checkpoint_record_pins_box() {
+        use crate::checkpoint::{restore, Checkpoint};
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Checkpoint::load(dir.path()).is_err());
+
+        let checkpoint = Checkpoint {
+            instance_id: "rustbox/966".to_string(),
+            pid: 42,
+            images_dir: dir.path().to_path_buf(),
+            created: chrono::Utc::now(),
+        };
+        std::fs::write(
+            dir.path().join("rustbox-checkpoint.json"),
+            serde_json::to_string(&checkpoint).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(Checkpoint::load(dir.path()).unwrap(), checkpoint);
+        // Images are only restored into the box they were taken in
+        let err = restore("rustbox/967", dir.path()).unwrap_err();
+        assert!(err.to_string().contains("rustbox/966"));
+    }
+
+    #[test]
+    #[cfg(feature = "tokio")]
+    fn test_execution_handle_cancels_on_drop() {
+        use crate::async_exec::ExecutionHandle;
+        use crate::executor::CancelToken;
+        use crate::types::IsolateError;
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let token = CancelToken::new();
+        let (done_tx, done_rx) = std::sync::mpsc::channel();
+        runtime.block_on(async {
+            let watched = token.clone();
+            let task = tokio::task::spawn_blocking(move || {
+                while !watched.is_cancelled() {
+                    std::thread::sleep(std::time::Duration::from_millis(1));
+                }
+                let _ = done_tx.send(());
+                Err(IsolateError::Process("cancelled".to_string()))
+            });
+            let handle = ExecutionHandle::new(task, token.clone());
+            assert!(!handle.is_finished());
+            drop(handle);
+        });
+        assert!(token.is_cancelled());
+        done_rx
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .expect("blocking task should stop once the handle is dropped");
+    }
+
+    #[test]
+    fn test_registry_schema_migrates_old_versions() {
+        use crate::schema::{self, SchemaError, REGISTRY_VERSION};
+        use crate::types::{DirectoryPermissions, IsolateConfig};
+        use std::collections::HashMap;
+
+        #[derive(serde::Serialize, serde::Deserialize)]
+        struct Instance {
+            config: IsolateConfig,
+            created_at: chrono::DateTime<chrono::Utc>,
+        }
+
+        // A version 1 registry from before most of today's config fields existed
+        let v1 = include_str!("fixtures/instances_v1.json");
+        let instances: HashMap<String, Instance> = schema::parse(v1).unwrap();
+        let config = &instances["rustbox/3"].config;
+        assert_eq!(config.memory_limit, Some(256 * 1024 * 1024));
+        assert_eq!(
+            config.directory_bindings[0].permissions,
+            DirectoryPermissions::ReadOnly
+        );
+        assert_eq!(config.directory_bindings[0].uid_map, None);
+        // Fields added since then take their defaults
+        assert_eq!(config.umask, IsolateConfig::default().umask);
+        assert!(config.admission_control);
+        assert_eq!(config.output_limit, None);
+
+        // Writing stores the current version, which reads back unchanged
+        let written = schema::to_string(&instances).unwrap();
+        let document: serde_json::Value = serde_json::from_str(&written).unwrap();
+        assert_eq!(document["version"], REGISTRY_VERSION);
+        let reread: HashMap<String, Instance> = schema::parse(&written).unwrap();
+        assert_eq!(
+            reread["rustbox/3"].created_at,
+            instances["rustbox/3"].created_at
+        );
+
+        assert!(schema::parse::<Instance>("{}").unwrap().is_empty());
+        assert!(matches!(
+            schema::parse::<Instance>(r#"{"version": 99, "instances": {}}"#),
+            Err(SchemaError::TooNew(99))
+        ));
+        assert!(matches!(
+            schema::entries("[]"),
+            Err(SchemaError::NotAnObject)
+        ));
+    }
+
+    #[test]
+    fn test_interactive_run_connects_both_sides() {
+        use crate::checker::Verdict;
+
+        let (mut config, _workdir) = config_in_tempdir();
+        config.admission_control = false;
+
+        // The interactor asks for the successor of 41 and accepts only 42
+        let interactor = python(
+            "import sys\nprint(41, flush=True)\nsys.exit(0 if sys.stdin.readline().strip() == '42' else 1)",
+        );
+        let submission = python("import sys\nprint(int(input()) + 1)\nsys.stderr.write('done')");
+        let mut executor = crate::executor::ProcessExecutor::new(config.clone()).unwrap();
+        let outcome = crate::interactive::run(&mut executor, &submission, &interactor).unwrap();
+        assert!(outcome.submission.success);
+        assert_eq!(outcome.submission.stderr, "done");
+        assert_eq!(outcome.verdict(), Some(Verdict::Accepted));
+
+        let wrong = python("print(int(input()) - 1)");
+        let mut executor = crate::executor::ProcessExecutor::new(config).unwrap();
+        let outcome = crate::interactive::run(&mut executor, &wrong, &interactor).unwrap();
+        assert_eq!(outcome.interactor.exit_code, Some(1));
+        assert_eq!(outcome.verdict(), Some(Verdict::WrongAnswer));
+    }
+
+    #[test]
+    fn test_state_check_without_repair_changes_nothing() {
+        use crate::state_check::StateIssueKind;
+
+        // An unreadable record is reported but left as it is
+        let record = crate::paths::paths()
+            .instances_dir()
+            .join("rustbox%2F30040.json");
+        std::fs::create_dir_all(record.parent().unwrap()).unwrap();
+        std::fs::write(&record, "{\"version\": 1, \"instance\":").unwrap();
+
+        let report = crate::state_check::check(false);
+        assert!(report.issues.iter().all(|issue| !issue.repaired));
+        assert_eq!(report.is_consistent(), report.unrepaired().count() == 0);
+        assert!(report
+            .issues
+            .iter()
+            .any(|issue| issue.kind == StateIssueKind::CorruptRegistry && issue.path == record));
+        let content = std::fs::read_to_string(&record).unwrap();
+        std::fs::remove_file(&record).unwrap();
+        assert_eq!(content, "{\"version\": 1, \"instance\":");
+    }
+
+    #[test]
+    #[cfg(feature = "cgroup-v1")]
+    fn test_state_check_removes_stale_freezer_and_cpuset_groups() {
+        use crate::state_check::StateIssueKind;
+
+        if skip_unless_root(true) {
+            return;
+        }
+        let freezer = crate::cgroup::cgroup_root().join("freezer");
+        if !freezer.exists() && skip("the freezer controller") {
+            return;
+        }
+
+        // A crashed run can leave its group frozen with the program inside
+        let group = freezer.join("rustbox_30130");
+        std::fs::create_dir_all(&group).unwrap();
+        let mut stray = std::process::Command::new("sleep")
+            .arg("60")
+            .spawn()
+            .unwrap();
+        std::fs::write(group.join("tasks"), stray.id().to_string()).unwrap();
+        std::fs::write(group.join("freezer.state"), "FROZEN").unwrap();
+
+        let report = crate::state_check::check(false);
+        let found_at = |report: &crate::state_check::StateReport, group: &std::path::Path| {
+            report
+                .issues
+                .iter()
+                .find(|issue| issue.kind == StateIssueKind::CgroupResidue && issue.path == group)
+                .cloned()
+        };
+        let issue = found_at(&report, &group).expect("stale freezer group not reported");
+        assert!(!issue.repaired);
+        assert!(issue.detail.contains("1 stray processes"));
+        assert!(group.exists());
+
+        let report = crate::state_check::check(true);
+        assert!(
+            found_at(&report, &group)
+                .expect("stale freezer group not reported")
+                .repaired
+        );
+        assert!(!group.exists());
+        assert!(!stray.wait().unwrap().success());
+
+        // cpuset is swept too where the host has it mounted
+        let cpuset = crate::cgroup::cgroup_root().join("cpuset");
+        if cpuset.exists() {
+            let group = cpuset.join("rustbox_30130");
+            std::fs::create_dir_all(&group).unwrap();
+            let report = crate::state_check::check(true);
+            assert!(
+                found_at(&report, &group)
+                    .expect("stale cpuset group not reported")
+                    .repaired
+            );
+            assert!(!group.exists());
+        }
+    }
+
+    #[test]
+    fn test_instance_store_records_and_migration() {
+        use crate::instance_store::InstanceStore;
+        use serde_json::{json, Value};
+
+        let root = tempfile::tempdir().unwrap();
+        let store = InstanceStore::new(root.path().join("instances"));
+        assert!(store.ids().unwrap().is_empty());
+        assert!(store.load::<Value>("rustbox/3").unwrap().is_none());
+
+        store.save("rustbox/3", &json!({"box": 3})).unwrap();
+        store.save("odd%2F/id", &json!({"box": "odd"})).unwrap();
+        store.save("rustbox/3", &json!({"box": 4})).unwrap();
+        let mut ids = store.ids().unwrap();
+        ids.sort();
+        assert_eq!(ids, ["odd%2F/id", "rustbox/3"]);
+        let record = store.load::<Value>("rustbox/3").unwrap();
+        assert_eq!(record, Some(json!({"box": 4})));
+        store.remove("odd%2F/id").unwrap();
+        store.remove("odd%2F/id").unwrap();
+        assert_eq!(store.ids().unwrap(), ["rustbox/3"]);
+
+        // A broken record only affects its own box and can be moved aside
+        std::fs::write(store.record_path("rustbox/5"), "{").unwrap();
+        assert!(store.load::<Value>("rustbox/5").is_err());
+        let unreadable = store.unreadable::<Value>().unwrap();
+        assert_eq!(unreadable.len(), 1);
+        assert_eq!(unreadable[0].0, "rustbox/5");
+        let backup = store.quarantine("rustbox/5").unwrap();
+        assert!(backup.exists());
+        assert_eq!(store.ids().unwrap(), ["rustbox/3"]);
+
+        // An old registry is moved in once; records already stored win
+        let registry = root.path().join("instances.json");
+        let lock = root.path().join("instances.json.lock");
+        std::fs::write(&registry, include_str!("fixtures/instances_v1.json")).unwrap();
+        store.remove("rustbox/3").unwrap();
+        assert_eq!(store.migrate::<Value>(&registry, &lock).unwrap(), 1);
+        assert!(!registry.exists());
+        assert!(root.path().join("instances.json.migrated").exists());
+        let migrated = store.load::<Value>("rustbox/3").unwrap().unwrap();
+        assert_eq!(migrated["config"]["memory_limit"], 268435456);
+        assert_eq!(store.migrate::<Value>(&registry, &lock).unwrap(), 0);
+        std::fs::write(&registry, "{\"rustbox/3\": {}}").unwrap();
+        assert_eq!(store.migrate::<Value>(&registry, &lock).unwrap(), 0);
+        assert_eq!(store.load::<Value>("rustbox/3").unwrap(), Some(migrated));
+
+        // An unreadable one stays until it is salvaged
+        std::fs::write(&registry, "{\"rustbox/9\": {}").unwrap();
+        assert!(store.migrate::<Value>(&registry, &lock).is_err());
+        assert!(registry.exists());
+        let recovery = store.salvage::<Value>(&registry, &lock).unwrap();
+        assert_eq!((recovery.kept, recovery.dropped), (0, 0));
+        assert!(recovery.backup.exists() && !registry.exists());
+    }
+
+    #[test]
+    fn test_regrade_drift_report() {
+        use crate::regrade::{DriftReport, RegradeOutcome};
+        use crate::types::ExecutionStatus;
+
+        for status in [
+            ExecutionStatus::Success,
+            ExecutionStatus::TimeLimit,
+            ExecutionStatus::MemoryLimit,
+            ExecutionStatus::CompileError,
+        ] {
+            assert_eq!(ExecutionStatus::from_label(&status.label()), status);
+        }
+        assert_eq!(
+            ExecutionStatus::from_label("TimeLimit"),
+            ExecutionStatus::TimeLimit
+        );
+
+        let outcome = |id: &str, changed: bool, error: Option<&str>| RegradeOutcome {
+            id: id.to_string(),
+            previous_status: Some("Success".to_string()),
+            status: Some("TLE".to_string()),
+            changed,
+            stdout_changed: false,
+            cpu_time: 0.0,
+            wall_time: 0.0,
+            error: error.map(String::from),
+        };
+        let report = DriftReport::from_outcomes(&[
+            outcome("same", false, None),
+            outcome("drifted", true, None),
+            outcome("broken", false, Some("Sandbox busy")),
+        ]);
+        assert_eq!(
+            (
+                report.total,
+                report.unchanged,
+                report.changed,
+                report.errors
+            ),
+            (3, 1, 1, 1)
+        );
+        assert_eq!(report.drift.len(), 2);
+    }
+
+    #[test]
+    fn test_box_set_is_all_or_nothing() {
+        use crate::lock_manager::RustboxLockManager;
+        use crate::types::LockError;
+        use std::time::Duration;
+
+        let manager = RustboxLockManager::new().unwrap();
+        let held = manager
+            .acquire_set(&[9405, 9403, 9403], Duration::ZERO)
+            .unwrap();
+        assert_eq!(held.box_ids(), vec![9403, 9405]);
+
+        // 9404 is free but 9405 is not, so neither may stay locked
+        let overlapping = manager.acquire_set(&[9405, 9404], Duration::from_millis(50));
+        assert!(matches!(
+            overlapping,
+            Err(LockError::Timeout { box_id: 9405, .. })
+        ));
+        let free = manager.acquire_set(&[9404], Duration::ZERO).unwrap();
+        assert_eq!(free.box_ids(), vec![9404]);
+
+        drop(held);
+        let guards = manager
+            .acquire_set(&[9403, 9405], Duration::ZERO)
+            .unwrap()
+            .into_guards();
+        assert_eq!(guards.len(), 2);
+    }
+
+    #[test]
+    fn test_stale_lock_takeover() {
+        use crate::lock_manager::RustboxLockManager;
+        use crate::types::LockError;
+        use std::os::unix::io::AsRawFd;
+        use std::time::Duration;
+
+        // The holder hangs after its first heartbeat
+        let hung = RustboxLockManager::new()
+            .unwrap()
+            .with_heartbeat(Duration::from_secs(3600), Duration::from_secs(3600));
+        let held = hung.acquire_lock(9406, Duration::ZERO).unwrap();
+
+        let contender = RustboxLockManager::new()
+            .unwrap()
+            .with_heartbeat(Duration::from_secs(1), Duration::from_millis(300));
+        let early = contender.acquire_lock(9406, Duration::from_millis(50));
+        assert!(matches!(
+            early,
+            Err(LockError::Timeout { box_id: 9406, .. })
+        ));
+        assert!(held.verify().is_ok());
+
+        std::thread::sleep(Duration::from_millis(400));
+        let taken = contender.acquire_lock(9406, Duration::ZERO).unwrap();
+        assert!(matches!(
+            held.verify(),
+            Err(LockError::HeartbeatFailed { box_id: 9406, .. })
+        ));
+
+        // The old holder letting go must not remove the new holder's lock
+        drop(held);
+        assert!(taken.verify().is_ok());
+        let again = contender.acquire_lock(9406, Duration::ZERO);
+        assert!(matches!(
+            again,
+            Err(LockError::Timeout { box_id: 9406, .. })
+        ));
+
+        // A dead holder's lock is taken over at once, even while a process it
+        // forked keeps the lock file locked
+        let (lock_path, _) = leave_dead_holder_lock(9407);
+        let inherited = std::fs::File::open(&lock_path).unwrap();
+        assert_eq!(
+            unsafe { libc::flock(inherited.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) },
+            0
+        );
+        let taken = contender.acquire_lock(9407, Duration::ZERO).unwrap();
+        assert!(taken.verify().is_ok());
+    }
+
+    #[test]
+    fn test_batch_verdict_is_first_failure() {
+        use crate::types::{BatchResult, ExecutionResult, ExecutionStatus};
+
+        let run = |script: &str| {
+            ExecutionResult::from(
+                std::process::Command::new("/bin/sh")
+                    .args(["-c", script])
+                    .output()
+                    .unwrap(),
+            )
+        };
+        let batch = BatchResult::from_results(vec![run("exit 0"), run("exit 1"), run("exit 0")]);
+        assert_eq!(batch.verdict, ExecutionStatus::RuntimeError);
+        assert_eq!(batch.passed(), 2);
+        let json = batch.to_cli_json();
+        assert_eq!(json["total"], 3);
+        assert_eq!(json["tests"].as_array().unwrap().len(), 3);
+
+        assert_eq!(
+            BatchResult::from_results(vec![run("exit 0")]).verdict,
+            ExecutionStatus::Success
+        );
+        let failed = BatchResult::compile_failed(run("exit 1"));
+        assert_eq!(failed.verdict, ExecutionStatus::CompileError);
+        assert!(failed.tests.is_empty());
+    }
+
+    #[test]
+    fn test_foreign_binaries_are_detected_for_emulation() {
+        use crate::emulation::{Arch, Emulation, EmulationMode};
+        use std::path::PathBuf;
+
+        let dir = tempfile::tempdir().unwrap();
+        let elf = |name: &str, class: u8, machine: u16| {
+            let mut header = vec![0u8; 64];
+            header[..4].copy_from_slice(b"\x7fELF");
+            header[4] = class;
+            header[5] = 1;
+            header[18..20].copy_from_slice(&machine.to_le_bytes());
+            let path = dir.path().join(name);
+            std::fs::write(&path, header).unwrap();
+            path
+        };
+        let riscv = elf("riscv", 2, 243);
+        assert_eq!(Arch::of_elf(&riscv), Some(Arch::Riscv64));
+        assert_eq!(Arch::of_elf(&elf("arm", 1, 40)), Some(Arch::Arm));
+        assert_eq!(
+            Arch::of_elf(std::path::Path::new("/proc/self/exe")),
+            Arch::host()
+        );
+        let script = dir.path().join("script.py");
+        std::fs::write(&script, "print(1)").unwrap();
+        assert_eq!(Arch::of_elf(&script), None);
+
+        // Native programs and scripts run as they are
+        assert!(Emulation::plan(std::path::Path::new("/proc/self/exe"))
+            .unwrap()
+            .is_none());
+        assert!(Emulation::plan(&script).unwrap().is_none());
+        // A foreign one needs binfmt or an installed qemu, and says so otherwise
+        match Emulation::plan(&riscv) {
+            Ok(Some(emulation)) => assert_eq!(emulation.arch, Arch::Riscv64),
+            Ok(None) => panic!("riscv64 binary planned as native"),
+            Err(e) => assert!(e.to_string().contains("qemu-riscv64")),
+        }
+
+        let emulation = Emulation {
+            arch: Arch::Riscv64,
+            mode: EmulationMode::Qemu,
+            emulator: Some(PathBuf::from("/usr/bin/qemu-riscv64-static")),
+        };
+        assert_eq!(
+            emulation.wrap(&["./solution".to_string(), "arg".to_string()]),
+            vec!["/usr/bin/qemu-riscv64-static", "./solution", "arg"]
+        );
+        assert_eq!(
+            serde_json::to_value(&emulation).unwrap()["mode"],
+            serde_json::json!("qemu")
+        );
+    }
+
+    #[test]
+    fn test_batch_warmup_runs_are_excluded() {
+        use crate::config::TimeConfig;
+        use crate::isolate::CleanupPolicy;
+        use crate::types::{ExecutionOverrides, ExecutionStatus};
+
+        assert_eq!(TimeConfig::warmup_runs_for("java"), 1);
+        assert_eq!(TimeConfig::warmup_runs_for("python"), 0);
+        if skip_unless_root(true) {
+            return;
+        }
+
+        let mut isolate = create_box(box_config(963));
+        let overrides = ExecutionOverrides {
+            warmup_runs: Some(2),
+            ..Default::default()
+        };
+        let inputs = vec!["1\n".to_string(), "2\n".to_string()];
+        let batch = isolate
+            .execute_batch("python", "print(int(input()) * 2)", &inputs, &overrides)
+            .unwrap();
+        isolate.finish(CleanupPolicy::Always).unwrap();
+
+        assert_eq!(batch.verdict, ExecutionStatus::Success);
+        assert_eq!(batch.warmup.len(), 2);
+        assert!(batch.warmup.iter().all(|run| run.stdout.trim() == "2"));
+        assert_eq!(batch.tests.len(), 2);
+        let json = batch.to_cli_json();
+        assert_eq!(json["total"], 2);
+        assert_eq!(json["warmup"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_live_stats_of_running_execution() {
+        use crate::isolate::{CleanupPolicy, Isolate};
+        use std::time::Duration;
+
+        if skip_unless_root(true) {
+            return;
+        }
+
+        let (mut config, _workdir) = box_config_in_tempdir(972);
+        config.wall_time_limit = Some(Duration::from_secs(10));
+        let mut isolate = create_box(config);
+        assert!(isolate.stats().is_err());
+
+        let running = std::thread::spawn(move || {
+            let command = python("import time\nx = b'x' * (20 << 20)\ntime.sleep(1)");
+            let result = isolate.execute(&command, None).unwrap();
+            (isolate, result)
+        });
+        // Read from a separate handle, as a dashboard in another process would
+        let observer = Isolate::load("rustbox/972").unwrap().unwrap();
+        let mut stats = None;
+        for _ in 0..100 {
+            std::thread::sleep(Duration::from_millis(20));
+            if let Ok(current) = observer.stats() {
+                if current.wall_time > 0.3 {
+                    stats = Some(current);
+                    break;
+                }
+            }
+        }
+        let (isolate, result) = running.join().unwrap();
+        assert!(result.success, "{:?}", result);
+        assert!(observer.stats().is_err());
+        isolate.finish(CleanupPolicy::Always).unwrap();
+
+        let stats = stats.unwrap();
+        assert_eq!(stats.processes, 1);
+        assert!(stats.memory_usage >= 20 << 20, "{:?}", stats);
+        assert!(stats.wall_time_remaining < 10.0 - 0.3, "{:?}", stats);
+    }
+
+    #[test]
+    #[cfg(feature = "cgroup-v1")]
+    fn test_pause_and_resume_running_execution() {
+        use crate::isolate::{CleanupPolicy, Isolate};
+        use std::time::Duration;
+
+        if skip_unless_root(true) {
+            return;
+        }
+
+        let (mut config, _workdir) = box_config_in_tempdir(975);
+        config.wall_time_limit = Some(Duration::from_secs(1));
+        let mut isolate = create_box(config);
+        assert!(!isolate.pause().unwrap());
+
+        let running = std::thread::spawn(move || {
+            let command = python("import time\ntime.sleep(0.5)\nprint('done')");
+            let result = isolate.execute(&command, None).unwrap();
+            (isolate, result)
+        });
+        let observer = Isolate::load("rustbox/975").unwrap().unwrap();
+        let mut paused = false;
+        for _ in 0..100 {
+            std::thread::sleep(Duration::from_millis(10));
+            if observer.pause().unwrap() {
+                paused = true;
+                break;
+            }
+        }
+        assert!(paused);
+        let cgroup = crate::cgroup::Cgroup::open("rustbox/975").unwrap();
+        assert!(cgroup.is_frozen());
+
+        // Paused past the wall time limit, which only counts running time
+        std::thread::sleep(Duration::from_millis(1200));
+        assert!(observer.stats().is_ok());
+        assert!(observer.resume().unwrap());
+        assert!(!observer.resume().unwrap());
+
+        let (isolate, result) = running.join().unwrap();
+        isolate.finish(CleanupPolicy::Always).unwrap();
+        assert!(result.success, "{:?}", result);
+        assert_eq!(result.stdout, "done\n");
+        assert!(result.wall_time >= 1.2, "{:?}", result);
+    }
+
+    #[test]
+    #[cfg(feature = "cgroup-v1")]
+    fn test_kill_running_after_supervisor_crash() {
+        use crate::isolate::{CleanupPolicy, Isolate};
+
+        if skip_unless_root(true) {
+            return;
+        }
+
+        let isolate = create_box(box_config(973));
+        assert_eq!(isolate.kill_running(libc::SIGKILL).unwrap(), 0);
+
+        // A program left in the box cgroup by a supervisor that has exited
+        let cgroup = crate::cgroup::Cgroup::new("rustbox/973", false).unwrap();
+        let mut program = std::process::Command::new("/usr/bin/python3")
+            .args(["-c", "import time; time.sleep(30)"])
+            .spawn()
+            .unwrap();
+        cgroup.add_process(program.id()).unwrap();
+        let mut supervisor = std::process::Command::new("/bin/true").spawn().unwrap();
+        supervisor.wait().unwrap();
+        let record = serde_json::json!({
+            "phase": "run",
+            "started": chrono::Utc::now(),
+            "wall_time_limit": { "secs": 30, "nanos": 0 },
+            "supervisor": supervisor.id(),
+        });
+        std::fs::write(
+            Isolate::instance_dir("rustbox/973").join("running.json"),
+            record.to_string(),
+        )
+        .unwrap();
+        let reaper = std::thread::spawn(move || program.wait().unwrap());
+
+        let observer = Isolate::load("rustbox/973").unwrap().unwrap();
+        assert_eq!(observer.kill_running(libc::SIGKILL).unwrap(), 1);
+        assert!(!reaper.join().unwrap().success());
+        assert!(crate::cgroup::Cgroup::open("rustbox/973").is_none());
+        assert!(observer.stats().is_err());
+        isolate.finish(CleanupPolicy::Always).unwrap();
+    }
+
+    #[test]
+    fn test_path_provider_layout_and_verify() {
+        use crate::paths::PathProvider;
+
+        let root = tempfile::tempdir().unwrap();
+        let paths = PathProvider::new(root.path().join("state"), root.path().join("scratch"));
+        assert_eq!(
+            paths.instance_dir("rustbox/3"),
+            root.path().join("state/rustbox/3")
+        );
+        assert_eq!(
+            paths.standard_workdir(3),
+            root.path().join("scratch/rustbox-3")
+        );
+        assert!(paths.lock_dir().starts_with(paths.state_dir()));
+        paths.verify().unwrap();
+        assert!(paths.state_dir().is_dir() && paths.scratch_dir().is_dir());
+
+        // A state dir that cannot be created names the variable to fix it with
+        std::fs::write(root.path().join("file"), b"").unwrap();
+        let blocked =
+            PathProvider::new(root.path().join("file/state"), root.path().join("scratch"));
+        let error = blocked.verify().unwrap_err().to_string();
+        assert!(error.contains("RUSTBOX_STATE_DIR"), "{}", error);
+
+        // Everything goes in the home directory unless the state is moved out
+        let home = root.path().join("home");
+        let paths = PathProvider::resolve(Some(home.clone()), None);
+        assert_eq!(paths, PathProvider::new(home.clone(), home.clone()));
+        assert!(paths.lock_dir().starts_with(&home));
+        let state_dir = root.path().join("state");
+        let paths = PathProvider::resolve(Some(home.clone()), Some(state_dir.clone()));
+        assert_eq!(paths, PathProvider::new(state_dir, home));
+    }
+
+    #[test]
+    fn test_rustbox_home_holds_every_path() {
+        use crate::paths::PathProvider;
+        use std::path::{Path, PathBuf};
+
+        let every_path = |paths: &PathProvider| -> Vec<(bool, PathBuf)> {
+            // Whether each path is state, which RUSTBOX_STATE_DIR moves
+            vec![
+                (true, paths.instances_dir()),
+                (true, paths.instances_file()),
+                (true, paths.instances_lock_file()),
+                (true, paths.admission_ledger()),
+                (true, paths.admission_ledger_lock()),
+                (true, paths.instance_dir("rustbox/3")),
+                (true, paths.history_db()),
+                (true, paths.security_audit_log()),
+                (true, paths.crash_reports_dir()),
+                (true, paths.lock_dir()),
+                (false, paths.data_cache_dir()),
+                (false, paths.result_cache_dir()),
+                (false, paths.visible_root_dir()),
+                (false, paths.image_root_dir()),
+                (false, paths.standard_workdir(3)),
+            ]
+        };
+        let home = PathBuf::from("/srv/rustbox");
+        let paths = PathProvider::resolve(Some(home.clone()), None);
+        for (_, path) in every_path(&paths) {
+            assert!(path.starts_with(&home), "{}", path.display());
+        }
+        let state_dir = PathBuf::from("/var/lib/rustbox");
+        let paths = PathProvider::resolve(Some(home.clone()), Some(state_dir.clone()));
+        for (state, path) in every_path(&paths) {
+            let expected = if state { &state_dir } else { &home };
+            assert!(path.starts_with(expected), "{}", path.display());
+        }
+        assert_eq!(
+            PathProvider::resolve(None, None).scratch_dir(),
+            std::env::temp_dir().join("rustbox")
+        );
+
+        // Nothing outside paths.rs builds a rustbox path in /tmp on its own
+        fn sources(dir: &Path, found: &mut Vec<PathBuf>) {
+            for entry in std::fs::read_dir(dir).unwrap().flatten() {
+                let path = entry.path();
+                if path.is_dir() && !path.ends_with("tests") {
+                    sources(&path, found);
+                } else if path.extension().is_some_and(|ext| ext == "rs") {
+                    found.push(path);
+                }
+            }
+        }
+        let mut found = Vec::new();
+        sources(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("src"),
+            &mut found,
+        );
+        assert!(found.len() > 10);
+        for path in found.iter().filter(|path| !path.ends_with("paths.rs")) {
+            let source = std::fs::read_to_string(path).unwrap();
+            for pattern in ["\"/tmp/rustbox", "temp_dir().join(\"rustbox\")"] {
+                assert!(
+                    !source.contains(pattern),
+                    "{} in {}",
+                    pattern,
+                    path.display()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_compile_limits_and_compile_output() {
+        use crate::config::CompilationConfig;
+        use crate::isolate::Compilation;
+        use crate::types::{ExecutionPhase, ExecutionResult, ExecutionStatus};
+
+        let compilation = CompilationConfig {
+            enabled: true,
+            compiler: "g++".to_string(),
+            compiler_args: Vec::new(),
+            max_compilation_time: 4,
+            max_compilation_memory_mb: -1,
+        };
+        let limits = compilation.limits();
+        let four_seconds = Some(std::time::Duration::from_secs(4));
+        assert_eq!(
+            (limits.max_cpu, limits.max_wall_time),
+            (four_seconds, four_seconds)
+        );
+        assert_eq!(limits.max_memory, None);
+
+        let compiler_run = ExecutionResult::from(
+            std::process::Command::new("/bin/sh")
+                .args(["-c", "echo 'solution.cpp:1: error' >&2; exit 1"])
+                .output()
+                .unwrap(),
+        );
+        let failed = Compilation {
+            language: "cpp".to_string(),
+            result: Some(compiler_run),
+            command: vec!["./solution".to_string()],
+        };
+        assert!(!failed.succeeded());
+        let report = failed.failure().unwrap();
+        assert_eq!(report.status, ExecutionStatus::CompileError);
+        assert_eq!(report.phase, ExecutionPhase::Compile);
+        assert_eq!(
+            report.compile_output.as_deref(),
+            Some("solution.cpp:1: error\n")
+        );
+        assert!(report.stderr.starts_with("Compilation Error:\n"));
+
+        let interpreted = Compilation {
+            language: "python".to_string(),
+            result: None,
+            command: Vec::new(),
+        };
+        assert!(interpreted.succeeded() && interpreted.failure().is_none());
+        assert_eq!(interpreted.output(), None);
+    }
+
+    #[test]
+    fn test_cms_report_profile() {
+        use crate::report::{render, ReportProfile};
+        use crate::types::{ExecutionResult, ExecutionStatus};
+
+        assert_eq!("cms".parse(), Ok(ReportProfile::Cms));
+        assert!("xml".parse::<ReportProfile>().is_err());
+
+        let output = std::process::Command::new("/bin/sh")
+            .args(["-c", "exit 3"])
+            .output()
+            .unwrap();
+        let mut result = ExecutionResult::from(output);
+        result.cpu_time = 0.12345;
+        result.wall_time = 1.5;
+        assert_eq!(result.to_cli_json()["status_code"], "RE");
+        assert_eq!(
+            render(&result, ReportProfile::Cms),
+            "time:0.123\ntime-wall:1.500\nmax-rss:0\ncg-mem:0\nexitcode:3\nstatus:RE\n\
+             message:Exited with error status 3"
+        );
+
+        result.status = ExecutionStatus::TimeLimit;
+        let meta = render(&result, ReportProfile::Cms);
+        assert!(meta.contains("killed:1\nstatus:TO\n"));
+        assert_eq!(result.isolate_status(), "TO");
+
+        result.status = ExecutionStatus::MemoryLimit;
+        result.signal = Some(9);
+        let meta = render(&result, ReportProfile::Cms);
+        assert!(meta.contains("cg-oom-killed:1\nexitsig:9\n"));
+        assert!(meta.contains("status:SG"));
+        for (status, code) in [
+            (ExecutionStatus::Success, "OK"),
+            (ExecutionStatus::InternalError, "XX"),
+            (ExecutionStatus::RuntimeError, "SG"),
+        ] {
+            let result = ExecutionResult {
+                status,
+                ..result.clone()
+            };
+            assert_eq!(result.isolate_status(), code);
+        }
+
+        // The --meta file holds the same entries, one newline-terminated line each
+        let file = result.to_isolate_meta();
+        assert!(file.ends_with('\n'));
+        assert_eq!(file.trim_end(), meta);
+    }
+
+    #[test]
+    fn test_status_code_follows_isolate() {
+        use crate::report::{render, ReportProfile};
+        use crate::types::{ExecutionResult, ExecutionStatus as Status};
+
+        // Status, then the code without and with a terminating signal
+        let table = [
+            (Status::Success, "OK", "OK"),
+            (Status::TimeLimit, "TO", "TO"),
+            (Status::IdleTimeout, "TO", "TO"),
+            (Status::MemoryLimit, "SG", "SG"),
+            (Status::RuntimeError, "RE", "SG"),
+            (Status::Signaled, "RE", "SG"),
+            (Status::SecurityViolation, "RE", "SG"),
+            (Status::ProcessLimit, "RE", "SG"),
+            (Status::ExecLimit, "RE", "SG"),
+            (Status::FileSizeLimit, "RE", "SG"),
+            (Status::StackLimit, "RE", "SG"),
+            (Status::CoreLimit, "RE", "SG"),
+            (Status::DiskQuotaExceeded, "RE", "SG"),
+            (Status::OutputLimit, "RE", "SG"),
+            (Status::CompileError, "RE", "SG"),
+            (Status::InternalError, "XX", "XX"),
+            (Status::Cancelled, "XX", "XX"),
+            (Status::Unknown, "XX", "XX"),
+        ];
+        for (status, exited, signaled) in table {
+            for (signal, code) in [(None, exited), (Some(libc::SIGSEGV), signaled)] {
+                let result = ExecutionResult {
+                    status: status.clone(),
+                    signal,
+                    ..Default::default()
+                };
+                assert_eq!(result.isolate_status(), code, "{:?} {:?}", status, signal);
+                assert_eq!(result.to_cli_json()["status_code"], code);
+                // Isolate's meta file leaves the status out of successful runs
+                let meta = render(&result, ReportProfile::Cms);
+                let line = format!("status:{}", code);
+                assert_eq!(meta.lines().any(|l| l == line), code != "OK", "{}", meta);
+            }
+        }
+    }
+
+    #[test]
+    fn test_judge0_schema_mapping() {
+        use crate::judge0::{self, SubmissionRequest, SubmissionResponse};
+        use crate::report::{render, ReportProfile};
+        use crate::types::{ExecutionResult, ExecutionStatus};
+
+        let request: SubmissionRequest = serde_json::from_value(serde_json::json!({
+            "source_code": "cHJpbnQoNDIp",
+            "language_id": 71,
+            "stdin": "MQ==",
+            "cpu_time_limit": 1.5,
+            "memory_limit": 128000,
+        }))
+        .unwrap();
+        let request = request.decode().unwrap();
+        assert_eq!(request.source_code, "print(42)");
+        assert_eq!(request.stdin.as_deref(), Some("1"));
+        assert_eq!(request.language().unwrap(), "python");
+        let overrides = request.overrides();
+        assert_eq!(
+            overrides.max_cpu,
+            Some(std::time::Duration::from_millis(1500))
+        );
+        assert_eq!(overrides.max_memory, Some(125));
+        assert_eq!(overrides.max_wall_time, None);
+        assert!(SubmissionRequest::default().language().is_err());
+        assert_eq!(judge0::language_id("Java"), Some(62));
+
+        let mut result = ExecutionResult {
+            status: ExecutionStatus::Success,
+            success: true,
+            exit_code: Some(0),
+            stdout: "42  \n\n".to_string(),
+            cpu_time: 0.0123,
+            memory_peak: 4096 * 1024,
+            ..Default::default()
+        };
+        let response =
+            SubmissionResponse::new(&result, Some("42\n"), false).with_language("python");
+        assert_eq!(response.status, judge0::ACCEPTED);
+        assert_eq!((response.time.as_str(), response.memory), ("0.012", 4096));
+        assert_eq!(response.language_id, Some(71));
+        assert_eq!(judge0::status(&result, Some("41")), judge0::WRONG_ANSWER);
+        let encoded = SubmissionResponse::new(&result, None, true);
+        assert_eq!(encoded.stdout.as_deref(), Some("NDIgIAoK"));
+
+        result.status = ExecutionStatus::Signaled;
+        result.signal = Some(libc::SIGFPE);
+        assert_eq!(judge0::status(&result, None), judge0::RUNTIME_ERROR_SIGFPE);
+        result.status = ExecutionStatus::RuntimeError;
+        result.signal = None;
+        result.exit_code = Some(3);
+        let response = SubmissionResponse::new(&result, None, false);
+        assert_eq!(response.status, judge0::RUNTIME_ERROR_NZEC);
+        assert_eq!(
+            response.message.as_deref(),
+            Some("Exited with error status 3")
+        );
+
+        // Compiler output goes to compile_output only
+        result.status = ExecutionStatus::CompileError;
+        result.compile_output = Some("error: expected ';'".to_string());
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&result, ReportProfile::Judge0)).unwrap();
+        assert_eq!(json["status"]["id"], 6);
+        assert_eq!(json["status"]["description"], "Compilation Error");
+        assert_eq!(json["compile_output"], "error: expected ';'");
+        assert!(json["stdout"].is_null() && json["token"].is_string());
+    }
+
+    #[test]
+    fn test_capability_report_json() {
+        let report = crate::capabilities::probe();
+        assert!(report
+            .capabilities
+            .iter()
+            .any(|c| c.name == "syscall_filter"));
+
+        let json = serde_json::to_value(&report).unwrap();
+        let state = json["capabilities"][0]["state"].as_str().unwrap();
+        assert!(["active", "degraded", "absent"].contains(&state));
+    }
+
+    #[test]
+    fn test_explain_resolves_without_running() {
+        use crate::types::{DirectoryBinding, DirectoryPermissions, IsolateConfig, NetworkMode};
+
+        let (config, workdir) = box_config_in_tempdir(978);
+        let config = IsolateConfig {
+            memory_limit: Some(64 * 1024 * 1024),
+            process_limit: Some(4),
+            exec_limit: Some(1),
+            fd_limit: Some(32),
+            minimal_visibility: true,
+            network_mode: NetworkMode::LoopbackOnly,
+            environment: vec![("LANG".to_string(), "C.UTF-8".to_string())],
+            directory_bindings: vec![DirectoryBinding {
+                source: workdir.path().to_path_buf(),
+                target: "/box".into(),
+                permissions: DirectoryPermissions::ReadOnly,
+                maybe: false,
+                is_tmp: false,
+                uid_map: None,
+                gid_map: None,
+            }],
+            strict_mode: false,
+            ..config
+        };
+        let effective = crate::explain::resolve(&config).unwrap();
+
+        assert!(effective.namespaces.contains(&"mount"));
+        let root = unsafe { libc::geteuid() } == 0;
+        assert_eq!(effective.namespaces.contains(&"net"), root);
+        assert_eq!(effective.network == NetworkMode::LoopbackOnly, root);
+        assert!(!effective.seccomp.enforcing);
+        assert_eq!(effective.seccomp.notified, vec!["execve", "execveat"]);
+        assert!(effective
+            .rlimits
+            .iter()
+            .any(|r| r.resource == "RLIMIT_NOFILE" && r.limit == Some(32) && r.applied));
+        if effective.cgroup.available {
+            assert!(effective.cgroup.limits.contains(&("pids", "4".to_string())));
+        }
+        assert!(effective
+            .mounts
+            .iter()
+            .any(|m| m.target.as_path() == std::path::Path::new("/usr") && m.read_only));
+        assert!(effective
+            .mounts
+            .iter()
+            .any(|m| m.target.as_path() == std::path::Path::new("/box") && m.read_only));
+        assert_eq!(effective.workdir, std::path::PathBuf::from("/box"));
+        assert!(effective
+            .environment
+            .contains(&("LANG".to_string(), "C.UTF-8".to_string())));
+
+        // Nothing was created for the box
+        assert!(crate::isolate::Isolate::load("rustbox/978")
+            .unwrap()
+            .is_none());
+        let json = serde_json::to_value(&effective).unwrap();
+        assert_eq!(json["seccomp"]["notified"][1], "execveat");
+    }
+
+    #[test]
+    fn test_environment_policy_clears_host_variables() {
+        use crate::executor::ProcessExecutor;
+        use crate::security::env_validation::MAX_ENV_VARS;
+        use crate::types::{EnvRule, ExecutionOverrides, IsolateConfig};
+
+        assert_eq!(
+            "LANG=C=1".parse(),
+            Ok(EnvRule::Set("LANG".to_string(), "C=1".to_string()))
+        );
+        assert_eq!("HOME".parse(), Ok(EnvRule::Preserve("HOME".to_string())));
+        assert!("=1".parse::<EnvRule>().is_err());
+        assert!("".parse::<EnvRule>().is_err());
+
+        // Any host variable rustbox does not set itself
+        let Some((name, value)) = std::env::vars().find(|(name, _)| name != "PATH") else {
+            return;
+        };
+        let (mut config, _workdir) = box_config_in_tempdir(976);
+        config.environment = vec![("RUSTBOX_SET".to_string(), "box".to_string())];
+        let environment =
+            |config: &IsolateConfig| crate::explain::resolve(config).unwrap().environment;
+        let cleared = environment(&config);
+        assert!(!cleared.iter().any(|(key, _)| *key == name));
+        assert!(cleared.contains(&("RUSTBOX_SET".to_string(), "box".to_string())));
+
+        let preserved = environment(&IsolateConfig {
+            preserve_env: vec![name.clone()],
+            ..config.clone()
+        });
+        assert!(preserved.contains(&(name.clone(), value.clone())));
+        // The whole host environment is still held to the size caps
+        let full = crate::explain::resolve(&IsolateConfig {
+            full_env: true,
+            ..config.clone()
+        });
+        match full {
+            Ok(full) => {
+                assert!(full.environment.contains(&(name.clone(), value.clone())));
+                assert!(full
+                    .environment
+                    .contains(&("RUSTBOX_SET".to_string(), "box".to_string())));
+            }
+            Err(_) => assert!(std::env::vars().count() + 2 > MAX_ENV_VARS),
+        }
+
+        // Per-run rules apply on top of the box's
+        let isolate = create_box(config.clone());
+        let overrides = ExecutionOverrides {
+            env: vec![
+                EnvRule::Set("RUSTBOX_SET".to_string(), "run".to_string()),
+                EnvRule::Preserve(name.clone()),
+            ],
+            ..Default::default()
+        };
+        let effective = isolate.effective_config(&overrides).unwrap();
+        isolate.cleanup().unwrap();
+        assert!(effective.environment.contains(&(name.clone(), value)));
+        assert!(effective
+            .environment
+            .contains(&("RUSTBOX_SET".to_string(), "run".to_string())));
+
+        // The program sees only what the policy lets through
+        let mut executor = ProcessExecutor::new(config).unwrap();
+        let script = format!("import os\nprint(os.environ.get({:?}, 'unset'))", name);
+        let result = executor.execute(&python(&script), None).unwrap();
+        executor.cleanup().unwrap();
+        assert_eq!(result.stdout.trim(), "unset");
+    }
+
+    #[test]
+    fn test_tty_runs_program_on_a_terminal() {
+        use crate::types::{IsolateConfig, TtySize};
+
+        let size = TtySize {
+            cols: 100,
+            rows: 30,
+        };
+        assert_eq!("100x30".parse(), Ok(size));
+        assert!("100".parse::<TtySize>().is_err());
+        assert!("0x30".parse::<TtySize>().is_err());
+
+        let (config, _workdir) = config_in_tempdir();
+        let config = IsolateConfig {
+            admission_control: false,
+            enable_tty: true,
+            tty_size: size,
+            ..config
+        };
+        let code = "import os, shutil, sys\n\
+            print(all(os.isatty(fd) for fd in range(3)), shutil.get_terminal_size())\n\
+            print('hello', input('name? '))\n\
+            sys.stderr.write('warn')\n\
+            print(sys.stdin.read() == '')";
+        let mut executor = crate::executor::ProcessExecutor::new(config).unwrap();
+        let result = executor.execute(&python(code), Some("alice\n")).unwrap();
+
+        assert!(result.success, "{:?}", result);
+        // Raw terminal output: echoed input, stderr mixed in and CRLF line ends;
+        // the echo may come before or after the program's first line
+        let output = &result.stdout;
+        assert!(output.contains("True os.terminal_size(columns=100, lines=30)\r\n"));
+        assert!(output.contains("alice\r\n"));
+        assert!(output.contains("name? hello alice\r\n"));
+        assert!(output.contains("warn"));
+        // Input ended once it was used up
+        assert_eq!(output.matches("True").count(), 2, "{:?}", output);
+        assert_eq!(result.stderr, "");
+    }
+
+    #[test]
+    fn test_sandbox_pool_resets_returned_boxes() {
+        use crate::pool::SandboxPool;
+        use crate::types::IsolateConfig;
+        use std::time::Duration;
+
+        // Another test may have initialized it already
+        let _ = crate::lock_manager::init_lock_manager();
+        let template = IsolateConfig {
+            admission_control: false,
+            ..Default::default()
+        };
+        let pool = SandboxPool::new(template, 960, 2).unwrap();
+
+        let first = pool.acquire(Duration::from_secs(1)).unwrap();
+        let mut second = pool.acquire(Duration::from_secs(1)).unwrap();
+        assert!(pool.try_acquire().is_none());
+        assert_ne!(first.config().instance_id, second.config().instance_id);
+
+        let workdir = second.config().workdir.clone();
+        std::fs::write(workdir.join("left-behind.txt"), b"secret").unwrap();
+        second.restrict_visibility(Vec::new()).unwrap();
+        drop(second);
+
+        // The returned box comes back empty and with the pool's configuration
+        let reused = pool.acquire(Duration::from_secs(1)).unwrap();
+        assert_eq!(reused.config().workdir, workdir);
+        assert!(!reused.config().minimal_visibility);
+        assert_eq!(std::fs::read_dir(&workdir).unwrap().count(), 0);
+
+        // A waiter gets the box released by another thread
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                std::thread::sleep(Duration::from_millis(50));
+                drop(reused);
+            });
+            assert!(pool.acquire(Duration::from_secs(5)).is_ok());
+        });
+        drop(first);
+
+        pool.shutdown().unwrap();
+        assert_eq!(pool.idle_count(), 0);
+        assert!(!workdir.exists());
+    }
+
+    #[test]
+    fn test_run_many_allocates_boxes_and_aggregates() {
+        use crate::lock_manager::acquire_box_lock_with_timeout;
+        use crate::orchestrator::{run_many, FIRST_BOX_ID, MAX_BOXES};
+        use crate::queue::Submission;
+        use crate::types::ExecutionStatus;
+        use std::time::Duration;
+
+        // Another test may have initialized it already
+        let _ = crate::lock_manager::init_lock_manager();
+        // A box busy elsewhere is skipped rather than waited for
+        let busy = acquire_box_lock_with_timeout(FIRST_BOX_ID, Duration::ZERO).unwrap();
+        let submission = |code: &str, stdin: &str| Submission {
+            language: "python".to_string(),
+            code: code.to_string(),
+            stdin: Some(stdin.to_string()),
+            ..Default::default()
+        };
+        let submissions = vec![
+            submission("print(int(input()) * 2)", "1"),
+            submission("print(int(input()) * 2)", "2"),
+            submission("raise SystemExit(3)", ""),
+            submission("print(int(input()) * 2)", "4"),
+        ];
+
+        // Runs start even when the supervisor holds more descriptors than a
+        // program may open
+        let open_files: Vec<_> = (0..40)
+            .map(|_| std::fs::File::open("/dev/null").unwrap())
+            .collect();
+        let outcome = run_many(&submissions, 2);
+        drop(open_files);
+        let stdout: Vec<_> = outcome
+            .results
+            .iter()
+            .map(|result| result.as_ref().unwrap().stdout.clone())
+            .collect();
+        assert_eq!(stdout, ["2\n", "4\n", "", "8\n"]);
+        assert_eq!(outcome.errors(), 0);
+        assert!(!outcome.all_succeeded());
+        assert_eq!(outcome.by_status().get("Success"), Some(&3));
+        assert_eq!(
+            outcome.results[2].as_ref().unwrap().status,
+            ExecutionStatus::RuntimeError
+        );
+        drop(busy);
+
+        // Every box was cleaned up
+        let leftover = crate::isolate::Isolate::list_all()
+            .unwrap()
+            .iter()
+            .filter_map(|id| id.strip_prefix("rustbox/")?.parse::<u32>().ok())
+            .any(|box_id| (FIRST_BOX_ID..FIRST_BOX_ID + MAX_BOXES).contains(&box_id));
+        assert!(!leftover);
+    }
+
+    #[test]
+    fn test_new_in_range_allocates_free_boxes() {
+        use crate::config::BoxIdRange;
+        use crate::isolate::{CleanupPolicy, Isolate};
+        use crate::lock_manager::acquire_box_lock_with_timeout;
+        use crate::types::{IsolateConfig, IsolateError};
+        use std::time::Duration;
+
+        // Another test may have initialized it already
+        let _ = crate::lock_manager::init_lock_manager();
+        let range = BoxIdRange {
+            first: 30_000,
+            count: 4,
+        };
+        let config = || IsolateConfig {
+            admission_control: false,
+            ..Default::default()
+        };
+        // Skipped: one box is locked elsewhere, one is set up but idle
+        let busy = acquire_box_lock_with_timeout(30_000, Duration::ZERO).unwrap();
+        let registered = create_box(box_config(30001));
+        drop(registered);
+
+        let allocations: Vec<_> = (0..2)
+            .map(|_| std::thread::spawn(move || Isolate::new_in_range(config(), range).unwrap()))
+            .collect();
+        let isolates: Vec<_> = allocations
+            .into_iter()
+            .map(|allocation| allocation.join().unwrap())
+            .collect();
+        let mut box_ids: Vec<_> = isolates
+            .iter()
+            .map(|isolate| isolate.box_id().unwrap())
+            .collect();
+        box_ids.sort_unstable();
+        assert_eq!(box_ids, [30_002, 30_003]);
+        assert!(matches!(
+            Isolate::new_in_range(config(), range),
+            Err(IsolateError::ResourceUnavailable(_))
+        ));
+
+        drop(busy);
+        for isolate in isolates {
+            isolate.finish(CleanupPolicy::Always).unwrap();
+        }
+        if let Some(registered) = Isolate::load("rustbox/30001").unwrap() {
+            registered.finish(CleanupPolicy::Always).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_try_acquire_box_lock_never_waits() {
+        use crate::lock_manager::{
+            acquire_box_lock_with_timeout, lock_directory, try_acquire_box_lock,
+        };
+        use std::time::{Duration, Instant};
+
+        // Another test may have initialized it already
+        let _ = crate::lock_manager::init_lock_manager();
+        let guard = try_acquire_box_lock(30_013).unwrap().unwrap();
+        let lock_dir = lock_directory().unwrap();
+        assert!(lock_dir.join("box-30013.lock").exists());
+
+        // Held: refused at once, where the blocking call would wait
+        let started = Instant::now();
+        assert!(try_acquire_box_lock(30_013).unwrap().is_none());
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert!(acquire_box_lock_with_timeout(30_013, Duration::from_millis(100)).is_err());
+
+        drop(guard);
+        assert!(try_acquire_box_lock(30_013).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_finish_keeps_or_removes_the_box() {
+        use crate::isolate::{CleanupPolicy, Isolate};
+        use crate::paths::paths;
+
+        let isolate = create_box(box_config(30012));
+        let standard_dir = paths().standard_workdir(30_012);
+        std::fs::create_dir_all(&standard_dir).unwrap();
+
+        // Kept: still registered, its files still there, for the next run
+        assert!(!isolate.finish(CleanupPolicy::Keep).unwrap());
+        let isolate = Isolate::load("rustbox/30012").unwrap().unwrap();
+        assert!(standard_dir.exists());
+
+        // Cleaned up: gone from the registry along with the run's work directory
+        assert!(isolate.finish(CleanupPolicy::Always).unwrap());
+        assert!(Isolate::load("rustbox/30012").unwrap().is_none());
+        assert!(!standard_dir.exists());
+    }
+
+    #[test]
+    fn test_execution_lock_waits_for_a_busy_box() {
+        use crate::isolate::{CleanupPolicy, Isolate};
+        use crate::lock_manager::acquire_box_lock_with_timeout;
+        use crate::types::{IsolateError, LockError};
+        use std::time::{Duration, Instant};
+
+        // Creating the box locks it; a loaded handle starts out unlocked
+        drop(create_box(box_config(30010)));
+        let mut isolate = Isolate::load("rustbox/30010").unwrap().unwrap();
+        let busy = acquire_box_lock_with_timeout(30_010, Duration::ZERO).unwrap();
+
+        // Held for the whole wait: times out once the wait is used up, not before
+        let started = Instant::now();
+        let result = isolate.acquire_execution_lock_with_timeout(Duration::from_millis(300));
+        assert!(
+            matches!(
+                result,
+                Err(IsolateError::AdvancedLock(LockError::Timeout {
+                    box_id: 30_010,
+                    ..
+                }))
+            ),
+            "{:?}",
+            result
+        );
+        assert!(started.elapsed() >= Duration::from_millis(250));
+        assert!(!isolate.try_acquire_execution_lock(Duration::ZERO).unwrap());
+
+        // Released during the wait: the waiter gets the box without waiting it out
+        let releaser = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            drop(busy);
+        });
+        let started = Instant::now();
+        isolate
+            .acquire_execution_lock_with_timeout(Duration::from_secs(10))
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(5));
+        releaser.join().unwrap();
+
+        isolate.finish(CleanupPolicy::Always).unwrap();
+    }
+
+    #[test]
+    fn test_gc_finds_boxes_of_dead_owners() {
+        use crate::gc::{collect, GcOptions, GcReason};
+        use crate::isolate::{CleanupPolicy, Isolate};
+
+        // A worker that crashed while holding box 982 left its lock file behind
+        drop(create_box(box_config(982)));
+        let (lock_path, worker) = leave_dead_holder_lock(982);
+        // A box in use is never collected, however old
+        let held = create_box(box_config(30_020));
+
+        let report = collect(&GcOptions {
+            older_than: Some(chrono::Duration::zero()),
+            dry_run: true,
+        });
+        let collected = |box_id| report.boxes.iter().find(|entry| entry.box_id == box_id);
+        let dead = collected(982).expect("box of a dead worker is collected");
+        assert_eq!(dead.reason, GcReason::DeadOwner);
+        assert!(dead.detail.contains(&worker.to_string()));
+        assert!(!dead.removed);
+        assert!(collected(30_020).is_none());
+        // A dry run removes nothing
+        assert!(lock_path.exists());
+        assert!(Isolate::load("rustbox/982").unwrap().is_some());
+
+        let _ = std::fs::remove_file(&lock_path);
+        Isolate::load("rustbox/982")
+            .unwrap()
+            .unwrap()
+            .finish(CleanupPolicy::Always)
+            .unwrap();
+        held.finish(CleanupPolicy::Always).unwrap();
+    }
+
+    #[test]
+    fn test_gc_leaves_a_box_taken_over_since_the_scan_alone() {
+        use crate::cgroup::Cgroup;
+        use crate::gc::{remove, GcOptions};
+        use crate::isolate::{CleanupPolicy, Isolate};
+
+        if skip_unless_root(true) {
+            return;
+        }
+        drop(create_box(box_config(984)));
+        let (_, dead) = leave_dead_holder_lock(984);
+        // What gc saw when it scanned
+        let scanned = Isolate::load("rustbox/984").unwrap().unwrap();
+
+        // A worker takes the box over from the dead holder and starts a run
+        let mut worker = Isolate::load("rustbox/984").unwrap().unwrap();
+        assert!(worker
+            .try_acquire_execution_lock(std::time::Duration::ZERO)
+            .unwrap());
+        let cgroup = Cgroup::new("rustbox/984", false).unwrap();
+        let mut run = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        cgroup.add_process(run.id()).unwrap();
+
+        let removed = remove(
+            scanned,
+            Some(Some(dead)),
+            &GcOptions {
+                older_than: None,
+                dry_run: false,
+            },
+        )
+        .unwrap();
+        assert!(!removed);
+        assert!(run.try_wait().unwrap().is_none(), "gc killed the new run");
+        assert!(Isolate::load("rustbox/984").unwrap().is_some());
+
+        run.kill().unwrap();
+        run.wait().unwrap();
+        drop(cgroup);
+        worker.finish(CleanupPolicy::Always).unwrap();
+    }
+
+    #[test]
+    fn test_list_and_inspect_boxes() {
+        use crate::isolate::{CleanupPolicy, Isolate};
+        use crate::types::{BoxState, IsolateConfig};
+
+        let isolate = create_box(IsolateConfig {
+            memory_limit: Some(64 * 1024 * 1024),
+            ..box_config(30030)
+        });
+        assert_eq!(isolate.state(), BoxState::Locked);
+        drop(isolate);
+
+        let summaries = Isolate::list_summaries().unwrap();
+        let summary = summaries
+            .iter()
+            .find(|summary| summary.instance_id == "rustbox/30030")
+            .unwrap();
+        assert_eq!(summary.state, BoxState::Idle);
+        assert_eq!(summary.memory_limit, Some(64 * 1024 * 1024));
+        assert!(summary.last_used >= summary.created_at);
+
+        let isolate = Isolate::load("rustbox/30030").unwrap().unwrap();
+        let record = isolate.inspect().unwrap();
+        assert_eq!(record["config"]["instance_id"], "rustbox/30030");
+        assert_eq!(record["state"], "idle");
+        isolate.finish(CleanupPolicy::Always).unwrap();
+    }
+
+    #[test]
+    fn test_artifact_cache_reuses_builds() {
+        use crate::artifact_cache::{metrics, ArtifactCache};
+        use crate::isolate::CleanupPolicy;
+        use crate::types::ExecutionOverrides;
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ArtifactCache::new(dir.path().to_path_buf(), 1024 * 1024 * 1024);
+        let mut isolate = create_box(box_config(981));
+        isolate.set_artifact_cache(Some(cache.clone()));
+        let limits = ExecutionOverrides::default();
+        let code = "public class Main {\n\
+            public static void main(String[] args) { System.out.println(\"built\"); }\n}";
+
+        let before = metrics();
+        let program = isolate.compile("java", code, &limits).unwrap();
+        assert!(program.succeeded(), "{:?}", program.result);
+        assert_eq!(cache.usage().0, 1);
+        assert!(metrics().stores > before.stores);
+
+        // The class file comes back from the cache without running the compiler
+        let class_file = isolate.config().workdir.join("Main.class");
+        std::fs::remove_file(&class_file).unwrap();
+        let hits = metrics().hits;
+        let program = isolate.compile("java", code, &limits).unwrap();
+        assert!(metrics().hits > hits);
+        assert!(class_file.exists());
+        let result = isolate.run(&program, None, &limits).unwrap();
+        assert_eq!(result.stdout, "built\n", "{:?}", result);
+
+        // Another source is built and cached on its own
+        let other = "public class Main { public static void main(String[] args) {} }";
+        assert!(isolate.compile("java", other, &limits).unwrap().succeeded());
+        assert_eq!(cache.usage().0, 2);
+        isolate.finish(CleanupPolicy::Always).unwrap();
+
+        // A cache too small for any build keeps none
+        let tiny = ArtifactCache::new(dir.path().join("tiny"), 0);
+        let evictions = metrics().evictions;
+        let workdir = tempfile::tempdir().unwrap();
+        let snapshot = crate::artifact_cache::WorkdirSnapshot::take(workdir.path());
+        std::fs::write(workdir.path().join("Main.class"), b"class").unwrap();
+        let key = crate::artifact_cache::ArtifactKey::new(&["javac".to_string()], code);
+        let built = crate::types::ExecutionResult {
+            success: true,
+            ..Default::default()
+        };
+        let source = workdir.path().join("Main.java");
+        tiny.store(&key, workdir.path(), &snapshot, &source, &built)
+            .unwrap();
+        assert_eq!(tiny.usage(), (0, 0));
+        assert!(metrics().evictions > evictions);
+        assert!(tiny.restore(&key, workdir.path(), None, None).is_none());
+    }
+
+    #[test]
+    fn test_artifact_cache_restore_ignores_planted_symlinks() {
+        use crate::artifact_cache::{ArtifactCache, ArtifactKey, WorkdirSnapshot};
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ArtifactCache::new(dir.path().to_path_buf(), 1024 * 1024);
+        let build = tempfile::tempdir().unwrap();
+        let snapshot = WorkdirSnapshot::take(build.path());
+        std::fs::write(build.path().join("Main.class"), b"main").unwrap();
+        std::fs::create_dir(build.path().join("pkg")).unwrap();
+        std::fs::write(build.path().join("pkg/Helper.class"), b"helper").unwrap();
+        let key = ArtifactKey::new(&["javac".to_string()], "class Main {}");
+        let built = crate::types::ExecutionResult {
+            success: true,
+            ..Default::default()
+        };
+        let source = build.path().join("Main.java");
+        cache
+            .store(&key, build.path(), &snapshot, &source, &built)
+            .unwrap();
+
+        // Left in the next box's workdir by an earlier run
+        let host = tempfile::tempdir().unwrap();
+        let secret = host.path().join("secret");
+        std::fs::write(&secret, "host data").unwrap();
+        let workdir = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(&secret, workdir.path().join("Main.class")).unwrap();
+        std::fs::create_dir(workdir.path().join("pkg")).unwrap();
+        std::os::unix::fs::symlink(&secret, workdir.path().join("pkg/Helper.class")).unwrap();
+
+        assert!(cache.restore(&key, workdir.path(), None, None).is_some());
+        assert_eq!(std::fs::read_to_string(&secret).unwrap(), "host data");
+        let restored = workdir.path().join("Main.class");
+        assert!(std::fs::symlink_metadata(&restored).unwrap().is_file());
+        assert_eq!(std::fs::read(&restored).unwrap(), b"main");
+        assert_eq!(
+            std::fs::read(workdir.path().join("pkg/Helper.class")).unwrap(),
+            b"helper"
+        );
+
+        // A directory replaced by a symlink is not written through
+        let workdir = tempfile::tempdir().unwrap();
+        std::os::unix::fs::symlink(host.path(), workdir.path().join("pkg")).unwrap();
+        assert!(cache.restore(&key, workdir.path(), None, None).is_none());
+        assert!(!host.path().join("Helper.class").exists());
+    }
+
+    #[test]
+    fn test_result_cache() {
+        use crate::result_cache::{
+            is_cacheable, CacheBackend, CacheKey, DiskCache, MemoryCache, ResultCache,
+        };
+        use crate::types::{ExecutionOverrides, ExecutionResult, ExecutionStatus, IsolateConfig};
+        use std::time::Duration;
+
+        let limits = ExecutionOverrides::default();
+        let key = |stdin: &str, config: &IsolateConfig, limits: &ExecutionOverrides| {
+            let code = "print(input())";
+            CacheKey::new("python", code, Some(stdin), config, limits, limits).unwrap()
+        };
+        // The same submission in another box has the same key
+        let config = IsolateConfig::default();
+        let other_box = IsolateConfig {
+            instance_id: "rustbox/7".to_string(),
+            workdir: "/tmp/elsewhere".into(),
+            uid: Some(60007),
+            gid: Some(60007),
+            cpu_affinity: Some(vec![1]),
+            ..Default::default()
+        };
+        assert_eq!(key("1", &config, &limits), key("1", &other_box, &limits));
+        assert_ne!(key("1", &config, &limits), key("2", &config, &limits));
+        let tighter = ExecutionOverrides {
+            max_cpu: Some(Duration::from_millis(500)),
+            ..Default::default()
+        };
+        assert_ne!(key("1", &config, &limits), key("1", &config, &tighter));
+
+        let result = |stdout: &str, status: ExecutionStatus| ExecutionResult {
+            stdout: stdout.to_string(),
+            status,
+            ..Default::default()
+        };
+        let (a, b, c) = (
+            key("a", &config, &limits),
+            key("b", &config, &limits),
+            key("c", &config, &limits),
+        );
+        let memory = MemoryCache::new(2);
+        for (key, stdout) in [(&a, "a"), (&b, "b")] {
+            let stored = result(stdout, ExecutionStatus::Success);
+            memory.put(key, &stored).unwrap();
+        }
+        // Using `a` makes `b` the least recently used, so `c` evicts it
+        assert!(memory.get(&a).is_some());
+        let stored = result("c", ExecutionStatus::Success);
+        memory.put(&c, &stored).unwrap();
+        assert_eq!(memory.len(), 2);
+        assert!(memory.get(&b).is_none());
+        assert_eq!(memory.get(&c).unwrap().stdout, "c");
+
+        let dir = tempfile::tempdir().unwrap();
+        let cache = ResultCache::new(vec![Box::new(DiskCache::new(dir.path().to_path_buf()))]);
+        assert!(cache.get(&a).is_none());
+        cache.put(&a, &result("a", ExecutionStatus::RuntimeError));
+        cache.put(&b, &result("b", ExecutionStatus::TimeLimit));
+        let cached = cache.get(&a).unwrap();
+        assert_eq!(cached.stdout, "a");
+        assert_eq!(cached.status, ExecutionStatus::RuntimeError);
+        assert!(dir.path().join(format!("{}.json", a.as_str())).exists());
+        // Load-dependent verdicts are never stored
+        assert!(cache.get(&b).is_none());
+
+        let killed = ExecutionResult {
+            status: ExecutionStatus::Signaled,
+            signal: Some(libc::SIGKILL),
+            ..Default::default()
+        };
+        assert!(!is_cacheable(&killed));
+        assert!(is_cacheable(&ExecutionResult {
+            signal: Some(libc::SIGSEGV),
+            ..killed
+        }));
+        assert!(is_cacheable(&result("", ExecutionStatus::CompileError)));
+        assert!(!is_cacheable(&result("", ExecutionStatus::Cancelled)));
+
+        // A submission run in one box is a hit when it comes again in another
+        if skip_unless_root(false) {
+            return;
+        }
+        let cache = ResultCache::new(vec![Box::new(MemoryCache::new(4))]);
+        let command = python("print(input())");
+        for box_id in [30120, 30121] {
+            let mut isolate = create_box(box_config(box_id));
+
+            let key = key("1", isolate.config(), &limits);
+            if box_id == 30120 {
+                assert!(cache.get(&key).is_none());
+                let run = isolate.execute(&command, Some("1")).unwrap();
+                cache.put(&key, &run);
+            } else {
+                assert_eq!(cache.get(&key).unwrap().stdout.trim(), "1");
+            }
+            isolate.cleanup().unwrap();
+        }
+    }
+
     #[test]
     fn test_core_test_run() {
         let mut config = TestConfig::default();
@@ -342,4 +2872,198 @@ mod tests {
             assert!(result.passed);
         }
     }
+
+    #[test]
+    fn test_config_validation_rejects_bad_limits() {
+        use crate::isolate::Isolate;
+        use crate::types::IsolateConfig;
+
+        assert!(IsolateConfig::default().validate().is_ok());
+        let error = |config: IsolateConfig| config.validate().unwrap_err().to_string();
+        assert!(error(IsolateConfig {
+            fd_limit: Some(2),
+            ..Default::default()
+        })
+        .contains("fd_limit 2 is below 3"));
+        assert!(error(IsolateConfig {
+            process_limit: Some(0),
+            ..Default::default()
+        })
+        .contains("process_limit is 0"));
+        assert!(error(IsolateConfig {
+            memory_limit: Some(4 << 20),
+            stack_limit: Some(8 << 20),
+            ..Default::default()
+        })
+        .contains("stack_limit of 8388608 bytes exceeds memory_limit"));
+        assert!(error(IsolateConfig {
+            workdir_tmpfs_size: Some(64 << 20),
+            disk_quota: Some(32 << 20),
+            ..Default::default()
+        })
+        .contains("workdir_tmpfs_size"));
+        assert!(error(IsolateConfig {
+            memory_limit: None,
+            swap_limit: Some(1 << 20),
+            ..Default::default()
+        })
+        .contains("swap_limit needs a memory_limit"));
+
+        // Rejected before the box gets a directory
+        let config = IsolateConfig {
+            process_limit: Some(0),
+            ..box_config(30050)
+        };
+        assert!(Isolate::new(config).is_err());
+        assert!(!Isolate::instance_dir("rustbox/30050").exists());
+    }
+
+    #[test]
+    fn test_comparator_inputs_are_staged_safely() {
+        use crate::checker::Comparator;
+        use crate::isolate::CleanupPolicy;
+
+        if skip_unless_root(true) {
+            return;
+        }
+        let mut isolate = create_box(box_config(30080));
+        let workdir = isolate.config().workdir.clone();
+        let host = tempfile::tempdir().unwrap();
+        let target = host.path().join("secret");
+        std::fs::write(&target, "host data").unwrap();
+        let expected = host.path().join("expected.txt");
+        std::fs::write(&expected, "42\n").unwrap();
+        // Planted by a submission under the names the outputs are staged as
+        for name in ["expected_output", "actual_output"] {
+            std::os::unix::fs::symlink(&target, workdir.join(name)).unwrap();
+        }
+
+        // Accepts only when both files match and neither is writable
+        let check = "import os, sys\n\
+            same = open(sys.argv[1]).read() == open(sys.argv[2]).read()\n\
+            writable = any(os.access(path, os.W_OK) for path in sys.argv[1:])\n\
+            print('AC' if same and not writable else 'WA')";
+        let comparator = Comparator {
+            command: python(check),
+            limits: Default::default(),
+        };
+        let outcome = isolate
+            .compare_output(&comparator, &expected, "42\n")
+            .unwrap();
+        let leftovers: Vec<_> = std::fs::read_dir(&workdir)
+            .unwrap()
+            .flatten()
+            .filter(|entry| {
+                entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with(".rustbox-compare")
+            })
+            .collect();
+        isolate.finish(CleanupPolicy::Always).unwrap();
+
+        assert_eq!(
+            outcome.verdict,
+            crate::checker::Verdict::Accepted,
+            "{:?}",
+            outcome.result
+        );
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "host data");
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn test_checker_runs_under_its_own_limits() {
+        use crate::checker::CheckerConfig;
+        use crate::isolate::CleanupPolicy;
+        use crate::types::{ExecutionPhase, ExecutionStatus, IsolateConfig};
+        use std::time::Duration;
+
+        let submission = IsolateConfig {
+            time_limit: Some(Duration::from_secs(60)),
+            cpu_time_limit: Some(Duration::from_secs(60)),
+            wall_time_limit: Some(Duration::from_secs(120)),
+            extra_time: Some(Duration::from_secs(5)),
+            memory_limit: Some(1024 * 1024 * 1024),
+            process_limit: Some(50),
+            enable_network: true,
+            ..box_config(30081)
+        };
+        let checker = CheckerConfig {
+            time_limit: Duration::from_secs(1),
+            wall_time_limit: Duration::from_secs(3),
+            ..serde_json::from_str("{}").unwrap()
+        };
+        assert_eq!(checker.memory_limit, CheckerConfig::default().memory_limit);
+
+        // The sandbox is the submission's, every limit the checker's
+        let config = checker.isolate_config(&submission);
+        assert_eq!(config.instance_id, submission.instance_id);
+        assert_eq!(config.workdir, submission.workdir);
+        assert_eq!(config.cpu_time_limit, Some(Duration::from_secs(1)));
+        assert_eq!(config.wall_time_limit, Some(Duration::from_secs(3)));
+        assert_eq!(config.extra_time, None);
+        assert_eq!(config.memory_limit, Some(checker.memory_limit));
+        assert_eq!(config.process_limit, Some(checker.process_limit));
+        assert_eq!(config.file_size_limit, Some(checker.file_size_limit));
+        assert_eq!(config.fd_limit, Some(checker.fd_limit));
+        assert!(!config.enable_network);
+
+        if skip_unless_root(true) {
+            return;
+        }
+        let mut isolate = create_box(submission);
+        // A checker stuck in a loop is stopped at its own second, not the submission's minute
+        let command = python("while True: pass");
+
+        let result = isolate.run_checker(&command, None, &checker).unwrap();
+        isolate.finish(CleanupPolicy::Always).unwrap();
+
+        assert_eq!(result.status, ExecutionStatus::TimeLimit, "{:?}", result);
+        assert_eq!(result.phase, ExecutionPhase::Checker);
+        assert!(result.wall_time < 10.0, "{:?}", result);
+        assert_eq!(result.limits.and_then(|limits| limits.cpu_time), Some(1.0));
+    }
+
+    #[test]
+    fn test_cli_defaults_layers() {
+        use crate::defaults::CliDefaults;
+        use std::time::Duration;
+
+        let dir = tempfile::tempdir().unwrap();
+        let system = dir.path().join("system.toml");
+        let user = dir.path().join("user.toml");
+        std::fs::write(
+            &system,
+            "strict = true\nhome = \"/srv/rustbox\"\nlock_dir = \"/run/locks\"\n[limits]\nmem = 256\ncpu = 2\nprocesses = 8\n",
+        )
+        .unwrap();
+        std::fs::write(&user, "[limits]\nmem = 512\nwall_time = 4.5\n").unwrap();
+        let files = [system, user.clone(), dir.path().join("missing.toml")];
+
+        // The user file overrides the system file, the environment both
+        let env = |name: &str| match name {
+            "RUSTBOX_PROCESSES" => Some("32".to_string()),
+            "RUSTBOX_STRICT" => Some("0".to_string()),
+            _ => None,
+        };
+        let defaults = CliDefaults::load_layers(&files, env).unwrap();
+        assert_eq!(defaults.strict, Some(false));
+        assert_eq!(defaults.home, Some("/srv/rustbox".into()));
+        assert_eq!(defaults.lock_dir, Some("/run/locks".into()));
+        assert_eq!(defaults.limits.mem, Some(512));
+        assert_eq!(defaults.limits.cpu(), Some(Duration::from_secs(2)));
+        let wall_time = Duration::from_millis(4500);
+        assert_eq!(defaults.limits.wall_time(), Some(wall_time));
+        assert_eq!(defaults.limits.processes, Some(32));
+        assert_eq!(defaults.limits.output_limit, None);
+
+        // Typos and bad values are errors rather than ignored
+        std::fs::write(&user, "[limits]\nmemory = 512\n").unwrap();
+        assert!(CliDefaults::load_layers(&files, |_| None).is_err());
+        std::fs::write(&user, "[limits]\ncpu = -1\n").unwrap();
+        assert!(CliDefaults::load_layers(&files, |_| None).is_err());
+        let env = |name: &str| (name == "RUSTBOX_MEM").then(|| "lots".to_string());
+        assert!(CliDefaults::load_layers(&[], env).is_err());
+    }
 }
@@ -0,0 +1,265 @@
+//! Latency budgets for the judging pipeline (feature `perf-tests`)
+//!
+//! Each test times one end-to-end path phase by phase through the library and
+//! fails when a phase or the whole path goes over its budget. Budgets are sized
+//! for the reference CI machine; `RUSTBOX_LATENCY_SCALE` multiplies them on
+//! slower hosts. Every measurement is appended as a JSON line to
+//! `RUSTBOX_PERF_HISTORY` (default `target/perf-history.jsonl`) so a slow drift
+//! shows up in the history before it trips a budget.
+//!
+//! Run as root: `cargo test --features perf-tests latency -- --test-threads=1`
+
+use crate::isolate::{CleanupPolicy, Isolate};
+use crate::types::{ExecutionOverrides, IsolateConfig};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Box ids used by the latency tests
+const FIRST_BOX_ID: u32 = 9700;
+
+/// Creating a box: registry entry, lock, cgroup and workdir
+pub const INIT_BUDGET: Duration = Duration::from_millis(25);
+/// Tearing a box down again, including the wait for its cgroup to empty
+pub const CLEANUP_BUDGET: Duration = Duration::from_millis(200);
+/// Python hello world, init to cleanup
+pub const PYTHON_HELLO_BUDGET: Duration = Duration::from_millis(400);
+/// C++ hello world, init to cleanup, most of it in g++
+pub const CPP_COMPILE_RUN_BUDGET: Duration = Duration::from_secs(5);
+
+/// Phase timings of one measured path
+#[derive(Debug, Default)]
+pub struct Timings {
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    /// Run `f` as phase `name` and record how long it took
+    pub fn phase<T>(&mut self, name: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let value = f();
+        self.phases.push((name, start.elapsed()));
+        value
+    }
+
+    pub fn get(&self, name: &str) -> Option<Duration> {
+        self.phases
+            .iter()
+            .find(|(phase, _)| *phase == name)
+            .map(|(_, elapsed)| *elapsed)
+    }
+
+    pub fn total(&self) -> Duration {
+        self.phases.iter().map(|(_, elapsed)| *elapsed).sum()
+    }
+
+    /// One history line for test `test`
+    pub fn to_json(&self, test: &str) -> serde_json::Value {
+        let phases: serde_json::Map<String, serde_json::Value> = self
+            .phases
+            .iter()
+            .map(|(name, elapsed)| (name.to_string(), serde_json::json!(millis(*elapsed))))
+            .collect();
+        serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "test": test,
+            "commit": crate::build_info::BuildInfo::current().git_commit,
+            "phases_ms": phases,
+            "total_ms": millis(self.total()),
+        })
+    }
+}
+
+/// `budget` scaled by `RUSTBOX_LATENCY_SCALE`
+pub fn scaled(budget: Duration) -> Duration {
+    let scale = std::env::var("RUSTBOX_LATENCY_SCALE")
+        .ok()
+        .and_then(|scale| scale.parse::<f64>().ok())
+        .filter(|scale| *scale > 0.0)
+        .unwrap_or(1.0);
+    budget.mul_f64(scale)
+}
+
+/// File the measurements are appended to
+pub fn history_path() -> PathBuf {
+    std::env::var_os("RUSTBOX_PERF_HISTORY")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| {
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target/perf-history.jsonl")
+        })
+}
+
+/// Append `timings` to the history; a history that can't be written is only reported
+pub fn record(test: &str, timings: &Timings) {
+    let path = history_path();
+    let written = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", timings.to_json(test)));
+    if let Err(e) = written {
+        eprintln!(
+            "⚠️  Failed to record latency history in {}: {}",
+            path.display(),
+            e
+        );
+    }
+}
+
+/// Budget violations of `timings`, one message each
+pub fn over_budget(
+    timings: &Timings,
+    phase_budgets: &[(&str, Duration)],
+    total_budget: Duration,
+) -> Vec<String> {
+    let mut violations: Vec<String> = phase_budgets
+        .iter()
+        .filter_map(|(phase, budget)| {
+            let elapsed = timings.get(phase)?;
+            let budget = scaled(*budget);
+            (elapsed > budget)
+                .then(|| format!("{} took {:?} (budget {:?})", phase, elapsed, budget))
+        })
+        .collect();
+    let total_budget = scaled(total_budget);
+    if timings.total() > total_budget {
+        violations.push(format!(
+            "total took {:?} (budget {:?})",
+            timings.total(),
+            total_budget
+        ));
+    }
+    violations
+}
+
+fn millis(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1000.0
+}
+
+/// Time init, the compile and run of `code`, and cleanup in a fresh box
+fn measure_code(box_id: u32, language: &str, code: &str, expected: &str) -> Timings {
+    // Another test may have initialized it already
+    let _ = crate::lock_manager::init_lock_manager();
+    let mut timings = Timings::default();
+    let mut isolate = timings
+        .phase("init", || {
+            Isolate::new(IsolateConfig {
+                instance_id: format!("rustbox/{}", box_id),
+                admission_control: false,
+                ..Default::default()
+            })
+        })
+        .unwrap();
+    let program = timings
+        .phase("compile", || {
+            isolate.compile(language, code, &ExecutionOverrides::default())
+        })
+        .unwrap();
+    assert!(
+        program.failure().is_none(),
+        "{} hello world failed to compile",
+        language
+    );
+    let result = timings
+        .phase("run", || {
+            isolate.run(&program, None, &ExecutionOverrides::default())
+        })
+        .unwrap();
+    timings
+        .phase("cleanup", || isolate.finish(CleanupPolicy::Always))
+        .unwrap();
+    assert!(
+        result.success,
+        "{} hello world failed: {:?}",
+        language, result
+    );
+    assert_eq!(result.stdout.trim(), expected);
+    timings
+}
+
+/// Fail with every budget violation at once, after recording the measurement
+fn assert_within(test: &str, timings: &Timings, phases: &[(&str, Duration)], total: Duration) {
+    record(test, timings);
+    let violations = over_budget(timings, phases, total);
+    assert!(
+        violations.is_empty(),
+        "{} over budget: {}",
+        test,
+        violations.join("; ")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_violations_are_reported_per_phase() {
+        let timings = Timings {
+            phases: vec![
+                ("init", Duration::from_millis(10)),
+                ("run", Duration::from_millis(90)),
+            ],
+        };
+        let phases = [
+            ("init", Duration::from_millis(20)),
+            ("run", Duration::from_millis(50)),
+        ];
+        let violations = over_budget(&timings, &phases, Duration::from_millis(80));
+        assert_eq!(violations.len(), 2);
+        assert!(violations[0].starts_with("run took"));
+        assert!(violations[1].starts_with("total took"));
+
+        let line = timings.to_json("example");
+        assert_eq!(line["phases_ms"]["run"], 90.0);
+        assert_eq!(line["total_ms"], 100.0);
+    }
+
+    #[test]
+    fn test_latency_init_and_cleanup() {
+        let mut timings = Timings::default();
+        // Another test may have initialized it already
+        let _ = crate::lock_manager::init_lock_manager();
+        let isolate = timings
+            .phase("init", || {
+                Isolate::new(IsolateConfig {
+                    instance_id: format!("rustbox/{}", FIRST_BOX_ID),
+                    admission_control: false,
+                    ..Default::default()
+                })
+            })
+            .unwrap();
+        timings
+            .phase("cleanup", || isolate.finish(CleanupPolicy::Always))
+            .unwrap();
+        assert_within(
+            "init_and_cleanup",
+            &timings,
+            &[("init", INIT_BUDGET), ("cleanup", CLEANUP_BUDGET)],
+            INIT_BUDGET + CLEANUP_BUDGET,
+        );
+    }
+
+    #[test]
+    fn test_latency_python_hello_world() {
+        let timings = measure_code(FIRST_BOX_ID + 1, "python", "print('hello')", "hello");
+        assert_within(
+            "python_hello_world",
+            &timings,
+            &[("init", INIT_BUDGET), ("cleanup", CLEANUP_BUDGET)],
+            PYTHON_HELLO_BUDGET,
+        );
+    }
+
+    #[test]
+    fn test_latency_cpp_compile_and_run() {
+        let code = "#include <iostream>\nint main() { std::cout << \"hello\" << std::endl; }";
+        let timings = measure_code(FIRST_BOX_ID + 2, "cpp", code, "hello");
+        assert_within(
+            "cpp_compile_and_run",
+            &timings,
+            &[("init", INIT_BUDGET), ("cleanup", CLEANUP_BUDGET)],
+            CPP_COMPILE_RUN_BUDGET,
+        );
+    }
+}
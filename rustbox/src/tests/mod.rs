@@ -1,8 +1,10 @@
-pub mod common;
-pub mod core;
 #[cfg(test)]
 pub mod all_tests;
+pub mod common;
+pub mod core;
 pub mod languages;
+#[cfg(all(test, feature = "perf-tests"))]
+pub mod latency;
 pub mod performance;
 pub mod resource;
 pub mod security;
@@ -53,6 +53,13 @@ pub fn run_resource_tests(config: &TestConfig) -> Result<Vec<TestResult>> {
         test_resource_limit_recovery(config)
     }));
 
+    // Test 9: Wall and CPU limits are independent in run
+    results.push(run_test(
+        config,
+        "Wall time independent of CPU time",
+        || test_wall_time_independent_of_cpu_time(config),
+    ));
+
     Ok(results)
 }
 
@@ -150,6 +157,71 @@ print('Wall time test completed')";
     Ok(())
 }
 
+/// Test that a sleeping (low CPU) program is governed only by the wall limit
+fn test_wall_time_independent_of_cpu_time(config: &TestConfig) -> Result<()> {
+    let box_id = generate_box_id();
+    let box_arg = box_id.to_string();
+    let code = "import time; time.sleep(2); print('slept')";
+
+    execute_rustbox_command(config, &["init", "--box-id", &box_arg])?;
+
+    // A 1 second CPU limit must not kill a program that mostly sleeps
+    let result = execute_rustbox_command(
+        config,
+        &[
+            "run",
+            "--box-id",
+            &box_arg,
+            "--time",
+            "1",
+            "--wall-time",
+            "5",
+            "--mem",
+            "128",
+            "--",
+            "/usr/bin/python3",
+            "-c",
+            code,
+        ],
+    )?;
+    TestUtils::validate_success_result(&result)?;
+    TestUtils::validate_output_contains(&result, "slept")?;
+
+    execute_rustbox_command(config, &["init", "--box-id", &box_arg])?;
+
+    // The same program is killed once the wall limit alone is exceeded
+    let result = execute_rustbox_command(
+        config,
+        &[
+            "run",
+            "--box-id",
+            &box_arg,
+            "--time",
+            "10",
+            "--wall-time",
+            "1",
+            "--mem",
+            "128",
+            "--",
+            "/usr/bin/python3",
+            "-c",
+            code,
+        ],
+    )?;
+    TestUtils::validate_time_limit_result(&result)?;
+
+    let wall_time = TestUtils::extract_wall_time(&result);
+    if wall_time >= 2.0 {
+        return Err(anyhow::anyhow!(
+            "Wall limit of 1s was not enforced (ran for {:.2}s)",
+            wall_time
+        ));
+    }
+
+    cleanup_test_box(config, box_id);
+    Ok(())
+}
+
 /// Test process limit enforcement
 fn test_process_limit_enforcement(config: &TestConfig) -> Result<()> {
     let box_id = generate_box_id();
@@ -417,6 +489,10 @@ fn test_resource_limit_recovery(config: &TestConfig) -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tests::common::{
+        box_config_in_tempdir, config_in_tempdir, execute, python, skip_unless_cgroups,
+        skip_unless_root,
+    };
 
     #[test]
     fn test_memory_limit_validation() {
@@ -468,6 +544,936 @@ mod tests {
         assert_eq!(TestUtils::extract_stderr(&json), "");
     }
 
+    /// Start a long-sleeping sandboxed process and return its executor and pid
+    fn spawn_sleeper() -> (crate::executor::ProcessExecutor, u32, tempfile::TempDir) {
+        let (config, workdir) = config_in_tempdir();
+        let mut executor = crate::executor::ProcessExecutor::new(config).unwrap();
+        let pid = executor
+            .spawn(&python("import time; time.sleep(30)"), None)
+            .unwrap();
+        (executor, pid, workdir)
+    }
+
+    fn process_exists(pid: u32) -> bool {
+        unsafe { libc::kill(pid as i32, 0) == 0 }
+    }
+
+    #[test]
+    fn test_executor_drop_kills_in_flight_process() {
+        let (executor, pid, _workdir) = spawn_sleeper();
+        assert!(process_exists(pid));
+
+        drop(executor);
+        assert!(
+            !process_exists(pid),
+            "process {} outlived its executor",
+            pid
+        );
+    }
+
+    #[test]
+    fn test_executor_unwind_kills_in_flight_process() {
+        let (executor, pid, _workdir) = spawn_sleeper();
+        assert!(process_exists(pid));
+
+        let outcome = std::panic::catch_unwind(std::panic::AssertUnwindSafe(move || {
+            let _executor = executor;
+            panic!("simulated supervisor panic mid-run");
+        }));
+        assert!(outcome.is_err());
+        assert!(
+            !process_exists(pid),
+            "process {} survived a panic unwind",
+            pid
+        );
+    }
+
+    #[test]
+    fn test_watchdog_is_reaped_after_a_panic_unwind() {
+        let children = || std::fs::read_to_string("/proc/thread-self/children").unwrap();
+        let before = children();
+
+        let outcome = std::panic::catch_unwind(|| {
+            let _watchdog = crate::watchdog::Watchdog::spawn(Vec::new()).unwrap();
+            panic!("simulated supervisor panic mid-run");
+        });
+        assert!(outcome.is_err());
+        assert_eq!(children(), before, "the watchdog was left as a zombie");
+    }
+
+    #[test]
+    fn test_adaptive_timeout_kills_silent_loop() {
+        if skip_unless_cgroups() {
+            return;
+        }
+
+        let (mut config, _workdir) = config_in_tempdir();
+        config.cpu_time_limit = Some(std::time::Duration::from_secs(1));
+        config.extra_time = Some(std::time::Duration::from_secs(10));
+        config.wall_time_limit = Some(std::time::Duration::from_secs(30));
+        config.adaptive_timeout = Some(crate::types::AdaptiveTimeout::new(
+            std::time::Duration::from_millis(500),
+        ));
+        let result = execute(&config, &python("while True: pass"));
+
+        assert_eq!(result.status, crate::types::ExecutionStatus::TimeLimit);
+        assert!(result.cpu_time < 5.0, "ran {}s of CPU", result.cpu_time);
+    }
+
+    #[test]
+    fn test_adaptive_timeout_refused_for_interactive_runs() {
+        let (mut config, _workdir) = config_in_tempdir();
+        config.adaptive_timeout = Some(crate::types::AdaptiveTimeout::new(
+            std::time::Duration::from_millis(500),
+        ));
+        let tty = crate::types::IsolateConfig {
+            enable_tty: true,
+            ..config.clone()
+        };
+        assert!(tty
+            .validate()
+            .unwrap_err()
+            .to_string()
+            .contains("adaptive_timeout"));
+
+        if skip_unless_cgroups() {
+            return;
+        }
+        let mut executor = crate::executor::ProcessExecutor::new(config).unwrap();
+        let null = || std::fs::File::open("/dev/null").unwrap().into();
+        let error = executor
+            .spawn_connected(&python(""), null(), null())
+            .unwrap_err();
+        assert!(error.to_string().contains("adaptive_timeout"), "{}", error);
+    }
+
+    #[test]
+    fn test_time_limits_apply_while_stdin_is_written() {
+        use crate::types::ExecutionStatus;
+        use std::time::{Duration, Instant};
+
+        let (mut config, _workdir) = config_in_tempdir();
+        config.cpu_time_limit = Some(Duration::from_secs(1));
+        config.wall_time_limit = Some(Duration::from_secs(1));
+        let mut executor = crate::executor::ProcessExecutor::new(config).unwrap();
+        // Far more than a pipe holds, to a program that never reads it
+        let stdin = "x".repeat(256 * 1024);
+        let command = python("import time; time.sleep(20)");
+        let started = Instant::now();
+        let result = executor.execute(&command, Some(&stdin)).unwrap();
+
+        assert_eq!(result.status, ExecutionStatus::TimeLimit);
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "took {:?}",
+            started.elapsed()
+        );
+    }
+
+    #[test]
+    fn test_sub_second_time_limits() {
+        use crate::types::{ExecutionOverrides, ExecutionStatus, IsolateConfig, LimitKind};
+        use std::time::Duration;
+
+        let overrides: ExecutionOverrides =
+            serde_json::from_str(r#"{"max_time": 0.25, "extra_time": 1}"#).unwrap();
+        assert_eq!(overrides.max_time, Some(Duration::from_millis(250)));
+        assert_eq!(overrides.extra_time, Some(Duration::from_secs(1)));
+        assert!(serde_json::from_str::<ExecutionOverrides>(r#"{"max_cpu": -1}"#).is_err());
+
+        if skip_unless_cgroups() {
+            return;
+        }
+        let (config, _workdir) = config_in_tempdir();
+        let run = |cpu: Duration, wall: Duration, program: &str| {
+            let config = IsolateConfig {
+                cpu_time_limit: Some(cpu),
+                wall_time_limit: Some(wall),
+                ..config.clone()
+            };
+            execute(&config, &python(program))
+        };
+
+        // A busy loop is stopped within a few samples of its 300ms of CPU
+        let busy = run(
+            Duration::from_millis(300),
+            Duration::from_secs(10),
+            "while True: pass",
+        );
+        assert_eq!(busy.status, ExecutionStatus::TimeLimit);
+        assert!(
+            busy.cpu_time >= 0.3 && busy.cpu_time < 0.45,
+            "ran {}s of CPU",
+            busy.cpu_time
+        );
+        let limits = busy.limits.unwrap();
+        assert_eq!(limits.triggered, Some(LimitKind::CpuTime));
+        assert_eq!((limits.cpu_time, limits.wall_time), (Some(0.3), Some(10.0)));
+
+        // A sleeping program is stopped at its 300ms of wall time
+        let sleeping = run(
+            Duration::from_secs(10),
+            Duration::from_millis(300),
+            "import time; time.sleep(5)",
+        );
+        assert_eq!(sleeping.status, ExecutionStatus::TimeLimit);
+        assert!(
+            sleeping.wall_time >= 0.3 && sleeping.wall_time < 0.6,
+            "ran {}s of wall time",
+            sleeping.wall_time
+        );
+        let triggered = sleeping.limits.and_then(|limits| limits.triggered);
+        assert_eq!(triggered, Some(LimitKind::WallTime));
+    }
+
+    #[test]
+    fn test_extra_time_keeps_partial_output() {
+        use crate::types::{ExecutionStatus, IsolateConfig};
+        use std::time::Duration;
+
+        if skip_unless_cgroups() {
+            return;
+        }
+        let (config, _workdir) = config_in_tempdir();
+        let run = |extra_time: Option<Duration>| {
+            let config = IsolateConfig {
+                cpu_time_limit: Some(Duration::from_millis(200)),
+                wall_time_limit: Some(Duration::from_secs(10)),
+                extra_time,
+                ..config.clone()
+            };
+            execute(
+                &config,
+                &python("print('partial', flush=True)\nwhile True: pass"),
+            )
+        };
+
+        // Killed after its grace period, still a time limit, with its output
+        let graced = run(Some(Duration::from_millis(200)));
+        assert_eq!(graced.status, ExecutionStatus::TimeLimit);
+        assert_eq!(graced.stdout, "partial\n");
+        assert!(graced.cpu_time >= 0.4, "ran {}s of CPU", graced.cpu_time);
+
+        // Without extra time the output of a run over its limit is dropped
+        let strict = run(None);
+        assert_eq!(strict.status, ExecutionStatus::TimeLimit);
+        assert_eq!(strict.stdout, "");
+    }
+
+    #[test]
+    fn test_limits_reap_processes_outside_process_group() {
+        use crate::types::{ExecutionStatus, IsolateConfig};
+        use std::time::Duration;
+
+        if skip_unless_cgroups() {
+            return;
+        }
+        // Zombies reparented to init count as gone
+        fn running(pid: u32) -> bool {
+            std::fs::read_to_string(format!("/proc/{}/stat", pid))
+                .is_ok_and(|stat| !stat.rsplit(')').next().unwrap_or("").starts_with(" Z"))
+        }
+
+        let (base, workdir) = config_in_tempdir();
+        let run = |config: IsolateConfig, tail: &str| {
+            let pid_file = workdir.path().join("grandchild.pid");
+            let _ = std::fs::remove_file(&pid_file);
+            let mut executor = crate::executor::ProcessExecutor::new(IsolateConfig {
+                workdir: base.workdir.clone(),
+                wall_time_limit: Some(Duration::from_secs(10)),
+                process_limit: Some(8),
+                ..config
+            })
+            .unwrap();
+            // The grandchild starts a session of its own, out of the process group
+            let script = format!(
+                "import subprocess, sys\n\
+                 sleeper = subprocess.Popen([sys.executable, '-c', 'import time; time.sleep(100)'], \
+                 start_new_session=True, stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL)\n\
+                 open('{}', 'w').write(str(sleeper.pid))\n\
+                 {}",
+                pid_file.display(),
+                tail
+            );
+            let result = executor.execute(&python(&script), None).unwrap();
+            let grandchild: u32 = std::fs::read_to_string(&pid_file)
+                .unwrap()
+                .trim()
+                .parse()
+                .unwrap();
+            // Checked before the executor is dropped and cleans up the box
+            (result, running(grandchild), executor)
+        };
+
+        let (result, survived, _executor) = run(
+            IsolateConfig {
+                cpu_time_limit: Some(Duration::from_millis(200)),
+                ..Default::default()
+            },
+            "while True: pass",
+        );
+        assert_eq!(result.status, ExecutionStatus::TimeLimit);
+        assert!(!survived, "a process outlived the time limit");
+
+        let (result, survived, _executor) = run(
+            IsolateConfig {
+                output_limit: Some(4096),
+                ..Default::default()
+            },
+            "while True: print('y')",
+        );
+        assert_eq!(result.status, ExecutionStatus::OutputLimit);
+        assert!(!survived, "a process outlived the output limit");
+    }
+
+    #[test]
+    fn test_output_capture_limit_and_streams() {
+        use crate::output_capture::{collect, CaptureProgress, OutputLimits};
+        use std::io::Write;
+        use std::os::fd::{FromRawFd, OwnedFd};
+
+        fn pipe() -> (OwnedFd, std::fs::File) {
+            let mut fds = [0; 2];
+            assert_eq!(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) }, 0);
+            unsafe {
+                (
+                    OwnedFd::from_raw_fd(fds[0]),
+                    std::fs::File::from_raw_fd(fds[1]),
+                )
+            }
+        }
+
+        // Streams stay separate and complete under the limit
+        let (stdout, mut stdout_writer) = pipe();
+        let (stderr, mut stderr_writer) = pipe();
+        stdout_writer.write_all(b"out").unwrap();
+        stderr_writer.write_all(b"err").unwrap();
+        drop((stdout_writer, stderr_writer));
+        let progress = CaptureProgress::default();
+        let limits = OutputLimits::total(Some(6));
+        let output = collect(Some(stdout), Some(stderr), limits, &progress).unwrap();
+        assert_eq!(output.stdout, b"out");
+        assert_eq!(output.stderr, b"err");
+        assert!(!output.limit_exceeded);
+        assert_eq!(progress.bytes(), 6);
+
+        // Output past the limit is cut at exactly the limit
+        let (stdout, mut stdout_writer) = pipe();
+        stdout_writer.write_all(&[b'x'; 10_000]).unwrap();
+        let progress = CaptureProgress::default();
+        let limits = OutputLimits::total(Some(4096));
+        let output = collect(Some(stdout), None, limits, &progress).unwrap();
+        assert_eq!(output.stdout.len(), 4096);
+        assert!(output.limit_exceeded);
+        assert!(progress.limit_exceeded());
+    }
+
+    #[test]
+    fn test_stream_limits_truncate_output() {
+        use crate::types::ExecutionStatus;
+
+        let (mut config, _workdir) = config_in_tempdir();
+        config.stdout_limit = Some(10);
+        config.stderr_limit = Some(1024);
+        config.admission_control = false;
+
+        // A flood on stdout is stopped and kept cut at its limit
+        let result = execute(&config, &python("while True: print('x' * 4096)"));
+        assert_eq!(result.status, ExecutionStatus::OutputLimit);
+        assert_eq!(result.stdout.len(), 10);
+        assert!(result.stdout_truncated);
+        assert!(!result.stderr_truncated);
+        assert_eq!(
+            result.error_message.as_deref(),
+            Some("Output Limit Exceeded (stdout)")
+        );
+
+        // Output within both limits is untouched
+        let result = execute(&config, &python("print('short')"));
+        assert!(result.success);
+        assert_eq!(result.stdout, "short\n");
+        assert!(!result.stdout_truncated);
+    }
+
+    #[test]
+    fn test_output_streams_live_within_caps() {
+        use crate::output_capture::{OutputSink, OutputStream};
+        use std::sync::mpsc;
+        use std::time::{Duration, Instant};
+
+        let (mut config, _workdir) = config_in_tempdir();
+        config.admission_control = false;
+        let (sender, chunks) = mpsc::channel();
+        let sink = OutputSink::new(move |stream, data: &[u8]| {
+            let _ = sender.send((stream, data.to_vec(), Instant::now()));
+        })
+        .with_caps(None, Some(2));
+
+        let command = python(
+            "import sys, time\nprint('first', flush=True)\ntime.sleep(0.3)\nsys.stderr.write('err')\nprint('last')",
+        );
+        let mut executor = crate::executor::ProcessExecutor::new(config).unwrap();
+        let result = executor.execute_streaming(&command, None, sink).unwrap();
+        assert_eq!(result.stdout, "first\nlast\n");
+        assert_eq!(result.stderr, "err");
+
+        let chunks: Vec<_> = chunks.try_iter().collect();
+        let stdout: Vec<u8> = chunks
+            .iter()
+            .filter(|(stream, _, _)| *stream == OutputStream::Stdout)
+            .flat_map(|(_, data, _)| data.clone())
+            .collect();
+        let stderr: Vec<u8> = chunks
+            .iter()
+            .filter(|(stream, _, _)| *stream == OutputStream::Stderr)
+            .flat_map(|(_, data, _)| data.clone())
+            .collect();
+        assert_eq!(stdout, b"first\nlast\n");
+        assert_eq!(stderr, b"er");
+        // The first line was forwarded while the program was still sleeping
+        let (first, last) = (chunks[0].2, chunks[chunks.len() - 1].2);
+        assert!(last.duration_since(first) >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_workdir_tmpfs_bounds_and_discards_writes() {
+        use crate::admission::ResourceRequest;
+        use crate::filesystem::FilesystemSecurity;
+
+        let (mut config, _workdir) = config_in_tempdir();
+        config.workdir_tmpfs_size = Some(1024 * 1024);
+        // The tmpfs is the write bound, so no disk is reserved for the run
+        assert_eq!(ResourceRequest::from_config(&config).disk, 0);
+
+        if skip_unless_root(false) {
+            return;
+        }
+        let filesystem = FilesystemSecurity::new(None, config.workdir.clone(), true);
+        filesystem
+            .mount_workdir_tmpfs(1024 * 1024, None, None)
+            .unwrap();
+        let small = config.workdir.join("small");
+        std::fs::write(&small, vec![0u8; 1024]).unwrap();
+        let err = std::fs::write(config.workdir.join("big"), vec![0u8; 2 * 1024 * 1024]);
+        filesystem.unmount_workdir().unwrap();
+
+        assert_eq!(err.unwrap_err().raw_os_error(), Some(libc::ENOSPC));
+        assert!(!small.exists());
+    }
+
+    #[test]
+    fn test_disk_quota_image_bounds_and_discards_writes() {
+        use crate::admission::ResourceRequest;
+        use crate::filesystem::FilesystemSecurity;
+
+        let (mut config, _workdir) = config_in_tempdir();
+        config.disk_quota = Some(4 * 1024 * 1024);
+        // The image is stored on the host disk, so the quota is reserved
+        assert_eq!(ResourceRequest::from_config(&config).disk, 4 * 1024 * 1024);
+        let filesystem = FilesystemSecurity::new(None, config.workdir.clone(), true);
+        assert!(filesystem.mount_workdir_image(4096, None, None).is_err());
+
+        if skip_unless_root(false) {
+            return;
+        }
+        filesystem
+            .mount_workdir_image(4 * 1024 * 1024, None, None)
+            .unwrap();
+        let small = config.workdir.join("small");
+        std::fs::write(&small, vec![0u8; 1024]).unwrap();
+        let full_before = filesystem.workdir_full();
+        let err = std::fs::write(config.workdir.join("big"), vec![0u8; 8 * 1024 * 1024]);
+        let full_after = filesystem.workdir_full();
+        filesystem.unmount_workdir().unwrap();
+
+        assert_eq!(err.unwrap_err().raw_os_error(), Some(libc::ENOSPC));
+        assert!(!full_before && full_after);
+        assert!(!small.exists());
+    }
+
+    #[test]
+    fn test_admission_deducts_reservations() {
+        use crate::admission::{evaluate, AdmissionDecision, HostResources, ResourceRequest};
+
+        const MB: u64 = 1024 * 1024;
+        let host = HostResources {
+            memory_total: 4096 * MB,
+            memory_available: 1088 * MB,
+            pids_current: 100,
+            pids_max: 1000,
+            disk_free: Some(528 * MB),
+        };
+        let run = ResourceRequest {
+            memory: 256 * MB,
+            pids: 64,
+            disk: 128 * MB,
+        };
+
+        // 1024 MB of memory and 512 MB of disk are free past the headroom
+        assert_eq!(evaluate(&run, &host, &[]), AdmissionDecision::Admit);
+        assert_eq!(evaluate(&run, &host, &[run; 3]), AdmissionDecision::Admit);
+        match evaluate(&run, &host, &[run; 4]) {
+            AdmissionDecision::Queue(reason) => assert!(reason.contains("1024 MB reserved")),
+            other => panic!("expected Queue, got {:?}", other),
+        }
+
+        // Each resource is deducted on its own
+        let pids_only = ResourceRequest {
+            pids: 400,
+            ..Default::default()
+        };
+        assert!(matches!(
+            evaluate(&run, &host, &[pids_only, pids_only]),
+            AdmissionDecision::Queue(_)
+        ));
+        let disk_only = ResourceRequest {
+            disk: 400 * MB,
+            ..Default::default()
+        };
+        assert!(matches!(
+            evaluate(&run, &host, &[disk_only]),
+            AdmissionDecision::Queue(_)
+        ));
+
+        // What could never fit is rejected whatever is reserved
+        let huge = ResourceRequest {
+            memory: 8192 * MB,
+            ..run
+        };
+        assert!(matches!(
+            evaluate(&huge, &host, &[]),
+            AdmissionDecision::Reject(_)
+        ));
+    }
+
+    #[test]
+    fn test_admission_ledger_holds_concurrent_reservations() {
+        use crate::admission::{AdmissionDecision, Ledger, ResourceRequest};
+        use std::sync::{Arc, Barrier};
+
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = Ledger::new(
+            dir.path().join("ledger.json"),
+            dir.path().join("ledger.lock"),
+        );
+        let run = ResourceRequest {
+            memory: 100,
+            pids: 1,
+            disk: 0,
+        };
+
+        // Eight runs race for room for three; the ledger lets exactly three in
+        let barrier = Arc::new(Barrier::new(8));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let (ledger, barrier) = (ledger.clone(), Arc::clone(&barrier));
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    ledger
+                        .reserve(run, |reserved| {
+                            let held: u64 = reserved.iter().map(|r| r.memory).sum();
+                            Ok(if held + run.memory <= 300 {
+                                AdmissionDecision::Admit
+                            } else {
+                                AdmissionDecision::Queue("full".to_string())
+                            })
+                        })
+                        .unwrap()
+                })
+            })
+            .collect();
+        let outcomes: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        let admitted = outcomes
+            .iter()
+            .filter(|(decision, reservation)| {
+                *decision == AdmissionDecision::Admit && reservation.is_some()
+            })
+            .count();
+        assert_eq!(admitted, 3);
+        assert_eq!(ledger.reserved().unwrap(), vec![run; 3]);
+
+        // Dropping a reservation gives its resources back
+        drop(outcomes);
+        assert!(ledger.reserved().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_host_pressure_pauses_admission() {
+        use crate::admission::{admit_with, metrics, HostPressure, Ledger, PressureThresholds};
+        use std::time::Duration;
+
+        let pressure = HostPressure::parse(
+            "some avg10=12.50 avg60=3.00 avg300=1.00 total=100\n\
+             full avg10=0.00 avg60=0.00 avg300=0.00 total=0\n",
+            "some avg10=45.00 avg60=1.00 avg300=0.00 total=5\n\
+             full avg10=11.25 avg60=0.50 avg300=0.00 total=2\n",
+            "some avg10=0.00 avg60=0.00 avg300=0.00 total=0\n",
+        );
+        assert_eq!(pressure.cpu_some, 12.5);
+        assert_eq!(pressure.memory_some, 45.0);
+        assert_eq!(pressure.memory_full, 11.25);
+        // A missing line reads as no pressure
+        assert_eq!(pressure.io_full, 0.0);
+
+        // The first threshold exceeded is reported; unset thresholds are skipped
+        let defaults = PressureThresholds::default();
+        assert_eq!(
+            pressure.exceeded(&defaults).as_deref(),
+            Some("host memory some pressure 45.0% above 40.0%")
+        );
+        let relaxed = PressureThresholds {
+            memory_some: None,
+            memory_full: Some(20.0),
+            ..defaults.clone()
+        };
+        assert_eq!(pressure.exceeded(&relaxed), None);
+        let at_limit = PressureThresholds {
+            cpu_some: Some(12.5),
+            ..relaxed.clone()
+        };
+        assert_eq!(pressure.exceeded(&at_limit), None);
+
+        // Thresholds any host exceeds keep the run queued until it gives up
+        if HostPressure::probe().is_none()
+            || std::env::var(crate::admission::IGNORE_PRESSURE_ENV).is_ok()
+        {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = Ledger::new(
+            dir.path().join("ledger.json"),
+            dir.path().join("ledger.lock"),
+        );
+        let config = crate::types::IsolateConfig {
+            workdir: dir.path().to_path_buf(),
+            memory_limit: Some(16 * 1024 * 1024),
+            admission_timeout: Duration::from_millis(300),
+            pressure_thresholds: PressureThresholds {
+                cpu_some: Some(-1.0),
+                ..relaxed
+            },
+            ..Default::default()
+        };
+        let pauses = metrics().pressure_pauses;
+        let error = admit_with(&config, &ledger).unwrap_err().to_string();
+        assert!(error.contains("host cpu some pressure"), "{}", error);
+        assert!(metrics().pressure_pauses > pauses);
+        assert!(ledger.reserved().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cpu_affinity_pins_program() {
+        let (base, _workdir) = box_config_in_tempdir(968);
+        let config = |cpus: Vec<usize>| crate::types::IsolateConfig {
+            cpu_affinity: Some(cpus),
+            ..base.clone()
+        };
+        assert!(crate::executor::ProcessExecutor::new(config(vec![])).is_err());
+        assert!(
+            crate::executor::ProcessExecutor::new(config(vec![libc::CPU_SETSIZE as usize]))
+                .is_err()
+        );
+
+        let cpu = (0..libc::CPU_SETSIZE as usize)
+            .find(|cpu| crate::executor::cpu_set(&[*cpu]).is_ok())
+            .unwrap();
+        let code = "import os; print(sorted(os.sched_getaffinity(0)))";
+        let result = execute(&config(vec![cpu]), &python(code));
+        assert_eq!(result.stdout, format!("[{}]\n", cpu));
+    }
+
+    #[test]
+    fn test_cpu_quota_throttles_program() {
+        if skip_unless_root(false) {
+            return;
+        }
+        let (mut config, _workdir) = box_config_in_tempdir(969);
+        config.cpu_quota_percent = Some(20);
+        let code = "import time\nstart = time.time()\nwhile time.time() - start < 0.5: pass";
+        let result = execute(&config, &python(code));
+        assert!(result.success);
+        // Busy for half a second of wall time, but allowed a fifth of a core
+        assert!(result.cpu_time < 0.5 * result.wall_time, "{:?}", result);
+    }
+
+    #[test]
+    #[cfg(feature = "cgroup-v1")]
+    fn test_memory_high_and_stall_use_only_the_group() {
+        if skip_unless_root(true) {
+            return;
+        }
+        let cgroup = crate::cgroup::Cgroup::new("rustbox/30090", false).unwrap();
+        let group = |controller: &str| {
+            crate::cgroup::cgroup_root()
+                .join(controller)
+                .join("rustbox_30090")
+        };
+
+        // The soft limit lands in the v1 file
+        cgroup.set_memory_high(32 * 1024 * 1024).unwrap();
+        let soft_limit =
+            std::fs::read_to_string(group("memory").join("memory.soft_limit_in_bytes")).unwrap();
+        assert_eq!(soft_limit.trim(), (32 * 1024 * 1024).to_string());
+
+        // Stalls come from the group's own PSI file, never the host-wide one
+        let group_psi = group("cpuacct").join("memory.pressure").exists();
+        assert_eq!(cgroup.memory_stall_total_us().is_some(), group_psi);
+        cgroup.cleanup().unwrap();
+    }
+
+    #[test]
+    fn test_kernel_memory_limits_default_to_memory_limit() {
+        use crate::types::IsolateConfig;
+
+        let mb = 1024 * 1024;
+        let config = |memory, kernel_memory, tcp_memory| IsolateConfig {
+            memory_limit: memory,
+            kernel_memory_limit: kernel_memory,
+            tcp_memory_limit: tcp_memory,
+            ..Default::default()
+        };
+        assert_eq!(config(None, None, None).kernel_memory_limits(), None);
+        // A TCP cap alone has no kmem cap to sit under
+        assert_eq!(config(None, None, Some(mb)).kernel_memory_limits(), None);
+        assert_eq!(
+            config(Some(64 * mb), None, None).kernel_memory_limits(),
+            Some((64 * mb, 64 * mb))
+        );
+        assert_eq!(
+            config(Some(64 * mb), Some(16 * mb), None).kernel_memory_limits(),
+            Some((16 * mb, 16 * mb))
+        );
+        assert_eq!(
+            config(None, Some(16 * mb), Some(4 * mb)).kernel_memory_limits(),
+            Some((16 * mb, 4 * mb))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "cgroup-v1")]
+    fn test_kernel_memory_limits_without_kmem_files() {
+        if skip_unless_root(true) {
+            return;
+        }
+        let cgroup = crate::cgroup::Cgroup::new("rustbox/30091", false).unwrap();
+        let kmem_v1 =
+            std::path::Path::new("/sys/fs/cgroup/memory/memory.kmem.limit_in_bytes").exists();
+        if !kmem_v1 {
+            // No kmem files: reported as not enforced, the TCP cap skipped, no peak
+            assert!(!cgroup.set_kernel_memory_limit(16 * 1024 * 1024).unwrap());
+            cgroup.set_tcp_memory_limit(4 * 1024 * 1024).unwrap();
+            assert_eq!(cgroup.get_kernel_memory_peak(), None);
+        }
+        // Out-of-range values are refused whether or not the files exist
+        assert!(cgroup.set_kernel_memory_limit(0).is_err());
+        assert!(cgroup.set_tcp_memory_limit(0).is_err());
+        cgroup.cleanup().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "cgroup-v1")]
+    fn test_memory_limit_from_oom_events() {
+        use crate::types::ExecutionStatus;
+
+        if skip_unless_root(false) {
+            return;
+        }
+        let (mut config, _workdir) = box_config_in_tempdir(970);
+        config.memory_limit = Some(64 * 1024 * 1024);
+        let cgroup = crate::cgroup::Cgroup::new(&config.instance_id, false).unwrap();
+        let mut events = cgroup.subscribe_oom().unwrap().unwrap();
+        assert!(!events.fired());
+
+        let run = |code: &str| execute(&config, &python(code));
+        let killed = run("x = bytearray(200 << 20)");
+        assert_eq!(killed.status, ExecutionStatus::MemoryLimit);
+        // Page cache filling the group up to its limit is reclaimed, not an OOM
+        let cached = run("x = bytearray(30 << 20)\nfor i in range(40):\n    \
+            open('f%d' % i, 'wb').write(b'y' * (1 << 20))");
+        assert_eq!(cached.status, ExecutionStatus::Success, "{:?}", cached);
+    }
+
+    #[test]
+    #[cfg(feature = "cgroup-v1")]
+    fn test_limits_report_what_applied_and_what_ended_the_run() {
+        use crate::types::{ExecutionStatus, LimitKind};
+        use std::time::Duration;
+
+        if skip_unless_root(true) {
+            return;
+        }
+        let (mut config, _workdir) = box_config_in_tempdir(30092);
+        config.memory_limit = Some(64 * 1024 * 1024);
+        config.cpu_time_limit = Some(Duration::from_secs(5));
+        config.process_limit = Some(8);
+        config.fd_limit = Some(64);
+        let run = |code: &str| execute(&config, &python(code));
+
+        // Every limit in effect is echoed back, none of them blamed
+        let finished = run("pass");
+        assert_eq!(finished.status, ExecutionStatus::Success, "{:?}", finished);
+        let limits = finished.limits.clone().unwrap();
+        assert_eq!(limits.memory, Some(64 * 1024 * 1024));
+        assert_eq!(limits.cpu_time, Some(5.0));
+        assert!(limits.wall_time.is_some());
+        assert_eq!((limits.processes, limits.fds), (Some(8), Some(64)));
+        assert_eq!(limits.triggered, None);
+        let json = finished.to_cli_json();
+        assert_eq!(json["limits"]["processes"], 8);
+        assert_eq!(json["limits"]["triggered"], serde_json::Value::Null);
+
+        let killed = run("x = bytearray(200 << 20)");
+        assert_eq!(killed.status, ExecutionStatus::MemoryLimit);
+        let triggered = killed.limits.and_then(|limits| limits.triggered);
+        assert_eq!(triggered, Some(LimitKind::Memory));
+    }
+
+    #[test]
+    fn test_standard_streams_redirect_to_box_files() {
+        use crate::types::{ExecutionStatus, IsolateConfig, IsolateError};
+
+        let (config, workdir) = config_in_tempdir();
+        std::fs::write(workdir.path().join("input.txt"), "data").unwrap();
+        let config = IsolateConfig {
+            admission_control: false,
+            stdin_file: Some("input.txt".into()),
+            stdout_file: Some("output.txt".into()),
+            stderr_file: Some(workdir.path().join("errors.txt")),
+            ..config
+        };
+        let run = |config: &IsolateConfig, code: &str| {
+            let mut executor = crate::executor::ProcessExecutor::new(config.clone()).unwrap();
+            executor.execute(&python(code), Some("ignored"))
+        };
+
+        let code = "import sys\nprint(sys.stdin.read().upper())\nsys.stderr.write('warn')";
+        let result = run(&config, code).unwrap();
+        assert!(result.success, "{:?}", result);
+        assert_eq!(result.stdout, "");
+        let read = |name: &str| std::fs::read_to_string(workdir.path().join(name)).unwrap();
+        assert_eq!(read("output.txt"), "DATA\n");
+        assert_eq!(read("errors.txt"), "warn");
+
+        // The stream's output limit caps the file
+        let capped = IsolateConfig {
+            stdout_limit: Some(1024),
+            ..config.clone()
+        };
+        let result = run(&capped, "print('x' * 100000)").unwrap();
+        assert_eq!(result.status, ExecutionStatus::OutputLimit);
+        let output = std::fs::metadata(workdir.path().join("output.txt")).unwrap();
+        assert_eq!(output.len(), 1024);
+
+        // Paths cannot leave the workdir
+        for outside in ["../escape.txt", "/etc/passwd"] {
+            let escaping = IsolateConfig {
+                stdout_file: Some(outside.into()),
+                ..config.clone()
+            };
+            assert!(matches!(
+                run(&escaping, "pass"),
+                Err(IsolateError::Config(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_process_memory_reports_largest_process() {
+        if skip_unless_root(false) {
+            return;
+        }
+        let (mut config, _workdir) = box_config_in_tempdir(971);
+        config.memory_limit = Some(256 * 1024 * 1024);
+        config.process_limit = Some(10);
+        config.track_process_memory = true;
+        // The child holds 80 MB while the parent holds 40 MB; the fork waits until
+        // the program has been moved into the box cgroup
+        let code = "import os, time\ntime.sleep(0.2)\nif os.fork() == 0:\n    \
+            y = bytearray(80 << 20)\n    time.sleep(0.3)\n    os._exit(0)\n\
+            x = bytearray(40 << 20)\nos.wait()";
+        let result = execute(&config, &python(code));
+        assert!(result.success, "{:?}", result);
+
+        let report = result.process_memory.unwrap();
+        assert!(report.processes >= 2, "{:?}", report);
+        assert!(report.max_process_rss >= 80 << 20, "{:?}", report);
+        // The cgroup peak counts both
+        assert!(
+            report.max_process_rss + (30 << 20) < result.memory_peak,
+            "{:?}",
+            report
+        );
+    }
+
+    #[test]
+    fn test_resource_detail_reports_usage() {
+        let (config, _workdir) = box_config_in_tempdir(30070);
+        // Touching fresh memory faults, sleeping gives up the CPU
+        let code = "import time\nx = bytearray(16 << 20)\nfor _ in range(5):\n    \
+            time.sleep(0.01)\nsum(range(10 ** 6))";
+        let result = execute(&config, &python(code));
+        assert!(result.success, "{:?}", result);
+
+        let detail = result.resource_detail.clone().unwrap();
+        assert!(
+            detail.voluntary_context_switches.unwrap() >= 5,
+            "{:?}",
+            detail
+        );
+        assert!(
+            detail.involuntary_context_switches.is_some(),
+            "{:?}",
+            detail
+        );
+        // 16 MB is 4096 pages at the least
+        assert!(detail.minor_page_faults.unwrap() >= 4096, "{:?}", detail);
+        assert!(detail.major_page_faults.is_some(), "{:?}", detail);
+        assert!(detail.user_time.unwrap() > 0.0, "{:?}", detail);
+        assert!(detail.system_time.is_some(), "{:?}", detail);
+        assert!(detail.block_read_bytes.is_some(), "{:?}", detail);
+        assert!(detail.block_write_bytes.is_some(), "{:?}", detail);
+
+        let json = result.to_cli_json();
+        assert_eq!(
+            json["resource_detail"]["voluntary_context_switches"],
+            detail.voluntary_context_switches.unwrap()
+        );
+    }
+
+    #[test]
+    fn test_subreaper_adopts_orphans_and_forwards_signals() {
+        if skip_unless_root(false) {
+            return;
+        }
+        let (mut config, workdir) = box_config_in_tempdir(30060);
+        config.process_limit = Some(5);
+
+        // The grandchild outlives its parent and is adopted by the program's parent
+        let code = "import os, sys, time\nprint(os.getppid(), flush=True)\n\
+            if os.fork() == 0:\n    if os.fork() == 0:\n        time.sleep(0.2)\n        \
+            print(os.getppid(), flush=True)\n    os._exit(0)\nos.wait()\ntime.sleep(0.5)";
+        let result = execute(&config, &python(code));
+        assert!(result.success, "{:?}", result);
+        let parents: Vec<&str> = result.stdout.split_whitespace().collect();
+        assert_eq!(parents.len(), 2, "{:?}", result.stdout);
+        assert_eq!(parents[0], parents[1]);
+
+        // A signal for the sandbox reaches the program, whose exit is the run's
+        let ready = workdir.path().join("ready");
+        let code = "import signal, sys, time\n\
+            signal.signal(signal.SIGUSR1, lambda *_: sys.exit(7))\n\
+            open('ready', 'w').close()\ntime.sleep(30)";
+        let mut executor = crate::executor::ProcessExecutor::new(config).unwrap();
+        let pid = executor.spawn(&python(code), None).unwrap();
+        let start = std::time::Instant::now();
+        while !ready.exists() && start.elapsed() < std::time::Duration::from_secs(10) {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        unsafe { libc::kill(pid as i32, libc::SIGUSR1) };
+        let result = executor.wait().unwrap();
+        assert_eq!(result.exit_code, Some(7), "{:?}", result);
+    }
+
     // run_resource_tests
     #[test]
     fn test_resource_test_run() {
@@ -54,6 +54,11 @@ pub fn run_security_tests(config: &TestConfig) -> Result<Vec<TestResult>> {
         test_security_boundary_enforcement(config)
     }));
 
+    // Test 9: Umask applied to compiler outputs and created files
+    results.push(run_test(config, "Sandbox file permissions", || {
+        test_sandbox_file_permissions(config)
+    }));
+
     Ok(results)
 }
 
@@ -592,9 +597,85 @@ except Exception as e:
     Ok(())
 }
 
+/// Test that files created in the workdir are not world-accessible
+fn test_sandbox_file_permissions(config: &TestConfig) -> Result<()> {
+    let box_id = generate_box_id();
+    let code = r#"#include <cstdio>
+#include <sys/stat.h>
+
+static void report(const char* name) {
+    struct stat st;
+    if (stat(name, &st) != 0) {
+        printf("%s missing\n", name);
+        return;
+    }
+    printf("%s %o\n", name, st.st_mode & 0777);
+}
+
+int main() {
+    FILE* f = fopen("created.txt", "w");
+    if (f) {
+        fputs("data", f);
+        fclose(f);
+    }
+    report("solution");
+    report("solution.cpp");
+    report("created.txt");
+    return 0;
+}"#;
+
+    let result = execute_rustbox_command(
+        config,
+        &[
+            "execute-code",
+            "--strict",
+            "--box-id",
+            &box_id.to_string(),
+            "--language",
+            "cpp",
+            "--code",
+            code,
+            "--time",
+            "10",
+            "--mem",
+            "256",
+            "--umask",
+            "027",
+        ],
+    )?;
+
+    TestUtils::validate_success_result(&result)?;
+
+    let stdout = TestUtils::extract_stdout(&result);
+
+    for name in ["solution", "solution.cpp", "created.txt"] {
+        let line = stdout
+            .lines()
+            .find(|line| line.starts_with(&format!("{} ", name)))
+            .ok_or_else(|| anyhow::anyhow!("No permission report for {}: {}", name, stdout))?;
+        let mode_str = line.rsplit(' ').next().unwrap_or_default();
+        let mode = u32::from_str_radix(mode_str, 8)
+            .map_err(|_| anyhow::anyhow!("Could not read mode of {}: {}", name, line))?;
+        if mode & 0o007 != 0 {
+            return Err(anyhow::anyhow!(
+                "{} is accessible to other users (mode {:o})",
+                name,
+                mode
+            ));
+        }
+    }
+
+    cleanup_test_box(config, box_id);
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tests::common::{
+        box_config, box_config_in_tempdir, config_in_tempdir, create_box, python, skip,
+        skip_unless_root,
+    };
 
     #[test]
     fn test_security_code_generation() {
@@ -613,6 +694,872 @@ mod tests {
         assert!(TestUtils::validate_output_contains(&json, "Goodbye").is_err());
     }
 
+    #[test]
+    fn test_security_log_rotates_and_feeds_syslog() {
+        use crate::security_logging::{
+            SecurityEvent, SecurityEventType, SecurityLogEntry, SecurityLogOptions, SecurityLogger,
+            SecuritySink,
+        };
+        use std::os::unix::net::UnixDatagram;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("security-audit.log");
+        let socket = dir.path().join("log.sock");
+        let syslog = UnixDatagram::bind(&socket).unwrap();
+        syslog
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+        let logger = SecurityLogger::with_options(SecurityLogOptions {
+            path: Some(path.clone()),
+            max_bytes: 1024,
+            max_files: 2,
+            sink: Some(SecuritySink::Syslog),
+            sink_socket: Some(socket),
+        })
+        .unwrap();
+
+        for n in 0..20 {
+            let event = SecurityEvent::new(
+                SecurityEventType::SyscallDenied,
+                format!("Refused execve call {}", n),
+            )
+            .with_box_id(7)
+            .with_pid(4242)
+            .with_uid(60007)
+            .with_syscall("execve".to_string());
+            logger.log_security_event(event);
+        }
+
+        // Each line is a self-contained JSON entry
+        let current = std::fs::read_to_string(&path).unwrap();
+        let entries: Vec<SecurityLogEntry> = current
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let last = entries.last().unwrap();
+        assert_eq!(last.details, "Refused execve call 19");
+        assert_eq!((last.box_id, last.pid, last.uid), (Some(7), 4242, 60007));
+        assert_eq!(last.syscall.as_deref(), Some("execve"));
+        assert_eq!(last.process_id, std::process::id());
+
+        // Rotated at 1 KiB, keeping two older files
+        assert!(current.len() <= 1024);
+        assert!(dir.path().join("security-audit.log.1").exists());
+        assert!(dir.path().join("security-audit.log.2").exists());
+        assert!(!dir.path().join("security-audit.log.3").exists());
+
+        let mut message = [0u8; 4096];
+        let len = syslog.recv(&mut message).unwrap();
+        let message = std::str::from_utf8(&message[..len]).unwrap();
+        // authpriv (10) * 8 + warning (4)
+        assert!(message.starts_with(&format!("<84>rustbox[{}]: {{", std::process::id())));
+        assert!(message.contains("\"event_type\":\"SyscallDenied\""));
+    }
+
+    #[test]
+    fn test_file_audit_records_child_writes() {
+        use crate::file_audit::{FileAccessKind, FileAudit};
+
+        if !FileAudit::supported() {
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let audit = FileAudit::start(&[dir.path().to_path_buf()]).unwrap();
+        let target = dir.path().join("written.txt");
+        let status = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(format!("echo data > {}", target.display()))
+            .status()
+            .unwrap();
+        assert!(status.success());
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        let log = audit.finish();
+        let target = target.canonicalize().unwrap();
+        assert!(log
+            .events
+            .iter()
+            .any(|event| event.path == target && event.kind == FileAccessKind::CloseWrite));
+    }
+
+    #[test]
+    fn test_directory_binding_id_maps() {
+        use crate::types::{DirectoryBinding, IdRemap};
+
+        let binding = DirectoryBinding::parse(
+            "/srv/data=/sandbox/data:rw,uidmap=1000-65534,gidmap=100-65534",
+        )
+        .unwrap();
+        assert_eq!(
+            binding.uid_map,
+            Some(IdRemap {
+                host: 1000,
+                sandbox: 65534
+            })
+        );
+        assert_eq!(binding.gid_map.map(|map| map.host), Some(100));
+
+        let plain = DirectoryBinding::parse("/srv/data=/sandbox/data:ro").unwrap();
+        assert!(plain.uid_map.is_none() && plain.gid_map.is_none());
+
+        assert!(DirectoryBinding::parse("/srv/data=/sandbox/data:uidmap=1000").is_err());
+        assert!(DirectoryBinding::parse("/srv/data=/sandbox/data:uidmap=x-1").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "seccomp-native")]
+    fn test_syscall_audit_builds_profile() {
+        use crate::syscall_audit::{install_in_child, SupervisorOptions, SyscallAudit};
+        use std::os::unix::process::CommandExt;
+
+        if !SyscallAudit::supported() {
+            return;
+        }
+
+        let options = SupervisorOptions {
+            record_syscalls: true,
+            exec_limit: None,
+        };
+        let mut audit = SyscallAudit::start(options).unwrap();
+        let handoff_fd = audit.child_fd();
+        let mut cmd = std::process::Command::new("true");
+        unsafe {
+            cmd.pre_exec(move || install_in_child(handoff_fd, true));
+        }
+        let mut child = cmd.spawn().unwrap();
+        audit.spawned();
+        assert!(child.wait().unwrap().success());
+
+        let report = audit.finish();
+        assert_eq!(report.execs, None);
+        let log = report.syscalls.unwrap();
+        assert!(log.syscalls.contains_key("execve"));
+        assert!(log.syscalls.contains_key("exit_group"));
+
+        let profile = log.profile();
+        assert_eq!(profile["defaultAction"], "SCMP_ACT_ERRNO");
+        let names = profile["syscalls"][0]["names"].as_array().unwrap();
+        assert_eq!(names.len(), log.syscalls.len());
+    }
+
+    #[test]
+    #[cfg(feature = "seccomp-native")]
+    fn test_exec_limit_refuses_extra_execs() {
+        use crate::syscall_audit::{install_in_child, ExecTally, SupervisorOptions, SyscallAudit};
+        use std::os::unix::process::CommandExt;
+
+        if !SyscallAudit::supported() {
+            return;
+        }
+
+        let options = SupervisorOptions {
+            record_syscalls: false,
+            exec_limit: Some(2),
+        };
+        let mut supervisor = SyscallAudit::start(options).unwrap();
+        let handoff_fd = supervisor.child_fd();
+        // sh itself and the attempt on the missing binary use up the limit,
+        // so both /bin/true are refused
+        let mut cmd = std::process::Command::new("/bin/sh");
+        cmd.args(["-c", "/nonexistent/prog; /bin/true; /bin/true"])
+            .stderr(std::process::Stdio::null());
+        unsafe {
+            cmd.pre_exec(move || install_in_child(handoff_fd, false));
+        }
+        let mut child = cmd.spawn().unwrap();
+        supervisor.spawned();
+        assert!(!child.wait().unwrap().success());
+
+        let report = supervisor.finish();
+        assert_eq!(report.syscalls, None);
+        assert_eq!(
+            report.execs,
+            Some(ExecTally {
+                allowed: 2,
+                denied: 2
+            })
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "seccomp-native", target_arch = "x86_64"))]
+    fn test_exec_limit_covers_compat_entry_points() {
+        use crate::syscall_audit::{install_in_child, ExecTally, SupervisorOptions, SyscallAudit};
+        use std::os::unix::process::{CommandExt, ExitStatusExt};
+
+        if !SyscallAudit::supported() {
+            return;
+        }
+
+        // execve of /bin/true through `int $0x80` (i386) or with an x32 number;
+        // with a limit of 0 the supervisor must refuse it, so it returns
+        fn compat_execve(i386: bool) -> std::io::Result<()> {
+            unsafe {
+                // i386 syscalls only see the low 32 bits of the path pointer
+                let page = libc::mmap(
+                    std::ptr::null_mut(),
+                    4096,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_32BIT,
+                    -1,
+                    0,
+                );
+                if page == libc::MAP_FAILED {
+                    return Err(std::io::Error::last_os_error());
+                }
+                std::ptr::copy_nonoverlapping(b"/bin/true\0".as_ptr(), page as *mut u8, 10);
+                let ret: i64 = if i386 {
+                    let ret: i32;
+                    std::arch::asm!(
+                        "xchg {path}, rbx",
+                        "int 0x80",
+                        "xchg {path}, rbx",
+                        path = inout(reg) page as u64 => _,
+                        inlateout("eax") 11i32 => ret,
+                        in("ecx") 0u32,
+                        in("edx") 0u32,
+                        out("r8") _,
+                        out("r9") _,
+                        out("r10") _,
+                        out("r11") _,
+                    );
+                    ret.into()
+                } else {
+                    libc::syscall(0x4000_0000 | 520, page, 0usize, 0usize)
+                };
+                let errno = if ret < 0 {
+                    -ret as i32
+                } else {
+                    *libc::__errno_location()
+                };
+                Err(std::io::Error::from_raw_os_error(errno))
+            }
+        }
+
+        for i386 in [true, false] {
+            let options = SupervisorOptions {
+                record_syscalls: false,
+                exec_limit: Some(0),
+            };
+            let mut supervisor = SyscallAudit::start(options).unwrap();
+            let handoff_fd = supervisor.child_fd();
+            let mut cmd = std::process::Command::new("/bin/true");
+            unsafe {
+                cmd.pre_exec(move || {
+                    install_in_child(handoff_fd, false)?;
+                    compat_execve(i386)
+                });
+            }
+            match cmd.spawn() {
+                Err(e) => assert_eq!(e.raw_os_error(), Some(libc::EPERM)),
+                Ok(mut child) => {
+                    // Kernels built without i386 emulation fault on `int $0x80`
+                    let status = child.wait().unwrap();
+                    assert!(i386 && status.signal() == Some(libc::SIGSEGV));
+                    supervisor.finish();
+                    continue;
+                }
+            }
+            supervisor.spawned();
+            assert_eq!(
+                supervisor.finish().execs,
+                Some(ExecTally {
+                    allowed: 0,
+                    denied: 1
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_minimal_visibility_hides_host_paths() {
+        use crate::visibility::VisibleRoot;
+        use std::os::unix::process::CommandExt;
+
+        if skip_unless_root(false) {
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let workdir = dir.path().join("work");
+        std::fs::create_dir_all(&workdir).unwrap();
+        let root = VisibleRoot::plan(&dir.path().join("root"), &[], &workdir).unwrap();
+
+        let mut cmd = std::process::Command::new("/bin/sh");
+        cmd.args([
+            "-c",
+            "test -x /usr/bin/env && test ! -e /root && test ! -e /etc/passwd && echo ok > marker",
+        ]);
+        unsafe {
+            cmd.pre_exec(move || root.enter());
+        }
+        assert!(cmd.status().unwrap().success());
+        assert!(workdir.join("marker").exists());
+        // The root was only ever mounted in the child's namespace
+        assert_eq!(
+            std::fs::read_dir(dir.path().join("root")).unwrap().count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_image_root_is_copy_on_write() {
+        use crate::visibility::{VisibleRoot, DEFAULT_UPPER_SIZE};
+        use std::os::unix::process::CommandExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (image, root, workdir) = (
+            dir.path().join("image"),
+            dir.path().join("root"),
+            dir.path().join("work"),
+        );
+        std::fs::create_dir_all(image.join("etc")).unwrap();
+        std::fs::create_dir_all(&workdir).unwrap();
+        std::fs::write(image.join("etc/marker"), "from image\n").unwrap();
+        std::fs::write(dir.path().join("file"), "").unwrap();
+        let plan = |image: &std::path::Path| {
+            VisibleRoot::plan_overlay(&root, image, DEFAULT_UPPER_SIZE, &workdir)
+        };
+        assert!(plan(&dir.path().join("file")).is_err());
+        assert!(plan(&dir.path().join("missing")).is_err());
+
+        if skip_unless_root(false) {
+            return;
+        }
+
+        // The image holds `head` and the libraries it links against, nothing else
+        let ldd = std::process::Command::new("ldd")
+            .arg("/usr/bin/head")
+            .output()
+            .unwrap();
+        let libraries = String::from_utf8_lossy(&ldd.stdout)
+            .split_whitespace()
+            .filter(|word| word.starts_with('/'))
+            .map(std::path::PathBuf::from)
+            .collect::<Vec<_>>();
+        for binary in libraries.iter().chain([&"/usr/bin/head".into()]) {
+            let copy = image.join(binary.strip_prefix("/").unwrap());
+            std::fs::create_dir_all(copy.parent().unwrap()).unwrap();
+            std::fs::copy(binary, copy).unwrap();
+        }
+        let root_plan = plan(&image).unwrap();
+
+        let mut cmd = std::process::Command::new("/usr/bin/head");
+        cmd.args(["/etc/marker", "/etc/hostname"]);
+        unsafe {
+            cmd.pre_exec(move || root_plan.enter());
+        }
+        let output = cmd.output().unwrap();
+        // The image's file is there, the host's is not
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "==> /etc/marker <==\nfrom image\n"
+        );
+        assert!(String::from_utf8_lossy(&output.stderr).contains("/etc/hostname"));
+        // Directories the run created went to its own upper layer
+        assert!(!image.join("proc").exists());
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_network_modes() {
+        use crate::types::{IsolateConfig, NetworkMode};
+        use std::os::unix::process::CommandExt;
+
+        assert_eq!("loopback".parse(), Ok(NetworkMode::LoopbackOnly));
+        assert!("bridge".parse::<NetworkMode>().is_err());
+        // Boxes saved before network modes existed stay isolated
+        let mut saved = serde_json::to_value(IsolateConfig::default()).unwrap();
+        saved.as_object_mut().unwrap().remove("network_mode");
+        let saved: IsolateConfig = serde_json::from_value(saved).unwrap();
+        assert_eq!(saved.network_mode, NetworkMode::None);
+
+        if skip_unless_root(false) {
+            return;
+        }
+        // Interfaces present, then the errno of connecting to a local listener
+        let code = "import socket\n\
+                    print(*[l.split(':')[0].strip() for l in list(open('/proc/net/dev'))[2:]])\n\
+                    server = socket.socket()\n\
+                    server.bind(('127.0.0.1', 0))\n\
+                    server.listen()\n\
+                    print(socket.socket().connect_ex(server.getsockname()))";
+        let run = |mode: NetworkMode| {
+            let mut cmd = std::process::Command::new("/usr/bin/python3");
+            cmd.args(["-c", code]);
+            unsafe {
+                cmd.pre_exec(move || crate::namespace::enter_network(mode));
+            }
+            String::from_utf8(cmd.output().unwrap().stdout).unwrap()
+        };
+        assert_eq!(
+            run(NetworkMode::None),
+            format!("lo\n{}\n", libc::ENETUNREACH)
+        );
+        assert_eq!(run(NetworkMode::LoopbackOnly), "lo\n0\n");
+    }
+
+    #[test]
+    fn test_egress_allowlist() {
+        use crate::egress::{report_from_listing, ruleset, EgressNetwork, EgressRule};
+        use std::io::Write;
+        use std::os::unix::process::CommandExt;
+
+        let rule: EgressRule = "10.1.0.0/16:80-443".parse().unwrap();
+        assert!(rule.allows("10.1.200.3:443".parse().unwrap()));
+        assert!(!rule.allows("10.1.200.3:8080".parse().unwrap()));
+        assert!(!rule.allows("10.2.0.1:80".parse().unwrap()));
+        assert!(!rule.allows("[2001:db8::1]:80".parse().unwrap()));
+        assert_eq!(rule.to_string(), "10.1.0.0/16:80-443");
+        let v6: EgressRule = "2001:db8::/32:443".parse().unwrap();
+        assert!(v6.allows("[2001:db8:ff::1]:443".parse().unwrap()));
+        assert!("0.0.0.0/0"
+            .parse::<EgressRule>()
+            .unwrap()
+            .allows("1.2.3.4:1".parse().unwrap()));
+        for invalid in ["10.0.0.1", "10.0.0.0/33", "10.0.0.0/8:443-80", "host/8"] {
+            assert!(invalid.parse::<EgressRule>().is_err(), "{}", invalid);
+        }
+
+        // Each rule accepts and counts new connections; the rest are dropped
+        let open: EgressRule = "192.0.2.0/24".parse().unwrap();
+        let script = ruleset("rbxe7", "10.231.0.30".parse().unwrap(), &[rule, open]);
+        for line in [
+            "table inet rustbox_rbxe7 {",
+            "ip daddr 10.1.0.0/16 meta l4proto { tcp, udp } th dport 80-443 ct state new counter accept comment \"10.1.0.0/16:80-443\"",
+            "ip daddr 192.0.2.0/24 ct state new counter accept comment \"192.0.2.0/24\"",
+            "ct state new counter drop comment \"refused\"",
+            "iifname \"rbxe7\" jump allowlist",
+            "oifname \"rbxe7\" drop",
+            "ip saddr 10.231.0.30 oifname != \"rbxe7\" masquerade",
+        ] {
+            assert!(script.lines().any(|l| l == line), "{}\n{}", line, script);
+        }
+        let listing = r#"{"nftables": [
+            {"metainfo": {"json_schema_version": 1}},
+            {"rule": {"chain": "allowlist", "comment": "10.1.0.0/16:80-443",
+                      "expr": [{"counter": {"packets": 3, "bytes": 180}}, {"accept": null}]}},
+            {"rule": {"chain": "allowlist", "comment": "192.0.2.0/24",
+                      "expr": [{"counter": {"packets": 0, "bytes": 0}}, {"accept": null}]}},
+            {"rule": {"chain": "allowlist", "comment": "refused",
+                      "expr": [{"counter": {"packets": 2, "bytes": 120}}, {"drop": null}]}},
+            {"rule": {"chain": "allowlist", "expr": [{"drop": null}]}}
+        ]}"#;
+        let report = report_from_listing(listing);
+        assert_eq!(
+            report.allowed.into_iter().collect::<Vec<_>>(),
+            [("10.1.0.0/16:80-443".to_string(), 3)]
+        );
+        assert_eq!(report.refused, 2);
+
+        if skip_unless_root(false) {
+            return;
+        }
+        assert!(EgressNetwork::start(&[v6]).is_err());
+        let egress_links = || {
+            std::fs::read_dir("/sys/class/net")
+                .unwrap()
+                .filter(|entry| {
+                    let name = entry.as_ref().unwrap().file_name();
+                    name.to_string_lossy().starts_with("rbxe")
+                })
+                .count()
+        };
+        let links_before = egress_links();
+        let nft_installed = std::process::Command::new("nft")
+            .arg("--version")
+            .output()
+            .is_ok();
+        if !nft_installed {
+            // Without a filter there is no egress, and nothing is left behind
+            assert!(EgressNetwork::start(&[rule]).is_err());
+            assert_eq!(egress_links(), links_before);
+            skip("nft");
+            return;
+        }
+
+        // A host service only the allowlisted port leads to
+        let server = std::net::TcpListener::bind("0.0.0.0:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (mut stream, _) = server.accept().unwrap();
+            stream.write_all(b"from host\n").unwrap();
+        });
+        let allowed: EgressRule = format!("10.231.0.0/16:{}", port).parse().unwrap();
+        let network = EgressNetwork::start(&[allowed]).unwrap();
+        assert_eq!(egress_links(), links_before + 1);
+        let code = "import socket, struct, sys\n\
+                    route = [l.split() for l in open('/proc/net/route')][1:]\n\
+                    gateway = [r[2] for r in route if r[1] == '00000000'][0]\n\
+                    gateway = socket.inet_ntoa(struct.pack('<I', int(gateway, 16)))\n\
+                    for port in sys.argv[1:]:\n    \
+                    try:\n        \
+                    s = socket.create_connection((gateway, int(port)), timeout=1)\n        \
+                    print(s.makefile().read().strip())\n    \
+                    except OSError as e:\n        \
+                    print(type(e).__name__)";
+        let netns = network.netns_fd();
+        let mut cmd = std::process::Command::new("/usr/bin/python3");
+        cmd.args(["-c", code])
+            .arg(port.to_string())
+            .arg((port ^ 1).to_string());
+        unsafe {
+            cmd.pre_exec(move || crate::namespace::join_network(netns));
+        }
+        let output = String::from_utf8(cmd.output().unwrap().stdout).unwrap();
+        let report = network.finish();
+
+        // Connected on the allowed port; the other one is dropped, not refused
+        assert_eq!(output, "from host\nTimeoutError\n");
+        assert_eq!(
+            report.allowed.into_iter().collect::<Vec<_>>(),
+            [(allowed.to_string(), 1)]
+        );
+        assert!(report.refused >= 1);
+        assert_eq!(egress_links(), links_before);
+    }
+
+    #[test]
+    fn test_box_runs_as_its_own_user() {
+        use crate::config::{RustBoxConfig, DEFAULT_FIRST_UID};
+        use crate::isolate::CleanupPolicy;
+
+        if skip_unless_root(true) {
+            return;
+        }
+
+        let first_uid = RustBoxConfig::load_default()
+            .map(|config| config.isolate.first_uid)
+            .unwrap_or(DEFAULT_FIRST_UID);
+        let mut isolate = create_box(box_config(962));
+        assert_eq!(isolate.config().uid, Some(first_uid + 962));
+        assert_ne!(
+            isolate.config().workdir,
+            crate::paths::paths().scratch_dir()
+        );
+
+        // Dropped to the box user: writes its workdir, cannot signal root's processes
+        let command = python(
+            "import os\nopen('out', 'w').write('x')\ntry:\n    os.kill(1, 0)\nexcept PermissionError:\n    print(os.getuid())",
+        );
+        let result = isolate.execute(&command, None).unwrap();
+        isolate.finish(CleanupPolicy::Always).unwrap();
+        assert_eq!(result.stdout.trim(), (first_uid + 962).to_string());
+    }
+
+    #[test]
+    fn test_capabilities_dropped_before_exec() {
+        use crate::executor::ProcessExecutor;
+        use crate::types::IsolateConfig;
+
+        if skip_unless_root(false) {
+            return;
+        }
+
+        let (base, _workdir) = box_config_in_tempdir(979);
+        let status = |drop: bool| {
+            let config = IsolateConfig {
+                drop_capabilities: drop,
+                no_new_privileges: drop,
+                ..base.clone()
+            };
+            let mut executor = ProcessExecutor::new(config).unwrap();
+            // Still root: only the capability sets tell the runs apart
+            let command = python(
+                "print(''.join(l for l in open('/proc/self/status') if l.startswith(('Cap', 'NoNewPrivs'))))",
+            );
+            let result = executor.execute(&command, None).unwrap();
+            executor.cleanup().unwrap();
+            result
+                .stdout
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .map(|(name, value)| (name.to_string(), value.trim().to_string()))
+                .collect::<std::collections::HashMap<_, _>>()
+        };
+
+        let dropped = status(true);
+        for set in ["CapInh", "CapPrm", "CapEff", "CapBnd", "CapAmb"] {
+            assert_eq!(dropped[set], "0000000000000000", "{}", set);
+        }
+        assert_eq!(dropped["NoNewPrivs"], "1");
+
+        let kept = status(false);
+        assert_ne!(kept["CapEff"], "0000000000000000");
+        assert_eq!(kept["NoNewPrivs"], "0");
+    }
+
+    #[test]
+    fn test_landlock_confines_to_workdir() {
+        use crate::executor::ProcessExecutor;
+        use crate::types::IsolateConfig;
+
+        if !crate::landlock::supported() {
+            skip("landlock");
+            return;
+        }
+
+        let (config, workdir) = box_config_in_tempdir(980);
+        let readable = tempfile::tempdir().unwrap();
+        std::fs::write(readable.path().join("data"), "visible").unwrap();
+        let config = IsolateConfig {
+            landlock: true,
+            visible_paths: vec![readable.path().to_path_buf()],
+            ..config
+        };
+        let mut executor = ProcessExecutor::new(config).unwrap();
+        let script = format!(
+            "import os\nopen(os.path.join({:?}, 'out'), 'w').write('x')\nprint(open({:?}).read())\ntry:\n    open('/etc/passwd').read()\nexcept PermissionError:\n    print('denied')",
+            workdir.path(),
+            readable.path().join("data")
+        );
+        let result = executor.execute(&python(&script), None).unwrap();
+        executor.cleanup().unwrap();
+
+        assert!(result.success, "{:?}", result);
+        assert_eq!(result.stdout.trim(), "visible\ndenied");
+        assert!(workdir.path().join("out").exists());
+    }
+
+    #[test]
+    fn test_chroot_gets_read_only_system_binds() {
+        use crate::filesystem::FilesystemSecurity;
+        use std::path::PathBuf;
+
+        if skip_unless_root(false) {
+            return;
+        }
+
+        let chroot = tempfile::tempdir().unwrap();
+        let workdir = tempfile::tempdir().unwrap();
+        let filesystem = FilesystemSecurity::new(
+            Some(chroot.path().to_path_buf()),
+            workdir.path().to_path_buf(),
+            false,
+        );
+        let paths = [PathBuf::from("/usr"), PathBuf::from("/etc/ld.so.cache")];
+        filesystem.populate_rootfs(&paths).unwrap();
+        // Populating again leaves the existing mounts alone
+        filesystem.populate_rootfs(&paths).unwrap();
+
+        assert!(chroot.path().join("usr/bin").is_dir());
+        let written = std::fs::write(chroot.path().join("usr/rustbox-bind-test"), "x");
+        assert_eq!(written.unwrap_err().raw_os_error(), Some(libc::EROFS));
+
+        filesystem.unmount_rootfs().unwrap();
+        let usr = chroot.path().join("usr");
+        assert!(!usr.exists() || std::fs::read_dir(&usr).unwrap().next().is_none());
+    }
+
+    #[test]
+    fn test_directory_bindings_enforce_permissions() {
+        use crate::executor::ProcessExecutor;
+        use crate::filesystem::FilesystemSecurity;
+        use crate::types::{DirectoryBinding, IsolateConfig};
+        use std::os::unix::fs::PermissionsExt;
+
+        if skip_unless_root(false) {
+            return;
+        }
+
+        let (config, workdir) = box_config_in_tempdir(977);
+        let sources = tempfile::tempdir().unwrap();
+        let source = |name: &str| {
+            let dir = sources.path().join(name);
+            std::fs::create_dir(&dir).unwrap();
+            dir
+        };
+        let (ro, rw, noexec) = (source("ro"), source("rw"), source("noexec"));
+        std::fs::write(ro.join("input"), "data").unwrap();
+        std::fs::write(noexec.join("run.sh"), "#!/bin/sh\necho ran\n").unwrap();
+        std::fs::set_permissions(
+            noexec.join("run.sh"),
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+        let bindings: Vec<DirectoryBinding> = [
+            format!("{}=/ro:ro", ro.display()),
+            format!("{}=/rw:rw", rw.display()),
+            format!("{}=/noexec:noexec", noexec.display()),
+        ]
+        .iter()
+        .map(|binding| DirectoryBinding::parse(binding).unwrap())
+        .collect();
+
+        let filesystem = FilesystemSecurity::new(None, workdir.path().to_path_buf(), true);
+        filesystem.setup_directory_bindings(&bindings).unwrap();
+        // Setting up again, as every run does, reapplies the flags without stacking
+        filesystem.setup_directory_bindings(&bindings).unwrap();
+
+        let bound = workdir.path();
+        assert_eq!(
+            std::fs::read_to_string(bound.join("ro/input")).unwrap(),
+            "data"
+        );
+        let written = std::fs::write(bound.join("ro/output"), "x");
+        assert_eq!(written.unwrap_err().raw_os_error(), Some(libc::EROFS));
+        std::fs::write(bound.join("rw/output"), "x").unwrap();
+        assert!(rw.join("output").exists());
+        let executed = std::process::Command::new(bound.join("noexec/run.sh")).output();
+        assert_eq!(
+            executed.unwrap_err().kind(),
+            std::io::ErrorKind::PermissionDenied
+        );
+
+        // The bindings keep their flags inside the run's mount namespace
+        let config = IsolateConfig {
+            minimal_visibility: true,
+            ..config
+        };
+        let mut executor = ProcessExecutor::new(config).unwrap();
+        let script = format!(
+            "try:\n    open({:?}, 'w')\nexcept OSError as e:\n    print(e.errno)\nprint(open({:?}).read())",
+            bound.join("ro/output"),
+            bound.join("ro/input")
+        );
+        let result = executor.execute(&python(&script), None).unwrap();
+        executor.cleanup().unwrap();
+        assert!(result.success, "{:?}", result);
+        assert_eq!(result.stdout.trim(), format!("{}\ndata", libc::EROFS));
+
+        filesystem.unmount_directory_bindings(&bindings).unwrap();
+        assert!(!bound.join("ro/input").exists());
+        assert!(ro.join("input").exists());
+    }
+
+    #[test]
+    fn test_socket_bindings_leave_host_socket_alone() {
+        use crate::filesystem::FilesystemSecurity;
+        use crate::types::SocketBinding;
+        use std::os::unix::fs::PermissionsExt;
+
+        if skip_unless_root(false) {
+            return;
+        }
+
+        let workdir = tempfile::tempdir().unwrap();
+        let host = tempfile::tempdir().unwrap();
+        let source = host.path().join("service.sock");
+        let _listener = std::os::unix::net::UnixListener::bind(&source).unwrap();
+        std::fs::set_permissions(&source, std::fs::Permissions::from_mode(0o600)).unwrap();
+        let bindings = vec![SocketBinding {
+            source: source.clone(),
+            target: std::path::PathBuf::from("/run/service.sock"),
+        }];
+        let target = workdir.path().join("run/service.sock");
+        let mounts = || {
+            std::fs::read_to_string("/proc/self/mountinfo")
+                .unwrap()
+                .lines()
+                .filter(|line| line.split_whitespace().nth(4) == Some(target.to_str().unwrap()))
+                .count()
+        };
+
+        let filesystem = FilesystemSecurity::new(None, workdir.path().to_path_buf(), false);
+        filesystem
+            .setup_socket_bindings(&bindings, Some(65534), Some(65534))
+            .unwrap();
+        // Every run sets the bindings up again without stacking them
+        filesystem
+            .setup_socket_bindings(&bindings, Some(65534), Some(65534))
+            .unwrap();
+        assert_eq!(mounts(), 1);
+        let metadata = std::fs::metadata(&source).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert_eq!(std::os::unix::fs::MetadataExt::uid(&metadata), 0);
+
+        filesystem.unmount_socket_bindings(&bindings).unwrap();
+        assert_eq!(mounts(), 0);
+
+        // Strict mode refuses a socket the box user cannot connect to
+        let strict = FilesystemSecurity::new(None, workdir.path().to_path_buf(), true);
+        assert!(strict
+            .setup_socket_bindings(&bindings, Some(65534), Some(65534))
+            .is_err());
+        assert_eq!(mounts(), 0);
+
+        // A symlink planted at the target is not mounted through
+        std::fs::remove_file(&target).unwrap();
+        std::os::unix::fs::symlink(host.path().join("elsewhere"), &target).unwrap();
+        assert!(filesystem
+            .setup_socket_bindings(&bindings, None, None)
+            .is_err());
+        assert!(!host.path().join("elsewhere").exists());
+
+        // Nor one planted in place of a directory on the way
+        let redirect = host.path().join("redirect");
+        std::fs::create_dir(&redirect).unwrap();
+        std::fs::remove_dir_all(workdir.path().join("run")).unwrap();
+        std::os::unix::fs::symlink(&redirect, workdir.path().join("run")).unwrap();
+        assert!(filesystem
+            .setup_socket_bindings(&bindings, None, None)
+            .is_err());
+        assert!(!redirect.join("service.sock").exists());
+        assert_eq!(mounts(), 0);
+    }
+
+    #[test]
+    fn test_sandbox_file_write_does_not_follow_symlinks() {
+        use crate::filesystem::FilesystemSecurity;
+        use std::os::unix::fs::PermissionsExt;
+
+        let workdir = tempfile::tempdir().unwrap();
+        let host = tempfile::tempdir().unwrap();
+        let target = host.path().join("secret");
+        std::fs::write(&target, "host data").unwrap();
+        std::fs::set_permissions(&target, std::fs::Permissions::from_mode(0o600)).unwrap();
+        // Left behind by an earlier run in the box
+        let planted = workdir.path().join("source.py");
+        std::os::unix::fs::symlink(&target, &planted).unwrap();
+
+        let filesystem = FilesystemSecurity::new(None, workdir.path().to_path_buf(), true);
+        filesystem
+            .write_sandbox_file(&planted, b"print(1)", 0o022, None, None)
+            .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "host data");
+        let mode = std::fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let written = std::fs::symlink_metadata(&planted).unwrap();
+        assert!(written.file_type().is_file());
+        // Strict mode also masks "other"
+        assert_eq!(written.permissions().mode() & 0o777, 0o640);
+        assert_eq!(std::fs::read_to_string(&planted).unwrap(), "print(1)");
+    }
+
+    #[test]
+    fn test_environment_size_and_count_are_capped() {
+        use crate::security::env_validation::{validate_environment, MAX_ENV_BYTES, MAX_ENV_VARS};
+        use crate::types::IsolateError;
+
+        let var = |i: usize, value: String| (format!("VAR{}", i), value);
+        assert!(validate_environment(&[var(0, "x".to_string())]).is_ok());
+        assert!(validate_environment(&[("A=B".to_string(), String::new())]).is_err());
+        assert!(validate_environment(&[var(0, "a\0b".to_string())]).is_err());
+
+        let many: Vec<_> = (0..=MAX_ENV_VARS).map(|i| var(i, String::new())).collect();
+        assert!(matches!(
+            validate_environment(&many),
+            Err(IsolateError::Config(_))
+        ));
+        let large = vec![var(0, "x".repeat(MAX_ENV_BYTES))];
+        assert!(matches!(
+            validate_environment(&large),
+            Err(IsolateError::Config(_))
+        ));
+
+        // Refused before anything is spawned
+        let (mut config, _workdir) = config_in_tempdir();
+        config.environment = large;
+        config.admission_control = false;
+        let mut executor = crate::executor::ProcessExecutor::new(config).unwrap();
+        assert!(matches!(
+            executor.execute(&python("pass"), None),
+            Err(IsolateError::Config(_))
+        ));
+    }
+
     #[test]
     fn test_security_test_run() {
         let mut config = TestConfig::default();
@@ -670,7 +670,10 @@ mod tests {
         for result in results {
             eprintln!("Test result: {}", result.name);
             eprintln!("Test passed: {}", result.passed);
-            eprintln!("Test error message: {}", result.error_message.unwrap_or("None".to_string()));
+            eprintln!(
+                "Test error message: {}",
+                result.error_message.unwrap_or("None".to_string())
+            );
             assert!(result.passed);
         }
     }
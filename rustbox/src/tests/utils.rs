@@ -78,17 +78,22 @@ impl TestUtils {
             "Success",
             "TimeLimit",
             "MemoryLimit",
-            "TLE",  // Time Limit Exceeded (actual rustbox status)
-            "Memory Limit Exceeded",  // Memory Limit Exceeded (actual rustbox status)
+            "TLE",                   // Time Limit Exceeded (actual rustbox status)
+            "Memory Limit Exceeded", // Memory Limit Exceeded (actual rustbox status)
             "RuntimeError",
             "InternalError",
             "Signaled",
             "SecurityViolation",
             "ProcessLimit",
+            "ExecLimit",
             "FileSizeLimit",
             "StackLimit",
             "CoreLimit",
             "DiskQuotaExceeded",
+            "OutputLimit",
+            "IdleTimeout",
+            "Cancelled",
+            "CompileError",
         ];
 
         if !valid_statuses.contains(&status) {
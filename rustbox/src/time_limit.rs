@@ -0,0 +1,171 @@
+/// Time limits enforced by a watchdog thread with sub-second precision
+///
+/// RLIMIT_CPU counts whole seconds and the supervisor's wait loop is busy with
+/// memory, output and cancellation, so both limits are kept by a thread of
+/// their own. It reads the run's CPU time from the cpuacct cgroup
+/// (`cpuacct.usage`, in nanoseconds) and its wall time from the monotonic clock
+/// every few milliseconds, and SIGKILLs the process group and every task in
+/// the cgroup as soon as either limit plus the extra time is used up. A 1.5s
+/// limit is then a 1.5s limit to within a sample, which judges with 100ms
+/// limits rely on. The wait loop only reports the verdict. Time the run
+/// spends paused in the freezer does not count against its wall time limit.
+use crate::types::{IsolateError, Result};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Time between two samples of the run's CPU time
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(5);
+
+const RUNNING: u8 = 0;
+const CPU_EXPIRED: u8 = 1;
+const WALL_EXPIRED: u8 = 2;
+
+/// Which limit ended the run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expired {
+    Cpu,
+    Wall,
+}
+
+/// Limits a run is killed at, extra time included
+#[derive(Debug, Clone)]
+pub struct TimeLimits {
+    pub cpu: Option<Duration>,
+    pub wall: Duration,
+}
+
+/// What the watchdog reads and kills
+#[derive(Debug, Clone, Default)]
+pub struct Target {
+    /// Process group of the program
+    pub pgid: u32,
+    /// `cpuacct.usage` of the run's cgroup; without it only wall time is kept
+    pub cpu_usage_file: Option<PathBuf>,
+    /// cgroup `tasks` files, for processes that left the process group
+    pub task_files: Vec<PathBuf>,
+    /// `freezer.state` of the run's cgroup, to tell when it is paused
+    pub freezer_state: Option<PathBuf>,
+}
+
+/// Running watchdog; it stops when dropped
+pub struct TimeLimitWatch {
+    expired: Arc<AtomicU8>,
+    stop: Arc<AtomicBool>,
+    watcher: Option<JoinHandle<()>>,
+}
+
+impl TimeLimitWatch {
+    /// Watch `target` from `start`, the moment the program was spawned
+    ///
+    /// Fails if the watchdog thread cannot be started, as the program would
+    /// otherwise run without its time limits.
+    pub fn start(target: Target, limits: TimeLimits, start: Instant) -> Result<Self> {
+        let expired = Arc::new(AtomicU8::new(RUNNING));
+        let stop = Arc::new(AtomicBool::new(false));
+        let (verdict, stopped) = (Arc::clone(&expired), Arc::clone(&stop));
+        let watcher = std::thread::Builder::new()
+            .name("rustbox-time-limit".to_string())
+            .spawn(move || {
+                // The cgroup may carry CPU time of earlier runs in the box
+                let baseline = cpu_usage(&target).unwrap_or_default();
+                let (mut paused, mut last_sample) = (Duration::ZERO, Instant::now());
+                while !stopped.load(Ordering::Relaxed) {
+                    let now = Instant::now();
+                    if is_paused(&target) {
+                        paused += now - last_sample;
+                    }
+                    last_sample = now;
+                    let cpu_exceeded = limits.cpu.is_some_and(|limit| {
+                        cpu_usage(&target)
+                            .is_some_and(|usage| usage.saturating_sub(baseline) >= limit)
+                    });
+                    let elapsed = start.elapsed().saturating_sub(paused);
+                    let reason = if cpu_exceeded {
+                        CPU_EXPIRED
+                    } else if elapsed >= limits.wall {
+                        WALL_EXPIRED
+                    } else {
+                        std::thread::sleep(SAMPLE_INTERVAL.min(limits.wall - elapsed));
+                        continue;
+                    };
+                    verdict.store(reason, Ordering::SeqCst);
+                    kill(&target);
+                    break;
+                }
+            })
+            .map_err(|e| {
+                IsolateError::Process(format!("Failed to start the time limit watchdog: {}", e))
+            })?;
+        Ok(Self {
+            expired,
+            stop,
+            watcher: Some(watcher),
+        })
+    }
+
+    /// The limit that ended the run, once the watchdog has killed it
+    pub fn expired(&self) -> Option<Expired> {
+        match self.expired.load(Ordering::SeqCst) {
+            CPU_EXPIRED => Some(Expired::Cpu),
+            WALL_EXPIRED => Some(Expired::Wall),
+            _ => None,
+        }
+    }
+
+    /// Stop watching once the run is over and return the verdict
+    ///
+    /// Waits for the thread, so it cannot kill anything after this returns.
+    pub fn finish(&mut self) -> Option<Expired> {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(watcher) = self.watcher.take() {
+            let _ = watcher.join();
+        }
+        self.expired()
+    }
+}
+
+impl Drop for TimeLimitWatch {
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+/// CPU time the cgroup has used so far
+fn cpu_usage(target: &Target) -> Option<Duration> {
+    let usage = fs::read_to_string(target.cpu_usage_file.as_ref()?).ok()?;
+    usage.trim().parse().ok().map(Duration::from_nanos)
+}
+
+/// Whether the run's cgroup is frozen or freezing
+fn is_paused(target: &Target) -> bool {
+    target
+        .freezer_state
+        .as_ref()
+        .and_then(|state| fs::read_to_string(state).ok())
+        .is_some_and(|state| state.trim() != "THAWED")
+}
+
+/// SIGKILL the program, everything in its process group and its cgroup
+fn kill(target: &Target) {
+    unsafe {
+        libc::kill(-(target.pgid as i32), libc::SIGKILL);
+        libc::kill(target.pgid as i32, libc::SIGKILL);
+    }
+    for tasks in target.task_files.iter() {
+        let Ok(tasks) = fs::read_to_string(tasks) else {
+            continue;
+        };
+        for pid in tasks
+            .lines()
+            .filter_map(|line| line.trim().parse::<i32>().ok())
+        {
+            unsafe {
+                libc::kill(pid, libc::SIGKILL);
+            }
+        }
+    }
+}
@@ -1,7 +1,7 @@
 /// Core types and structures for the rustbox system
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, SystemTime};
 use thiserror::Error;
 
@@ -18,6 +18,68 @@ pub struct DirectoryBinding {
     pub maybe: bool,
     /// Create as temporary directory
     pub is_tmp: bool,
+    /// Show files owned by a host uid as another uid inside the sandbox (idmapped mount)
+    #[serde(default)]
+    pub uid_map: Option<IdRemap>,
+    /// Show files owned by a host gid as another gid inside the sandbox (idmapped mount)
+    #[serde(default)]
+    pub gid_map: Option<IdRemap>,
+}
+
+/// A single host id presented as a different id inside the sandbox
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdRemap {
+    /// Owner id as stored on the host filesystem
+    pub host: u32,
+    /// Id the sandbox sees (and writes as) instead
+    pub sandbox: u32,
+}
+
+impl IdRemap {
+    /// Parse `HOST-SANDBOX`, e.g. `1000-65534`
+    pub fn parse(value: &str) -> std::result::Result<Self, String> {
+        let (host, sandbox) = value
+            .split_once('-')
+            .ok_or_else(|| format!("Invalid id mapping '{}'. Use: HOST-SANDBOX", value))?;
+        let parse_id = |id: &str| {
+            id.trim()
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid id in mapping '{}'", value))
+        };
+        Ok(Self {
+            host: parse_id(host)?,
+            sandbox: parse_id(sandbox)?,
+        })
+    }
+}
+
+/// Network a sandboxed program gets
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkMode {
+    /// Own network namespace without any interface up
+    #[default]
+    None,
+    /// Own network namespace with only `lo` up, so localhost sockets work
+    LoopbackOnly,
+    /// The host's network
+    Full,
+}
+
+impl std::str::FromStr for NetworkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(NetworkMode::None),
+            "loopback" | "loopback_only" => Ok(NetworkMode::LoopbackOnly),
+            "full" => Ok(NetworkMode::Full),
+            other => Err(format!(
+                "Unknown network mode '{}' (expected none, loopback or full)",
+                other
+            )),
+        }
+    }
 }
 
 /// Directory access permissions
@@ -55,6 +117,8 @@ impl DirectoryBinding {
         let mut permissions = DirectoryPermissions::ReadOnly;
         let mut maybe = false;
         let mut is_tmp = false;
+        let mut uid_map = None;
+        let mut gid_map = None;
 
         for option in options.split(',') {
             match option.trim() {
@@ -64,7 +128,11 @@ impl DirectoryBinding {
                 "maybe" => maybe = true,
                 "tmp" => is_tmp = true,
                 "" => {} // Empty option
-                _ => return Err(format!("Unknown directory binding option: {}", option)),
+                option => match option.split_once('=') {
+                    Some(("uidmap", value)) => uid_map = Some(IdRemap::parse(value)?),
+                    Some(("gidmap", value)) => gid_map = Some(IdRemap::parse(value)?),
+                    _ => return Err(format!("Unknown directory binding option: {}", option)),
+                },
             }
         }
 
@@ -74,6 +142,8 @@ impl DirectoryBinding {
             permissions,
             maybe,
             is_tmp,
+            uid_map,
+            gid_map,
         })
     }
 
@@ -81,7 +151,7 @@ impl DirectoryBinding {
     pub fn parse_secure(binding_str: &str) -> crate::types::Result<Self> {
         use crate::security::path_validation;
         use crate::security_logging::events;
-        
+
         let parts: Vec<&str> = binding_str.split(':').collect();
         let path_part = parts[0];
         let options = if parts.len() > 1 { parts[1] } else { "" };
@@ -94,7 +164,10 @@ impl DirectoryBinding {
                         .to_string(),
                 ));
             }
-            (std::path::Path::new(path_parts[0]), std::path::Path::new(path_parts[1]))
+            (
+                std::path::Path::new(path_parts[0]),
+                std::path::Path::new(path_parts[1]),
+            )
         } else {
             // If no target specified, use same path in sandbox
             let path = std::path::Path::new(path_part);
@@ -102,18 +175,21 @@ impl DirectoryBinding {
         };
 
         // Use security validation for paths
-        let (validated_source, validated_target) = match path_validation::validate_directory_binding(source, target) {
-            Ok(paths) => paths,
-            Err(e) => {
-                // Log security event for path traversal attempt
-                events::path_traversal_attempt(binding_str.to_string(), None);
-                return Err(e);
-            }
-        };
+        let (validated_source, validated_target) =
+            match path_validation::validate_directory_binding(source, target) {
+                Ok(paths) => paths,
+                Err(e) => {
+                    // Log security event for path traversal attempt
+                    events::path_traversal_attempt(binding_str.to_string(), None);
+                    return Err(e);
+                }
+            };
 
         let mut permissions = DirectoryPermissions::ReadOnly;
         let mut maybe = false;
         let mut is_tmp = false;
+        let mut uid_map = None;
+        let mut gid_map = None;
 
         for option in options.split(',') {
             match option.trim() {
@@ -123,7 +199,24 @@ impl DirectoryBinding {
                 "maybe" => maybe = true,
                 "tmp" => is_tmp = true,
                 "" => {} // Empty option
-                _ => return Err(crate::types::IsolateError::Config(format!("Unknown directory binding option: {}", option))),
+                option => match option.split_once('=') {
+                    Some(("uidmap", value)) => {
+                        uid_map = Some(
+                            IdRemap::parse(value).map_err(crate::types::IsolateError::Config)?,
+                        )
+                    }
+                    Some(("gidmap", value)) => {
+                        gid_map = Some(
+                            IdRemap::parse(value).map_err(crate::types::IsolateError::Config)?,
+                        )
+                    }
+                    _ => {
+                        return Err(crate::types::IsolateError::Config(format!(
+                            "Unknown directory binding option: {}",
+                            option
+                        )))
+                    }
+                },
             }
         }
 
@@ -133,12 +226,231 @@ impl DirectoryBinding {
             permissions,
             maybe,
             is_tmp,
+            uid_map,
+            gid_map,
         })
     }
 }
 
+/// Host Unix socket exposed inside the sandbox
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SocketBinding {
+    /// Socket path on host system
+    pub source: PathBuf,
+    /// Socket path within sandbox
+    pub target: PathBuf,
+}
+
+impl SocketBinding {
+    /// Parse socket binding from string format like "host.sock=/sandbox/path.sock"
+    pub fn parse(binding_str: &str) -> crate::types::Result<Self> {
+        let (source, target) = match binding_str.split_once('=') {
+            Some((source, target)) if !source.is_empty() && !target.is_empty() => {
+                (Path::new(source), Path::new(target))
+            }
+            _ => {
+                return Err(IsolateError::Config(
+                    "Invalid socket binding format. Use: host.sock=/sandbox/path.sock".to_string(),
+                ))
+            }
+        };
+
+        let (source, target) =
+            crate::security::path_validation::validate_socket_binding(source, target)?;
+
+        Ok(SocketBinding { source, target })
+    }
+}
+
+/// One `--env` argument: `KEY=VALUE` sets a variable and a bare `KEY` passes
+/// the host's value through, like isolate's `-E`
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum EnvRule {
+    Set(String, String),
+    Preserve(String),
+}
+
+impl std::str::FromStr for EnvRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let rule = match s.split_once('=') {
+            Some((name, value)) => EnvRule::Set(name.to_string(), value.to_string()),
+            None => EnvRule::Preserve(s.to_string()),
+        };
+        match rule {
+            EnvRule::Set(ref name, _) | EnvRule::Preserve(ref name) if name.is_empty() => {
+                Err(format!("Missing variable name in '{}'", s))
+            }
+            rule => Ok(rule),
+        }
+    }
+}
+
+impl TryFrom<String> for EnvRule {
+    type Error = String;
+
+    fn try_from(value: String) -> std::result::Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// Window size of a run's terminal, `COLSxROWS` on the command line
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TtySize {
+    pub cols: u16,
+    pub rows: u16,
+}
+
+impl Default for TtySize {
+    fn default() -> Self {
+        Self { cols: 80, rows: 24 }
+    }
+}
+
+impl std::str::FromStr for TtySize {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || format!("Invalid terminal size '{}' (expected COLSxROWS)", s);
+        let (cols, rows) = s.split_once('x').ok_or_else(invalid)?;
+        let size = Self {
+            cols: cols.parse().map_err(|_| invalid())?,
+            rows: rows.parse().map_err(|_| invalid())?,
+        };
+        if size.cols == 0 || size.rows == 0 {
+            return Err(invalid());
+        }
+        Ok(size)
+    }
+}
+
+/// Time limits in requests: a number of seconds, with a fraction for
+/// sub-second limits (`1.5`)
+mod seconds {
+    use serde::{Deserialize, Deserializer};
+    use std::time::Duration;
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Option<Duration>, D::Error> {
+        Option::<f64>::deserialize(deserializer)?
+            .map(|secs| Duration::try_from_secs_f64(secs).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+/// Per-execution resource overrides supplied on the command line
+///
+/// CPU and wall clock limits are independent: `max_cpu`/`max_time` only ever
+/// touch the CPU limit and `max_wall_time` only the wall clock limit. Time
+/// limits are kept to the millisecond and read as fractional seconds.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ExecutionOverrides {
+    /// CPU time limit (takes precedence over `max_time`)
+    #[serde(deserialize_with = "seconds::deserialize")]
+    pub max_cpu: Option<Duration>,
+    /// Memory limit in MB
+    pub max_memory: Option<u64>,
+    /// Soft memory limit in MB
+    pub memory_high: Option<u64>,
+    /// Kernel memory (kmem) limit in MB
+    pub kernel_memory: Option<u64>,
+    /// Swap allowed on top of the memory limit in MB
+    pub max_swap: Option<u64>,
+    /// CPU bandwidth in percent of one core
+    pub cpu_quota_percent: Option<u32>,
+    /// CPU time limit (alias kept for `--time`)
+    #[serde(deserialize_with = "seconds::deserialize")]
+    pub max_time: Option<Duration>,
+    /// Wall clock time limit
+    #[serde(deserialize_with = "seconds::deserialize")]
+    pub max_wall_time: Option<Duration>,
+    /// Grace period before a process over its time limits is killed
+    #[serde(deserialize_with = "seconds::deserialize")]
+    pub extra_time: Option<Duration>,
+    /// File descriptor limit
+    pub fd_limit: Option<u64>,
+    /// Record sandbox writes with fanotify
+    pub audit_file_writes: bool,
+    /// Sample the peak RSS of every process in the box
+    pub track_process_memory: bool,
+    /// Record every syscall instead of enforcing anything (profile authoring)
+    pub audit_syscalls: bool,
+    /// Run foreign-architecture programs under qemu-user
+    pub emulate_foreign_arch: bool,
+    /// Attach build information to the result
+    pub build_info: bool,
+    /// Maximum number of exec calls, counting the one starting the program
+    pub max_execs: Option<u32>,
+    /// Maximum number of processes
+    pub max_processes: Option<u32>,
+    /// Kill CPU-bound runs that stay silent this many seconds past the CPU limit
+    pub adaptive_timeout: Option<u64>,
+    /// Combined stdout and stderr limit in KB
+    pub max_output: Option<u64>,
+    /// stdout limit in KB
+    pub max_stdout: Option<u64>,
+    /// stderr limit in KB
+    pub max_stderr: Option<u64>,
+    /// Batch warm-up runs, instead of the language's `time.warmup_runs`
+    pub warmup_runs: Option<u32>,
+    /// Variables set or passed through on top of the box's environment
+    pub env: Vec<EnvRule>,
+    /// Pass the host's whole environment through
+    pub full_env: bool,
+    /// File in the box to read stdin from
+    pub stdin_file: Option<PathBuf>,
+    /// File in the box to write stdout to
+    pub stdout_file: Option<PathBuf>,
+    /// File in the box to write stderr to
+    pub stderr_file: Option<PathBuf>,
+    /// Run the program on a pseudo-terminal
+    pub tty: bool,
+    /// Window size of that terminal
+    pub tty_size: Option<TtySize>,
+}
+
+/// Heuristic early kill for runs that are obviously stuck in a loop
+///
+/// Once a run has used `expected_time` of CPU (the CPU limit by default), it is
+/// killed with `TimeLimit` as soon as it has kept a core pegged while producing
+/// no output for `silence`, instead of being allowed its full extra time. Meant
+/// to reclaim worker time during mass regrades.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AdaptiveTimeout {
+    /// How long the run must be CPU-bound and silent before it is killed
+    pub silence: Duration,
+    /// Share of one core regarded as pegged, leaving room for scheduling noise
+    #[serde(default = "default_pegged_cpu")]
+    pub cpu_threshold: f64,
+    /// CPU time after which the heuristic may kill; defaults to the CPU limit
+    #[serde(default)]
+    pub expected_time: Option<Duration>,
+}
+
+fn default_pegged_cpu() -> f64 {
+    0.8
+}
+
+impl AdaptiveTimeout {
+    pub fn new(silence: Duration) -> Self {
+        Self {
+            silence,
+            cpu_threshold: default_pegged_cpu(),
+            expected_time: None,
+        }
+    }
+}
+
 /// Process isolation configuration
+///
+/// Fields missing from stored configurations take their defaults, so boxes
+/// registered by older versions still load.
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct IsolateConfig {
     /// Unique identifier for this isolation instance
     pub instance_id: String,
@@ -152,12 +464,26 @@ pub struct IsolateConfig {
     pub gid: Option<u32>,
     /// Memory limit in bytes
     pub memory_limit: Option<u64>,
+    /// Soft memory limit in bytes (`memory.soft_limit_in_bytes`): above it the
+    /// process is reclaimed first under memory pressure instead of killed, until
+    /// it reaches `memory_limit`
+    #[serde(default)]
+    pub memory_high: Option<u64>,
+    /// Swap the program may use on top of `memory_limit` in bytes; none when unset
+    #[serde(default)]
+    pub swap_limit: Option<u64>,
     /// Time limit for execution
     pub time_limit: Option<Duration>,
     /// CPU time limit
     pub cpu_time_limit: Option<Duration>,
     /// Wall clock time limit
     pub wall_time_limit: Option<Duration>,
+    /// Extra time granted past the CPU/wall limits before the process is killed
+    ///
+    /// A run that needs it is still a time limit, but keeps the output it
+    /// wrote up to the kill.
+    #[serde(default)]
+    pub extra_time: Option<Duration>,
     /// Maximum number of processes
     pub process_limit: Option<u32>,
     /// Maximum file size
@@ -168,28 +494,40 @@ pub struct IsolateConfig {
     pub core_limit: Option<u64>,
     /// File descriptor limit (max open files)
     pub fd_limit: Option<u64>,
-    /// Disk quota limit in bytes (filesystem-dependent)
+    /// Disk quota in bytes: the workdir becomes an ext4 image of this size for
+    /// the life of the box (needs root and a loop device)
     pub disk_quota: Option<u64>,
     /// Enable networking
     pub enable_network: bool,
-    /// Custom environment variables
+    /// Variables set for the program, replacing any inherited value
     pub environment: Vec<(String, String)>,
+    /// Host variables passed through to the program when set on the host
+    #[serde(default)]
+    pub preserve_env: Vec<String>,
+    /// Start from the host's whole environment instead of an empty one
+    #[serde(default)]
+    pub full_env: bool,
     /// Strict mode: fail hard if cgroups unavailable or permission denied
     pub strict_mode: bool,
     /// Inherit file descriptors from parent process
     #[serde(default)]
     pub inherit_fds: bool,
-    /// Redirect stdout to file (optional)
+    /// Write stdout to this file in the box (relative to the workdir) instead
+    /// of returning it; `stdout_limit` or `output_limit` caps its size
     pub stdout_file: Option<PathBuf>,
-    /// Redirect stderr to file (optional)
+    /// Write stderr to this file in the box, capped like `stdout_file`
     pub stderr_file: Option<PathBuf>,
-    /// Enable TTY support for interactive programs
+    /// Run the program on a pseudo-terminal (see `pty`); stdout then holds
+    /// everything the terminal showed and stderr is empty
     pub enable_tty: bool,
+    /// Window size of the terminal with `enable_tty`
+    #[serde(default)]
+    pub tty_size: TtySize,
     /// Use pipes for real-time I/O instead of files
     pub use_pipes: bool,
     /// Input data to send to stdin
     pub stdin_data: Option<String>,
-    /// Redirect stdin from file (optional)
+    /// Read stdin from this file in the box instead of `stdin_data`
     pub stdin_file: Option<PathBuf>,
     /// Buffer size for I/O operations (bytes)
     pub io_buffer_size: usize,
@@ -202,20 +540,164 @@ pub struct IsolateConfig {
     pub enable_user_namespace: bool,
     /// Directory bindings for filesystem access
     pub directory_bindings: Vec<DirectoryBinding>,
+    /// Host Unix sockets bound into the sandbox
+    #[serde(default)]
+    pub socket_bindings: Vec<SocketBinding>,
+    /// File mode creation mask for the sandboxed process
+    #[serde(default = "default_umask")]
+    pub umask: u32,
+    /// Check requested limits against free host resources before running
+    #[serde(default = "default_admission_control")]
+    pub admission_control: bool,
+    /// How long a run may wait for host resources before being rejected
+    #[serde(default = "default_admission_timeout")]
+    pub admission_timeout: Duration,
+    /// Host pressure (PSI) levels above which admission pauses
+    #[serde(default)]
+    pub pressure_thresholds: crate::admission::PressureThresholds,
+    /// Cap on kernel memory (pipes, epoll watches, sockets) in bytes;
+    /// defaults to the memory limit
+    #[serde(default)]
+    pub kernel_memory_limit: Option<u64>,
+    /// Cap on TCP socket buffer memory in bytes; defaults to the kernel memory cap
+    #[serde(default)]
+    pub tcp_memory_limit: Option<u64>,
+    /// Record every write under the sandbox directories (fanotify, needs root)
+    #[serde(default)]
+    pub audit_file_writes: bool,
+    /// Record every syscall the program makes to build a seccomp profile (slow)
+    #[serde(default)]
+    pub audit_syscalls: bool,
+    /// Attach build information to every result
+    #[serde(default)]
+    pub report_build_info: bool,
+    /// Maximum number of exec calls in the run phase, counting the one starting
+    /// the program (seccomp user notification); unlimited when unset
+    #[serde(default)]
+    pub exec_limit: Option<u32>,
+    /// Early kill of runs that are obviously looping; off by default
+    #[serde(default)]
+    pub adaptive_timeout: Option<AdaptiveTimeout>,
+    /// How many recent executions the box keeps in its history
+    #[serde(default)]
+    pub history: crate::history::HistoryPolicy,
+    /// Hide the host filesystem except the runtime directories and the workdir
+    /// (runs without a chroot only)
+    #[serde(default)]
+    pub minimal_visibility: bool,
+    /// Extra host paths shown read-only in minimal visibility mode, or
+    /// readable under Landlock
+    #[serde(default)]
+    pub visible_paths: Vec<PathBuf>,
+    /// Directory holding a prebuilt root filesystem; runs see it through an
+    /// overlayfs whose writable layer is a per-run tmpfs (no chroot needed)
+    #[serde(default)]
+    pub rootfs_image: Option<PathBuf>,
+    /// Size in bytes of the writable layer over `rootfs_image`
+    #[serde(default)]
+    pub rootfs_upper_size: Option<u64>,
+    /// Combined stdout and stderr limit in bytes; the run is stopped with
+    /// `OutputLimit` once it writes more
+    #[serde(default)]
+    pub output_limit: Option<u64>,
+    /// stdout limit in bytes; past it the run is stopped with `OutputLimit`
+    /// and stdout is kept cut at the limit
+    #[serde(default)]
+    pub stdout_limit: Option<u64>,
+    /// stderr limit in bytes, enforced like `stdout_limit`
+    #[serde(default)]
+    pub stderr_limit: Option<u64>,
+    /// Run foreign-architecture ELF programs under qemu-user
+    #[serde(default)]
+    pub emulate_foreign_arch: bool,
+    /// Mount the workdir as a tmpfs of this many bytes for the life of the box,
+    /// bounding what the program can write and discarding it on cleanup
+    #[serde(default)]
+    pub workdir_tmpfs_size: Option<u64>,
+    /// Network of the program; the host's when `enable_network_namespace` is off
+    #[serde(default)]
+    pub network_mode: NetworkMode,
+    /// IPv4 destinations the program may connect to through the egress
+    /// network; none when empty (needs an isolated network)
+    #[serde(default)]
+    pub egress_allowlist: Vec<crate::egress::EgressRule>,
+    /// CPUs the program runs on, through the cpuset controller and
+    /// sched_setaffinity; any CPU when unset
+    #[serde(default)]
+    pub cpu_affinity: Option<Vec<usize>>,
+    /// CPU bandwidth in percent of one core (200 = two cores); past it the
+    /// program is throttled until the next period instead of killed
+    #[serde(default)]
+    pub cpu_quota_percent: Option<u32>,
+    /// Sample the peak RSS of every process in the box and report the largest
+    /// next to the cgroup peak
+    #[serde(default)]
+    pub track_process_memory: bool,
+    /// Empty the capability bounding and ambient sets and drop every capability
+    /// before exec, so even a program left running as root holds none
+    #[serde(default = "default_drop_privileges")]
+    pub drop_capabilities: bool,
+    /// Set `PR_SET_NO_NEW_PRIVS` before exec, so setuid binaries and file
+    /// capabilities grant the program nothing
+    #[serde(default = "default_drop_privileges")]
+    pub no_new_privileges: bool,
+    /// Confine the program to its workdir and read-only runtime paths with
+    /// Landlock (runs without a chroot or image root only)
+    #[serde(default)]
+    pub landlock: bool,
+    /// Host paths bound read-only (nosuid, nodev) into `chroot_dir` at the same
+    /// locations before each run, so runtimes work inside the chroot
+    #[serde(default = "default_chroot_bind_paths")]
+    pub chroot_bind_paths: Vec<PathBuf>,
+    /// Run the program under a small reaper that adopts its orphans, reaps
+    /// them and forwards signals to it; takes one slot of the process limit
+    #[serde(default = "default_subreaper")]
+    pub subreaper: bool,
+}
+
+/// Default umask: no write for group, nothing for others
+fn default_umask() -> u32 {
+    0o027
+}
+
+fn default_admission_control() -> bool {
+    true
+}
+
+fn default_drop_privileges() -> bool {
+    true
+}
+
+fn default_subreaper() -> bool {
+    true
+}
+
+pub(crate) fn default_chroot_bind_paths() -> Vec<PathBuf> {
+    crate::filesystem::DEFAULT_CHROOT_BIND_PATHS
+        .iter()
+        .map(PathBuf::from)
+        .collect()
+}
+
+fn default_admission_timeout() -> Duration {
+    Duration::from_secs(30)
 }
 
 impl Default for IsolateConfig {
     fn default() -> Self {
         Self {
             instance_id: uuid::Uuid::new_v4().to_string(),
-            workdir: std::env::temp_dir().join("rustbox"),
+            workdir: crate::paths::paths().scratch_dir().to_path_buf(),
             chroot_dir: None,
             uid: None,
             gid: None,
             memory_limit: Some(256 * 1024 * 1024), // 128MB default
+            memory_high: None,
+            swap_limit: None,
             time_limit: Some(Duration::from_secs(10)),
             cpu_time_limit: Some(Duration::from_secs(10)),
             wall_time_limit: Some(Duration::from_secs(20)),
+            extra_time: None,
             process_limit: Some(1),
             file_size_limit: Some(64 * 1024 * 1024), // 64MB
             stack_limit: Some(8 * 1024 * 1024),      // 8MB default stack
@@ -224,11 +706,14 @@ impl Default for IsolateConfig {
             disk_quota: None,   // No disk quota by default
             enable_network: false,
             environment: Vec::new(),
+            preserve_env: Vec::new(),
+            full_env: false,
             strict_mode: false,
             inherit_fds: false,
             stdout_file: None,
             stderr_file: None,
             enable_tty: false,
+            tty_size: TtySize::default(),
             use_pipes: false,
             stdin_data: None,
             stdin_file: None,
@@ -239,10 +724,117 @@ impl Default for IsolateConfig {
             enable_network_namespace: true,
             enable_user_namespace: false, // User namespace can be complex, disabled by default
             directory_bindings: Vec::new(),
+            socket_bindings: Vec::new(),
+            umask: default_umask(),
+            admission_control: default_admission_control(),
+            admission_timeout: default_admission_timeout(),
+            pressure_thresholds: crate::admission::PressureThresholds::default(),
+            kernel_memory_limit: None,
+            tcp_memory_limit: None,
+            audit_file_writes: false,
+            audit_syscalls: false,
+            report_build_info: false,
+            exec_limit: None,
+            adaptive_timeout: None,
+            history: crate::history::HistoryPolicy::default(),
+            minimal_visibility: false,
+            visible_paths: Vec::new(),
+            rootfs_image: None,
+            rootfs_upper_size: None,
+            output_limit: None,
+            stdout_limit: None,
+            stderr_limit: None,
+            emulate_foreign_arch: false,
+            workdir_tmpfs_size: None,
+            network_mode: NetworkMode::None,
+            egress_allowlist: Vec::new(),
+            cpu_affinity: None,
+            cpu_quota_percent: None,
+            track_process_memory: false,
+            drop_capabilities: true,
+            no_new_privileges: true,
+            landlock: false,
+            chroot_bind_paths: default_chroot_bind_paths(),
+            subreaper: true,
         }
     }
 }
 
+impl IsolateConfig {
+    /// Reject limits that cannot work together
+    ///
+    /// Run before a box is created and before every execution, so a bad
+    /// combination is reported as such instead of failing halfway through
+    /// setting up cgroups, mounts or rlimits.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(IsolateError::Config(message));
+        if let Some(fd_limit) = self.fd_limit.filter(|&limit| limit < 3) {
+            return invalid(format!(
+                "fd_limit {} is below 3: the program needs stdin, stdout and stderr",
+                fd_limit
+            ));
+        }
+        if self.process_limit == Some(0) {
+            return invalid("process_limit is 0: the program itself is a process".to_string());
+        }
+        if let Some(memory_limit) = self.memory_limit.filter(|&limit| limit < 1024 * 1024) {
+            return invalid(format!(
+                "memory_limit of {} bytes is below the 1 MB minimum",
+                memory_limit
+            ));
+        }
+        for (name, limit) in [
+            ("cpu_time_limit", self.cpu_time_limit),
+            ("wall_time_limit", self.wall_time_limit),
+        ] {
+            if limit.is_some_and(|limit| limit.is_zero()) {
+                return invalid(format!("{} is 0: nothing could run", name));
+            }
+        }
+        if let Some(memory_limit) = self.memory_limit {
+            if let Some(stack_limit) = self.stack_limit.filter(|&stack| stack > memory_limit) {
+                return invalid(format!(
+                    "stack_limit of {} bytes exceeds memory_limit of {} bytes",
+                    stack_limit, memory_limit
+                ));
+            }
+            if let Some(memory_high) = self.memory_high.filter(|&high| high > memory_limit) {
+                return invalid(format!(
+                    "memory_high of {} bytes exceeds memory_limit of {} bytes",
+                    memory_high, memory_limit
+                ));
+            }
+        } else if self.swap_limit.is_some() {
+            return invalid("swap_limit needs a memory_limit".to_string());
+        }
+        if self.adaptive_timeout.is_some() && self.enable_tty {
+            return invalid("adaptive_timeout needs a batch run, not a terminal".to_string());
+        }
+        if let (Some(tmpfs), Some(quota)) = (self.workdir_tmpfs_size, self.disk_quota) {
+            if tmpfs > quota {
+                return invalid(format!(
+                    "workdir_tmpfs_size of {} bytes exceeds disk_quota of {} bytes",
+                    tmpfs, quota
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Kernel memory and TCP buffer caps in bytes, if any
+    ///
+    /// Without an explicit `kernel_memory_limit` the kmem cap is the memory
+    /// limit, so kernel allocations alone can never take more than the program
+    /// may use; the TCP cap in turn defaults to the kmem cap.
+    pub fn kernel_memory_limits(&self) -> Option<(u64, u64)> {
+        let kernel_memory = self.kernel_memory_limit.or(self.memory_limit)?;
+        Some((
+            kernel_memory,
+            self.tcp_memory_limit.unwrap_or(kernel_memory),
+        ))
+    }
+}
+
 /// Execution result from an isolated process
 #[derive(Default, Clone, Debug, Serialize, Deserialize)]
 pub struct ExecutionResult {
@@ -266,9 +858,267 @@ pub struct ExecutionResult {
     pub success: bool,
     /// Additional error message
     pub error_message: Option<String>,
+    /// Judging phase that produced this result
+    #[serde(default)]
+    pub phase: ExecutionPhase,
+    /// Time spent stalled on memory (PSI) while running, in microseconds; only
+    /// reported by kernels booted with `psi_v1=1`
+    #[serde(default)]
+    pub memory_stall_us: Option<u64>,
+    /// Peak kernel memory (kmem) usage in bytes, reported separately from `memory_peak`
+    #[serde(default)]
+    pub kernel_memory_peak: Option<u64>,
+    /// Writes observed under the sandbox when write auditing is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_audit: Option<crate::file_audit::FileAuditLog>,
+    /// Syscalls observed when syscall auditing is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub syscall_audit: Option<crate::syscall_audit::SyscallAuditLog>,
+    /// Binary that produced the result, when build reporting is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<crate::build_info::BuildInfo>,
+    /// Programs and processes started, when an exec limit is set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spawns: Option<SpawnReport>,
+    /// Compiler stdout and stderr, for compiled languages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compile_output: Option<String>,
+    /// `stdout` is not everything the program wrote
+    #[serde(default)]
+    pub stdout_truncated: bool,
+    /// `stderr` is not everything the program wrote
+    #[serde(default)]
+    pub stderr_truncated: bool,
+    /// How a foreign-architecture program was emulated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub emulation: Option<crate::emulation::Emulation>,
+    /// Connections opened through the egress network
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub egress: Option<crate::egress::EgressReport>,
+    /// Largest peak RSS of a single process, next to the cgroup-wide `memory_peak`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub process_memory: Option<crate::process_memory::ProcessMemoryReport>,
+    /// Limits the run was actually held to, and which one ended it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<EffectiveLimits>,
+    /// CPU split, context switches, page faults and block I/O of the run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_detail: Option<ResourceDetail>,
+}
+
+/// Exec calls and refused forks of a run
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct SpawnReport {
+    /// Exec calls let through, including the one starting the program
+    pub execs: u64,
+    /// Exec calls refused because the exec limit was reached
+    pub execs_denied: u64,
+    /// Forks refused by the process limit (`pids.events`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forks_denied: Option<u64>,
+}
+
+/// Limits a run was held to after CLI flags, config.json and the language
+/// profile were combined; unset when not enforced (e.g. without cgroups)
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct EffectiveLimits {
+    /// Memory limit in bytes
+    pub memory: Option<u64>,
+    /// CPU time limit in seconds
+    pub cpu_time: Option<f64>,
+    /// Wall clock time limit in seconds
+    pub wall_time: Option<f64>,
+    /// Process limit
+    pub processes: Option<u32>,
+    /// Open file descriptor limit
+    pub fds: Option<u64>,
+    /// Limit that terminated the run, if one did
+    pub triggered: Option<LimitKind>,
+}
+
+/// Finer-grained usage of a run for benchmarking, from the box cgroup or
+/// else the program's own usage; unset when neither provides a value
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct ResourceDetail {
+    /// CPU seconds spent in user mode
+    pub user_time: Option<f64>,
+    /// CPU seconds spent in the kernel on the run's behalf
+    pub system_time: Option<f64>,
+    /// Times a process gave up the CPU to wait (I/O, sleep, locks); only
+    /// counts processes that were waited for
+    pub voluntary_context_switches: Option<u64>,
+    /// Times a process was preempted; only counts processes that were waited for
+    pub involuntary_context_switches: Option<u64>,
+    /// Page faults served without reading from disk
+    pub minor_page_faults: Option<u64>,
+    /// Page faults that had to read from disk
+    pub major_page_faults: Option<u64>,
+    /// Bytes read from block devices
+    pub block_read_bytes: Option<u64>,
+    /// Bytes written to block devices
+    pub block_write_bytes: Option<u64>,
+}
+
+/// A limit that can end a run
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitKind {
+    Memory,
+    CpuTime,
+    WallTime,
+    Processes,
+    Execs,
+    FileSize,
+    Stack,
+    Core,
+    DiskQuota,
+    Output,
+    Idle,
+}
+
+/// What a registered box is doing, as seen from any process
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BoxState {
+    /// Set up, with nothing running and nobody holding it
+    Idle,
+    /// Held by a process, e.g. being set up, compiled in or cleaned up
+    Locked,
+    /// An execution is running
+    Running,
+    /// An execution is frozen by `pause`
+    Paused,
+}
+
+/// One registered box with its owner and limits (`Isolate::summary`)
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct InstanceSummary {
+    pub instance_id: String,
+    pub state: BoxState,
+    /// User and group the box's programs run as
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used: chrono::DateTime<chrono::Utc>,
+    /// Memory limit in bytes
+    pub memory_limit: Option<u64>,
+    /// CPU time limit in seconds
+    pub time_limit: Option<f64>,
+    /// Wall clock limit in seconds
+    pub wall_time_limit: Option<f64>,
+    pub process_limit: Option<u32>,
+}
+
+/// Resource usage of an execution while it runs (`Isolate::stats`)
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LiveStats {
+    /// Phase the running execution belongs to
+    pub phase: ExecutionPhase,
+    /// Memory the box uses now, in bytes
+    pub memory_usage: u64,
+    /// CPU time consumed so far, in seconds
+    pub cpu_time: f64,
+    /// Processes in the box
+    pub processes: usize,
+    /// Seconds since the program started
+    pub wall_time: f64,
+    /// Seconds left before the wall clock limit (with extra time) kills it
+    pub wall_time_remaining: f64,
+}
+
+/// Results of one program run against several test inputs
+#[derive(Clone, Debug)]
+pub struct BatchResult {
+    /// First failing test's status in input order, `CompileError`, or `Success`
+    pub verdict: ExecutionStatus,
+    /// The failed build, when the program never ran
+    pub compile_error: Option<ExecutionResult>,
+    /// One result per input, in input order
+    pub tests: Vec<ExecutionResult>,
+    /// Warm-up runs on the first input, left out of the verdict and timings
+    pub warmup: Vec<ExecutionResult>,
+}
+
+impl BatchResult {
+    pub fn from_results(tests: Vec<ExecutionResult>) -> Self {
+        let verdict = tests
+            .iter()
+            .find(|result| !result.success)
+            .map_or(ExecutionStatus::Success, |result| result.status.clone());
+        Self {
+            verdict,
+            compile_error: None,
+            tests,
+            warmup: Vec::new(),
+        }
+    }
+
+    pub fn compile_failed(compile_error: ExecutionResult) -> Self {
+        Self {
+            verdict: ExecutionStatus::CompileError,
+            compile_error: Some(compile_error),
+            tests: Vec::new(),
+            warmup: Vec::new(),
+        }
+    }
+
+    /// Number of tests that ran successfully
+    pub fn passed(&self) -> usize {
+        self.tests.iter().filter(|result| result.success).count()
+    }
+
+    /// JSON object printed by `rustbox run-batch`
+    pub fn to_cli_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "verdict": self.verdict.label(),
+            "passed": self.passed(),
+            "total": self.tests.len(),
+            "max_cpu_time": self.tests.iter().map(|result| result.cpu_time).fold(0.0, f64::max),
+            "max_memory_peak_kb": self.tests.iter().map(|result| result.memory_peak / 1024).max(),
+            "total_wall_time": self.tests.iter().map(|result| result.wall_time).sum::<f64>(),
+            "compile_error": self.compile_error.as_ref().map(ExecutionResult::to_cli_json),
+            "tests": self.tests.iter().map(ExecutionResult::to_cli_json).collect::<Vec<_>>(),
+            "warmup": self.warmup.iter().map(ExecutionResult::to_cli_json).collect::<Vec<_>>()
+        })
+    }
+}
+
+/// Phase of the judging pipeline an execution belongs to
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionPhase {
+    /// Compiling the submission
+    Compile,
+    /// Running the submission
+    #[default]
+    Run,
+    /// Running the checker/validator against the submission's output
+    Checker,
 }
 
 /// Status of process execution
+///
+/// Every execution ends in exactly one verdict:
+///
+/// ```text
+/// submitted ──> [Cancelled]                 cancelled before or while running
+///     │
+///     ├─ compile ──> [CompileError]          compiled languages only
+///     │
+///     └─ running ──> [Success]               exit code 0
+///                ├─> [RuntimeError]          non-zero exit code
+///                ├─> [Signaled]              killed by a signal we did not send
+///                ├─> [TimeLimit]             CPU or wall clock limit
+///                ├─> [IdleTimeout]           no CPU progress for too long
+///                ├─> [MemoryLimit] / [ProcessLimit] / [ExecLimit] / [FileSizeLimit] /
+///                │   [StackLimit] / [CoreLimit] / [DiskQuotaExceeded] /
+///                │   [OutputLimit]           resource limits
+///                ├─> [SecurityViolation]     forbidden operation attempted
+///                └─> [InternalError]         failure inside rustbox itself
+/// ```
+///
+/// `Unknown` is never produced by rustbox; it lets older consumers deserialize
+/// verdicts added by newer versions instead of failing.
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub enum ExecutionStatus {
     /// Process completed successfully
@@ -287,6 +1137,8 @@ pub enum ExecutionStatus {
     SecurityViolation,
     /// Process limit exceeded
     ProcessLimit,
+    /// More exec calls than the exec limit allows
+    ExecLimit,
     /// File size limit exceeded
     FileSizeLimit,
     /// Stack limit exceeded
@@ -295,6 +1147,38 @@ pub enum ExecutionStatus {
     CoreLimit,
     /// Disk quota exceeded
     DiskQuotaExceeded,
+    /// Process produced more output than allowed
+    OutputLimit,
+    /// Process made no progress for too long (e.g. blocked on input)
+    IdleTimeout,
+    /// Execution was cancelled before it completed
+    Cancelled,
+    /// Source failed to compile; the program never ran
+    CompileError,
+    /// Verdict not known to this version of rustbox
+    #[serde(other)]
+    Unknown,
+}
+
+impl ExecutionStatus {
+    /// Short label used in CLI output (`TLE`, `Success`, ...)
+    pub fn label(&self) -> String {
+        match self {
+            ExecutionStatus::TimeLimit => "TLE".to_string(),
+            ExecutionStatus::MemoryLimit => "Memory Limit Exceeded".to_string(),
+            other => format!("{:?}", other),
+        }
+    }
+
+    /// Parse a CLI label or a serialized status name
+    pub fn from_label(label: &str) -> Self {
+        match label {
+            "TLE" => ExecutionStatus::TimeLimit,
+            "Memory Limit Exceeded" => ExecutionStatus::MemoryLimit,
+            other => serde_json::from_value(serde_json::Value::String(other.to_string()))
+                .unwrap_or(ExecutionStatus::Unknown),
+        }
+    }
 }
 
 /// Custom error types for rustbox
@@ -327,6 +1211,12 @@ pub enum IsolateError {
     #[error("Resource limit error: {0}")]
     ResourceLimit(String),
 
+    #[error("Host resources unavailable: {0}")]
+    ResourceUnavailable(String),
+
+    #[error("Data fetch error: {0}")]
+    Fetch(String),
+
     #[error("Advanced lock error: {0}")]
     AdvancedLock(LockError),
 }
@@ -360,6 +1250,9 @@ pub enum LockError {
 
     #[error("Lock manager not initialized")]
     NotInitialized,
+
+    #[error("Lost the lock on box {box_id}: {details}")]
+    HeartbeatFailed { box_id: u32, details: String },
 }
 
 /// Convert lock errors to appropriate exit codes
@@ -373,6 +1266,7 @@ impl From<LockError> for i32 {
             LockError::CorruptedLock { .. } => 75,    // Data error
             LockError::SystemError { .. } => 1,       // General error
             LockError::NotInitialized => 1,           // General error
+            LockError::HeartbeatFailed { .. } => 1,   // General error
         }
     }
 }
@@ -421,6 +1315,69 @@ pub struct LockMetrics {
 
 /// Result type alias for rustbox operations
 pub type Result<T> = std::result::Result<T, IsolateError>;
+impl ExecutionResult {
+    /// JSON object printed by the CLI and returned by `rustbox serve`
+    pub fn to_cli_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "status": self.status.label(),
+            "status_code": self.isolate_status(),
+            "exit_code": self.exit_code,
+            "stdout": self.stdout,
+            "stderr": self.stderr,
+            "wall_time": self.wall_time,
+            "cpu_time": self.cpu_time,
+            "memory_peak_kb": self.memory_peak / 1024,
+            "success": self.success,
+            "signal": self.signal,
+            "error_message": self.error_message,
+            "phase": self.phase,
+            "memory_stall_us": self.memory_stall_us,
+            "kernel_memory_peak_kb": self.kernel_memory_peak.map(|bytes| bytes / 1024),
+            "file_audit": self.file_audit,
+            "syscall_audit": self.syscall_audit,
+            "build": self.build,
+            "spawns": self.spawns,
+            "compile_output": self.compile_output,
+            "stdout_truncated": self.stdout_truncated,
+            "stderr_truncated": self.stderr_truncated,
+            "emulation": self.emulation,
+            "egress": self.egress,
+            "process_memory": self.process_memory,
+            "limits": self.limits,
+            "resource_detail": self.resource_detail
+        })
+    }
+
+    /// Isolate's meta status for this result: `OK`, `TO`, `SG`, `RE` or `XX`
+    ///
+    /// Isolate only writes a status for failed runs; `OK` stands in for its
+    /// absence so every result has one.
+    pub fn isolate_status(&self) -> &'static str {
+        match self.status {
+            ExecutionStatus::Success => "OK",
+            ExecutionStatus::TimeLimit | ExecutionStatus::IdleTimeout => "TO",
+            ExecutionStatus::InternalError
+            | ExecutionStatus::Cancelled
+            | ExecutionStatus::Unknown => "XX",
+            // Out of memory is a SIGKILL from the kernel, as isolate reports it
+            ExecutionStatus::MemoryLimit => "SG",
+            _ if self.signal.is_some() => "SG",
+            _ => "RE",
+        }
+    }
+
+    /// Contents of an isolate `--meta` file: one `key:value` line per entry
+    ///
+    /// Written by `--meta` so CMS and DOMjudge integrations can read rustbox
+    /// results unchanged; see `report::cms_meta` for the keys.
+    pub fn to_isolate_meta(&self) -> String {
+        crate::report::cms_meta(self)
+            .iter()
+            .map(|(key, value)| format!("{}:{}\n", key, value))
+            .collect()
+    }
+}
+
 impl From<std::process::Output> for ExecutionResult {
     fn from(output: std::process::Output) -> Self {
         let status = if output.status.success() {
@@ -450,6 +1407,21 @@ impl From<std::process::Output> for ExecutionResult {
             },
             success: output.status.success(),
             error_message: None,
+            phase: ExecutionPhase::Run,
+            memory_stall_us: None,
+            kernel_memory_peak: None,
+            file_audit: None,
+            syscall_audit: None,
+            build: None,
+            spawns: None,
+            compile_output: None,
+            stdout_truncated: false,
+            stderr_truncated: false,
+            emulation: None,
+            egress: None,
+            process_memory: None,
+            limits: None,
+            resource_detail: None,
         }
     }
 }
@@ -0,0 +1,479 @@
+/// Minimal host filesystem view for runs without a chroot image
+///
+/// Without a chroot the sandboxed program sees the whole host filesystem. In
+/// minimal visibility mode the child builds a fresh root on a tmpfs in its own
+/// mount namespace, binds only the runtime directories (read-only), the working
+/// directory and a few device nodes into it, mounts a restricted `/proc`, then
+/// pivots into it and detaches the host tree.
+///
+/// A root can also be built from a prebuilt image as an overlayfs: the image
+/// is the read-only lower layer and a per-run tmpfs the writable upper layer,
+/// so programs may change anything in the image without affecting other runs.
+/// The tmpfs lives only in the child's mount namespace and is gone with it.
+///
+/// Everything is resolved into C strings before fork, so the child only makes
+/// raw syscalls between fork and exec.
+use crate::types::{IsolateError, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
+
+/// Host paths every minimal root shows read-only
+pub const DEFAULT_VISIBLE_PATHS: &[&str] = &["/bin", "/lib", "/lib64", "/usr", "/etc/ld.so.cache"];
+
+/// Device nodes bound from the host
+pub(crate) const DEVICES: &[&str] = &["/dev/null", "/dev/zero", "/dev/random", "/dev/urandom"];
+
+/// Longest symlink chain followed for a visible path
+const MAX_LINK_DEPTH: usize = 8;
+
+/// Size of the writable layer of an image root unless configured
+pub const DEFAULT_UPPER_SIZE: u64 = 64 * 1024 * 1024;
+
+const RDONLY_FLAGS: libc::c_ulong = libc::MS_RDONLY | libc::MS_NOSUID | libc::MS_NODEV;
+const WORKDIR_FLAGS: libc::c_ulong = libc::MS_REC | libc::MS_NOSUID | libc::MS_NODEV;
+
+/// How a host path appears in the minimal root
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum HostEntry {
+    Dir,
+    File,
+    Symlink(PathBuf),
+}
+
+/// One setup step run in the child
+#[derive(Debug, Clone)]
+enum Step {
+    Mkdir(CString),
+    /// Create an empty file for a file bind mount
+    Touch(CString),
+    Symlink {
+        target: CString,
+        link: CString,
+    },
+    /// Bind `source` over `target`, then remount it with `flags` unless zero;
+    /// `MS_REC` in `flags` also binds the mounts beneath `source`
+    Bind {
+        source: CString,
+        target: CString,
+        flags: libc::c_ulong,
+    },
+    Tmpfs {
+        target: CString,
+        options: CString,
+    },
+    /// Overlayfs at `target`, its layers given in `options`
+    Overlay {
+        target: CString,
+        options: CString,
+    },
+    Proc(CString),
+    /// Change the flags of the mount at `target`
+    Remount {
+        target: CString,
+        flags: libc::c_ulong,
+    },
+}
+
+/// A minimal root, planned in the parent and entered by the child
+#[derive(Debug, Clone)]
+pub struct VisibleRoot {
+    root: CString,
+    workdir: CString,
+    steps: Vec<Step>,
+}
+
+impl VisibleRoot {
+    /// Plan a root at `root` showing `visible` read-only and `workdir` read-write
+    ///
+    /// Symlinks among the visible paths are followed and recreated, so a
+    /// binary such as `/usr/bin/java` brings its `/etc/alternatives` hop along.
+    /// Paths missing on this host are skipped.
+    pub fn plan(root: &Path, visible: &[PathBuf], workdir: &Path) -> Result<Self> {
+        std::fs::create_dir_all(root).map_err(|e| {
+            IsolateError::Config(format!(
+                "Failed to create minimal root mount point {}: {}",
+                root.display(),
+                e
+            ))
+        })?;
+
+        let entries = host_entries(
+            DEFAULT_VISIBLE_PATHS
+                .iter()
+                .map(PathBuf::from)
+                .chain(visible.iter().cloned()),
+        );
+
+        let mut plan = Planner {
+            root: root.to_path_buf(),
+            steps: Vec::new(),
+            dirs: BTreeSet::new(),
+        };
+        plan.steps.push(Step::Tmpfs {
+            target: c_path(root)?,
+            options: c"mode=755,size=16m".to_owned(),
+        });
+
+        plan.mkdir_all(Path::new("/tmp"))?;
+        plan.steps.push(Step::Tmpfs {
+            target: plan.target(Path::new("/tmp"))?,
+            options: c"mode=1777,size=64m".to_owned(),
+        });
+        plan.mkdir_all(Path::new("/proc"))?;
+        plan.steps
+            .push(Step::Proc(plan.target(Path::new("/proc"))?));
+        for device in DEVICES
+            .iter()
+            .map(Path::new)
+            .filter(|device| device.exists())
+        {
+            plan.bind(device, false, 0)?;
+        }
+
+        // Entries below a bound directory are already visible through it
+        let mut bound: Vec<&PathBuf> = Vec::new();
+        for (path, entry) in &entries {
+            if bound.iter().any(|dir| path.starts_with(dir)) {
+                continue;
+            }
+            match entry {
+                HostEntry::Dir => {
+                    plan.bind(path, true, RDONLY_FLAGS)?;
+                    bound.push(path);
+                }
+                HostEntry::File => plan.bind(path, false, RDONLY_FLAGS)?,
+                HostEntry::Symlink(target) => {
+                    if let Some(parent) = path.parent() {
+                        plan.mkdir_all(parent)?;
+                    }
+                    plan.steps.push(Step::Symlink {
+                        target: c_path(target)?,
+                        link: plan.target(path)?,
+                    });
+                }
+            }
+        }
+
+        // Recursive so directory bindings under the workdir keep their flags
+        plan.bind(workdir, true, WORKDIR_FLAGS)?;
+        plan.steps.push(Step::Remount {
+            target: c_path(root)?,
+            flags: RDONLY_FLAGS,
+        });
+
+        Ok(Self {
+            root: c_path(root)?,
+            workdir: c_path(workdir)?,
+            steps: plan.steps,
+        })
+    }
+
+    /// Plan a copy-on-write root at `root` from the directory `image`
+    ///
+    /// Writes go to a tmpfs of `upper_size` bytes. Besides the image the root
+    /// shows the workdir read-write, a few host devices and a restricted `/proc`.
+    pub fn plan_overlay(
+        root: &Path,
+        image: &Path,
+        upper_size: u64,
+        workdir: &Path,
+    ) -> Result<Self> {
+        let image = image.canonicalize().map_err(|e| {
+            IsolateError::Config(format!("Root image {} unavailable: {}", image.display(), e))
+        })?;
+        if !image.is_dir() {
+            return Err(IsolateError::Config(format!(
+                "Root image {} is not a directory",
+                image.display()
+            )));
+        }
+        // overlayfs separates layers with ':' and options with ','
+        if image.to_string_lossy().contains([':', ',']) {
+            return Err(IsolateError::Config(format!(
+                "Root image path {} may not contain ':' or ','",
+                image.display()
+            )));
+        }
+        std::fs::create_dir_all(root).map_err(|e| {
+            IsolateError::Config(format!(
+                "Failed to create image root mount point {}: {}",
+                root.display(),
+                e
+            ))
+        })?;
+
+        let merged = root.join("merged");
+        let mut steps = vec![Step::Tmpfs {
+            target: c_path(root)?,
+            options: CString::new(format!("mode=755,size={}", upper_size))
+                .map_err(|e| IsolateError::Config(format!("Invalid tmpfs options: {}", e)))?,
+        }];
+        for layer in ["upper", "work", "merged"] {
+            steps.push(Step::Mkdir(c_path(&root.join(layer))?));
+        }
+        let layers = format!(
+            "lowerdir={},upperdir={},workdir={}",
+            image.display(),
+            root.join("upper").display(),
+            root.join("work").display()
+        );
+        steps.push(Step::Overlay {
+            target: c_path(&merged)?,
+            options: CString::new(layers)
+                .map_err(|e| IsolateError::Config(format!("Invalid overlay options: {}", e)))?,
+        });
+
+        let mut plan = Planner {
+            root: merged.clone(),
+            steps,
+            dirs: BTreeSet::new(),
+        };
+        plan.mkdir_all(Path::new("/proc"))?;
+        plan.steps
+            .push(Step::Proc(plan.target(Path::new("/proc"))?));
+        for device in DEVICES
+            .iter()
+            .map(Path::new)
+            .filter(|device| device.exists())
+        {
+            plan.bind(device, false, 0)?;
+        }
+        // Recursive so directory bindings under the workdir keep their flags
+        plan.bind(workdir, true, WORKDIR_FLAGS)?;
+
+        Ok(Self {
+            root: c_path(&merged)?,
+            workdir: c_path(workdir)?,
+            steps: plan.steps,
+        })
+    }
+
+    /// Unshare the mount namespace, build the root and pivot into it
+    ///
+    /// Runs in the forked child; leaves the working directory at the workdir.
+    pub fn enter(&self) -> std::io::Result<()> {
+        unsafe {
+            check(libc::unshare(libc::CLONE_NEWNS))?;
+            // Nothing mounted from here on may propagate back to the host
+            check(libc::mount(
+                std::ptr::null(),
+                c"/".as_ptr(),
+                std::ptr::null(),
+                libc::MS_REC | libc::MS_PRIVATE,
+                std::ptr::null(),
+            ))?;
+
+            for step in &self.steps {
+                step.apply()?;
+            }
+
+            check(libc::chdir(self.root.as_ptr()))?;
+            check(
+                libc::syscall(libc::SYS_pivot_root, c".".as_ptr(), c".".as_ptr()) as libc::c_int,
+            )?;
+            check(libc::umount2(c".".as_ptr(), libc::MNT_DETACH))?;
+            check(libc::chdir(self.workdir.as_ptr()))
+        }
+    }
+}
+
+/// Accumulates steps and remembers which directories already exist in the root
+struct Planner {
+    root: PathBuf,
+    steps: Vec<Step>,
+    dirs: BTreeSet<PathBuf>,
+}
+
+impl Planner {
+    /// Host path of `path` inside the new root
+    fn target(&self, path: &Path) -> Result<CString> {
+        c_path(&self.root.join(path.strip_prefix("/").unwrap_or(path)))
+    }
+
+    fn mkdir_all(&mut self, path: &Path) -> Result<()> {
+        let mut ancestors: Vec<&Path> = path
+            .ancestors()
+            .filter(|dir| dir.parent().is_some())
+            .collect();
+        ancestors.reverse();
+        for dir in ancestors {
+            if self.dirs.insert(dir.to_path_buf()) {
+                self.steps.push(Step::Mkdir(self.target(dir)?));
+            }
+        }
+        Ok(())
+    }
+
+    fn bind(&mut self, path: &Path, is_dir: bool, flags: libc::c_ulong) -> Result<()> {
+        if is_dir {
+            self.mkdir_all(path)?;
+        } else {
+            if let Some(parent) = path.parent() {
+                self.mkdir_all(parent)?;
+            }
+            self.steps.push(Step::Touch(self.target(path)?));
+        }
+        self.steps.push(Step::Bind {
+            source: c_path(path)?,
+            target: self.target(path)?,
+            flags,
+        });
+        Ok(())
+    }
+}
+
+/// `paths` by their real location, with every hop of the symlink chains they
+/// go through; paths missing on this host are left out
+pub(crate) fn host_entries(
+    paths: impl IntoIterator<Item = PathBuf>,
+) -> BTreeMap<PathBuf, HostEntry> {
+    let mut entries = BTreeMap::new();
+    for path in paths {
+        collect_host_path(&path, &mut entries, 0);
+    }
+    entries
+}
+
+/// Record `path` and, for symlinks, every hop of the chain it points along
+fn collect_host_path(path: &Path, entries: &mut BTreeMap<PathBuf, HostEntry>, depth: usize) {
+    if depth > MAX_LINK_DEPTH {
+        return;
+    }
+    // Resolve the parent so every entry sits under real directories
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return;
+    };
+    let Ok(parent) = parent.canonicalize() else {
+        return;
+    };
+    let path = parent.join(name);
+    if entries.contains_key(&path) {
+        return;
+    }
+    let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+        log::debug!("Visible path {} not present on this host", path.display());
+        return;
+    };
+
+    if metadata.file_type().is_symlink() {
+        let Ok(target) = std::fs::read_link(&path) else {
+            return;
+        };
+        let resolved = parent.join(&target);
+        entries.insert(path, HostEntry::Symlink(target));
+        collect_host_path(&resolved, entries, depth + 1);
+    } else if metadata.is_dir() {
+        entries.insert(path, HostEntry::Dir);
+    } else {
+        entries.insert(path, HostEntry::File);
+    }
+}
+
+fn c_path(path: &Path) -> Result<CString> {
+    use std::os::unix::ffi::OsStrExt;
+
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| IsolateError::Config(format!("Invalid path {}: {}", path.display(), e)))
+}
+
+impl Step {
+    unsafe fn apply(&self) -> std::io::Result<()> {
+        match self {
+            Step::Mkdir(dir) => {
+                if libc::mkdir(dir.as_ptr(), 0o755) == 0 {
+                    // The program's umask is already in effect; the box user must
+                    // still be able to traverse (an overlay root takes this mode)
+                    return check(libc::chmod(dir.as_ptr(), 0o755));
+                }
+                if *libc::__errno_location() != libc::EEXIST {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            }
+            Step::Touch(file) => {
+                let fd = libc::open(
+                    file.as_ptr(),
+                    libc::O_CREAT | libc::O_WRONLY | libc::O_CLOEXEC,
+                    0o644,
+                );
+                if fd < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                libc::close(fd);
+                Ok(())
+            }
+            Step::Symlink { target, link } => check(libc::symlink(target.as_ptr(), link.as_ptr())),
+            Step::Bind {
+                source,
+                target,
+                flags,
+            } => {
+                check(libc::mount(
+                    source.as_ptr(),
+                    target.as_ptr(),
+                    std::ptr::null(),
+                    libc::MS_BIND | (*flags & libc::MS_REC),
+                    std::ptr::null(),
+                ))?;
+                // A bind remount only ever changes the top mount
+                let flags = *flags & !libc::MS_REC;
+                if flags != 0 {
+                    remount(target, flags)?;
+                }
+                Ok(())
+            }
+            Step::Overlay { target, options } => check(libc::mount(
+                c"overlay".as_ptr(),
+                target.as_ptr(),
+                c"overlay".as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV,
+                options.as_ptr() as *const libc::c_void,
+            )),
+            Step::Tmpfs { target, options } => check(libc::mount(
+                c"tmpfs".as_ptr(),
+                target.as_ptr(),
+                c"tmpfs".as_ptr(),
+                libc::MS_NOSUID | libc::MS_NODEV,
+                options.as_ptr() as *const libc::c_void,
+            )),
+            Step::Proc(target) => {
+                let flags = libc::MS_NOSUID | libc::MS_NODEV | libc::MS_NOEXEC;
+                // Only process directories, and only the caller's own (Linux 5.8+)
+                let mount_proc = |options: &CStr| {
+                    libc::mount(
+                        c"proc".as_ptr(),
+                        target.as_ptr(),
+                        c"proc".as_ptr(),
+                        flags,
+                        options.as_ptr() as *const libc::c_void,
+                    )
+                };
+                if mount_proc(c"hidepid=invisible,subset=pid") == 0 {
+                    return Ok(());
+                }
+                check(mount_proc(c"hidepid=2"))
+            }
+            Step::Remount { target, flags } => remount(target, *flags),
+        }
+    }
+}
+
+/// Change the flags of the mount at `target`
+unsafe fn remount(target: &CStr, flags: libc::c_ulong) -> std::io::Result<()> {
+    check(libc::mount(
+        std::ptr::null(),
+        target.as_ptr(),
+        std::ptr::null(),
+        libc::MS_BIND | libc::MS_REMOUNT | flags,
+        std::ptr::null(),
+    ))
+}
+
+fn check(rc: libc::c_int) -> std::io::Result<()> {
+    if rc == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
@@ -0,0 +1,327 @@
+/// Crash safety for the supervisor: panic hook, crash reports and a watchdog process
+///
+/// The watchdog is forked before any sandbox setup happens and holds the read end
+/// of a pipe. The supervisor tells it which process group to guard and disarms it
+/// once the run is over. If the supervisor dies instead (panic, abort, SIGKILL),
+/// the pipe reaches EOF and the watchdog kills the sandboxed process group and
+/// every task left in the run's cgroups, so no contestant process outlives it.
+use crate::types::{IsolateError, Result};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::Mutex;
+
+/// Process group of the run currently in flight (0 when idle)
+static ACTIVE_PGID: AtomicI32 = AtomicI32::new(0);
+
+/// Instance id of the run currently in flight, included in crash reports
+static CRASH_CONTEXT: Mutex<Option<String>> = Mutex::new(None);
+
+/// Secondary process that kills the sandbox if the supervisor dies mid-run
+pub struct Watchdog {
+    pid: libc::pid_t,
+    control_fd: libc::c_int,
+    disarmed: bool,
+}
+
+impl Watchdog {
+    /// Fork the watchdog; `task_files` are the cgroup `tasks` files of the run
+    #[cfg(unix)]
+    pub fn spawn(task_files: Vec<PathBuf>) -> Result<Self> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        // Prepare everything that allocates before forking; the child must only
+        // make async-signal-safe calls since other supervisor threads may hold locks
+        let task_files: Vec<CString> = task_files
+            .iter()
+            .filter_map(|path| CString::new(path.as_os_str().as_bytes()).ok())
+            .collect();
+        let dev_null = CString::new("/dev/null").unwrap();
+
+        let mut fds = [0 as libc::c_int; 2];
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+            return Err(IsolateError::Process(format!(
+                "Failed to create watchdog pipe: {}",
+                std::io::Error::last_os_error()
+            )));
+        }
+        let (read_fd, write_fd) = (fds[0], fds[1]);
+
+        let pid = unsafe { libc::fork() };
+        if pid < 0 {
+            let err = std::io::Error::last_os_error();
+            unsafe {
+                libc::close(read_fd);
+                libc::close(write_fd);
+            }
+            return Err(IsolateError::Process(format!(
+                "Failed to fork watchdog: {}",
+                err
+            )));
+        }
+
+        if pid == 0 {
+            unsafe { watchdog_main(read_fd, write_fd, &task_files, &dev_null) }
+        }
+
+        unsafe { libc::close(read_fd) };
+        Ok(Self {
+            pid,
+            control_fd: write_fd,
+            disarmed: false,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn spawn(_task_files: Vec<PathBuf>) -> Result<Self> {
+        Err(IsolateError::Process(
+            "Watchdog is only supported on Unix".to_string(),
+        ))
+    }
+
+    /// Tell the watchdog which process group to kill if the supervisor dies
+    pub fn arm(&self, pgid: u32) {
+        ACTIVE_PGID.store(pgid as i32, Ordering::SeqCst);
+        self.send(pgid as i32);
+    }
+
+    /// Run finished normally; let the watchdog exit without killing anything
+    pub fn disarm(mut self) {
+        self.disarm_inner();
+    }
+
+    fn disarm_inner(&mut self) {
+        if self.disarmed {
+            return;
+        }
+        self.disarmed = true;
+        ACTIVE_PGID.store(0, Ordering::SeqCst);
+        self.send(0);
+        unsafe { libc::close(self.control_fd) };
+        self.reap();
+    }
+
+    /// Wait for the watchdog to exit so it does not linger as a zombie
+    ///
+    /// Once our end of the pipe is closed it exits on its own, after killing
+    /// the sandbox if it was still armed.
+    fn reap(&self) {
+        let wait = |flags| loop {
+            let pid = unsafe { libc::waitpid(self.pid, std::ptr::null_mut(), flags) };
+            if pid >= 0 || std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted
+            {
+                return pid;
+            }
+        };
+        // Not gone yet: it is still killing the sandbox, which is quick
+        if wait(libc::WNOHANG) == 0 {
+            wait(0);
+        }
+    }
+
+    fn send(&self, value: i32) {
+        let bytes = value.to_ne_bytes();
+        unsafe {
+            libc::write(
+                self.control_fd,
+                bytes.as_ptr() as *const libc::c_void,
+                bytes.len(),
+            );
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            // Leave the watchdog armed: closing our end makes it kill the sandbox
+            unsafe { libc::close(self.control_fd) };
+            self.disarmed = true;
+            self.reap();
+            return;
+        }
+        self.disarm_inner();
+    }
+}
+
+/// Body of the forked watchdog process
+#[cfg(unix)]
+unsafe fn watchdog_main(
+    read_fd: libc::c_int,
+    write_fd: libc::c_int,
+    task_files: &[std::ffi::CString],
+    dev_null: &std::ffi::CString,
+) -> ! {
+    libc::close(write_fd);
+
+    // Don't keep the supervisor's stdio open, callers may be waiting for EOF
+    let null_fd = libc::open(dev_null.as_ptr(), libc::O_RDWR);
+    if null_fd >= 0 {
+        libc::dup2(null_fd, 0);
+        libc::dup2(null_fd, 1);
+        libc::dup2(null_fd, 2);
+        if null_fd > 2 {
+            libc::close(null_fd);
+        }
+    }
+
+    // Nor any other pipe the supervisor had open when forking, such as the
+    // ends of an interactive run that must see EOF when their process exits
+    close_fds_except(read_fd);
+
+    let mut pgid: i32 = 0;
+    loop {
+        let mut buf = [0u8; 4];
+        let mut filled = 0;
+        while filled < buf.len() {
+            let n = libc::read(
+                read_fd,
+                buf[filled..].as_mut_ptr() as *mut libc::c_void,
+                buf.len() - filled,
+            );
+            if n > 0 {
+                filled += n as usize;
+            } else if n < 0 && *libc::__errno_location() == libc::EINTR {
+                continue;
+            } else {
+                break;
+            }
+        }
+        if filled < buf.len() {
+            // EOF without a disarm message: the supervisor is gone
+            break;
+        }
+        match i32::from_ne_bytes(buf) {
+            0 => libc::_exit(0),
+            value => pgid = value,
+        }
+    }
+
+    if pgid > 0 {
+        libc::kill(-pgid, libc::SIGKILL);
+    }
+    for tasks in task_files {
+        kill_cgroup_tasks(tasks);
+    }
+    libc::_exit(0);
+}
+
+/// Close every descriptor above stderr except `keep`
+#[cfg(unix)]
+unsafe fn close_fds_except(keep: libc::c_int) {
+    let close_range = |first: libc::c_int, last: libc::c_int| {
+        first > last
+            || libc::syscall(
+                libc::SYS_close_range,
+                first as libc::c_uint,
+                last as libc::c_uint,
+                0,
+            ) == 0
+    };
+    if close_range(3, keep - 1) && close_range(keep + 1, libc::c_int::MAX) {
+        return;
+    }
+    // Kernels before 5.9 lack close_range
+    for fd in 3..1024 {
+        if fd != keep {
+            libc::close(fd);
+        }
+    }
+}
+
+/// SIGKILL every pid listed in a cgroup `tasks` file without allocating
+#[cfg(unix)]
+unsafe fn kill_cgroup_tasks(tasks: &std::ffi::CString) {
+    let fd = libc::open(tasks.as_ptr(), libc::O_RDONLY);
+    if fd < 0 {
+        return;
+    }
+    let mut buf = [0u8; 4096];
+    let mut pid: i32 = 0;
+    loop {
+        let n = libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len());
+        if n <= 0 {
+            break;
+        }
+        for &byte in &buf[..n as usize] {
+            if byte.is_ascii_digit() {
+                pid = pid * 10 + (byte - b'0') as i32;
+            } else {
+                if pid > 0 {
+                    libc::kill(pid, libc::SIGKILL);
+                }
+                pid = 0;
+            }
+        }
+    }
+    if pid > 0 {
+        libc::kill(pid, libc::SIGKILL);
+    }
+    libc::close(fd);
+}
+
+/// Record which instance is running so crash reports can name it
+pub fn set_crash_context(instance_id: Option<String>) {
+    if let Ok(mut context) = CRASH_CONTEXT.lock() {
+        *context = instance_id;
+    }
+}
+
+/// Directory where crash reports are written
+pub fn crash_report_dir() -> PathBuf {
+    crate::paths::paths().crash_reports_dir()
+}
+
+/// Install a panic hook that kills the active sandbox and writes a crash report
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // Kill the sandbox right away; the watchdog is the fallback if we can't
+        let pgid = ACTIVE_PGID.swap(0, Ordering::SeqCst);
+        #[cfg(unix)]
+        if pgid > 0 {
+            unsafe {
+                libc::kill(-pgid, libc::SIGKILL);
+            }
+        }
+
+        let message = if let Some(s) = info.payload().downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = info.payload().downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown panic payload".to_string()
+        };
+        let instance_id = CRASH_CONTEXT.try_lock().ok().and_then(|c| c.clone());
+
+        let report = serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "pid": std::process::id(),
+            "thread": std::thread::current().name().unwrap_or("unnamed"),
+            "message": message,
+            "location": info.location().map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column())),
+            "instance_id": instance_id,
+            "sandbox_pgid": if pgid > 0 { Some(pgid) } else { None },
+            "backtrace": std::backtrace::Backtrace::force_capture().to_string(),
+        });
+
+        let dir = crash_report_dir();
+        let path = dir.join(format!(
+            "crash-{}-{}.json",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+            std::process::id()
+        ));
+        let written = std::fs::create_dir_all(&dir).and_then(|_| {
+            std::fs::write(
+                &path,
+                serde_json::to_string_pretty(&report).unwrap_or_default(),
+            )
+        });
+        match written {
+            Ok(()) => eprintln!("💥 rustbox crashed; report written to {}", path.display()),
+            Err(e) => eprintln!("💥 rustbox crashed; failed to write crash report: {}", e),
+        }
+
+        previous(info);
+    }));
+}